@@ -5,6 +5,7 @@
 
 use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
+use std::ffi::CString;
 use std::fs;
 use std::io::{Error, ErrorKind, Result};
 use std::path::Path;
@@ -177,16 +178,37 @@ impl ConfigV2 {
             if let Some(oss_cfg) = backend_cfg.oss.as_mut() {
                 oss_cfg.access_key_id = String::new();
                 oss_cfg.access_key_secret = String::new();
+                Self::remove_mirror_secrets(&mut oss_cfg.mirrors);
+            }
+            if let Some(s3_cfg) = backend_cfg.s3.as_mut() {
+                s3_cfg.access_key_id = String::new();
+                s3_cfg.access_key_secret = String::new();
+                Self::remove_mirror_secrets(&mut s3_cfg.mirrors);
             }
             if let Some(registry_cfg) = backend_cfg.registry.as_mut() {
                 registry_cfg.auth = None;
                 registry_cfg.registry_token = None;
+                Self::remove_mirror_secrets(&mut registry_cfg.mirrors);
+            }
+            if let Some(http_proxy_cfg) = backend_cfg.http_proxy.as_mut() {
+                Self::remove_mirror_secrets(&mut http_proxy_cfg.mirrors);
             }
         }
 
         cfg
     }
 
+    // Mirror requests may carry credentials in the `Authorization` header.
+    fn remove_mirror_secrets(mirrors: &mut [MirrorConfig]) {
+        for mirror in mirrors.iter_mut() {
+            for (key, value) in mirror.headers.iter_mut() {
+                if key.eq_ignore_ascii_case("authorization") {
+                    *value = String::new();
+                }
+            }
+        }
+    }
+
     /// Check whether chunk digest validation is enabled or not.
     pub fn is_chunk_validation_enabled(&self) -> bool {
         let mut validation = if let Some(cache) = &self.cache {
@@ -275,6 +297,9 @@ pub struct BackendConfigV2 {
     /// Configuration for local http proxy.
     #[serde(rename = "http-proxy")]
     pub http_proxy: Option<HttpProxyConfig>,
+    /// Configuration for OCI image layer backend.
+    #[serde(rename = "oci-layer")]
+    pub oci_layer: Option<OciLayerConfig>,
 }
 
 impl BackendConfigV2 {
@@ -343,6 +368,14 @@ impl BackendConfigV2 {
                 }
                 None => return false,
             },
+            "oci-layer" => match self.oci_layer.as_ref() {
+                Some(v) => {
+                    if v.dir.is_empty() {
+                        return false;
+                    }
+                }
+                None => return false,
+            },
             _ => return false,
         }
 
@@ -450,6 +483,23 @@ impl BackendConfigV2 {
             })
         }
     }
+
+    /// Get configuration information for OCI image layer backend
+    pub fn get_oci_layer_config(&self) -> Result<&OciLayerConfig> {
+        if &self.backend_type != "oci-layer" {
+            Err(Error::new(
+                ErrorKind::InvalidInput,
+                "backend type is not 'oci-layer'",
+            ))
+        } else {
+            self.oci_layer.as_ref().ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    "no configuration information for oci-layer",
+                )
+            })
+        }
+    }
 }
 
 /// Configuration information for localdisk storage backend.
@@ -475,6 +525,26 @@ pub struct LocalFsConfig {
     /// Alternative dirs to search for blobs.
     #[serde(default)]
     pub alt_dirs: Vec<String>,
+    /// Memory map blob files and read data from the mapping instead of issuing syscalls.
+    ///
+    /// Blob files must not be truncated while they are in use.
+    #[serde(default)]
+    pub mmap: bool,
+}
+
+/// Configuration information for OCI image layer storage backend.
+///
+/// Layers are read from an OCI image layout directory, as `dir/blobs/sha256/<digest>`. It works
+/// with RAFS images converted in reference mode, such as `nydus-image create --type targz-ref`,
+/// which records the mapping from RAFS chunks to offsets in the original layers.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct OciLayerConfig {
+    /// Root directory of the OCI image layout.
+    #[serde(default)]
+    pub dir: String,
+    /// Alternative dirs to search for blobs not in the OCI image layout, such as `blob.meta`.
+    #[serde(default)]
+    pub alt_dirs: Vec<String>,
 }
 
 /// OSS configuration information to access blobs.
@@ -511,6 +581,11 @@ pub struct OssConfig {
     /// Retry count when read request failed.
     #[serde(default)]
     pub retry_limit: u8,
+    /// Close connections to the remote server after being idle for the period, in seconds.
+    ///
+    /// Connections are re-established on demand, and zero means to keep idle connections open.
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout: u32,
     /// Enable HTTP proxy for the read request.
     #[serde(default)]
     pub proxy: ProxyConfig,
@@ -555,6 +630,11 @@ pub struct S3Config {
     /// Retry count when read request failed.
     #[serde(default)]
     pub retry_limit: u8,
+    /// Close connections to the remote server after being idle for the period, in seconds.
+    ///
+    /// Connections are re-established on demand, and zero means to keep idle connections open.
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout: u32,
     /// Enable HTTP proxy for the read request.
     #[serde(default)]
     pub proxy: ProxyConfig,
@@ -584,6 +664,11 @@ pub struct HttpProxyConfig {
     /// Retry count when read request failed.
     #[serde(default)]
     pub retry_limit: u8,
+    /// Close connections to the remote server after being idle for the period, in seconds.
+    ///
+    /// Connections are re-established on demand, and zero means to keep idle connections open.
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout: u32,
     /// Enable HTTP proxy for the read request.
     #[serde(default)]
     pub proxy: ProxyConfig,
@@ -602,6 +687,9 @@ pub struct RegistryConfig {
     pub host: String,
     /// Registry image name, like 'library/ubuntu'
     pub repo: String,
+    /// Namespace of the image repository, prepended to 'repo' for multi-tenant registries.
+    #[serde(default)]
+    pub namespace: String,
     /// Base64_encoded(username:password), the field should be sent to registry auth server to get a bearer token.
     #[serde(default)]
     pub auth: Option<String>,
@@ -617,6 +705,11 @@ pub struct RegistryConfig {
     /// Retry count when read request failed.
     #[serde(default)]
     pub retry_limit: u8,
+    /// Close connections to the remote server after being idle for the period, in seconds.
+    ///
+    /// Connections are re-established on demand, and zero means to keep idle connections open.
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout: u32,
     /// The field is a bearer token to be sent to registry to authorize registry requests.
     #[serde(default)]
     pub registry_token: Option<String>,
@@ -627,6 +720,11 @@ pub struct RegistryConfig {
     /// Redirect blob access to a different host regardless of the one specified in 'host'.
     #[serde(default)]
     pub blob_redirected_host: String,
+    /// Template of the blob url path, with placeholders `{repo}` and `{blob_id}`.
+    ///
+    /// Defaults to `/v2/{repo}/blobs/sha256:{blob_id}` if empty.
+    #[serde(default)]
+    pub blob_url_template: String,
     /// Enable HTTP proxy for the read request.
     #[serde(default)]
     pub proxy: ProxyConfig,
@@ -744,7 +842,7 @@ impl CacheConfigV2 {
 /// Configuration information for file cache.
 #[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 pub struct FileCacheConfig {
-    /// Working directory to store state and cached files.
+    /// Working directory to store state and cached files, must be writable.
     #[serde(default = "default_work_dir")]
     pub work_dir: String,
     /// Deprecated: disable index mapping, keep it as false when possible.
@@ -759,6 +857,12 @@ pub struct FileCacheConfig {
     /// Key for data encryption, a heximal representation of [u8; 32].
     #[serde(default)]
     pub encryption_key: String,
+    /// Size in bytes of the in-memory cache for recently used decompressed chunks, shared by all
+    /// blobs of the cache.
+    ///
+    /// It only takes effect when caching compressed data, zero to disable.
+    #[serde(default)]
+    pub decompressed_lru_size: usize,
 }
 
 impl FileCacheConfig {
@@ -774,14 +878,29 @@ impl FileCacheConfig {
                 e
             })?;
 
-        if path.is_dir() {
-            Ok(&self.work_dir)
-        } else {
-            Err(Error::new(
+        if !path.is_dir() {
+            return Err(Error::new(
                 ErrorKind::NotFound,
                 format!("filecache work_dir {} is not a directory", self.work_dir),
-            ))
+            ));
+        }
+
+        // Chunk map and cache files are created under the working directory, so fail early
+        // instead of failing on the first blob.
+        let dir = CString::new(self.work_dir.as_bytes()).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid filecache work_dir {}", self.work_dir),
+            )
+        })?;
+        if unsafe { libc::access(dir.as_ptr(), libc::W_OK) } != 0 {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                format!("filecache work_dir {} is not writable", self.work_dir),
+            ));
         }
+
+        Ok(&self.work_dir)
     }
 }
 
@@ -820,7 +939,7 @@ impl FsCacheConfig {
 /// Configuration information for RAFS filesystem.
 #[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 pub struct RafsConfigV2 {
-    /// Filesystem metadata cache mode.
+    /// Filesystem metadata cache mode: "direct", "cached" or "auto".
     #[serde(default = "default_rafs_mode")]
     pub mode: String,
     /// Amplified user IO request batch size to read data from remote storage backend / local cache.
@@ -829,6 +948,29 @@ pub struct RafsConfigV2 {
     /// Whether to validate data digest.
     #[serde(default)]
     pub validate: bool,
+    /// Path to a PEM encoded Ed25519 public key to verify signature of RAFS metadata.
+    ///
+    /// Unsigned or tampered RAFS metadata is rejected if it's set.
+    #[serde(default)]
+    pub trusted_key: String,
+    /// Prefault RAFS metadata into memory and lock it by mlock() after mounting.
+    ///
+    /// It avoids page faults when accessing metadata, at the cost of memory. Metadata is only
+    /// prefaulted if it exceeds `RLIMIT_MEMLOCK`.
+    #[serde(default)]
+    pub mlock_metadata: bool,
+    /// Maximum size of RAFS metadata files in bytes, metadata files exceeding it are rejected.
+    ///
+    /// The default limit of 2GB is used if it's 0.
+    #[serde(default)]
+    pub max_metadata_size: u64,
+    /// Size threshold in bytes to select metadata mode for RAFS v5 if `mode` is "auto".
+    ///
+    /// Metadata files not exceeding it are loaded in `cached` mode for lower latency, and larger
+    /// ones in `direct` mode for lower memory usage. The default threshold of 16MB is used if
+    /// it's 0.
+    #[serde(default)]
+    pub auto_mode_threshold: u64,
     /// Enable support of extended attributes.
     #[serde(default)]
     pub enable_xattr: bool,
@@ -843,6 +985,60 @@ pub struct RafsConfigV2 {
     /// Record file name if file access trace log.
     #[serde(default)]
     pub latest_read_files: bool,
+    /// Check whether data blobs are reachable from the storage backend when mounting.
+    #[serde(default)]
+    pub check_backend: bool,
+    /// Absolute path of the directory in the image to be used as the filesystem root.
+    ///
+    /// The whole image is exposed if it's empty.
+    #[serde(default)]
+    pub subdir: String,
+    /// Warn when the data chunks backing a read exceed the read size by this ratio.
+    ///
+    /// Helps to detect small random reads on images with oversized chunks, zero to disable.
+    #[serde(default)]
+    pub amplification_warn_ratio: u32,
+    /// Present all regular files in a single directory, named by their inode numbers.
+    ///
+    /// Useful for scanning tools which need to enumerate every file without walking the tree.
+    #[serde(default)]
+    pub flat_view: bool,
+    /// Maximum number of concurrent reads from the storage backend, zero means no limit.
+    #[serde(default)]
+    pub max_concurrent_backend_reads: usize,
+    /// Fast-fail reads from the storage backend after this many consecutive failures, zero means
+    /// never.
+    #[serde(default)]
+    pub backend_failure_threshold: u32,
+    /// Seconds to fast-fail reads before probing the failing storage backend again.
+    #[serde(default = "default_backend_failure_cooldown")]
+    pub backend_failure_cooldown: u64,
+    /// Prefetch data of small files in a directory when the directory is looked up or listed.
+    ///
+    /// It works only when blob data prefetching is enabled for the cache.
+    #[serde(default)]
+    pub dir_prefetch: bool,
+    /// Do not negotiate the fuse `WRITEBACK_CACHE` capability with the kernel.
+    ///
+    /// The kernel may keep stale file attributes across a live bootstrap update when enabled.
+    #[serde(default)]
+    pub disable_writeback_cache: bool,
+    /// Do not negotiate the fuse `ZERO_MESSAGE_OPEN` capability with the kernel.
+    #[serde(default)]
+    pub disable_zero_message_open: bool,
+    /// Seconds for the kernel to cache the result of looking up a non-existent file.
+    ///
+    /// Saves repeated lookups when probing many non-existent paths, zero to disable caching.
+    #[serde(default = "default_negative_entry_timeout")]
+    pub negative_entry_timeout: u64,
+    /// Absolute paths of directories in the image to be announced as submounts to virtio-fs.
+    ///
+    /// The guest kernel creates a separate mount with a distinct device number for each of them.
+    #[serde(default)]
+    pub submounts: Vec<String>,
+    /// Shift ownership of files recorded in the image, like idmapped mounts.
+    #[serde(default)]
+    pub idmap: IdMapConfig,
     /// Filesystem prefetching configuration.
     #[serde(default)]
     pub prefetch: PrefetchConfigV2,
@@ -851,12 +1047,18 @@ pub struct RafsConfigV2 {
 impl RafsConfigV2 {
     /// Validate RAFS filesystem configuration information.
     pub fn validate(&self) -> bool {
-        if self.mode != "direct" && self.mode != "cached" {
+        if self.mode != "direct" && self.mode != "cached" && self.mode != "auto" {
             return false;
         }
         if self.user_io_batch_size > 0x10000000 {
             return false;
         }
+        if !self.subdir.is_empty() && !self.subdir.starts_with('/') {
+            return false;
+        }
+        if self.submounts.iter().any(|v| !v.starts_with('/')) {
+            return false;
+        }
         if self.prefetch.enable {
             if self.prefetch.batch_size > 0x10000000 {
                 return false;
@@ -887,6 +1089,23 @@ pub struct PrefetchConfigV2 {
     /// Prefetch all data from backend.
     #[serde(default)]
     pub prefetch_all: bool,
+    /// Directory to persist access history of images, to prefetch files accessed by previous
+    /// mounts of the same image. Disabled if empty.
+    #[serde(default)]
+    pub history_dir: String,
+}
+
+/// Configuration information to shift ownership of files presented by RAFS filesystem.
+///
+/// It allows to present the same image under different ownership for different containers.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct IdMapConfig {
+    /// Offset added to uid of all files.
+    #[serde(default)]
+    pub uid_offset: u32,
+    /// Offset added to gid of all files.
+    #[serde(default)]
+    pub gid_offset: u32,
 }
 
 /// Configuration information for network proxy.
@@ -1187,6 +1406,10 @@ fn default_http_timeout() -> u32 {
     5
 }
 
+fn default_idle_timeout() -> u32 {
+    90
+}
+
 fn default_check_interval() -> u64 {
     5
 }
@@ -1195,6 +1418,14 @@ fn default_failure_limit() -> u8 {
     5
 }
 
+fn default_backend_failure_cooldown() -> u64 {
+    10
+}
+
+fn default_negative_entry_timeout() -> u64 {
+    1 << 32
+}
+
 fn default_work_dir() -> String {
     ".".to_string()
 }
@@ -1247,6 +1478,7 @@ impl TryFrom<&BackendConfig> for BackendConfigV2 {
             s3: None,
             registry: None,
             http_proxy: None,
+            oci_layer: None,
         };
 
         match value.backend_type.as_str() {
@@ -1265,6 +1497,9 @@ impl TryFrom<&BackendConfig> for BackendConfigV2 {
             "registry" => {
                 config.registry = Some(serde_json::from_value(value.backend_config.clone())?);
             }
+            "oci-layer" => {
+                config.oci_layer = Some(serde_json::from_value(value.backend_config.clone())?);
+            }
             v => {
                 return Err(Error::new(
                     ErrorKind::InvalidInput,
@@ -1353,6 +1588,18 @@ struct RafsConfig {
     /// Whether to validate data digest before use.
     #[serde(default)]
     pub digest_validate: bool,
+    /// Path to a PEM encoded Ed25519 public key to verify signature of RAFS metadata.
+    #[serde(default)]
+    pub trusted_key: String,
+    /// Prefault RAFS metadata into memory and lock it by mlock().
+    #[serde(default)]
+    pub mlock_metadata: bool,
+    /// Maximum size of RAFS metadata files, use the default limit if it's 0.
+    #[serde(default)]
+    pub max_metadata_size: u64,
+    /// Size threshold to select metadata mode if `mode` is "auto", use the default if it's 0.
+    #[serde(default)]
+    pub auto_mode_threshold: u64,
     /// Io statistics.
     #[serde(default)]
     pub iostats_files: bool,
@@ -1368,10 +1615,52 @@ struct RafsConfig {
     /// Record file name if file access trace log.
     #[serde(default)]
     pub latest_read_files: bool,
+    /// Check whether data blobs are reachable from the storage backend when mounting.
+    #[serde(default)]
+    pub check_backend: bool,
+    /// Absolute path of the directory in the image to be used as the filesystem root.
+    #[serde(default)]
+    pub subdir: String,
     // Amplified user IO request batch size to read data from remote storage backend / local cache.
     // ZERO value means, amplifying user io is not enabled.
     #[serde(rename = "amplify_io", default = "default_user_io_batch_size")]
     pub user_io_batch_size: usize,
+    /// Warn when the data chunks backing a read exceed the read size by this ratio.
+    #[serde(default)]
+    pub amplification_warn_ratio: u32,
+    /// Present all regular files in a single directory, named by their inode numbers.
+    #[serde(default)]
+    pub flat_view: bool,
+    /// Maximum number of concurrent reads from the storage backend, zero means no limit.
+    #[serde(default)]
+    pub max_concurrent_backend_reads: usize,
+    /// Fast-fail reads from the storage backend after this many consecutive failures, zero means
+    /// never.
+    #[serde(default)]
+    pub backend_failure_threshold: u32,
+    /// Seconds to fast-fail reads before probing the failing storage backend again.
+    #[serde(default = "default_backend_failure_cooldown")]
+    pub backend_failure_cooldown: u64,
+    /// Prefetch data of small files in a directory when the directory is looked up or listed.
+    ///
+    /// It works only when blob data prefetching is enabled for the cache.
+    #[serde(default)]
+    pub dir_prefetch: bool,
+    /// Do not negotiate the fuse `WRITEBACK_CACHE` capability with the kernel.
+    #[serde(default)]
+    pub disable_writeback_cache: bool,
+    /// Do not negotiate the fuse `ZERO_MESSAGE_OPEN` capability with the kernel.
+    #[serde(default)]
+    pub disable_zero_message_open: bool,
+    /// Seconds for the kernel to cache the result of looking up a non-existent file.
+    #[serde(default = "default_negative_entry_timeout")]
+    pub negative_entry_timeout: u64,
+    /// Absolute paths of directories in the image to be announced as submounts to virtio-fs.
+    #[serde(default)]
+    pub submounts: Vec<String>,
+    /// Shift ownership of files recorded in the image, like idmapped mounts.
+    #[serde(default)]
+    pub idmap: IdMapConfig,
 }
 
 impl TryFrom<RafsConfig> for ConfigV2 {
@@ -1384,10 +1673,27 @@ impl TryFrom<RafsConfig> for ConfigV2 {
             mode: v.mode,
             user_io_batch_size: v.user_io_batch_size,
             validate: v.digest_validate,
+            trusted_key: v.trusted_key,
+            mlock_metadata: v.mlock_metadata,
+            max_metadata_size: v.max_metadata_size,
+            auto_mode_threshold: v.auto_mode_threshold,
             enable_xattr: v.enable_xattr,
             iostats_files: v.iostats_files,
             access_pattern: v.access_pattern,
             latest_read_files: v.latest_read_files,
+            check_backend: v.check_backend,
+            subdir: v.subdir,
+            amplification_warn_ratio: v.amplification_warn_ratio,
+            flat_view: v.flat_view,
+            max_concurrent_backend_reads: v.max_concurrent_backend_reads,
+            backend_failure_threshold: v.backend_failure_threshold,
+            backend_failure_cooldown: v.backend_failure_cooldown,
+            dir_prefetch: v.dir_prefetch,
+            disable_writeback_cache: v.disable_writeback_cache,
+            disable_zero_message_open: v.disable_zero_message_open,
+            negative_entry_timeout: v.negative_entry_timeout,
+            submounts: v.submounts,
+            idmap: v.idmap,
             prefetch: v.fs_prefetch.into(),
         };
         if !cache.prefetch.enable && rafs.prefetch.enable {
@@ -1435,6 +1741,12 @@ struct FsPrefetchControl {
     /// Whether to prefetch all filesystem data.
     #[serde(default = "default_prefetch_all")]
     pub prefetch_all: bool,
+
+    /// Directory to persist file access history of images.
+    ///
+    /// Files accessed by previous mounts of the same image will be prefetched on mount.
+    #[serde(default)]
+    pub history_dir: String,
 }
 
 impl From<FsPrefetchControl> for PrefetchConfigV2 {
@@ -1445,6 +1757,7 @@ impl From<FsPrefetchControl> for PrefetchConfigV2 {
             batch_size: v.batch_size,
             bandwidth_limit: v.bandwidth_limit,
             prefetch_all: v.prefetch_all,
+            history_dir: v.history_dir,
         }
     }
 }
@@ -1472,6 +1785,7 @@ impl From<&BlobPrefetchConfig> for PrefetchConfigV2 {
             batch_size: v.batch_size,
             bandwidth_limit: v.bandwidth_limit,
             prefetch_all: true,
+            history_dir: String::new(),
         }
     }
 }
@@ -1542,6 +1856,7 @@ pub struct OverlayConfig {
 mod tests {
     use super::*;
     use crate::{BlobCacheEntry, BLOB_CACHE_TYPE_META_BLOB};
+    use vmm_sys_util::tempdir::TempDir;
 
     #[test]
     fn test_blob_prefetch_config() {
@@ -1587,6 +1902,21 @@ mod tests {
             serde_json::from_str("{\"work_dir\":\"/proc/mounts\",\"disable_indexed_map\":true}")
                 .unwrap();
         assert!(config.get_work_dir().is_err());
+
+        let work_dir = TempDir::new().unwrap();
+        let mut config = FileCacheConfig {
+            work_dir: work_dir.as_path().join("cache").display().to_string(),
+            ..Default::default()
+        };
+        assert_eq!(config.get_work_dir().unwrap(), &config.work_dir);
+        assert!(work_dir.as_path().join("cache").is_dir());
+
+        // Always writable for root, so only check for normal users.
+        if unsafe { libc::geteuid() } != 0 {
+            config.work_dir = "/proc/sys".to_string();
+            let err = config.get_work_dir().unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        }
     }
 
     #[test]
@@ -1743,12 +2073,14 @@ mod tests {
         let content = r#"{
             "blob_file": "blob_file",
             "dir": "blob_dir",
-            "alt_dirs": ["dir1", "dir2"]
+            "alt_dirs": ["dir1", "dir2"],
+            "mmap": true
         }"#;
         let config: LocalFsConfig = serde_json::from_str(content).unwrap();
         assert_eq!(config.blob_file, "blob_file");
         assert_eq!(config.dir, "blob_dir");
         assert_eq!(config.alt_dirs, vec!["dir1", "dir2"]);
+        assert!(config.mmap);
     }
 
     #[test]
@@ -1822,6 +2154,26 @@ mod tests {
         assert_eq!(&localfs.alt_dirs[0], "/var/nydus/cache");
     }
 
+    #[test]
+    fn test_v2_backend_oci_layer() {
+        let content = r#"version=2
+        [backend]
+        type = "oci-layer"
+        [backend.oci-layer]
+        dir = "/var/lib/oci/image"
+        alt_dirs = ["/var/nydus/meta"]
+        "#;
+        let config: ConfigV2 = toml::from_str(content).unwrap();
+        let backend = config.backend.as_ref().unwrap();
+        assert_eq!(&backend.backend_type, "oci-layer");
+        assert!(backend.validate());
+
+        let oci_layer = backend.get_oci_layer_config().unwrap();
+        assert_eq!(&oci_layer.dir, "/var/lib/oci/image");
+        assert_eq!(&oci_layer.alt_dirs[0], "/var/nydus/meta");
+        assert!(backend.get_localfs_config().is_err());
+    }
+
     #[test]
     fn test_v2_backend_oss() {
         let content = r#"version=2
@@ -1870,6 +2222,7 @@ mod tests {
         assert_eq!(oss.timeout, 10);
         assert_eq!(oss.connect_timeout, 10);
         assert_eq!(oss.retry_limit, 5);
+        assert_eq!(oss.idle_timeout, 90);
         assert_eq!(&oss.proxy.url, "localhost:6789");
         assert_eq!(&oss.proxy.ping_url, "localhost:6789/ping");
         assert_eq!(oss.proxy.check_interval, 10);
@@ -1894,14 +2247,17 @@ mod tests {
         scheme = "http"
         host = "localhost"
         repo = "nydus"
+        namespace = "tenant"
         auth = "auth"
         skip_verify = true
         timeout = 10
         connect_timeout = 10
         retry_limit = 5
+        idle_timeout = 30
         registry_token = "bear_token"
         blob_url_scheme = "https"
         blob_redirected_host = "redirect.registry.com"
+        blob_url_template = "/v2/{repo}/blobs/sha256:{blob_id}"
         [backend.registry.proxy]
         url = "localhost:6789"
         ping_url = "localhost:6789/ping"
@@ -1927,14 +2283,20 @@ mod tests {
         assert_eq!(&registry.scheme, "http");
         assert_eq!(&registry.host, "localhost");
         assert_eq!(&registry.repo, "nydus");
+        assert_eq!(&registry.namespace, "tenant");
         assert_eq!(registry.auth.as_ref().unwrap(), "auth");
         assert!(registry.skip_verify);
         assert_eq!(registry.timeout, 10);
         assert_eq!(registry.connect_timeout, 10);
         assert_eq!(registry.retry_limit, 5);
+        assert_eq!(registry.idle_timeout, 30);
         assert_eq!(registry.registry_token.as_ref().unwrap(), "bear_token");
         assert_eq!(registry.blob_url_scheme, "https");
         assert_eq!(registry.blob_redirected_host, "redirect.registry.com");
+        assert_eq!(
+            registry.blob_url_template,
+            "/v2/{repo}/blobs/sha256:{blob_id}"
+        );
 
         assert_eq!(&registry.proxy.url, "localhost:6789");
         assert_eq!(&registry.proxy.ping_url, "localhost:6789/ping");
@@ -1960,6 +2322,7 @@ mod tests {
         validate = true
         [cache.filecache]
         work_dir = "/tmp"
+        decompressed_lru_size = 1048576
         [cache.fscache]
         work_dir = "./"
         [cache.prefetch]
@@ -1980,6 +2343,7 @@ mod tests {
         assert!(cache.cache_validate);
         let filecache = cache.file_cache.as_ref().unwrap();
         assert_eq!(&filecache.work_dir, "/tmp");
+        assert_eq!(filecache.decompressed_lru_size, 1048576);
         let fscache = cache.fs_cache.as_ref().unwrap();
         assert_eq!(&fscache.work_dir, "./");
 
@@ -1997,16 +2361,36 @@ mod tests {
         mode = "direct"
         batch_size = 1000000
         validate = true
+        trusted_key = "/etc/nydus/trusted.pem"
+        mlock_metadata = true
+        max_metadata_size = 4294967296
+        auto_mode_threshold = 1048576
         enable_xattr = true
         iostats_files = true
         access_pattern = true
         latest_read_files = true
+        check_backend = true
+        subdir = "/etc"
+        amplification_warn_ratio = 64
+        flat_view = true
+        max_concurrent_backend_reads = 8
+        backend_failure_threshold = 10
+        backend_failure_cooldown = 30
+        dir_prefetch = true
+        disable_writeback_cache = true
+        disable_zero_message_open = true
+        negative_entry_timeout = 5
+        submounts = ["/data"]
+        [rafs.idmap]
+        uid_offset = 100000
+        gid_offset = 200000
         [rafs.prefetch]
         enable = true
         threads = 4
         batch_size = 1000000
         bandwidth_limit = 10000000
         prefetch_all = true
+        history_dir = "/var/lib/nydus/history"
         "#;
         let config: ConfigV2 = toml::from_str(content).unwrap();
         assert_eq!(config.version, 2);
@@ -2018,15 +2402,42 @@ mod tests {
         assert_eq!(&rafs.mode, "direct");
         assert_eq!(rafs.user_io_batch_size, 1000000);
         assert!(rafs.validate);
+        assert_eq!(&rafs.trusted_key, "/etc/nydus/trusted.pem");
+        assert!(rafs.mlock_metadata);
+        assert_eq!(rafs.max_metadata_size, 0x1_0000_0000);
+        assert_eq!(rafs.auto_mode_threshold, 0x100000);
         assert!(rafs.enable_xattr);
         assert!(rafs.iostats_files);
         assert!(rafs.access_pattern);
         assert!(rafs.latest_read_files);
+        assert!(rafs.check_backend);
+        assert_eq!(&rafs.subdir, "/etc");
+        assert_eq!(rafs.amplification_warn_ratio, 64);
+        assert!(rafs.flat_view);
+        assert_eq!(rafs.max_concurrent_backend_reads, 8);
+        assert_eq!(rafs.backend_failure_threshold, 10);
+        assert_eq!(rafs.backend_failure_cooldown, 30);
+        assert!(rafs.dir_prefetch);
+        assert!(rafs.disable_writeback_cache);
+        assert!(rafs.disable_zero_message_open);
+        assert_eq!(rafs.negative_entry_timeout, 5);
+        assert_eq!(rafs.submounts, vec!["/data".to_string()]);
+        assert_eq!(rafs.idmap.uid_offset, 100000);
+        assert_eq!(rafs.idmap.gid_offset, 200000);
         assert!(rafs.prefetch.enable);
         assert_eq!(rafs.prefetch.threads_count, 4);
         assert_eq!(rafs.prefetch.batch_size, 1000000);
         assert_eq!(rafs.prefetch.bandwidth_limit, 10000000);
-        assert!(rafs.prefetch.prefetch_all)
+        assert!(rafs.prefetch.prefetch_all);
+        assert_eq!(&rafs.prefetch.history_dir, "/var/lib/nydus/history");
+        assert!(rafs.validate());
+
+        let mut rafs = rafs.clone();
+        rafs.subdir = "etc".to_string();
+        assert!(!rafs.validate());
+        rafs.subdir = "/etc".to_string();
+        rafs.submounts = vec!["data".to_string()];
+        assert!(!rafs.validate());
     }
 
     #[test]
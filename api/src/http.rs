@@ -35,6 +35,50 @@ pub struct ApiMountCmd {
     /// List of files to prefetch.
     #[serde(default)]
     pub prefetch_files: Option<Vec<String>>,
+    /// Recreate the filesystem instead of migrating it on online upgrade.
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+/// Data chunk to be warmed up, identified as reported by the chunk layout of files.
+#[derive(Clone, Deserialize, Debug)]
+pub struct ApiWarmChunk {
+    /// Id of the data blob containing the chunk.
+    pub blob_id: String,
+    /// Offset of compressed chunk data in the data blob.
+    pub compressed_offset: u64,
+}
+
+/// Fetch data chunks of a mounted filesystem into the blob cache.
+#[derive(Clone, Default, Deserialize, Debug)]
+pub struct ApiWarmCmd {
+    /// Inode numbers of regular files, all data chunks of which will be fetched.
+    #[serde(default)]
+    pub inodes: Vec<u64>,
+    /// Data chunks to be fetched.
+    #[serde(default)]
+    pub chunks: Vec<ApiWarmChunk>,
+}
+
+/// Access pattern of a file, like advices of `posix_fadvise()`.
+#[derive(Clone, Copy, Deserialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiAccessAdvice {
+    /// No special access pattern.
+    Normal,
+    /// The file is expected to be read sequentially.
+    Sequential,
+    /// The file is expected to be read randomly.
+    Random,
+}
+
+/// Advise the access pattern of a file of a mounted filesystem.
+#[derive(Clone, Deserialize, Debug)]
+pub struct ApiFadviseCmd {
+    /// Absolute path of the file in the filesystem.
+    pub path: String,
+    /// Access pattern of the file.
+    pub advice: ApiAccessAdvice,
 }
 
 /// Umount a mounted filesystem.
@@ -51,6 +95,13 @@ pub struct DaemonConf {
     pub log_level: String,
 }
 
+/// Update the number of worker threads of a running daemon.
+#[derive(Clone, Deserialize, Debug)]
+pub struct DaemonThreadsConf {
+    /// Number of FUSE worker threads.
+    pub threads: u32,
+}
+
 /// Identifier for cached blob objects.
 ///
 /// Domains are used to control the blob sharing scope. All blobs associated with the same domain
@@ -69,8 +120,12 @@ pub struct BlobCacheObjectId {
 pub enum ApiRequest {
     /// Set daemon configuration.
     ConfigureDaemon(DaemonConf),
+    /// Set number of worker threads of a running daemon.
+    SetDaemonThreads(DaemonThreadsConf),
     /// Get daemon information.
     GetDaemonInfo,
+    /// Get daemon build and version information.
+    GetDaemonVersion,
     /// Get daemon global events.
     GetEvents,
     /// Stop the daemon.
@@ -89,6 +144,8 @@ pub enum ApiRequest {
     Remount(String, ApiMountCmd),
     /// Unmount a filesystem.
     Umount(String),
+    /// Check whether a filesystem could be mounted, without actually mounting it.
+    ValidateMount(ApiMountCmd),
 
     /// Get storage backend metrics.
     ExportBackendMetrics(Option<String>),
@@ -102,10 +159,20 @@ pub enum ApiRequest {
     ExportFsAccessPatterns(Option<String>),
     /// Get filesystem backend information.
     ExportFsBackendInfo(String),
+    /// Get effective configuration of mounted filesystems, with secrets redacted.
+    ExportFsConfig(Option<String>),
+    /// Start fetching data chunks of a mounted filesystem into the blob cache in background.
+    WarmFs(String, ApiWarmCmd),
+    /// Get progress of fetching data chunks of a mounted filesystem into the blob cache.
+    GetWarmFsStatus(String),
+    /// Advise the access pattern of a file of a mounted filesystem.
+    FadviseFs(String, ApiFadviseCmd),
     /// Get filesystem file metrics.
     ExportFsFilesMetrics(Option<String>, bool),
     /// Get information about filesystem inflight requests.
     ExportFsInflightMetrics,
+    /// Get number of slow filesystem requests.
+    ExportFsSlowOpsMetrics,
 
     // Nydus API v2
     /// Get daemon information excluding filesystem backends.
@@ -135,6 +202,10 @@ pub enum DaemonErrorKind {
     UpgradeManager(String),
     /// Unsupported requests.
     Unsupported,
+    /// Filesystem metadata is corrupted.
+    CorruptMetadata(String),
+    /// Filesystem format version is unsupported.
+    UnsupportedVersion(String),
 }
 
 /// Kinds for metrics related error messages.
@@ -193,6 +264,12 @@ pub enum ApiResponsePayload {
     FsBackendInfo(String),
     // Filesystem Inflight Requests, v1.
     FsInflightMetrics(String),
+    /// Number of slow filesystem requests, v1.
+    FsSlowOpsMetrics(String),
+    /// Effective configuration of mounted filesystems, v1.
+    FsConfig(String),
+    /// Progress of warming up data chunks of a mounted filesystem, v1.
+    FsWarm(String),
 
     /// List of blob objects, v2
     BlobObjectList(String),
@@ -237,14 +314,22 @@ pub enum HttpError {
     // Filesystem related errors (v1)
     /// Failed to get filesystem backend information
     FsBackendInfo(ApiError),
+    /// Failed to get effective filesystem configuration.
+    FsConfig(ApiError),
     /// Failed to get filesystem per-file metrics.
     FsFilesMetrics(ApiError),
     /// Failed to get global metrics.
     GlobalMetrics(ApiError),
     /// Failed to get information about inflight request
     InflightMetrics(ApiError),
+    /// Failed to get number of slow requests.
+    SlowOpsMetrics(ApiError),
     /// Failed to get filesystem file access trace.
     Pattern(ApiError),
+    /// Failed to warm up data chunks of a filesystem.
+    FsWarm(ApiError),
+    /// Failed to advise the access pattern of a file.
+    FsFadvise(ApiError),
 
     // Blob cache management related errors (v2)
     /// Failed to create blob object
@@ -160,6 +160,25 @@ impl EndpointHandler for MountHandler {
     }
 }
 
+/// Validate a filesystem mount request without mounting it.
+pub struct ValidateMountHandler {}
+impl EndpointHandler for ValidateMountHandler {
+    fn handle_request(
+        &self,
+        req: &Request,
+        kicker: &dyn Fn(ApiRequest) -> ApiResponse,
+    ) -> HttpResult {
+        match (req.method(), req.body.as_ref()) {
+            (Method::Post, Some(body)) => {
+                let cmd = parse_body(body)?;
+                let r = kicker(ApiRequest::ValidateMount(cmd));
+                Ok(convert_to_response(r, HttpError::Mount))
+            }
+            _ => Err(HttpError::BadRequest),
+        }
+    }
+}
+
 /// Send fuse fd to new daemon.
 pub struct SendFuseFdHandler {}
 impl EndpointHandler for SendFuseFdHandler {
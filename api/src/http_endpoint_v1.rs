@@ -33,7 +33,10 @@ fn convert_to_response<O: FnOnce(ApiError) -> HttpError>(api_resp: ApiResponse,
                 FsFilesMetrics(d) => success_response(Some(d)),
                 FsFilesPatterns(d) => success_response(Some(d)),
                 FsBackendInfo(d) => success_response(Some(d)),
+                FsConfig(d) => success_response(Some(d)),
+                FsWarm(d) => success_response(Some(d)),
                 FsInflightMetrics(d) => success_response(Some(d)),
+                FsSlowOpsMetrics(d) => success_response(Some(d)),
                 _ => panic!("Unexpected response message from API service"),
             }
         }
@@ -62,6 +65,29 @@ impl EndpointHandler for InfoHandler {
                 let r = kicker(ApiRequest::ConfigureDaemon(conf));
                 Ok(convert_to_response(r, HttpError::Configure))
             }
+            (Method::Patch, Some(body)) => {
+                let conf = parse_body(body)?;
+                let r = kicker(ApiRequest::SetDaemonThreads(conf));
+                Ok(convert_to_response(r, HttpError::Configure))
+            }
+            _ => Err(HttpError::BadRequest),
+        }
+    }
+}
+
+/// Get daemon build and version information.
+pub struct VersionHandler {}
+impl EndpointHandler for VersionHandler {
+    fn handle_request(
+        &self,
+        req: &Request,
+        kicker: &dyn Fn(ApiRequest) -> ApiResponse,
+    ) -> HttpResult {
+        match (req.method(), req.body.as_ref()) {
+            (Method::Get, None) => {
+                let r = kicker(ApiRequest::GetDaemonVersion);
+                Ok(convert_to_response(r, HttpError::DaemonInfo))
+            }
             _ => Err(HttpError::BadRequest),
         }
     }
@@ -90,6 +116,73 @@ impl EndpointHandler for FsBackendInfo {
     }
 }
 
+/// Get effective configuration of mounted filesystems, with secrets redacted.
+pub struct FsConfigHandler {}
+impl EndpointHandler for FsConfigHandler {
+    fn handle_request(
+        &self,
+        req: &Request,
+        kicker: &dyn Fn(ApiRequest) -> ApiResponse,
+    ) -> HttpResult {
+        match (req.method(), req.body.as_ref()) {
+            (Method::Get, None) => {
+                let mountpoint = extract_query_part(req, "mountpoint");
+                let r = kicker(ApiRequest::ExportFsConfig(mountpoint));
+                Ok(convert_to_response(r, HttpError::FsConfig))
+            }
+            _ => Err(HttpError::BadRequest),
+        }
+    }
+}
+
+/// Fetch data chunks of a mounted filesystem into the blob cache, or query the progress.
+pub struct FsWarmHandler {}
+impl EndpointHandler for FsWarmHandler {
+    fn handle_request(
+        &self,
+        req: &Request,
+        kicker: &dyn Fn(ApiRequest) -> ApiResponse,
+    ) -> HttpResult {
+        let mountpoint = extract_query_part(req, "mountpoint").ok_or_else(|| {
+            HttpError::QueryString("'mountpoint' should be specified in query string".to_string())
+        })?;
+        match (req.method(), req.body.as_ref()) {
+            (Method::Put, Some(body)) => {
+                let cmd = parse_body(body)?;
+                let r = kicker(ApiRequest::WarmFs(mountpoint, cmd));
+                Ok(convert_to_response(r, HttpError::FsWarm))
+            }
+            (Method::Get, None) => {
+                let r = kicker(ApiRequest::GetWarmFsStatus(mountpoint));
+                Ok(convert_to_response(r, HttpError::FsWarm))
+            }
+            _ => Err(HttpError::BadRequest),
+        }
+    }
+}
+
+/// Advise the access pattern of a file of a mounted filesystem.
+pub struct FsFadviseHandler {}
+impl EndpointHandler for FsFadviseHandler {
+    fn handle_request(
+        &self,
+        req: &Request,
+        kicker: &dyn Fn(ApiRequest) -> ApiResponse,
+    ) -> HttpResult {
+        let mountpoint = extract_query_part(req, "mountpoint").ok_or_else(|| {
+            HttpError::QueryString("'mountpoint' should be specified in query string".to_string())
+        })?;
+        match (req.method(), req.body.as_ref()) {
+            (Method::Put, Some(body)) => {
+                let cmd = parse_body(body)?;
+                let r = kicker(ApiRequest::FadviseFs(mountpoint, cmd));
+                Ok(convert_to_response(r, HttpError::FsFadvise))
+            }
+            _ => Err(HttpError::BadRequest),
+        }
+    }
+}
+
 /// Get filesystem global metrics.
 pub struct MetricsFsGlobalHandler {}
 impl EndpointHandler for MetricsFsGlobalHandler {
@@ -166,3 +259,21 @@ impl EndpointHandler for MetricsFsInflightHandler {
         }
     }
 }
+
+/// Get number of slow filesystem requests.
+pub struct MetricsFsSlowOpsHandler {}
+impl EndpointHandler for MetricsFsSlowOpsHandler {
+    fn handle_request(
+        &self,
+        req: &Request,
+        kicker: &dyn Fn(ApiRequest) -> ApiResponse,
+    ) -> HttpResult {
+        match (req.method(), req.body.as_ref()) {
+            (Method::Get, None) => {
+                let r = kicker(ApiRequest::ExportFsSlowOpsMetrics);
+                Ok(convert_to_response(r, HttpError::SlowOpsMetrics))
+            }
+            _ => Err(HttpError::BadRequest),
+        }
+    }
+}
@@ -21,11 +21,12 @@ use crate::http::{
 };
 use crate::http_endpoint_common::{
     EventsHandler, ExitHandler, MetricsBackendHandler, MetricsBlobcacheHandler, MountHandler,
-    SendFuseFdHandler, StartHandler, TakeoverFuseFdHandler,
+    SendFuseFdHandler, StartHandler, TakeoverFuseFdHandler, ValidateMountHandler,
 };
 use crate::http_endpoint_v1::{
-    FsBackendInfo, InfoHandler, MetricsFsAccessPatternHandler, MetricsFsFilesHandler,
-    MetricsFsGlobalHandler, MetricsFsInflightHandler, HTTP_ROOT_V1,
+    FsBackendInfo, FsConfigHandler, FsFadviseHandler, FsWarmHandler, InfoHandler,
+    MetricsFsAccessPatternHandler, MetricsFsFilesHandler, MetricsFsGlobalHandler,
+    MetricsFsInflightHandler, MetricsFsSlowOpsHandler, VersionHandler, HTTP_ROOT_V1,
 };
 use crate::http_endpoint_v2::{BlobObjectListHandlerV2, InfoV2Handler, HTTP_ROOT_V2};
 
@@ -68,8 +69,12 @@ pub(crate) fn translate_status_code(e: &ApiError) -> StatusCode {
     match e {
         ApiError::DaemonAbnormal(kind) | ApiError::MountFilesystem(kind) => match kind {
             DaemonErrorKind::NotReady => StatusCode::ServiceUnavailable,
-            DaemonErrorKind::Unsupported => StatusCode::NotImplemented,
-            DaemonErrorKind::UnexpectedEvent(_) => StatusCode::BadRequest,
+            DaemonErrorKind::Unsupported | DaemonErrorKind::UnsupportedVersion(_) => {
+                StatusCode::NotImplemented
+            }
+            DaemonErrorKind::UnexpectedEvent(_) | DaemonErrorKind::CorruptMetadata(_) => {
+                StatusCode::BadRequest
+            }
             _ => StatusCode::InternalServerError,
         },
         ApiError::Metrics(MetricsErrorKind::Stats(MetricsError::NoCounter)) => StatusCode::NotFound,
@@ -147,16 +152,22 @@ lazy_static! {
         r.routes.insert(endpoint_v1!("/daemon/fuse/sendfd"), Box::new(SendFuseFdHandler{}));
         r.routes.insert(endpoint_v1!("/daemon/fuse/takeover"), Box::new(TakeoverFuseFdHandler{}));
         r.routes.insert(endpoint_v1!("/mount"), Box::new(MountHandler{}));
+        r.routes.insert(endpoint_v1!("/mount/validate"), Box::new(ValidateMountHandler{}));
         r.routes.insert(endpoint_v1!("/metrics/backend"), Box::new(MetricsBackendHandler{}));
         r.routes.insert(endpoint_v1!("/metrics/blobcache"), Box::new(MetricsBlobcacheHandler{}));
 
         // Nydus API, v1
         r.routes.insert(endpoint_v1!("/daemon"), Box::new(InfoHandler{}));
         r.routes.insert(endpoint_v1!("/daemon/backend"), Box::new(FsBackendInfo{}));
+        r.routes.insert(endpoint_v1!("/daemon/config"), Box::new(FsConfigHandler{}));
+        r.routes.insert(endpoint_v1!("/daemon/fadvise"), Box::new(FsFadviseHandler{}));
+        r.routes.insert(endpoint_v1!("/daemon/version"), Box::new(VersionHandler{}));
+        r.routes.insert(endpoint_v1!("/daemon/warm"), Box::new(FsWarmHandler{}));
         r.routes.insert(endpoint_v1!("/metrics"), Box::new(MetricsFsGlobalHandler{}));
         r.routes.insert(endpoint_v1!("/metrics/files"), Box::new(MetricsFsFilesHandler{}));
         r.routes.insert(endpoint_v1!("/metrics/inflight"), Box::new(MetricsFsInflightHandler{}));
         r.routes.insert(endpoint_v1!("/metrics/pattern"), Box::new(MetricsFsAccessPatternHandler{}));
+        r.routes.insert(endpoint_v1!("/metrics/slow_ops"), Box::new(MetricsFsSlowOpsHandler{}));
 
         // Nydus API, v2
         r.routes.insert(endpoint_v2!("/daemon"), Box::new(InfoV2Handler{}));
@@ -324,11 +335,40 @@ mod tests {
     use std::sync::mpsc::channel;
     use vmm_sys_util::tempfile::TempFile;
 
+    #[test]
+    fn test_translate_status_code() {
+        let status = |kind: DaemonErrorKind| translate_status_code(&ApiError::DaemonAbnormal(kind));
+
+        assert_eq!(
+            status(DaemonErrorKind::NotReady),
+            StatusCode::ServiceUnavailable
+        );
+        assert_eq!(
+            status(DaemonErrorKind::Unsupported),
+            StatusCode::NotImplemented
+        );
+        assert_eq!(
+            status(DaemonErrorKind::CorruptMetadata("test".to_string())),
+            StatusCode::BadRequest
+        );
+        assert_eq!(
+            status(DaemonErrorKind::UnsupportedVersion("test".to_string())),
+            StatusCode::NotImplemented
+        );
+        assert_eq!(
+            status(DaemonErrorKind::Other("test".to_string())),
+            StatusCode::InternalServerError
+        );
+    }
+
     #[test]
     fn test_http_api_routes_v1() {
         assert!(HTTP_ROUTES.routes.get("/api/v1/daemon").is_some());
         assert!(HTTP_ROUTES.routes.get("/api/v1/daemon/events").is_some());
         assert!(HTTP_ROUTES.routes.get("/api/v1/daemon/backend").is_some());
+        assert!(HTTP_ROUTES.routes.get("/api/v1/daemon/config").is_some());
+        assert!(HTTP_ROUTES.routes.get("/api/v1/daemon/version").is_some());
+        assert!(HTTP_ROUTES.routes.get("/api/v1/daemon/warm").is_some());
         assert!(HTTP_ROUTES.routes.get("/api/v1/daemon/start").is_some());
         assert!(HTTP_ROUTES.routes.get("/api/v1/daemon/exit").is_some());
         assert!(HTTP_ROUTES
@@ -340,6 +380,7 @@ mod tests {
             .get("/api/v1/daemon/fuse/takeover")
             .is_some());
         assert!(HTTP_ROUTES.routes.get("/api/v1/mount").is_some());
+        assert!(HTTP_ROUTES.routes.get("/api/v1/mount/validate").is_some());
         assert!(HTTP_ROUTES.routes.get("/api/v1/metrics").is_some());
         assert!(HTTP_ROUTES.routes.get("/api/v1/metrics/files").is_some());
         assert!(HTTP_ROUTES.routes.get("/api/v1/metrics/pattern").is_some());
@@ -349,6 +390,7 @@ mod tests {
             .get("/api/v1/metrics/blobcache")
             .is_some());
         assert!(HTTP_ROUTES.routes.get("/api/v1/metrics/inflight").is_some());
+        assert!(HTTP_ROUTES.routes.get("/api/v1/metrics/slow_ops").is_some());
     }
 
     #[test]
@@ -45,3 +45,11 @@ pub struct BuildTimeInfo {
     pub profile: String,
     pub rustc: String,
 }
+
+impl BuildTimeInfo {
+    /// Dump build and version information as a JSON object.
+    pub fn dump_json(&self) -> String {
+        // Serializing a struct of strings never fails.
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
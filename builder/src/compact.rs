@@ -147,9 +147,16 @@ impl ChunkSet {
                 .get_reader(&ori_blob_ids[blob_idx as usize])
                 .expect("get blob err");
             let mut buf = alloc_buf(chunk.compressed_size() as usize);
-            reader
+            let size = reader
                 .read(&mut buf, chunk.compressed_offset())
                 .expect("read blob data err");
+            ensure!(
+                size == buf.len(),
+                "compactor: got {} bytes of chunk data from blob {}, expect {}",
+                size,
+                ori_blob_ids[blob_idx as usize],
+                buf.len()
+            );
             blob_writer.write_all(&buf)?;
 
             let mut new_chunk = chunk.clone();
@@ -3,18 +3,25 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::ErrorKind;
 use std::mem::size_of;
+use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
 
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use hex::FromHex;
+use nix::fcntl::{flock, FlockArg};
 use nydus_api::ConfigV2;
 use nydus_rafs::metadata::chunk::ChunkWrapper;
 use nydus_rafs::metadata::layout::v5::RafsV5ChunkInfo;
 use nydus_rafs::metadata::{RafsSuper, RafsSuperConfig};
-use nydus_storage::device::BlobInfo;
+use nydus_storage::device::{BlobChunkFlags, BlobFeatures, BlobInfo};
 use nydus_utils::digest::{self, RafsDigest};
+use nydus_utils::{compress, crypt};
+use serde::{Deserialize, Serialize};
 
 use crate::Tree;
 
@@ -208,6 +215,304 @@ impl HashChunkDict {
     }
 }
 
+/// Data blob recorded in a [DedupIndex], with fields needed to rebuild its `BlobInfo`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct DedupBlob {
+    blob_id: String,
+    features: u32,
+    compressed_size: u64,
+    uncompressed_size: u64,
+    chunk_size: u32,
+    chunk_count: u32,
+    compressor: u32,
+    digester: u32,
+    meta_ci_compressor: u32,
+    meta_ci_offset: u64,
+    meta_ci_compressed_size: u64,
+    meta_ci_uncompressed_size: u64,
+    blob_toc_digest: String,
+    blob_toc_size: u32,
+    blob_meta_digest: String,
+    blob_meta_size: u64,
+}
+
+impl DedupBlob {
+    fn from_blob_info(blob: &BlobInfo) -> Self {
+        DedupBlob {
+            blob_id: blob.raw_blob_id().to_string(),
+            features: blob.features().bits(),
+            compressed_size: blob.compressed_size(),
+            uncompressed_size: blob.uncompressed_size(),
+            chunk_size: blob.chunk_size(),
+            chunk_count: blob.chunk_count(),
+            compressor: blob.compressor() as u32,
+            digester: blob.digester() as u32,
+            meta_ci_compressor: blob.meta_ci_compressor() as u32,
+            meta_ci_offset: blob.meta_ci_offset(),
+            meta_ci_compressed_size: blob.meta_ci_compressed_size(),
+            meta_ci_uncompressed_size: blob.meta_ci_uncompressed_size(),
+            blob_toc_digest: hex::encode(blob.blob_toc_digest()),
+            blob_toc_size: blob.blob_toc_size(),
+            blob_meta_digest: hex::encode(blob.blob_meta_digest()),
+            blob_meta_size: blob.blob_meta_size(),
+        }
+    }
+
+    fn to_blob_info(&self, blob_index: u32) -> Result<BlobInfo> {
+        let features = BlobFeatures::from_bits(self.features)
+            .ok_or_else(|| anyhow!("invalid blob features 0x{:x}", self.features))?;
+        let compressor = compress::Algorithm::try_from(self.compressor)
+            .map_err(|_| anyhow!("invalid blob compressor {}", self.compressor))?;
+        let digester = digest::Algorithm::try_from(self.digester)
+            .map_err(|_| anyhow!("invalid blob digester {}", self.digester))?;
+        let mut blob = BlobInfo::new(
+            blob_index,
+            self.blob_id.clone(),
+            self.uncompressed_size,
+            self.compressed_size,
+            self.chunk_size,
+            self.chunk_count,
+            features,
+        );
+        blob.set_compressor(compressor);
+        blob.set_digester(digester);
+        blob.set_blob_meta_info(
+            self.meta_ci_offset,
+            self.meta_ci_compressed_size,
+            self.meta_ci_uncompressed_size,
+            self.meta_ci_compressor,
+        );
+        blob.set_blob_toc_digest(<[u8; 32]>::from_hex(&self.blob_toc_digest)?);
+        blob.set_blob_toc_size(self.blob_toc_size);
+        blob.set_blob_meta_digest(<[u8; 32]>::from_hex(&self.blob_meta_digest)?);
+        blob.set_blob_meta_size(self.blob_meta_size);
+        Ok(blob)
+    }
+}
+
+/// Location of chunk data recorded in a [DedupIndex].
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct DedupChunk {
+    /// Index into blobs of the dedup index.
+    blob: u32,
+    index: u32,
+    flags: u32,
+    compressed_offset: u64,
+    compressed_size: u32,
+    uncompressed_offset: u64,
+    uncompressed_size: u32,
+}
+
+/// A persistent chunk index shared by images for data deduplication across images.
+///
+/// The index maps digests of chunks to their locations in data blobs, so chunks already stored
+/// in data blobs of other images won't be written again. Chunks of encrypted blobs are not
+/// recorded because the index doesn't keep encryption keys.
+///
+/// The index is stored as a JSON file and updated under an exclusive lock on `<path>.lock`, so
+/// concurrent builds may share it.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct DedupIndex {
+    #[serde(skip)]
+    path: PathBuf,
+    version: u32,
+    digester: u32,
+    chunk_size: u32,
+    blobs: Vec<DedupBlob>,
+    chunks: BTreeMap<String, DedupChunk>,
+}
+
+impl DedupIndex {
+    /// Load the index from `path`, or create an empty one if the file doesn't exist.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut index: DedupIndex = match fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .with_context(|| format!("failed to parse dedup index file {:?}", path))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => DedupIndex::default(),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to read dedup index file {:?}", path))
+            }
+        };
+        index.path = path.to_path_buf();
+        Ok(index)
+    }
+
+    /// Record chunks of a newly built bootstrap into the index file at `path`.
+    ///
+    /// The index is reloaded and written back while holding the index lock, so chunks recorded
+    /// by concurrent builds are kept.
+    pub fn update(
+        path: &Path,
+        bootstrap: &Path,
+        config: Arc<ConfigV2>,
+        rafs_config: &RafsSuperConfig,
+    ) -> Result<()> {
+        let _lock = Self::lock(path)?;
+        let mut index = Self::load(path)?;
+        index.add_bootstrap(bootstrap, config, rafs_config)?;
+        index.save()
+    }
+
+    /// Get number of chunks recorded in the index.
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Get number of data blobs recorded in the index.
+    pub fn blob_count(&self) -> usize {
+        self.blobs.len()
+    }
+
+    /// Record chunks from a bootstrap into the index.
+    pub fn add_bootstrap(
+        &mut self,
+        path: &Path,
+        config: Arc<ConfigV2>,
+        rafs_config: &RafsSuperConfig,
+    ) -> Result<()> {
+        if self.blobs.is_empty() {
+            self.version = u32::from(rafs_config.version);
+            self.digester = rafs_config.digester as u32;
+            self.chunk_size = rafs_config.chunk_size;
+        } else {
+            self.check_compatibility(rafs_config)?;
+        }
+
+        let dict = HashChunkDict::from_bootstrap_file(path, config, rafs_config)?;
+        let mut idx_map = HashMap::new();
+        for blob in dict.blobs.iter() {
+            if blob.cipher() != crypt::Algorithm::None || blob.is_legacy_stargz() {
+                continue;
+            }
+            let idx = match self
+                .blobs
+                .iter()
+                .position(|b| b.blob_id == blob.raw_blob_id())
+            {
+                Some(idx) => idx,
+                None => {
+                    self.blobs.push(DedupBlob::from_blob_info(blob));
+                    self.blobs.len() - 1
+                }
+            };
+            idx_map.insert(blob.blob_index(), idx as u32);
+        }
+
+        for (digest, (chunk, _)) in dict.m.iter() {
+            let blob = match idx_map.get(&chunk.blob_index()) {
+                Some(idx) => *idx,
+                None => continue,
+            };
+            let mut flags = BlobChunkFlags::empty();
+            match chunk.compressor() {
+                Some(algo) => flags.set_compressor(algo),
+                None => flags.set(BlobChunkFlags::COMPRESSED, chunk.is_compressed()),
+            }
+            flags.set(BlobChunkFlags::BATCH, chunk.is_batch());
+            self.chunks
+                .entry(hex::encode(digest.data))
+                .or_insert(DedupChunk {
+                    blob,
+                    index: chunk.index(),
+                    flags: flags.bits(),
+                    compressed_offset: chunk.compressed_offset(),
+                    compressed_size: chunk.compressed_size(),
+                    uncompressed_offset: chunk.uncompressed_offset(),
+                    uncompressed_size: chunk.uncompressed_size(),
+                });
+        }
+
+        Ok(())
+    }
+
+    /// Build a chunk dictionary from chunks recorded in the index.
+    pub fn to_chunk_dict(&self, rafs_config: &RafsSuperConfig) -> Result<HashChunkDict> {
+        let mut dict = HashChunkDict::new(rafs_config.digester);
+        if self.blobs.is_empty() {
+            return Ok(dict);
+        }
+        self.check_compatibility(rafs_config)?;
+
+        for (idx, blob) in self.blobs.iter().enumerate() {
+            let blob = blob
+                .to_blob_info(idx as u32)
+                .with_context(|| format!("invalid blob {} in dedup index", blob.blob_id))?;
+            dict.blobs.push(Arc::new(blob));
+        }
+        for (digest, c) in self.chunks.iter() {
+            if c.blob as usize >= self.blobs.len() {
+                bail!("invalid blob index {} for chunk {}", c.blob, digest);
+            }
+            let id = <[u8; 32]>::from_hex(digest)
+                .with_context(|| format!("invalid chunk digest {} in dedup index", digest))?;
+            let flags = BlobChunkFlags::from_bits_truncate(c.flags);
+            let mut chunk = ChunkWrapper::new(rafs_config.version);
+            chunk.set_id(RafsDigest { data: id });
+            chunk.set_chunk_info(
+                c.blob,
+                c.index,
+                0,
+                c.uncompressed_offset,
+                c.uncompressed_size,
+                c.compressed_offset,
+                c.compressed_size,
+                flags.contains(BlobChunkFlags::COMPRESSED),
+                false,
+            )?;
+            if let Some(algo) = flags.compressor() {
+                chunk.set_compressor(algo);
+            }
+            chunk.set_batch(flags.contains(BlobChunkFlags::BATCH));
+            dict.add_chunk(Arc::new(chunk), rafs_config.digester);
+        }
+
+        Ok(dict)
+    }
+
+    fn check_compatibility(&self, rafs_config: &RafsSuperConfig) -> Result<()> {
+        if self.version != u32::from(rafs_config.version)
+            || self.digester != rafs_config.digester as u32
+            || self.chunk_size != rafs_config.chunk_size
+        {
+            bail!(
+                "inconsistent configuration with dedup index {:?}, version {} digester {} chunk size 0x{:x}",
+                self.path,
+                self.version,
+                self.digester,
+                self.chunk_size
+            );
+        }
+        Ok(())
+    }
+
+    /// Write the index back to its file, the caller must hold the index lock.
+    fn save(&self) -> Result<()> {
+        let data = serde_json::to_vec(self).context("failed to serialize dedup index")?;
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(format!(".{}.tmp", std::process::id()));
+        fs::write(&tmp, data)
+            .and_then(|_| fs::rename(&tmp, &self.path))
+            .with_context(|| format!("failed to write dedup index file {:?}", self.path))
+    }
+
+    /// Take an exclusive lock on `<path>.lock`, which is released when the file is closed.
+    ///
+    /// The index file itself is replaced by rename, so it can't be used for locking.
+    fn lock(path: &Path) -> Result<File> {
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(&lock_path)
+            .with_context(|| format!("failed to open dedup index lock file {:?}", lock_path))?;
+        flock(file.as_raw_fd(), FlockArg::LockExclusive)
+            .with_context(|| format!("failed to lock dedup index file {:?}", path))?;
+        Ok(file)
+    }
+}
+
 /// Parse a chunk dictionary argument string.
 ///
 /// # Argument
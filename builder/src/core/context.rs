@@ -17,22 +17,25 @@ use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::{fmt, fs};
 
-use anyhow::{anyhow, Context, Error, Result};
+use anyhow::{anyhow, bail, Context, Error, Result};
 use nydus_utils::crypt::{self, Cipher, CipherContext};
 use sha2::{Digest, Sha256};
 use tar::{EntryType, Header};
 use vmm_sys_util::tempfile::TempFile;
 
-use nydus_api::ConfigV2;
+use nydus_api::{ConfigV2, LocalFsConfig};
 use nydus_rafs::metadata::chunk::ChunkWrapper;
+use nydus_rafs::metadata::encoding::MetadataWriter;
 use nydus_rafs::metadata::layout::v5::RafsV5BlobTable;
 use nydus_rafs::metadata::layout::v6::{
     RafsV6BlobTable, EROFS_BLOCK_SIZE_4096, EROFS_INODE_SLOT_SIZE,
 };
 use nydus_rafs::metadata::layout::RafsBlobTable;
-use nydus_rafs::metadata::{Inode, RAFS_DEFAULT_CHUNK_SIZE};
+use nydus_rafs::metadata::{Inode, RAFS_DEFAULT_CHUNK_SIZE, RAFS_MAX_CHUNK_SIZE};
 use nydus_rafs::metadata::{RafsSuperFlags, RafsVersion};
 use nydus_rafs::RafsIoWrite;
+use nydus_storage::backend::localfs::LocalFs;
+use nydus_storage::backend::BlobBackend;
 use nydus_storage::device::{BlobFeatures, BlobInfo};
 use nydus_storage::factory::BlobFactory;
 use nydus_storage::meta::toc::{TocEntryList, TocLocation};
@@ -41,14 +44,17 @@ use nydus_storage::meta::{
     BlobMetaChunkArray, BlobMetaChunkInfo, ZranContextGenerator,
 };
 use nydus_utils::digest::DigestData;
-use nydus_utils::{compress, digest, div_round_up, round_down, try_round_up_4k, BufReaderInfo};
+use nydus_utils::{compress, digest, div_round_up, round_down, try_round_up_4k};
 
 use super::node::ChunkSource;
+use super::reader::BufReaderInfo;
 use crate::core::tree::TreeNode;
 use crate::{ChunkDict, Feature, Features, HashChunkDict, Prefetch, PrefetchPolicy, WhiteoutSpec};
 
 // TODO: select BufWriter capacity by performance testing.
 pub const BUF_WRITER_CAPACITY: usize = 2 << 17;
+/// Minimum chunk size supported by the builder.
+pub const RAFS_MIN_CHUNK_SIZE: u32 = 0x1000;
 
 /// Filesystem conversion type supported by RAFS builder.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -118,6 +124,67 @@ impl fmt::Display for ConversionType {
     }
 }
 
+/// Mapping of a range of host user or group ids to ids in the generated image.
+///
+/// It's in format `<image_id>:<host_id>:<count>`, like the user namespace mapping of container
+/// runtimes, so `0:100000:65536` maps host ids [100000, 165536) to [0, 65536) in the image.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct IdMapping {
+    /// First id of the range in the image.
+    pub image_id: u32,
+    /// First id of the range on the host.
+    pub host_id: u32,
+    /// Number of ids in the range.
+    pub count: u32,
+}
+
+impl IdMapping {
+    /// Map a host id into the image, returning `None` if it's out of the range.
+    pub fn map(&self, id: u32) -> Option<u32> {
+        if id >= self.host_id && (id - self.host_id) < self.count {
+            Some(self.image_id + (id - self.host_id))
+        } else {
+            None
+        }
+    }
+
+    /// Map a host id by the first matching mapping, ids without matching mapping are kept.
+    pub fn map_id(mappings: &[IdMapping], id: u32) -> u32 {
+        mappings.iter().find_map(|m| m.map(id)).unwrap_or(id)
+    }
+}
+
+impl FromStr for IdMapping {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let fields = s
+            .split(':')
+            .map(|v| v.trim().parse::<u32>())
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("invalid id mapping '{}', {}", s, e))?;
+        if fields.len() != 3 {
+            bail!(
+                "invalid id mapping '{}', expect <image_id>:<host_id>:<count>",
+                s
+            );
+        }
+        let (image_id, host_id, count) = (fields[0], fields[1], fields[2]);
+        if count == 0
+            || image_id.checked_add(count - 1).is_none()
+            || host_id.checked_add(count - 1).is_none()
+        {
+            bail!("invalid id mapping '{}', id range is empty or overflows", s);
+        }
+
+        Ok(IdMapping {
+            image_id,
+            host_id,
+            count,
+        })
+    }
+}
+
 impl ConversionType {
     /// Check whether the generated image references the original OCI image data.
     pub fn is_to_ref(&self) -> bool {
@@ -354,6 +421,19 @@ impl ArtifactWriter {
     }
 }
 
+// Check whether blob `name` of `size` bytes is already present in directory `dir`, through the
+// localfs storage backend.
+fn is_blob_present(dir: &Path, name: &str, size: u64) -> bool {
+    let config = LocalFsConfig {
+        dir: dir.display().to_string(),
+        ..Default::default()
+    };
+    match LocalFs::new(&config, Some(&format!("artifact-{}", name))) {
+        Ok(backend) => backend.has_blob(name, size),
+        Err(_) => false,
+    }
+}
+
 impl Artifact for ArtifactWriter {
     /// Get the current write position.
     fn pos(&self) -> Result<u64> {
@@ -368,9 +448,12 @@ impl Artifact for ArtifactWriter {
 
         if let Some(n) = name {
             if let ArtifactStorage::FileDir(s) = &self.storage {
-                let path = Path::new(s).join(n);
-                if !path.exists() {
-                    if let Some(tmp_file) = &self.tmp_file {
+                let path = Path::new(s).join(&n);
+                if let Some(tmp_file) = &self.tmp_file {
+                    let size = tmp_file.as_file().metadata()?.len();
+                    if is_blob_present(s, &n, size) {
+                        info!("blob {} already present in {}", n, s.display());
+                    } else {
                         rename(tmp_file.as_path(), &path).with_context(|| {
                             format!(
                                 "failed to rename blob {:?} to {:?}",
@@ -597,6 +680,9 @@ impl BlobContext {
         blob_ctx
             .blob_meta_header
             .set_encrypted(features.contains(BlobFeatures::ENCRYPTED));
+        blob_ctx
+            .blob_meta_header
+            .set_chunk_compressor(features.contains(BlobFeatures::CHUNK_COMPRESSOR));
 
         blob_ctx
     }
@@ -788,6 +874,7 @@ impl BlobContext {
                             chunk.is_encrypted(),
                             chunk.is_batch(),
                             0,
+                            chunk.compressor(),
                         );
                     }
                     self.blob_chunk_digest.push(chunk.id().data);
@@ -1225,6 +1312,7 @@ impl BootstrapContext {
 pub struct BootstrapManager {
     pub(crate) f_parent_path: Option<PathBuf>,
     pub(crate) bootstrap_storage: Option<ArtifactStorage>,
+    pub(crate) bootstrap_compressor: compress::Algorithm,
 }
 
 impl BootstrapManager {
@@ -1233,12 +1321,27 @@ impl BootstrapManager {
         Self {
             f_parent_path: f_parent_path.map(PathBuf::from),
             bootstrap_storage,
+            bootstrap_compressor: compress::Algorithm::None,
         }
     }
 
+    /// Set algorithm to compress generated bootstraps, see [MetadataWriter].
+    pub fn set_compressor(&mut self, compressor: compress::Algorithm) {
+        self.bootstrap_compressor = compressor;
+    }
+
     /// Create a new instance of [BootstrapContext]
     pub fn create_ctx(&self) -> Result<BootstrapContext> {
-        BootstrapContext::new(self.bootstrap_storage.clone(), self.f_parent_path.is_some())
+        let mut ctx =
+            BootstrapContext::new(self.bootstrap_storage.clone(), self.f_parent_path.is_some())?;
+        if !self.bootstrap_compressor.is_none() {
+            let writer = std::mem::replace(
+                &mut ctx.writer,
+                Box::<ArtifactMemoryWriter>::default() as Box<dyn RafsIoWrite>,
+            );
+            ctx.writer = Box::new(MetadataWriter::new(writer, self.bootstrap_compressor));
+        }
+        Ok(ctx)
     }
 }
 
@@ -1254,18 +1357,34 @@ pub struct BuildContext {
     pub blob_offset: u64,
     /// Blob chunk compress flag.
     pub compressor: compress::Algorithm,
+    /// Alternative algorithm to compress data chunks, the smaller output is kept for each chunk.
+    pub chunk_compressor: Option<compress::Algorithm>,
     /// Inode and chunk digest algorithm flag.
     pub digester: digest::Algorithm,
     /// Blob encryption algorithm flag.
     pub cipher: crypt::Algorithm,
     /// Save host uid gid in each inode.
     pub explicit_uidgid: bool,
+    /// Permission bits to clear from modes of files and directories, like umask.
+    pub mode_mask: u32,
+    /// Permission bits to replace modes of directories with.
+    pub force_dir_mode: Option<u32>,
+    /// Permission bits to replace modes of regular files with.
+    pub force_file_mode: Option<u32>,
+    /// Mappings to remap host user ids to user ids in the image.
+    pub uid_map: Vec<IdMapping>,
+    /// Mappings to remap host group ids to group ids in the image.
+    pub gid_map: Vec<IdMapping>,
     /// whiteout spec: overlayfs or oci
     pub whiteout_spec: WhiteoutSpec,
     /// Chunk slice size.
     pub chunk_size: u32,
     /// Batch chunk data size.
     pub batch_size: u32,
+    /// Minimum size of chunks generated by content-defined chunking.
+    pub min_chunk_size: u32,
+    /// Maximum size of chunks generated by content-defined chunking.
+    pub max_chunk_size: u32,
     /// Version number of output metadata and data blob.
     pub fs_version: RafsVersion,
     /// Whether any directory/file has extended attributes.
@@ -1337,13 +1456,21 @@ impl BuildContext {
             aligned_chunk,
             blob_offset,
             compressor,
+            chunk_compressor: None,
             digester,
             cipher,
             explicit_uidgid,
+            mode_mask: 0,
+            force_dir_mode: None,
+            force_file_mode: None,
+            uid_map: Vec::new(),
+            gid_map: Vec::new(),
             whiteout_spec,
 
             chunk_size: RAFS_DEFAULT_CHUNK_SIZE as u32,
             batch_size: 0,
+            min_chunk_size: RAFS_MIN_CHUNK_SIZE,
+            max_chunk_size: RAFS_MAX_CHUNK_SIZE as u32,
             fs_version: RafsVersion::default(),
 
             conversion_type,
@@ -1376,6 +1503,111 @@ impl BuildContext {
         self.batch_size = batch_size;
     }
 
+    /// Set an alternative algorithm to compress data chunks besides the blob compressor.
+    ///
+    /// Chunks compressed by it are recorded in chunk flags and blob meta, so the generated blobs
+    /// are marked with `BlobFeatures::CHUNK_COMPRESSOR` to be rejected by old readers.
+    pub fn set_chunk_compressor(&mut self, algo: compress::Algorithm) -> Result<()> {
+        if algo.is_none() || algo == self.compressor {
+            bail!(
+                "chunk compressor {} must differ from blob compressor {} and not be none",
+                algo,
+                self.compressor
+            );
+        }
+        self.chunk_compressor = Some(algo);
+        self.blob_features.insert(BlobFeatures::CHUNK_COMPRESSOR);
+        self.blob_features.insert(BlobFeatures::CHUNK_INFO_V2);
+        Ok(())
+    }
+
+    /// Set policy to normalize permission bits of files and directories.
+    ///
+    /// Forced modes take precedence over the mode mask.
+    pub fn set_mode_policy(
+        &mut self,
+        mode_mask: u32,
+        force_dir_mode: Option<u32>,
+        force_file_mode: Option<u32>,
+    ) -> Result<()> {
+        for mode in [Some(mode_mask), force_dir_mode, force_file_mode]
+            .into_iter()
+            .flatten()
+        {
+            if mode & !0o7777 != 0 {
+                bail!("invalid permission bits 0o{:o}", mode);
+            }
+        }
+        self.mode_mask = mode_mask;
+        self.force_dir_mode = force_dir_mode;
+        self.force_file_mode = force_file_mode;
+        Ok(())
+    }
+
+    /// Set mappings to remap host user and group ids to ids in the image.
+    ///
+    /// Owners are not saved into the image without explicit uid/gid, so mappings are rejected.
+    pub fn set_id_maps(&mut self, uid_map: Vec<IdMapping>, gid_map: Vec<IdMapping>) -> Result<()> {
+        if !self.explicit_uidgid && !(uid_map.is_empty() && gid_map.is_empty()) {
+            bail!("id mappings require explicit uid/gid to be saved into the image");
+        }
+        self.uid_map = uid_map;
+        self.gid_map = gid_map;
+        Ok(())
+    }
+
+    /// Set lower and upper bounds for chunk size, used by content-defined chunking.
+    ///
+    /// The upper bound must fit into the 24-bit chunk size field of the blob meta chunk info,
+    /// and the chunk size set by [Self::set_chunk_size] must be within the bounds.
+    pub fn set_chunk_size_bounds(
+        &mut self,
+        min_chunk_size: u32,
+        max_chunk_size: u32,
+    ) -> Result<()> {
+        if min_chunk_size < RAFS_MIN_CHUNK_SIZE {
+            bail!(
+                "minimum chunk size 0x{:x} is less than 0x{:x}",
+                min_chunk_size,
+                RAFS_MIN_CHUNK_SIZE
+            );
+        }
+        if max_chunk_size as u64 > RAFS_MAX_CHUNK_SIZE {
+            bail!(
+                "maximum chunk size 0x{:x} is bigger than 0x{:x}",
+                max_chunk_size,
+                RAFS_MAX_CHUNK_SIZE
+            );
+        }
+        if min_chunk_size > max_chunk_size {
+            bail!(
+                "minimum chunk size 0x{:x} is bigger than maximum chunk size 0x{:x}",
+                min_chunk_size,
+                max_chunk_size
+            );
+        }
+        if self.chunk_size < min_chunk_size || self.chunk_size > max_chunk_size {
+            bail!(
+                "chunk size 0x{:x} is out of bounds [0x{:x}, 0x{:x}]",
+                self.chunk_size,
+                min_chunk_size,
+                max_chunk_size
+            );
+        }
+        self.min_chunk_size = min_chunk_size;
+        self.max_chunk_size = max_chunk_size;
+        Ok(())
+    }
+
+    /// Clamp a chunk boundary proposed by content-defined chunking into `[min, max]`.
+    ///
+    /// Returns end offset of the chunk starting at `start`, which never exceeds `end`.
+    pub fn clamp_chunk_boundary(&self, start: u64, boundary: u64, end: u64) -> u64 {
+        let lower = start + self.min_chunk_size as u64;
+        let upper = start + self.max_chunk_size as u64;
+        boundary.max(lower).min(upper).min(end)
+    }
+
     pub fn set_configuration(&mut self, config: Arc<ConfigV2>) {
         self.configuration = config;
     }
@@ -1388,6 +1620,7 @@ impl Default for BuildContext {
             aligned_chunk: false,
             blob_offset: 0,
             compressor: compress::Algorithm::default(),
+            chunk_compressor: None,
             digester: digest::Algorithm::default(),
             cipher: crypt::Algorithm::None,
             explicit_uidgid: true,
@@ -1395,6 +1628,8 @@ impl Default for BuildContext {
 
             chunk_size: RAFS_DEFAULT_CHUNK_SIZE as u32,
             batch_size: 0,
+            min_chunk_size: RAFS_MIN_CHUNK_SIZE,
+            max_chunk_size: RAFS_MAX_CHUNK_SIZE as u32,
             fs_version: RafsVersion::default(),
 
             conversion_type: ConversionType::default(),
@@ -1473,6 +1708,60 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_id_mapping() {
+        let map = IdMapping::from_str("0:100000:65536").unwrap();
+        assert_eq!(map.map(99999), None);
+        assert_eq!(map.map(100000), Some(0));
+        assert_eq!(map.map(165535), Some(65535));
+        assert_eq!(map.map(165536), None);
+
+        let maps = vec![map, IdMapping::from_str("70000:0:1").unwrap()];
+        assert_eq!(IdMapping::map_id(&maps, 100001), 1);
+        assert_eq!(IdMapping::map_id(&maps, 0), 70000);
+        assert_eq!(IdMapping::map_id(&maps, 1000), 1000);
+
+        assert!(IdMapping::from_str("0:100000").is_err());
+        assert!(IdMapping::from_str("0:100000:0").is_err());
+        assert!(IdMapping::from_str("0:-1:10").is_err());
+        assert!(IdMapping::from_str("0:4294967295:2").is_err());
+    }
+
+    #[test]
+    fn test_chunk_size_bounds() {
+        let mut ctx = BuildContext::default();
+        ctx.set_chunk_size(0x10000);
+        assert!(ctx.set_chunk_size_bounds(0x800, 0x10000).is_err());
+        assert!(ctx.set_chunk_size_bounds(0x1000, 0x8000).is_err());
+        assert!(ctx
+            .set_chunk_size_bounds(0x1000, RAFS_MAX_CHUNK_SIZE as u32 + 1)
+            .is_err());
+        assert!(ctx.set_chunk_size_bounds(0x20000, 0x10000).is_err());
+        ctx.set_chunk_size_bounds(0x1000, 0x10000).unwrap();
+
+        // Highly repetitive content never hits a content-defined boundary, so the chunker always
+        // proposes the end of data, which must be clamped to the maximum chunk size.
+        let data = vec![0x5au8; 0x100000 + 0x123];
+        let end = data.len() as u64;
+        let mut start = 0u64;
+        let mut chunks = Vec::new();
+        while start < end {
+            let next = ctx.clamp_chunk_boundary(start, end, end);
+            chunks.push(next - start);
+            start = next;
+        }
+        assert_eq!(chunks.len(), 17);
+        assert_eq!(chunks.iter().sum::<u64>(), end);
+        for size in chunks {
+            assert!(size <= ctx.max_chunk_size as u64);
+            assert!(size <= RAFS_MAX_CHUNK_SIZE);
+        }
+
+        // Boundaries too close to the chunk start are pushed out to the minimum chunk size.
+        assert_eq!(ctx.clamp_chunk_boundary(0x2000, 0x2010, end), 0x3000);
+        assert_eq!(ctx.clamp_chunk_boundary(end - 0x10, end - 0x8, end), end);
+    }
+
     #[test]
     fn test_blob_context_from() {
         let mut blob = BlobInfo::new(
@@ -1507,11 +1796,13 @@ mod tests {
                     blob_file: source_path.to_str().unwrap().to_owned(),
                     dir: "/tmp".to_owned(),
                     alt_dirs: vec!["/var/nydus/cache".to_owned()],
+                    mmap: false,
                 }),
                 oss: None,
                 s3: None,
                 registry: None,
                 http_proxy: None,
+                oci_layer: None,
             }),
             id: "id".to_owned(),
             cache: None,
@@ -1532,4 +1823,29 @@ mod tests {
         assert_eq!(blob_ctx.uncompressed_blob_size, 16);
         assert!(blob_ctx.blob_meta_info_enabled);
     }
+
+    #[test]
+    fn test_skip_storing_present_blob() {
+        use std::os::unix::fs::MetadataExt;
+        use vmm_sys_util::tempdir::TempDir;
+
+        let blob_dir = TempDir::new().unwrap();
+        let store = |data: &[u8]| {
+            let storage = ArtifactStorage::FileDir(blob_dir.as_path().to_path_buf());
+            let mut writer = ArtifactWriter::new(storage).unwrap();
+            writer.write_all(data).unwrap();
+            writer.finalize(Some("blob".to_string())).unwrap();
+            let path = blob_dir.as_path().join("blob");
+            assert_eq!(fs::read(&path).unwrap(), data);
+            fs::metadata(&path).unwrap().ino()
+        };
+
+        let ino = store(b"blob data");
+        // Storing an identical blob again is skipped.
+        assert_eq!(store(b"blob data"), ino);
+        // A partially stored blob of the same id is replaced.
+        assert_ne!(store(b"blob data and more"), ino);
+        // Temporary files are removed in both cases.
+        assert_eq!(fs::read_dir(blob_dir.as_path()).unwrap().count(), 1);
+    }
 }
@@ -14,6 +14,10 @@ const ERR_UNSUPPORTED_FEATURE: &str = "unsupported feature";
 pub enum Feature {
     /// Append a Table Of Content footer to RAFS v6 data blob, to help locate data sections.
     BlobToc,
+    /// Store an extended record with 64-bit rdev and atime/ctime for RAFS v5 inodes.
+    ExtendedInode,
+    /// Store content of tiny regular files inline in RAFS v5 inodes instead of the data blob.
+    InlineData,
 }
 
 impl TryFrom<&str> for Feature {
@@ -22,6 +26,8 @@ impl TryFrom<&str> for Feature {
     fn try_from(f: &str) -> Result<Self> {
         match f {
             "blob-toc" => Ok(Self::BlobToc),
+            "extended-inode" => Ok(Self::ExtendedInode),
+            "inline-data" => Ok(Self::InlineData),
             _ => bail!(
                 "{} `{}`, please try upgrading to the latest nydus-image",
                 ERR_UNSUPPORTED_FEATURE,
@@ -75,6 +81,14 @@ mod tests {
     #[test]
     fn test_feature() {
         assert_eq!(Feature::try_from("blob-toc").unwrap(), Feature::BlobToc);
+        assert_eq!(
+            Feature::try_from("extended-inode").unwrap(),
+            Feature::ExtendedInode
+        );
+        assert_eq!(
+            Feature::try_from("inline-data").unwrap(),
+            Feature::InlineData
+        );
         Feature::try_from("unknown-feature-bit").unwrap_err();
     }
 
@@ -90,5 +104,8 @@ mod tests {
         assert!(features.is_enabled(Feature::BlobToc));
         let features = Features::try_from(" blob-toc ").unwrap();
         assert!(features.is_enabled(Feature::BlobToc));
+        let features = Features::try_from("blob-toc,extended-inode").unwrap();
+        assert!(features.is_enabled(Feature::BlobToc));
+        assert!(features.is_enabled(Feature::ExtendedInode));
     }
 }
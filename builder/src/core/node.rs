@@ -3,6 +3,7 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+use std::borrow::Cow;
 use std::ffi::{OsStr, OsString};
 use std::fmt::{self, Display, Formatter, Result as FmtResult};
 use std::fs::{self, File};
@@ -12,13 +13,14 @@ use std::ops::Deref;
 use std::os::linux::fs::MetadataExt;
 #[cfg(target_os = "macos")]
 use std::os::macos::fs::MetadataExt;
-use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::{anyhow, bail, Context, Error, Result};
 use nydus_rafs::metadata::chunk::ChunkWrapper;
 use nydus_rafs::metadata::inode::InodeWrapper;
+use nydus_rafs::metadata::layout::v5::{RafsV5InodeExtended, RAFSV5_MAX_INLINE_DATA_SIZE};
 use nydus_rafs::metadata::layout::v6::EROFS_INODE_FLAT_PLAIN;
 use nydus_rafs::metadata::layout::RafsXAttrs;
 use nydus_rafs::metadata::{Inode, RafsVersion};
@@ -29,13 +31,25 @@ use nydus_utils::{compress, crypt};
 use nydus_utils::{div_round_up, event_tracer, root_tracer, try_round_up_4k, ByteSize};
 use sha2::digest::Digest;
 
-use crate::{BlobContext, BlobManager, BuildContext, ChunkDict, ConversionType, Overlay};
+use crate::{
+    BlobContext, BlobManager, BuildContext, ChunkDict, ConversionType, IdMapping, Overlay,
+};
 
 use super::context::Artifact;
+use super::feature::Feature;
 
 /// Filesystem root path for Unix OSs.
 const ROOT_PATH_NAME: &[u8] = &[b'/'];
 
+// Extended attributes storing POSIX ACLs, whose entries may carry user or group ids.
+const POSIX_ACL_XATTRS: [&str; 2] = ["system.posix_acl_access", "system.posix_acl_default"];
+// Size of the version header and of each entry in POSIX ACL xattr values.
+const POSIX_ACL_HEADER_SIZE: usize = 4;
+const POSIX_ACL_ENTRY_SIZE: usize = 8;
+// Tags of POSIX ACL entries for named users and named groups.
+const POSIX_ACL_USER: u16 = 0x02;
+const POSIX_ACL_GROUP: u16 = 0x08;
+
 /// Source of chunk data: chunk dictionary, parent filesystem or builder.
 #[derive(Clone, Hash, PartialEq, Eq)]
 pub enum ChunkSource {
@@ -137,6 +151,8 @@ pub struct NodeInfo {
 
     /// V6: whether it's forced to use an extended inode.
     pub v6_force_extended_inode: bool,
+    /// V5: extended inode record, only stored if the inode has the `EXTENDED` flag set.
+    pub v5_extended: RafsV5InodeExtended,
 }
 
 /// An in-memory representation of RAFS inode for image building and inspection.
@@ -233,6 +249,23 @@ impl Node {
         self.dump_node_data_with_reader(ctx, blob_mgr, blob_writer, reader.as_mut(), chunk_data_buf)
     }
 
+    /// Store content of tiny regular files inline in the inode, instead of the data blob.
+    ///
+    /// Inline data shares the on-disk slot with symlink target, so it's kept in `info.symlink`.
+    fn dump_inline_data<R: Read>(&mut self, ctx: &BuildContext, reader: &mut R) -> Result<u64> {
+        let mut data = vec![0u8; self.inode.size() as usize];
+        reader
+            .read_exact(&mut data)
+            .with_context(|| format!("failed to read inline data for {:?}", self.path()))?;
+        self.inode
+            .set_digest(RafsDigest::from_buf(&data, ctx.digester));
+        self.inode.set_inline_data_size(data.len());
+        self.inode.set_child_count(0);
+        self.set_symlink(OsString::from_vec(data));
+
+        Ok(0)
+    }
+
     /// Dump data from a reader into the data blob, and generate chunk information.
     ///
     /// # Arguments
@@ -269,6 +302,14 @@ impl Node {
 
         let mut blob_size = 0u64;
         let reader = reader.ok_or_else(|| anyhow!("missing reader to read file data"))?;
+        if ctx.features.is_enabled(Feature::InlineData)
+            && ctx.conversion_type != ConversionType::TarToTarfs
+            && self.inode.is_v5()
+            && self.inode.size() > 0
+            && self.inode.size() <= RAFSV5_MAX_INLINE_DATA_SIZE as u64
+        {
+            return self.dump_inline_data(ctx, reader);
+        }
         let mut inode_hasher = if self.inode.is_v5() {
             Some(RafsDigest::hasher(ctx.digester))
         } else {
@@ -276,14 +317,15 @@ impl Node {
         };
 
         // `child_count` of regular file is reused as `chunk_count`.
-        for i in 0..self.inode.child_count() {
-            let chunk_size = ctx.chunk_size;
-            let file_offset = i as u64 * chunk_size as u64;
-            let uncompressed_size = if i == self.inode.child_count() - 1 {
-                (self.inode.size() - chunk_size as u64 * i as u64) as u32
-            } else {
-                chunk_size
-            };
+        let mut next_offset = 0u64;
+        for _ in 0..self.inode.child_count() {
+            let file_offset = next_offset;
+            next_offset = ctx.clamp_chunk_boundary(
+                file_offset,
+                file_offset + ctx.chunk_size as u64,
+                self.inode.size(),
+            );
+            let uncompressed_size = (next_offset - file_offset) as u32;
 
             let chunk_data = &mut data_buf[0..uncompressed_size as usize];
             let (mut chunk, mut chunk_info) = self.read_file_chunk(ctx, reader, chunk_data)?;
@@ -477,13 +519,18 @@ impl Node {
                 }
             }
 
-            let (pre_c_offset, c_size, is_compressed) =
-                Self::write_chunk_data(ctx, blob_ctx, blob_writer, chunk_data)
+            let (compressed, compressor) = Self::compress_chunk_data(ctx, chunk_data)?;
+            let (pre_c_offset, c_size) =
+                Self::write_compressed_data(blob_ctx, blob_writer, &compressed)
                     .with_context(|| format!("failed to write chunk data {:?}", self.path()))?;
             dumped_size = Some(dumped_size.unwrap_or(0) + c_size);
             chunk.set_compressed_offset(pre_c_offset);
             chunk.set_compressed_size(c_size);
-            chunk.set_compressed(is_compressed);
+            if compressor.is_none() || compressor == ctx.compressor {
+                chunk.set_compressed(!compressor.is_none());
+            } else {
+                chunk.set_compressor(compressor);
+            }
         }
 
         if let Some(blob_cache) = ctx.blob_cache_generator.as_ref() {
@@ -502,8 +549,47 @@ impl Node {
     ) -> Result<(u64, u32, bool)> {
         let (compressed, is_compressed) = compress::compress(chunk_data, ctx.compressor)
             .with_context(|| "failed to compress node file".to_string())?;
+        let (pre_compressed_offset, compressed_size) =
+            Self::write_compressed_data(blob_ctx, blob_writer, &compressed)?;
+
+        Ok((pre_compressed_offset, compressed_size, is_compressed))
+    }
+
+    /// Compress chunk data with the blob compressor, or the chunk compressor if its output is
+    /// smaller.
+    ///
+    /// Return the compressed data and the algorithm used, `compress::Algorithm::None` if the data
+    /// is stored as is.
+    fn compress_chunk_data<'a>(
+        ctx: &BuildContext,
+        chunk_data: &'a [u8],
+    ) -> Result<(Cow<'a, [u8]>, compress::Algorithm)> {
+        let (mut compressed, is_compressed) = compress::compress(chunk_data, ctx.compressor)
+            .with_context(|| "failed to compress node file".to_string())?;
+        let mut compressor = if is_compressed {
+            ctx.compressor
+        } else {
+            compress::Algorithm::None
+        };
+        if let Some(algo) = ctx.chunk_compressor {
+            let (data, is_compressed) = compress::compress(chunk_data, algo)
+                .with_context(|| format!("failed to compress node file with {}", algo))?;
+            if is_compressed && data.len() < compressed.len() {
+                compressed = data;
+                compressor = algo;
+            }
+        }
+
+        Ok((compressed, compressor))
+    }
+
+    fn write_compressed_data(
+        blob_ctx: &mut BlobContext,
+        blob_writer: &mut dyn Artifact,
+        compressed: &[u8],
+    ) -> Result<(u64, u32)> {
         let encrypted = crypt::encrypt_with_context(
-            &compressed,
+            compressed,
             &blob_ctx.cipher_object,
             &blob_ctx.cipher_ctx,
             blob_ctx.blob_cipher != crypt::Algorithm::None,
@@ -517,7 +603,7 @@ impl Node {
         blob_ctx.current_compressed_offset += compressed_size as u64;
         blob_ctx.compressed_blob_size += compressed_size as u64;
 
-        Ok((pre_compressed_offset, compressed_size, is_compressed))
+        Ok((pre_compressed_offset, compressed_size))
     }
 
     fn deduplicate_chunk(
@@ -612,6 +698,7 @@ impl Node {
             symlink: None,
             xattrs: RafsXAttrs::default(),
             v6_force_extended_inode,
+            v5_extended: RafsV5InodeExtended::default(),
         };
         let mut node = Node {
             info: Arc::new(info),
@@ -676,6 +763,13 @@ impl Node {
         info.src_ino = meta.st_ino();
         info.src_dev = meta.st_dev();
         info.rdev = meta.st_rdev();
+        info.v5_extended = RafsV5InodeExtended {
+            i_rdev: meta.st_rdev(),
+            i_atime: meta.st_atime() as u64,
+            i_ctime: meta.st_ctime() as u64,
+            i_atime_nsec: meta.st_atime_nsec() as u32,
+            i_ctime_nsec: meta.st_ctime_nsec() as u32,
+        };
 
         self.inode.set_mode(meta.st_mode());
         if info.explicit_uidgid {
@@ -747,6 +841,74 @@ impl Node {
         Ok(())
     }
 
+    /// Normalize permission bits of directories and regular files by the build context policy.
+    ///
+    /// File type bits are always kept, and modes of other types of files are not changed.
+    pub fn normalize_mode(&mut self, ctx: &BuildContext) {
+        let forced = if self.is_dir() {
+            ctx.force_dir_mode
+        } else if self.is_reg() {
+            ctx.force_file_mode
+        } else {
+            return;
+        };
+        let mode = self.inode.mode();
+        let perm = forced.unwrap_or(mode & 0o7777 & !ctx.mode_mask);
+        self.inode.set_mode((mode & !0o7777) | perm);
+    }
+
+    /// Remap owner of the inode from host ids to ids in the image by the build context mappings.
+    ///
+    /// Ids of named users and groups in POSIX ACLs are remapped too.
+    pub fn remap_ids(&mut self, ctx: &BuildContext) -> Result<()> {
+        if ctx.uid_map.is_empty() && ctx.gid_map.is_empty() {
+            return Ok(());
+        }
+
+        let acls = POSIX_ACL_XATTRS
+            .iter()
+            .filter_map(|name| {
+                let value = self.info.xattrs.get(OsStr::new(name))?;
+                Some((OsString::from(name), Self::remap_acl_ids(ctx, value)))
+            })
+            .collect::<Vec<_>>();
+        if !acls.is_empty() {
+            let mut info = self.info.deref().clone();
+            for (name, value) in acls {
+                info.xattrs.add(name, value)?;
+            }
+            self.info = Arc::new(info);
+        }
+
+        // Owner is not saved into the image without explicit uid/gid.
+        if self.info.explicit_uidgid {
+            let uid = IdMapping::map_id(&ctx.uid_map, self.inode.uid());
+            let gid = IdMapping::map_id(&ctx.gid_map, self.inode.gid());
+            self.inode.set_uid(uid);
+            self.inode.set_gid(gid);
+        }
+
+        Ok(())
+    }
+
+    // Remap ids in a POSIX ACL xattr value, which has little-endian entries of
+    // `|tag: u16|perm: u16|id: u32|` following the version header.
+    fn remap_acl_ids(ctx: &BuildContext, value: &[u8]) -> Vec<u8> {
+        let mut value = value.to_vec();
+        if value.len() > POSIX_ACL_HEADER_SIZE {
+            for entry in value[POSIX_ACL_HEADER_SIZE..].chunks_exact_mut(POSIX_ACL_ENTRY_SIZE) {
+                let mappings = match u16::from_le_bytes([entry[0], entry[1]]) {
+                    POSIX_ACL_USER => &ctx.uid_map,
+                    POSIX_ACL_GROUP => &ctx.gid_map,
+                    _ => continue,
+                };
+                let id = u32::from_le_bytes(entry[4..8].try_into().unwrap());
+                entry[4..8].copy_from_slice(&IdMapping::map_id(mappings, id).to_le_bytes());
+            }
+        }
+        value
+    }
+
     fn meta(&self) -> Result<impl MetadataExt> {
         self.path()
             .symlink_metadata()
@@ -891,16 +1053,32 @@ impl Node {
         }
         self.info = Arc::new(info);
     }
+
+    /// Release chunk records and directory entries after dumping the node into the bootstrap.
+    ///
+    /// They are not needed once the node has been dumped. Note that this doesn't lower peak memory
+    /// usage, because records of all nodes are held until the bootstrap gets dumped.
+    pub(crate) fn release_dumped_data(&mut self) {
+        self.chunks = Vec::new();
+        self.v6_dirents = Vec::new();
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::BufReader;
-
-    use nydus_utils::{digest, BufReaderInfo};
+    use std::ffi::CString;
+    use std::io::{BufReader, BufWriter, Write};
+
+    use nydus_rafs::metadata::cached_v5::CachedInodeV5;
+    use nydus_rafs::metadata::layout::v5::RafsV5BlobTable;
+    use nydus_rafs::metadata::{RafsInode, RafsSuperFlags, RafsSuperMeta, RAFS_DEFAULT_CHUNK_SIZE};
+    use nydus_rafs::RafsIoReader;
+    use nydus_utils::digest;
+    use vmm_sys_util::tempdir::TempDir;
     use vmm_sys_util::tempfile::TempFile;
 
-    use crate::{ArtifactWriter, BlobCacheGenerator, HashChunkDict};
+    use crate::core::feature::Features;
+    use crate::{ArtifactWriter, BlobCacheGenerator, BufReaderInfo, HashChunkDict};
 
     use super::*;
 
@@ -949,6 +1127,7 @@ mod tests {
             symlink: Some(OsString::from("symlink")),
             xattrs: RafsXAttrs::new(),
             v6_force_extended_inode: false,
+            v5_extended: RafsV5InodeExtended::default(),
         };
         let mut node = Node::new(inode, info, 1);
 
@@ -1018,6 +1197,124 @@ mod tests {
         assert_eq!(data_size.unwrap(), 18);
     }
 
+    #[test]
+    fn test_node_v5_extended_inode() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp_file = TempFile::new_in(tmp_dir.as_path()).unwrap();
+        let times = [
+            libc::timespec {
+                tv_sec: 1_600_000_001,
+                tv_nsec: 987_654_321,
+            },
+            libc::timespec {
+                tv_sec: 1_600_000_000,
+                tv_nsec: 123_456_789,
+            },
+        ];
+        let path = CString::new(tmp_file.as_path().as_os_str().as_bytes()).unwrap();
+        let ret = unsafe { libc::utimensat(libc::AT_FDCWD, path.as_ptr(), times.as_ptr(), 0) };
+        assert_eq!(ret, 0);
+
+        let mut node = Node::from_fs_object(
+            RafsVersion::V5,
+            tmp_dir.as_path().to_path_buf(),
+            tmp_file.as_path().to_path_buf(),
+            Overlay::UpperAddition,
+            RAFS_DEFAULT_CHUNK_SIZE as u32,
+            false,
+            false,
+        )
+        .unwrap();
+        node.inode.set_has_extended(true);
+        node.inode.set_ino(2);
+        node.inode.set_parent(1);
+        assert_eq!(node.info.v5_extended.i_atime_nsec, 987_654_321);
+
+        let bootstrap = TempFile::new().unwrap();
+        let mut writer = BufWriter::new(bootstrap.as_file().try_clone().unwrap());
+        let mut ctx = BuildContext::default();
+        node.dump_bootstrap_v5(&mut ctx, &mut writer).unwrap();
+        writer.flush().unwrap();
+
+        let mut reader = Box::new(File::open(bootstrap.as_path()).unwrap()) as RafsIoReader;
+        let meta = Arc::new(RafsSuperMeta {
+            inodes_count: 2,
+            chunk_size: RAFS_DEFAULT_CHUNK_SIZE as u32,
+            flags: RafsSuperFlags::EXTENDED_INODE,
+            ..Default::default()
+        });
+        let mut inode = CachedInodeV5::new(Arc::new(RafsV5BlobTable::new()), meta.clone());
+        inode.load(&meta, &mut reader).unwrap();
+        let attr = inode.get_attr();
+        assert_eq!(attr.mtime, 1_600_000_000);
+        assert_eq!(attr.mtimensec, 123_456_789);
+        assert_eq!(attr.atime, 1_600_000_001);
+        assert_eq!(attr.atimensec, 987_654_321);
+        assert_eq!(attr.ctime, node.info.v5_extended.i_ctime);
+        assert_eq!(attr.ctimensec, node.info.v5_extended.i_ctime_nsec);
+    }
+
+    #[test]
+    fn test_node_v5_inline_data() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp_file = TempFile::new_in(tmp_dir.as_path()).unwrap();
+        std::fs::write(tmp_file.as_path(), b"tiny file content").unwrap();
+
+        let mut node = Node::from_fs_object(
+            RafsVersion::V5,
+            tmp_dir.as_path().to_path_buf(),
+            tmp_file.as_path().to_path_buf(),
+            Overlay::UpperAddition,
+            RAFS_DEFAULT_CHUNK_SIZE as u32,
+            false,
+            false,
+        )
+        .unwrap();
+        node.inode.set_ino(2);
+        node.inode.set_parent(1);
+        assert_eq!(node.inode.child_count(), 1);
+
+        let mut ctx = BuildContext {
+            features: Features::try_from("inline-data").unwrap(),
+            ..Default::default()
+        };
+        let mut blob_mgr = BlobManager::new(digest::Algorithm::Sha256);
+        let tmp_blob = TempFile::new().unwrap();
+        let mut blob_writer = ArtifactWriter::new(crate::ArtifactStorage::SingleFile(
+            PathBuf::from(tmp_blob.as_path()),
+        ))
+        .unwrap();
+        let mut chunk_data_buf = vec![0u8; RAFS_DEFAULT_CHUNK_SIZE as usize];
+        let data_size = node
+            .dump_node_data(&ctx, &mut blob_mgr, &mut blob_writer, &mut chunk_data_buf)
+            .unwrap();
+        assert_eq!(data_size, 0);
+        assert!(node.chunks.is_empty());
+        assert!(blob_mgr.get_current_blob().is_none());
+        assert!(node.inode.has_inline_data());
+        assert_eq!(node.inode.child_count(), 0);
+
+        let bootstrap = TempFile::new().unwrap();
+        let mut writer = BufWriter::new(bootstrap.as_file().try_clone().unwrap());
+        node.dump_bootstrap_v5(&mut ctx, &mut writer).unwrap();
+        writer.flush().unwrap();
+
+        let mut reader = Box::new(File::open(bootstrap.as_path()).unwrap()) as RafsIoReader;
+        let meta = Arc::new(RafsSuperMeta {
+            inodes_count: 2,
+            chunk_size: RAFS_DEFAULT_CHUNK_SIZE as u32,
+            ..Default::default()
+        });
+        let mut inode = CachedInodeV5::new(Arc::new(RafsV5BlobTable::new()), meta.clone());
+        inode.load(&meta, &mut reader).unwrap();
+        inode.validate(2, RAFS_DEFAULT_CHUNK_SIZE).unwrap();
+        assert_eq!(inode.get_chunk_count(), 0);
+        assert_eq!(
+            inode.get_inline_data().unwrap(),
+            b"tiny file content".to_vec()
+        );
+    }
+
     #[test]
     fn test_node() {
         let inode = InodeWrapper::new(RafsVersion::V5);
@@ -1033,6 +1330,7 @@ mod tests {
             symlink: None,
             xattrs: RafsXAttrs::new(),
             v6_force_extended_inode: false,
+            v5_extended: RafsV5InodeExtended::default(),
         };
 
         let mut inode1 = inode.clone();
@@ -0,0 +1,104 @@
+// Copyright (C) 2022 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+
+use nydus_utils::digest::DigestHasher;
+use sha2::Sha256;
+
+struct BufReaderState<R: Read> {
+    reader: BufReader<R>,
+    pos: u64,
+    hash: Sha256,
+}
+
+/// A wrapper over `BufReader` to track current position and calculate digest of source data.
+pub struct BufReaderInfo<R: Read> {
+    calc_digest: bool,
+    state: Arc<Mutex<BufReaderState<R>>>,
+}
+
+impl<R: Read> BufReaderInfo<R> {
+    /// Create a new instance of `BufReaderPos` from a `BufReader`.
+    pub fn from_buf_reader(buf_reader: BufReader<R>) -> Self {
+        let state = BufReaderState {
+            reader: buf_reader,
+            pos: 0,
+            hash: Sha256::default(),
+        };
+        Self {
+            calc_digest: true,
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Get current position of the reader.
+    pub fn position(&self) -> u64 {
+        self.state.lock().unwrap().pos
+    }
+
+    /// Get the hash object.
+    pub fn get_hash_object(&self) -> Sha256 {
+        self.state.lock().unwrap().hash.clone()
+    }
+
+    /// Enable or disable blob digest calculation.
+    pub fn enable_digest_calculation(&mut self, enable: bool) {
+        self.calc_digest = enable;
+    }
+}
+
+impl<R: Read> Read for BufReaderInfo<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        state.reader.read(buf).map(|v| {
+            state.pos += v as u64;
+            if v > 0 && self.calc_digest {
+                state.hash.digest_update(&buf[..v]);
+            }
+            v
+        })
+    }
+}
+
+impl<R: Read + Seek> Seek for BufReaderInfo<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        let pos = state.reader.seek(pos)?;
+        state.pos = pos;
+        Ok(pos)
+    }
+}
+
+impl<R: Read> Clone for BufReaderInfo<R> {
+    fn clone(&self) -> Self {
+        Self {
+            calc_digest: self.calc_digest,
+            state: self.state.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::Digest;
+
+    #[test]
+    fn test_buf_reader_info() {
+        let data = b"This is a test".to_vec();
+        let mut reader = BufReaderInfo::from_buf_reader(BufReader::new(data.as_slice()));
+        let mut buf = vec![0u8; 4];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(reader.position(), 4);
+
+        let mut clone = reader.clone();
+        clone.enable_digest_calculation(false);
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        assert_eq!(clone.position(), data.len() as u64);
+        assert_eq!(reader.get_hash_object().finalize(), Sha256::digest(&data));
+    }
+}
@@ -0,0 +1,93 @@
+// Copyright (C) 2023 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Sign generated RAFS metadata files with Ed25519 keys.
+//!
+//! Only the builder holds private keys and generates signatures, the format of the signature
+//! trailer and the verification path are defined by [nydus_utils::signature].
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use nydus_utils::signature::{SIGNATURE_MAGIC, SIGNATURE_SIZE};
+use openssl::pkey::{Id, PKey, Private};
+use openssl::sign::Signer;
+
+/// Load a PEM encoded Ed25519 private key to sign files.
+pub fn load_private_key(path: &Path) -> Result<PKey<Private>> {
+    let pem = fs::read(path).with_context(|| format!("failed to read private key {:?}", path))?;
+    let key = PKey::private_key_from_pem(&pem)
+        .map_err(|e| anyhow!("failed to load private key from {:?}, {}", path, e))?;
+    if key.id() != Id::ED25519 {
+        bail!("private key {:?} is not an Ed25519 key", path);
+    }
+    Ok(key)
+}
+
+/// Sign the file at `path` and append the signature trailer to it.
+pub fn sign_file(path: &Path, key: &PKey<Private>) -> Result<()> {
+    let data = fs::read(path).with_context(|| format!("failed to read file {:?}", path))?;
+    let mut signer =
+        Signer::new_without_digest(key).map_err(|e| anyhow!("failed to create signer, {}", e))?;
+    let signature = signer
+        .sign_oneshot_to_vec(&data)
+        .map_err(|e| anyhow!("failed to sign {:?}, {}", path, e))?;
+    if signature.len() != SIGNATURE_SIZE {
+        bail!("invalid signature size {}", signature.len());
+    }
+
+    let mut file = OpenOptions::new().append(true).open(path)?;
+    file.write_all(&signature)?;
+    file.write_all(&SIGNATURE_MAGIC)?;
+    file.sync_all()
+        .with_context(|| format!("failed to append signature to {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nydus_utils::signature::{load_public_key, verify_file, SIGNATURE_TRAILER_SIZE};
+    use std::fs::File;
+    use std::io::Read;
+    use std::path::PathBuf;
+    use vmm_sys_util::tempfile::TempFile;
+
+    fn key_path(name: &str) -> PathBuf {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        PathBuf::from(root_dir)
+            .join("../tests/texture/signing")
+            .join(name)
+    }
+
+    #[test]
+    fn test_sign_and_verify_file() {
+        let private_key = load_private_key(&key_path("ed25519-private.pem")).unwrap();
+        let public_key = load_public_key(&key_path("ed25519-public.pem")).unwrap();
+        assert!(load_private_key(&key_path("ed25519-public.pem")).is_err());
+
+        let tmp_file = TempFile::new().unwrap();
+        let content = (0..0x10000).map(|v| (v % 253) as u8).collect::<Vec<u8>>();
+        fs::write(tmp_file.as_path(), &content).unwrap();
+        let mut file = File::open(tmp_file.as_path()).unwrap();
+        assert!(verify_file(&mut file, &public_key, u64::MAX).is_err());
+
+        sign_file(tmp_file.as_path(), &private_key).unwrap();
+        let data = fs::read(tmp_file.as_path()).unwrap();
+        assert_eq!(data.len(), content.len() + SIGNATURE_TRAILER_SIZE);
+        assert_eq!(&data[..content.len()], content.as_slice());
+        let mut file = File::open(tmp_file.as_path()).unwrap();
+        let mut verified = verify_file(&mut file, &public_key, u64::MAX).unwrap();
+        let mut copy = Vec::new();
+        verified.read_to_end(&mut copy).unwrap();
+        assert_eq!(copy, content);
+
+        let mut tampered = data.clone();
+        tampered[0x100] ^= 0x1;
+        fs::write(tmp_file.as_path(), &tampered).unwrap();
+        let mut file = File::open(tmp_file.as_path()).unwrap();
+        assert!(verify_file(&mut file, &public_key, u64::MAX).is_err());
+    }
+}
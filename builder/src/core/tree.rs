@@ -17,7 +17,7 @@
 //! - Traverse the merged tree (OverlayTree) to dump bootstrap and data blobs.
 
 use std::ffi::OsString;
-use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::{Arc, Mutex, MutexGuard};
@@ -329,10 +329,13 @@ impl<'a> MetadataTreeBuilder<'a> {
             Vec::new()
         };
 
+        // Inline file content shares the same on-disk slot with symlink target.
         let symlink = if inode.is_symlink() {
             Some(inode.get_symlink()?)
         } else {
-            None
+            inode
+                .get_inline_data()
+                .map(|v| OsString::from_vec(v.to_vec()))
         };
 
         let mut xattrs = RafsXAttrs::new();
@@ -345,7 +348,13 @@ impl<'a> MetadataTreeBuilder<'a> {
         // Nodes loaded from bootstrap will only be used as `Overlay::Lower`, so make `dev` invalid
         // to avoid breaking hardlink detecting logic.
         let src_dev = u64::MAX;
-        let rdev = inode.rdev() as u64;
+        let v5_extended = inode.get_extended();
+        // The extended record keeps the device number without truncation to 32 bits.
+        let rdev = match v5_extended {
+            Some(ext) => ext.i_rdev,
+            None => inode.rdev() as u64,
+        };
+        let v5_extended = v5_extended.unwrap_or_default();
         let inode = InodeWrapper::from_inode_info(inode.clone());
         let source = PathBuf::from("/");
         let target = Node::generate_target(&path, &source);
@@ -362,6 +371,7 @@ impl<'a> MetadataTreeBuilder<'a> {
             symlink,
             xattrs,
             v6_force_extended_inode: false,
+            v5_extended,
         };
 
         Ok(Node {
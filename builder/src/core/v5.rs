@@ -45,6 +45,7 @@ impl Node {
                 name,
                 symlink: self.info.symlink.as_deref(),
                 inode: raw_inode,
+                extended: Some(&self.info.v5_extended),
             };
             inode
                 .store(f_bootstrap)
@@ -199,9 +200,13 @@ impl Bootstrap {
             + extended_blob_table_size) as u32;
 
         let mut has_xattr = false;
+        let mut has_extended = false;
+        let mut has_inline_data = false;
         self.tree.walk_dfs_pre(&mut |t| {
             let node = t.lock_node();
             inode_table.set(node.index, inode_offset)?;
+            has_extended |= node.inode.has_extended();
+            has_inline_data |= node.inode.has_inline_data();
             // Add inode size
             inode_offset += node.inode.inode_size() as u32;
             if node.inode.has_xattr() {
@@ -221,6 +226,12 @@ impl Bootstrap {
         if has_xattr {
             super_block.set_has_xattr();
         }
+        if has_extended {
+            super_block.set_has_extended_inode();
+        }
+        if has_inline_data {
+            super_block.set_has_inline_data();
+        }
 
         // Dump super block
         super_block
@@ -253,9 +264,11 @@ impl Bootstrap {
         timing_tracer!(
             {
                 self.tree.walk_dfs_pre(&mut |t| {
-                    t.lock_node()
-                        .dump_bootstrap_v5(ctx, bootstrap_ctx.writer.as_mut())
-                        .context("failed to dump bootstrap")
+                    let mut node = t.lock_node();
+                    node.dump_bootstrap_v5(ctx, bootstrap_ctx.writer.as_mut())
+                        .context("failed to dump bootstrap")?;
+                    node.release_dumped_data();
+                    Ok(())
                 })
             },
             "dump_bootstrap"
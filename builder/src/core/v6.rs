@@ -704,13 +704,16 @@ impl Bootstrap {
         timing_tracer!(
             {
                 self.tree.walk_bfs(true, &mut |n| {
-                    n.lock_node().dump_bootstrap_v6(
+                    let mut node = n.lock_node();
+                    node.dump_bootstrap_v6(
                         ctx,
                         bootstrap_ctx.writer.as_mut(),
                         orig_meta_addr,
                         meta_addr,
                         &mut chunk_cache,
-                    )
+                    )?;
+                    node.release_dumped_data();
+                    Ok(())
                 })
             },
             "dump_bootstrap"
@@ -9,6 +9,7 @@ use anyhow::{Context, Result};
 use nydus_utils::{event_tracer, lazy_drop, root_tracer, timing_tracer};
 
 use crate::core::context::{Artifact, NoopArtifactWriter};
+use crate::core::feature::Feature;
 
 use super::core::blob::Blob;
 use super::core::context::{
@@ -57,6 +58,11 @@ impl FilesystemTreeBuilder {
             )
             .with_context(|| format!("failed to create node {:?}", path))?;
             child.layer_idx = layer_idx;
+            child.normalize_mode(ctx);
+            child.remap_ids(ctx)?;
+            if ctx.features.is_enabled(Feature::ExtendedInode) {
+                child.inode.set_has_extended(true);
+            }
 
             // as per OCI spec, whiteout file should not be present within final image
             // or filesystem, only existed in layers.
@@ -96,7 +102,7 @@ impl DirectoryBuilder {
         bootstrap_ctx: &mut BootstrapContext,
         layer_idx: u16,
     ) -> Result<Tree> {
-        let node = Node::from_fs_object(
+        let mut node = Node::from_fs_object(
             ctx.fs_version,
             ctx.source_path.clone(),
             ctx.source_path.clone(),
@@ -105,6 +111,11 @@ impl DirectoryBuilder {
             ctx.explicit_uidgid,
             true,
         )?;
+        node.normalize_mode(ctx);
+        node.remap_ids(ctx)?;
+        if ctx.features.is_enabled(Feature::ExtendedInode) {
+            node.inode.set_has_extended(true);
+        }
         let mut tree = Tree::new(node);
         let tree_builder = FilesystemTreeBuilder::new();
 
@@ -195,3 +206,116 @@ impl Builder for DirectoryBuilder {
         BuildOutput::new(blob_mgr, &bootstrap_mgr.bootstrap_storage)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use nydus_rafs::metadata::{RafsSuper, RafsVersion};
+    use nydus_utils::digest;
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+    use crate::test_utils::{image_config, new_build_context};
+    use crate::{ArtifactStorage, ConversionType, Features};
+
+    #[test]
+    fn test_build_releases_dumped_node_data() {
+        let src_dir = TempDir::new().unwrap();
+        for d in 0..16 {
+            let dir = src_dir.as_path().join(format!("dir{}", d));
+            fs::create_dir(&dir).unwrap();
+            for f in 0..64 {
+                let content = format!("{}-{}", d, f).repeat(f * 64 + 1);
+                fs::write(dir.join(format!("file{}", f)), content).unwrap();
+            }
+        }
+        let content = (0..3 * 0x100000 + 0x456)
+            .map(|v| (v % 239) as u8)
+            .collect::<Vec<u8>>();
+        fs::write(src_dir.as_path().join("big"), content).unwrap();
+
+        for version in [RafsVersion::V5, RafsVersion::V6] {
+            let blob_dir = TempDir::new().unwrap();
+            let mut ctx = new_build_context(
+                ConversionType::DirectoryToRafs,
+                src_dir.as_path(),
+                blob_dir.as_path(),
+                Features::new(),
+            );
+            ctx.set_fs_version(version);
+            let bootstrap_path = blob_dir.as_path().join("bootstrap");
+            let mut bootstrap_mgr = BootstrapManager::new(
+                Some(ArtifactStorage::SingleFile(bootstrap_path.clone())),
+                None,
+            );
+            let mut blob_mgr = BlobManager::new(digest::Algorithm::Blake3);
+            let mut bootstrap_ctx = bootstrap_mgr.create_ctx().unwrap();
+            let mut blob_writer =
+                ArtifactWriter::new(ArtifactStorage::FileDir(blob_dir.as_path().to_path_buf()))
+                    .unwrap();
+
+            let tree = DirectoryBuilder::new()
+                .build_tree(&mut ctx, &mut bootstrap_ctx, 0)
+                .unwrap();
+            let mut bootstrap = build_bootstrap(
+                &mut ctx,
+                &mut bootstrap_mgr,
+                &mut bootstrap_ctx,
+                &mut blob_mgr,
+                tree,
+            )
+            .unwrap();
+            Blob::dump(&ctx, &mut blob_mgr, &mut blob_writer).unwrap();
+            if let Some((_, blob_ctx)) = blob_mgr.get_current_blob() {
+                Blob::dump_meta_data(&ctx, blob_ctx, &mut blob_writer).unwrap();
+            }
+            finalize_blob(&mut ctx, &mut blob_mgr, &mut blob_writer).unwrap();
+
+            let mut files = Vec::new();
+            bootstrap
+                .tree
+                .walk_dfs_pre(&mut |t| {
+                    let node = t.lock_node();
+                    if node.is_reg() {
+                        let digests: Vec<_> = node.chunks.iter().map(|c| *c.inner.id()).collect();
+                        files.push((node.target().clone(), digests));
+                    }
+                    Ok(())
+                })
+                .unwrap();
+            assert_eq!(files.len(), 16 * 64 + 1);
+
+            dump_bootstrap(
+                &mut ctx,
+                &mut bootstrap_mgr,
+                &mut bootstrap_ctx,
+                &mut bootstrap,
+                &mut blob_mgr,
+                &mut blob_writer,
+            )
+            .unwrap();
+            // Nodes don't hold chunk records and directory entries once dumped.
+            bootstrap
+                .tree
+                .walk_dfs_pre(&mut |t| {
+                    let node = t.lock_node();
+                    assert!(node.chunks.is_empty());
+                    assert!(node.v6_dirents.is_empty());
+                    Ok(())
+                })
+                .unwrap();
+
+            // The dumped bootstrap still records all chunks of all files.
+            let config = image_config(blob_dir.as_path(), "");
+            let (sb, _) = RafsSuper::load_from_file(&bootstrap_path, config, false).unwrap();
+            for (path, digests) in files.iter() {
+                let ino = sb.ino_from_path(path).unwrap();
+                let inode = sb.get_extended_inode(ino, false).unwrap();
+                assert_eq!(inode.get_chunk_count() as usize, digests.len());
+                for (idx, digest) in digests.iter().enumerate() {
+                    let chunk = inode.get_chunk_info(idx as u32).unwrap();
+                    assert_eq!(chunk.chunk_id(), digest);
+                }
+            }
+        }
+    }
+}
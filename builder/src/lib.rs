@@ -14,6 +14,7 @@ use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Context, Result};
 use nydus_rafs::metadata::inode::InodeWrapper;
+use nydus_rafs::metadata::layout::v5::RafsV5InodeExtended;
 use nydus_rafs::metadata::layout::RafsXAttrs;
 use nydus_rafs::metadata::{Inode, RafsVersion};
 use nydus_storage::meta::toc;
@@ -25,18 +26,22 @@ use self::core::node::{Node, NodeInfo};
 
 pub use self::compact::BlobCompactor;
 pub use self::core::bootstrap::Bootstrap;
-pub use self::core::chunk_dict::{parse_chunk_dict_arg, ChunkDict, HashChunkDict};
+pub use self::core::chunk_dict::{parse_chunk_dict_arg, ChunkDict, DedupIndex, HashChunkDict};
 pub use self::core::context::{
     ArtifactStorage, ArtifactWriter, BlobCacheGenerator, BlobContext, BlobManager,
-    BootstrapContext, BootstrapManager, BuildContext, BuildOutput, ConversionType,
+    BootstrapContext, BootstrapManager, BuildContext, BuildOutput, ConversionType, IdMapping,
+    RAFS_MIN_CHUNK_SIZE,
 };
 pub use self::core::feature::{Feature, Features};
 pub use self::core::node::{ChunkSource, NodeChunk};
 pub use self::core::overlay::{Overlay, WhiteoutSpec};
 pub use self::core::prefetch::{Prefetch, PrefetchPolicy};
+pub use self::core::reader::BufReaderInfo;
+pub use self::core::signature::{load_private_key, sign_file};
 pub use self::core::tree::{MetadataTreeBuilder, Tree, TreeNode};
 pub use self::directory::DirectoryBuilder;
 pub use self::merge::Merger;
+pub use self::pack::Packer;
 pub use self::stargz::StargzBuilder;
 pub use self::tarball::TarballBuilder;
 
@@ -44,8 +49,11 @@ mod compact;
 mod core;
 mod directory;
 mod merge;
+mod pack;
 mod stargz;
 mod tarball;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
 
 /// Trait to generate a RAFS filesystem from the source.
 pub trait Builder {
@@ -341,6 +349,7 @@ impl TarBuilder {
             symlink: None,
             xattrs: RafsXAttrs::new(),
             v6_force_extended_inode: false,
+            v5_extended: RafsV5InodeExtended::default(),
         };
 
         Ok(Node::new(inode, info, self.layer_idx))
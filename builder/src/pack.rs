@@ -0,0 +1,250 @@
+// Copyright (C) 2023 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Convert between separate and packed layouts of single-blob RAFS images.
+//!
+//! A packed image is a single file, which contains the data blob followed by the RAFS metadata
+//! and a tar header describing the metadata:
+//!
+//! |data blob|RAFS metadata|tar header for `image.boot`|
+//!
+//! It's the same layout as data blobs with inlined RAFS metadata in legacy format, so a packed
+//! image could be mounted directly from the file, as long as the file is named by the blob id.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{bail, ensure, Context, Result};
+use nydus_api::ConfigV2;
+use nydus_rafs::metadata::RafsSuper;
+use nydus_storage::device::BlobFeatures;
+use nydus_storage::meta::toc::TOC_ENTRY_BOOTSTRAP;
+use tar::{EntryType, Header};
+
+const TAR_HEADER_SIZE: u64 = 512;
+
+/// Struct to pack RAFS metadata and data blob of an image into one file, and split it back.
+pub struct Packer {}
+
+impl Packer {
+    /// Pack the RAFS metadata `bootstrap` and its data blob `blob` into `output`.
+    pub fn pack(bootstrap: &Path, blob: &Path, output: &Path) -> Result<()> {
+        let config = Arc::new(ConfigV2::default());
+        config.internal.set_blob_accessible(false);
+        let (rs, _) = RafsSuper::load_from_file(bootstrap, config, false)
+            .with_context(|| format!("failed to load RAFS metadata {}", bootstrap.display()))?;
+        let blobs = rs.superblock.get_blob_infos();
+        ensure!(
+            blobs.len() == 1,
+            "only RAFS filesystems with one data blob can be packed, got {} blobs",
+            blobs.len()
+        );
+        let blob_info = &blobs[0];
+        if blob_info.has_feature(BlobFeatures::INLINED_FS_META) {
+            bail!(
+                "data blob {} already has inlined RAFS metadata",
+                blob_info.blob_id()
+            );
+        } else if blob_info.has_feature(BlobFeatures::HAS_TOC)
+            || blob_info.has_feature(BlobFeatures::HAS_TAR_HEADER)
+        {
+            // The blob ToC and tar headers are located from the end of the data blob.
+            bail!(
+                "data blob {} with ToC or tar headers can't be packed",
+                blob_info.blob_id()
+            );
+        } else if blob_info.has_feature(BlobFeatures::INLINED_CHUNK_DIGEST) {
+            // The chunk digest table is located by the blob ToC at the end of the data blob.
+            bail!(
+                "data blob {} with inlined chunk digest can't be packed",
+                blob_info.blob_id()
+            );
+        }
+
+        let mut blob_file =
+            File::open(blob).with_context(|| format!("failed to open {}", blob.display()))?;
+        let blob_size = blob_file.metadata()?.len();
+        if blob_info.compressed_size() != 0 && blob_info.compressed_size() != blob_size {
+            bail!(
+                "size of data blob {} doesn't match RAFS metadata, expect {}, got {}",
+                blob.display(),
+                blob_info.compressed_size(),
+                blob_size
+            );
+        }
+        let meta = fs::read(bootstrap)
+            .with_context(|| format!("failed to read {}", bootstrap.display()))?;
+
+        let mut header = Header::new_gnu();
+        header.set_path(Path::new(TOC_ENTRY_BOOTSTRAP))?;
+        header.set_entry_type(EntryType::Regular);
+        header.set_size(meta.len() as u64);
+        header.set_cksum();
+
+        let file = File::create(output)
+            .with_context(|| format!("failed to create {}", output.display()))?;
+        let mut writer = BufWriter::new(file);
+        io::copy(&mut blob_file, &mut writer).context("failed to write data blob")?;
+        writer
+            .write_all(&meta)
+            .context("failed to write RAFS metadata")?;
+        writer.write_all(header.as_bytes())?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Split the packed image `packed` into RAFS metadata `bootstrap` and data blob `blob`.
+    pub fn split(packed: &Path, bootstrap: &Path, blob: &Path) -> Result<()> {
+        let mut file =
+            File::open(packed).with_context(|| format!("failed to open {}", packed.display()))?;
+        let size = file.metadata()?.len();
+        ensure!(
+            size >= TAR_HEADER_SIZE,
+            "{} is not a packed RAFS image",
+            packed.display()
+        );
+
+        let mut buf = [0u8; TAR_HEADER_SIZE as usize];
+        file.seek(SeekFrom::Start(size - TAR_HEADER_SIZE))?;
+        file.read_exact(&mut buf)?;
+        let header = Header::from_byte_slice(&buf);
+        let is_packed = header.entry_type() == EntryType::Regular
+            && header
+                .path()
+                .map(|p| p == Path::new(TOC_ENTRY_BOOTSTRAP))
+                .unwrap_or_default();
+        ensure!(is_packed, "{} is not a packed RAFS image", packed.display());
+        let meta_size = header.entry_size()?;
+        ensure!(
+            meta_size <= size - TAR_HEADER_SIZE,
+            "invalid size of RAFS metadata in {}, max {}, got {}",
+            packed.display(),
+            size - TAR_HEADER_SIZE,
+            meta_size
+        );
+        let blob_size = size - TAR_HEADER_SIZE - meta_size;
+
+        file.seek(SeekFrom::Start(0))?;
+        Self::copy_to_file(&mut file, blob_size, blob)?;
+        Self::copy_to_file(&mut file, meta_size, bootstrap)?;
+
+        Ok(())
+    }
+
+    fn copy_to_file(reader: &mut File, size: u64, path: &Path) -> Result<()> {
+        let file =
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        let copied = io::copy(&mut reader.take(size), &mut writer)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        ensure!(
+            copied == size,
+            "short write to {}, expect {}, got {}",
+            path.display(),
+            size,
+            copied
+        );
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nydus_rafs::metadata::RafsVersion;
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+    use crate::test_utils::{build_with, filecache_toml, mount_rafs, new_build_context};
+    use crate::{ConversionType, DirectoryBuilder, Features};
+
+    #[test]
+    fn test_pack_and_split_image() {
+        let src_dir = TempDir::new().unwrap();
+        let blob_dir = TempDir::new().unwrap();
+        let pack_dir = TempDir::new().unwrap();
+        let cache_dir = TempDir::new().unwrap();
+        let content = (0..2 * 0x100000 + 0x567)
+            .map(|v| (v % 223) as u8)
+            .collect::<Vec<u8>>();
+        fs::write(src_dir.as_path().join("file"), &content).unwrap();
+
+        let mut ctx = new_build_context(
+            ConversionType::DirectoryToRafs,
+            src_dir.as_path(),
+            blob_dir.as_path(),
+            Features::new(),
+        );
+        ctx.set_fs_version(RafsVersion::V6);
+        let bootstrap_path = blob_dir.as_path().join("bootstrap");
+        let output = build_with(
+            &mut DirectoryBuilder::new(),
+            &mut ctx,
+            Some(&bootstrap_path),
+            None,
+        );
+        let blob_id = &output.blobs[0];
+        let blob_path = blob_dir.as_path().join(blob_id);
+
+        // Name the packed image by the blob id, so it can be mounted from the localfs backend.
+        let packed_path = pack_dir.as_path().join(blob_id);
+        Packer::pack(&bootstrap_path, &blob_path, &packed_path).unwrap();
+        assert!(Packer::pack(&blob_path, &bootstrap_path, &packed_path).is_err());
+
+        let rafs = mount_rafs(
+            &packed_path,
+            pack_dir.as_path(),
+            &filecache_toml(cache_dir.as_path()),
+        );
+        let mut data = Vec::new();
+        rafs.read_file_to(Path::new("/file"), &mut data).unwrap();
+        assert_eq!(data, content);
+
+        let split_bootstrap = blob_dir.as_path().join("split.boot");
+        let split_blob = blob_dir.as_path().join("split.blob");
+        Packer::split(&packed_path, &split_bootstrap, &split_blob).unwrap();
+        assert_eq!(
+            fs::read(&split_bootstrap).unwrap(),
+            fs::read(&bootstrap_path).unwrap()
+        );
+        assert_eq!(
+            fs::read(&split_blob).unwrap(),
+            fs::read(&blob_path).unwrap()
+        );
+        assert!(Packer::split(&blob_path, &split_bootstrap, &split_blob).is_err());
+    }
+
+    #[test]
+    fn test_pack_image_with_toc() {
+        let src_dir = TempDir::new().unwrap();
+        let blob_dir = TempDir::new().unwrap();
+        let pack_dir = TempDir::new().unwrap();
+        fs::write(src_dir.as_path().join("file"), vec![0x5au8; 0x1234]).unwrap();
+
+        let mut ctx = new_build_context(
+            ConversionType::DirectoryToRafs,
+            src_dir.as_path(),
+            blob_dir.as_path(),
+            Features::try_from("blob-toc").unwrap(),
+        );
+        ctx.set_fs_version(RafsVersion::V6);
+        let bootstrap_path = blob_dir.as_path().join("bootstrap");
+        let output = build_with(
+            &mut DirectoryBuilder::new(),
+            &mut ctx,
+            Some(&bootstrap_path),
+            None,
+        );
+        let blob_id = &output.blobs[0];
+        let blob_path = blob_dir.as_path().join(blob_id);
+
+        let packed_path = pack_dir.as_path().join(blob_id);
+        let err = Packer::pack(&bootstrap_path, &blob_path, &packed_path).unwrap_err();
+        assert!(err.to_string().contains("with ToC or tar headers"));
+        assert!(!packed_path.exists());
+    }
+}
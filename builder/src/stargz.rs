@@ -18,7 +18,7 @@ use base64::Engine;
 use nix::NixPath;
 use nydus_rafs::metadata::chunk::ChunkWrapper;
 use nydus_rafs::metadata::inode::{InodeWrapper, RafsInodeFlags, RafsV6Inode};
-use nydus_rafs::metadata::layout::v5::RafsV5ChunkInfo;
+use nydus_rafs::metadata::layout::v5::{RafsV5ChunkInfo, RafsV5InodeExtended};
 use nydus_rafs::metadata::layout::RafsXAttrs;
 use nydus_rafs::metadata::RafsVersion;
 use nydus_storage::device::BlobChunkFlags;
@@ -670,6 +670,7 @@ impl StargzBuilder {
             symlink,
             xattrs,
             v6_force_extended_inode: false,
+            v5_extended: RafsV5InodeExtended::default(),
         };
         let node = Node::new(inode, info, self.builder.layer_idx);
 
@@ -27,7 +27,7 @@ use tar::{Archive, Entry, EntryType, Header};
 
 use nydus_api::enosys;
 use nydus_rafs::metadata::inode::{InodeWrapper, RafsInodeFlags, RafsV6Inode};
-use nydus_rafs::metadata::layout::v5::RafsV5Inode;
+use nydus_rafs::metadata::layout::v5::{RafsV5Inode, RafsV5InodeExtended};
 use nydus_rafs::metadata::layout::RafsXAttrs;
 use nydus_rafs::metadata::RafsVersion;
 use nydus_storage::device::BlobFeatures;
@@ -37,15 +37,17 @@ use nydus_utils::compact::makedev;
 use nydus_utils::compress::zlib_random::{ZranReader, ZRAN_READER_BUF_SIZE};
 use nydus_utils::compress::ZlibDecoder;
 use nydus_utils::digest::RafsDigest;
-use nydus_utils::{div_round_up, lazy_drop, root_tracer, timing_tracer, BufReaderInfo, ByteSize};
+use nydus_utils::{div_round_up, lazy_drop, root_tracer, timing_tracer, ByteSize};
 
 use crate::core::context::{Artifact, NoopArtifactWriter};
+use crate::core::feature::Feature;
 
 use super::core::blob::Blob;
 use super::core::context::{
     ArtifactWriter, BlobManager, BootstrapManager, BuildContext, BuildOutput, ConversionType,
 };
 use super::core::node::{Node, NodeInfo};
+use super::core::reader::BufReaderInfo;
 use super::core::tree::Tree;
 use super::{build_bootstrap, dump_bootstrap, finalize_blob, Builder, TarBuilder};
 
@@ -292,9 +294,9 @@ impl<'a> TarballTreeBuilder<'a> {
                 .device_minor()
                 .context("tarball: failed to get device major from tar entry")?
                 .ok_or_else(|| anyhow!("tarball: failed to get minor device from tar entry"))?;
-            makedev(major as u64, minor as u64) as u32
+            makedev(major as u64, minor as u64) as u64
         } else {
-            u32::MAX
+            u32::MAX as u64
         };
 
         // Parse symlink
@@ -405,7 +407,7 @@ impl<'a> TarballTreeBuilder<'a> {
                 i_child_count: child_count as u32,
                 i_name_size: name.len() as u16,
                 i_symlink_size: symlink_size,
-                i_rdev: rdev,
+                i_rdev: rdev as u32,
                 i_mtime: mtime,
                 i_mtime_nsec: 0,
                 i_reserved: [0; 8],
@@ -423,12 +425,22 @@ impl<'a> TarballTreeBuilder<'a> {
                 i_child_count: child_count as u32,
                 i_name_size: name.len() as u16,
                 i_symlink_size: symlink_size,
-                i_rdev: rdev,
+                i_rdev: rdev as u32,
                 i_mtime: mtime,
                 i_mtime_nsec: 0,
             }),
         };
         inode.set_has_xattr(!xattrs.is_empty());
+        // Tar headers only carry mtime, use it for atime/ctime too.
+        let v5_extended = RafsV5InodeExtended {
+            i_rdev: rdev,
+            i_atime: mtime,
+            i_ctime: mtime,
+            ..Default::default()
+        };
+        if self.ctx.features.is_enabled(Feature::ExtendedInode) {
+            inode.set_has_extended(true);
+        }
 
         let source = PathBuf::from("/");
         let target = Node::generate_target(path, &source);
@@ -437,7 +449,7 @@ impl<'a> TarballTreeBuilder<'a> {
             explicit_uidgid: self.ctx.explicit_uidgid,
             src_ino: ino,
             src_dev: u64::MAX,
-            rdev: rdev as u64,
+            rdev,
             path: path.to_path_buf(),
             source,
             target,
@@ -445,8 +457,11 @@ impl<'a> TarballTreeBuilder<'a> {
             symlink,
             xattrs,
             v6_force_extended_inode: false,
+            v5_extended,
         };
         let mut node = Node::new(inode, info, self.builder.layer_idx);
+        node.normalize_mode(self.ctx);
+        node.remap_ids(self.ctx)?;
 
         // Special handling of hardlink.
         // Tar hardlink header has zero file size and no file data associated, so copy value from
@@ -666,8 +681,14 @@ impl Builder for TarballBuilder {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{ArtifactStorage, Features, Prefetch, WhiteoutSpec};
+    use crate::test_utils::{build_with, image_config, new_build_context};
+    use crate::{ArtifactStorage, Features, IdMapping, Prefetch, WhiteoutSpec};
+    use nydus_api::ConfigV2;
+    use nydus_rafs::fs::Rafs;
+    use nydus_rafs::metadata::RafsSuper;
     use nydus_utils::{compress, digest};
+    use std::str::FromStr;
+    use std::sync::Arc;
 
     #[test]
     fn test_build_tarfs() {
@@ -701,6 +722,182 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn test_build_with_id_maps() {
+        let tmp_dir = vmm_sys_util::tempdir::TempDir::new().unwrap();
+        let source_path = tmp_dir.as_path().join("layer.tar");
+        let bootstrap_path = tmp_dir.as_path().join("bootstrap");
+        let mut tar = tar::Builder::new(File::create(&source_path).unwrap());
+        // POSIX ACL granting access to host user 100500 and host group 200000.
+        let mut acl = 2u32.to_le_bytes().to_vec();
+        for (tag, perm, id) in [
+            (0x01u16, 6u16, u32::MAX),
+            (0x02, 6, 100500),
+            (0x04, 4, u32::MAX),
+            (0x08, 4, 200000),
+            (0x10, 6, u32::MAX),
+            (0x20, 4, u32::MAX),
+        ] {
+            acl.extend_from_slice(&tag.to_le_bytes());
+            acl.extend_from_slice(&perm.to_le_bytes());
+            acl.extend_from_slice(&id.to_le_bytes());
+        }
+        for (path, uid, gid, is_dir) in [
+            ("data", 100000u64, 100000u64, true),
+            ("data/a", 101000, 100100, false),
+            ("b", 5000, 200000, false),
+        ] {
+            if path == "b" {
+                // PAX record "<len> <key>=<value>\n", where the length covers the whole record.
+                let key = "SCHILY.xattr.system.posix_acl_access";
+                let size = key.len() + acl.len() + 3;
+                let mut len = size;
+                while len != size + len.to_string().len() {
+                    len = size + len.to_string().len();
+                }
+                let mut record = format!("{} {}=", len, key).into_bytes();
+                record.extend_from_slice(&acl);
+                record.push(b'\n');
+                let mut header = tar::Header::new_ustar();
+                header.set_entry_type(tar::EntryType::XHeader);
+                header.set_size(record.len() as u64);
+                tar.append_data(&mut header, "PaxHeaders/b", record.as_slice())
+                    .unwrap();
+            }
+            let mut header = tar::Header::new_gnu();
+            header.set_uid(uid);
+            header.set_gid(gid);
+            if is_dir {
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_mode(0o755);
+                header.set_size(0);
+                tar.append_data(&mut header, path, std::io::empty())
+                    .unwrap();
+            } else {
+                header.set_mode(0o644);
+                header.set_size(4);
+                tar.append_data(&mut header, path, &b"data"[..]).unwrap();
+            }
+        }
+        tar.finish().unwrap();
+        drop(tar);
+
+        let mut ctx = new_build_context(
+            ConversionType::TarToRafs,
+            &source_path,
+            tmp_dir.as_path(),
+            Features::new(),
+        );
+        ctx.set_id_maps(
+            vec![IdMapping::from_str("0:100000:65536").unwrap()],
+            vec![
+                IdMapping::from_str("0:100000:65536").unwrap(),
+                IdMapping::from_str("1000:200000:1").unwrap(),
+            ],
+        )
+        .unwrap();
+        build_with(
+            &mut TarballBuilder::new(ConversionType::TarToRafs),
+            &mut ctx,
+            Some(&bootstrap_path),
+            None,
+        );
+
+        let config = image_config(tmp_dir.as_path(), "");
+        let (sb, _) = RafsSuper::load_from_file(&bootstrap_path, config, false).unwrap();
+        let owner_of = |path: &str| {
+            let ino = sb.ino_from_path(Path::new(path)).unwrap();
+            let attr = sb.get_inode(ino, false).unwrap().get_attr();
+            (attr.uid, attr.gid)
+        };
+        assert_eq!(owner_of("/data"), (0, 0));
+        assert_eq!(owner_of("/data/a"), (1000, 100));
+        // Ids out of the mapped ranges are kept as is.
+        assert_eq!(owner_of("/b"), (5000, 1000));
+
+        // Ids of named users and groups in ACLs are remapped too.
+        let ino = sb.ino_from_path(Path::new("/b")).unwrap();
+        let value = sb
+            .get_inode(ino, false)
+            .unwrap()
+            .get_xattr(OsStr::new("system.posix_acl_access"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(value.len(), acl.len());
+        let ids = value[4..]
+            .chunks_exact(8)
+            .map(|e| u32::from_le_bytes(e[4..8].try_into().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [u32::MAX, 500, u32::MAX, 1000, u32::MAX, u32::MAX]);
+
+        // Owners are not saved without explicit uid/gid, so mappings are rejected.
+        ctx.explicit_uidgid = false;
+        assert!(ctx
+            .set_id_maps(vec![IdMapping::from_str("0:100000:65536").unwrap()], vec![])
+            .is_err());
+        assert!(ctx.set_id_maps(vec![], vec![]).is_ok());
+    }
+
+    #[test]
+    fn test_build_with_extended_inode() {
+        let tmp_dir = vmm_sys_util::tempdir::TempDir::new().unwrap();
+        let source_path = tmp_dir.as_path().join("layer.tar");
+        let bootstrap_path = tmp_dir.as_path().join("bootstrap");
+        let mut tar = tar::Builder::new(File::create(&source_path).unwrap());
+        let mut header = tar::Header::new_ustar();
+        header.set_entry_type(tar::EntryType::Char);
+        header.set_mode(0o600);
+        header.set_mtime(1_600_000_000);
+        // The major number doesn't fit into a 32-bit device number.
+        header.set_device_major(0x12345).unwrap();
+        header.set_device_minor(0x6789).unwrap();
+        header.set_size(0);
+        tar.append_data(&mut header, "dev", std::io::empty())
+            .unwrap();
+        let mut header = tar::Header::new_ustar();
+        header.set_mode(0o644);
+        header.set_mtime(1_600_000_001);
+        header.set_size(4);
+        tar.append_data(&mut header, "file", &b"data"[..]).unwrap();
+        tar.finish().unwrap();
+        drop(tar);
+
+        let mut ctx = new_build_context(
+            ConversionType::TarToRafs,
+            &source_path,
+            tmp_dir.as_path(),
+            Features::try_from("extended-inode").unwrap(),
+        );
+        ctx.set_fs_version(RafsVersion::V5);
+        build_with(
+            &mut TarballBuilder::new(ConversionType::TarToRafs),
+            &mut ctx,
+            Some(&bootstrap_path),
+            None,
+        );
+
+        let config = image_config(tmp_dir.as_path(), "");
+        let (sb, _) = RafsSuper::load_from_file(&bootstrap_path, config, false).unwrap();
+        assert!(sb.meta.has_extended_inode());
+        let rdev = makedev(0x12345, 0x6789) as u64;
+        assert!(rdev > u32::MAX as u64);
+        let ino = sb.ino_from_path(Path::new("/dev")).unwrap();
+        let inode = sb.get_extended_inode(ino, false).unwrap();
+        assert_eq!(inode.get_extended().unwrap().i_rdev, rdev);
+        assert_eq!(inode.get_attr().rdev, rdev as u32);
+        let ino = sb.ino_from_path(Path::new("/file")).unwrap();
+        let attr = sb.get_inode(ino, false).unwrap().get_attr();
+        assert_eq!(attr.mtime, 1_600_000_001);
+        assert_eq!(attr.atime, 1_600_000_001);
+        assert_eq!(attr.ctime, 1_600_000_001);
+
+        // The device number survives rebuilding the filesystem tree from the bootstrap.
+        let tree = Tree::from_bootstrap(&sb, &mut ()).unwrap();
+        let node = tree.get_node(Path::new("/dev")).unwrap().lock_node();
+        assert_eq!(node.info.rdev, rdev);
+        assert_eq!(node.info.v5_extended.i_rdev, rdev);
+    }
+
     #[test]
     fn test_build_encrypted_tarfs() {
         let tmp_dir = vmm_sys_util::tempdir::TempDir::new().unwrap();
@@ -732,4 +929,73 @@ mod tests {
             .build(&mut ctx, &mut bootstrap_mgr, &mut blob_mgr)
             .unwrap();
     }
+
+    #[test]
+    fn test_read_targz_ref_from_oci_layer() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let source_path = PathBuf::from(root_dir).join("../tests/texture/zran/bgzip.tar.gz");
+        let meta_dir = vmm_sys_util::tempdir::TempDir::new().unwrap();
+        let layout_dir = vmm_sys_util::tempdir::TempDir::new().unwrap();
+        let cache_dir = vmm_sys_util::tempdir::TempDir::new().unwrap();
+        let bootstrap_path = meta_dir.as_path().join("bootstrap");
+
+        let mut ctx = new_build_context(
+            ConversionType::TargzToRef,
+            &source_path,
+            meta_dir.as_path(),
+            Features::new(),
+        );
+        ctx.compressor = compress::Algorithm::GZip;
+        ctx.digester = digest::Algorithm::Sha256;
+        let output = build_with(
+            &mut TarballBuilder::new(ConversionType::TargzToRef),
+            &mut ctx,
+            Some(&bootstrap_path),
+            None,
+        );
+        assert_eq!(output.blobs.len(), 1);
+
+        // Lay out the original layer as an OCI image layout.
+        let blobs_dir = layout_dir.as_path().join("blobs").join("sha256");
+        std::fs::create_dir_all(&blobs_dir).unwrap();
+        std::fs::write(layout_dir.as_path().join("oci-layout"), "{}").unwrap();
+        std::fs::copy(&source_path, blobs_dir.join(&output.blobs[0])).unwrap();
+
+        let config = format!(
+            r#"
+            version = 2
+            id = "test"
+            backend.type = "oci-layer"
+            backend.oci-layer.dir = "{}"
+            backend.oci-layer.alt_dirs = ["{}"]
+            cache.type = "filecache"
+            cache.filecache.work_dir = "{}"
+            rafs.mode = "direct"
+            "#,
+            layout_dir.as_path().display(),
+            meta_dir.as_path().display(),
+            cache_dir.as_path().display()
+        );
+        let config = Arc::new(ConfigV2::from_str(&config).unwrap());
+        let (mut rafs, reader) = Rafs::new(&config, "test", &bootstrap_path).unwrap();
+        rafs.import(reader, None).unwrap();
+
+        let mut expected = Vec::new();
+        let mut archive = Archive::new(ZlibDecoder::new(File::open(&source_path).unwrap()));
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            if entry.path().unwrap() == Path::new("bin/busybox") {
+                entry.read_to_end(&mut expected).unwrap();
+                break;
+            }
+        }
+        assert!(!expected.is_empty());
+
+        let mut data = Vec::new();
+        let size = rafs
+            .read_file_to(Path::new("/bin/busybox"), &mut data)
+            .unwrap();
+        assert_eq!(size, expected.len() as u64);
+        assert_eq!(data, expected);
+    }
 }
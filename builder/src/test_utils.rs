@@ -0,0 +1,134 @@
+// Copyright (C) 2023 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Helpers to build and mount RAFS images in tests.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use nydus_api::ConfigV2;
+use nydus_rafs::fs::Rafs;
+use nydus_rafs::metadata::RafsVersion;
+use nydus_storage::device::BlobFeatures;
+use nydus_storage::meta::BatchContextGenerator;
+use nydus_utils::{compress, digest};
+use vmm_sys_util::tempdir::TempDir;
+
+use crate::{
+    ArtifactStorage, BlobManager, BootstrapManager, BuildContext, BuildOutput, Builder,
+    ConversionType, DirectoryBuilder, Features, Prefetch, WhiteoutSpec,
+};
+
+/// Create a build context to convert `src` into data blobs stored in `blob_dir`.
+///
+/// Data is compressed with zstd and digested with blake3, callers may adjust the context before
+/// building.
+pub fn new_build_context(
+    conversion_type: ConversionType,
+    src: &Path,
+    blob_dir: &Path,
+    features: Features,
+) -> BuildContext {
+    BuildContext::new(
+        "".to_string(),
+        false,
+        0,
+        compress::Algorithm::Zstd,
+        digest::Algorithm::Blake3,
+        true,
+        WhiteoutSpec::Oci,
+        conversion_type,
+        src.to_path_buf(),
+        Prefetch::default(),
+        Some(ArtifactStorage::FileDir(blob_dir.to_path_buf())),
+        false,
+        features,
+        false,
+    )
+}
+
+/// Pack small data chunks into batches of `batch_size` bytes, as `--batch-size` of nydus-image.
+pub fn set_batch_size(ctx: &mut BuildContext, batch_size: u32) {
+    ctx.set_batch_size(batch_size);
+    ctx.blob_batch_generator = Some(Mutex::new(BatchContextGenerator::new(batch_size).unwrap()));
+    ctx.blob_features.insert(BlobFeatures::BATCH);
+    ctx.blob_features.insert(BlobFeatures::CHUNK_INFO_V2);
+}
+
+/// Build an image with `builder`, writing the bootstrap to `bootstrap` on top of `parent`.
+pub fn build_with(
+    builder: &mut dyn Builder,
+    ctx: &mut BuildContext,
+    bootstrap: Option<&Path>,
+    parent: Option<&Path>,
+) -> BuildOutput {
+    let mut bootstrap_mgr = BootstrapManager::new(
+        bootstrap.map(|p| ArtifactStorage::SingleFile(p.to_path_buf())),
+        parent.map(|p| p.display().to_string()),
+    );
+    let mut blob_mgr = BlobManager::new(ctx.digester);
+    builder
+        .build(ctx, &mut bootstrap_mgr, &mut blob_mgr)
+        .unwrap()
+}
+
+/// Build an image from directory `src` into a new blob directory.
+///
+/// Return the path of the bootstrap and the blob directory holding it.
+pub fn build_image(src: &Path, version: RafsVersion, features: Features) -> (PathBuf, TempDir) {
+    let blob_dir = TempDir::new().unwrap();
+    let bootstrap = blob_dir.as_path().join("bootstrap");
+    let mut ctx = new_build_context(
+        ConversionType::DirectoryToRafs,
+        src,
+        blob_dir.as_path(),
+        features,
+    );
+    ctx.set_fs_version(version);
+    build_with(
+        &mut DirectoryBuilder::new(),
+        &mut ctx,
+        Some(&bootstrap),
+        None,
+    );
+    (bootstrap, blob_dir)
+}
+
+/// Configuration snippet to cache blob data in `work_dir`.
+pub fn filecache_toml(work_dir: &Path) -> String {
+    format!(
+        "cache.type = \"filecache\"\ncache.filecache.work_dir = \"{}\"\n",
+        work_dir.display()
+    )
+}
+
+/// Configuration to access images with blobs in `blob_dir`, extended by `extra_toml`.
+///
+/// Metadata is accessed in direct mode unless `extra_toml` sets `rafs.mode`.
+pub fn image_config(blob_dir: &Path, extra_toml: &str) -> Arc<ConfigV2> {
+    let mut config = format!(
+        r#"
+        version = 2
+        id = "test"
+        backend.type = "localfs"
+        backend.localfs.dir = "{}"
+        {}
+        "#,
+        blob_dir.display(),
+        extra_toml
+    );
+    if !extra_toml.contains("rafs.mode") {
+        config.push_str("rafs.mode = \"direct\"\n");
+    }
+    Arc::new(ConfigV2::from_str(&config).unwrap())
+}
+
+/// Mount the image `bootstrap` with blobs in `blob_dir`, see [image_config].
+pub fn mount_rafs(bootstrap: &Path, blob_dir: &Path, extra_toml: &str) -> Rafs {
+    let config = image_config(blob_dir, extra_toml);
+    let (mut rafs, reader) = Rafs::new(&config, "test", bootstrap).unwrap();
+    rafs.import(reader, None).unwrap();
+    rafs
+}
@@ -0,0 +1,67 @@
+// Copyright (C) 2023 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Writers to collect file data read from RAFS filesystems, shared by integration tests.
+
+#![allow(dead_code)]
+
+use std::io::Write;
+
+use fuse_backend_rs::api::filesystem::ZeroCopyWriter;
+use fuse_backend_rs::file_buf::FileVolatileSlice;
+use fuse_backend_rs::file_traits::FileReadWriteVolatile;
+
+#[derive(Default)]
+pub struct Sink {
+    pub data: Vec<u8>,
+    pub max_write: usize,
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.max_write = self.max_write.max(buf.len());
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// Collect file data read by `FileSystem::read()`, like FUSE reply buffers.
+pub struct ZeroCopySink {
+    pub data: Vec<u8>,
+    pub available: usize,
+}
+
+impl Write for ZeroCopySink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        self.available = self.available.saturating_sub(buf.len());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl ZeroCopyWriter for ZeroCopySink {
+    fn write_from(
+        &mut self,
+        f: &mut dyn FileReadWriteVolatile,
+        count: usize,
+        off: u64,
+    ) -> std::io::Result<usize> {
+        let mut buf = vec![0u8; count];
+        let bufs = [unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) }];
+        let r = f.read_vectored_at_volatile(&bufs, off)?;
+        self.write(&buf[..r])
+    }
+
+    fn available_bytes(&self) -> usize {
+        self.available
+    }
+}
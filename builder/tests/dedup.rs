@@ -0,0 +1,162 @@
+// Copyright (C) 2023 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Deduplicate chunks across images with a shared dedup index.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use nydus_api::ConfigV2;
+use nydus_builder::test_utils::{build_with, image_config, new_build_context};
+use nydus_builder::{
+    ArtifactStorage, BlobManager, BootstrapManager, BuildContext, Builder, ChunkDict,
+    ConversionType, DedupIndex, DirectoryBuilder, Features,
+};
+use nydus_rafs::metadata::{RafsSuper, RafsSuperConfig};
+use nydus_utils::{compress, digest};
+use vmm_sys_util::tempdir::TempDir;
+
+#[test]
+fn test_build_with_shared_dedup_index() {
+    let blob_dir = TempDir::new().unwrap();
+    let index_path = blob_dir.as_path().join("dedup-index.json");
+    let shared = (0..0x300000).map(|v| (v % 251) as u8).collect::<Vec<u8>>();
+
+    let build = |name: &str| {
+        let src_dir = TempDir::new().unwrap();
+        fs::write(src_dir.as_path().join("shared"), &shared).unwrap();
+        fs::write(src_dir.as_path().join(name), name).unwrap();
+
+        let mut ctx = new_build_context(
+            ConversionType::DirectoryToRafs,
+            src_dir.as_path(),
+            blob_dir.as_path(),
+            Features::new(),
+        );
+        let rafs_config = RafsSuperConfig {
+            version: ctx.fs_version,
+            compressor: ctx.compressor,
+            digester: ctx.digester,
+            chunk_size: ctx.chunk_size,
+            batch_size: ctx.batch_size,
+            explicit_uidgid: ctx.explicit_uidgid,
+            is_tarfs_mode: false,
+        };
+        let dict = DedupIndex::load(&index_path)
+            .unwrap()
+            .to_chunk_dict(&rafs_config)
+            .unwrap();
+        let bootstrap_path = blob_dir.as_path().join(format!("{}.boot", name));
+        let mut bootstrap_mgr = BootstrapManager::new(
+            Some(ArtifactStorage::SingleFile(bootstrap_path.clone())),
+            None,
+        );
+        let mut blob_mgr = BlobManager::new(digest::Algorithm::Blake3);
+        blob_mgr.set_chunk_dict(Arc::new(dict));
+        let output = DirectoryBuilder::new()
+            .build(&mut ctx, &mut bootstrap_mgr, &mut blob_mgr)
+            .unwrap();
+        DedupIndex::update(
+            &index_path,
+            &bootstrap_path,
+            Arc::new(ConfigV2::default()),
+            &rafs_config,
+        )
+        .unwrap();
+        (bootstrap_path, output)
+    };
+
+    let (bootstrap1, output1) = build("image1");
+    assert_eq!(output1.blobs.len(), 1);
+    let blob1 = output1.blobs[0].clone();
+    let index = DedupIndex::load(&index_path).unwrap();
+    assert_eq!(index.blob_count(), 1);
+    assert_eq!(index.chunk_count(), 4);
+    // The index records chunk locations, so it doesn't depend on bootstraps of other images.
+    fs::remove_file(&bootstrap1).unwrap();
+    let (bootstrap2, _) = build("image2");
+    let index = DedupIndex::load(&index_path).unwrap();
+    assert_eq!(index.blob_count(), 2);
+    assert_eq!(index.chunk_count(), 5);
+
+    let config = image_config(blob_dir.as_path(), "");
+    let (sb, _) = RafsSuper::load_from_file(&bootstrap2, config, false).unwrap();
+    let blobs = sb.superblock.get_blob_infos();
+    assert_eq!(blobs.len(), 2);
+    assert!(blobs.iter().any(|b| b.blob_id() == blob1));
+
+    // Chunks of the shared file are reused from the data blob of the first image.
+    let ino = sb.ino_from_path(Path::new("/shared")).unwrap();
+    let layout = sb.get_chunk_layout(ino).unwrap();
+    assert_eq!(layout.len(), 3);
+    assert!(layout.iter().all(|c| c.blob_id == blob1));
+    let ino = sb.ino_from_path(Path::new("/image2")).unwrap();
+    let layout = sb.get_chunk_layout(ino).unwrap();
+    assert_eq!(layout.len(), 1);
+    assert_ne!(layout[0].blob_id, blob1);
+    let blob2 = layout[0].blob_id.clone();
+    let size1 = fs::metadata(blob_dir.as_path().join(&blob1)).unwrap().len();
+    let size2 = fs::metadata(blob_dir.as_path().join(&blob2)).unwrap().len();
+    assert!(size2 < size1);
+}
+
+#[test]
+fn test_update_shared_dedup_index_concurrently() {
+    let blob_dir = TempDir::new().unwrap();
+    let index_path = blob_dir.as_path().join("dedup-index.json");
+    let mut bootstraps = Vec::new();
+    for i in 0..4 {
+        let src_dir = TempDir::new().unwrap();
+        let content = format!("image{}", i).repeat(0x1000);
+        fs::write(src_dir.as_path().join("file"), content).unwrap();
+        let mut ctx = new_build_context(
+            ConversionType::DirectoryToRafs,
+            src_dir.as_path(),
+            blob_dir.as_path(),
+            Features::new(),
+        );
+        let bootstrap_path = blob_dir.as_path().join(format!("image{}.boot", i));
+        build_with(
+            &mut DirectoryBuilder::new(),
+            &mut ctx,
+            Some(&bootstrap_path),
+            None,
+        );
+        bootstraps.push(bootstrap_path);
+    }
+
+    let ctx = BuildContext::default();
+    let rafs_config = RafsSuperConfig {
+        version: ctx.fs_version,
+        compressor: compress::Algorithm::Zstd,
+        digester: digest::Algorithm::Blake3,
+        chunk_size: ctx.chunk_size,
+        batch_size: 0,
+        explicit_uidgid: true,
+        is_tarfs_mode: false,
+    };
+    std::thread::scope(|s| {
+        for bootstrap in bootstraps.iter() {
+            let index_path = &index_path;
+            s.spawn(move || {
+                DedupIndex::update(
+                    index_path,
+                    bootstrap,
+                    Arc::new(ConfigV2::default()),
+                    &rafs_config,
+                )
+                .unwrap()
+            });
+        }
+    });
+
+    // Updates are serialized by the index lock, so no image is lost.
+    let index = DedupIndex::load(&index_path).unwrap();
+    assert_eq!(index.blob_count(), 4);
+    assert_eq!(index.chunk_count(), 4);
+    let dict = index.to_chunk_dict(&rafs_config).unwrap();
+    assert_eq!(dict.get_blobs().len(), 4);
+    assert_eq!(dict.hashmap().len(), 4);
+}
@@ -0,0 +1,448 @@
+// Copyright (C) 2023 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Build images from directories and serve them through the FUSE filesystem interface.
+
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use fuse_backend_rs::api::filesystem::{Context, FileSystem};
+use fuse_backend_rs::file_buf::FileVolatileSlice;
+use nydus_api::ConfigV2;
+use nydus_builder::test_utils::{
+    build_image, build_with, filecache_toml, image_config, mount_rafs, new_build_context,
+};
+use nydus_builder::{
+    ArtifactStorage, BlobManager, BootstrapManager, Builder, ConversionType, DirectoryBuilder,
+    Features,
+};
+use nydus_rafs::fs::{Rafs, RafsAccessAdvice};
+use nydus_rafs::metadata::encoding::RAFS_META_ENCODED_MAGIC;
+use nydus_rafs::metadata::{RafsSuper, RafsVersion};
+use nydus_storage::device::BlobDevice;
+use nydus_utils::{compress, digest};
+use vmm_sys_util::tempdir::TempDir;
+
+mod common;
+
+use common::{Sink, ZeroCopySink};
+
+#[test]
+fn test_read_inline_data_without_backend() {
+    let src_dir = TempDir::new().unwrap();
+    fs::write(src_dir.as_path().join("tiny1"), b"tiny file 1").unwrap();
+    fs::write(src_dir.as_path().join("tiny2"), b"tiny file 22").unwrap();
+    let features = Features::try_from("inline-data").unwrap();
+    let (bootstrap_path, _blob_dir) = build_image(src_dir.as_path(), RafsVersion::V5, features);
+
+    // There's no data blob at all, so files must be served without any storage backend.
+    let config = r#"
+        version = 2
+        id = "test"
+        rafs.mode = "direct"
+        "#;
+    let config = Arc::new(ConfigV2::from_str(config).unwrap());
+    let (mut rafs, reader) = Rafs::new(&config, "test", &bootstrap_path).unwrap();
+    rafs.import(reader, None).unwrap();
+    assert!(rafs.metadata().has_inline_data());
+
+    let mut sink = Sink::default();
+    let size = rafs.read_file_to(Path::new("/tiny2"), &mut sink).unwrap();
+    assert_eq!(size, 12);
+    assert_eq!(sink.data, b"tiny file 22");
+
+    let name = std::ffi::CString::new("tiny1").unwrap();
+    let entry = rafs
+        .lookup(&Context::default(), rafs.root_inode(), &name)
+        .unwrap();
+    let mut buf = vec![0u8; 8];
+    let bufs = [unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) }];
+    assert_eq!(rafs.readv(entry.inode, 5, &bufs).unwrap(), 6);
+    assert_eq!(&buf[..6], b"file 1");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_build_and_announce_submounts() {
+    use fuse_backend_rs::abi::fuse_abi::ATTR_SUBMOUNT;
+    use fuse_backend_rs::api::filesystem::{FsOptions, Layer};
+    use std::ffi::CString;
+
+    let src_dir = TempDir::new().unwrap();
+    let root = src_dir.as_path();
+    fs::create_dir_all(root.join("data/sub")).unwrap();
+    fs::write(root.join("data/file"), vec![0x1u8; 0x1000]).unwrap();
+    fs::create_dir(root.join("etc")).unwrap();
+
+    for version in [RafsVersion::V5, RafsVersion::V6] {
+        let (bootstrap_path, blob_dir) = build_image(root, version, Features::new());
+        let config = image_config(blob_dir.as_path(), "rafs.submounts = [\"/data\"]");
+        let ctx = Context::default();
+        let lookup = |rafs: &Rafs, parent: u64, name: &str| {
+            let name = CString::new(name).unwrap();
+            rafs.lookup(&ctx, parent, &name).unwrap()
+        };
+
+        // Submounts are announced only if supported by the kernel.
+        let (rafs, _) = Rafs::new(&config, "test", &bootstrap_path).unwrap();
+        let opts = rafs.init(FsOptions::empty()).unwrap();
+        assert!(!opts.contains(FsOptions::SUBMOUNTS));
+        let entry = lookup(&rafs, rafs.root_inode(), "data");
+        assert_eq!(entry.attr_flags & ATTR_SUBMOUNT, 0);
+
+        let (rafs, _) = Rafs::new(&config, "test", &bootstrap_path).unwrap();
+        let opts = rafs.init(FsOptions::SUBMOUNTS).unwrap();
+        assert!(opts.contains(FsOptions::SUBMOUNTS));
+        let data = lookup(&rafs, rafs.root_inode(), "data");
+        assert_ne!(data.attr_flags & ATTR_SUBMOUNT, 0);
+        let etc = lookup(&rafs, rafs.root_inode(), "etc");
+        assert_eq!(etc.attr_flags & ATTR_SUBMOUNT, 0);
+        let file = lookup(&rafs, data.inode, "file");
+        assert_eq!(file.attr_flags & ATTR_SUBMOUNT, 0);
+        let sub = lookup(&rafs, data.inode, "sub");
+        assert_eq!(sub.attr_flags & ATTR_SUBMOUNT, 0);
+
+        let config = image_config(blob_dir.as_path(), "rafs.submounts = [\"/data/file\"]");
+        assert!(Rafs::new(&config, "test", &bootstrap_path).is_err());
+    }
+}
+
+#[test]
+fn test_build_and_mount_with_idmap() {
+    use std::ffi::CString;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let src_dir = TempDir::new().unwrap();
+    let cache_dir = TempDir::new().unwrap();
+    let content = (0..0x12345).map(|v| (v % 251) as u8).collect::<Vec<u8>>();
+    let file_path = src_dir.as_path().join("file");
+    fs::write(&file_path, &content).unwrap();
+    fs::set_permissions(&file_path, fs::Permissions::from_mode(0o600)).unwrap();
+    let meta = fs::metadata(&file_path).unwrap();
+
+    let (bootstrap_path, blob_dir) =
+        build_image(src_dir.as_path(), RafsVersion::V5, Features::new());
+    let open_rafs = |uid_offset: u32, gid_offset: u32| {
+        let extra = format!(
+            "{}rafs.idmap.uid_offset = {}\nrafs.idmap.gid_offset = {}",
+            filecache_toml(cache_dir.as_path()),
+            uid_offset,
+            gid_offset,
+        );
+        mount_rafs(&bootstrap_path, blob_dir.as_path(), &extra)
+    };
+    let rafs1 = open_rafs(100000, 200000);
+    let rafs2 = open_rafs(300000, 400000);
+
+    let name = CString::new("file").unwrap();
+    let entry1 = rafs1
+        .lookup(&Context::default(), rafs1.root_inode(), &name)
+        .unwrap();
+    let entry2 = rafs2
+        .lookup(&Context::default(), rafs2.root_inode(), &name)
+        .unwrap();
+    assert_eq!(entry1.attr.st_uid, meta.uid() + 100000);
+    assert_eq!(entry1.attr.st_gid, meta.gid() + 200000);
+    assert_eq!(entry2.attr.st_uid, meta.uid() + 300000);
+    assert_eq!(entry2.attr.st_gid, meta.gid() + 400000);
+    let (attr1, _) = rafs1
+        .getattr(&Context::default(), entry1.inode, None)
+        .unwrap();
+    assert_eq!(attr1.st_uid, entry1.attr.st_uid);
+    assert_eq!(attr1.st_gid, entry1.attr.st_gid);
+
+    // Permission checks use the shifted ids.
+    let ctx = Context {
+        uid: entry1.attr.st_uid,
+        gid: entry1.attr.st_gid,
+        ..Default::default()
+    };
+    rafs1.access(&ctx, entry1.inode, libc::R_OK as u32).unwrap();
+    assert!(rafs2.access(&ctx, entry2.inode, libc::R_OK as u32).is_err());
+
+    let mut sink1 = Sink::default();
+    let mut sink2 = Sink::default();
+    rafs1.read_file_to(Path::new("/file"), &mut sink1).unwrap();
+    rafs2.read_file_to(Path::new("/file"), &mut sink2).unwrap();
+    assert_eq!(sink1.data, content);
+    assert_eq!(sink2.data, content);
+}
+
+#[test]
+fn test_build_and_readv_scattered() {
+    let src_dir = TempDir::new().unwrap();
+    let cache_dir = TempDir::new().unwrap();
+    let content = (0..2 * 0x100000 + 0x789)
+        .map(|v| (v % 233) as u8)
+        .collect::<Vec<u8>>();
+    fs::write(src_dir.as_path().join("big"), &content).unwrap();
+
+    let (bootstrap_path, blob_dir) =
+        build_image(src_dir.as_path(), RafsVersion::V5, Features::new());
+    let extra = filecache_toml(cache_dir.as_path());
+    let rafs = mount_rafs(&bootstrap_path, blob_dir.as_path(), &extra);
+    let config = image_config(blob_dir.as_path(), &extra);
+    let (sb, _) = RafsSuper::load_from_file(&bootstrap_path, config, false).unwrap();
+    let ino = sb.ino_from_path(Path::new("/big")).unwrap();
+
+    // Scattered buffers crossing chunk boundaries, like guest memory of a descriptor chain.
+    let offset = 0xff000;
+    let mut bufs = [vec![0u8; 0x800], vec![0u8; 0x1234], vec![0u8; 0x100000]];
+    let slices = bufs
+        .iter_mut()
+        .map(|v| unsafe { FileVolatileSlice::from_raw_ptr(v.as_mut_ptr(), v.len()) })
+        .collect::<Vec<_>>();
+    let size = rafs.readv(ino, offset, &slices).unwrap();
+    assert_eq!(size, 0x800 + 0x1234 + 0x100000);
+    let mut pos = offset as usize;
+    for buf in bufs.iter() {
+        assert_eq!(buf.as_slice(), &content[pos..pos + buf.len()]);
+        pos += buf.len();
+    }
+
+    // Short read at the end of file leaves the tail of buffers untouched.
+    let mut bufs = [vec![0u8; 0x500], vec![0u8; 0x1000]];
+    let slices = bufs
+        .iter_mut()
+        .map(|v| unsafe { FileVolatileSlice::from_raw_ptr(v.as_mut_ptr(), v.len()) })
+        .collect::<Vec<_>>();
+    let offset = content.len() - 0x700;
+    let size = rafs.readv(ino, offset as u64, &slices).unwrap();
+    assert_eq!(size, 0x700);
+    assert_eq!(bufs[0].as_slice(), &content[offset..offset + 0x500]);
+    assert_eq!(&bufs[1][..0x200], &content[offset + 0x500..]);
+    assert!(bufs[1][0x200..].iter().all(|v| *v == 0));
+    assert_eq!(rafs.readv(ino, content.len() as u64, &slices).unwrap(), 0);
+}
+
+#[test]
+fn test_build_and_readv_across_layers() {
+    let lower_dir = TempDir::new().unwrap();
+    let upper_dir = TempDir::new().unwrap();
+    let blob_dir = TempDir::new().unwrap();
+    let cache_dir = TempDir::new().unwrap();
+    let content = (0..2 * 0x100000)
+        .map(|v| (v % 227) as u8)
+        .collect::<Vec<u8>>();
+    // The first chunk of the upper file is deduplicated against the lower layer.
+    fs::write(lower_dir.as_path().join("lower"), &content[..0x100000]).unwrap();
+    fs::write(upper_dir.as_path().join("upper"), &content).unwrap();
+
+    let lower_bootstrap = blob_dir.as_path().join("lower.boot");
+    let upper_bootstrap = blob_dir.as_path().join("upper.boot");
+    let mut ctx = new_build_context(
+        ConversionType::DirectoryToRafs,
+        lower_dir.as_path(),
+        blob_dir.as_path(),
+        Features::new(),
+    );
+    build_with(
+        &mut DirectoryBuilder::new(),
+        &mut ctx,
+        Some(&lower_bootstrap),
+        None,
+    );
+    let mut ctx = new_build_context(
+        ConversionType::DirectoryToRafs,
+        upper_dir.as_path(),
+        blob_dir.as_path(),
+        Features::new(),
+    );
+    let output = build_with(
+        &mut DirectoryBuilder::new(),
+        &mut ctx,
+        Some(&upper_bootstrap),
+        Some(&lower_bootstrap),
+    );
+    assert_eq!(output.blobs.len(), 2);
+
+    let id = "test-readv-across-layers";
+    let config = image_config(blob_dir.as_path(), &filecache_toml(cache_dir.as_path()));
+    let (mut rafs, reader) = Rafs::new(&config, id, &upper_bootstrap).unwrap();
+    rafs.import(reader, None).unwrap();
+    let ino = rafs.path_to_ino("/upper").unwrap();
+
+    let mut bufs = [vec![0u8; 0x80000], vec![0u8; 0x180000]];
+    let slices = bufs
+        .iter_mut()
+        .map(|v| unsafe { FileVolatileSlice::from_raw_ptr(v.as_mut_ptr(), v.len()) })
+        .collect::<Vec<_>>();
+    assert_eq!(rafs.readv(ino, 0, &slices).unwrap(), content.len());
+    assert_eq!(bufs.concat(), content);
+
+    // Data read from both blobs is accounted to the read request.
+    let stats = nydus_utils::metrics::export_global_stats(&Some(id.to_string())).unwrap();
+    let stats: serde_json::Value = serde_json::from_str(&stats).unwrap();
+    assert_eq!(stats["data_read"].as_u64().unwrap(), content.len() as u64);
+}
+
+#[test]
+fn test_build_and_mount_compressed_bootstrap() {
+    let src_dir = TempDir::new().unwrap();
+    let content = (0..0x20000).map(|v| (v % 251) as u8).collect::<Vec<u8>>();
+    fs::create_dir(src_dir.as_path().join("dir")).unwrap();
+    fs::write(src_dir.as_path().join("dir/file"), &content).unwrap();
+
+    for (version, mode) in [
+        (RafsVersion::V5, "direct"),
+        (RafsVersion::V5, "cached"),
+        (RafsVersion::V6, "direct"),
+    ] {
+        let blob_dir = TempDir::new().unwrap();
+        let bootstrap_path = blob_dir.as_path().join("bootstrap");
+        let mut ctx = new_build_context(
+            ConversionType::DirectoryToRafs,
+            src_dir.as_path(),
+            blob_dir.as_path(),
+            Features::new(),
+        );
+        ctx.set_fs_version(version);
+        let mut bootstrap_mgr = BootstrapManager::new(
+            Some(ArtifactStorage::SingleFile(bootstrap_path.clone())),
+            None,
+        );
+        bootstrap_mgr.set_compressor(compress::Algorithm::Zstd);
+        let mut blob_mgr = BlobManager::new(digest::Algorithm::Blake3);
+        DirectoryBuilder::new()
+            .build(&mut ctx, &mut bootstrap_mgr, &mut blob_mgr)
+            .unwrap();
+        let encoded = fs::read(&bootstrap_path).unwrap();
+        assert_eq!(encoded[..8], RAFS_META_ENCODED_MAGIC);
+
+        let extra = format!("rafs.mode = \"{}\"\n", mode);
+        let rafs = mount_rafs(&bootstrap_path, blob_dir.as_path(), &extra);
+        let ino = rafs.path_to_ino("/dir/file").unwrap();
+        let mut sink = ZeroCopySink {
+            data: Vec::new(),
+            available: content.len(),
+        };
+        rafs.read(
+            &Context::default(),
+            ino,
+            0,
+            &mut sink,
+            content.len() as u32,
+            0,
+            None,
+            0,
+        )
+        .unwrap();
+        assert_eq!(sink.data, content);
+    }
+}
+
+#[test]
+fn test_build_and_warn_amplified_reads() {
+    let src_dir = TempDir::new().unwrap();
+    let cache_dir = TempDir::new().unwrap();
+    let content = (0..0x100000).map(|v| (v % 251) as u8).collect::<Vec<u8>>();
+    fs::write(src_dir.as_path().join("file"), &content).unwrap();
+
+    let (bootstrap_path, blob_dir) =
+        build_image(src_dir.as_path(), RafsVersion::V5, Features::new());
+    let extra = format!(
+        "{}rafs.amplification_warn_ratio = 16\n",
+        filecache_toml(cache_dir.as_path())
+    );
+    let config = image_config(blob_dir.as_path(), &extra);
+    let id = "test-amplified-reads";
+    let (mut rafs, reader) = Rafs::new(&config, id, &bootstrap_path).unwrap();
+    rafs.import(reader, None).unwrap();
+    let ino = rafs.path_to_ino("/file").unwrap();
+
+    // Small reads of the 1MB chunk are amplified, but only the first one is warned about.
+    let ctx = Context::default();
+    for idx in 0..4 {
+        let mut sink = ZeroCopySink {
+            data: Vec::new(),
+            available: 0x1000,
+        };
+        let offset = idx * 0x1000;
+        rafs.read(&ctx, ino, 0, &mut sink, 0x1000, offset as u64, None, 0)
+            .unwrap();
+        assert_eq!(sink.data, &content[offset..offset + 0x1000]);
+    }
+    let mut sink = ZeroCopySink {
+        data: Vec::new(),
+        available: content.len(),
+    };
+    rafs.read(&ctx, ino, 0, &mut sink, content.len() as u32, 0, None, 0)
+        .unwrap();
+    assert_eq!(sink.data, content);
+
+    let stats = nydus_utils::metrics::export_global_stats(&Some(id.to_string())).unwrap();
+    let stats: serde_json::Value = serde_json::from_str(&stats).unwrap();
+    assert_eq!(stats["amplified_reads"].as_u64().unwrap(), 4);
+    assert_eq!(stats["amplified_read_warns"].as_u64().unwrap(), 1);
+}
+
+#[test]
+fn test_build_and_read_with_access_advice() {
+    let src_dir = TempDir::new().unwrap();
+    let cache_dir = TempDir::new().unwrap();
+    let names = ["normal", "random", "sequential"];
+    let contents = names
+        .iter()
+        .enumerate()
+        .map(|(idx, name)| {
+            let content = (0..4 * 0x100000)
+                .map(|v| ((v + idx) % 239) as u8)
+                .collect::<Vec<u8>>();
+            fs::write(src_dir.as_path().join(name), &content).unwrap();
+            content
+        })
+        .collect::<Vec<_>>();
+
+    let (bootstrap_path, blob_dir) =
+        build_image(src_dir.as_path(), RafsVersion::V5, Features::new());
+    let extra = format!(
+        "{}rafs.batch_size = {}\n",
+        filecache_toml(cache_dir.as_path()),
+        2 * 0x100000
+    );
+    let rafs = mount_rafs(&bootstrap_path, blob_dir.as_path(), &extra);
+    let inodes = names
+        .iter()
+        .map(|name| rafs.path_to_ino(&format!("/{}", name)).unwrap())
+        .collect::<Vec<_>>();
+    rafs.fadvise(inodes[1], RafsAccessAdvice::Random);
+    rafs.fadvise(inodes[2], RafsAccessAdvice::Sequential);
+    assert_eq!(rafs.access_advice(inodes[0]), RafsAccessAdvice::Normal);
+
+    let ctx = Context::default();
+    for (ino, content) in inodes.iter().zip(contents.iter()) {
+        let mut sink = ZeroCopySink {
+            data: Vec::new(),
+            available: content.len(),
+        };
+        rafs.read(&ctx, *ino, 0, &mut sink, 0x1000, 0, None, 0)
+            .unwrap();
+        assert_eq!(&sink.data[..0x1000], &content[..0x1000]);
+    }
+
+    // Reads are amplified to the 2MB batch size by default, to 512KB for random access and
+    // to 8MB, up to the end of file, for sequential access.
+    let config = image_config(blob_dir.as_path(), &extra);
+    let (sb, _) = RafsSuper::load_from_file(&bootstrap_path, config.clone(), false).unwrap();
+    let device = BlobDevice::new(&config, &sb.superblock.get_blob_infos()).unwrap();
+    let chunk_ready = |ino: u64, idx: u64| {
+        let inode = sb.get_inode(ino, false).unwrap();
+        let io_vecs = inode
+            .alloc_bio_vecs(&device, idx * 0x100000, 0x100000, true)
+            .unwrap();
+        device.all_chunks_ready(&io_vecs)
+    };
+    assert!(chunk_ready(inodes[0], 1));
+    assert!(!chunk_ready(inodes[0], 2));
+    assert!(chunk_ready(inodes[1], 0));
+    assert!(!chunk_ready(inodes[1], 1));
+    assert!((0..4).all(|idx| chunk_ready(inodes[2], idx)));
+
+    // Advices are dropped once the kernel forgets the inode.
+    rafs.forget(&ctx, inodes[2], 1);
+    assert_eq!(rafs.access_advice(inodes[2]), RafsAccessAdvice::Normal);
+    assert_eq!(rafs.access_advice(inodes[1]), RafsAccessAdvice::Random);
+}
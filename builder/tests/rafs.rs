@@ -0,0 +1,976 @@
+// Copyright (C) 2023 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Build images from directories and access them through RAFS.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use fuse_backend_rs::api::filesystem::{Context, FileSystem};
+use nydus_builder::test_utils::{
+    build_image, build_with, filecache_toml, image_config, mount_rafs, new_build_context,
+    set_batch_size,
+};
+use nydus_builder::{BuildContext, BuildOutput, ConversionType, DirectoryBuilder, Features, Tree};
+use nydus_rafs::fs::Rafs;
+use nydus_rafs::metadata::{RafsSuper, RafsVersion};
+use nydus_storage::device::{BlobDevice, BlobFeatures};
+use nydus_storage::{RAFS_DEFAULT_CHUNK_SIZE, RAFS_MAX_CHUNK_SIZE};
+use nydus_utils::{compress, crypt};
+use vmm_sys_util::tempdir::TempDir;
+
+mod common;
+
+use common::Sink;
+
+fn new_encrypted_context(src_dir: &Path, blob_dir: &Path) -> BuildContext {
+    let mut ctx = new_build_context(
+        ConversionType::DirectoryToRafs,
+        src_dir,
+        blob_dir,
+        Features::new(),
+    );
+    ctx.set_fs_version(RafsVersion::V6);
+    ctx.cipher = crypt::Algorithm::Aes128Xts;
+    ctx.blob_features.insert(BlobFeatures::CHUNK_INFO_V2);
+    ctx.blob_features.insert(BlobFeatures::ENCRYPTED);
+    ctx
+}
+
+#[test]
+fn test_build_and_stream_file() {
+    let src_dir = TempDir::new().unwrap();
+    let cache_dir = TempDir::new().unwrap();
+    let content = (0..3 * 0x100000 + 0x123)
+        .map(|v| (v % 251) as u8)
+        .collect::<Vec<u8>>();
+    fs::write(src_dir.as_path().join("big"), &content).unwrap();
+
+    let (bootstrap, blob_dir) = build_image(src_dir.as_path(), RafsVersion::V5, Features::new());
+    let rafs = mount_rafs(
+        &bootstrap,
+        blob_dir.as_path(),
+        &filecache_toml(cache_dir.as_path()),
+    );
+
+    let mut sink = Sink::default();
+    let size = rafs.read_file_to(Path::new("/big"), &mut sink).unwrap();
+    assert_eq!(size, content.len() as u64);
+    assert_eq!(sink.data, content);
+    assert!(sink.max_write <= RAFS_DEFAULT_CHUNK_SIZE as usize);
+    assert!(rafs
+        .read_file_to(&PathBuf::from("/"), &mut Sink::default())
+        .is_err());
+}
+
+#[test]
+fn test_build_blob_compression_stats() {
+    let src_dir = TempDir::new().unwrap();
+    let content = (0..2 * 0x100000 + 0x456)
+        .map(|v| (v % 239) as u8)
+        .collect::<Vec<u8>>();
+    fs::write(src_dir.as_path().join("file1"), &content).unwrap();
+    // Duplicated chunks are stored only once in the data blob.
+    fs::write(src_dir.as_path().join("file2"), &content).unwrap();
+    fs::write(src_dir.as_path().join("file3"), &content[..0x1234]).unwrap();
+
+    let (bootstrap_path, blob_dir) =
+        build_image(src_dir.as_path(), RafsVersion::V5, Features::new());
+    let config = image_config(blob_dir.as_path(), "");
+    let (sb, _) = RafsSuper::load_from_file(&bootstrap_path, config.clone(), false).unwrap();
+    let tree = Tree::from_bootstrap(&sb, &mut ()).unwrap();
+    let mut seen = HashSet::new();
+    let (mut compressed_size, mut uncompressed_size) = (0u64, 0u64);
+    tree.walk_dfs_pre(&mut |t: &Tree| -> Result<()> {
+        for chunk in t.lock_node().chunks.iter() {
+            assert_eq!(chunk.inner.blob_index(), 0);
+            if seen.insert(chunk.inner.compressed_offset()) {
+                compressed_size += chunk.inner.compressed_size() as u64;
+                uncompressed_size += chunk.inner.uncompressed_size() as u64;
+            }
+        }
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(seen.len(), 4);
+
+    let (rafs, _) = Rafs::new(&config, "test", &bootstrap_path).unwrap();
+    let blobs = rafs.blobs();
+    assert_eq!(blobs.len(), 1);
+    assert_eq!(blobs[0].compressor(), compress::Algorithm::Zstd);
+    assert_eq!(blobs[0].compressed_data_size(), compressed_size);
+    assert_eq!(blobs[0].uncompressed_size(), uncompressed_size);
+    assert!(blobs[0].compressed_data_size() < blobs[0].uncompressed_size());
+}
+
+#[test]
+fn test_build_and_verify_all() {
+    let src_dir = TempDir::new().unwrap();
+    let blob_dir = TempDir::new().unwrap();
+    let cache_dir = TempDir::new().unwrap();
+    let content = (0..2 * 0x100000 + 0x123)
+        .map(|v| (v % 241) as u8)
+        .collect::<Vec<u8>>();
+    fs::write(src_dir.as_path().join("big"), &content).unwrap();
+    // Duplicated chunks are verified only once.
+    fs::write(src_dir.as_path().join("dup"), &content).unwrap();
+    fs::write(src_dir.as_path().join("small"), b"small").unwrap();
+
+    let mut ctx = new_build_context(
+        ConversionType::DirectoryToRafs,
+        src_dir.as_path(),
+        blob_dir.as_path(),
+        Features::new(),
+    );
+    ctx.compressor = compress::Algorithm::None;
+    let bootstrap_path = blob_dir.as_path().join("bootstrap");
+    build_with(
+        &mut DirectoryBuilder::new(),
+        &mut ctx,
+        Some(&bootstrap_path),
+        None,
+    );
+
+    let open_rafs = |work_dir: &Path| {
+        mount_rafs(
+            &bootstrap_path,
+            blob_dir.as_path(),
+            &filecache_toml(work_dir),
+        )
+    };
+
+    let rafs = open_rafs(cache_dir.as_path());
+    let report = rafs.verify_all(4).unwrap();
+    assert_eq!(report.total_chunks, 4);
+    assert_eq!(report.verified_chunks(), 4);
+    assert!(report.bad_chunks.is_empty());
+    assert_eq!(report.score(), 100.0);
+
+    // Corrupt one byte of the second chunk of "/big", and verify with an empty cache.
+    let layout = rafs.chunk_layout(Path::new("/big")).unwrap();
+    let blob_path = blob_dir.as_path().join(&layout[1].blob_id);
+    let mut blob = fs::read(&blob_path).unwrap();
+    blob[layout[1].compressed_offset as usize + 0x10] ^= 0xff;
+    fs::write(&blob_path, &blob).unwrap();
+
+    let cache_dir = TempDir::new().unwrap();
+    let rafs = open_rafs(cache_dir.as_path());
+    let report = rafs.verify_all(1).unwrap();
+    assert_eq!(report.total_chunks, 4);
+    assert_eq!(report.verified_chunks(), 3);
+    assert_eq!(report.score(), 75.0);
+    assert_eq!(report.bad_chunks.len(), 1);
+    let bad = &report.bad_chunks[0];
+    assert_eq!(bad.path, PathBuf::from("/big"));
+    assert_eq!(bad.file_offset, ctx.chunk_size as u64);
+    assert_eq!(bad.blob_id, layout[1].blob_id);
+    assert_eq!(bad.compressed_offset, layout[1].compressed_offset);
+}
+
+#[test]
+fn test_build_and_verify_all_with_batch_and_inline_data() {
+    let src_dir = TempDir::new().unwrap();
+    for idx in 0..4u8 {
+        fs::write(
+            src_dir.as_path().join(format!("small{}", idx)),
+            vec![idx; 0x800],
+        )
+        .unwrap();
+    }
+    fs::write(src_dir.as_path().join("tiny"), b"tiny").unwrap();
+
+    // Chunks packed into the same batch are verified one by one.
+    let blob_dir = TempDir::new().unwrap();
+    let cache_dir = TempDir::new().unwrap();
+    let mut ctx = new_build_context(
+        ConversionType::DirectoryToRafs,
+        src_dir.as_path(),
+        blob_dir.as_path(),
+        Features::new(),
+    );
+    set_batch_size(&mut ctx, 0x10000);
+    let bootstrap_path = blob_dir.as_path().join("bootstrap");
+    build_with(
+        &mut DirectoryBuilder::new(),
+        &mut ctx,
+        Some(&bootstrap_path),
+        None,
+    );
+    let rafs = mount_rafs(
+        &bootstrap_path,
+        blob_dir.as_path(),
+        &filecache_toml(cache_dir.as_path()),
+    );
+    let small0 = rafs.chunk_layout(Path::new("/small0")).unwrap();
+    let small1 = rafs.chunk_layout(Path::new("/small1")).unwrap();
+    assert_eq!(small0[0].compressed_offset, small1[0].compressed_offset);
+    let report = rafs.verify_all(2).unwrap();
+    assert_eq!(report.total_chunks, 5);
+    assert!(report.bad_chunks.is_empty());
+    assert_eq!(report.inline_files, 0);
+
+    // Files with inlined data are verified against the inode digest.
+    let features = Features::try_from("inline-data").unwrap();
+    let (bootstrap_path, blob_dir) = build_image(src_dir.as_path(), RafsVersion::V5, features);
+    let cache_dir = TempDir::new().unwrap();
+    let rafs = mount_rafs(
+        &bootstrap_path,
+        blob_dir.as_path(),
+        &filecache_toml(cache_dir.as_path()),
+    );
+    let report = rafs.verify_all(2).unwrap();
+    assert_eq!(report.total_chunks, 4);
+    assert!(report.bad_chunks.is_empty());
+    assert_eq!(report.inline_files, 1);
+    assert!(report.bad_inline_files.is_empty());
+
+    let mut bootstrap = fs::read(&bootstrap_path).unwrap();
+    let pos = bootstrap.len() - bootstrap.iter().rev().position(|v| *v == b'y').unwrap() - 1;
+    assert_eq!(&bootstrap[pos - 3..=pos], b"tiny");
+    bootstrap[pos] = b'Y';
+    fs::write(&bootstrap_path, bootstrap).unwrap();
+    let rafs = mount_rafs(
+        &bootstrap_path,
+        blob_dir.as_path(),
+        &filecache_toml(cache_dir.as_path()),
+    );
+    let report = rafs.verify_all(2).unwrap();
+    assert_eq!(report.inline_files, 1);
+    assert_eq!(report.bad_inline_files, vec![PathBuf::from("/tiny")]);
+}
+
+#[test]
+fn test_build_with_mode_policy() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let src_dir = TempDir::new().unwrap();
+    let blob_dir = TempDir::new().unwrap();
+    let root = src_dir.as_path();
+    fs::create_dir(root.join("dir")).unwrap();
+    fs::write(root.join("dir/file"), b"file").unwrap();
+    fs::write(root.join("exec"), b"exec").unwrap();
+    std::os::unix::fs::symlink("exec", root.join("link")).unwrap();
+    for (name, mode) in [("dir", 0o777), ("dir/file", 0o666), ("exec", 0o4777)] {
+        fs::set_permissions(root.join(name), fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    let build = |mode_mask: u32, force_dir_mode: Option<u32>| {
+        let mut ctx = new_build_context(
+            ConversionType::DirectoryToRafs,
+            root,
+            blob_dir.as_path(),
+            Features::new(),
+        );
+        ctx.set_mode_policy(mode_mask, force_dir_mode, None)
+            .unwrap();
+        let bootstrap_path = blob_dir.as_path().join("bootstrap");
+        build_with(
+            &mut DirectoryBuilder::new(),
+            &mut ctx,
+            Some(&bootstrap_path),
+            None,
+        );
+
+        let config = image_config(blob_dir.as_path(), "");
+        let (sb, _) = RafsSuper::load_from_file(&bootstrap_path, config, false).unwrap();
+        sb
+    };
+    let mode_of = |sb: &RafsSuper, path: &str| {
+        let ino = sb.ino_from_path(Path::new(path)).unwrap();
+        sb.get_inode(ino, false).unwrap().get_attr().mode
+    };
+
+    let sb = build(0o022, None);
+    assert_eq!(mode_of(&sb, "/dir"), libc::S_IFDIR as u32 | 0o755);
+    assert_eq!(mode_of(&sb, "/dir/file"), libc::S_IFREG as u32 | 0o644);
+    assert_eq!(mode_of(&sb, "/exec"), libc::S_IFREG as u32 | 0o4755);
+    assert_eq!(mode_of(&sb, "/link"), libc::S_IFLNK as u32 | 0o777);
+
+    // Forced modes take precedence over the mode mask.
+    let sb = build(0o027, Some(0o700));
+    assert_eq!(mode_of(&sb, "/"), libc::S_IFDIR as u32 | 0o700);
+    assert_eq!(mode_of(&sb, "/dir"), libc::S_IFDIR as u32 | 0o700);
+    assert_eq!(mode_of(&sb, "/dir/file"), libc::S_IFREG as u32 | 0o640);
+
+    let mut ctx = BuildContext::default();
+    assert!(ctx.set_mode_policy(0o10022, None, None).is_err());
+    assert!(ctx.set_mode_policy(0o022, None, Some(0o100644)).is_err());
+}
+
+#[test]
+fn test_build_and_walk_paths() {
+    let src_dir = TempDir::new().unwrap();
+    let root = src_dir.as_path();
+    fs::create_dir_all(root.join("a/b/c")).unwrap();
+    fs::create_dir(root.join("empty")).unwrap();
+    fs::write(root.join("file"), b"file").unwrap();
+    fs::write(root.join("a/file1"), b"file1").unwrap();
+    fs::write(root.join("a/b/file2"), b"file2").unwrap();
+    fs::hard_link(root.join("a/b/file2"), root.join("a/b/c/link")).unwrap();
+    std::os::unix::fs::symlink("../file", root.join("a/symlink")).unwrap();
+
+    let (bootstrap_path, blob_dir) = build_image(root, RafsVersion::V5, Features::new());
+    let config = image_config(blob_dir.as_path(), "");
+    let (sb, _) = RafsSuper::load_from_file(&bootstrap_path, config, false).unwrap();
+    let mut paths = Vec::new();
+    sb.walk(&mut |path, inode| {
+        if path == Path::new("/a/b/c/link") {
+            assert!(inode.is_hardlink());
+        }
+        paths.push(path.to_path_buf());
+        Ok(())
+    })
+    .unwrap();
+
+    let expected = [
+        "/",
+        "/a",
+        "/a/b",
+        "/a/b/c",
+        "/a/b/c/link",
+        "/a/b/file2",
+        "/a/file1",
+        "/a/symlink",
+        "/empty",
+        "/file",
+    ];
+    assert_eq!(
+        paths,
+        expected.iter().map(PathBuf::from).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_build_and_read_flat_view() {
+    let src_dir = TempDir::new().unwrap();
+    let cache_dir = TempDir::new().unwrap();
+    let root = src_dir.as_path();
+    let big = (0..0x100000 + 0x321)
+        .map(|v| (v % 241) as u8)
+        .collect::<Vec<u8>>();
+    fs::create_dir_all(root.join("a/b")).unwrap();
+    fs::write(root.join("a/b/big"), &big).unwrap();
+    fs::write(root.join("a/small"), b"small").unwrap();
+    fs::write(root.join("top"), b"top").unwrap();
+    fs::hard_link(root.join("top"), root.join("a/b/link")).unwrap();
+
+    let (bootstrap_path, blob_dir) = build_image(root, RafsVersion::V5, Features::new());
+    let extra = format!(
+        "{}rafs.flat_view = true",
+        filecache_toml(cache_dir.as_path())
+    );
+    let rafs = mount_rafs(&bootstrap_path, blob_dir.as_path(), &extra);
+    let config = image_config(blob_dir.as_path(), &extra);
+    let (sb, _) = RafsSuper::load_from_file(&bootstrap_path, config, false).unwrap();
+
+    let mut files = HashSet::new();
+    for path in ["/a/b/big", "/a/small", "/top", "/a/b/link"] {
+        let ino = sb.ino_from_path(Path::new(path)).unwrap();
+        let mut sink = Sink::default();
+        let size = rafs
+            .read_file_to(&PathBuf::from(format!("/{}", ino)), &mut sink)
+            .unwrap();
+        assert_eq!(size, sink.data.len() as u64);
+        assert_eq!(sink.data, fs::read(root.join(&path[1..])).unwrap());
+        files.insert(ino);
+    }
+    // Hardlinks share the same entry in the flat view.
+    assert_eq!(files.len(), 3);
+    // Original paths are not visible in the flat view.
+    assert!(rafs
+        .read_file_to(Path::new("/top"), &mut Sink::default())
+        .is_err());
+}
+
+#[test]
+fn test_build_and_get_chunk_layout() {
+    let src_dir = TempDir::new().unwrap();
+    let root = src_dir.as_path();
+    let content = (0..3 * 0x100000 + 0x567)
+        .map(|v| (v % 251) as u8)
+        .collect::<Vec<u8>>();
+    fs::create_dir(root.join("dir")).unwrap();
+    fs::write(root.join("dir/file"), &content).unwrap();
+
+    for (version, modes) in [
+        (RafsVersion::V5, vec!["direct", "cached"]),
+        (RafsVersion::V6, vec!["direct"]),
+    ] {
+        let blob_dir = TempDir::new().unwrap();
+        let mut ctx = new_build_context(
+            ConversionType::DirectoryToRafs,
+            root,
+            blob_dir.as_path(),
+            Features::new(),
+        );
+        ctx.set_fs_version(version);
+        let bootstrap_path = blob_dir.as_path().join("bootstrap");
+        let output = build_with(
+            &mut DirectoryBuilder::new(),
+            &mut ctx,
+            Some(&bootstrap_path),
+            None,
+        );
+        assert_eq!(output.blobs.len(), 1);
+
+        for mode in modes {
+            let config = image_config(blob_dir.as_path(), &format!("rafs.mode = \"{}\"", mode));
+            let (sb, _) = RafsSuper::load_from_file(&bootstrap_path, config, false).unwrap();
+            let blobs = sb.superblock.get_blob_infos();
+            assert_eq!(blobs[0].blob_id(), output.blobs[0]);
+
+            let ino = sb.ino_from_path(Path::new("/dir/file")).unwrap();
+            let layout = sb.get_chunk_layout(ino).unwrap();
+            assert_eq!(layout.len(), 4);
+            let inode = sb.get_extended_inode(ino, false).unwrap();
+            let mut offset = 0;
+            for (idx, chunk) in layout.iter().enumerate() {
+                let info = inode.get_chunk_info(idx as u32).unwrap();
+                assert_eq!(chunk.blob_id, output.blobs[0]);
+                assert_eq!(chunk.compressed_offset, info.compressed_offset());
+                assert_eq!(chunk.compressed_size, info.compressed_size());
+                // Chunks of the only file are stored in order in the data blob.
+                assert_eq!(chunk.compressed_offset, offset);
+                offset += chunk.compressed_size as u64;
+            }
+            assert_eq!(offset, blobs[0].compressed_data_size());
+
+            let ino = sb.ino_from_path(Path::new("/dir")).unwrap();
+            assert!(sb.get_chunk_layout(ino).is_err());
+        }
+    }
+}
+
+#[test]
+fn test_build_and_get_inode_generation() {
+    let src_dir = TempDir::new().unwrap();
+    let root = src_dir.as_path();
+    fs::write(root.join("file"), vec![0x1u8; 0x1000]).unwrap();
+    std::os::unix::fs::symlink("file", root.join("link")).unwrap();
+
+    for version in [RafsVersion::V5, RafsVersion::V6] {
+        let build = || {
+            let (bootstrap_path, blob_dir) = build_image(root, version, Features::new());
+            let config = image_config(blob_dir.as_path(), "");
+            let (sb, _) = RafsSuper::load_from_file(&bootstrap_path, config, false).unwrap();
+            ["/file", "/link"]
+                .iter()
+                .map(|path| {
+                    let ino = sb.ino_from_path(Path::new(path)).unwrap();
+                    let entry = sb.get_inode(ino, false).unwrap().get_entry();
+                    assert_eq!(entry.inode, ino);
+                    (entry.inode, entry.generation)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Rebuilding the same source tree yields the same generation numbers.
+        let entries = build();
+        assert_ne!(entries[0].1, 0);
+        assert_ne!(entries[0].1, entries[1].1);
+        assert_eq!(build(), entries);
+
+        // Changing file content reuses inode numbers but bumps generations of the image.
+        fs::write(root.join("file"), vec![0x2u8; 0x1000]).unwrap();
+        let changed = build();
+        for (new, old) in changed.iter().zip(entries.iter()) {
+            assert_eq!(new.0, old.0);
+            assert_ne!(new.1, old.1);
+        }
+        fs::write(root.join("file"), vec![0x1u8; 0x1000]).unwrap();
+    }
+}
+
+#[test]
+fn test_build_with_blob_id_from_digest() {
+    use sha2::{Digest, Sha256};
+
+    let src_dir = TempDir::new().unwrap();
+    let blob_dir = TempDir::new().unwrap();
+    let content = (0..0x123456).map(|v| (v % 239) as u8).collect::<Vec<u8>>();
+    fs::write(src_dir.as_path().join("file"), &content).unwrap();
+
+    let mut ctx = new_build_context(
+        ConversionType::DirectoryToRafs,
+        src_dir.as_path(),
+        blob_dir.as_path(),
+        Features::try_from("blob-toc").unwrap(),
+    );
+    let bootstrap_path = blob_dir.as_path().join("bootstrap");
+    let output = build_with(
+        &mut DirectoryBuilder::new(),
+        &mut ctx,
+        Some(&bootstrap_path),
+        None,
+    );
+    assert_eq!(output.blobs.len(), 1);
+
+    // The blob file is named by its own SHA-256 digest, which is also the blob id.
+    let blob_id = &output.blobs[0];
+    let data = fs::read(blob_dir.as_path().join(blob_id)).unwrap();
+    assert_eq!(&format!("{:x}", Sha256::digest(&data)), blob_id);
+
+    let config = image_config(blob_dir.as_path(), "");
+    let (sb, _) = RafsSuper::load_from_file(&bootstrap_path, config, false).unwrap();
+    let blobs = sb.superblock.get_blob_infos();
+    assert_eq!(blobs.len(), 1);
+    assert_eq!(&blobs[0].blob_id(), blob_id);
+}
+
+#[test]
+fn test_build_and_reload_metadata() {
+    use fuse_backend_rs::api::filesystem::Layer;
+    use std::ffi::CString;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    for version in [RafsVersion::V5, RafsVersion::V6] {
+        let blob_dir = TempDir::new().unwrap();
+        let cache_dir = TempDir::new().unwrap();
+        // Images with the same file data but different directory entries share the data blob.
+        let build = |prefix: &str, count: usize| {
+            let src_dir = TempDir::new().unwrap();
+            let root = src_dir.as_path();
+            fs::write(root.join("data"), vec![0x5au8; 0x3000]).unwrap();
+            for idx in 0..count {
+                fs::write(root.join(format!("{}{:02}", prefix, idx)), b"").unwrap();
+            }
+            let mut ctx = new_build_context(
+                ConversionType::DirectoryToRafs,
+                root,
+                blob_dir.as_path(),
+                Features::new(),
+            );
+            ctx.set_fs_version(version);
+            let bootstrap_path = blob_dir.as_path().join(format!("bootstrap-{}", prefix));
+            build_with(
+                &mut DirectoryBuilder::new(),
+                &mut ctx,
+                Some(&bootstrap_path),
+                None,
+            );
+            bootstrap_path
+        };
+        let old_bootstrap = build("a", 8);
+        let new_bootstrap = build("b", 16);
+
+        let config = image_config(blob_dir.as_path(), &filecache_toml(cache_dir.as_path()));
+        let (mut rafs, reader) = Rafs::new(&config, "test", &old_bootstrap).unwrap();
+        assert!(rafs.reload(&new_bootstrap).is_err());
+        rafs.import(reader, None).unwrap();
+        let rafs = Arc::new(rafs);
+        let old_meta = rafs.metadata();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut handles = Vec::new();
+        for _ in 0..4 {
+            let rafs = rafs.clone();
+            let stop = stop.clone();
+            handles.push(std::thread::spawn(move || {
+                let ctx = Context::default();
+                let data = CString::new("data").unwrap();
+                let mut rounds = 0;
+                while !stop.load(Ordering::Acquire) {
+                    let mut names = Vec::new();
+                    rafs.readdir(&ctx, rafs.root_inode(), 0, 0x10000, 0, &mut |entry| {
+                        names.push(String::from_utf8(entry.name.to_vec()).unwrap());
+                        Ok(1)
+                    })
+                    .unwrap();
+                    names.retain(|v| v != "." && v != ".." && v != "data");
+                    // Each listing must come from either the old or the new metadata.
+                    let old = names.iter().filter(|v| v.starts_with('a')).count();
+                    let new = names.iter().filter(|v| v.starts_with('b')).count();
+                    assert!((old, new) == (8, 0) || (old, new) == (0, 16), "{:?}", names);
+
+                    let entry = rafs.lookup(&ctx, rafs.root_inode(), &data).unwrap();
+                    assert_eq!(entry.attr.st_size, 0x3000);
+                    rounds += 1;
+                }
+                rounds
+            }));
+        }
+
+        for idx in 0..32 {
+            let bootstrap = if idx % 2 == 0 {
+                &new_bootstrap
+            } else {
+                &old_bootstrap
+            };
+            rafs.reload(bootstrap).unwrap();
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        stop.store(true, Ordering::Release);
+        for handle in handles {
+            assert!(handle.join().unwrap() > 0);
+        }
+
+        rafs.reload(&new_bootstrap).unwrap();
+        // Superblock metadata is refreshed together with the inodes.
+        let new_meta = rafs.metadata();
+        assert_ne!(new_meta.generation, old_meta.generation);
+        let entry = rafs
+            .lookup(
+                &Context::default(),
+                rafs.root_inode(),
+                &CString::new("b15").unwrap(),
+            )
+            .unwrap();
+        assert_eq!(entry.generation, new_meta.get_generation(entry.inode));
+        let mut sink = Sink::default();
+        assert_eq!(rafs.read_file_to(Path::new("/b15"), &mut sink).unwrap(), 0);
+        assert!(rafs.read_file_to(Path::new("/a07"), &mut sink).is_err());
+        let size = rafs.read_file_to(Path::new("/data"), &mut sink).unwrap();
+        assert_eq!(size, 0x3000);
+        assert!(sink.data.iter().all(|v| *v == 0x5a));
+    }
+}
+
+#[test]
+fn test_mount_latency_metrics() {
+    let src_dir = TempDir::new().unwrap();
+    let cache_dir = TempDir::new().unwrap();
+    for idx in 0..64 {
+        let dir = src_dir.as_path().join(format!("dir{:02}", idx));
+        fs::create_dir(&dir).unwrap();
+        for file in 0..64 {
+            fs::write(dir.join(format!("file{:02}", file)), idx.to_string()).unwrap();
+        }
+    }
+
+    let (bootstrap_path, blob_dir) =
+        build_image(src_dir.as_path(), RafsVersion::V5, Features::new());
+    let config = image_config(blob_dir.as_path(), &filecache_toml(cache_dir.as_path()));
+    // Metrics are registered by the id of the filesystem instance.
+    let start = Instant::now();
+    let (mut rafs, reader) = Rafs::new(&config, "test-mount-latency", &bootstrap_path).unwrap();
+    let elapsed = start.elapsed().as_micros() as u64;
+    rafs.import(reader, None).unwrap();
+
+    let stats =
+        nydus_utils::metrics::export_global_stats(&Some("test-mount-latency".to_string())).unwrap();
+    let stats: serde_json::Value = serde_json::from_str(&stats).unwrap();
+    let metadata_load = stats["metadata_load_latency"].as_u64().unwrap();
+    let mount = stats["mount_latency"].as_u64().unwrap();
+    assert!(metadata_load > 0);
+    assert!(metadata_load <= mount);
+    assert!(mount <= elapsed);
+}
+
+#[test]
+fn test_build_with_chunk_size_bounds() {
+    let src_dir = TempDir::new().unwrap();
+    let blob_dir = TempDir::new().unwrap();
+    let cache_dir = TempDir::new().unwrap();
+    // Highly repetitive content.
+    let content = vec![0x5au8; 0x100000 + 0x123];
+    fs::write(src_dir.as_path().join("repeat"), &content).unwrap();
+
+    let mut ctx = new_build_context(
+        ConversionType::DirectoryToRafs,
+        src_dir.as_path(),
+        blob_dir.as_path(),
+        Features::new(),
+    );
+    ctx.set_chunk_size(0x10000);
+    assert!(ctx.set_chunk_size_bounds(0x1000, 0x8000).is_err());
+    ctx.set_chunk_size_bounds(0x1000, 0x10000).unwrap();
+    let bootstrap_path = blob_dir.as_path().join("bootstrap");
+    build_with(
+        &mut DirectoryBuilder::new(),
+        &mut ctx,
+        Some(&bootstrap_path),
+        None,
+    );
+
+    let config = image_config(blob_dir.as_path(), &filecache_toml(cache_dir.as_path()));
+    let (sb, _) = RafsSuper::load_from_file(&bootstrap_path, config, false).unwrap();
+    let tree = Tree::from_bootstrap(&sb, &mut ()).unwrap();
+    let mut sizes = Vec::new();
+    tree.walk_dfs_pre(&mut |t: &Tree| -> Result<()> {
+        for chunk in t.lock_node().chunks.iter() {
+            sizes.push(chunk.inner.uncompressed_size() as u64);
+        }
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(sizes.len(), 17);
+    assert_eq!(sizes.iter().sum::<u64>(), content.len() as u64);
+    assert!(sizes
+        .iter()
+        .all(|v| *v <= 0x10000 && *v <= RAFS_MAX_CHUNK_SIZE));
+
+    let rafs = mount_rafs(
+        &bootstrap_path,
+        blob_dir.as_path(),
+        &filecache_toml(cache_dir.as_path()),
+    );
+    let mut sink = Sink::default();
+    rafs.read_file_to(Path::new("/repeat"), &mut sink).unwrap();
+    assert_eq!(sink.data, content);
+}
+
+#[test]
+fn test_build_and_read_with_chunk_compressor() {
+    let src_dir = TempDir::new().unwrap();
+    let text = (0..2 * 0x100000 + 0x321)
+        .map(|v| (v % 233) as u8)
+        .collect::<Vec<u8>>();
+    fs::write(src_dir.as_path().join("text"), &text).unwrap();
+    // Pseudo random data can't be compressed by any algorithm.
+    let mut seed = 0x1234_5678u32;
+    let random = (0..0x100000 + 0x10)
+        .map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) as u8
+        })
+        .collect::<Vec<u8>>();
+    fs::write(src_dir.as_path().join("random"), &random).unwrap();
+
+    for version in [RafsVersion::V5, RafsVersion::V6] {
+        let blob_dir = TempDir::new().unwrap();
+        let cache_dir = TempDir::new().unwrap();
+        let mut ctx = new_build_context(
+            ConversionType::DirectoryToRafs,
+            src_dir.as_path(),
+            blob_dir.as_path(),
+            Features::new(),
+        );
+        ctx.set_fs_version(version);
+        ctx.compressor = compress::Algorithm::Lz4Block;
+        assert!(ctx
+            .set_chunk_compressor(compress::Algorithm::Lz4Block)
+            .is_err());
+        assert!(ctx.set_chunk_compressor(compress::Algorithm::None).is_err());
+        ctx.set_chunk_compressor(compress::Algorithm::Zstd).unwrap();
+        let bootstrap_path = blob_dir.as_path().join("bootstrap");
+        build_with(
+            &mut DirectoryBuilder::new(),
+            &mut ctx,
+            Some(&bootstrap_path),
+            None,
+        );
+
+        let config = image_config(blob_dir.as_path(), &filecache_toml(cache_dir.as_path()));
+        config.internal.set_blob_accessible(true);
+        let (sb, _) = RafsSuper::load_from_file(&bootstrap_path, config.clone(), false).unwrap();
+        let blobs = sb.superblock.get_blob_infos();
+        assert_eq!(blobs.len(), 1);
+        assert_eq!(blobs[0].compressor(), compress::Algorithm::Lz4Block);
+        assert!(blobs[0].has_feature(BlobFeatures::CHUNK_COMPRESSOR));
+
+        // Zstd beats lz4 on text, random data is stored as is.
+        let device = BlobDevice::new(&config, &blobs).unwrap();
+        let tree = Tree::from_bootstrap(&sb, &mut ()).unwrap();
+        let (mut zstd_chunks, mut plain_chunks) = (0, 0);
+        tree.walk_dfs_pre(&mut |t: &Tree| -> Result<()> {
+            for chunk in t.lock_node().chunks.iter() {
+                let chunk = &chunk.inner;
+                match (chunk.is_compressed(), chunk.compressor()) {
+                    (true, Some(compress::Algorithm::Zstd)) => zstd_chunks += 1,
+                    (false, None) => plain_chunks += 1,
+                    v => panic!("unexpected chunk compression {:?}", v),
+                }
+                let meta_chunks = device
+                    .get_chunks_by_compressed_offset(0, chunk.compressed_offset())
+                    .unwrap();
+                if version == RafsVersion::V6 {
+                    let meta_chunks = meta_chunks.unwrap();
+                    assert_eq!(meta_chunks.len(), 1);
+                    assert_eq!(meta_chunks[0].is_compressed(), chunk.is_compressed());
+                    assert_eq!(meta_chunks[0].compressor(), chunk.compressor());
+                }
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert!(zstd_chunks > 0);
+        assert!(plain_chunks > 0);
+
+        let rafs = mount_rafs(
+            &bootstrap_path,
+            blob_dir.as_path(),
+            &filecache_toml(cache_dir.as_path()),
+        );
+        for (path, content) in [("/text", &text), ("/random", &random)] {
+            let mut sink = Sink::default();
+            rafs.read_file_to(Path::new(path), &mut sink).unwrap();
+            assert_eq!(&sink.data, content);
+        }
+    }
+}
+
+#[test]
+fn test_build_and_read_encrypted() {
+    let src_dir = TempDir::new().unwrap();
+    let blob_dir = TempDir::new().unwrap();
+    let cache_dir = TempDir::new().unwrap();
+    // Pseudo random data is stored as is by the compressor, so it would show up in the blob
+    // if not encrypted.
+    let mut seed = 0x1234_5678u32;
+    let content = (0..2 * 0x100000 + 0x321)
+        .map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) as u8
+        })
+        .collect::<Vec<u8>>();
+    fs::write(src_dir.as_path().join("secret"), &content).unwrap();
+
+    let mut ctx = new_encrypted_context(src_dir.as_path(), blob_dir.as_path());
+    let bootstrap_path = blob_dir.as_path().join("bootstrap");
+    let output = build_with(
+        &mut DirectoryBuilder::new(),
+        &mut ctx,
+        Some(&bootstrap_path),
+        None,
+    );
+    assert_eq!(output.blobs.len(), 1);
+
+    // Chunks are encrypted at rest in the data blob.
+    let blob = fs::read(blob_dir.as_path().join(&output.blobs[0])).unwrap();
+    for pos in (0..content.len() - 64).step_by(0x40000) {
+        let window = &content[pos..pos + 64];
+        assert!(!blob.windows(64).any(|w| w == window));
+    }
+
+    let rafs = mount_rafs(
+        &bootstrap_path,
+        blob_dir.as_path(),
+        &filecache_toml(cache_dir.as_path()),
+    );
+    let blobs = rafs.blobs();
+    assert_eq!(blobs.len(), 1);
+    assert!(blobs[0].has_feature(BlobFeatures::ENCRYPTED));
+    assert_eq!(blobs[0].cipher(), nydus_utils::crypt::Algorithm::Aes128Xts);
+
+    let mut sink = Sink::default();
+    let size = rafs.read_file_to(Path::new("/secret"), &mut sink).unwrap();
+    assert_eq!(size, content.len() as u64);
+    assert_eq!(sink.data, content);
+}
+
+#[test]
+fn test_build_encrypted_layers_with_per_blob_keys() {
+    fn build_layer(
+        src_dir: &Path,
+        blob_dir: &Path,
+        bootstrap_path: &Path,
+        parent_path: Option<&Path>,
+    ) -> BuildOutput {
+        let mut ctx = new_encrypted_context(src_dir, blob_dir);
+        build_with(
+            &mut DirectoryBuilder::new(),
+            &mut ctx,
+            Some(bootstrap_path),
+            parent_path,
+        )
+    }
+
+    let lower_dir = TempDir::new().unwrap();
+    let upper_dir = TempDir::new().unwrap();
+    let blob_dir = TempDir::new().unwrap();
+    let cache_dir = TempDir::new().unwrap();
+    let lower_content = (0..0x100000 + 0x123)
+        .map(|v| (v % 211) as u8)
+        .collect::<Vec<u8>>();
+    let upper_content = (0..0x80000 + 0x456)
+        .map(|v| (v % 199) as u8)
+        .collect::<Vec<u8>>();
+    fs::write(lower_dir.as_path().join("lower"), &lower_content).unwrap();
+    fs::write(upper_dir.as_path().join("upper"), &upper_content).unwrap();
+
+    let lower_bootstrap = blob_dir.as_path().join("lower.boot");
+    let upper_bootstrap = blob_dir.as_path().join("upper.boot");
+    build_layer(
+        lower_dir.as_path(),
+        blob_dir.as_path(),
+        &lower_bootstrap,
+        None,
+    );
+    let output = build_layer(
+        upper_dir.as_path(),
+        blob_dir.as_path(),
+        &upper_bootstrap,
+        Some(&lower_bootstrap),
+    );
+    assert_eq!(output.blobs.len(), 2);
+
+    let rafs = mount_rafs(
+        &upper_bootstrap,
+        blob_dir.as_path(),
+        &filecache_toml(cache_dir.as_path()),
+    );
+
+    // Each blob is encrypted with its own key.
+    let blobs = rafs.blobs();
+    assert_eq!(blobs.len(), 2);
+    let keys = blobs
+        .iter()
+        .map(|b| {
+            assert!(b.has_feature(BlobFeatures::ENCRYPTED));
+            let cipher_ctx = b.cipher_context().unwrap();
+            cipher_ctx.get_cipher_meta().0.to_vec()
+        })
+        .collect::<Vec<_>>();
+    assert_ne!(keys[0], keys[1]);
+
+    let mut sink = Sink::default();
+    rafs.read_file_to(Path::new("/lower"), &mut sink).unwrap();
+    assert_eq!(sink.data, lower_content);
+    let mut sink = Sink::default();
+    rafs.read_file_to(Path::new("/upper"), &mut sink).unwrap();
+    assert_eq!(sink.data, upper_content);
+}
+
+#[test]
+fn test_build_and_mount_single_file_image() {
+    let src_dir = TempDir::new().unwrap();
+    let blob_dir = TempDir::new().unwrap();
+    let cache_dir = TempDir::new().unwrap();
+    let big = (0..2 * 0x100000 + 0x789)
+        .map(|v| (v % 227) as u8)
+        .collect::<Vec<u8>>();
+    fs::create_dir(src_dir.as_path().join("dir")).unwrap();
+    fs::write(src_dir.as_path().join("dir/big"), &big).unwrap();
+    fs::write(src_dir.as_path().join("small"), b"small file").unwrap();
+
+    let mut ctx = new_build_context(
+        ConversionType::DirectoryToRafs,
+        src_dir.as_path(),
+        blob_dir.as_path(),
+        Features::try_from("blob-toc").unwrap(),
+    );
+    ctx.set_fs_version(RafsVersion::V6);
+    // RAFS metadata is inlined into the data blob instead of a separate bootstrap file.
+    ctx.blob_inline_meta = true;
+    ctx.blob_features.insert(BlobFeatures::INLINED_FS_META);
+    build_with(&mut DirectoryBuilder::new(), &mut ctx, None, None);
+
+    let files = fs::read_dir(blob_dir.as_path())
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect::<Vec<_>>();
+    assert_eq!(files.len(), 1);
+    let image_path = &files[0];
+
+    // Mount from the single file, RAFS metadata is extracted from the data blob.
+    let rafs = mount_rafs(
+        image_path,
+        blob_dir.as_path(),
+        &filecache_toml(cache_dir.as_path()),
+    );
+    let blobs = rafs.blobs();
+    assert_eq!(blobs.len(), 1);
+    assert!(blobs[0].has_feature(BlobFeatures::INLINED_FS_META));
+
+    let mut sink = Sink::default();
+    rafs.read_file_to(Path::new("/dir/big"), &mut sink).unwrap();
+    assert_eq!(sink.data, big);
+    let mut sink = Sink::default();
+    rafs.read_file_to(Path::new("/small"), &mut sink).unwrap();
+    assert_eq!(sink.data, b"small file");
+}
@@ -0,0 +1,364 @@
+// Copyright (C) 2023 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Build images from directories and access their data blobs through the storage subsystem.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use fuse_backend_rs::api::filesystem::{Context, FileSystem};
+use fuse_backend_rs::file_buf::FileVolatileSlice;
+use nydus_builder::test_utils::{build_image, filecache_toml, image_config, mount_rafs};
+use nydus_builder::Features;
+use nydus_rafs::fs::Rafs;
+use nydus_rafs::metadata::{RafsSuper, RafsVersion};
+use nydus_storage::device::BlobDevice;
+use vmm_sys_util::tempdir::TempDir;
+
+mod common;
+
+use common::Sink;
+
+#[test]
+fn test_build_and_read_uncached() {
+    let src_dir = TempDir::new().unwrap();
+    let cache_dir = TempDir::new().unwrap();
+    let content = (0..2 * 0x100000 + 0x789)
+        .map(|v| (v % 233) as u8)
+        .collect::<Vec<u8>>();
+    fs::write(src_dir.as_path().join("big"), &content).unwrap();
+
+    let (bootstrap_path, blob_dir) =
+        build_image(src_dir.as_path(), RafsVersion::V5, Features::new());
+    let config = image_config(blob_dir.as_path(), &filecache_toml(cache_dir.as_path()));
+    config.internal.set_blob_accessible(true);
+    let (sb, _) = RafsSuper::load_from_file(&bootstrap_path, config.clone(), false).unwrap();
+    let device = BlobDevice::new(&config, &sb.superblock.get_blob_infos()).unwrap();
+    let ino = sb.ino_from_path(Path::new("/big")).unwrap();
+    let inode = sb.get_inode(ino, false).unwrap();
+
+    let mut io_vecs = inode
+        .alloc_bio_vecs(&device, 0, content.len(), true)
+        .unwrap();
+    let mut buf = vec![0u8; content.len()];
+    let mut pos = 0;
+    for io_vec in io_vecs.iter_mut() {
+        io_vec.set_uncached(true);
+        let len = io_vec.size() as usize;
+        let r = device
+            .read_to_buf(io_vec, &mut buf[pos..pos + len])
+            .unwrap();
+        assert_eq!(r, len);
+        pos += r;
+    }
+    assert_eq!(buf, content);
+    // Data fetched from the backend must not be saved into the blob cache.
+    assert!(!device.all_chunks_ready(&io_vecs));
+}
+
+#[test]
+fn test_build_and_prefetch_dir() {
+    let src_dir = TempDir::new().unwrap();
+    let cache_dir = TempDir::new().unwrap();
+    let root = src_dir.as_path();
+    fs::create_dir_all(root.join("dir/sub")).unwrap();
+    for idx in 0..4u8 {
+        let content = vec![idx + 1; 0x1000 + idx as usize * 0x321];
+        fs::write(root.join(format!("dir/small{}", idx)), &content).unwrap();
+    }
+    let big = (0..0x100000 + 0x123)
+        .map(|v| (v % 227) as u8)
+        .collect::<Vec<u8>>();
+    fs::write(root.join("dir/big"), &big).unwrap();
+
+    let (bootstrap_path, blob_dir) = build_image(root, RafsVersion::V5, Features::new());
+    let extra = format!(
+        "{}cache.prefetch.enable = true\nrafs.dir_prefetch = true",
+        filecache_toml(cache_dir.as_path())
+    );
+    let rafs = mount_rafs(&bootstrap_path, blob_dir.as_path(), &extra);
+    let config = image_config(blob_dir.as_path(), &extra);
+    let (sb, _) = RafsSuper::load_from_file(&bootstrap_path, config.clone(), false).unwrap();
+    let device = BlobDevice::new(&config, &sb.superblock.get_blob_infos()).unwrap();
+
+    let mut small_files = Vec::new();
+    for idx in 0..4 {
+        let path = format!("/dir/small{}", idx);
+        let ino = sb.ino_from_path(Path::new(&path)).unwrap();
+        let inode = sb.get_inode(ino, false).unwrap();
+        let io_vecs = inode
+            .alloc_bio_vecs(&device, 0, inode.size() as usize, true)
+            .unwrap();
+        assert!(!device.all_chunks_ready(&io_vecs));
+        small_files.push(io_vecs);
+    }
+
+    let ino = sb.ino_from_path(Path::new("/dir")).unwrap();
+    let mut names = Vec::new();
+    rafs.readdir(&Context::default(), ino, 0, 4096, 0, &mut |entry| {
+        names.push(String::from_utf8(entry.name.to_vec()).unwrap());
+        Ok(1)
+    })
+    .unwrap();
+    assert_eq!(names.len(), 8);
+
+    // Small files are prefetched in background without being read.
+    let start = Instant::now();
+    while !small_files.iter().all(|v| device.all_chunks_ready(v)) {
+        assert!(start.elapsed() < Duration::from_secs(10));
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn test_build_and_prefetch_from_history() {
+    use std::ffi::CString;
+
+    let src_dir = TempDir::new().unwrap();
+    let history_dir = TempDir::new().unwrap();
+    let root = src_dir.as_path();
+    fs::create_dir_all(root.join("dir")).unwrap();
+    for idx in 0..4u8 {
+        let content = vec![idx + 1; 0x1000 + idx as usize * 0x321];
+        fs::write(root.join(format!("dir/file{}", idx)), &content).unwrap();
+    }
+
+    let (bootstrap_path, blob_dir) = build_image(root, RafsVersion::V5, Features::new());
+    let extra_toml = |cache_dir: &TempDir| {
+        format!(
+            r#"
+            {}
+            cache.prefetch.enable = true
+            rafs.prefetch.enable = true
+            rafs.prefetch.history_dir = "{}"
+            "#,
+            filecache_toml(cache_dir.as_path()),
+            history_dir.as_path().display()
+        )
+    };
+
+    // Read some files on the first mount, to build the access history.
+    let cache_dir = TempDir::new().unwrap();
+    let rafs = mount_rafs(&bootstrap_path, blob_dir.as_path(), &extra_toml(&cache_dir));
+    let ctx = Context::default();
+    let name = CString::new("dir").unwrap();
+    let dir = rafs.lookup(&ctx, rafs.root_inode(), &name).unwrap();
+    for idx in [2, 0] {
+        let name = CString::new(format!("file{}", idx)).unwrap();
+        let entry = rafs.lookup(&ctx, dir.inode, &name).unwrap();
+        let mut buf = vec![0u8; entry.attr.st_size as usize];
+        let bufs = [unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) }];
+        assert_eq!(rafs.readv(entry.inode, 0, &bufs).unwrap(), buf.len());
+        assert!(buf.iter().all(|v| *v == idx + 1));
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    rafs.save_prefetch_history().unwrap();
+    let history = fs::read_dir(history_dir.as_path())
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect::<Vec<_>>();
+    assert_eq!(history.len(), 1);
+    assert_eq!(
+        fs::read_to_string(&history[0]).unwrap(),
+        "/dir/file2\n/dir/file0\n"
+    );
+
+    // Files in the history are prefetched on the next mount, with an empty cache, before
+    // being read.
+    let cache_dir = TempDir::new().unwrap();
+    let _rafs = mount_rafs(&bootstrap_path, blob_dir.as_path(), &extra_toml(&cache_dir));
+    let config = image_config(blob_dir.as_path(), &extra_toml(&cache_dir));
+    let (sb, _) = RafsSuper::load_from_file(&bootstrap_path, config.clone(), false).unwrap();
+    let device = BlobDevice::new(&config, &sb.superblock.get_blob_infos()).unwrap();
+    let files = (0..4)
+        .map(|idx| {
+            let path = format!("/dir/file{}", idx);
+            let ino = sb.ino_from_path(Path::new(&path)).unwrap();
+            let inode = sb.get_inode(ino, false).unwrap();
+            inode
+                .alloc_bio_vecs(&device, 0, inode.size() as usize, true)
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    let start = Instant::now();
+    while ![2, 0]
+        .iter()
+        .all(|idx| device.all_chunks_ready(&files[*idx]))
+    {
+        assert!(start.elapsed() < Duration::from_secs(10));
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    // Files not in the history are left alone.
+    assert!(!device.all_chunks_ready(&files[1]));
+    assert!(!device.all_chunks_ready(&files[3]));
+}
+
+#[test]
+fn test_build_and_warm_chunks() {
+    use nydus_rafs::metadata::ChunkLayout;
+
+    let src_dir = TempDir::new().unwrap();
+    let root = src_dir.as_path();
+    fs::create_dir_all(root.join("dir")).unwrap();
+    // Pseudo random data is stored as is by the compressor, so fetching a chunk never brings
+    // in neighbouring chunks within the read amplification window.
+    let mut seed = 0x1357_9bdfu32;
+    let mut contents = Vec::new();
+    for idx in 0..4 {
+        let content = (0..0x40000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect::<Vec<u8>>();
+        fs::write(root.join(format!("dir/file{}", idx)), &content).unwrap();
+        contents.push(content);
+    }
+
+    // Listed chunks are resolved by the blob meta for RAFS v6, and by file chunks for v5.
+    for version in [RafsVersion::V5, RafsVersion::V6] {
+        let cache_dir = TempDir::new().unwrap();
+        let (bootstrap_path, blob_dir) = build_image(root, version, Features::new());
+        let extra = format!("{}rafs.batch_size = 0", filecache_toml(cache_dir.as_path()));
+        let rafs = mount_rafs(&bootstrap_path, blob_dir.as_path(), &extra);
+        let config = image_config(blob_dir.as_path(), &extra);
+        let (sb, _) = RafsSuper::load_from_file(&bootstrap_path, config.clone(), false).unwrap();
+        let device = BlobDevice::new(&config, &sb.superblock.get_blob_infos()).unwrap();
+        let files = (0..4)
+            .map(|idx| {
+                let path = format!("/dir/file{}", idx);
+                let ino = sb.ino_from_path(Path::new(&path)).unwrap();
+                let inode = sb.get_inode(ino, false).unwrap();
+                inode
+                    .alloc_bio_vecs(&device, 0, inode.size() as usize, true)
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert!(files.iter().all(|v| !device.all_chunks_ready(v)));
+
+        // Warm up file0 by inode and file2 by chunks, file0 is also listed by chunks.
+        let ino = rafs.path_to_ino("/dir/file0").unwrap();
+        let mut chunks = rafs.chunk_layout(Path::new("/dir/file2")).unwrap();
+        chunks.extend(rafs.chunk_layout(Path::new("/dir/file0")).unwrap());
+        let blob_id = chunks[0].blob_id.clone();
+        chunks.push(ChunkLayout {
+            blob_id,
+            compressed_offset: u64::MAX,
+            compressed_size: 0,
+        });
+        let report = rafs.warm(&[ino], &chunks, 2).unwrap();
+        assert!(!report.running);
+        assert_eq!(report.succeeded, 2);
+        assert_eq!(report.failed, 1);
+        assert!(device.all_chunks_ready(&files[0]));
+        assert!(!device.all_chunks_ready(&files[1]));
+        assert!(device.all_chunks_ready(&files[2]));
+        assert!(!device.all_chunks_ready(&files[3]));
+
+        let mut sink = Sink::default();
+        rafs.read_file_to(Path::new("/dir/file2"), &mut sink)
+            .unwrap();
+        assert_eq!(sink.data, contents[2]);
+
+        // Directories and unknown inodes are counted as failed.
+        let report = rafs.warm(&[rafs.root_inode(), u64::MAX], &[], 2).unwrap();
+        assert_eq!(report.succeeded, 0);
+        assert_eq!(report.failed, 2);
+        assert_eq!(rafs.warm_report().failed, 2);
+    }
+}
+
+#[test]
+fn test_build_and_account_cached_data() {
+    let src_dir = TempDir::new().unwrap();
+    let cache_dir = TempDir::new().unwrap();
+    let content = (0..3 * 0x100000)
+        .map(|v| (v % 233) as u8)
+        .collect::<Vec<u8>>();
+    fs::write(src_dir.as_path().join("file"), &content).unwrap();
+    let (bootstrap_path, blob_dir) =
+        build_image(src_dir.as_path(), RafsVersion::V5, Features::new());
+    let extra = filecache_toml(cache_dir.as_path());
+    let cache_stats = |id: &str| {
+        let stats = nydus_utils::metrics::export_blobcache_metrics(&Some(id.to_string()));
+        serde_json::from_str::<serde_json::Value>(&stats.unwrap()).unwrap()
+    };
+
+    // RAFS v5 blobs have no chunk information array, cached chunks are accounted when read.
+    let id = "test-account-cached-data";
+    let mut config = image_config(blob_dir.as_path(), &extra).as_ref().clone();
+    config.id = id.to_string();
+    let (mut rafs, reader) = Rafs::new(&Arc::new(config), id, &bootstrap_path).unwrap();
+    rafs.import(reader, None).unwrap();
+    assert_eq!(cache_stats(id)["cached_chunks"], 0);
+    let ino = rafs.path_to_ino("/file").unwrap();
+    let mut buf = vec![0u8; content.len()];
+    let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+    assert_eq!(rafs.readv(ino, 0, &[slice]).unwrap(), content.len());
+    assert_eq!(buf, content);
+
+    // Chunk data is persisted into the cache file in background.
+    let start = Instant::now();
+    while cache_stats(id)["cached_chunks"] != 3 {
+        assert!(start.elapsed() < Duration::from_secs(10));
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(cache_stats(id)["cached_bytes"], content.len() as u64);
+    drop(rafs);
+
+    // Chunks cached by the previous instance are accounted when the cache is opened again.
+    let id = "test-account-cached-data-reopen";
+    let mut config = image_config(blob_dir.as_path(), &extra).as_ref().clone();
+    config.id = id.to_string();
+    let (mut rafs, reader) = Rafs::new(&Arc::new(config), id, &bootstrap_path).unwrap();
+    rafs.import(reader, None).unwrap();
+    let stats = cache_stats(id);
+    assert_eq!(stats["cached_chunks"], 3);
+    assert!(stats["cached_bytes"].as_u64().unwrap() >= content.len() as u64);
+}
+
+#[test]
+fn test_build_file_of_chunk_size_multiple() {
+    let src_dir = TempDir::new().unwrap();
+    let chunk_size = nydus_storage::RAFS_DEFAULT_CHUNK_SIZE as usize;
+    let content = (0..2 * chunk_size)
+        .map(|v| (v % 241) as u8)
+        .collect::<Vec<u8>>();
+    fs::write(src_dir.as_path().join("file"), &content).unwrap();
+
+    for version in [RafsVersion::V5, RafsVersion::V6] {
+        let cache_dir = TempDir::new().unwrap();
+        let (bootstrap_path, blob_dir) = build_image(src_dir.as_path(), version, Features::new());
+        let config = image_config(blob_dir.as_path(), &filecache_toml(cache_dir.as_path()));
+        config.internal.set_blob_accessible(true);
+        let (sb, _) = RafsSuper::load_from_file(&bootstrap_path, config.clone(), false).unwrap();
+        let device = BlobDevice::new(&config, &sb.superblock.get_blob_infos()).unwrap();
+        let ino = sb.ino_from_path(Path::new("/file")).unwrap();
+        let inode = sb.get_extended_inode(ino, false).unwrap();
+        assert_eq!(inode.get_chunk_count(), 2);
+        // The last chunk is a full chunk instead of an empty one.
+        for idx in 0..2 {
+            let chunk = inode.get_chunk_info(idx).unwrap();
+            assert_eq!(chunk.uncompressed_size() as usize, chunk_size);
+        }
+
+        let mut io_vecs = inode
+            .alloc_bio_vecs(&device, 0, content.len(), true)
+            .unwrap();
+        let mut buf = vec![0u8; content.len()];
+        let mut pos = 0;
+        for io_vec in io_vecs.iter_mut() {
+            let len = io_vec.size() as usize;
+            let r = device
+                .read_to_buf(io_vec, &mut buf[pos..pos + len])
+                .unwrap();
+            assert_eq!(r, len);
+            pos += r;
+        }
+        assert_eq!(pos, content.len());
+        assert_eq!(buf, content);
+    }
+}
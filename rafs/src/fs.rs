@@ -16,30 +16,42 @@
 
 use std::any::Any;
 use std::cmp;
+use std::collections::{HashMap, HashSet};
 use std::ffi::{CStr, OsStr, OsString};
-use std::io::Result;
+use std::fs;
+use std::io::{ErrorKind, Result, Write};
 use std::ops::Deref;
 use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
 
+use arc_swap::ArcSwap;
 use fuse_backend_rs::abi::fuse_abi::Attr;
 use fuse_backend_rs::abi::fuse_abi::{stat64, statvfs64};
 use fuse_backend_rs::api::filesystem::*;
 use fuse_backend_rs::api::BackendFileSystem;
+use fuse_backend_rs::file_buf::FileVolatileSlice;
 use nix::unistd::{getegid, geteuid};
+use serde::Serialize;
 
 use nydus_api::ConfigV2;
-use nydus_storage::device::{BlobDevice, BlobIoVec, BlobPrefetchRequest};
+use nydus_storage::device::{
+    BlobChunkInfo, BlobDevice, BlobInfo, BlobIoChunk, BlobIoDesc, BlobIoMerge, BlobIoVec,
+    BlobPrefetchRequest,
+};
+use nydus_storage::utils::{check_digest, copyv};
 use nydus_storage::{RAFS_DEFAULT_CHUNK_SIZE, RAFS_MAX_CHUNK_SIZE};
 use nydus_utils::{
+    digest::{self, RafsDigest},
     div_round_up,
     metrics::{self, FopRecorder, StatsFop::*},
 };
 
 use crate::metadata::{
-    Inode, RafsInode, RafsInodeWalkAction, RafsSuper, RafsSuperMeta, DOT, DOTDOT,
+    ChunkLayout, Inode, RafsInode, RafsInodeWalkAction, RafsMode, RafsSuper, RafsSuperMeta, DOT,
+    DOTDOT,
 };
 use crate::{RafsError, RafsIoReader, RafsResult};
 
@@ -50,6 +62,124 @@ pub type Handle = u64;
 pub const RAFS_DEFAULT_ATTR_TIMEOUT: u64 = 1 << 32;
 /// Rafs default entry timeout value.
 pub const RAFS_DEFAULT_ENTRY_TIMEOUT: u64 = RAFS_DEFAULT_ATTR_TIMEOUT;
+/// Handle for files whose data should be read without populating the blob cache.
+const RAFS_UNCACHED_HANDLE: Handle = 1;
+/// Directories with more children are skipped by the directory prefetch heuristic.
+const RAFS_DIR_PREFETCH_MAX_CHILDREN: u32 = 256;
+/// Only regular files not bigger than this are prefetched by the directory prefetch heuristic.
+const RAFS_DIR_PREFETCH_MAX_FILE_SIZE: u64 = 0x10000;
+/// Factor to scale the read amplification window by for files with access advices.
+const RAFS_ADVICE_IO_BATCH_FACTOR: u32 = 4;
+/// Number of shards of the access advice map, to avoid contention between reads of files.
+const RAFS_ADVICE_SHARDS: usize = 16;
+/// Minimum interval in seconds between warnings about amplified reads.
+const RAFS_AMPLIFICATION_WARN_INTERVAL: u64 = 60;
+/// Sequence number to generate unique names for temporary prefetch history files.
+static RAFS_HISTORY_TMP_SEQ: AtomicUsize = AtomicUsize::new(0);
+
+/// Access pattern of a file advised by clients, like advices of `posix_fadvise()`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RafsAccessAdvice {
+    /// No special access pattern, the default.
+    Normal,
+    /// The file is expected to be read sequentially, so amplify reads with larger windows.
+    Sequential,
+    /// The file is expected to be read randomly, so amplify reads with smaller windows.
+    Random,
+}
+
+// Access advices of files, sharded by inode number.
+#[derive(Default)]
+struct AccessAdvices {
+    shards: [RwLock<HashMap<Inode, RafsAccessAdvice>>; RAFS_ADVICE_SHARDS],
+    // Number of files with advices, so reads don't touch the shards when there's none.
+    count: AtomicUsize,
+}
+
+impl AccessAdvices {
+    fn shard(&self, ino: Inode) -> &RwLock<HashMap<Inode, RafsAccessAdvice>> {
+        &self.shards[ino as usize % RAFS_ADVICE_SHARDS]
+    }
+
+    fn get(&self, ino: Inode) -> RafsAccessAdvice {
+        if self.count.load(Ordering::Relaxed) == 0 {
+            return RafsAccessAdvice::Normal;
+        }
+        self.shard(ino)
+            .read()
+            .unwrap()
+            .get(&ino)
+            .copied()
+            .unwrap_or(RafsAccessAdvice::Normal)
+    }
+
+    fn set(&self, ino: Inode, advice: RafsAccessAdvice) {
+        let mut shard = self.shard(ino).write().unwrap();
+        if advice == RafsAccessAdvice::Normal {
+            if shard.remove(&ino).is_some() {
+                self.count.fetch_sub(1, Ordering::Relaxed);
+            }
+        } else if shard.insert(ino, advice).is_none() {
+            self.count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Data chunk failing verification by [Rafs::verify_all()].
+#[derive(Clone, Debug)]
+pub struct BadChunk {
+    /// Path of a file containing the chunk.
+    pub path: PathBuf,
+    /// Offset of the chunk in the file.
+    pub file_offset: u64,
+    /// Id of the data blob containing the chunk.
+    pub blob_id: String,
+    /// Offset of compressed chunk data in the data blob.
+    pub compressed_offset: u64,
+    /// Reason of the failure.
+    pub error: String,
+}
+
+/// Result of verifying all data chunks of a RAFS filesystem.
+#[derive(Clone, Debug, Default)]
+pub struct VerifyReport {
+    /// Number of data chunks checked, shared chunks are counted once.
+    pub total_chunks: u64,
+    /// Data chunks failing verification.
+    pub bad_chunks: Vec<BadChunk>,
+    /// Number of regular files with data inlined into the metadata, checked against inode digests.
+    pub inline_files: u64,
+    /// Regular files whose inline data doesn't match the inode digest.
+    pub bad_inline_files: Vec<PathBuf>,
+}
+
+impl VerifyReport {
+    /// Get number of data chunks passing verification.
+    pub fn verified_chunks(&self) -> u64 {
+        self.total_chunks - self.bad_chunks.len() as u64
+    }
+
+    /// Get percentage of data chunks passing verification.
+    pub fn score(&self) -> f64 {
+        if self.total_chunks == 0 {
+            100.0
+        } else {
+            self.verified_chunks() as f64 * 100.0 / self.total_chunks as f64
+        }
+    }
+}
+
+/// Result of warming up data chunks by [Rafs::warm()].
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct WarmReport {
+    /// Whether the warm-up is still in progress.
+    pub running: bool,
+    /// Number of data chunks ready in the blob cache.
+    pub succeeded: u64,
+    /// Number of data chunks failed to fetch, including listed chunks not found in the filesystem
+    /// and listed inodes which are not regular files.
+    pub failed: u64,
+}
 
 /// Struct to glue fuse, storage backend and filesystem metadata together.
 ///
@@ -61,14 +191,40 @@ pub struct Rafs {
     id: String,
     device: BlobDevice,
     ios: Arc<metrics::FsIoStats>,
-    sb: Arc<RafsSuper>,
+    sb: ArcSwap<RafsSuper>,
+    root_ino: Inode,
 
     initialized: bool,
     digest_validate: bool,
     fs_prefetch: bool,
     prefetch_all: bool,
+    // File to save paths of accessed files, to be prefetched by following mounts of the image.
+    prefetch_history: Option<PathBuf>,
     xattr_enabled: bool,
     user_io_batch_size: u32,
+    amplification_warn_ratio: u32,
+    // Sorted inode numbers of regular files presented under the root directory in flat view mode.
+    flat_inodes: Option<Vec<Inode>>,
+    dir_prefetch: bool,
+    // Directories which have already been handled by the directory prefetch heuristic.
+    prefetched_dirs: Mutex<HashSet<Inode>>,
+    disable_writeback_cache: bool,
+    disable_zero_message_open: bool,
+    // Timeout for the kernel to cache failed lookups.
+    negative_timeout: Duration,
+    // Directories to be announced as submounts, once negotiated with the kernel.
+    submount_inodes: HashSet<Inode>,
+    announce_submounts: AtomicBool,
+
+    // Offsets to shift uid/gid recorded in the image.
+    uid_offset: u32,
+    gid_offset: u32,
+    // Access advices of files, files without advices are in `RafsAccessAdvice::Normal` mode.
+    access_advices: AccessAdvices,
+    // Progress of the running warm-up, or result of the last one.
+    warm_report: Mutex<WarmReport>,
+    // Public key to verify signatures of metadata files, empty if not required.
+    trusted_key: String,
 
     // static inode attributes
     i_uid: u32,
@@ -82,12 +238,32 @@ impl Rafs {
         // Assume all meta/data blobs are accessible, otherwise it will always cause IO errors.
         cfg.internal.set_blob_accessible(true);
 
+        let start = Instant::now();
         let cache_cfg = cfg.get_cache_config().map_err(RafsError::LoadConfig)?;
         let rafs_cfg = cfg.get_rafs_config().map_err(RafsError::LoadConfig)?;
-        let (sb, reader) = RafsSuper::load_from_file(path, cfg.clone(), false)
+        // Metadata is accessed in direct mode unless the mode is selected automatically.
+        let mode = if rafs_cfg.mode == "auto" {
+            RafsMode::Auto
+        } else {
+            RafsMode::Direct
+        };
+        let (sb, reader) = RafsSuper::load_from_file_with_mode(path, cfg.clone(), false, mode)
             .map_err(RafsError::FillSuperBlock)?;
+        let metadata_load_latency = start.elapsed();
+        let root_ino = Self::subdir_ino(&sb, &rafs_cfg.subdir)?;
+        let flat_inodes = if rafs_cfg.flat_view {
+            Some(Self::collect_flat_inodes(&sb, root_ino)?)
+        } else {
+            None
+        };
+        let submount_inodes = Self::submount_inodes(&sb, &rafs_cfg.submounts)?;
         let blob_infos = sb.superblock.get_blob_infos();
         let device = BlobDevice::new(cfg, &blob_infos).map_err(RafsError::CreateDevice)?;
+        let prefetch_history = if rafs_cfg.prefetch.enable {
+            Self::prefetch_history_path(&rafs_cfg.prefetch.history_dir, &blob_infos)
+        } else {
+            None
+        };
 
         if cfg.is_chunk_validation_enabled() && sb.meta.has_inlined_chunk_digest() {
             sb.superblock.set_blob_device(device.clone());
@@ -97,14 +273,30 @@ impl Rafs {
             id: id.to_string(),
             device,
             ios: metrics::FsIoStats::new(id),
-            sb: Arc::new(sb),
+            sb: ArcSwap::from_pointee(sb),
+            root_ino,
 
             initialized: false,
             digest_validate: rafs_cfg.validate,
             fs_prefetch: rafs_cfg.prefetch.enable,
             user_io_batch_size: rafs_cfg.user_io_batch_size as u32,
             prefetch_all: rafs_cfg.prefetch.prefetch_all,
+            prefetch_history,
             xattr_enabled: rafs_cfg.enable_xattr,
+            amplification_warn_ratio: rafs_cfg.amplification_warn_ratio,
+            flat_inodes,
+            dir_prefetch: rafs_cfg.dir_prefetch,
+            prefetched_dirs: Mutex::new(HashSet::new()),
+            disable_writeback_cache: rafs_cfg.disable_writeback_cache,
+            disable_zero_message_open: rafs_cfg.disable_zero_message_open,
+            negative_timeout: Duration::from_secs(rafs_cfg.negative_entry_timeout),
+            submount_inodes,
+            announce_submounts: AtomicBool::new(false),
+            uid_offset: rafs_cfg.idmap.uid_offset,
+            gid_offset: rafs_cfg.idmap.gid_offset,
+            access_advices: AccessAdvices::default(),
+            warm_report: Mutex::new(WarmReport::default()),
+            trusted_key: rafs_cfg.trusted_key.clone(),
 
             i_uid: geteuid().into(),
             i_gid: getegid().into(),
@@ -130,9 +322,13 @@ impl Rafs {
         }
 
         rafs.ios.toggle_files_recording(rafs_cfg.iostats_files);
-        rafs.ios.toggle_access_pattern(rafs_cfg.access_pattern);
+        // Access history is built from access patterns of files.
+        rafs.ios
+            .toggle_access_pattern(rafs_cfg.access_pattern || rafs.prefetch_history.is_some());
         rafs.ios
             .toggle_latest_read_files_recording(rafs_cfg.latest_read_files);
+        rafs.ios
+            .mount_latency_update(&metadata_load_latency, &start.elapsed());
 
         Ok((rafs, reader))
     }
@@ -148,14 +344,14 @@ impl Rafs {
         // TODO: seems no need to do self.sb.update()
         // step 1: update sb.
         // No lock is needed thanks to ArcSwap.
-        self.sb.update(r).map_err(|e| {
+        self.sb.load().update(r).map_err(|e| {
             error!("update failed due to {:?}", e);
             e
         })?;
         info!("update sb is successful");
 
         // step 2: update device (only localfs is supported)
-        let blob_infos = self.sb.superblock.get_blob_infos();
+        let blob_infos = self.sb.load().superblock.get_blob_infos();
         self.device
             .update(conf, &blob_infos, self.fs_prefetch)
             .map_err(RafsError::SwapBackend)?;
@@ -164,6 +360,48 @@ impl Rafs {
         Ok(())
     }
 
+    /// Switch to metadata from a new bootstrap file without remounting the filesystem.
+    ///
+    /// The metadata is swapped atomically, so each filesystem request sees either the old or the
+    /// new metadata. See [RafsSuper::reload] for requirements on the new bootstrap. Reloading is
+    /// not supported with `subdir`, `flat_view` or `submounts` enabled, because they cache inode
+    /// numbers of the old metadata.
+    ///
+    /// Inode generation numbers change with the metadata, but entries and attributes cached by
+    /// the kernel are not invalidated, they expire after `attr_timeout` and `entry_timeout`.
+    pub fn reload(&self, bootstrap: &Path) -> RafsResult<()> {
+        if !self.initialized {
+            warn!("Rafs is not yet initialized");
+            return Err(RafsError::Uninitialized);
+        }
+        let sb = self.sb.load();
+        if self.root_ino != sb.superblock.root_ino()
+            || self.flat_inodes.is_some()
+            || !self.submount_inodes.is_empty()
+        {
+            return Err(RafsError::Unsupported);
+        }
+
+        let file = sb
+            .open_metadata_file(bootstrap, &self.trusted_key)
+            .map_err(RafsError::FillSuperBlock)?;
+        let mut reader = Box::new(file) as RafsIoReader;
+        let new_sb = sb.reload(&mut reader).map_err(|e| {
+            error!(
+                "failed to reload metadata from {}, {}",
+                bootstrap.display(),
+                e
+            );
+            e
+        })?;
+        self.sb.store(Arc::new(new_sb));
+        // Inode numbers may refer to different directories now.
+        self.prefetched_dirs.lock().unwrap().clear();
+        info!("reload metadata from {}", bootstrap.display());
+
+        Ok(())
+    }
+
     /// Import an rafs bootstrap to initialize the filesystem instance.
     pub fn import(
         &mut self,
@@ -173,10 +411,13 @@ impl Rafs {
         if self.initialized {
             return Err(RafsError::AlreadyMounted);
         }
-        if self.fs_prefetch {
+        if self.fs_prefetch || self.dir_prefetch {
             // Device should be ready before any prefetch.
             self.device.start_prefetch();
-            self.prefetch(r, prefetch_files);
+        }
+        if self.fs_prefetch {
+            let history = self.load_prefetch_history();
+            self.prefetch(r, prefetch_files, history);
         }
         self.initialized = true;
 
@@ -187,11 +428,15 @@ impl Rafs {
     pub fn destroy(&mut self) -> Result<()> {
         info! {"Destroy rafs"}
 
+        if let Err(e) = self.save_prefetch_history() {
+            warn!("failed to save prefetch history, {}", e);
+        }
         if self.initialized {
-            Arc::get_mut(&mut self.sb)
+            let mut sb = self.sb.swap(Arc::new(RafsSuper::default()));
+            Arc::get_mut(&mut sb)
                 .expect("Superblock is no longer used")
                 .destroy();
-            if self.fs_prefetch {
+            if self.fs_prefetch || self.dir_prefetch {
                 self.device.stop_prefetch();
             }
             self.device.close()?;
@@ -207,12 +452,119 @@ impl Rafs {
     }
 
     /// Get the cached file system super block metadata.
-    pub fn metadata(&self) -> &RafsSuperMeta {
-        &self.sb.meta
+    pub fn metadata(&self) -> RafsSuperMeta {
+        self.sb.load().meta
+    }
+
+    /// Get information about data blobs referenced by the filesystem.
+    ///
+    /// Each `BlobInfo` carries the compressor, compressed size and uncompressed size of the blob
+    /// recorded in the blob table at build time.
+    pub fn blobs(&self) -> Vec<Arc<BlobInfo>> {
+        self.sb.load().superblock.get_blob_infos()
+    }
+
+    /// Check whether all data blobs are reachable from the storage backend.
+    pub fn check_backend(&self) -> Result<()> {
+        self.device.check_backend()
+    }
+
+    /// Flush cached data and chunk state of all data blobs to the underlying storage.
+    pub fn flush(&self) -> Result<()> {
+        self.device.flush()
+    }
+
+    /// Advise the access pattern of a file, to tune the read amplification window of the file.
+    ///
+    /// The FUSE protocol doesn't forward `posix_fadvise()` to filesystems, so advices are passed
+    /// down through the nydusd API. Advices are dropped once the kernel forgets the inode.
+    pub fn fadvise(&self, ino: Inode, advice: RafsAccessAdvice) {
+        self.access_advices.set(ino, advice);
+    }
+
+    /// Get the access pattern advised for a file.
+    pub fn access_advice(&self, ino: Inode) -> RafsAccessAdvice {
+        self.access_advices.get(ino)
+    }
+
+    /// Read data of a regular file into a list of buffers, such as scattered guest memory.
+    ///
+    /// Data from each blob is fetched with one coalesced request and scattered into `bufs` by
+    /// the cache layer directly, instead of being copied chunk by chunk through a
+    /// [ZeroCopyWriter]. Reads are not amplified because the buffers are provided by the caller.
+    pub fn readv(&self, ino: Inode, offset: u64, bufs: &[FileVolatileSlice]) -> Result<usize> {
+        let size = bufs.iter().map(|v| v.len() as u64).sum::<u64>();
+        if offset.checked_add(size).is_none() {
+            return Err(einval!("offset + size wraps around."));
+        }
+
+        let inode = self.sb.load().get_inode(ino, false)?;
+        let inode_size = inode.size();
+        let mut recorder = FopRecorder::settle(Read, ino, &self.ios);
+        if size == 0 || offset >= inode_size {
+            recorder.mark_success(0);
+            return Ok(0);
+        }
+
+        let real_size = cmp::min(size, inode_size - offset) as usize;
+        if let Some(data) = inode.get_inline_data() {
+            let start = offset as usize;
+            let end = start + real_size;
+            if end > data.len() {
+                return Err(einval!("inline data is truncated"));
+            }
+            let (r, _) = copyv(&[&data[start..end]], bufs, 0, real_size, 0, 0)
+                .map_err(|e| eio!(format!("failed to copy inline data, {:?}", e)))?;
+            recorder.mark_success(r);
+            return Ok(r);
+        }
+
+        let mut io_vecs = inode.alloc_bio_vecs(&self.device, offset, real_size, true)?;
+        let start = self.ios.latency_start();
+        let mut result = 0;
+        let (mut index, mut pos) = (0, 0);
+        for io_vec in io_vecs.iter_mut() {
+            // Continue from where the previous blob stopped.
+            let slices = bufs[index..]
+                .iter()
+                .enumerate()
+                .map(|(idx, v)| {
+                    let skip = if idx == 0 { pos } else { 0 };
+                    // Safe because the range is within the slice provided by the caller.
+                    unsafe { FileVolatileSlice::from_raw_ptr(v.as_ptr().add(skip), v.len() - skip) }
+                })
+                .collect::<Vec<_>>();
+            let r = self.device.read_to_slices(io_vec, &slices)?;
+            result += r;
+            if r as u64 != io_vec.size() {
+                break;
+            }
+
+            pos += r;
+            while index < bufs.len() && pos >= bufs[index].len() {
+                pos -= bufs[index].len();
+                index += 1;
+            }
+        }
+        self.ios.latency_end(&start, Read);
+        recorder.mark_success(result);
+
+        Ok(result)
+    }
+
+    // Get size of the read amplification window for the file, according to its access advice.
+    fn user_io_batch_size(&self, ino: Inode) -> u32 {
+        match self.access_advices.get(ino) {
+            RafsAccessAdvice::Sequential => self
+                .user_io_batch_size
+                .saturating_mul(RAFS_ADVICE_IO_BATCH_FACTOR),
+            RafsAccessAdvice::Random => self.user_io_batch_size / RAFS_ADVICE_IO_BATCH_FACTOR,
+            RafsAccessAdvice::Normal => self.user_io_batch_size,
+        }
     }
 
     fn xattr_supported(&self) -> bool {
-        self.xattr_enabled || self.sb.meta.has_xattr()
+        self.xattr_enabled || self.sb.load().meta.has_xattr()
     }
 
     fn do_readdir(
@@ -226,10 +578,18 @@ impl Rafs {
             return Ok(());
         }
 
-        let parent = self.sb.get_inode(ino, self.digest_validate)?;
+        let parent = self.sb.load().get_inode(ino, self.digest_validate)?;
         if !parent.is_dir() {
             return Err(enotdir!());
         }
+        if let Some(inodes) = self.flat_inodes.as_ref() {
+            if ino == self.root_ino() {
+                return self.do_flat_readdir(ino, inodes, offset, add_entry);
+            }
+        }
+        if offset == 0 {
+            self.prefetch_dir(parent.as_ref());
+        }
 
         let mut handler = |_inode, name: OsString, ino, offset| {
             match add_entry(DirEntry {
@@ -255,6 +615,87 @@ impl Rafs {
         Ok(())
     }
 
+    // Directories are often listed right after being looked up, and small files in them are likely
+    // to be accessed soon, so issue background prefetch requests for them.
+    fn prefetch_dir(&self, dir: &dyn RafsInode) {
+        let count = dir.get_child_count();
+        if !self.dir_prefetch
+            || count == 0
+            || count > RAFS_DIR_PREFETCH_MAX_CHILDREN
+            || !self.prefetched_dirs.lock().unwrap().insert(dir.ino())
+        {
+            return;
+        }
+
+        let mut state = BlobIoMerge::default();
+        for idx in 0..count {
+            let child = match dir.get_child_by_index(idx) {
+                Ok(child) => child,
+                Err(e) => {
+                    warn!(
+                        "dir prefetch: failed to get child of inode {}, {}",
+                        dir.ino(),
+                        e
+                    );
+                    return;
+                }
+            };
+            if !child.is_reg()
+                || child.is_empty_size()
+                || child.size() > RAFS_DIR_PREFETCH_MAX_FILE_SIZE
+                || child.get_inline_data().is_some()
+            {
+                continue;
+            }
+            match child.alloc_bio_vecs(&self.device, 0, child.size() as usize, false) {
+                Ok(descs) => descs.into_iter().for_each(|desc| state.append(desc)),
+                Err(e) => warn!(
+                    "dir prefetch: failed to prefetch inode {}, {}",
+                    child.ino(),
+                    e
+                ),
+            }
+        }
+
+        for (_id, desc) in state.drain() {
+            trace!(
+                "dir prefetch: 0x{:x} bytes for {} descriptors",
+                desc.size(),
+                desc.len()
+            );
+            self.device.prefetch(&[&desc], &[]).unwrap_or_else(|e| {
+                warn!("Prefetch error, {:?}", e);
+            });
+        }
+    }
+
+    // List all regular files in the flat view, named by their inode numbers.
+    fn do_flat_readdir(
+        &self,
+        ino: Inode,
+        inodes: &[Inode],
+        offset: u64,
+        add_entry: &mut dyn FnMut(DirEntry) -> Result<usize>,
+    ) -> Result<()> {
+        let entries = [(DOT.to_string(), ino), (DOTDOT.to_string(), ino)]
+            .into_iter()
+            .chain(inodes.iter().map(|i| (i.to_string(), *i)));
+        for (idx, (name, ino)) in entries.enumerate().skip(offset as usize) {
+            let r = add_entry(DirEntry {
+                ino,
+                offset: idx as u64 + 1,
+                type_: 0,
+                name: name.as_bytes(),
+            })?;
+            self.ios.new_file_counter(ino);
+            if r == 0 {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
     fn negative_entry(&self) -> Entry {
         Entry {
             attr: Attr {
@@ -264,19 +705,22 @@ impl Rafs {
             inode: 0,
             generation: 0,
             attr_flags: 0,
-            attr_timeout: self.sb.meta.attr_timeout,
-            entry_timeout: self.sb.meta.entry_timeout,
+            attr_timeout: self.sb.load().meta.attr_timeout,
+            entry_timeout: self.negative_timeout,
         }
     }
 
     fn get_inode_attr(&self, ino: u64) -> Result<Attr> {
-        let inode = self.sb.get_inode(ino, false)?;
+        let inode = self.sb.load().get_inode(ino, false)?;
         let mut attr = inode.get_attr();
 
         // override uid/gid if there is no explicit inode uid/gid
-        if !self.sb.meta.explicit_uidgid() {
+        if !self.sb.load().meta.explicit_uidgid() {
             attr.uid = self.i_uid;
             attr.gid = self.i_gid;
+        } else {
+            attr.uid = attr.uid.saturating_add(self.uid_offset);
+            attr.gid = attr.gid.saturating_add(self.gid_offset);
         }
 
         // Older rafs image or the root inode doesn't include mtime, in such cases
@@ -303,9 +747,12 @@ impl Rafs {
         let mut entry = inode.get_entry();
 
         // override uid/gid if there is no explicit inode uid/gid
-        if !self.sb.meta.explicit_uidgid() {
+        if !self.sb.load().meta.explicit_uidgid() {
             entry.attr.st_uid = self.i_uid;
             entry.attr.st_gid = self.i_gid;
+        } else {
+            entry.attr.st_uid = entry.attr.st_uid.saturating_add(self.uid_offset);
+            entry.attr.st_gid = entry.attr.st_gid.saturating_add(self.gid_offset);
         }
 
         // Older rafs image doesn't include mtime, in such case we use runtime timestamp.
@@ -319,24 +766,518 @@ impl Rafs {
         // since nydusify gives root directory permission of 0o750 and fuse mount
         // options `rootmode=` does not affect root directory's permission bits, ending
         // up with preventing other users from accessing the container rootfs.
-        if entry.inode == ROOT_ID {
+        if entry.inode == ROOT_ID || entry.inode == self.root_ino() {
             entry.attr.st_mode = entry.attr.st_mode & !0o777 | 0o755;
         }
 
+        // Let the kernel create a separate mount, with a distinct device number, for submounts.
+        #[cfg(target_os = "linux")]
+        {
+            if self.announce_submounts.load(Ordering::Relaxed)
+                && self.submount_inodes.contains(&entry.inode)
+            {
+                entry.attr_flags |= fuse_backend_rs::abi::fuse_abi::ATTR_SUBMOUNT;
+            }
+        }
+
         entry
     }
 }
 
 impl Rafs {
-    fn prefetch(&self, reader: RafsIoReader, prefetch_files: Option<Vec<PathBuf>>) {
-        let sb = self.sb.clone();
+    fn prefetch(
+        &self,
+        reader: RafsIoReader,
+        prefetch_files: Option<Vec<PathBuf>>,
+        history: Vec<PathBuf>,
+    ) {
+        let sb = self.sb.load_full();
         let device = self.device.clone();
         let prefetch_all = self.prefetch_all;
-        let root_ino = self.root_ino();
+        // Prefetch file list is always relative to the image root, even when mounting a subdir.
+        let root_ino = self.sb.load().superblock.root_ino();
 
         let _ = std::thread::spawn(move || {
-            Self::do_prefetch(root_ino, reader, prefetch_files, prefetch_all, sb, device);
+            Self::do_prefetch(
+                root_ino,
+                reader,
+                prefetch_files,
+                history,
+                prefetch_all,
+                sb,
+                device,
+            );
+        });
+    }
+
+    /// Save paths of files read so far, in the order of first access, to the prefetch history.
+    ///
+    /// Files in the history will be prefetched when the same image is mounted next time. It's a
+    /// no-op if `prefetch.history_dir` is not configured or no file has been read.
+    pub fn save_prefetch_history(&self) -> Result<()> {
+        let path = match self.prefetch_history.as_ref() {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        let mut content = String::new();
+        for ino in self.ios.accessed_files() {
+            // Files may have gone away by live metadata update.
+            let file = match self.sb.load().path_from_ino(ino) {
+                Ok(v) => v.components().collect::<PathBuf>(),
+                Err(_) => continue,
+            };
+            match file.to_str() {
+                Some(v) if file.is_absolute() && !v.contains('\n') => {
+                    content.push_str(v);
+                    content.push('\n');
+                }
+                _ => warn!("skip saving {} to prefetch history", file.display()),
+            }
+        }
+        if content.is_empty() {
+            return Ok(());
+        }
+
+        // Write to a temporary file first, so readers never see a partial history. The name of
+        // the temporary file is unique, so concurrent unmounts of the same image don't clobber
+        // each other.
+        let tmp = path.with_extension(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            RAFS_HISTORY_TMP_SEQ.fetch_add(1, Ordering::Relaxed)
+        ));
+        if let Err(e) = fs::write(&tmp, content).and_then(|_| fs::rename(&tmp, path)) {
+            let _ = fs::remove_file(&tmp);
+            return Err(e);
+        }
+        info!("save prefetch history to {}", path.display());
+
+        Ok(())
+    }
+
+    // Load paths of files accessed by previous mounts of the image.
+    fn load_prefetch_history(&self) -> Vec<PathBuf> {
+        let path = match self.prefetch_history.as_ref() {
+            Some(v) => v,
+            None => return Vec::new(),
+        };
+
+        match fs::read_to_string(path) {
+            Ok(content) => content
+                .lines()
+                .filter(|l| !l.is_empty())
+                .map(PathBuf::from)
+                .collect(),
+            Err(e) => {
+                if e.kind() != ErrorKind::NotFound {
+                    warn!(
+                        "failed to load prefetch history from {}, {}",
+                        path.display(),
+                        e
+                    );
+                }
+                Vec::new()
+            }
+        }
+    }
+
+    // Get path of the prefetch history file for the image, which is identified by its data blobs.
+    fn prefetch_history_path(dir: &str, blob_infos: &[Arc<BlobInfo>]) -> Option<PathBuf> {
+        if dir.is_empty() || blob_infos.is_empty() {
+            return None;
+        }
+
+        let ids = blob_infos
+            .iter()
+            .map(|b| b.blob_id())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let id = RafsDigest::from_buf(ids.as_bytes(), digest::Algorithm::Sha256);
+        Some(Path::new(dir).join(format!("{}.prefetch_history", id)))
+    }
+
+    /// Read content of a regular file and write it into the writer.
+    ///
+    /// Data is streamed chunk by chunk, so memory usage is bounded by the chunk size instead of
+    /// the file size.
+    ///
+    /// In flat view mode, files are addressed by their names in the flat root directory.
+    pub fn read_file_to(&self, path: &Path, w: &mut dyn Write) -> Result<u64> {
+        let ino = self.ino_from_path(path)?;
+        let inode = self.sb.load().get_inode(ino, self.digest_validate)?;
+        if !inode.is_reg() {
+            return Err(einval!(format!("{} is not a regular file", path.display())));
+        }
+        if let Some(data) = inode.get_inline_data() {
+            w.write_all(data)?;
+            return Ok(data.len() as u64);
+        }
+
+        let file_size = inode.size();
+        let chunk_size = self.metadata().chunk_size as u64;
+        let mut buf = vec![0u8; cmp::min(chunk_size, file_size) as usize];
+        let mut offset = 0u64;
+        while offset < file_size {
+            // Align reads to chunk boundary, so each round touches one chunk only.
+            let size = cmp::min(chunk_size - offset % chunk_size, file_size - offset) as usize;
+            let mut io_vecs = inode.alloc_bio_vecs(&self.device, offset, size, true)?;
+            let mut pos = 0;
+            for io_vec in io_vecs.iter_mut() {
+                let len = io_vec.size() as usize;
+                if pos + len > size {
+                    return Err(eio!("unexpected size of BlobIoVec"));
+                }
+                let r = self.device.read_to_buf(io_vec, &mut buf[pos..pos + len])?;
+                if r != len {
+                    return Err(eio!(format!(
+                        "short read from blob device, expect {}, got {}",
+                        len, r
+                    )));
+                }
+                pos += r;
+            }
+            if pos != size {
+                return Err(eio!(format!(
+                    "short read of {} at offset {}, expect {}, got {}",
+                    path.display(),
+                    offset,
+                    size,
+                    pos
+                )));
+            }
+            w.write_all(&buf[..pos])?;
+            offset += pos as u64;
+        }
+
+        Ok(offset)
+    }
+
+    /// Resolve an absolute path to its inode number, without fetching attributes of the file.
+    ///
+    /// Path components are looked up one by one from the image root, and `ENOENT` is returned
+    /// if any of them doesn't exist. In flat view mode, files are addressed by their names in
+    /// the flat root directory.
+    pub fn path_to_ino(&self, path: &str) -> Result<Inode> {
+        self.ino_from_path(Path::new(path))
+    }
+
+    /// Get location of data chunks of a regular file in data blobs, without reading file data.
+    ///
+    /// It helps external prefetch controllers to fetch file data on their own.
+    pub fn chunk_layout(&self, path: &Path) -> Result<Vec<ChunkLayout>> {
+        let ino = self.ino_from_path(path)?;
+        self.sb.load().get_chunk_layout(ino)
+    }
+
+    /// Fetch data chunks into the blob cache by `concurrency` threads, before they are read.
+    ///
+    /// All data chunks of regular files `inodes` are fetched, together with data chunks listed
+    /// in `chunks`, which are identified by blob id and compressed offset as returned by
+    /// [Rafs::chunk_layout()]. Each data chunk is fetched once even if listed multiple times.
+    /// Listed inodes which are not regular files and listed chunks which can't be found are
+    /// counted as failed.
+    ///
+    /// Only one warm-up runs at a time, and its progress is available from
+    /// [Rafs::warm_report()] while running, so the caller may issue it in background.
+    pub fn warm(
+        &self,
+        inodes: &[Inode],
+        chunks: &[ChunkLayout],
+        concurrency: usize,
+    ) -> Result<WarmReport> {
+        {
+            let mut report = self.warm_report.lock().unwrap();
+            if report.running {
+                return Err(std::io::Error::from_raw_os_error(libc::EBUSY));
+            }
+            *report = WarmReport {
+                running: true,
+                ..Default::default()
+            };
+        }
+
+        let descs = self.collect_warm_chunks(inodes, chunks);
+        let next = AtomicUsize::new(0);
+        std::thread::scope(|s| {
+            for _ in 0..cmp::max(concurrency, 1) {
+                s.spawn(|| {
+                    let mut buf = Vec::new();
+                    while let Some(desc) = descs.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let size = desc.size as usize;
+                        buf.resize(size, 0);
+                        let mut io_vec = BlobIoVec::new(desc.blob.clone());
+                        io_vec.push(desc.clone());
+                        let result = self.device.read_to_buf(&mut io_vec, &mut buf);
+                        let mut report = self.warm_report.lock().unwrap();
+                        match result {
+                            Ok(r) if r == size => report.succeeded += 1,
+                            Ok(r) => {
+                                warn!("failed to warm up chunk, short read {} bytes", r);
+                                report.failed += 1;
+                            }
+                            Err(e) => {
+                                warn!("failed to warm up chunk, {}", e);
+                                report.failed += 1;
+                            }
+                        }
+                    }
+                });
+            }
         });
+
+        let mut report = self.warm_report.lock().unwrap();
+        report.running = false;
+        Ok(report.clone())
+    }
+
+    /// Get progress of the running warm-up, or result of the last one, see [Rafs::warm()].
+    pub fn warm_report(&self) -> WarmReport {
+        self.warm_report.lock().unwrap().clone()
+    }
+
+    // Collect whole data chunks to be warmed up, failures are recorded in the warm-up report.
+    fn collect_warm_chunks(&self, inodes: &[Inode], chunks: &[ChunkLayout]) -> Vec<BlobIoDesc> {
+        let sb = self.sb.load();
+        let blob_infos = sb.superblock.get_blob_infos();
+        let mut descs = Vec::new();
+        let mut failed = 0u64;
+        // Chunks in the same batch share the compressed offset, so identify chunks by index.
+        let mut fetched = HashSet::new();
+        let mut push = |blob: &Arc<BlobInfo>, chunk: BlobIoChunk| {
+            if fetched.insert((blob.blob_index(), chunk.id())) {
+                // Warm-up reads are issued as user IO, otherwise they may be dropped by the cache.
+                let size = chunk.uncompressed_size();
+                descs.push(BlobIoDesc::new(blob.clone(), chunk, 0, size, true));
+            }
+        };
+
+        for ino in inodes {
+            match self.file_chunks(&sb, *ino) {
+                Ok(chunks) => chunks.into_iter().for_each(|(b, c)| push(&b, c)),
+                Err(e) => {
+                    warn!("failed to warm up inode {}, {}", ino, e);
+                    failed += 1;
+                }
+            }
+        }
+
+        // Listed chunks are resolved by the blob meta, which is missing for RAFS v5 blobs.
+        let mut unresolved = HashSet::new();
+        for c in chunks {
+            let blob = match blob_infos.iter().find(|b| b.blob_id() == c.blob_id) {
+                Some(v) => v,
+                None => {
+                    warn!("failed to warm up chunk, unknown blob {}", c.blob_id);
+                    failed += 1;
+                    continue;
+                }
+            };
+            match self
+                .device
+                .get_chunks_by_compressed_offset(blob.blob_index(), c.compressed_offset)
+            {
+                Ok(Some(v)) if !v.is_empty() => {
+                    v.into_iter().for_each(|chunk| push(blob, chunk.into()))
+                }
+                Ok(None) => {
+                    unresolved.insert((c.blob_id.clone(), c.compressed_offset));
+                }
+                Ok(Some(_)) | Err(_) => {
+                    warn!(
+                        "failed to warm up chunk, no chunk at offset 0x{:x} of blob {}",
+                        c.compressed_offset, c.blob_id
+                    );
+                    failed += 1;
+                }
+            }
+        }
+        if !unresolved.is_empty() {
+            let root_ino = sb.superblock.root_ino();
+            let result = sb.walk_directory::<PathBuf>(root_ino, None, &mut |inode, _| {
+                if inode.is_reg() && !unresolved.is_empty() {
+                    for (b, c) in self.file_chunks(&sb, inode.ino())? {
+                        if unresolved.remove(&(b.blob_id(), c.compressed_offset())) {
+                            push(&b, c);
+                        }
+                    }
+                }
+                Ok(())
+            });
+            if let Err(e) = result {
+                warn!("failed to walk filesystem to warm up chunks, {}", e);
+            }
+            failed += unresolved.len() as u64;
+        }
+
+        self.warm_report.lock().unwrap().failed += failed;
+        descs
+    }
+
+    // Get data chunks of the regular file `ino`, together with blobs containing them.
+    fn file_chunks(&self, sb: &RafsSuper, ino: Inode) -> Result<Vec<(Arc<BlobInfo>, BlobIoChunk)>> {
+        let inode = sb.get_inode(ino, self.digest_validate)?;
+        if !inode.is_reg() {
+            return Err(einval!(format!("inode {} is not a regular file", ino)));
+        }
+        if inode.size() == 0 || inode.get_inline_data().is_some() {
+            return Ok(Vec::new());
+        }
+
+        let mut chunks = Vec::new();
+        let io_vecs = inode.alloc_bio_vecs(&self.device, 0, inode.size() as usize, false)?;
+        for io_vec in io_vecs.iter() {
+            for idx in 0..io_vec.len() {
+                let desc = io_vec.blob_io_desc(idx).unwrap();
+                chunks.push((desc.blob.clone(), desc.chunkinfo.clone()));
+            }
+        }
+
+        Ok(chunks)
+    }
+
+    /// Read all data chunks of the filesystem and validate them against their digests.
+    ///
+    /// Regular files are verified by `concurrency` threads, and data chunks shared by multiple
+    /// files are verified only once. Failures to read or validate data chunks are reported in
+    /// the returned [VerifyReport] instead of aborting the verification.
+    pub fn verify_all(&self, concurrency: usize) -> Result<VerifyReport> {
+        let mut files = Vec::new();
+        let mut inodes = HashSet::new();
+        let sb = self.sb.load();
+        sb.walk_directory::<PathBuf>(sb.superblock.root_ino(), None, &mut |inode, path| {
+            // Hardlinks are verified only once.
+            if inode.is_reg() && inodes.insert(inode.ino()) {
+                files.push((inode.ino(), path.to_path_buf()));
+            }
+            Ok(())
+        })
+        .map_err(|e| eio!(format!("failed to walk filesystem, {}", e)))?;
+
+        let next = AtomicUsize::new(0);
+        let chunks = Mutex::new(HashSet::new());
+        let report = Mutex::new(VerifyReport::default());
+        std::thread::scope(|s| {
+            let handles = (0..cmp::max(concurrency, 1))
+                .map(|_| {
+                    s.spawn(|| -> Result<()> {
+                        while let Some((ino, path)) =
+                            files.get(next.fetch_add(1, Ordering::Relaxed))
+                        {
+                            self.verify_file(*ino, path, &chunks, &report)?;
+                        }
+                        Ok(())
+                    })
+                })
+                .collect::<Vec<_>>();
+            for handle in handles {
+                handle
+                    .join()
+                    .map_err(|_| eio!("verification thread panicked"))??;
+            }
+            Ok::<(), std::io::Error>(())
+        })?;
+
+        Ok(report.into_inner().unwrap())
+    }
+
+    fn verify_file(
+        &self,
+        ino: Inode,
+        path: &Path,
+        chunks: &Mutex<HashSet<(u32, u32)>>,
+        report: &Mutex<VerifyReport>,
+    ) -> Result<()> {
+        let sb = self.sb.load();
+        let inode = sb.get_extended_inode(ino, self.digest_validate)?;
+        // Inline data of RAFS v5 files is digested as a whole into the inode digest.
+        if let Some(data) = inode.get_inline_data() {
+            let mut report = report.lock().unwrap();
+            report.inline_files += 1;
+            if !check_digest(data, &inode.get_digest(), sb.meta.get_digester()) {
+                report.bad_inline_files.push(path.to_path_buf());
+            }
+            return Ok(());
+        }
+
+        let file_size = inode.size();
+        let chunk_size = self.metadata().chunk_size as u64;
+        let mut buf = vec![0u8; cmp::min(chunk_size, file_size) as usize];
+        let mut offset = 0u64;
+        while offset < file_size {
+            // Align reads to chunk boundary, so each round touches one chunk only.
+            let size = cmp::min(chunk_size - offset % chunk_size, file_size - offset) as usize;
+            let mut io_vecs = inode.alloc_bio_vecs(&self.device, offset, size, false)?;
+            let mut file_offset = offset;
+            for io_vec in io_vecs.iter_mut() {
+                let len = io_vec.size() as usize;
+                if len > buf.len() {
+                    return Err(eio!("unexpected size of BlobIoVec"));
+                }
+                // Skip data chunks which have already been verified through other files.
+                let mut pending = Vec::new();
+                {
+                    let mut chunks = chunks.lock().unwrap();
+                    for idx in 0..io_vec.len() {
+                        let desc = io_vec.blob_io_desc(idx).unwrap();
+                        // Chunks in the same batch share the compressed offset.
+                        let key = (desc.blob.blob_index(), desc.chunkinfo.id());
+                        if chunks.insert(key) {
+                            pending.push(idx);
+                        }
+                    }
+                }
+                if !pending.is_empty() {
+                    let result = self.device.read_to_buf(io_vec, &mut buf[..len]);
+                    let mut report = report.lock().unwrap();
+                    let mut pos = 0usize;
+                    for idx in 0..io_vec.len() {
+                        let desc = io_vec.blob_io_desc(idx).unwrap();
+                        let (start, end) = (pos, pos + desc.size as usize);
+                        pos = end;
+                        if !pending.contains(&idx) {
+                            continue;
+                        }
+                        report.total_chunks += 1;
+                        let error = match &result {
+                            Err(e) => Some(e.to_string()),
+                            Ok(r) if *r < end => Some(format!("short read, {} bytes", r)),
+                            Ok(_) => {
+                                let chunk = &desc.chunkinfo;
+                                // Only whole chunks can be validated against the chunk digest.
+                                let whole =
+                                    desc.offset == 0 && desc.size == chunk.uncompressed_size();
+                                if whole
+                                    && !desc.blob.is_legacy_stargz()
+                                    && !check_digest(
+                                        &buf[start..end],
+                                        chunk.chunk_id(),
+                                        desc.blob.digester(),
+                                    )
+                                {
+                                    Some("data digest value doesn't match".to_string())
+                                } else {
+                                    None
+                                }
+                            }
+                        };
+                        if let Some(error) = error {
+                            report.bad_chunks.push(BadChunk {
+                                path: path.to_path_buf(),
+                                file_offset: file_offset + start as u64,
+                                blob_id: desc.blob.blob_id(),
+                                compressed_offset: desc.chunkinfo.compressed_offset(),
+                                error,
+                            });
+                        }
+                    }
+                }
+                file_offset += len as u64;
+            }
+            offset += size as u64;
+        }
+
+        Ok(())
     }
 
     /// for blobfs
@@ -345,13 +1286,108 @@ impl Rafs {
     }
 
     fn root_ino(&self) -> u64 {
-        self.sb.superblock.root_ino()
+        self.root_ino
+    }
+
+    // Collect regular files under `root_ino` for the flat view, hardlinks are listed only once.
+    fn collect_flat_inodes(sb: &RafsSuper, root_ino: Inode) -> RafsResult<Vec<Inode>> {
+        let mut inodes = Vec::new();
+        sb.walk_directory::<PathBuf>(root_ino, None, &mut |inode, _path| {
+            if inode.is_reg() {
+                inodes.push(inode.ino());
+            }
+            Ok(())
+        })
+        .map_err(|e| RafsError::Configure(format!("failed to build flat view, {}", e)))?;
+        inodes.sort_unstable();
+        inodes.dedup();
+
+        Ok(inodes)
+    }
+
+    // Get inode number of a file in the flat view by its name.
+    fn flat_ino(&self, name: &OsStr) -> Option<Inode> {
+        let inodes = self.flat_inodes.as_ref()?;
+        let name = name.to_str()?;
+        let ino = name
+            .parse::<Inode>()
+            .ok()
+            .filter(|ino| ino.to_string() == name)?;
+        inodes.binary_search(&ino).ok().map(|_| ino)
+    }
+
+    fn ino_from_path(&self, path: &Path) -> Result<Inode> {
+        if self.flat_inodes.is_some() {
+            path.strip_prefix("/")
+                .ok()
+                .and_then(|p| self.flat_ino(p.as_os_str()))
+                .ok_or_else(|| enoent!())
+        } else {
+            self.sb.load().ino_from_path(path)
+        }
+    }
+
+    // Files opened with O_DIRECT are read without populating the blob cache, so workloads reading
+    // each file only once, such as backup scanners, don't evict hot data from the cache.
+    #[cfg(target_os = "linux")]
+    fn is_uncached_open(flags: u32) -> bool {
+        flags & libc::O_DIRECT as u32 != 0
+    }
+
+    #[cfg(target_os = "macos")]
+    fn is_uncached_open(_flags: u32) -> bool {
+        false
+    }
+
+    // Get inode number of the directory to be used as the filesystem root.
+    fn subdir_ino(sb: &RafsSuper, subdir: &str) -> RafsResult<Inode> {
+        if subdir.is_empty() {
+            return Ok(sb.superblock.root_ino());
+        }
+
+        let ino = sb.ino_from_path(Path::new(subdir)).map_err(|e| {
+            RafsError::Configure(format!("failed to find subdir {} in image, {}", subdir, e))
+        })?;
+        let inode = sb.get_inode(ino, false).map_err(|e| {
+            RafsError::Configure(format!("failed to get inode of subdir {}, {}", subdir, e))
+        })?;
+        if !inode.is_dir() {
+            return Err(RafsError::Configure(format!(
+                "subdir {} is not a directory",
+                subdir
+            )));
+        }
+
+        Ok(ino)
+    }
+
+    // Get inode numbers of directories to be announced as submounts.
+    fn submount_inodes(sb: &RafsSuper, submounts: &[String]) -> RafsResult<HashSet<Inode>> {
+        let mut inodes = HashSet::new();
+        for path in submounts {
+            let ino = sb.ino_from_path(Path::new(path)).map_err(|e| {
+                RafsError::Configure(format!("failed to find submount {} in image, {}", path, e))
+            })?;
+            let inode = sb.get_inode(ino, false).map_err(|e| {
+                RafsError::Configure(format!("failed to get inode of submount {}, {}", path, e))
+            })?;
+            if !inode.is_dir() {
+                return Err(RafsError::Configure(format!(
+                    "submount {} is not a directory",
+                    path
+                )));
+            }
+            inodes.insert(ino);
+        }
+
+        Ok(inodes)
     }
 
     fn do_prefetch(
         root_ino: u64,
         mut reader: RafsIoReader,
         prefetch_files: Option<Vec<PathBuf>>,
+        history: Vec<PathBuf>,
         prefetch_all: bool,
         sb: Arc<RafsSuper>,
         device: BlobDevice,
@@ -401,6 +1437,15 @@ impl Rafs {
             }
         };
 
+        // Files accessed by previous mounts of the image are likely to be accessed again soon.
+        if !history.is_empty() {
+            let inodes = Self::convert_file_list(&history, &sb);
+            let res = sb.prefetch_files(&device, &mut reader, root_ino, Some(inodes), &fetcher);
+            if let Err(e) = res {
+                info!("failed to prefetch files in prefetch history, {:?}", e);
+            }
+        }
+
         // Bootstrap has non-empty prefetch table indicating a full prefetch
         let inlay_prefetch_all = sb
             .is_inlay_prefetch_all(&mut reader)
@@ -488,10 +1533,13 @@ impl Rafs {
 
 impl BackendFileSystem for Rafs {
     fn mount(&self) -> Result<(Entry, u64)> {
-        let root_inode = self.sb.get_inode(self.root_ino(), self.digest_validate)?;
+        let root_inode = self
+            .sb
+            .load()
+            .get_inode(self.root_ino(), self.digest_validate)?;
         self.ios.new_file_counter(root_inode.ino());
         let e = self.get_inode_entry(root_inode);
-        Ok((e, self.sb.get_max_ino()))
+        Ok((e, self.sb.load().get_max_ino()))
     }
 
     fn as_any(&self) -> &dyn Any {
@@ -512,21 +1560,30 @@ impl FileSystem for Rafs {
     }
 
     #[cfg(target_os = "linux")]
-    fn init(&self, _opts: FsOptions) -> Result<FsOptions> {
-        Ok(
-            // These fuse features are supported by rafs by default.
-            FsOptions::ASYNC_READ
-                | FsOptions::PARALLEL_DIROPS
-                | FsOptions::BIG_WRITES
-                | FsOptions::HANDLE_KILLPRIV
-                | FsOptions::ASYNC_DIO
-                | FsOptions::HAS_IOCTL_DIR
-                | FsOptions::WRITEBACK_CACHE
-                | FsOptions::ZERO_MESSAGE_OPEN
-                | FsOptions::ATOMIC_O_TRUNC
-                | FsOptions::CACHE_SYMLINKS
-                | FsOptions::ZERO_MESSAGE_OPENDIR,
-        )
+    fn init(&self, capable: FsOptions) -> Result<FsOptions> {
+        // These fuse features are supported by rafs by default.
+        let mut opts = FsOptions::ASYNC_READ
+            | FsOptions::PARALLEL_DIROPS
+            | FsOptions::BIG_WRITES
+            | FsOptions::HANDLE_KILLPRIV
+            | FsOptions::ASYNC_DIO
+            | FsOptions::HAS_IOCTL_DIR
+            | FsOptions::WRITEBACK_CACHE
+            | FsOptions::ZERO_MESSAGE_OPEN
+            | FsOptions::ATOMIC_O_TRUNC
+            | FsOptions::CACHE_SYMLINKS
+            | FsOptions::ZERO_MESSAGE_OPENDIR;
+        if self.disable_writeback_cache {
+            opts.remove(FsOptions::WRITEBACK_CACHE);
+        }
+        if self.disable_zero_message_open {
+            opts.remove(FsOptions::ZERO_MESSAGE_OPEN);
+        }
+        if !self.submount_inodes.is_empty() && capable.contains(FsOptions::SUBMOUNTS) {
+            opts |= FsOptions::SUBMOUNTS;
+            self.announce_submounts.store(true, Ordering::Relaxed);
+        }
+        Ok(opts)
     }
 
     fn destroy(&self) {}
@@ -534,20 +1591,30 @@ impl FileSystem for Rafs {
     fn lookup(&self, _ctx: &Context, ino: u64, name: &CStr) -> Result<Entry> {
         let mut rec = FopRecorder::settle(Lookup, ino, &self.ios);
         let target = OsStr::from_bytes(name.to_bytes());
-        let parent = self.sb.get_inode(ino, self.digest_validate)?;
+        let parent = self.sb.load().get_inode(ino, self.digest_validate)?;
         if !parent.is_dir() {
             return Err(enotdir!());
         }
 
         rec.mark_success(0);
-        if target == DOT || (ino == ROOT_ID && target == DOTDOT) {
+        if target == DOT || ((ino == ROOT_ID || ino == self.root_ino()) && target == DOTDOT) {
             let mut entry = self.get_inode_entry(parent);
             entry.inode = ino;
             Ok(entry)
+        } else if self.flat_inodes.is_some() && ino == self.root_ino() {
+            Ok(self
+                .flat_ino(target)
+                .and_then(|i| self.sb.load().get_inode(i, self.digest_validate).ok())
+                .map(|i| {
+                    self.ios.new_file_counter(i.ino());
+                    self.get_inode_entry(i)
+                })
+                .unwrap_or_else(|| self.negative_entry()))
         } else if target == DOTDOT {
-            let parent = self.sb.get_extended_inode(parent.ino(), false)?;
+            let parent = self.sb.load().get_extended_inode(parent.ino(), false)?;
             Ok(self
                 .sb
+                .load()
                 .get_inode(parent.parent(), self.digest_validate)
                 .map(|i| self.get_inode_entry(i))
                 .unwrap_or_else(|_| self.negative_entry()))
@@ -556,13 +1623,18 @@ impl FileSystem for Rafs {
                 .get_child_by_name(target)
                 .map(|i| {
                     self.ios.new_file_counter(i.ino());
+                    if i.is_dir() {
+                        self.prefetch_dir(i.as_inode());
+                    }
                     self.get_inode_entry(i.as_inode())
                 })
                 .unwrap_or_else(|_| self.negative_entry()))
         }
     }
 
-    fn forget(&self, _ctx: &Context, _inode: u64, _count: u64) {}
+    fn forget(&self, _ctx: &Context, inode: u64, _count: u64) {
+        self.access_advices.set(inode, RafsAccessAdvice::Normal);
+    }
 
     fn batch_forget(&self, ctx: &Context, requests: Vec<(u64, u64)>) {
         for (inode, count) in requests {
@@ -583,12 +1655,12 @@ impl FileSystem for Rafs {
             r
         })?;
 
-        Ok((attr.into(), self.sb.meta.attr_timeout))
+        Ok((attr.into(), self.sb.load().meta.attr_timeout))
     }
 
     fn readlink(&self, _ctx: &Context, ino: u64) -> Result<Vec<u8>> {
         let mut rec = FopRecorder::settle(Readlink, ino, &self.ios);
-        let inode = self.sb.get_inode(ino, self.digest_validate)?;
+        let inode = self.sb.load().get_inode(ino, self.digest_validate)?;
 
         Ok(inode
             .get_symlink()
@@ -605,7 +1677,7 @@ impl FileSystem for Rafs {
         &self,
         _ctx: &Context,
         ino: u64,
-        _handle: u64,
+        handle: u64,
         w: &mut dyn ZeroCopyWriter,
         size: u32,
         offset: u64,
@@ -616,7 +1688,7 @@ impl FileSystem for Rafs {
             return Err(einval!("offset + size wraps around."));
         }
 
-        let inode = self.sb.get_inode(ino, false)?;
+        let inode = self.sb.load().get_inode(ino, false)?;
         let inode_size = inode.size();
         let mut recorder = FopRecorder::settle(Read, ino, &self.ios);
         // Check for zero size read.
@@ -626,14 +1698,47 @@ impl FileSystem for Rafs {
         }
 
         let real_size = cmp::min(size as u64, inode_size - offset);
+        // Serve tiny files from the inline data directly, no need to touch the storage backend.
+        if let Some(data) = inode.get_inline_data() {
+            let start = offset as usize;
+            let end = start + real_size as usize;
+            if end > data.len() {
+                return Err(einval!("inline data is truncated"));
+            }
+            w.write_all(&data[start..end])?;
+            recorder.mark_success(real_size as usize);
+            return Ok(real_size as usize);
+        }
+
         let mut result = 0;
         let mut io_vecs = inode.alloc_bio_vecs(&self.device, offset, real_size as usize, true)?;
         assert!(!io_vecs.is_empty() && !io_vecs[0].is_empty());
+        let uncached = handle == RAFS_UNCACHED_HANDLE;
+        if uncached {
+            for io_vec in io_vecs.iter_mut() {
+                io_vec.set_uncached(true);
+            }
+        }
+
+        // Small reads may need to fetch whole chunks from the backend, keep track of the ratio.
+        let chunk_bytes = io_vecs.iter().map(|v| v.chunk_data_size()).sum::<u64>();
+        self.ios.read_amplification_update(real_size, chunk_bytes);
+        if self.amplification_warn_ratio > 0
+            && chunk_bytes / real_size >= self.amplification_warn_ratio as u64
+        {
+            let interval = Duration::from_secs(RAFS_AMPLIFICATION_WARN_INTERVAL);
+            if let Some(reads) = self.ios.amplified_read_update(interval) {
+                warn!(
+                    "rafs: read {} bytes at offset {} of inode {} touches {} bytes of chunk data, {} amplified reads since last warning",
+                    real_size, offset, ino, chunk_bytes, reads
+                );
+            }
+        }
 
         // Try to amplify user io for Rafs v5, to improve performance.
         let user_io_batch_size =
-            cmp::min(self.user_io_batch_size as usize, w.available_bytes()) as u32;
-        if self.sb.meta.is_v5() && size < user_io_batch_size {
+            cmp::min(self.user_io_batch_size(ino) as usize, w.available_bytes()) as u32;
+        if self.sb.load().meta.is_v5() && size < user_io_batch_size && !uncached {
             let all_chunks_ready = self.device.all_chunks_ready(&io_vecs);
             if !all_chunks_ready {
                 let chunk_mask = self.metadata().chunk_size as u64 - 1;
@@ -643,7 +1748,7 @@ impl FileSystem for Rafs {
                 if actual_size < user_io_batch_size as u64 {
                     let window_size = user_io_batch_size as u64 - actual_size;
                     let orig_cnt = io_vecs.iter().fold(0, |s, d| s + d.len());
-                    self.sb.amplify_user_io(
+                    self.sb.load().amplify_user_io(
                         &self.device,
                         user_io_batch_size,
                         &mut io_vecs,
@@ -683,11 +1788,16 @@ impl FileSystem for Rafs {
         &self,
         _ctx: &Context,
         _inode: Self::Inode,
-        _flags: u32,
+        flags: u32,
         _fuse_flags: u32,
     ) -> Result<(Option<Self::Handle>, OpenOptions, Option<u32>)> {
+        let handle = if Self::is_uncached_open(flags) {
+            Some(RAFS_UNCACHED_HANDLE)
+        } else {
+            None
+        };
         // Keep cache since we are readonly
-        Ok((None, OpenOptions::KEEP_CACHE, None))
+        Ok((handle, OpenOptions::KEEP_CACHE, None))
     }
 
     fn release(
@@ -711,15 +1821,15 @@ impl FileSystem for Rafs {
         // filesystem doesn't implement this method.
         st.f_namemax = 255;
         st.f_bsize = 512;
-        st.f_fsid = self.sb.meta.magic as u64;
+        st.f_fsid = self.sb.load().meta.magic as u64;
         #[cfg(target_os = "macos")]
         {
-            st.f_files = self.sb.meta.inodes_count as u32;
+            st.f_files = self.sb.load().meta.inodes_count as u32;
         }
 
         #[cfg(target_os = "linux")]
         {
-            st.f_files = self.sb.meta.inodes_count;
+            st.f_files = self.sb.load().meta.inodes_count;
         }
 
         Ok(st)
@@ -739,7 +1849,7 @@ impl FileSystem for Rafs {
         }
 
         let name = OsStr::from_bytes(name.to_bytes());
-        let inode = self.sb.get_inode(inode, false)?;
+        let inode = self.sb.load().get_inode(inode, false)?;
         let value = inode.get_xattr(name)?;
         let r = match value {
             Some(value) => match size {
@@ -768,7 +1878,7 @@ impl FileSystem for Rafs {
             return Err(std::io::Error::from_raw_os_error(libc::ENOSYS));
         }
 
-        let inode = self.sb.get_inode(inode, false)?;
+        let inode = self.sb.load().get_inode(inode, false)?;
         let mut count = 0;
         let mut buf = Vec::new();
         for mut name in inode.get_xattrs()? {
@@ -817,7 +1927,10 @@ impl FileSystem for Rafs {
         let mut rec = FopRecorder::settle(Readdirplus, ino, &self.ios);
 
         self.do_readdir(ino, size, offset, &mut |dir_entry| {
-            let inode = self.sb.get_inode(dir_entry.ino, self.digest_validate)?;
+            let inode = self
+                .sb
+                .load()
+                .get_inode(dir_entry.ino, self.digest_validate)?;
             add_entry(dir_entry, self.get_inode_entry(inode))
         })
         .map(|r| {
@@ -843,6 +1956,20 @@ impl FileSystem for Rafs {
         Ok(())
     }
 
+    fn fallocate(
+        &self,
+        _ctx: &Context,
+        _inode: u64,
+        _handle: u64,
+        _mode: u32,
+        _offset: u64,
+        _length: u64,
+    ) -> Result<()> {
+        // Report a read-only filesystem instead of ENOSYS, which means a broken filesystem to
+        // some tools.
+        Err(std::io::Error::from_raw_os_error(libc::EROFS))
+    }
+
     fn access(&self, ctx: &Context, ino: u64, mask: u32) -> Result<()> {
         let mut rec = FopRecorder::settle(Access, ino, &self.ios);
         let st = self.get_inode_attr(ino)?;
@@ -901,6 +2028,14 @@ pub(crate) mod tests {
     use std::str::FromStr;
 
     pub fn new_rafs_backend() -> Box<Rafs> {
+        new_rafs_backend_with_subdir("").unwrap()
+    }
+
+    fn new_rafs_backend_with_subdir(subdir: &str) -> RafsResult<Box<Rafs>> {
+        new_rafs_backend_with_options(subdir, false)
+    }
+
+    fn new_rafs_backend_with_options(subdir: &str, flat_view: bool) -> RafsResult<Box<Rafs>> {
         let config = r#"
         version = 2
         id = "test"
@@ -921,22 +2056,26 @@ pub(crate) mod tests {
         mode = "direct"
         validate = false
         enable_xattr = true
+        subdir = "SUBDIR"
+        flat_view = FLAT_VIEW
+        negative_entry_timeout = 60
         [rafs.prefetch]
         enable = true
         threads = 10
         batch_size = 131072
         bandwidth_limit = 10485760
-        "#;
+        "#
+        .replace("SUBDIR", subdir)
+        .replace("FLAT_VIEW", &flat_view.to_string());
         let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
         let mut source_path = PathBuf::from(root_dir);
         source_path.push("../tests/texture/bootstrap/rafs-v5.boot");
         let mountpoint = "/mnt";
-        let config = Arc::new(ConfigV2::from_str(config).unwrap());
+        let config = Arc::new(ConfigV2::from_str(&config).unwrap());
         let bootstrapfile = source_path.to_str().unwrap();
-        let (mut rafs, reader) = Rafs::new(&config, mountpoint, Path::new(bootstrapfile)).unwrap();
-        rafs.import(reader, Some(vec![std::path::PathBuf::new()]))
-            .unwrap();
-        Box::new(rafs)
+        let (mut rafs, reader) = Rafs::new(&config, mountpoint, Path::new(bootstrapfile))?;
+        rafs.import(reader, Some(vec![std::path::PathBuf::new()]))?;
+        Ok(Box::new(rafs))
     }
 
     #[test]
@@ -950,6 +2089,126 @@ pub(crate) mod tests {
         assert_eq!(attr.mode & 0o777, 0o755);
     }
 
+    #[test]
+    fn it_should_mount_subdir() {
+        let rafs = new_rafs_backend_with_subdir("/etc").unwrap();
+        let ctx = &Context::default();
+        let (root, _) = rafs.mount().unwrap();
+        // Inode of `/etc` in the test image.
+        assert_eq!(root.inode, 5);
+        assert_eq!(root.attr.st_mode & 0o777, 0o755);
+
+        // Entries in the subtree are visible from the root.
+        let name = std::ffi::CString::new("passwd").unwrap();
+        assert_eq!(rafs.lookup(ctx, root.inode, &name).unwrap().inode, 111);
+        // Entries out of the subtree are invisible.
+        let name = std::ffi::CString::new("usr").unwrap();
+        assert_eq!(rafs.lookup(ctx, root.inode, &name).unwrap().inode, 0);
+        // Can't escape from the root.
+        let name = std::ffi::CString::new("..").unwrap();
+        assert_eq!(
+            rafs.lookup(ctx, root.inode, &name).unwrap().inode,
+            root.inode
+        );
+
+        assert!(new_rafs_backend_with_subdir("/etc/passwd").is_err());
+        assert!(new_rafs_backend_with_subdir("/nonexist").is_err());
+    }
+
+    #[test]
+    fn it_should_resolve_path_to_ino() {
+        let rafs = new_rafs_backend();
+        assert_eq!(rafs.path_to_ino("/").unwrap(), ROOT_ID);
+        assert_eq!(rafs.path_to_ino("/etc").unwrap(), 5);
+        assert_eq!(rafs.path_to_ino("/etc/passwd").unwrap(), 111);
+        assert_eq!(rafs.path_to_ino("/etc/./passwd").unwrap(), 111);
+
+        let err = rafs.path_to_ino("/nonexist").unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOENT));
+        let err = rafs.path_to_ino("/etc/passwd/nonexist").unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOENT));
+        assert!(rafs.path_to_ino("etc").is_err());
+    }
+
+    #[test]
+    fn it_should_mount_flat_view() {
+        let rafs = new_rafs_backend_with_options("", true).unwrap();
+        let ctx = &Context::default();
+        let root = rafs.root_ino();
+
+        let mut files = Vec::new();
+        rafs.sb
+            .load()
+            .walk(&mut |path, inode| {
+                if inode.is_reg() {
+                    files.push((inode.ino(), path.to_path_buf()));
+                }
+                Ok(())
+            })
+            .unwrap();
+        assert!(!files.is_empty());
+
+        let mut names = Vec::new();
+        rafs.readdir(ctx, root, 0, 4096, 0, &mut |entry| {
+            names.push(String::from_utf8(entry.name.to_vec()).unwrap());
+            Ok(1)
+        })
+        .unwrap();
+        assert_eq!(&names[..2], &[DOT, DOTDOT]);
+        let names = &names[2..];
+        // Every regular file appears exactly once, hardlinks share the same entry.
+        let mut inos = files.iter().map(|(ino, _)| *ino).collect::<Vec<_>>();
+        inos.sort_unstable();
+        inos.dedup();
+        assert_eq!(
+            names,
+            inos.iter().map(|i| i.to_string()).collect::<Vec<_>>()
+        );
+
+        // Resuming from an offset continues with the following entry.
+        let mut resumed = Vec::new();
+        rafs.readdir(ctx, root, 0, 4096, 3, &mut |entry| {
+            // Pretend that the buffer is full after two entries.
+            if resumed.len() == 2 {
+                return Ok(0);
+            }
+            resumed.push(String::from_utf8(entry.name.to_vec()).unwrap());
+            Ok(1)
+        })
+        .unwrap();
+        assert_eq!(resumed, names[1..3]);
+
+        for (ino, path) in files.iter() {
+            let name = std::ffi::CString::new(ino.to_string()).unwrap();
+            let entry = rafs.lookup(ctx, root, &name).unwrap();
+            assert_eq!(entry.inode, *ino);
+            let inode = rafs.sb.load().get_inode(*ino, false).unwrap();
+            assert_eq!(entry.attr.st_size as u64, inode.size());
+            assert_eq!(rafs.sb.load().ino_from_path(path).unwrap(), *ino);
+        }
+        let name = std::ffi::CString::new("etc").unwrap();
+        assert_eq!(rafs.lookup(ctx, root, &name).unwrap().inode, 0);
+        let name = std::ffi::CString::new(format!("0{}", files[0].0)).unwrap();
+        assert_eq!(rafs.lookup(ctx, root, &name).unwrap().inode, 0);
+    }
+
+    #[test]
+    fn it_should_cache_negative_lookup() {
+        let rafs = new_rafs_backend();
+        let ctx = &Context::default();
+
+        let name = std::ffi::CString::new("nonexist").unwrap();
+        let entry = rafs.lookup(ctx, ROOT_ID, &name).unwrap();
+        assert_eq!(entry.inode, 0);
+        assert_eq!(entry.entry_timeout, Duration::from_secs(60));
+
+        // Timeout of existing entries is not affected.
+        let name = std::ffi::CString::new("etc").unwrap();
+        let entry = rafs.lookup(ctx, ROOT_ID, &name).unwrap();
+        assert_ne!(entry.inode, 0);
+        assert_eq!(entry.entry_timeout, rafs.sb.load().meta.entry_timeout);
+    }
+
     #[test]
     fn it_should_access() {
         let rafs = new_rafs_backend();
@@ -1029,21 +2288,39 @@ pub(crate) mod tests {
 #[cfg(test)]
 mod tests {
     use nydus_utils::metrics::FsIoStats;
+    use std::str::FromStr;
+    use vmm_sys_util::tempdir::TempDir;
 
     use super::*;
     #[test]
     fn test_rafs() {
-        let rafs = Rafs {
+        let mut rafs = Rafs {
             id: "foo".into(),
             device: BlobDevice::default(),
             ios: FsIoStats::default().into(),
-            sb: Arc::new(RafsSuper::default()),
+            sb: ArcSwap::from_pointee(RafsSuper::default()),
+            root_ino: ROOT_ID,
             initialized: false,
             digest_validate: false,
             fs_prefetch: false,
             prefetch_all: false,
+            prefetch_history: None,
             xattr_enabled: false,
             user_io_batch_size: 0,
+            amplification_warn_ratio: 0,
+            flat_inodes: None,
+            dir_prefetch: false,
+            prefetched_dirs: Mutex::new(HashSet::new()),
+            disable_writeback_cache: false,
+            disable_zero_message_open: false,
+            negative_timeout: Duration::from_secs(RAFS_DEFAULT_ENTRY_TIMEOUT),
+            submount_inodes: HashSet::new(),
+            announce_submounts: AtomicBool::new(false),
+            uid_offset: 0,
+            gid_offset: 0,
+            access_advices: AccessAdvices::default(),
+            warm_report: Mutex::new(WarmReport::default()),
+            trusted_key: String::new(),
             i_uid: 0,
             i_gid: 0,
             i_time: 0,
@@ -1054,10 +2331,38 @@ mod tests {
         assert_eq!(ent.inode, 0);
         assert_eq!(ent.generation, 0);
         assert_eq!(ent.attr_flags, 0);
+        assert_eq!(
+            ent.entry_timeout,
+            Duration::from_secs(RAFS_DEFAULT_ENTRY_TIMEOUT)
+        );
         #[cfg(target_os = "linux")]
-        rafs.init(FsOptions::ASYNC_DIO).unwrap();
-        rafs.open(&Context::default(), Inode::default(), 0, 0)
+        {
+            let opts = rafs.init(FsOptions::ASYNC_DIO).unwrap();
+            assert!(opts.contains(FsOptions::WRITEBACK_CACHE | FsOptions::ZERO_MESSAGE_OPEN));
+
+            rafs.disable_writeback_cache = true;
+            let opts = rafs.init(FsOptions::ASYNC_DIO).unwrap();
+            assert!(!opts.contains(FsOptions::WRITEBACK_CACHE));
+            assert!(opts.contains(FsOptions::ZERO_MESSAGE_OPEN));
+
+            rafs.disable_zero_message_open = true;
+            let opts = rafs.init(FsOptions::ASYNC_DIO).unwrap();
+            assert!(!opts.contains(FsOptions::WRITEBACK_CACHE));
+            assert!(!opts.contains(FsOptions::ZERO_MESSAGE_OPEN));
+            assert!(opts.contains(FsOptions::ASYNC_READ | FsOptions::ZERO_MESSAGE_OPENDIR));
+        }
+        let (handle, _, _) = rafs
+            .open(&Context::default(), Inode::default(), 0, 0)
             .unwrap();
+        assert_eq!(handle, None);
+        #[cfg(target_os = "linux")]
+        {
+            let flags = (libc::O_RDONLY | libc::O_DIRECT) as u32;
+            let (handle, _, _) = rafs
+                .open(&Context::default(), Inode::default(), flags, 0)
+                .unwrap();
+            assert_eq!(handle, Some(RAFS_UNCACHED_HANDLE));
+        }
         rafs.release(
             &Context::default(),
             Inode::default(),
@@ -1069,6 +2374,126 @@ mod tests {
         )
         .unwrap();
         rafs.statfs(&Context::default(), Inode::default()).unwrap();
+        let err = rafs
+            .fallocate(&Context::default(), ROOT_ID, 0, 0, 0, 4096)
+            .unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EROFS));
+        #[cfg(target_os = "linux")]
+        {
+            let mode = (libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE) as u32;
+            let err = rafs
+                .fallocate(&Context::default(), ROOT_ID, 0, mode, 0, 4096)
+                .unwrap_err();
+            assert_eq!(err.raw_os_error(), Some(libc::EROFS));
+        }
+
         rafs.destroy();
     }
+
+    #[test]
+    fn test_new_rafs_in_auto_mode() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let bootstrap = PathBuf::from(root_dir).join("../tests/texture/bootstrap/rafs-v5.boot");
+        let blob_dir = TempDir::new().unwrap();
+        let new_config = |mode: &str, threshold: u64| {
+            let config = format!(
+                r#"
+                version = 2
+                id = "test"
+                backend.type = "localfs"
+                backend.localfs.dir = "{}"
+                rafs.mode = "{}"
+                rafs.auto_mode_threshold = {}
+                "#,
+                blob_dir.as_path().display(),
+                mode,
+                threshold
+            );
+            Arc::new(ConfigV2::from_str(&config).unwrap())
+        };
+
+        // Blobs are never read, empty blob files are enough to set up the blob device.
+        let (rs, _) =
+            RafsSuper::load_from_file(&bootstrap, new_config("direct", 0), false).unwrap();
+        for blob in rs.superblock.get_blob_infos() {
+            fs::File::create(blob_dir.as_path().join(blob.blob_id())).unwrap();
+        }
+
+        let size = fs::metadata(&bootstrap).unwrap().len();
+        let load_mode = |mode: &str, threshold: u64| {
+            let (rafs, _) = Rafs::new(&new_config(mode, threshold), "test", &bootstrap).unwrap();
+            let mode = rafs.sb.load().mode.clone();
+            mode
+        };
+        // Small images are loaded in cached mode, and large ones in direct mode.
+        assert_eq!(load_mode("auto", size), RafsMode::Cached);
+        assert_eq!(load_mode("auto", size - 1), RafsMode::Direct);
+        assert_eq!(load_mode("direct", size), RafsMode::Direct);
+    }
+
+    #[test]
+    fn test_reload_with_mount_options() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let root_dir = PathBuf::from(root_dir).join("../tests/texture");
+        let bootstrap = root_dir.join("bootstrap/rafs-v5.boot");
+        let size = fs::metadata(&bootstrap).unwrap().len();
+        let blob_dir = TempDir::new().unwrap();
+        let new_config = |extra: &str| {
+            let config = format!(
+                r#"
+                version = 2
+                id = "test"
+                backend.type = "localfs"
+                backend.localfs.dir = "{}"
+                rafs.mode = "direct"
+                {}
+                "#,
+                blob_dir.as_path().display(),
+                extra
+            );
+            Arc::new(ConfigV2::from_str(&config).unwrap())
+        };
+        let mount = |config: &Arc<ConfigV2>, path: &Path| {
+            let (mut rafs, reader) = Rafs::new(config, "test", path).unwrap();
+            rafs.import(reader, None).unwrap();
+            rafs
+        };
+
+        // Blobs are never read, empty blob files are enough to set up the blob device.
+        let (rs, _) = RafsSuper::load_from_file(&bootstrap, new_config(""), false).unwrap();
+        for blob in rs.superblock.get_blob_infos() {
+            fs::File::create(blob_dir.as_path().join(blob.blob_id())).unwrap();
+        }
+
+        // The trailer is generated by signing `rafs-v5.boot` with `ed25519-private.pem`.
+        let mut data = fs::read(&bootstrap).unwrap();
+        data.extend_from_slice(&fs::read(root_dir.join("signing/rafs-v5.boot.sig")).unwrap());
+        let signed = blob_dir.as_path().join("signed.boot");
+        fs::write(&signed, &data).unwrap();
+        data[0x200] ^= 0x1;
+        let tampered = blob_dir.as_path().join("tampered.boot");
+        fs::write(&tampered, &data).unwrap();
+
+        // Metadata to switch to must be signed if the mount requires signatures.
+        let config = new_config(&format!(
+            "rafs.trusted_key = \"{}\"",
+            root_dir.join("signing/ed25519-public.pem").display()
+        ));
+        let rafs = mount(&config, &signed);
+        assert!(rafs.reload(&bootstrap).is_err());
+        assert!(rafs.reload(&tampered).is_err());
+        rafs.reload(&signed).unwrap();
+
+        // Metadata to switch to is limited by the configured maximum size.
+        let padded = blob_dir.as_path().join("padded.boot");
+        let mut data = fs::read(&bootstrap).unwrap();
+        data.resize(size as usize + 0x1000, 0);
+        fs::write(&padded, &data).unwrap();
+        let rafs = mount(
+            &new_config(&format!("rafs.max_metadata_size = {}", size)),
+            &bootstrap,
+        );
+        assert!(rafs.reload(&padded).is_err());
+        rafs.reload(&bootstrap).unwrap();
+    }
 }
@@ -23,13 +23,14 @@ use fuse_backend_rs::abi::fuse_abi;
 use fuse_backend_rs::api::filesystem::Entry;
 use nydus_storage::device::v5::BlobV5ChunkInfo;
 use nydus_storage::device::{BlobChunkFlags, BlobChunkInfo, BlobDevice, BlobInfo};
+use nydus_utils::compress;
 use nydus_utils::digest::RafsDigest;
 use nydus_utils::ByteSize;
 
 use crate::metadata::inode::RafsInodeFlags;
 use crate::metadata::layout::v5::{
     rafsv5_alloc_bio_vecs, rafsv5_validate_inode, RafsV5BlobTable, RafsV5ChunkInfo, RafsV5Inode,
-    RafsV5InodeChunkOps, RafsV5InodeOps, RafsV5XAttrsTable, RAFSV5_ALIGNMENT,
+    RafsV5InodeChunkOps, RafsV5InodeExtended, RafsV5InodeOps, RafsV5XAttrsTable, RAFSV5_ALIGNMENT,
 };
 use crate::metadata::layout::{bytes_to_os_str, parse_xattr, RAFS_V5_ROOT_INODE};
 use crate::metadata::{
@@ -248,7 +249,9 @@ pub struct CachedInodeV5 {
     i_rdev: u32,
     i_mtime_nsec: u32,
     i_mtime: u64,
+    i_extended: RafsV5InodeExtended,
     i_target: OsString, // for symbol link
+    i_inline_data: Vec<u8>,
     i_xattr: HashMap<OsString, Vec<u8>>,
     i_data: Vec<Arc<CachedChunkInfoV5>>,
     i_child: Vec<Arc<CachedInodeV5>>,
@@ -288,6 +291,46 @@ impl CachedInodeV5 {
         Ok(())
     }
 
+    fn load_inline_data(
+        &mut self,
+        sb: &RafsSuperMeta,
+        data_size: usize,
+        r: &mut RafsIoReader,
+    ) -> Result<()> {
+        if self.has_inline_data() && !sb.has_inline_data() {
+            return Err(einval!(format!(
+                "inode {} has inline data but the filesystem doesn't support it",
+                self.i_ino
+            )));
+        }
+        if self.has_inline_data() && data_size > 0 {
+            let mut data_buf = vec![0u8; data_size];
+            r.read_exact(data_buf.as_mut_slice())?;
+            r.seek_to_next_aligned(data_size, RAFSV5_ALIGNMENT)?;
+            self.i_inline_data = data_buf;
+        }
+
+        Ok(())
+    }
+
+    fn has_inline_data(&self) -> bool {
+        self.is_reg() && self.i_flags.contains(RafsInodeFlags::INLINE_DATA)
+    }
+
+    fn load_extended(&mut self, sb: &RafsSuperMeta, r: &mut RafsIoReader) -> Result<()> {
+        if self.i_flags.contains(RafsInodeFlags::EXTENDED) {
+            if !sb.has_extended_inode() {
+                return Err(einval!(format!(
+                    "inode {} has extended record but the filesystem doesn't support it",
+                    self.i_ino
+                )));
+            }
+            self.i_extended.load(r)?;
+        }
+
+        Ok(())
+    }
+
     fn load_xattr(&mut self, r: &mut RafsIoReader) -> Result<()> {
         if self.has_xattr() {
             let mut xattrs = RafsV5XAttrsTable::new();
@@ -319,14 +362,16 @@ impl CachedInodeV5 {
 
     /// Load an inode metadata from a reader.
     pub fn load(&mut self, sb: &RafsSuperMeta, r: &mut RafsIoReader) -> Result<()> {
-        // RafsV5Inode...name...symbol link...xattrs...chunks
+        // RafsV5Inode...name...symbol link...extended...xattrs...chunks
         let mut inode = RafsV5Inode::new();
 
-        // parse ondisk inode: RafsV5Inode|name|symbol|xattr|chunks
+        // parse ondisk inode: RafsV5Inode|name|symbol|extended|xattr|chunks
         r.read_exact(inode.as_mut())?;
         self.copy_from_ondisk(&inode);
         self.load_name(inode.i_name_size as usize, r)?;
         self.load_symlink(inode.i_symlink_size as usize, r)?;
+        self.load_inline_data(sb, inode.i_symlink_size as usize, r)?;
+        self.load_extended(sb, r)?;
         self.load_xattr(r)?;
         self.load_chunk_info(r)?;
         self.i_chunksize = sb.chunk_size;
@@ -378,7 +423,11 @@ impl RafsInode for CachedInodeV5 {
         if !self.is_hardlink() && self.i_parent >= self.i_ino {
             return Err(einval!("invalid parent inode"));
         }
-        if self.is_reg() {
+        if self.has_inline_data() {
+            if !self.i_data.is_empty() || self.i_size != self.i_inline_data.len() as u64 {
+                return Err(einval!("invalid inline data"));
+            }
+        } else if self.is_reg() {
             let chunks = (self.i_size + chunk_size - 1) / chunk_size;
             if !self.has_hole() && chunks != self.i_data.len() as u64 {
                 return Err(einval!("invalid chunk count"));
@@ -439,7 +488,7 @@ impl RafsInode for CachedInodeV5 {
         Entry {
             attr: self.get_attr().into(),
             inode: self.i_ino,
-            generation: 0,
+            generation: self.i_meta.get_generation(self.i_ino),
             attr_flags: 0,
             attr_timeout: self.i_meta.attr_timeout,
             entry_timeout: self.i_meta.entry_timeout,
@@ -448,16 +497,26 @@ impl RafsInode for CachedInodeV5 {
 
     #[inline]
     fn get_attr(&self) -> fuse_abi::Attr {
-        fuse_abi::Attr {
+        let mut attr = fuse_abi::Attr {
             ino: self.i_ino,
             size: self.i_size,
             blocks: self.i_blocks,
             mode: self.i_mode,
             nlink: self.i_nlink as u32,
+            mtime: self.i_mtime,
+            mtimensec: self.i_mtime_nsec,
             blksize: RAFS_ATTR_BLOCK_SIZE,
             rdev: self.i_rdev,
             ..Default::default()
+        };
+        if self.i_flags.contains(RafsInodeFlags::EXTENDED) {
+            attr.atime = self.i_extended.i_atime;
+            attr.atimensec = self.i_extended.i_atime_nsec;
+            attr.ctime = self.i_extended.i_ctime;
+            attr.ctimensec = self.i_extended.i_ctime_nsec;
         }
+
+        attr
     }
 
     #[inline]
@@ -618,6 +677,14 @@ impl RafsInode for CachedInodeV5 {
         self.get_child_count()
     }
 
+    fn get_inline_data(&self) -> Option<&[u8]> {
+        if self.has_inline_data() {
+            Some(&self.i_inline_data)
+        } else {
+            None
+        }
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -662,6 +729,14 @@ impl RafsInodeExt for CachedInodeV5 {
         }
     }
 
+    fn get_extended(&self) -> Option<RafsV5InodeExtended> {
+        if self.i_flags.contains(RafsInodeFlags::EXTENDED) {
+            Some(self.i_extended)
+        } else {
+            None
+        }
+    }
+
     impl_getter!(parent, i_parent, u64);
 }
 
@@ -761,6 +836,10 @@ impl BlobChunkInfo for CachedChunkInfoV5 {
         false
     }
 
+    fn compressor(&self) -> Option<compress::Algorithm> {
+        self.flags.compressor()
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -795,8 +874,8 @@ mod cached_tests {
     use std::cmp;
     use std::ffi::{OsStr, OsString};
     use std::fs::OpenOptions;
-    use std::io::Seek;
     use std::io::SeekFrom::Start;
+    use std::io::{Seek, Write};
     use std::os::unix::ffi::OsStrExt;
     use std::sync::Arc;
 
@@ -809,11 +888,13 @@ mod cached_tests {
     use crate::metadata::cached_v5::{CachedInodeV5, CachedSuperBlockV5};
     use crate::metadata::inode::RafsInodeFlags;
     use crate::metadata::layout::v5::{
-        rafsv5_align, RafsV5BlobTable, RafsV5ChunkInfo, RafsV5Inode, RafsV5InodeWrapper,
+        rafsv5_align, RafsV5BlobTable, RafsV5ChunkInfo, RafsV5Inode, RafsV5InodeExtended,
+        RafsV5InodeWrapper,
     };
     use crate::metadata::layout::{RafsXAttrs, RAFS_V5_ROOT_INODE};
     use crate::metadata::{
-        RafsInode, RafsInodeWalkAction, RafsStore, RafsSuperBlock, RafsSuperInodes, RafsSuperMeta,
+        RafsInode, RafsInodeWalkAction, RafsStore, RafsSuperBlock, RafsSuperFlags, RafsSuperInodes,
+        RafsSuperMeta,
     };
     use crate::{BufWriter, RafsInodeExt, RafsIoRead, RafsIoReader};
     use vmm_sys_util::tempfile::TempFile;
@@ -858,6 +939,7 @@ mod cached_tests {
             name: file_name.as_os_str(),
             symlink: None,
             inode: &ondisk_inode,
+            extended: None,
         };
         inode.store(&mut writer).unwrap();
         chunk.store(&mut writer).unwrap();
@@ -896,6 +978,174 @@ mod cached_tests {
         std::fs::remove_file("/tmp/buf_1").unwrap();
     }
 
+    #[test]
+    fn test_load_extended_inode() {
+        let tmp_file = TempFile::new().unwrap();
+        let mut f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(tmp_file.as_path())
+            .unwrap();
+        let mut writer = BufWriter::new(f.try_clone().unwrap());
+        let mut reader = Box::new(f.try_clone().unwrap()) as RafsIoReader;
+
+        // A char device whose device number doesn't fit into 32 bits.
+        let dev_name = OsString::from("c_dev");
+        let mut dev_inode = RafsV5Inode::new();
+        dev_inode.i_name_size = dev_name.byte_size() as u16;
+        dev_inode.i_ino = 3;
+        dev_inode.i_parent = RAFS_V5_ROOT_INODE;
+        dev_inode.i_nlink = 1;
+        dev_inode.i_mode = libc::S_IFCHR as u32;
+        dev_inode.i_flags = RafsInodeFlags::EXTENDED;
+        let dev_ext = RafsV5InodeExtended {
+            i_rdev: 0x1234_5678_9abc_def0,
+            ..Default::default()
+        };
+        dev_inode.i_rdev = dev_ext.i_rdev as u32;
+        RafsV5InodeWrapper {
+            name: dev_name.as_os_str(),
+            symlink: None,
+            inode: &dev_inode,
+            extended: Some(&dev_ext),
+        }
+        .store(&mut writer)
+        .unwrap();
+
+        // An empty file with sub-second timestamps.
+        let file_name = OsString::from("c_file");
+        let mut file_inode = RafsV5Inode::new();
+        file_inode.i_name_size = file_name.byte_size() as u16;
+        file_inode.i_ino = 4;
+        file_inode.i_parent = RAFS_V5_ROOT_INODE;
+        file_inode.i_nlink = 1;
+        file_inode.i_mode = libc::S_IFREG as u32;
+        file_inode.i_mtime = 1_600_000_000;
+        file_inode.i_mtime_nsec = 123_456_789;
+        file_inode.i_flags = RafsInodeFlags::EXTENDED;
+        let file_ext = RafsV5InodeExtended {
+            i_rdev: 0,
+            i_atime: 1_600_000_001,
+            i_ctime: 1_600_000_002,
+            i_atime_nsec: 987_654_321,
+            i_ctime_nsec: 555_555_555,
+        };
+        RafsV5InodeWrapper {
+            name: file_name.as_os_str(),
+            symlink: None,
+            inode: &file_inode,
+            extended: Some(&file_ext),
+        }
+        .store(&mut writer)
+        .unwrap();
+        writer.flush().unwrap();
+
+        // Extended records are rejected if the superblock doesn't declare them.
+        f.seek(Start(0)).unwrap();
+        let md = RafsSuperMeta {
+            inodes_count: 100,
+            chunk_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let meta = Arc::new(md);
+        let blob_table = Arc::new(RafsV5BlobTable::new());
+        let mut cached_dev = CachedInodeV5::new(blob_table.clone(), meta.clone());
+        assert!(cached_dev.load(&meta, &mut reader).is_err());
+
+        f.seek(Start(0)).unwrap();
+        let md = RafsSuperMeta {
+            inodes_count: 100,
+            chunk_size: 1024 * 1024,
+            flags: RafsSuperFlags::EXTENDED_INODE,
+            ..Default::default()
+        };
+        let meta = Arc::new(md);
+
+        let mut cached_dev = CachedInodeV5::new(blob_table.clone(), meta.clone());
+        cached_dev.load(&meta, &mut reader).unwrap();
+        assert_eq!(
+            cached_dev.get_extended().unwrap().i_rdev,
+            0x1234_5678_9abc_def0
+        );
+        assert_eq!(cached_dev.get_attr().rdev, 0x9abc_def0);
+
+        let mut cached_file = CachedInodeV5::new(blob_table, meta.clone());
+        cached_file.load(&meta, &mut reader).unwrap();
+        assert_eq!(cached_file.i_name, "c_file");
+        assert_eq!(cached_file.get_extended().unwrap(), file_ext);
+        let attr = cached_file.get_attr();
+        assert_eq!(attr.atime, 1_600_000_001);
+        assert_eq!(attr.atimensec, 987_654_321);
+        assert_eq!(attr.ctime, 1_600_000_002);
+        assert_eq!(attr.ctimensec, 555_555_555);
+    }
+
+    #[test]
+    fn test_load_inline_data() {
+        let tmp_file = TempFile::new().unwrap();
+        let mut f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(tmp_file.as_path())
+            .unwrap();
+        let mut writer = BufWriter::new(f.try_clone().unwrap());
+        let mut reader = Box::new(f.try_clone().unwrap()) as RafsIoReader;
+
+        let file_name = OsString::from("c_tiny");
+        let data = OsString::from("tiny file content");
+        let mut file_inode = RafsV5Inode::new();
+        file_inode.i_name_size = file_name.byte_size() as u16;
+        file_inode.i_symlink_size = data.byte_size() as u16;
+        file_inode.i_size = data.byte_size() as u64;
+        file_inode.i_ino = 5;
+        file_inode.i_parent = RAFS_V5_ROOT_INODE;
+        file_inode.i_nlink = 1;
+        file_inode.i_mode = libc::S_IFREG as u32;
+        file_inode.i_flags = RafsInodeFlags::INLINE_DATA;
+        RafsV5InodeWrapper {
+            name: file_name.as_os_str(),
+            symlink: Some(data.as_os_str()),
+            inode: &file_inode,
+            extended: None,
+        }
+        .store(&mut writer)
+        .unwrap();
+        writer.flush().unwrap();
+
+        // Inline data is rejected if the superblock doesn't declare it.
+        f.seek(Start(0)).unwrap();
+        let md = RafsSuperMeta {
+            inodes_count: 100,
+            chunk_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let meta = Arc::new(md);
+        let blob_table = Arc::new(RafsV5BlobTable::new());
+        let mut cached_inode = CachedInodeV5::new(blob_table.clone(), meta.clone());
+        assert!(cached_inode.load(&meta, &mut reader).is_err());
+
+        f.seek(Start(0)).unwrap();
+        let md = RafsSuperMeta {
+            inodes_count: 100,
+            chunk_size: 1024 * 1024,
+            flags: RafsSuperFlags::INLINE_DATA,
+            ..Default::default()
+        };
+        let meta = Arc::new(md);
+        let mut cached_inode = CachedInodeV5::new(blob_table, meta.clone());
+        cached_inode.load(&meta, &mut reader).unwrap();
+        cached_inode.validate(100, 1024 * 1024).unwrap();
+
+        assert!(!cached_inode.is_symlink());
+        assert_eq!(cached_inode.get_symlink_size(), 0);
+        assert_eq!(cached_inode.get_chunk_count(), 0);
+        assert_eq!(
+            cached_inode.get_inline_data().unwrap(),
+            b"tiny file content".to_vec()
+        );
+        assert_eq!(cached_inode.size(), 17);
+    }
+
     #[test]
     fn test_load_symlink() {
         let mut f = OpenOptions::new()
@@ -921,6 +1171,7 @@ mod cached_tests {
             name: file_name.as_os_str(),
             symlink: Some(symlink_name.as_os_str()),
             inode: &ondisk_inode,
+            extended: None,
         };
         inode.store(&mut writer).unwrap();
 
@@ -965,6 +1216,7 @@ mod cached_tests {
             name: file_name.as_os_str(),
             symlink: None,
             inode: &ondisk_inode,
+            extended: None,
         };
         inode.store(&mut writer).unwrap();
 
@@ -1024,10 +1276,101 @@ mod cached_tests {
         assert_eq!(desc3.len(), 3);
         assert_eq!(desc3.blob_io_desc(2).unwrap().size, 8192);
 
+        // Reads at or beyond EOF must return nothing instead of wrapping around.
+        let descs = cached_inode
+            .alloc_bio_vecs(&device, u64::MAX - 10, 1024 * 1024 * 4, true)
+            .unwrap();
+        assert!(descs.is_empty());
+        let descs = cached_inode
+            .alloc_bio_vecs(&device, ondisk_inode.i_size, 100, true)
+            .unwrap();
+        assert!(descs.is_empty());
+
+        // Reads crossing EOF are clamped to the file size.
+        let descs = cached_inode
+            .alloc_bio_vecs(&device, 1024 * 1024 * 3, usize::MAX, true)
+            .unwrap();
+        assert_eq!(descs.len(), 1);
+        assert_eq!(descs[0].size(), 8192);
+
         drop(f);
         std::fs::remove_file("/tmp/buf_3").unwrap();
     }
 
+    #[test]
+    fn test_alloc_bio_desc_with_invalid_blob_index() {
+        let tmp_file = TempFile::new().unwrap();
+        let mut f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(tmp_file.as_path())
+            .unwrap();
+        let mut writer = BufWriter::new(f.try_clone().unwrap());
+        let mut reader = Box::new(f.try_clone().unwrap()) as RafsIoReader;
+        let file_name = OsString::from("c_bad_blob");
+        let mut ondisk_inode = RafsV5Inode::new();
+        ondisk_inode.i_name_size = rafsv5_align(file_name.len()) as u16;
+        ondisk_inode.i_ino = 3;
+        ondisk_inode.i_parent = RAFS_V5_ROOT_INODE;
+        ondisk_inode.i_nlink = 1;
+        ondisk_inode.i_child_count = 2;
+        ondisk_inode.i_mode = libc::S_IFREG as u32;
+        ondisk_inode.i_size = 1024 * 1024 * 2;
+        ondisk_inode.i_blocks = 4096;
+        let inode = RafsV5InodeWrapper {
+            name: file_name.as_os_str(),
+            symlink: None,
+            inode: &ondisk_inode,
+            extended: None,
+        };
+        inode.store(&mut writer).unwrap();
+
+        // The second chunk references a blob beyond the blob table.
+        for i in 0..ondisk_inode.i_child_count {
+            let mut chunk = RafsV5ChunkInfo::new();
+            chunk.blob_index = i * 5;
+            chunk.uncompressed_size = 1024 * 1024;
+            chunk.uncompressed_offset = (i * 1024 * 1024) as u64;
+            chunk.compressed_size = chunk.uncompressed_size / 2;
+            chunk.compressed_offset = ((i * 1024 * 1024) / 2) as u64;
+            chunk.file_offset = chunk.uncompressed_offset;
+            chunk.store(&mut writer).unwrap();
+        }
+        writer.flush().unwrap();
+        f.seek(Start(0)).unwrap();
+
+        let mut meta = Arc::new(RafsSuperMeta::default());
+        Arc::get_mut(&mut meta).unwrap().chunk_size = 1024 * 1024;
+        Arc::get_mut(&mut meta).unwrap().inodes_count = 4;
+        let mut blob_table = Arc::new(RafsV5BlobTable::new());
+        Arc::get_mut(&mut blob_table).unwrap().add(
+            String::from("blob1"),
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            BlobFeatures::_V5_NO_EXT_BLOB_TABLE,
+            meta.flags,
+        );
+        let mut cached_inode = CachedInodeV5::new(blob_table, meta.clone());
+        cached_inode.load(&meta, &mut reader).unwrap();
+        assert!(cached_inode.get_chunk_info(2).is_err());
+
+        let device = BlobDevice::default();
+        let descs = cached_inode.alloc_bio_vecs(&device, 0, 100, true).unwrap();
+        assert_eq!(descs[0].blob_io_desc(0).unwrap().blob.blob_id(), "blob1");
+        let err = cached_inode
+            .alloc_bio_vecs(&device, 1024 * 1024, 100, true)
+            .unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EIO));
+        let err = cached_inode
+            .alloc_bio_vecs(&device, 0, 1024 * 1024 * 2, true)
+            .unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EIO));
+    }
+
     #[test]
     fn test_rafsv5_superblock() {
         let md = RafsSuperMeta::default();
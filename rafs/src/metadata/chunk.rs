@@ -11,6 +11,7 @@ use anyhow::{Context, Result};
 use nydus_storage::device::v5::BlobV5ChunkInfo;
 use nydus_storage::device::{BlobChunkFlags, BlobChunkInfo};
 use nydus_storage::meta::BlobMetaChunk;
+use nydus_utils::compress;
 use nydus_utils::digest::RafsDigest;
 
 use crate::metadata::cached_v5::CachedChunkInfoV5;
@@ -248,6 +249,25 @@ impl ChunkWrapper {
         }
     }
 
+    /// Get the compression algorithm of the chunk if it differs from the blob compressor.
+    pub fn compressor(&self) -> Option<compress::Algorithm> {
+        match self {
+            ChunkWrapper::V5(c) => c.flags.compressor(),
+            ChunkWrapper::V6(c) => c.flags.compressor(),
+            ChunkWrapper::Ref(c) => c.compressor(),
+        }
+    }
+
+    /// Set the compression algorithm of the chunk, which also updates the compressed flag.
+    pub fn set_compressor(&mut self, algo: compress::Algorithm) {
+        self.ensure_owned();
+        match self {
+            ChunkWrapper::V5(c) => c.flags.set_compressor(algo),
+            ChunkWrapper::V6(c) => c.flags.set_compressor(algo),
+            ChunkWrapper::Ref(_c) => panic!("unexpected"),
+        }
+    }
+
     /// Check whether the chunk is encrypted or not.
     pub fn is_encrypted(&self) -> bool {
         match self {
@@ -30,13 +30,14 @@ use arc_swap::{ArcSwap, Guard};
 use nydus_storage::device::v5::BlobV5ChunkInfo;
 use nydus_storage::device::{BlobChunkFlags, BlobChunkInfo, BlobDevice, BlobInfo, BlobIoVec};
 use nydus_storage::utils::readahead;
+use nydus_utils::compress;
 use nydus_utils::digest::RafsDigest;
 use nydus_utils::filemap::{clone_file, FileMapState};
 
 use crate::metadata::layout::v5::{
     rafsv5_align, rafsv5_alloc_bio_vecs, rafsv5_validate_inode, RafsV5BlobTable, RafsV5ChunkInfo,
-    RafsV5Inode, RafsV5InodeChunkOps, RafsV5InodeOps, RafsV5InodeTable, RafsV5XAttrsTable,
-    RAFSV5_ALIGNMENT, RAFSV5_EXT_BLOB_ENTRY_SIZE, RAFSV5_SUPERBLOCK_SIZE,
+    RafsV5Inode, RafsV5InodeChunkOps, RafsV5InodeExtended, RafsV5InodeOps, RafsV5InodeTable,
+    RafsV5XAttrsTable, RAFSV5_ALIGNMENT, RAFSV5_EXT_BLOB_ENTRY_SIZE, RAFSV5_SUPERBLOCK_SIZE,
 };
 use crate::metadata::layout::{
     bytes_to_os_str, parse_xattr_names, parse_xattr_value, MetaRange, XattrName, XattrValue,
@@ -44,8 +45,7 @@ use crate::metadata::layout::{
 };
 use crate::metadata::{
     Attr, Entry, Inode, RafsInode, RafsInodeWalkAction, RafsInodeWalkHandler, RafsSuperBlock,
-    RafsSuperInodes, RafsSuperMeta, DOT, DOTDOT, RAFS_ATTR_BLOCK_SIZE, RAFS_MAX_METADATA_SIZE,
-    RAFS_MAX_NAME,
+    RafsSuperInodes, RafsSuperMeta, DOT, DOTDOT, RAFS_ATTR_BLOCK_SIZE, RAFS_MAX_NAME,
 };
 use crate::{RafsError, RafsInodeExt, RafsIoReader, RafsResult};
 
@@ -128,6 +128,7 @@ impl Drop for DirectMappingState {
 #[derive(Clone)]
 pub struct DirectSuperBlockV5 {
     state: Arc<ArcSwap<DirectMappingState>>,
+    pinned: Option<Arc<DirectMappingState>>,
 }
 
 impl DirectSuperBlockV5 {
@@ -137,6 +138,7 @@ impl DirectSuperBlockV5 {
 
         Self {
             state: Arc::new(ArcSwap::new(Arc::new(state))),
+            pinned: None,
         }
     }
 
@@ -171,7 +173,7 @@ impl DirectSuperBlockV5 {
         Ok(wrapper)
     }
 
-    fn update_state(&self, r: &mut RafsIoReader) -> Result<()> {
+    fn update_state(&self, meta: &RafsSuperMeta, r: &mut RafsIoReader) -> Result<()> {
         let old_state = self.state();
 
         // Validate file size
@@ -179,10 +181,8 @@ impl DirectSuperBlockV5 {
         let md = file.metadata()?;
         let len = md.len();
         let size = len as usize;
-        if len < RAFSV5_SUPERBLOCK_SIZE as u64
-            || len > RAFS_MAX_METADATA_SIZE as u64
-            || len & (RAFSV5_ALIGNMENT as u64 - 1) != 0
-        {
+        meta.check_metadata_size(len)?;
+        if len < RAFSV5_SUPERBLOCK_SIZE as u64 || len & (RAFSV5_ALIGNMENT as u64 - 1) != 0 {
             return Err(ebadf!("invalid bootstrap file"));
         }
         let md_range = MetaRange::new(
@@ -192,16 +192,16 @@ impl DirectSuperBlockV5 {
         )?;
 
         // Validate inode table layout
-        let inode_table_start = old_state.meta.inode_table_offset;
-        let inode_table_size = old_state.meta.inode_table_entries as u64 * size_of::<u32>() as u64;
+        let inode_table_start = meta.inode_table_offset;
+        let inode_table_size = meta.inode_table_entries as u64 * size_of::<u32>() as u64;
         let inode_table_range = MetaRange::new(inode_table_start, inode_table_size, false)?;
         if !inode_table_range.is_subrange_of(&md_range) {
             return Err(ebadf!("invalid inode table"));
         }
 
         // Validate blob table layout
-        let blob_table_start = old_state.meta.blob_table_offset;
-        let blob_table_size = old_state.meta.blob_table_size as u64;
+        let blob_table_start = meta.blob_table_offset;
+        let blob_table_size = meta.blob_table_size as u64;
         let blob_table_range = MetaRange::new(blob_table_start, blob_table_size, false)?;
         if !blob_table_range.is_subrange_of(&md_range)
             || blob_table_range.intersect_with(&inode_table_range)
@@ -210,9 +210,9 @@ impl DirectSuperBlockV5 {
         }
 
         // Validate extended blob table layout
-        let extended_blob_table_offset = old_state.meta.extended_blob_table_offset;
+        let extended_blob_table_offset = meta.extended_blob_table_offset;
         let extended_blob_table_size =
-            old_state.meta.extended_blob_table_entries as u64 * RAFSV5_EXT_BLOB_ENTRY_SIZE as u64;
+            meta.extended_blob_table_entries as u64 * RAFSV5_EXT_BLOB_ENTRY_SIZE as u64;
         let extended_blob_table_range =
             MetaRange::new(extended_blob_table_offset, extended_blob_table_size, true)?;
         if extended_blob_table_offset > 0
@@ -229,10 +229,19 @@ impl DirectSuperBlockV5 {
 
         // Mmap the bootstrap file into current process for direct access
         let file_map = FileMapState::new(file, 0, size, false)?;
+        if meta.mlock_metadata {
+            match file_map.prefault_and_lock() {
+                Ok(true) => {}
+                Ok(false) => warn!(
+                    "RAFS metadata size {} exceeds RLIMIT_MEMLOCK, only prefault it",
+                    file_map.size()
+                ),
+                Err(e) => warn!("failed to lock RAFS metadata in memory, {}", e),
+            }
+        }
 
         // Load blob table. Safe because we have validated the blob table layout.
         let mut blob_table = RafsV5BlobTable::new();
-        let meta = &old_state.meta;
 
         // Load extended blob table if the bootstrap including extended blob table.
         if extended_blob_table_offset > 0 && extended_blob_table_size > 0 {
@@ -251,8 +260,8 @@ impl DirectSuperBlockV5 {
             RafsV5InodeTable {
                 data: Vec::from_raw_parts(
                     file_map.offset(inode_table_start as usize) as *const u32 as *mut u32,
-                    old_state.meta.inode_table_entries as usize,
-                    old_state.meta.inode_table_entries as usize,
+                    meta.inode_table_entries as usize,
+                    meta.inode_table_entries as usize,
                 ),
             }
         };
@@ -260,7 +269,7 @@ impl DirectSuperBlockV5 {
         let validate_inode = old_state.validate_inode;
 
         let state = DirectMappingState {
-            meta: old_state.meta,
+            meta: *meta,
             inode_table: ManuallyDrop::new(inode_table),
             blob_table,
             file_map,
@@ -277,7 +286,19 @@ impl DirectSuperBlockV5 {
 
     #[inline]
     fn state(&self) -> Guard<Arc<DirectMappingState>> {
-        self.state.load()
+        match self.pinned.as_ref() {
+            Some(state) => Guard::from_inner(state.clone()),
+            None => self.state.load(),
+        }
+    }
+
+    // Get a `DirectSuperBlockV5` object sticking to `state`, so inodes created from it keep
+    // accessing the same metadata after the metadata has been reloaded.
+    fn pinned(&self, state: &Guard<Arc<DirectMappingState>>) -> Self {
+        Self {
+            state: self.state.clone(),
+            pinned: Some(Arc::clone(state)),
+        }
     }
 }
 
@@ -289,7 +310,9 @@ impl RafsSuperInodes for DirectSuperBlockV5 {
     /// Find inode offset by ino from inode table and mmap to OndiskInode.
     fn get_inode(&self, ino: Inode, validate_inode: bool) -> Result<Arc<dyn RafsInode>> {
         let state = self.state();
-        let wrapper = self.get_inode_wrapper(ino, state.deref(), validate_inode)?;
+        let wrapper = self
+            .pinned(&state)
+            .get_inode_wrapper(ino, state.deref(), validate_inode)?;
         Ok(Arc::new(wrapper))
     }
 
@@ -299,18 +322,27 @@ impl RafsSuperInodes for DirectSuperBlockV5 {
         validate_inode: bool,
     ) -> Result<Arc<dyn RafsInodeExt>> {
         let state = self.state();
-        let wrapper = self.get_inode_wrapper(ino, state.deref(), validate_inode)?;
+        let wrapper = self
+            .pinned(&state)
+            .get_inode_wrapper(ino, state.deref(), validate_inode)?;
         Ok(Arc::new(wrapper))
     }
 }
 
 impl RafsSuperBlock for DirectSuperBlockV5 {
     fn load(&mut self, r: &mut RafsIoReader) -> Result<()> {
-        self.update_state(r)
+        let meta = self.state().meta;
+        self.update_state(&meta, r)
     }
 
     fn update(&self, r: &mut RafsIoReader) -> RafsResult<()> {
-        self.update_state(r).map_err(RafsError::SwapBackend)
+        let meta = self.state().meta;
+        self.update_state(&meta, r).map_err(RafsError::SwapBackend)
+    }
+
+    fn reload(&self, meta: &RafsSuperMeta, r: &mut RafsIoReader) -> RafsResult<()> {
+        self.update_state(meta, r)
+            .map_err(RafsError::FillSuperBlock)
     }
 
     fn destroy(&mut self) {
@@ -368,6 +400,20 @@ impl OndiskInodeWrapper {
         bytes_to_os_str(name)
     }
 
+    /// Get an reference to the extended inode record, if any.
+    ///
+    /// # Safety
+    /// It depends on Self::validate() to ensure valid memory layout.
+    fn extended_ref<'a>(&self, state: &'a DirectMappingState) -> Option<&'a RafsV5InodeExtended> {
+        let inode = self.inode(state);
+        if !inode.has_extended() {
+            return None;
+        }
+
+        let offset = self.offset + inode.size() - size_of::<RafsV5InodeExtended>();
+        state.file_map.get_ref::<RafsV5InodeExtended>(offset).ok()
+    }
+
     fn get_xattr_data<'a>(
         &self,
         state: &'a Guard<Arc<DirectMappingState>>,
@@ -442,12 +488,31 @@ impl RafsInode for OndiskInodeWrapper {
                 inode
             )));
         }
+        if inode.has_extended() && !state.meta.has_extended_inode() {
+            return Err(ebadf!(format!(
+                "inode {} has extended record but the filesystem doesn't support it",
+                inode.i_ino
+            )));
+        }
+        if inode.has_inline_data() && !state.meta.has_inline_data() {
+            return Err(ebadf!(format!(
+                "inode {} has inline data but the filesystem doesn't support it",
+                inode.i_ino
+            )));
+        }
         if !inode.is_hardlink() && inode.i_parent >= inode.i_ino {
             return Err(einval!("invalid parent inode"));
         }
 
         let chunk_count = 0;
-        if inode.is_reg() {
+        if inode.has_inline_data() {
+            if inode.i_child_count != 0 || inode.i_size != inode.i_symlink_size as u64 {
+                return Err(einval!(format!(
+                    "invalid inline data, ino {}, size {}, inline size {}",
+                    inode.i_ino, inode.i_size, inode.i_symlink_size,
+                )));
+            }
+        } else if inode.is_reg() {
             if self.state().meta.is_chunk_dict() {
                 // chunk-dict doesn't support chunk_count check
                 return Err(std::io::Error::from_raw_os_error(libc::EOPNOTSUPP));
@@ -534,7 +599,7 @@ impl RafsInode for OndiskInodeWrapper {
         Entry {
             attr: self.get_attr().into(),
             inode: inode.i_ino,
-            generation: 0,
+            generation: state.meta.get_generation(inode.i_ino),
             attr_flags: 0,
             attr_timeout: state.meta.attr_timeout,
             entry_timeout: state.meta.entry_timeout,
@@ -544,8 +609,7 @@ impl RafsInode for OndiskInodeWrapper {
     fn get_attr(&self) -> Attr {
         let state = self.state();
         let inode = self.inode(state.deref());
-
-        Attr {
+        let mut attr = Attr {
             ino: inode.i_ino,
             size: inode.i_size,
             blocks: inode.i_blocks,
@@ -558,7 +622,15 @@ impl RafsInode for OndiskInodeWrapper {
             blksize: RAFS_ATTR_BLOCK_SIZE,
             rdev: inode.i_rdev,
             ..Default::default()
+        };
+        if let Some(ext) = self.extended_ref(state.deref()) {
+            attr.atime = ext.i_atime;
+            attr.atimensec = ext.i_atime_nsec;
+            attr.ctime = ext.i_ctime;
+            attr.ctimensec = ext.i_ctime_nsec;
         }
+
+        attr
     }
 
     fn get_xattr(&self, name: &OsStr) -> Result<Option<XattrValue>> {
@@ -587,6 +659,24 @@ impl RafsInode for OndiskInodeWrapper {
         Ok(bytes_to_os_str(symlink).to_os_string())
     }
 
+    /// Get file content stored inline in the inode.
+    ///
+    /// # Safety
+    /// It depends on Self::validate() to ensure valid memory layout.
+    fn get_inline_data(&self) -> Option<&[u8]> {
+        // Inode objects stick to the metadata they are created from, which outlives them.
+        let state = self.mapping.pinned.as_deref()?;
+        let inode = self.inode(state);
+        if !inode.has_inline_data() {
+            return None;
+        }
+
+        let offset =
+            self.offset + size_of::<RafsV5Inode>() + rafsv5_align(inode.i_name_size as usize);
+        let size = inode.i_symlink_size as usize;
+        state.file_map.get_slice(offset, size).ok()
+    }
+
     fn walk_children_inodes(&self, entry_offset: u64, handler: RafsInodeWalkHandler) -> Result<()> {
         // offset 0 and 1 is for "." and ".." respectively.
         let mut cur_offset = entry_offset;
@@ -766,6 +856,11 @@ impl RafsInodeExt for OndiskInodeWrapper {
             .map(|v| v as Arc<dyn BlobChunkInfo>)
     }
 
+    fn get_extended(&self) -> Option<RafsV5InodeExtended> {
+        let state = self.state();
+        self.extended_ref(state.deref()).copied()
+    }
+
     impl_inode_getter!(get_name_size, i_name_size, u16);
     impl_inode_getter!(parent, i_parent, u64);
 }
@@ -854,6 +949,10 @@ impl BlobChunkInfo for DirectChunkInfoV5 {
         false
     }
 
+    fn compressor(&self) -> Option<compress::Algorithm> {
+        self.chunk(self.state().deref()).flags.compressor()
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -34,7 +34,7 @@ use nydus_storage::device::{
 };
 use nydus_storage::utils::readahead;
 use nydus_utils::filemap::{clone_file, FileMapState};
-use nydus_utils::{digest::RafsDigest, div_round_up, round_up};
+use nydus_utils::{compress, digest::RafsDigest, div_round_up, round_up};
 
 use crate::metadata::layout::v5::RafsV5ChunkInfo;
 use crate::metadata::layout::v6::{
@@ -67,6 +67,7 @@ struct DirectMappingState {
     blob_table: RafsV6BlobTable,
     blob_extra_infos: HashMap<String, RafsBlobExtraInfo>,
     map: FileMapState,
+    chunk_map: Mutex<Option<HashMap<RafsV6InodeChunkAddr, usize>>>,
 }
 
 impl DirectMappingState {
@@ -76,6 +77,7 @@ impl DirectMappingState {
             blob_table: RafsV6BlobTable::default(),
             blob_extra_infos: HashMap::new(),
             map: FileMapState::default(),
+            chunk_map: Mutex::new(None),
         }
     }
 
@@ -96,7 +98,6 @@ struct DirectCachedInfo {
     meta_offset: usize,
     root_ino: Inode,
     chunk_size: u32,
-    chunk_map: Mutex<Option<HashMap<RafsV6InodeChunkAddr, usize>>>,
     attr_timeout: Duration,
     entry_timeout: Duration,
 }
@@ -106,6 +107,7 @@ struct DirectCachedInfo {
 pub struct DirectSuperBlockV6 {
     info: Arc<DirectCachedInfo>,
     state: Arc<ArcSwap<DirectMappingState>>,
+    pinned: Option<Arc<DirectMappingState>>,
     device: Arc<Mutex<BlobDevice>>,
 }
 
@@ -119,7 +121,6 @@ impl DirectSuperBlockV6 {
             meta_offset,
             root_ino: meta.root_nid as Inode,
             chunk_size: meta.chunk_size,
-            chunk_map: Mutex::new(None),
             attr_timeout: meta.attr_timeout,
             entry_timeout: meta.entry_timeout,
         };
@@ -127,10 +128,30 @@ impl DirectSuperBlockV6 {
         Self {
             info: Arc::new(info),
             state: Arc::new(ArcSwap::new(Arc::new(state))),
+            pinned: None,
             device: Arc::new(Mutex::new(BlobDevice::default())),
         }
     }
 
+    #[inline]
+    fn state(&self) -> Guard<Arc<DirectMappingState>> {
+        match self.pinned.as_ref() {
+            Some(state) => Guard::from_inner(state.clone()),
+            None => self.state.load(),
+        }
+    }
+
+    // Get a `DirectSuperBlockV6` object sticking to `state`, so inodes created from it keep
+    // accessing the same metadata after the metadata has been reloaded.
+    fn pinned(&self, state: &Guard<Arc<DirectMappingState>>) -> Self {
+        Self {
+            info: self.info.clone(),
+            state: self.state.clone(),
+            pinned: Some(Arc::clone(state)),
+            device: self.device.clone(),
+        }
+    }
+
     fn disk_inode(
         state: &Guard<Arc<DirectMappingState>>,
         offset: usize,
@@ -179,11 +200,12 @@ impl DirectSuperBlockV6 {
         })
     }
 
-    fn update_state(&self, r: &mut RafsIoReader) -> Result<()> {
+    fn update_state(&self, meta: &RafsSuperMeta, r: &mut RafsIoReader) -> Result<()> {
         // Validate file size
         let file = clone_file(r.as_raw_fd())?;
         let md = file.metadata()?;
         let len = md.len();
+        meta.check_metadata_size(len)?;
         let md_range = MetaRange::new(
             EROFS_BLOCK_SIZE_4096 as u64,
             len - EROFS_BLOCK_SIZE_4096 as u64,
@@ -191,9 +213,8 @@ impl DirectSuperBlockV6 {
         )?;
 
         // Validate blob table layout as blob_table_start and blob_table_offset is read from bootstrap.
-        let old_state = self.state.load();
-        let blob_table_size = old_state.meta.blob_table_size as u64;
-        let blob_table_start = old_state.meta.blob_table_offset;
+        let blob_table_size = meta.blob_table_size as u64;
+        let blob_table_start = meta.blob_table_offset;
         let blob_table_range = MetaRange::new(blob_table_start, blob_table_size, false)?;
         if !blob_table_range.is_subrange_of(&md_range) {
             return Err(ebadf!("invalid blob table"));
@@ -204,17 +225,27 @@ impl DirectSuperBlockV6 {
 
         // Load extended blob table if the bootstrap including extended blob table.
         let mut blob_table = RafsV6BlobTable::new();
-        let meta = &old_state.meta;
         r.seek(SeekFrom::Start(meta.blob_table_offset))?;
         blob_table.load(r, meta.blob_table_size, meta.chunk_size, meta.flags)?;
         let blob_extra_infos = rafsv6_load_blob_extra_info(meta, r)?;
 
         let file_map = FileMapState::new(file, 0, len as usize, false)?;
+        if meta.mlock_metadata {
+            match file_map.prefault_and_lock() {
+                Ok(true) => {}
+                Ok(false) => warn!(
+                    "RAFS metadata size {} exceeds RLIMIT_MEMLOCK, only prefault it",
+                    file_map.size()
+                ),
+                Err(e) => warn!("failed to lock RAFS metadata in memory, {}", e),
+            }
+        }
         let state = DirectMappingState {
-            meta: old_state.meta.clone(),
+            meta: Arc::new(*meta),
             blob_table,
             blob_extra_infos,
             map: file_map,
+            chunk_map: Mutex::new(None),
         };
 
         // Swap new and old DirectMappingState object,
@@ -228,7 +259,7 @@ impl DirectSuperBlockV6 {
     // so we need to use the chunk table at the end of the bootstrap to restore the chunk info of an inode
     fn load_chunk_map(&self) -> Result<HashMap<RafsV6InodeChunkAddr, usize>> {
         let mut chunk_map = HashMap::default();
-        let state = self.state.load();
+        let state = self.state();
         let size = state.meta.chunk_table_size as usize;
         if size == 0 {
             return Ok(chunk_map);
@@ -255,15 +286,15 @@ impl DirectSuperBlockV6 {
 
 impl RafsSuperInodes for DirectSuperBlockV6 {
     fn get_max_ino(&self) -> Inode {
-        let state = self.state.load();
+        let state = self.state();
         // The maximum inode number supported by RAFSv6 is smaller than limit of fuse-backend-rs.
         (0xffff_ffffu64) * state.block_size() / EROFS_INODE_SLOT_SIZE as u64
     }
 
     /// Find inode offset by ino from inode table and mmap to OndiskInode.
     fn get_inode(&self, ino: Inode, _validate_digest: bool) -> Result<Arc<dyn RafsInode>> {
-        let state = self.state.load();
-        Ok(Arc::new(self.inode_wrapper(&state, ino)?))
+        let state = self.state();
+        Ok(Arc::new(self.pinned(&state).inode_wrapper(&state, ino)?))
     }
 
     fn get_extended_inode(
@@ -271,12 +302,13 @@ impl RafsSuperInodes for DirectSuperBlockV6 {
         ino: Inode,
         _validate_digest: bool,
     ) -> Result<Arc<dyn RafsInodeExt>> {
-        let state = self.state.load();
+        let state = self.state();
+        let mapping = self.pinned(&state);
         if ino == state.meta.root_nid as u64 {
-            let inode = self.inode_wrapper_with_info(&state, ino, ino, OsString::from("/"))?;
+            let inode = mapping.inode_wrapper_with_info(&state, ino, ino, OsString::from("/"))?;
             return Ok(Arc::new(inode));
         }
-        let mut inode = self.inode_wrapper(&state, ino)?;
+        let mut inode = mapping.inode_wrapper(&state, ino)?;
         if inode.is_dir() {
             inode.get_parent()?;
             inode.get_name(&state)?;
@@ -291,11 +323,18 @@ impl RafsSuperInodes for DirectSuperBlockV6 {
 
 impl RafsSuperBlock for DirectSuperBlockV6 {
     fn load(&mut self, r: &mut RafsIoReader) -> Result<()> {
-        self.update_state(r)
+        let meta = *self.state().meta;
+        self.update_state(&meta, r)
     }
 
     fn update(&self, r: &mut RafsIoReader) -> RafsResult<()> {
-        self.update_state(r).map_err(RafsError::SwapBackend)
+        let meta = *self.state().meta;
+        self.update_state(&meta, r).map_err(RafsError::SwapBackend)
+    }
+
+    fn reload(&self, meta: &RafsSuperMeta, r: &mut RafsIoReader) -> RafsResult<()> {
+        self.update_state(meta, r)
+            .map_err(RafsError::FillSuperBlock)
     }
 
     fn destroy(&mut self) {
@@ -304,11 +343,11 @@ impl RafsSuperBlock for DirectSuperBlockV6 {
     }
 
     fn get_blob_infos(&self) -> Vec<Arc<BlobInfo>> {
-        self.state.load().blob_table.get_all()
+        self.state().blob_table.get_all()
     }
 
     fn get_blob_extra_infos(&self) -> Result<HashMap<String, RafsBlobExtraInfo>> {
-        Ok(self.state.load().blob_extra_infos.clone())
+        Ok(self.state().blob_extra_infos.clone())
     }
 
     fn root_ino(&self) -> u64 {
@@ -316,8 +355,8 @@ impl RafsSuperBlock for DirectSuperBlockV6 {
     }
 
     fn get_chunk_info(&self, idx: usize) -> Result<Arc<dyn BlobChunkInfo>> {
-        let state = self.state.load();
-        let chunk = DirectChunkInfoV6::new(&state, self.clone(), idx)?;
+        let state = self.state();
+        let chunk = DirectChunkInfoV6::new(&state, self.pinned(&state), idx)?;
         Ok(Arc::new(chunk))
     }
 
@@ -354,7 +393,7 @@ impl OndiskInodeWrapper {
     }
 
     fn state(&self) -> Guard<Arc<DirectMappingState>> {
-        self.mapping.state.load()
+        self.mapping.state()
     }
 
     fn blocks_count(&self) -> u64 {
@@ -792,6 +831,10 @@ impl RafsInode for OndiskInodeWrapper {
         size: usize,
         user_io: bool,
     ) -> Result<Vec<BlobIoVec>> {
+        // Nothing to read at or beyond EOF, also avoids underflow of `self.size() - offset`.
+        if size == 0 || offset >= self.size() {
+            return Ok(vec![]);
+        }
         let state = self.state();
         let chunk_size = self.chunk_size();
         let head_chunk_index = offset / chunk_size as u64;
@@ -900,10 +943,11 @@ impl RafsInode for OndiskInodeWrapper {
     }
 
     fn get_entry(&self) -> Entry {
+        let ino = self.ino();
         Entry {
             attr: self.get_attr().into(),
-            inode: self.ino(),
-            generation: 0,
+            inode: ino,
+            generation: self.state().meta.get_generation(ino),
             attr_timeout: self.mapping.info.attr_timeout,
             entry_timeout: self.mapping.info.entry_timeout,
             ..Default::default()
@@ -1358,7 +1402,7 @@ impl RafsInodeExt for OndiskInodeWrapper {
             let chunk_info = TarfsChunkInfoV6::from_chunk_addr(chunk_addr, size)?;
             Ok(Arc::new(chunk_info))
         } else {
-            let mut chunk_map = self.mapping.info.chunk_map.lock().unwrap();
+            let mut chunk_map = state.chunk_map.lock().unwrap();
             if chunk_map.is_none() {
                 *chunk_map = Some(self.mapping.load_chunk_map()?);
             }
@@ -1419,7 +1463,7 @@ impl DirectChunkInfoV6 {
 
     #[inline]
     fn state(&self) -> Guard<Arc<DirectMappingState>> {
-        self.mapping.state.load()
+        self.mapping.state()
     }
 
     /// Dereference the underlying OndiskChunkInfo object.
@@ -1461,6 +1505,11 @@ impl BlobChunkInfo for DirectChunkInfoV6 {
             .contains(BlobChunkFlags::ENCYPTED)
     }
 
+    fn compressor(&self) -> Option<compress::Algorithm> {
+        let state = self.state();
+        self.v5_chunk(&state).flags.compressor()
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -1578,6 +1627,10 @@ impl BlobV5ChunkInfo for TarfsChunkInfoV6 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::metadata::RafsSuper;
+    use nydus_api::{ConfigV2, RafsConfigV2};
+    use std::fs::File;
+    use std::path::PathBuf;
 
     #[test]
     fn test_direct_mapping_state() {
@@ -1610,4 +1663,48 @@ mod tests {
         assert_eq!(info1.file_offset(), 0x0000_0000);
         assert_eq!(info1.flags(), BlobChunkFlags::empty());
     }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_mlock_metadata() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let path = PathBuf::from(root_dir).join("../tests/texture/bootstrap/rafs-v6-2.2.boot");
+        let mut config = ConfigV2::new("test");
+        config.rafs = Some(RafsConfigV2 {
+            mlock_metadata: true,
+            ..Default::default()
+        });
+        let (rs, _) = RafsSuper::load_from_file(&path, Arc::new(config), false).unwrap();
+        assert!(rs.meta.mlock_metadata);
+
+        let mut sb = DirectSuperBlockV6::new(&rs.meta);
+        let mut reader = Box::new(File::open(&path).unwrap()) as RafsIoReader;
+        sb.load(&mut reader).unwrap();
+        assert!(sb.state.load().map.is_resident().unwrap());
+    }
+
+    #[test]
+    fn test_max_metadata_size() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let path = PathBuf::from(root_dir).join("../tests/texture/bootstrap/rafs-v6-2.2.boot");
+        let size = std::fs::metadata(&path).unwrap().len();
+        let mut config = ConfigV2::new("test");
+        config.rafs = Some(RafsConfigV2 {
+            max_metadata_size: 4096,
+            ..Default::default()
+        });
+        let err = RafsSuper::load_from_file(&path, Arc::new(config), false)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains(&format!("size of RAFS metadata file {} ", size)));
+        assert!(err.contains("exceeds the limit 4096"));
+
+        let mut config = ConfigV2::new("test");
+        config.rafs = Some(RafsConfigV2 {
+            max_metadata_size: size,
+            ..Default::default()
+        });
+        RafsSuper::load_from_file(&path, Arc::new(config), false).unwrap();
+    }
 }
@@ -0,0 +1,288 @@
+// Copyright (C) 2023 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Alternative encodings of RAFS metadata files.
+//!
+//! A RAFS metadata file (bootstrap) is normally stored as is, so it can be mmapped and accessed
+//! directly. The [MetadataWriter] may encode the bootstrap stream instead, for example compress
+//! it to reduce the size of metadata to transfer. An encoded bootstrap starts with a header
+//! tagged with [RAFS_META_ENCODED_MAGIC], which is detected and decoded by
+//! [decode_metadata()] transparently when loading the filesystem.
+
+use std::any::Any;
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::io::{Cursor, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+
+use nydus_utils::compress;
+use nydus_utils::filemap::sealed_copy;
+
+use crate::{RafsIoReader, RafsIoWrite, RafsIoWriter};
+
+/// Magic number at the start of encoded RAFS metadata files.
+pub const RAFS_META_ENCODED_MAGIC: [u8; 8] = *b"RAFSMENC";
+
+const RAFS_META_ENCODED_HEADER_SIZE: usize = 32;
+
+/// Header of encoded RAFS metadata files, all fields are in little-endian.
+///
+/// |magic: 8 bytes|compressor: u32|reserved: u32|compressed size: u64|uncompressed size: u64|
+struct EncodedHeader {
+    compressor: compress::Algorithm,
+    compressed_size: u64,
+    uncompressed_size: u64,
+}
+
+impl EncodedHeader {
+    fn to_bytes(&self) -> [u8; RAFS_META_ENCODED_HEADER_SIZE] {
+        let mut buf = [0u8; RAFS_META_ENCODED_HEADER_SIZE];
+        buf[0..8].copy_from_slice(&RAFS_META_ENCODED_MAGIC);
+        buf[8..12].copy_from_slice(&(self.compressor as u32).to_le_bytes());
+        buf[16..24].copy_from_slice(&self.compressed_size.to_le_bytes());
+        buf[24..32].copy_from_slice(&self.uncompressed_size.to_le_bytes());
+        buf
+    }
+
+    /// Parse the header, return `None` if the data is not encoded.
+    fn from_bytes(
+        buf: &[u8; RAFS_META_ENCODED_HEADER_SIZE],
+        max_size: u64,
+    ) -> Result<Option<Self>> {
+        if buf[0..8] != RAFS_META_ENCODED_MAGIC {
+            return Ok(None);
+        }
+
+        let compressor = u32::from_le_bytes(buf[8..12].try_into().unwrap());
+        let compressor = compress::Algorithm::try_from(compressor).map_err(|_| {
+            einval!(format!(
+                "invalid compressor {} for encoded RAFS metadata",
+                compressor
+            ))
+        })?;
+        let compressed_size = u64::from_le_bytes(buf[16..24].try_into().unwrap());
+        let uncompressed_size = u64::from_le_bytes(buf[24..32].try_into().unwrap());
+        if uncompressed_size > max_size
+            || compressed_size > max_size
+            || (compressor.is_none() && compressed_size != uncompressed_size)
+        {
+            return Err(einval!(format!(
+                "invalid size of encoded RAFS metadata, compressed {}, uncompressed {}",
+                compressed_size, uncompressed_size
+            )));
+        }
+
+        Ok(Some(EncodedHeader {
+            compressor,
+            compressed_size,
+            uncompressed_size,
+        }))
+    }
+}
+
+/// A [RafsIoWrite] wrapper to encode RAFS metadata before writing it to the underlying writer.
+///
+/// When encoding is enabled, metadata is buffered in memory because the bootstrap is generated
+/// with random writes, and the encoded stream is written to the underlying writer on
+/// `finalize()`. Otherwise all operations are passed through to the underlying writer.
+pub struct MetadataWriter {
+    inner: RafsIoWriter,
+    compressor: compress::Algorithm,
+    buf: Cursor<Vec<u8>>,
+    encoded: bool,
+}
+
+impl MetadataWriter {
+    /// Create a new instance of [MetadataWriter] to compress metadata with `compressor`.
+    ///
+    /// Metadata is written to `inner` as is if `compressor` is `compress::Algorithm::None`.
+    pub fn new(inner: RafsIoWriter, compressor: compress::Algorithm) -> Self {
+        MetadataWriter {
+            inner,
+            compressor,
+            buf: Cursor::new(Vec::new()),
+            encoded: false,
+        }
+    }
+
+    /// Check whether metadata is encoded by the writer.
+    pub fn is_encoding(&self) -> bool {
+        !self.compressor.is_none()
+    }
+
+    fn encode(&mut self) -> Result<()> {
+        let data = self.buf.get_ref();
+        let (compressed, is_compressed) = compress::compress(data, self.compressor)?;
+        let header = EncodedHeader {
+            compressor: if is_compressed {
+                self.compressor
+            } else {
+                compress::Algorithm::None
+            },
+            compressed_size: compressed.len() as u64,
+            uncompressed_size: data.len() as u64,
+        };
+
+        self.inner.seek_offset(0)?;
+        self.inner.write_all(&header.to_bytes())?;
+        self.inner.write_all(&compressed)?;
+        self.inner.flush()
+    }
+}
+
+impl Write for MetadataWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if self.is_encoding() {
+            self.buf.write(buf)
+        } else {
+            self.inner.write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.is_encoding() {
+            Ok(())
+        } else {
+            self.inner.flush()
+        }
+    }
+}
+
+impl Seek for MetadataWriter {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        if self.is_encoding() {
+            self.buf.seek(pos)
+        } else {
+            self.inner.seek(pos)
+        }
+    }
+}
+
+impl RafsIoWrite for MetadataWriter {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn finalize(&mut self, name: Option<String>) -> anyhow::Result<()> {
+        if self.is_encoding() && !self.encoded {
+            self.encode()?;
+            self.encoded = true;
+        }
+        self.inner.finalize(name)
+    }
+
+    /// Return the metadata before encoding.
+    fn as_bytes(&mut self) -> Result<Cow<[u8]>> {
+        if self.is_encoding() {
+            Ok(Cow::Borrowed(self.buf.get_ref().as_slice()))
+        } else {
+            self.inner.as_bytes()
+        }
+    }
+}
+
+/// Decode RAFS metadata from `reader` if it's encoded by [MetadataWriter].
+///
+/// The reader is left untouched, except being rewound to the start, if metadata is not encoded.
+/// Otherwise it's replaced by a sealed in-memory file containing the decoded metadata, which
+/// should not exceed `max_size`, so the metadata can still be mmapped in direct mode.
+pub fn decode_metadata(reader: &mut RafsIoReader, max_size: u64) -> Result<()> {
+    let mut buf = [0u8; RAFS_META_ENCODED_HEADER_SIZE];
+    reader.seek_to_offset(0)?;
+    let header = match reader.read_exact(&mut buf) {
+        Ok(()) => EncodedHeader::from_bytes(&buf, max_size)?,
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => None,
+        Err(e) => return Err(e),
+    };
+    let header = match header {
+        Some(v) => v,
+        None => {
+            reader.seek_to_offset(0)?;
+            return Ok(());
+        }
+    };
+
+    let mut compressed = vec![0u8; header.compressed_size as usize];
+    reader.read_exact(&mut compressed)?;
+    let mut data = vec![0u8; header.uncompressed_size as usize];
+    let size = compress::decompress(&compressed, &mut data, header.compressor)?;
+    if size != data.len() {
+        return Err(eio!(format!(
+            "failed to decode RAFS metadata, expect {} bytes, got {}",
+            data.len(),
+            size
+        )));
+    }
+
+    *reader = Box::new(sealed_copy(&data)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{RafsSuper, RAFS_MAX_METADATA_SIZE};
+    use nydus_api::ConfigV2;
+    use std::fs::{self, File};
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use vmm_sys_util::tempfile::TempFile;
+
+    #[test]
+    fn test_write_and_load_compressed_bootstrap() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let path = PathBuf::from(root_dir).join("../tests/texture/bootstrap/rafs-v5.boot");
+        let data = fs::read(&path).unwrap();
+
+        let tmp_file = TempFile::new().unwrap();
+        let file = tmp_file.as_file().try_clone().unwrap();
+        let mut writer = MetadataWriter::new(Box::new(file), compress::Algorithm::Zstd);
+        assert!(writer.is_encoding());
+        // Metadata is generated with random writes.
+        writer.seek_offset(0x100).unwrap();
+        writer.write_all(&data[0x100..]).unwrap();
+        writer.seek_offset(0).unwrap();
+        writer.write_all(&data[..0x100]).unwrap();
+        assert_eq!(writer.as_bytes().unwrap().as_ref(), data.as_slice());
+        writer.finalize(None).unwrap();
+
+        let encoded = fs::read(tmp_file.as_path()).unwrap();
+        assert_eq!(encoded[..8], RAFS_META_ENCODED_MAGIC);
+        assert!(encoded.len() < data.len());
+
+        let config = Arc::new(ConfigV2::new("test"));
+        let (rs1, _) = RafsSuper::load_from_file(&path, config.clone(), false).unwrap();
+        let (rs2, _) = RafsSuper::load_from_file(tmp_file.as_path(), config, false).unwrap();
+        assert_eq!(rs1.meta.inodes_count, rs2.meta.inodes_count);
+        assert_eq!(rs1.superblock.root_ino(), rs2.superblock.root_ino());
+        let ino = rs2.ino_from_path(Path::new("/etc")).unwrap();
+        assert_eq!(ino, rs1.ino_from_path(Path::new("/etc")).unwrap());
+        let attr1 = rs1.get_inode(ino, false).unwrap().get_attr();
+        let attr2 = rs2.get_inode(ino, false).unwrap().get_attr();
+        assert_eq!(attr1.mode, attr2.mode);
+        assert_eq!(attr1.size, attr2.size);
+    }
+
+    #[test]
+    fn test_decode_plain_and_invalid_metadata() {
+        let tmp_file = TempFile::new().unwrap();
+        fs::write(tmp_file.as_path(), b"plain").unwrap();
+        let file = File::open(tmp_file.as_path()).unwrap();
+        let mut reader = Box::new(file) as RafsIoReader;
+        reader.seek_to_offset(3).unwrap();
+        decode_metadata(&mut reader, RAFS_MAX_METADATA_SIZE as u64).unwrap();
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"plain");
+
+        let header = EncodedHeader {
+            compressor: compress::Algorithm::None,
+            compressed_size: 0x10,
+            uncompressed_size: 0x20,
+        };
+        fs::write(tmp_file.as_path(), header.to_bytes()).unwrap();
+        let file = File::open(tmp_file.as_path()).unwrap();
+        let mut reader = Box::new(file) as RafsIoReader;
+        assert!(decode_metadata(&mut reader, RAFS_MAX_METADATA_SIZE as u64).is_err());
+    }
+}
@@ -254,6 +254,46 @@ impl InodeWrapper {
         }
     }
 
+    /// Check whether the inode has an extended record, only for RAFS v5.
+    pub fn has_extended(&self) -> bool {
+        match self {
+            InodeWrapper::V5(i) => i.has_extended(),
+            InodeWrapper::V6(_i) => false,
+            InodeWrapper::Ref(i) => i.flags() & RafsInodeFlags::EXTENDED.bits() != 0,
+        }
+    }
+
+    /// Set whether the inode has an extended record, only for RAFS v5.
+    pub fn set_has_extended(&mut self, enable: bool) {
+        self.ensure_owned();
+        if let InodeWrapper::V5(i) = self {
+            if enable {
+                i.i_flags |= RafsInodeFlags::EXTENDED;
+            } else {
+                i.i_flags &= !RafsInodeFlags::EXTENDED;
+            }
+        }
+    }
+
+    /// Check whether file content is stored inline in the inode, only for RAFS v5.
+    pub fn has_inline_data(&self) -> bool {
+        match self {
+            InodeWrapper::V5(i) => i.has_inline_data(),
+            InodeWrapper::V6(_i) => false,
+            InodeWrapper::Ref(i) => i.flags() & RafsInodeFlags::INLINE_DATA.bits() != 0,
+        }
+    }
+
+    /// Set size of file content stored inline in the inode, only for RAFS v5.
+    pub fn set_inline_data_size(&mut self, size: usize) {
+        debug_assert!(size <= u16::MAX as usize);
+        self.ensure_owned();
+        if let InodeWrapper::V5(i) = self {
+            i.i_flags |= RafsInodeFlags::INLINE_DATA;
+            i.i_symlink_size = size as u16;
+        }
+    }
+
     /// Get inode number.
     pub fn ino(&self) -> Inode {
         match self {
@@ -502,7 +542,10 @@ impl InodeWrapper {
         match self {
             InodeWrapper::V5(i) => i.i_symlink_size,
             InodeWrapper::V6(i) => i.i_symlink_size,
-            InodeWrapper::Ref(i) => i.get_symlink_size(),
+            InodeWrapper::Ref(i) => i
+                .get_inline_data()
+                .map(|v| v.len() as u16)
+                .unwrap_or_else(|| i.get_symlink_size()),
         }
     }
 
@@ -750,6 +793,10 @@ bitflags! {
         const XATTR = 0x0000_0004;
         /// Inode chunks has holes.
         const HAS_HOLE = 0x0000_0008;
+        /// Inode has an extended record with 64-bit rdev and atime/ctime.
+        const EXTENDED = 0x0000_0010;
+        /// Content of the regular file is stored inline, following the file name.
+        const INLINE_DATA = 0x0000_0020;
    }
 }
 
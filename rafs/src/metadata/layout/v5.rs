@@ -67,6 +67,8 @@ use crate::{
 pub(crate) const RAFSV5_ALIGNMENT: usize = 8;
 pub(crate) const RAFSV5_SUPERBLOCK_SIZE: usize = 8192;
 pub(crate) const RAFSV5_EXT_BLOB_ENTRY_SIZE: usize = 64;
+/// Maximum size of regular files whose content may be stored inline in the inode.
+pub const RAFSV5_MAX_INLINE_DATA_SIZE: usize = 64;
 
 const RAFSV5_SUPER_MAGIC: u32 = 0x5241_4653;
 const RAFSV5_SUPERBLOCK_RESERVED_SIZE: usize = RAFSV5_SUPERBLOCK_SIZE - 80;
@@ -250,6 +252,16 @@ impl RafsV5SuperBlock {
         self.s_flags |= RafsSuperFlags::HAS_XATTR.bits();
     }
 
+    /// Enable support of extended inode records.
+    pub fn set_has_extended_inode(&mut self) {
+        self.s_flags |= RafsSuperFlags::EXTENDED_INODE.bits();
+    }
+
+    /// Enable support of regular files with inlined content.
+    pub fn set_has_inline_data(&mut self) {
+        self.s_flags |= RafsSuperFlags::INLINE_DATA.bits();
+    }
+
     impl_pub_getter_setter!(magic, set_magic, s_magic, u32);
     impl_pub_getter_setter!(version, set_version, s_fs_version, u32);
     impl_pub_getter_setter!(sb_size, set_sb_size, s_sb_size, u32);
@@ -924,9 +936,16 @@ impl RafsV5Inode {
     /// Get on disk size of the inode content.
     #[inline]
     pub fn size(&self) -> usize {
+        let ext_size = if self.has_extended() {
+            size_of::<RafsV5InodeExtended>()
+        } else {
+            0
+        };
+
         size_of::<Self>()
             + (rafsv5_align(self.i_name_size as usize) + rafsv5_align(self.i_symlink_size as usize))
                 as usize
+            + ext_size
     }
 
     /// Get the uid and the gid of the inode.
@@ -1008,6 +1027,18 @@ impl RafsV5Inode {
         self.i_flags.contains(RafsInodeFlags::HAS_HOLE)
     }
 
+    /// Check whether the inode has an extended record following the symlink target.
+    #[inline]
+    pub fn has_extended(&self) -> bool {
+        self.i_flags.contains(RafsInodeFlags::EXTENDED)
+    }
+
+    /// Check whether file content is stored inline, in place of the symlink target.
+    #[inline]
+    pub fn has_inline_data(&self) -> bool {
+        self.is_reg() && self.i_flags.contains(RafsInodeFlags::INLINE_DATA)
+    }
+
     /// Load an inode from a reader.
     pub fn load(&mut self, r: &mut RafsIoReader) -> Result<()> {
         r.read_exact(self.as_mut())
@@ -1043,7 +1074,10 @@ impl From<&dyn RafsInodeExt> for RafsV5Inode {
             i_child_index: inode.get_child_index().unwrap_or(0),
             i_child_count: inode.get_child_count(),
             i_name_size: inode.get_name_size(),
-            i_symlink_size: inode.get_symlink_size(),
+            i_symlink_size: inode
+                .get_inline_data()
+                .map(|v| v.len() as u16)
+                .unwrap_or_else(|| inode.get_symlink_size()),
             i_rdev: attr.rdev,
             i_mtime_nsec: attr.mtimensec,
             i_mtime: attr.mtime,
@@ -1052,11 +1086,46 @@ impl From<&dyn RafsInodeExt> for RafsV5Inode {
     }
 }
 
+/// Rafs v5 extended inode record, 32 bytes.
+///
+/// The record is stored after the file name and symlink target of inodes with the
+/// `RafsInodeFlags::EXTENDED` flag set, to carry information which doesn't fit into `RafsV5Inode`.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct RafsV5InodeExtended {
+    /// Device ID for special files, without truncation to 32 bits.
+    pub i_rdev: u64,
+    /// Time of last access - second part.
+    pub i_atime: u64,
+    /// Time of last status change - second part.
+    pub i_ctime: u64,
+    /// Time of last access - nanoseconds part.
+    pub i_atime_nsec: u32,
+    /// Time of last status change - nanoseconds part.
+    pub i_ctime_nsec: u32,
+}
+
+impl RafsV5InodeExtended {
+    /// Create a new instance of `RafsV5InodeExtended`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load an extended inode record from a reader.
+    pub fn load(&mut self, r: &mut RafsIoReader) -> Result<()> {
+        r.read_exact(self.as_mut())
+    }
+}
+
+impl_bootstrap_converter!(RafsV5InodeExtended);
+
 /// A in-memory wrapper of a Rafs v5 inode.
 pub struct RafsV5InodeWrapper<'a> {
     pub name: &'a OsStr,
     pub symlink: Option<&'a OsStr>,
     pub inode: &'a RafsV5Inode,
+    /// Extended record to store if the inode has the `RafsInodeFlags::EXTENDED` flag set.
+    pub extended: Option<&'a RafsV5InodeExtended>,
 }
 
 impl<'a> RafsStore for RafsV5InodeWrapper<'a> {
@@ -1083,6 +1152,14 @@ impl<'a> RafsStore for RafsV5InodeWrapper<'a> {
             size += padding;
         }
 
+        if self.inode.has_extended() {
+            let extended = self
+                .extended
+                .ok_or_else(|| einval!("missing extended record for inode"))?;
+            w.write_all(extended.as_ref())?;
+            size += size_of::<RafsV5InodeExtended>();
+        }
+
         w.validate_alignment(size, RAFSV5_ALIGNMENT)
     }
 }
@@ -1234,9 +1311,13 @@ pub(crate) fn rafsv5_alloc_bio_vecs<I: RafsInode + RafsV5InodeChunkOps + RafsV5I
     size: usize,
     user_io: bool,
 ) -> Result<Vec<BlobIoVec>> {
-    let end = offset
-        .checked_add(size as u64)
-        .ok_or_else(|| einval!("invalid read size"))?;
+    // Reading at or beyond EOF yields nothing, as read(2) does. Clamp the end of the range to
+    // the file size so a huge `size` can't wrap around or reach chunks beyond EOF.
+    let i_size = inode.size();
+    if size == 0 || offset >= i_size {
+        return Ok(vec![]);
+    }
+    let end = cmp::min(offset.saturating_add(size as u64), i_size);
     let (index_start, index_end) = calculate_bio_chunk_index(
         offset,
         end,
@@ -1248,31 +1329,37 @@ pub(crate) fn rafsv5_alloc_bio_vecs<I: RafsInode + RafsV5InodeChunkOps + RafsV5I
         "alloc bio desc offset {} size {} i_size {} index_start {} index_end {} i_child_count {}",
         offset,
         size,
-        inode.size(),
+        i_size,
         index_start,
         index_end,
         inode.get_child_count()
     );
-    if size == 0 || index_start >= inode.get_chunk_count() {
+    if index_start >= inode.get_chunk_count() {
         return Ok(vec![]);
     }
 
     let chunk = inode.get_chunk_info_v5(index_start)?;
-    let blob = inode.get_blob_by_index(chunk.blob_index())?;
+    let mut blob_index = chunk.blob_index();
+    let mut blob = rafsv5_get_chunk_blob(inode, chunk.as_ref())?;
     let mut desc = BlobIoVec::new(blob.clone());
-    if !add_chunk_to_bio_desc(&mut desc, offset, end, chunk, blob, user_io) {
+    if !add_chunk_to_bio_desc(&mut desc, offset, end, chunk, blob.clone(), user_io) {
         return Err(einval!("failed to create blob io vector"));
     }
 
     let mut descs = Vec::with_capacity(4);
     for idx in index_start + 1..index_end {
         let chunk = inode.get_chunk_info_v5(idx)?;
-        let blob = inode.get_blob_by_index(chunk.blob_index())?;
+        // Consecutive chunks are mostly stored in the same blob, so only look up the blob table
+        // when switching to another blob.
+        if chunk.blob_index() != blob_index {
+            blob_index = chunk.blob_index();
+            blob = rafsv5_get_chunk_blob(inode, chunk.as_ref())?;
+        }
         if blob.blob_index() != desc.blob_index() {
             descs.push(desc);
             desc = BlobIoVec::new(blob.clone());
         }
-        if !add_chunk_to_bio_desc(&mut desc, offset, end, chunk, blob, user_io) {
+        if !add_chunk_to_bio_desc(&mut desc, offset, end, chunk, blob.clone(), user_io) {
             return Err(einval!("failed to create blob io vector"));
         }
     }
@@ -1281,6 +1368,21 @@ pub(crate) fn rafsv5_alloc_bio_vecs<I: RafsInode + RafsV5InodeChunkOps + RafsV5I
     Ok(descs)
 }
 
+// A malformed image may have chunks referencing blobs beyond the blob table, fail the IO instead.
+fn rafsv5_get_chunk_blob<I: RafsInode + RafsV5InodeOps>(
+    inode: &I,
+    chunk: &dyn BlobV5ChunkInfo,
+) -> Result<Arc<BlobInfo>> {
+    inode.get_blob_by_index(chunk.blob_index()).map_err(|e| {
+        eio!(format!(
+            "chunk of inode {} references invalid blob index {}, {}",
+            inode.ino(),
+            chunk.blob_index(),
+            e
+        ))
+    })
+}
+
 /// Add a new bio covering the IO range into the provided bio desc.
 ///
 /// Returns true if caller should continue checking more chunks.
@@ -1397,6 +1499,8 @@ pub(crate) fn rafsv5_validate_inode(
 
     if inode.is_symlink() {
         hasher.digest_update(inode.get_symlink()?.as_bytes());
+    } else if let Some(data) = inode.get_inline_data() {
+        hasher.digest_update(data);
     } else if inode.is_reg() {
         for idx in 0..child_count {
             let chunk = inode.get_chunk_info(idx)?;
@@ -1913,6 +2017,7 @@ pub mod tests {
             name: &name,
             symlink: Some(&symlink),
             inode: &inode,
+            extended: None,
         };
 
         let tmp_file = TempFile::new().unwrap();
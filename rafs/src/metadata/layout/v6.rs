@@ -2566,6 +2566,42 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_rafs_v6_super_block_ext_digester() {
+        for algo in [digest::Algorithm::Blake3, digest::Algorithm::Sha256] {
+            let temp = TempFile::new().unwrap();
+            let w = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(temp.as_path())
+                .unwrap();
+            let r = OpenOptions::new()
+                .read(true)
+                .write(false)
+                .open(temp.as_path())
+                .unwrap();
+            let mut writer = BufWriter::new(w);
+            let mut reader: Box<dyn RafsIoRead> = Box::new(r);
+
+            let sb = RafsV6SuperBlock::new();
+            sb.store(&mut writer).unwrap();
+            let mut ext = RafsV6SuperBlockExt::new();
+            ext.set_compressor(compress::Algorithm::Zstd);
+            ext.set_digester(digest::Algorithm::from_str(algo.as_str()).unwrap());
+            writer
+                .seek_offset((EROFS_SUPER_OFFSET + EROFS_SUPER_BLOCK_SIZE) as u64)
+                .unwrap();
+            ext.store(&mut writer).unwrap();
+            writer.flush().unwrap();
+
+            let mut ext2 = RafsV6SuperBlockExt::new();
+            ext2.load(&mut reader).unwrap();
+            let flags = RafsSuperFlags::from_bits(ext2.flags()).unwrap();
+            assert_eq!(digest::Algorithm::from(flags), algo);
+            assert_eq!(digest::Algorithm::from(flags).to_string(), algo.as_str());
+        }
+    }
+
     #[test]
     fn test_rafs_v6_inode_compact() {
         let mut cpt = RafsV6InodeCompact::new();
@@ -39,9 +39,10 @@ impl RafsSuper {
         self.meta.extended_blob_table_entries = sb.extended_blob_table_entries();
         self.meta.prefetch_table_entries = sb.prefetch_table_entries();
         self.meta.prefetch_table_offset = sb.prefetch_table_offset();
+        self.init_generation(r, &[sb.as_ref()])?;
 
         match self.mode {
-            RafsMode::Direct => {
+            RafsMode::Direct | RafsMode::Auto => {
                 let mut inodes = DirectSuperBlockV5::new(&self.meta, self.validate_digest);
                 inodes.load(r)?;
                 self.superblock = Arc::new(inodes);
@@ -249,6 +250,10 @@ impl BlobChunkInfo for V5IoChunk {
         false
     }
 
+    fn compressor(&self) -> Option<compress::Algorithm> {
+        self.flags.compressor()
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -57,9 +57,10 @@ impl RafsSuper {
             self.meta.prefetch_table_offset,
             self.meta.prefetch_table_entries
         );
+        self.init_generation(r, &[sb.as_ref(), ext_sb.as_ref()])?;
 
         match self.mode {
-            RafsMode::Direct => {
+            RafsMode::Direct | RafsMode::Auto => {
                 let mut sb_v6 = DirectSuperBlockV6::new(&self.meta);
                 sb_v6.load(r)?;
                 self.superblock = Arc::new(sb_v6);
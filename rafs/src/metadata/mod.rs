@@ -10,7 +10,7 @@ use std::collections::{HashMap, HashSet};
 use std::convert::{TryFrom, TryInto};
 use std::ffi::{OsStr, OsString};
 use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
-use std::fs::OpenOptions;
+use std::fs::{File, OpenOptions};
 use std::io::{Error, ErrorKind, Result};
 use std::os::unix::ffi::OsStrExt;
 use std::path::{Component, Path, PathBuf};
@@ -27,11 +27,11 @@ use nydus_storage::device::{
     BlobChunkInfo, BlobDevice, BlobFeatures, BlobInfo, BlobIoMerge, BlobIoVec,
 };
 use nydus_storage::meta::toc::TocEntryList;
-use nydus_utils::digest::{self, RafsDigest};
-use nydus_utils::{compress, crypt};
+use nydus_utils::digest::{self, DigestHasher, RafsDigest};
+use nydus_utils::{compress, crypt, signature};
 use serde::Serialize;
 
-use self::layout::v5::RafsV5PrefetchTable;
+use self::layout::v5::{RafsV5InodeExtended, RafsV5PrefetchTable};
 use self::layout::v6::RafsV6PrefetchTable;
 use self::layout::{XattrName, XattrValue, RAFS_SUPER_VERSION_V5, RAFS_SUPER_VERSION_V6};
 use self::noop::NoopSuperBlock;
@@ -46,6 +46,7 @@ pub mod cached_v5;
 pub mod chunk;
 pub mod direct_v5;
 pub mod direct_v6;
+pub mod encoding;
 pub mod inode;
 pub mod layout;
 
@@ -58,8 +59,10 @@ pub const RAFS_BLOB_ID_MAX_LENGTH: usize = 64;
 pub const RAFS_ATTR_BLOCK_SIZE: u32 = 4096;
 /// Maximum size of file name supported by RAFS.
 pub const RAFS_MAX_NAME: usize = 255;
-/// Maximum size of RAFS filesystem metadata blobs.
+/// Default maximum size of RAFS filesystem metadata blobs.
 pub const RAFS_MAX_METADATA_SIZE: usize = 0x8000_0000;
+/// Default size threshold of metadata files to select cached mode in auto mode.
+pub const RAFS_AUTO_MODE_THRESHOLD: u64 = 0x100_0000;
 /// File name for Unix current directory.
 pub const DOT: &str = ".";
 /// File name for Unix parent directory.
@@ -98,6 +101,13 @@ pub trait RafsSuperBlock: RafsSuperInodes + Send + Sync {
     /// Update/reload the RAFS filesystem super block from the specified reader.
     fn update(&self, r: &mut RafsIoReader) -> RafsResult<()>;
 
+    /// Switch to a new metadata file described by `meta`, with compatible layout.
+    ///
+    /// The switch is atomic, inode objects got before switching keep accessing the old metadata.
+    fn reload(&self, _meta: &RafsSuperMeta, _r: &mut RafsIoReader) -> RafsResult<()> {
+        Err(RafsError::Unsupported)
+    }
+
     /// Destroy the RAFS filesystem super block object.
     fn destroy(&mut self);
 
@@ -242,6 +252,11 @@ pub trait RafsInode: Any {
     /// Regular: get number of data chunks.
     fn get_chunk_count(&self) -> u32;
 
+    /// Regular: get file content stored inline in the inode, if any.
+    fn get_inline_data(&self) -> Option<&[u8]> {
+        None
+    }
+
     fn as_any(&self) -> &dyn Any;
 }
 
@@ -267,6 +282,50 @@ pub trait RafsInodeExt: RafsInode {
 
     /// RAFS v5: get chunk info object by chunk index, chunk index starts from 0.
     fn get_chunk_info(&self, idx: u32) -> Result<Arc<dyn BlobChunkInfo>>;
+
+    /// RAFS v5: get the extended inode record, if the inode has one.
+    fn get_extended(&self) -> Option<RafsV5InodeExtended> {
+        None
+    }
+
+    /// Regular: get location of data chunks in data blobs, without reading chunk data.
+    ///
+    /// The `blobs` should be the blob table of the filesystem the inode belongs to.
+    fn chunk_layout(&self, blobs: &[Arc<BlobInfo>]) -> Result<Vec<ChunkLayout>> {
+        if !self.is_reg() {
+            return Err(einval!(format!(
+                "inode {} is not a regular file",
+                self.ino()
+            )));
+        }
+
+        let count = self.get_chunk_count();
+        let mut layout = Vec::with_capacity(count as usize);
+        for idx in 0..count {
+            let chunk = self.get_chunk_info(idx)?;
+            let blob = blobs
+                .get(chunk.blob_index() as usize)
+                .ok_or_else(|| einval!(format!("invalid blob index {}", chunk.blob_index())))?;
+            layout.push(ChunkLayout {
+                blob_id: blob.blob_id(),
+                compressed_offset: chunk.compressed_offset(),
+                compressed_size: chunk.compressed_size(),
+            });
+        }
+
+        Ok(layout)
+    }
+}
+
+/// Location of a data chunk in a data blob.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ChunkLayout {
+    /// Id of the data blob containing the chunk.
+    pub blob_id: String,
+    /// Offset of compressed chunk data in the data blob.
+    pub compressed_offset: u64,
+    /// Size of compressed chunk data.
+    pub compressed_size: u32,
 }
 
 /// Trait to write out RAFS filesystem meta objects into the metadata blob.
@@ -301,6 +360,10 @@ bitflags! {
         const INLINED_CHUNK_DIGEST = 0x0000_0100;
         /// RAFS works in Tarfs mode, which directly uses tar streams as data blobs.
         const TARTFS_MODE = 0x0000_0200;
+        /// RAFS v5 inodes may have an extended record with 64-bit rdev and atime/ctime.
+        const EXTENDED_INODE = 0x0000_0400;
+        /// Content of small RAFS v5 regular files may be inlined into their inodes.
+        const INLINE_DATA = 0x0000_0800;
         /// Data chunks are not encrypted.
         const ENCRYPTION_NONE = 0x0100_0000;
         /// Data chunks are encrypted with AES-128-XTS.
@@ -510,6 +573,12 @@ pub struct RafsSuperMeta {
     pub entry_timeout: Duration,
     /// Whether the RAFS instance is a chunk dictionary.
     pub is_chunk_dict: bool,
+    /// Whether to prefault and lock metadata in memory.
+    pub mlock_metadata: bool,
+    /// Maximum size of the metadata file.
+    pub max_metadata_size: u64,
+    /// Size threshold of the metadata file to select cached mode in auto mode.
+    pub auto_mode_threshold: u64,
     /// Metadata block address for RAFS v6.
     pub meta_blkaddr: u32,
     /// Root nid for RAFS v6.
@@ -518,6 +587,8 @@ pub struct RafsSuperMeta {
     pub chunk_table_offset: u64,
     /// Size  of the chunk table for RAFS v6.
     pub chunk_table_size: u64,
+    /// Generation number of the filesystem, derived from the superblock and the blob table.
+    pub generation: u64,
 }
 
 impl RafsSuperMeta {
@@ -536,6 +607,28 @@ impl RafsSuperMeta {
         self.is_chunk_dict
     }
 
+    /// Posix: get generation number of inode `ino`.
+    ///
+    /// Inode numbers are reused by different images, so the generation number changes with the
+    /// filesystem metadata and NFS file handles referring to another image become stale.
+    pub fn get_generation(&self, ino: Inode) -> u64 {
+        self.generation ^ ino
+    }
+
+    /// Check whether size of the metadata file is within the `max_metadata_size` limit.
+    pub fn check_metadata_size(&self, size: u64) -> Result<()> {
+        if size > self.max_metadata_size {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "size of RAFS metadata file {} exceeds the limit {}, please increase `max_metadata_size`",
+                    size, self.max_metadata_size
+                ),
+            ));
+        }
+        Ok(())
+    }
+
     /// Check whether the explicit UID/GID feature has been enable or not.
     pub fn explicit_uidgid(&self) -> bool {
         self.flags.contains(RafsSuperFlags::EXPLICIT_UID_GID)
@@ -546,6 +639,16 @@ impl RafsSuperMeta {
         self.flags.contains(RafsSuperFlags::HAS_XATTR)
     }
 
+    /// Check whether RAFS v5 inodes may have an extended record.
+    pub fn has_extended_inode(&self) -> bool {
+        self.flags.contains(RafsSuperFlags::EXTENDED_INODE)
+    }
+
+    /// Check whether RAFS v5 regular files may have inlined content.
+    pub fn has_inline_data(&self) -> bool {
+        self.flags.contains(RafsSuperFlags::INLINE_DATA)
+    }
+
     /// Check whether data blobs have inlined chunk digest array.
     pub fn has_inlined_chunk_digest(&self) -> bool {
         self.is_v6() && self.flags.contains(RafsSuperFlags::INLINED_CHUNK_DIGEST)
@@ -618,8 +721,12 @@ impl Default for RafsSuperMeta {
             meta_blkaddr: 0,
             root_nid: 0,
             is_chunk_dict: false,
+            mlock_metadata: false,
+            max_metadata_size: RAFS_MAX_METADATA_SIZE as u64,
+            auto_mode_threshold: RAFS_AUTO_MODE_THRESHOLD,
             chunk_table_offset: 0,
             chunk_table_size: 0,
+            generation: 0,
         }
     }
 }
@@ -685,6 +792,8 @@ pub enum RafsMode {
     Direct,
     /// Read metadata into memory before using, for RAFS v5.
     Cached,
+    /// Select `Cached` or `Direct` mode according to size of the metadata when loading.
+    Auto,
 }
 
 impl FromStr for RafsMode {
@@ -694,7 +803,8 @@ impl FromStr for RafsMode {
         match s {
             "direct" => Ok(Self::Direct),
             "cached" => Ok(Self::Cached),
-            _ => Err(einval!("rafs mode should be direct or cached")),
+            "auto" => Ok(Self::Auto),
+            _ => Err(einval!("rafs mode should be direct, cached or auto")),
         }
     }
 }
@@ -704,6 +814,7 @@ impl Display for RafsMode {
         match self {
             Self::Direct => write!(f, "direct"),
             Self::Cached => write!(f, "cached"),
+            Self::Auto => write!(f, "auto"),
         }
     }
 }
@@ -748,11 +859,21 @@ impl RafsSuper {
             .destroy();
     }
 
-    /// Load Rafs super block from a metadata file.
+    /// Load Rafs super block from a metadata file in direct mode.
     pub fn load_from_file<P: AsRef<Path>>(
         path: P,
         config: Arc<ConfigV2>,
         is_chunk_dict: bool,
+    ) -> Result<(Self, RafsIoReader)> {
+        Self::load_from_file_with_mode(path, config, is_chunk_dict, RafsMode::Direct)
+    }
+
+    /// Load Rafs super block from a metadata file in `mode`.
+    pub(crate) fn load_from_file_with_mode<P: AsRef<Path>>(
+        path: P,
+        config: Arc<ConfigV2>,
+        is_chunk_dict: bool,
+        mode: RafsMode,
     ) -> Result<(Self, RafsIoReader)> {
         let validate_digest = config
             .rafs
@@ -760,17 +881,39 @@ impl RafsSuper {
             .map(|rafs| rafs.validate)
             .unwrap_or_default();
         let mut rs = RafsSuper {
-            mode: RafsMode::Direct,
+            mode: mode.clone(),
             validate_digest,
             ..Default::default()
         };
         rs.meta.is_chunk_dict = is_chunk_dict;
+        rs.meta.mlock_metadata = config
+            .rafs
+            .as_ref()
+            .map(|rafs| rafs.mlock_metadata)
+            .unwrap_or_default();
+        let max_metadata_size = config
+            .rafs
+            .as_ref()
+            .map(|rafs| rafs.max_metadata_size)
+            .unwrap_or_default();
+        if max_metadata_size > 0 {
+            rs.meta.max_metadata_size = max_metadata_size;
+        }
+        let auto_mode_threshold = config
+            .rafs
+            .as_ref()
+            .map(|rafs| rafs.auto_mode_threshold)
+            .unwrap_or_default();
+        if auto_mode_threshold > 0 {
+            rs.meta.auto_mode_threshold = auto_mode_threshold;
+        }
 
-        // open bootstrap file
-        let file = OpenOptions::new()
-            .read(true)
-            .write(false)
-            .open(path.as_ref())?;
+        let trusted_key = config
+            .rafs
+            .as_ref()
+            .map(|rafs| rafs.trusted_key.as_str())
+            .unwrap_or_default();
+        let file = rs.open_metadata_file(path.as_ref(), trusted_key)?;
         let mut reader = Box::new(file) as RafsIoReader;
         let mut blob_accessible = config.internal.blob_accessible();
 
@@ -785,6 +928,8 @@ impl RafsSuper {
             };
             let file = OpenOptions::new().read(true).write(false).open(new_path)?;
             reader = Box::new(file) as RafsIoReader;
+            // The failed attempt may have resolved the auto mode already.
+            rs.mode = mode;
             rs.load(&mut reader)?;
             rs.set_blob_id_from_meta_path(path.as_ref())?;
             blob_accessible = true;
@@ -815,11 +960,31 @@ impl RafsSuper {
 
     /// Load RAFS metadata and optionally cache inodes.
     pub(crate) fn load(&mut self, r: &mut RafsIoReader) -> Result<()> {
+        encoding::decode_metadata(r, self.meta.max_metadata_size)?;
+        let auto_mode = self.mode == RafsMode::Auto;
+        if auto_mode {
+            let size = r.seek_to_end(0)?;
+            self.mode = if size <= self.meta.auto_mode_threshold {
+                RafsMode::Cached
+            } else {
+                RafsMode::Direct
+            };
+            info!(
+                "select {} mode for RAFS metadata of {} bytes",
+                self.mode, size
+            );
+        }
+
         // Try to load the filesystem as Rafs v5
         if self.try_load_v5(r)? {
             return Ok(());
         }
 
+        // Rafs v6 only supports direct mode.
+        if auto_mode {
+            self.mode = RafsMode::Direct;
+        }
+
         if self.try_load_v6(r)? {
             return Ok(());
         }
@@ -827,6 +992,28 @@ impl RafsSuper {
         Err(Error::new(ErrorKind::Other, "invalid RAFS superblock"))
     }
 
+    /// Derive the generation number from the raw superblock and the blob table.
+    ///
+    /// Blob ids are digests of data blobs, so the generation changes with file content without
+    /// reading the whole metadata file.
+    pub(crate) fn init_generation(
+        &mut self,
+        r: &mut RafsIoReader,
+        superblocks: &[&[u8]],
+    ) -> Result<()> {
+        let mut hasher = RafsDigest::hasher(digest::Algorithm::Blake3);
+        for sb in superblocks {
+            hasher.digest_update(sb);
+        }
+        let mut blob_table = vec![0u8; self.meta.blob_table_size as usize];
+        r.seek_to_offset(self.meta.blob_table_offset)?;
+        r.read_exact(&mut blob_table)?;
+        hasher.digest_update(&blob_table);
+        let digest = hasher.digest_finalize();
+        self.meta.generation = u64::from_le_bytes(digest.data[..8].try_into().unwrap());
+        Ok(())
+    }
+
     /// Set meta blob file path from which the `RafsSuper` object is loaded from.
     ///
     /// It's used to support inlined-meta and ZRan blobs.
@@ -854,6 +1041,8 @@ impl RafsSuper {
 
     /// Update the filesystem metadata and storage backend.
     pub fn update(&self, r: &mut RafsIoReader) -> RafsResult<()> {
+        encoding::decode_metadata(r, self.meta.max_metadata_size)
+            .map_err(RafsError::FillSuperBlock)?;
         if self.meta.is_v5() {
             self.skip_v5_superblock(r)
                 .map_err(RafsError::FillSuperBlock)?;
@@ -862,6 +1051,83 @@ impl RafsSuper {
         self.superblock.update(r)
     }
 
+    /// Open the metadata file `path` to load or reload metadata from it.
+    ///
+    /// If `trusted_key` is not empty, the file must be signed by the private key matching the
+    /// public key at `trusted_key`. Metadata is then loaded from a verified copy instead of the
+    /// file, which may change later.
+    pub(crate) fn open_metadata_file(&self, path: &Path, trusted_key: &str) -> Result<File> {
+        let mut file = OpenOptions::new().read(true).write(false).open(path)?;
+        if !trusted_key.is_empty() {
+            let key = signature::load_public_key(Path::new(trusted_key))?;
+            let max_size = self.meta.max_metadata_size + signature::SIGNATURE_TRAILER_SIZE as u64;
+            file = signature::verify_file(&mut file, &key, max_size).map_err(|e| {
+                error!(
+                    "failed to verify signature of RAFS metadata {}, {}",
+                    path.display(),
+                    e
+                );
+                e
+            })?;
+        }
+        Ok(file)
+    }
+
+    /// Atomically switch to a new version of the filesystem metadata.
+    ///
+    /// The new metadata must have the same format, feature flags, chunk size and root inode,
+    /// and reference the same data blobs as the current one. Return a `RafsSuper` object sharing
+    /// the switched super block, with superblock metadata of the new version.
+    pub fn reload(&self, r: &mut RafsIoReader) -> RafsResult<RafsSuper> {
+        let mut rs = RafsSuper {
+            mode: self.mode.clone(),
+            validate_digest: self.validate_digest,
+            ..Default::default()
+        };
+        rs.meta.max_metadata_size = self.meta.max_metadata_size;
+        rs.load(r).map_err(RafsError::FillSuperBlock)?;
+
+        let (old, new) = (&self.meta, &rs.meta);
+        if old.version != new.version
+            || old.flags != new.flags
+            || old.chunk_size != new.chunk_size
+            || old.meta_blkaddr != new.meta_blkaddr
+            || old.root_nid != new.root_nid
+        {
+            return Err(RafsError::FillSuperBlock(einval!(
+                "incompatible metadata layout to reload"
+            )));
+        }
+        let old_blobs = self.superblock.get_blob_infos();
+        let new_blobs = rs.superblock.get_blob_infos();
+        if old_blobs.len() != new_blobs.len()
+            || old_blobs
+                .iter()
+                .zip(new_blobs.iter())
+                .any(|(a, b)| a.blob_id() != b.blob_id())
+        {
+            return Err(RafsError::FillSuperBlock(einval!(
+                "metadata to reload references different data blobs"
+            )));
+        }
+
+        let mut meta = rs.meta;
+        meta.attr_timeout = old.attr_timeout;
+        meta.entry_timeout = old.entry_timeout;
+        meta.is_chunk_dict = old.is_chunk_dict;
+        meta.mlock_metadata = old.mlock_metadata;
+        meta.max_metadata_size = old.max_metadata_size;
+        meta.auto_mode_threshold = old.auto_mode_threshold;
+        self.superblock.reload(&meta, r)?;
+
+        Ok(RafsSuper {
+            mode: self.mode.clone(),
+            validate_digest: self.validate_digest,
+            meta,
+            superblock: self.superblock.clone(),
+        })
+    }
+
     /// Get the maximum inode number supported by the filesystem instance.
     pub fn get_max_ino(&self) -> Inode {
         self.superblock.get_max_ino()
@@ -881,6 +1147,12 @@ impl RafsSuper {
         self.superblock.get_extended_inode(ino, validate_inode)
     }
 
+    /// Get location of data chunks of the regular file `ino` in data blobs.
+    pub fn get_chunk_layout(&self, ino: Inode) -> Result<Vec<ChunkLayout>> {
+        let inode = self.get_extended_inode(ino, self.validate_digest)?;
+        inode.chunk_layout(&self.superblock.get_blob_infos())
+    }
+
     /// Convert a file path to an inode number.
     pub fn ino_from_path(&self, f: &Path) -> Result<Inode> {
         let root_ino = self.superblock.root_ino();
@@ -1094,6 +1366,17 @@ impl RafsSuper {
         self.do_walk_directory(inode, parent, cb)
     }
 
+    /// Walk through the whole filesystem tree by DFS order, calling `cb` with the absolute path
+    /// and the inode object of each file or directory, starting from the root directory.
+    pub fn walk(
+        &self,
+        cb: &mut dyn FnMut(&Path, &dyn RafsInode) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        self.walk_directory::<PathBuf>(self.superblock.root_ino(), None, &mut |inode, path| {
+            cb(path, inode.as_inode())
+        })
+    }
+
     #[allow(clippy::only_used_in_recursion)]
     fn do_walk_directory<P: AsRef<Path>>(
         &self,
@@ -1120,6 +1403,7 @@ impl RafsSuper {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use vmm_sys_util::tempfile::TempFile;
 
     #[test]
     fn test_rafs_mode() {
@@ -1129,8 +1413,10 @@ mod tests {
         assert!(RafsMode::from_str("Cached").is_err());
         assert_eq!(RafsMode::from_str("direct").unwrap(), RafsMode::Direct);
         assert_eq!(RafsMode::from_str("cached").unwrap(), RafsMode::Cached);
+        assert_eq!(RafsMode::from_str("auto").unwrap(), RafsMode::Auto);
         assert_eq!(&format!("{}", RafsMode::Direct), "direct");
         assert_eq!(&format!("{}", RafsMode::Cached), "cached");
+        assert_eq!(&format!("{}", RafsMode::Auto), "auto");
     }
 
     #[test]
@@ -1234,6 +1520,107 @@ mod tests {
         rs.destroy();
     }
 
+    #[test]
+    fn test_load_signed_bootstrap() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let root_dir = PathBuf::from(root_dir).join("../tests/texture");
+        let source = root_dir.join("bootstrap/rafs-v5.boot");
+        let config = format!(
+            r#"
+            version = 2
+            id = "test"
+            rafs.mode = "direct"
+            rafs.trusted_key = "{}"
+            "#,
+            root_dir.join("signing/ed25519-public.pem").display()
+        );
+        let config = Arc::new(ConfigV2::from_str(&config).unwrap());
+
+        // An unsigned bootstrap is rejected when verification is required.
+        assert!(RafsSuper::load_from_file(&source, config.clone(), false).is_err());
+
+        // The trailer is generated by signing `rafs-v5.boot` with `ed25519-private.pem`.
+        let tmp_file = TempFile::new().unwrap();
+        let mut data = std::fs::read(&source).unwrap();
+        data.extend_from_slice(&std::fs::read(root_dir.join("signing/rafs-v5.boot.sig")).unwrap());
+        std::fs::write(tmp_file.as_path(), &data).unwrap();
+        let (rs, _) = RafsSuper::load_from_file(tmp_file.as_path(), config.clone(), false).unwrap();
+        assert!(rs.ino_from_path(Path::new("/etc")).is_ok());
+        // Signed bootstrap is compatible with loaders not verifying signature.
+        let (rs, _) =
+            RafsSuper::load_from_file(tmp_file.as_path(), Arc::new(ConfigV2::new("test")), false)
+                .unwrap();
+        assert!(rs.ino_from_path(Path::new("/etc")).is_ok());
+
+        // A tampered bootstrap is rejected.
+        data[0x200] ^= 0x1;
+        std::fs::write(tmp_file.as_path(), &data).unwrap();
+        assert!(RafsSuper::load_from_file(tmp_file.as_path(), config, false).is_err());
+    }
+
+    #[test]
+    fn test_load_in_auto_mode() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let root_dir = PathBuf::from(root_dir).join("../tests/texture/bootstrap");
+        let config = r#"
+        version = 2
+        id = "test"
+        rafs.mode = "auto"
+        rafs.auto_mode_threshold = 0
+        "#;
+        let config = Arc::new(ConfigV2::from_str(config).unwrap());
+
+        // load_from_file() always accesses metadata in direct mode.
+        let (rs, _) =
+            RafsSuper::load_from_file(root_dir.join("rafs-v5.boot"), config.clone(), false)
+                .unwrap();
+        assert_eq!(rs.mode, RafsMode::Direct);
+
+        let load = |name: &str| {
+            let path = root_dir.join(name);
+            let (rs, _) =
+                RafsSuper::load_from_file_with_mode(path, config.clone(), false, RafsMode::Auto)
+                    .unwrap();
+            assert!(rs.ino_from_path(Path::new("/")).is_ok());
+            rs.mode
+        };
+        assert_eq!(load("rafs-v5.boot"), RafsMode::Cached);
+        // RAFS v6 only supports direct mode.
+        assert_eq!(load("rafs-v6-2.2.boot"), RafsMode::Direct);
+    }
+
+    #[test]
+    fn test_collect_descendants_inodes_across_modes() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let path = PathBuf::from(root_dir).join("../tests/texture/bootstrap/rafs-v5.boot");
+        let collect = |mode: &str, path_in_image: &str| {
+            let cfg = RafsConfigV2 {
+                mode: mode.into(),
+                ..RafsConfigV2::default()
+            };
+            let mut rs = RafsSuper::new(&cfg).unwrap();
+            let file = OpenOptions::new().read(true).open(&path).unwrap();
+            let mut reader = Box::new(file) as RafsIoReader;
+            rs.load(&mut reader).unwrap();
+            let ino = rs.ino_from_path(Path::new(path_in_image)).unwrap();
+            let mut descendants = Vec::new();
+            rs.get_inode(ino, false)
+                .unwrap()
+                .collect_descendants_inodes(&mut descendants)
+                .unwrap();
+            descendants
+                .iter()
+                .map(|i| (i.ino(), i.size()))
+                .collect::<Vec<_>>()
+        };
+
+        for path_in_image in ["/", "/etc"] {
+            let direct = collect("direct", path_in_image);
+            assert!(!direct.is_empty());
+            assert_eq!(direct, collect("cached", path_in_image));
+        }
+    }
+
     fn get_meta(
         chunk_size: u32,
         explice_uidgid: bool,
@@ -47,6 +47,10 @@ impl MockChunkInfo {
             ..Default::default()
         }
     }
+
+    pub fn set_blob_index(&mut self, blob_index: u32) {
+        self.c_blob_index = blob_index;
+    }
 }
 
 impl BlobChunkInfo for MockChunkInfo {
@@ -8,6 +8,7 @@ use std::collections::HashMap;
 use std::ffi::{OsStr, OsString};
 use std::io::Result;
 use std::os::unix::ffi::OsStrExt;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use fuse_backend_rs::abi::fuse_abi;
@@ -56,6 +57,8 @@ pub struct MockInode {
     i_child: Vec<Arc<MockInode>>,
     i_blob_table: Arc<RafsV5BlobTable>,
     i_meta: Arc<RafsSuperMeta>,
+    // Number of blob table lookups.
+    i_blob_lookups: Arc<AtomicUsize>,
 }
 
 impl MockInode {
@@ -142,7 +145,10 @@ impl RafsInode for MockInode {
 
     #[inline]
     fn get_child_by_index(&self, index: u32) -> Result<Arc<dyn RafsInodeExt>> {
-        Ok(self.i_child[index as usize].clone())
+        match self.i_child.get(index as usize) {
+            Some(child) => Ok(child.clone()),
+            None => Err(einval!("invalid child index")),
+        }
     }
 
     #[inline]
@@ -279,7 +285,10 @@ impl RafsInodeExt for MockInode {
 
     #[inline]
     fn get_chunk_info(&self, idx: u32) -> Result<Arc<dyn BlobChunkInfo>> {
-        Ok(self.i_data[idx as usize].clone())
+        match self.i_data.get(idx as usize) {
+            Some(chunk) => Ok(chunk.clone()),
+            None => Err(einval!("invalid chunk index")),
+        }
     }
 
     fn as_inode(&self) -> &dyn RafsInode {
@@ -291,13 +300,21 @@ impl RafsInodeExt for MockInode {
 
 impl RafsV5InodeChunkOps for MockInode {
     fn get_chunk_info_v5(&self, idx: u32) -> Result<Arc<dyn BlobV5ChunkInfo>> {
-        Ok(self.i_data[idx as usize].clone())
+        match self.i_data.get(idx as usize) {
+            Some(chunk) => Ok(chunk.clone()),
+            None => Err(einval!("invalid chunk index")),
+        }
     }
 }
 
 impl RafsV5InodeOps for MockInode {
-    fn get_blob_by_index(&self, _idx: u32) -> Result<Arc<BlobInfo>> {
-        Ok(Arc::new(BlobInfo::default()))
+    fn get_blob_by_index(&self, idx: u32) -> Result<Arc<BlobInfo>> {
+        self.i_blob_lookups.fetch_add(1, Ordering::Relaxed);
+        if self.i_blob_table.entries.is_empty() {
+            Ok(Arc::new(BlobInfo::default()))
+        } else {
+            self.i_blob_table.get(idx)
+        }
     }
 
     fn get_chunk_size(&self) -> u32 {
@@ -311,9 +328,11 @@ impl RafsV5InodeOps for MockInode {
 
 #[cfg(test)]
 mod tests {
+    use nydus_storage::device::BlobFeatures;
     use nydus_utils::digest::Algorithm;
 
     use crate::metadata::layout::RAFS_V5_ROOT_INODE;
+    use crate::metadata::RafsSuperFlags;
 
     use super::*;
 
@@ -401,4 +420,50 @@ mod tests {
         node.collect_descendants_inodes(&mut inodes).unwrap();
         assert_eq!(inodes.len(), 2);
     }
+
+    #[test]
+    fn test_alloc_bio_vecs_across_blobs() {
+        let mut blob_table = RafsV5BlobTable::new();
+        for id in ["blob0", "blob1"] {
+            blob_table.add(
+                id.to_string(),
+                0,
+                0,
+                CHUNK_SIZE,
+                4,
+                4 * CHUNK_SIZE as u64,
+                4 * CHUNK_SIZE as u64,
+                BlobFeatures::_V5_NO_EXT_BLOB_TABLE,
+                RafsSuperFlags::empty(),
+            );
+        }
+
+        let mut chunks = Vec::new();
+        for idx in 0..8u32 {
+            let offset = (idx % 4 * CHUNK_SIZE) as u64;
+            let mut chunk = MockChunkInfo::mock(
+                (idx * CHUNK_SIZE) as u64,
+                offset,
+                CHUNK_SIZE,
+                offset,
+                CHUNK_SIZE,
+            );
+            chunk.set_blob_index(idx / 4);
+            chunks.push(Arc::new(chunk));
+        }
+        let mut node = MockInode::mock(13, 8 * CHUNK_SIZE as u64, chunks);
+        node.i_blob_table = Arc::new(blob_table);
+
+        let device = BlobDevice::default();
+        let descs = node
+            .alloc_bio_vecs(&device, 0, 8 * CHUNK_SIZE as usize, true)
+            .unwrap();
+        assert_eq!(descs.len(), 2);
+        assert_eq!(descs[0].len(), 4);
+        assert_eq!(descs[0].blob_io_desc(0).unwrap().blob.blob_id(), "blob0");
+        assert_eq!(descs[1].len(), 4);
+        assert_eq!(descs[1].blob_io_desc(0).unwrap().blob.blob_id(), "blob1");
+        // The blob table is looked up once for each blob instead of for each chunk.
+        assert_eq!(node.i_blob_lookups.load(Ordering::Relaxed), 2);
+    }
 }
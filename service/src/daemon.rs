@@ -112,6 +112,10 @@ pub trait NydusDaemon: DaemonStateMachineSubscriber + Send + Sync {
     fn umount(&self) -> Result<()>;
     /// Stop the daemon object.
     fn stop(&self) {}
+    /// Change the number of worker threads of the running daemon.
+    fn set_threads(&self, _threads: u32) -> Result<()> {
+        Err(Error::Unsupported)
+    }
     /// Trigger `Stop` transition event to stop the daemon.
     fn trigger_stop(&self) -> Result<()> {
         let s = self.get_state();
@@ -298,17 +302,20 @@ impl DaemonStateMachineContext {
                     }
                     res
                 }
-                Some(Umount) => d.umount().map(|r| {
-                    // Always interrupt fuse service loop after shutdown connection to kernel.
-                    // In case that kernel does not really shutdown the session due to some reasons
-                    // causing service loop keep waiting of `/dev/fuse`.
-                    d.stop();
-                    d.wait_service()
-                        .unwrap_or_else(|e| error!("failed to wait service {}", e));
-                    // at least all fuse thread stopped, no matter what error each thread got
-                    d.set_state(DaemonState::STOPPED);
-                    r
-                }),
+                Some(Umount) => {
+                    Self::flush_cache(d);
+                    d.umount().map(|r| {
+                        // Always interrupt fuse service loop after shutdown connection to kernel.
+                        // In case that kernel does not really shutdown the session due to some
+                        // reasons causing service loop keep waiting of `/dev/fuse`.
+                        d.stop();
+                        d.wait_service()
+                            .unwrap_or_else(|e| error!("failed to wait service {}", e));
+                        // at least all fuse thread stopped, no matter what error each thread got
+                        d.set_state(DaemonState::STOPPED);
+                        r
+                    })
+                }
                 Some(Restore) => {
                     let res = d.restore();
                     if res.is_ok() {
@@ -317,6 +324,7 @@ impl DaemonStateMachineContext {
                     res
                 }
                 Some(StopStateMachine) => {
+                    Self::flush_cache(d);
                     d.set_state(DaemonState::STOPPED);
                     Ok(())
                 }
@@ -335,6 +343,13 @@ impl DaemonStateMachineContext {
         info!("state_machine thread exits");
         Ok(())
     }
+
+    // Persist cached data before the daemon dies, so the cache is still warm after restarting.
+    fn flush_cache(d: &dyn NydusDaemon) {
+        if let Some(fs) = d.get_default_fs_service() {
+            fs.flush_cache();
+        }
+    }
 }
 
 /// Handler to process state transition events emitted from the state machine.
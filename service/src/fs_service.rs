@@ -14,15 +14,16 @@ use std::str::FromStr;
 use std::sync::{Arc, MutexGuard};
 
 #[cfg(target_os = "linux")]
-use fuse_backend_rs::api::filesystem::{FileSystem, FsOptions, Layer};
+use fuse_backend_rs::api::filesystem::{Context, FileSystem, FsOptions, Layer};
 use fuse_backend_rs::api::vfs::VfsError;
 use fuse_backend_rs::api::{BackFileSystem, Vfs};
 #[cfg(target_os = "linux")]
 use fuse_backend_rs::overlayfs::{config::Config as overlay_config, OverlayFs};
 #[cfg(target_os = "linux")]
 use fuse_backend_rs::passthrough::{CachePolicy, Config as passthrough_config, PassthroughFs};
-use nydus_api::ConfigV2;
-use nydus_rafs::fs::Rafs;
+use nydus_api::{ApiAccessAdvice, ApiFadviseCmd, ApiWarmCmd, ConfigV2};
+use nydus_rafs::fs::{Rafs, RafsAccessAdvice, WarmReport};
+use nydus_rafs::metadata::ChunkLayout;
 use nydus_rafs::{RafsError, RafsIoRead};
 use nydus_storage::factory::BLOB_FACTORY;
 use serde::{Deserialize, Serialize};
@@ -79,6 +80,7 @@ impl FsBackendCollection {
             mountpoint: cmd.mountpoint.clone(),
             mounted_time: time::OffsetDateTime::now_utc(),
             config: fs_config,
+            mount_cmd: Some(cmd.clone()),
         };
 
         self.0.insert(id.to_string(), desc);
@@ -89,6 +91,22 @@ impl FsBackendCollection {
     fn del(&mut self, id: &str) {
         self.0.remove(id);
     }
+
+    // Check whether the filesystem instance at `id` is mounted by an identical request.
+    fn is_mounted_by(&self, id: &str, cmd: &FsBackendMountCmd) -> bool {
+        match self.0.get(id).and_then(|desc| desc.mount_cmd.as_ref()) {
+            Some(c) => c.fs_type == cmd.fs_type && c.source == cmd.source && c.config == cmd.config,
+            None => false,
+        }
+    }
+
+    // Get the source of the filesystem instance at `id`.
+    fn source(&self, id: &str) -> Option<String> {
+        self.0
+            .get(id)
+            .and_then(|desc| desc.mount_cmd.as_ref())
+            .map(|c| c.source.clone())
+    }
 }
 
 /// Abstract interfaces for filesystem service provider.
@@ -107,10 +125,23 @@ pub trait FsService: Send + Sync {
     fn upgrade_mgr(&self) -> Option<MutexGuard<UpgradeManager>>;
 
     /// Mount a new filesystem instance.
+    ///
+    /// Mounting the same filesystem at the same mountpoint again succeeds without doing anything,
+    /// so mount requests can be safely retried.
     // NOTE: This method is not thread-safe, however, it is acceptable as
     // mount/umount/remount/restore_mount is invoked from single thread in FSM
     fn mount(&self, cmd: FsBackendMountCmd) -> Result<()> {
         if self.backend_from_mountpoint(&cmd.mountpoint)?.is_some() {
+            if self
+                .backend_collection()
+                .is_mounted_by(&cmd.mountpoint, &cmd)
+            {
+                info!(
+                    "{} filesystem has already been mounted at {}",
+                    &cmd.fs_type, &cmd.mountpoint
+                );
+                return Ok(());
+            }
             return Err(Error::AlreadyExists);
         }
         let backend = fs_backend_factory(&cmd)?;
@@ -131,7 +162,23 @@ pub trait FsService: Send + Sync {
         Ok(())
     }
 
+    /// Check whether a filesystem instance could be mounted, without mounting it.
+    ///
+    /// The filesystem instance is created and then discarded, the VFS and mountpoints are left
+    /// untouched.
+    fn validate_mount(&self, cmd: &FsBackendMountCmd) -> Result<()> {
+        fs_backend_validate(cmd, false)?;
+        info!(
+            "{} filesystem at {} is mountable",
+            &cmd.fs_type, &cmd.source
+        );
+        Ok(())
+    }
+
     /// Remount a filesystem instance.
+    ///
+    /// A RAFS instance switches to the new bootstrap in place if the mount source changes, see
+    /// [Rafs::reload].
     fn remount(&self, cmd: FsBackendMountCmd) -> Result<()> {
         let rootfs = self
             .backend_from_mountpoint(&cmd.mountpoint)?
@@ -144,6 +191,13 @@ pub trait FsService: Send + Sync {
         let rafs_cfg = ConfigV2::from_str(&cmd.config).map_err(RafsError::LoadConfig)?;
         let rafs_cfg = Arc::new(rafs_cfg);
 
+        let source = self.backend_collection().source(&cmd.mountpoint);
+        if matches!(source, Some(s) if s != cmd.source) {
+            rafs.reload(Path::new(&cmd.source)).map_err(|e| match e {
+                RafsError::Unsupported => Error::Unsupported,
+                e => Error::Rafs(e),
+            })?;
+        }
         rafs.update(&mut bootstrap, &rafs_cfg)
             .map_err(|e| match e {
                 RafsError::Unsupported => Error::Unsupported,
@@ -199,6 +253,22 @@ pub trait FsService: Send + Sync {
     /// Get list of metrics information objects about mounted filesystem instances.
     fn backend_collection(&self) -> MutexGuard<FsBackendCollection>;
 
+    /// Flush cached data and chunk state of all mounted RAFS instances to disk, so the cache is
+    /// still warm after restarting.
+    fn flush_cache(&self) {
+        let mountpoints: Vec<String> = self.backend_collection().0.keys().cloned().collect();
+        for mp in mountpoints {
+            if let Ok(Some(fs)) = self.backend_from_mountpoint(&mp) {
+                if let Some(rafs) = fs.deref().as_any().downcast_ref::<Rafs>() {
+                    match rafs.flush() {
+                        Ok(_) => debug!("flushed cache of filesystem at {}", mp),
+                        Err(e) => warn!("failed to flush cache of filesystem at {}, {}", mp, e),
+                    }
+                }
+            }
+        }
+    }
+
     /// Export information about the filesystem service.
     fn export_backend_info(&self, mountpoint: &str) -> Result<String> {
         let fs = self
@@ -208,17 +278,211 @@ pub trait FsService: Send + Sync {
         let rafs = any_fs
             .downcast_ref::<Rafs>()
             .ok_or_else(|| Error::FsTypeMismatch("RAFS".to_string()))?;
-        let resp = serde_json::to_string(rafs.metadata()).map_err(Error::Serde)?;
+        let resp = serde_json::to_string(&rafs.metadata()).map_err(Error::Serde)?;
         Ok(resp)
     }
 
+    /// Export effective configuration of mounted filesystems, with secrets redacted.
+    ///
+    /// A json object mapping mountpoints to filesystem configuration is returned, which only
+    /// contains the filesystem mounted at `mountpoint` if specified.
+    fn export_fs_config(&self, mountpoint: Option<&str>) -> Result<String> {
+        let collection = self.backend_collection();
+        let mut configs = HashMap::new();
+        match mountpoint {
+            Some(mp) => {
+                let desc = collection.0.get(mp).ok_or(Error::NotFound)?;
+                configs.insert(mp, &desc.config);
+            }
+            None => {
+                for (mp, desc) in collection.0.iter() {
+                    configs.insert(mp.as_str(), &desc.config);
+                }
+            }
+        }
+        serde_json::to_string(&configs).map_err(Error::Serde)
+    }
+
+    /// Start fetching data chunks of the RAFS filesystem mounted at `mountpoint` into the blob
+    /// cache in background.
+    ///
+    /// Only one warm-up runs for a filesystem at a time. A json object with the running state
+    /// and numbers of succeeded and failed data chunks is returned, see [Self::warm_fs_status].
+    fn warm_fs(&self, mountpoint: &str, cmd: &ApiWarmCmd) -> Result<String> {
+        let fs = self
+            .backend_from_mountpoint(mountpoint)?
+            .ok_or(Error::NotFound)?;
+        let rafs = fs
+            .deref()
+            .as_any()
+            .downcast_ref::<Rafs>()
+            .ok_or_else(|| Error::FsTypeMismatch("RAFS".to_string()))?;
+        if rafs.warm_report().running {
+            return Err(Error::Rafs(RafsError::Prefetch(
+                "warm-up is already in progress".to_string(),
+            )));
+        }
+
+        // Data chunks are identified by blob id and compressed offset only.
+        let chunks = cmd
+            .chunks
+            .iter()
+            .map(|c| ChunkLayout {
+                blob_id: c.blob_id.clone(),
+                compressed_offset: c.compressed_offset,
+                compressed_size: 0,
+            })
+            .collect::<Vec<_>>();
+        let inodes = cmd.inodes.clone();
+        let mp = mountpoint.to_string();
+        let task = fs.clone();
+        std::thread::Builder::new()
+            .name("warm_fs".to_string())
+            .spawn(move || {
+                let rafs = task.deref().as_any().downcast_ref::<Rafs>().unwrap();
+                match rafs.warm(&inodes, &chunks, WARM_THREADS) {
+                    Ok(r) => info!(
+                        "warmed up filesystem at {}, {} chunks succeeded, {} failed",
+                        mp, r.succeeded, r.failed
+                    ),
+                    Err(e) => warn!("failed to warm up filesystem at {}, {}", mp, e),
+                }
+            })
+            .map_err(Error::ThreadSpawn)?;
+
+        let report = WarmReport {
+            running: true,
+            ..Default::default()
+        };
+        serde_json::to_string(&report).map_err(Error::Serde)
+    }
+
+    /// Get progress of fetching data chunks of the RAFS filesystem mounted at `mountpoint`.
+    ///
+    /// A json object with the running state and numbers of succeeded and failed data chunks of
+    /// the running warm-up, or of the last one, is returned.
+    fn warm_fs_status(&self, mountpoint: &str) -> Result<String> {
+        let fs = self
+            .backend_from_mountpoint(mountpoint)?
+            .ok_or(Error::NotFound)?;
+        let rafs = fs
+            .deref()
+            .as_any()
+            .downcast_ref::<Rafs>()
+            .ok_or_else(|| Error::FsTypeMismatch("RAFS".to_string()))?;
+        serde_json::to_string(&rafs.warm_report()).map_err(Error::Serde)
+    }
+
+    /// Advise the access pattern of a file of the RAFS filesystem mounted at `mountpoint`.
+    ///
+    /// The advice tunes the read amplification window of the file, until the kernel forgets it.
+    fn fadvise_fs(&self, mountpoint: &str, cmd: &ApiFadviseCmd) -> Result<()> {
+        let fs = self
+            .backend_from_mountpoint(mountpoint)?
+            .ok_or(Error::NotFound)?;
+        let rafs = fs
+            .deref()
+            .as_any()
+            .downcast_ref::<Rafs>()
+            .ok_or_else(|| Error::FsTypeMismatch("RAFS".to_string()))?;
+        let ino = rafs
+            .path_to_ino(&cmd.path)
+            .map_err(|e| Error::InvalidArguments(format!("{}: {}", cmd.path, e)))?;
+        let advice = match cmd.advice {
+            ApiAccessAdvice::Normal => RafsAccessAdvice::Normal,
+            ApiAccessAdvice::Sequential => RafsAccessAdvice::Sequential,
+            ApiAccessAdvice::Random => RafsAccessAdvice::Random,
+        };
+        rafs.fadvise(ino, advice);
+        Ok(())
+    }
+
     /// Export metrics about in-flight operations.
     fn export_inflight_ops(&self) -> Result<Option<String>>;
 
+    /// Get number of operations taking longer than the slow operation threshold so far.
+    fn slow_ops(&self) -> u64 {
+        0
+    }
+
     /// Cast `self` to trait object of [Any] to support object downcast.
     fn as_any(&self) -> &dyn Any;
 }
 
+// Number of threads to fetch data chunks for a warm-up request.
+const WARM_THREADS: usize = 4;
+
+// Id of the throwaway filesystem instances created to validate mount requests.
+const VALIDATE_MOUNT_ID: &str = "__validate_mount__";
+
+/// Check whether a filesystem could be served, without mounting it.
+///
+/// In addition to the checks done when validating mount requests, the storage backend is always
+/// checked and the root directory is read from the throwaway filesystem instance.
+pub fn fs_backend_selftest(cmd: &FsBackendMountCmd) -> Result<()> {
+    fs_backend_validate(cmd, true)
+}
+
+fn fs_backend_validate(cmd: &FsBackendMountCmd, selftest: bool) -> Result<()> {
+    let prefetch_files = validate_prefetch_file_list(&cmd.prefetch_files)?;
+
+    match cmd.fs_type {
+        FsBackendType::Rafs => {
+            let mut config =
+                ConfigV2::from_str(cmd.config.as_str()).map_err(RafsError::LoadConfig)?;
+            // Don't pull any data from the storage backend for a throwaway instance.
+            if let Some(rafs) = config.rafs.as_mut() {
+                rafs.prefetch.enable = false;
+            }
+            let config = Arc::new(config);
+            let (mut rafs, reader) = Rafs::new(&config, VALIDATE_MOUNT_ID, Path::new(&cmd.source))?;
+            let result = if selftest
+                || config
+                    .rafs
+                    .as_ref()
+                    .map(|c| c.check_backend)
+                    .unwrap_or(false)
+            {
+                rafs.check_backend()
+                    .map_err(|e| Error::InvalidConfig(format!("{}", e)))
+            } else {
+                Ok(())
+            };
+            let result =
+                result.and_then(|_| rafs.import(reader, prefetch_files).map_err(Error::Rafs));
+            let result = if selftest {
+                result.and_then(|_| {
+                    rafs_read_root(&rafs)
+                        .map_err(|e| Error::Rafs(RafsError::ReadMetadata(e, "/".to_string())))
+                })
+            } else {
+                result
+            };
+            if let Err(e) = rafs.destroy() {
+                warn!("failed to destroy validated rafs instance, {}", e);
+            }
+            result
+        }
+        FsBackendType::PassthroughFs => {
+            if !Path::new(&cmd.source).is_dir() {
+                return Err(Error::InvalidArguments(format!(
+                    "passthroughfs source {} is not a directory",
+                    cmd.source
+                )));
+            }
+            Ok(())
+        }
+    }
+}
+
+// Read attributes and entries of the root directory through the FUSE interfaces.
+fn rafs_read_root(rafs: &Rafs) -> std::io::Result<()> {
+    let ctx = Context::new();
+    let root = rafs.root_inode();
+    rafs.getattr(&ctx, root, None)?;
+    rafs.readdir(&ctx, root, 0, 4096, 0, &mut |_| Ok(1))
+}
+
 /// Validate prefetch file list from user input.
 ///
 /// Validation rules:
@@ -247,6 +511,16 @@ fn fs_backend_factory(cmd: &FsBackendMountCmd) -> Result<BackFileSystem> {
             let config = ConfigV2::from_str(cmd.config.as_str()).map_err(RafsError::LoadConfig)?;
             let config = Arc::new(config);
             let (mut rafs, reader) = Rafs::new(&config, &cmd.mountpoint, Path::new(&cmd.source))?;
+            // Fail the mount on misconfigured backend instead of returning EIO on first read.
+            if config
+                .rafs
+                .as_ref()
+                .map(|c| c.check_backend)
+                .unwrap_or(false)
+            {
+                rafs.check_backend()
+                    .map_err(|e| Error::InvalidConfig(format!("{}", e)))?;
+            }
             rafs.import(reader, prefetch_files)?;
 
             // Put a writable upper layer above the rafs to create an OverlayFS with two layers.
@@ -351,6 +625,46 @@ fn fs_backend_factory(cmd: &FsBackendMountCmd) -> Result<BackFileSystem> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Write;
+    use std::sync::Mutex;
+    use vmm_sys_util::tempdir::TempDir;
+    use vmm_sys_util::tempfile::TempFile;
+
+    struct TestFsService {
+        vfs: Vfs,
+        backend_collection: Mutex<FsBackendCollection>,
+    }
+
+    impl TestFsService {
+        fn new() -> Self {
+            TestFsService {
+                vfs: Vfs::new(Default::default()),
+                backend_collection: Default::default(),
+            }
+        }
+    }
+
+    impl FsService for TestFsService {
+        fn get_vfs(&self) -> &Vfs {
+            &self.vfs
+        }
+
+        fn upgrade_mgr(&self) -> Option<MutexGuard<UpgradeManager>> {
+            None
+        }
+
+        fn backend_collection(&self) -> MutexGuard<FsBackendCollection> {
+            self.backend_collection.lock().unwrap()
+        }
+
+        fn export_inflight_ops(&self) -> Result<Option<String>> {
+            Ok(None)
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
 
     #[test]
     fn it_should_add_new_backend() {
@@ -445,4 +759,235 @@ mod tests {
             panic!("failed to create rafs backend")
         }
     }
+
+    #[test]
+    fn it_should_check_backend_when_mounting() {
+        let config = r#"
+        {
+            "device": {
+              "backend": {
+                "type": "oss",
+                "config": {
+                  "endpoint": "127.0.0.1:1",
+                  "access_key_id": "test",
+                  "access_key_secret": "test",
+                  "bucket_name": "antsys-nydus",
+                  "object_prefix":"nydus_v2/",
+                  "scheme": "http"
+                }
+              }
+            },
+            "mode": "direct",
+            "check_backend": CHECK_BACKEND
+          }"#;
+        let bootstrap = "../tests/texture/bootstrap/nydusd_daemon_test_bootstrap";
+        let cmd = |check: bool| FsBackendMountCmd {
+            fs_type: FsBackendType::Rafs,
+            config: config.replace("CHECK_BACKEND", &check.to_string()),
+            mountpoint: "testmountpoint".to_string(),
+            source: bootstrap.to_string(),
+            prefetch_files: None,
+        };
+
+        // The backend is only accessed on first read without the check.
+        assert!(fs_backend_factory(&cmd(false)).is_ok());
+        match fs_backend_factory(&cmd(true)) {
+            Err(Error::InvalidConfig(msg)) => {
+                assert!(msg.contains("from storage backend"), "{}", msg)
+            }
+            Err(e) => panic!("unexpected error {:?}", e),
+            Ok(_) => panic!("mount should fail with unreachable backend"),
+        }
+    }
+
+    #[test]
+    fn it_should_validate_mount_without_mounting() {
+        let svc = TestFsService::new();
+        let config = r#"
+        {
+            "device": {
+              "backend": {
+                "type": "oss",
+                "config": {
+                  "endpoint": "test",
+                  "access_key_id": "test",
+                  "access_key_secret": "test",
+                  "bucket_name": "antsys-nydus",
+                  "object_prefix":"nydus_v2/",
+                  "scheme": "http"
+                }
+              }
+            },
+            "mode": "direct"
+          }"#;
+        let cmd = |source: &str| FsBackendMountCmd {
+            fs_type: FsBackendType::Rafs,
+            config: config.to_string(),
+            mountpoint: "/validate".to_string(),
+            source: source.to_string(),
+            prefetch_files: None,
+        };
+
+        svc.validate_mount(&cmd(
+            "../tests/texture/bootstrap/nydusd_daemon_test_bootstrap",
+        ))
+        .unwrap();
+
+        let corrupted = TempFile::new().unwrap();
+        corrupted.as_file().write_all(&[0x5au8; 8192]).unwrap();
+        let source = corrupted.as_path().to_str().unwrap();
+        match svc.validate_mount(&cmd(source)) {
+            Err(Error::Rafs(e)) => {
+                assert!(e.to_string().contains("Failed to fill superBlock"), "{}", e)
+            }
+            Err(e) => panic!("unexpected error {:?}", e),
+            Ok(_) => panic!("corrupted bootstrap should fail validation"),
+        }
+
+        assert!(svc.backend_from_mountpoint("/validate").unwrap().is_none());
+        assert!(svc.backend_collection().0.is_empty());
+    }
+
+    #[test]
+    fn it_should_run_selftest_without_mounting() {
+        let blob_id = "be7d77eeb719f70884758d1aa800ed0fb09d701aaec469964e9d54325f0d5fef";
+        let tmp_dir = TempDir::new().unwrap();
+        std::fs::copy(
+            format!("../tests/texture/blobs/{}", blob_id),
+            tmp_dir.as_path().join(blob_id),
+        )
+        .unwrap();
+        let config = r#"
+        {
+            "version": 2,
+            "id": "selftest",
+            "backend": {
+                "type": "localfs",
+                "localfs": {
+                    "dir": "LOCAL_FS_DIR"
+                }
+            },
+            "cache": {
+                "type": "filecache",
+                "filecache": {
+                    "work_dir": "LOCAL_FS_DIR"
+                }
+            },
+            "rafs": {
+                "mode": "direct"
+            }
+        }"#;
+        let cmd = |dir: &str| FsBackendMountCmd {
+            fs_type: FsBackendType::Rafs,
+            config: config.replace("LOCAL_FS_DIR", dir),
+            mountpoint: "/selftest".to_string(),
+            source: "../tests/texture/bootstrap/rafs-v6-2.2.boot".to_string(),
+            prefetch_files: None,
+        };
+
+        fs_backend_selftest(&cmd(tmp_dir.as_path().to_str().unwrap())).unwrap();
+
+        // The data blob is missing from the storage backend.
+        let empty_dir = TempDir::new().unwrap();
+        match fs_backend_selftest(&cmd(empty_dir.as_path().to_str().unwrap())) {
+            Err(Error::InvalidConfig(_)) | Err(Error::Rafs(_)) => {}
+            Err(e) => panic!("unexpected error {:?}", e),
+            Ok(_) => panic!("selftest should fail with missing data blob"),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn it_should_mount_idempotently() {
+        let svc = TestFsService::new();
+        let dir1 = TempDir::new().unwrap();
+        let dir2 = TempDir::new().unwrap();
+        let cmd = |source: &TempDir| FsBackendMountCmd {
+            fs_type: FsBackendType::PassthroughFs,
+            config: "".to_string(),
+            mountpoint: "/idempotent".to_string(),
+            source: source.as_path().to_str().unwrap().to_string(),
+            prefetch_files: None,
+        };
+
+        // Fresh mount.
+        svc.mount(cmd(&dir1)).unwrap();
+        let fs = svc.backend_from_mountpoint("/idempotent").unwrap().unwrap();
+        // Identical mount request is a no-op.
+        svc.mount(cmd(&dir1)).unwrap();
+        let fs2 = svc.backend_from_mountpoint("/idempotent").unwrap().unwrap();
+        assert!(Arc::ptr_eq(&fs, &fs2));
+        // Conflicting mount request still fails.
+        assert!(matches!(svc.mount(cmd(&dir2)), Err(Error::AlreadyExists)));
+        assert_eq!(svc.backend_collection().0.len(), 1);
+    }
+
+    #[test]
+    fn it_should_export_fs_config_without_secrets() {
+        let svc = TestFsService::new();
+        let config = r#"
+        {
+            "device": {
+              "backend": {
+                "type": "oss",
+                "config": {
+                  "endpoint": "test",
+                  "access_key_id": "test_ak",
+                  "access_key_secret": "test_sk",
+                  "bucket_name": "antsys-nydus",
+                  "object_prefix":"nydus_v2/",
+                  "scheme": "http",
+                  "mirrors": [{
+                    "host": "http://127.0.0.1:65001",
+                    "headers": {
+                      "Authorization": "Basic dGVzdDp0ZXN0",
+                      "X-Dragonfly-Registry": "https://index.docker.io"
+                    }
+                  }]
+                }
+              }
+            },
+            "mode": "direct",
+            "enable_xattr": true
+          }"#;
+        svc.mount(FsBackendMountCmd {
+            fs_type: FsBackendType::Rafs,
+            config: config.to_string(),
+            mountpoint: "/config".to_string(),
+            source: "../tests/texture/bootstrap/nydusd_daemon_test_bootstrap".to_string(),
+            prefetch_files: None,
+        })
+        .unwrap();
+
+        let resp = svc.export_fs_config(Some("/config")).unwrap();
+        assert!(!resp.contains("test_ak"), "{}", resp);
+        assert!(!resp.contains("test_sk"), "{}", resp);
+        assert!(!resp.contains("dGVzdDp0ZXN0"), "{}", resp);
+        let configs: HashMap<String, Option<ConfigV2>> = serde_json::from_str(&resp).unwrap();
+        assert_eq!(configs.len(), 1);
+        let cfg = configs["/config"].as_ref().unwrap();
+        let oss = cfg.get_backend_config().unwrap().get_oss_config().unwrap();
+        assert_eq!(oss.endpoint, "test");
+        assert_eq!(oss.bucket_name, "antsys-nydus");
+        assert_eq!(oss.object_prefix, "nydus_v2/");
+        assert_eq!(oss.access_key_id, "");
+        assert_eq!(oss.access_key_secret, "");
+        assert_eq!(oss.mirrors.len(), 1);
+        assert_eq!(oss.mirrors[0].headers["Authorization"], "");
+        assert_eq!(
+            oss.mirrors[0].headers["X-Dragonfly-Registry"],
+            "https://index.docker.io"
+        );
+        let rafs = cfg.get_rafs_config().unwrap();
+        assert_eq!(rafs.mode, "direct");
+        assert!(rafs.enable_xattr);
+
+        let all: HashMap<String, Option<ConfigV2>> =
+            serde_json::from_str(&svc.export_fs_config(None).unwrap()).unwrap();
+        assert!(all.contains_key("/config"));
+        assert!(matches!(
+            svc.export_fs_config(Some("/not_mounted")),
+            Err(Error::NotFound)
+        ));
+    }
 }
@@ -6,7 +6,9 @@
 //! Nydus FUSE filesystem daemon.
 
 use std::any::Any;
+use std::cmp;
 use std::ffi::{CStr, CString};
+use std::fmt::Display;
 use std::fs::metadata;
 use std::io::{Error, ErrorKind, Result};
 use std::ops::Deref;
@@ -19,12 +21,12 @@ use std::os::unix::fs::MetadataExt;
 use std::os::unix::net::UnixStream;
 use std::path::Path;
 use std::sync::{
-    atomic::{AtomicI32, AtomicU64, Ordering},
+    atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, Ordering},
     mpsc::{channel, Receiver, Sender},
     Arc, Mutex, MutexGuard,
 };
 use std::thread::{self, JoinHandle};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use fuse_backend_rs::abi::fuse_abi::{InHeader, OutHeader};
 use fuse_backend_rs::api::server::{MetricsHook, Server};
@@ -50,6 +52,11 @@ struct FuseOp {
     opcode: u32,
     unique: u64,
     timestamp_secs: u64,
+    #[serde(skip)]
+    start: Instant,
+    // Whether the request has been counted as a slow request.
+    #[serde(skip)]
+    slow: bool,
 }
 
 impl Default for FuseOp {
@@ -65,6 +72,8 @@ impl Default for FuseOp {
             opcode: u32::default(),
             unique: u64::default(),
             timestamp_secs,
+            start: Instant::now(),
+            slow: false,
         }
     }
 }
@@ -74,6 +83,11 @@ struct FuseOpWrapper {
     // Mutex should be acceptable since `inflight_op` is always updated
     // within the same thread, which means locking is always directly acquired.
     op: Arc<Mutex<Option<FuseOp>>>,
+    // Requests taking longer than the threshold are logged, zero means disabled.
+    #[serde(skip)]
+    slow_threshold: Duration,
+    #[serde(skip)]
+    slow_ops: Arc<AtomicU64>,
 }
 
 impl MetricsHook for FuseOpWrapper {
@@ -89,13 +103,53 @@ impl MetricsHook for FuseOpWrapper {
             unique: u,
             opcode: o,
             timestamp_secs,
+            start: Instant::now(),
+            slow: false,
         };
 
         *self.op.lock().expect("Not expect poisoned lock") = Some(op);
     }
 
     fn release(&self, _oh: Option<&OutHeader>) {
-        *self.op.lock().expect("Not expect poisoned lock") = None
+        let op = self.op.lock().expect("Not expect poisoned lock").take();
+        if let Some(op) = op {
+            let elapsed = op.start.elapsed();
+            if op.slow {
+                info!(
+                    "slow fuse request: opcode {} inode {} unique {} completed after {:?}",
+                    op.opcode, op.inode, op.unique, elapsed
+                );
+            } else if !self.slow_threshold.is_zero() && elapsed >= self.slow_threshold {
+                self.slow_ops.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "slow fuse request: opcode {} inode {} unique {} took {:?}, threshold {:?}",
+                    op.opcode, op.inode, op.unique, elapsed, self.slow_threshold
+                );
+            }
+        }
+    }
+}
+
+impl FuseOpWrapper {
+    /// Count the in-flight request as slow once it runs longer than the threshold.
+    ///
+    /// So hung requests are reported without waiting for them to complete.
+    fn check_slow(&self) {
+        if self.slow_threshold.is_zero() {
+            return;
+        }
+        let mut guard = self.op.lock().expect("Not expect poisoned lock");
+        if let Some(op) = guard.as_mut() {
+            let elapsed = op.start.elapsed();
+            if !op.slow && elapsed >= self.slow_threshold {
+                op.slow = true;
+                self.slow_ops.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "slow fuse request: opcode {} inode {} unique {} in flight for {:?}, threshold {:?}",
+                    op.opcode, op.inode, op.unique, elapsed, self.slow_threshold
+                );
+            }
+        }
     }
 }
 
@@ -157,6 +211,9 @@ pub struct FusedevFsService {
 
     backend_collection: Mutex<FsBackendCollection>,
     inflight_ops: Mutex<Vec<FuseOpWrapper>>,
+    slow_threshold: Duration,
+    slow_ops: Arc<AtomicU64>,
+    slow_ops_checker: AtomicBool,
 }
 
 impl FusedevFsService {
@@ -166,6 +223,7 @@ impl FusedevFsService {
         supervisor: Option<&String>,
         failover_policy: FailoverPolicy,
         readonly: bool,
+        slow_threshold: Duration,
     ) -> Result<Self> {
         let session = FuseSession::new(mnt, "rafs", "", readonly).map_err(|e| eother!(e))?;
         let upgrade_mgr = supervisor
@@ -182,6 +240,9 @@ impl FusedevFsService {
 
             backend_collection: Default::default(),
             inflight_ops: Default::default(),
+            slow_threshold,
+            slow_ops: Default::default(),
+            slow_ops_checker: AtomicBool::new(false),
         })
     }
 
@@ -190,7 +251,11 @@ impl FusedevFsService {
     }
 
     fn create_inflight_op(&self) -> FuseOpWrapper {
-        let inflight_op = FuseOpWrapper::default();
+        let inflight_op = FuseOpWrapper {
+            op: Default::default(),
+            slow_threshold: self.slow_threshold,
+            slow_ops: self.slow_ops.clone(),
+        };
 
         // "Not expected poisoned lock"
         self.inflight_ops.lock().unwrap().push(inflight_op.clone());
@@ -198,6 +263,34 @@ impl FusedevFsService {
         inflight_op
     }
 
+    /// Check in-flight requests for those running longer than the slow request threshold.
+    fn check_slow_ops(&self) {
+        for op in self.inflight_ops.lock().unwrap().iter() {
+            op.check_slow();
+        }
+    }
+
+    /// Periodically check in-flight requests for slow ones until the service is dropped.
+    fn start_slow_ops_checker(service: &Arc<Self>) -> Result<()> {
+        if service.slow_threshold.is_zero() || service.slow_ops_checker.swap(true, Ordering::AcqRel)
+        {
+            return Ok(());
+        }
+        let interval =
+            (service.slow_threshold / 2).clamp(Duration::from_millis(10), Duration::from_secs(1));
+        let service = Arc::downgrade(service);
+        thread::Builder::new()
+            .name("slow_ops_checker".to_string())
+            .spawn(move || {
+                while let Some(service) = service.upgrade() {
+                    service.check_slow_ops();
+                    drop(service);
+                    thread::sleep(interval);
+                }
+            })
+            .map(|_| ())
+    }
+
     fn umount(&self) -> NydusResult<()> {
         let mut session = self.session.lock().expect("Not expect poisoned lock.");
         session.umount().map_err(NydusError::SessionShutdown)?;
@@ -236,6 +329,12 @@ impl FsService for FusedevFsService {
         }
     }
 
+    fn slow_ops(&self) -> u64 {
+        // Count hung requests without waiting for the checker thread.
+        self.check_slow_ops();
+        self.slow_ops.load(Ordering::Relaxed)
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -254,7 +353,7 @@ pub struct FusedevDaemon {
     service: Arc<FusedevFsService>,
     state: AtomicI32,
     pub supervisor: Option<String>,
-    threads_cnt: u32,
+    threads_cnt: AtomicU32,
     state_machine_thread: Mutex<Option<JoinHandle<Result<()>>>>,
     fuse_service_threads: Mutex<Vec<JoinHandle<Result<()>>>>,
     waker: Arc<Waker>,
@@ -275,14 +374,23 @@ impl FusedevDaemon {
         supervisor: Option<String>,
         readonly: bool,
         fp: FailoverPolicy,
+        slow_threshold: Duration,
     ) -> Result<Self> {
-        let service = FusedevFsService::new(vfs, mountpoint, supervisor.as_ref(), fp, readonly)?;
+        let service = FusedevFsService::new(
+            vfs,
+            mountpoint,
+            supervisor.as_ref(),
+            fp,
+            readonly,
+            slow_threshold,
+        )?;
+        let threads_cnt = crate::get_threads_count(threads_cnt);
 
         Ok(FusedevDaemon {
             bti,
             id,
             supervisor,
-            threads_cnt,
+            threads_cnt: AtomicU32::new(threads_cnt),
             waker,
 
             state: AtomicI32::new(DaemonState::INIT as i32),
@@ -357,15 +465,15 @@ impl NydusDaemon for FusedevDaemon {
     }
 
     fn start(&self) -> NydusResult<()> {
-        info!(
-            "start fuse servers with {} worker threads",
-            self.threads_cnt
-        );
-        for _ in 0..self.threads_cnt {
+        let threads_cnt = self.threads_cnt.load(Ordering::Acquire);
+        info!("start fuse servers with {} worker threads", threads_cnt);
+        for _ in 0..threads_cnt {
             let waker = self.waker.clone();
             self.kick_one_server(waker)
                 .map_err(|e| NydusError::StartService(format!("{}", e)))?;
         }
+        FusedevFsService::start_slow_ops_checker(&self.service)
+            .map_err(|e| NydusError::StartService(format!("{}", e)))?;
 
         Ok(())
     }
@@ -374,6 +482,25 @@ impl NydusDaemon for FusedevDaemon {
         self.service.umount()
     }
 
+    fn set_threads(&self, threads: u32) -> NydusResult<()> {
+        if self.get_state() != DaemonState::RUNNING {
+            return Err(NydusError::NotReady);
+        }
+        let current = self.threads_cnt.load(Ordering::Acquire);
+        let count = threads_to_spawn(current, threads)?;
+        for _ in 0..count {
+            self.kick_one_server(self.waker.clone())?;
+            self.threads_cnt.fetch_add(1, Ordering::AcqRel);
+        }
+        info!(
+            "fuse server worker threads changed from {} to {}",
+            current,
+            self.threads_cnt.load(Ordering::Acquire)
+        );
+
+        Ok(())
+    }
+
     fn stop(&self) {
         let session = self
             .service
@@ -547,6 +674,52 @@ fn calc_fuse_conn(mp: impl AsRef<Path>) -> Result<u64> {
     Ok(major << 20 | minor)
 }
 
+// Get number of extra FUSE server threads to spawn to scale from `current` to `target` threads.
+// Only scaling up is supported, running threads can't be stopped individually.
+fn threads_to_spawn(current: u32, target: u32) -> NydusResult<u32> {
+    if target == 0 || target > crate::MAX_THREADS_COUNT {
+        return Err(NydusError::InvalidArguments(format!(
+            "number of fuse threads should be in range [1, {}]",
+            crate::MAX_THREADS_COUNT
+        )));
+    }
+    if target < current {
+        return Err(NydusError::Unsupported);
+    }
+    Ok(target - current)
+}
+
+// Delay before the first retry of mounting the FUSE session, doubled on each retry.
+const MOUNT_RETRY_INITIAL_DELAY_MS: u64 = 100;
+// Upper bound of the delay between retries of mounting the FUSE session.
+const MOUNT_RETRY_MAX_DELAY_MS: u64 = 5000;
+
+// Call `mount` and retry up to `retries` times with exponential backoff if it fails, so transient
+// failures such as `/dev/fuse` not being ready yet in a freshly started container are tolerated.
+fn mount_with_retry<T, E, F>(retries: u32, mut mount: F) -> std::result::Result<T, E>
+where
+    E: Display,
+    F: FnMut() -> std::result::Result<T, E>,
+{
+    let mut delay = Duration::from_millis(MOUNT_RETRY_INITIAL_DELAY_MS);
+    let mut attempts = 0;
+
+    loop {
+        match mount() {
+            Err(e) if attempts < retries => {
+                attempts += 1;
+                warn!(
+                    "failed to mount fuse session, {}, retry {}/{} in {:?}",
+                    e, attempts, retries, delay
+                );
+                thread::sleep(delay);
+                delay = cmp::min(delay * 2, Duration::from_millis(MOUNT_RETRY_MAX_DELAY_MS));
+            }
+            r => return r,
+        }
+    }
+}
+
 /// Create and start a [FusedevDaemon] instance.
 #[allow(clippy::too_many_arguments)]
 pub fn create_fuse_daemon(
@@ -560,6 +733,8 @@ pub fn create_fuse_daemon(
     upgrade: bool,
     readonly: bool,
     fp: FailoverPolicy,
+    mount_retry: u32,
+    slow_threshold: Duration,
     mount_cmd: Option<FsBackendMountCmd>,
     bti: BuildTimeInfo,
 ) -> Result<Arc<dyn NydusDaemon>> {
@@ -578,6 +753,7 @@ pub fn create_fuse_daemon(
         supervisor,
         readonly,
         fp,
+        slow_threshold,
     )?;
     let daemon = Arc::new(daemon);
     let machine = DaemonStateMachineContext::new(daemon.clone(), events_rx, result_sender);
@@ -595,16 +771,13 @@ pub fn create_fuse_daemon(
                 eother!(e)
             })?;
         }
-        daemon
-            .service
-            .session
-            .lock()
-            .unwrap()
-            .mount()
-            .map_err(|e| {
-                error!("service session mount error: {}", &e);
-                eother!(e)
-            })?;
+        mount_with_retry(mount_retry, || {
+            daemon.service.session.lock().unwrap().mount()
+        })
+        .map_err(|e| {
+            error!("service session mount error: {}", &e);
+            eother!(e)
+        })?;
 
         daemon
             .on_event(DaemonStateMachineInput::Mount)
@@ -672,3 +845,289 @@ pub fn create_vfs_backend(
     let vfs = fuse_backend_rs::api::Vfs::new(opts);
     Ok(Arc::new(vfs))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    mod slow_fs {
+        use std::ffi::CStr;
+        use std::io::Result;
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use std::sync::Arc;
+        use std::thread;
+        use std::time::Duration;
+
+        use fuse_backend_rs::abi::fuse_abi::{stat64, Attr};
+        use fuse_backend_rs::api::filesystem::{Context, Entry, FileSystem};
+        use fuse_backend_rs::api::BackendFileSystem;
+
+        /// A filesystem with files `/0`..`/9`, whose `getattr` takes a while to finish.
+        pub struct SlowFs {
+            active: AtomicU32,
+            // Max number of concurrent `getattr` requests.
+            max_active: Arc<AtomicU32>,
+        }
+
+        impl SlowFs {
+            pub fn new(max_active: Arc<AtomicU32>) -> Self {
+                SlowFs {
+                    active: AtomicU32::new(0),
+                    max_active,
+                }
+            }
+
+            fn entry(ino: u64) -> Entry {
+                let mode = if ino == 1 {
+                    libc::S_IFDIR | 0o755
+                } else {
+                    libc::S_IFREG | 0o644
+                };
+                let attr = Attr {
+                    ino,
+                    mode,
+                    nlink: 1,
+                    ..Default::default()
+                };
+                Entry {
+                    attr: attr.into(),
+                    inode: ino,
+                    generation: 0,
+                    attr_flags: 0,
+                    // Keep dentries but not attributes, so each stat() reaches the daemon.
+                    attr_timeout: Duration::ZERO,
+                    entry_timeout: Duration::from_secs(60),
+                }
+            }
+        }
+
+        impl BackendFileSystem for SlowFs {
+            fn mount(&self) -> Result<(Entry, u64)> {
+                Ok((Self::entry(1), 11))
+            }
+
+            fn as_any(&self) -> &dyn std::any::Any {
+                self
+            }
+        }
+
+        impl FileSystem for SlowFs {
+            type Inode = u64;
+            type Handle = u64;
+
+            fn lookup(&self, _ctx: &Context, _parent: u64, name: &CStr) -> Result<Entry> {
+                match name.to_bytes() {
+                    [c] if c.is_ascii_digit() => Ok(Self::entry((c - b'0') as u64 + 2)),
+                    _ => Err(std::io::Error::from_raw_os_error(libc::ENOENT)),
+                }
+            }
+
+            fn getattr(
+                &self,
+                _ctx: &Context,
+                ino: u64,
+                _handle: Option<u64>,
+            ) -> Result<(stat64, Duration)> {
+                let active = self.active.fetch_add(1, Ordering::AcqRel) + 1;
+                self.max_active.fetch_max(active, Ordering::AcqRel);
+                thread::sleep(Duration::from_millis(200));
+                self.active.fetch_sub(1, Ordering::AcqRel);
+                Ok((Self::entry(ino).attr, Duration::ZERO))
+            }
+        }
+    }
+
+    // Requires privilege to mount FUSE filesystems.
+    #[cfg(target_os = "linux")]
+    #[ignore]
+    #[test]
+    fn test_scale_fuse_threads() {
+        use fuse_backend_rs::api::VfsOptions;
+        use mio::{Poll, Token};
+        use vmm_sys_util::tempdir::TempDir;
+
+        let mountpoint = TempDir::new().unwrap();
+        let vfs = Arc::new(Vfs::new(VfsOptions::default()));
+        let max_active = Arc::new(AtomicU32::new(0));
+        let fs = slow_fs::SlowFs::new(max_active.clone());
+        vfs.mount(Box::new(fs), "/").unwrap();
+        let poller = Poll::new().unwrap();
+        let waker = Arc::new(Waker::new(poller.registry(), Token(1)).unwrap());
+        let bti = BuildTimeInfo {
+            package_ver: String::from("package_ver"),
+            git_commit: String::from("git_commit"),
+            build_time: String::from("build_time"),
+            profile: String::from("profile"),
+            rustc: String::from("rustc"),
+        };
+        let daemon = create_fuse_daemon(
+            mountpoint.as_path().to_str().unwrap(),
+            vfs,
+            None,
+            None,
+            1,
+            waker,
+            None::<&Path>,
+            false,
+            true,
+            FailoverPolicy::Flush,
+            0,
+            Duration::ZERO,
+            None,
+            bti,
+        )
+        .unwrap();
+
+        // Stat four files concurrently, and report the max number of concurrent requests.
+        let workload = || {
+            for idx in 0..4 {
+                metadata(mountpoint.as_path().join(idx.to_string())).unwrap();
+            }
+            max_active.store(0, Ordering::Release);
+            let clients = (0..4)
+                .map(|idx| {
+                    let path = mountpoint.as_path().join(idx.to_string());
+                    thread::spawn(move || metadata(path).unwrap())
+                })
+                .collect::<Vec<_>>();
+            for client in clients {
+                client.join().unwrap();
+            }
+            max_active.load(Ordering::Acquire)
+        };
+
+        assert_eq!(workload(), 1);
+        daemon.set_threads(4).unwrap();
+        assert_eq!(workload(), 4);
+
+        daemon.trigger_stop().unwrap();
+        daemon.wait().unwrap();
+    }
+
+    #[test]
+    fn test_threads_to_spawn() {
+        assert_eq!(threads_to_spawn(1, 4).unwrap(), 3);
+        assert_eq!(threads_to_spawn(4, 4).unwrap(), 0);
+        assert!(matches!(
+            threads_to_spawn(4, 1),
+            Err(NydusError::Unsupported)
+        ));
+        assert!(matches!(
+            threads_to_spawn(1, 0),
+            Err(NydusError::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            threads_to_spawn(1, crate::MAX_THREADS_COUNT + 1),
+            Err(NydusError::InvalidArguments(_))
+        ));
+    }
+
+    #[test]
+    fn test_mount_with_retry() {
+        let mut attempts = 0;
+        let r = mount_with_retry(3, || {
+            attempts += 1;
+            if attempts < 2 {
+                Err(eother!("fuse device not ready"))
+            } else {
+                Ok(())
+            }
+        });
+        assert!(r.is_ok());
+        assert_eq!(attempts, 2);
+
+        let mut attempts = 0;
+        let r: Result<()> = mount_with_retry(2, || {
+            attempts += 1;
+            Err(eother!("fuse device not ready"))
+        });
+        assert!(r.is_err());
+        assert_eq!(attempts, 3);
+
+        let mut attempts = 0;
+        let r: Result<()> = mount_with_retry(0, || {
+            attempts += 1;
+            Err(eother!("fuse device not ready"))
+        });
+        assert!(r.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_slow_request_detection() {
+        let slow_ops = Arc::new(AtomicU64::new(0));
+        let hook = FuseOpWrapper {
+            op: Default::default(),
+            slow_threshold: Duration::from_millis(20),
+            slow_ops: slow_ops.clone(),
+        };
+        let ih = InHeader {
+            opcode: 15,
+            nodeid: 2,
+            unique: 1,
+            ..Default::default()
+        };
+
+        hook.collect(&ih);
+        hook.release(None);
+        assert_eq!(slow_ops.load(Ordering::Relaxed), 0);
+        assert!(hook.op.lock().unwrap().is_none());
+
+        // A deliberately slow request should be reported.
+        hook.collect(&ih);
+        thread::sleep(Duration::from_millis(40));
+        hook.release(None);
+        assert_eq!(slow_ops.load(Ordering::Relaxed), 1);
+        assert!(hook.op.lock().unwrap().is_none());
+
+        // Slow requests are not reported when the threshold is disabled.
+        let hook = FuseOpWrapper {
+            slow_ops: slow_ops.clone(),
+            ..Default::default()
+        };
+        hook.collect(&ih);
+        thread::sleep(Duration::from_millis(40));
+        hook.release(None);
+        assert_eq!(slow_ops.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_slow_inflight_request_detection() {
+        let mnt = vmm_sys_util::tempdir::TempDir::new().unwrap();
+        let service = Arc::new(
+            FusedevFsService::new(
+                Arc::new(Vfs::new(Default::default())),
+                mnt.as_path(),
+                None,
+                FailoverPolicy::Flush,
+                true,
+                Duration::from_millis(20),
+            )
+            .unwrap(),
+        );
+        assert!(service.export_inflight_ops().unwrap().is_none());
+
+        let hook = service.create_inflight_op();
+        let ih = InHeader {
+            opcode: 15,
+            nodeid: 2,
+            unique: 1,
+            ..Default::default()
+        };
+        hook.collect(&ih);
+        FusedevFsService::start_slow_ops_checker(&service).unwrap();
+        // A hung request is detected while still in flight, and counted only once.
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(service.slow_ops.load(Ordering::Relaxed), 1);
+        assert_eq!(service.slow_ops(), 1);
+        let ops: serde_json::Value =
+            serde_json::from_str(&service.export_inflight_ops().unwrap().unwrap()).unwrap();
+        assert_eq!(ops.as_array().unwrap().len(), 1);
+        assert_eq!(ops[0]["inode"], 2);
+
+        hook.release(None);
+        assert_eq!(service.slow_ops(), 1);
+        assert!(service.export_inflight_ops().unwrap().is_none());
+    }
+}
@@ -39,7 +39,9 @@ mod singleton;
 pub mod upgrade;
 
 pub use blob_cache::BlobCacheMgr;
-pub use fs_service::{FsBackendCollection, FsBackendMountCmd, FsBackendUmountCmd, FsService};
+pub use fs_service::{
+    fs_backend_selftest, FsBackendCollection, FsBackendMountCmd, FsBackendUmountCmd, FsService,
+};
 pub use fusedev::{create_fuse_daemon, create_vfs_backend, FusedevDaemon};
 pub use singleton::create_daemon;
 
@@ -128,7 +130,17 @@ pub enum Error {
 
 impl From<Error> for io::Error {
     fn from(e: Error) -> Self {
-        einval!(e)
+        use Error::*;
+        match e {
+            // Map to distinct error codes so callers could tell transient errors from permanent ones.
+            NotReady => eagain!(e),
+            Unsupported => enosys!(e),
+            AlreadyExists => eexist!(e),
+            NotFound => enoent!(e),
+            // Keep the error code from the underlying IO error.
+            ThreadSpawn(e) | WaitDaemon(e) | PassthroughFs(e) | CreateFuseServer(e) | Epoll(e) => e,
+            e => einval!(e),
+        }
     }
 }
 
@@ -141,6 +153,14 @@ impl From<Error> for DaemonErrorKind {
             Unsupported => DaemonErrorKind::Unsupported,
             Serde(e) => DaemonErrorKind::Serde(e),
             UnexpectedEvent(e) => DaemonErrorKind::UnexpectedEvent(format!("{:?}", e)),
+            Rafs(e) => match e {
+                RafsError::Unsupported => DaemonErrorKind::Unsupported,
+                RafsError::InvalidImageData | RafsError::IllegalMetaStruct(..) => {
+                    DaemonErrorKind::CorruptMetadata(e.to_string())
+                }
+                RafsError::Incompatible(_) => DaemonErrorKind::UnsupportedVersion(e.to_string()),
+                e => DaemonErrorKind::Other(Error::Rafs(e).to_string()),
+            },
             o => DaemonErrorKind::Other(o.to_string()),
         }
     }
@@ -192,6 +212,9 @@ pub struct FsBackendDescriptor {
     pub mounted_time: time::OffsetDateTime,
     /// Optional configuration information for the backend filesystem.
     pub config: Option<ConfigV2>,
+    /// The mount request, to detect repeated requests for the same filesystem.
+    #[serde(skip)]
+    pub(crate) mount_cmd: Option<FsBackendMountCmd>,
 }
 
 /// Validate thread number configuration, valid range is `[1-1024]`.
@@ -213,6 +236,29 @@ pub fn validate_threads_configuration<V: AsRef<str>>(v: V) -> std::result::Resul
     }
 }
 
+/// Maximum number of worker threads.
+const MAX_THREADS_COUNT: u32 = 1024;
+
+/// Get number of worker threads, `0` means to scale with the number of online CPUs.
+///
+/// The result is clamped to `[1-1024]`.
+pub fn get_threads_count(threads: u32) -> u32 {
+    calculate_threads_count(threads, || {
+        std::thread::available_parallelism()
+            .map(|v| v.get())
+            .unwrap_or(1)
+    })
+}
+
+fn calculate_threads_count<F: FnOnce() -> usize>(threads: u32, cpus: F) -> u32 {
+    let threads = if threads == 0 {
+        cpus().min(MAX_THREADS_COUNT as usize) as u32
+    } else {
+        threads
+    };
+    threads.clamp(1, MAX_THREADS_COUNT)
+}
+
 /// Trait to get configuration options for services.
 pub trait ServiceArgs {
     /// Get value of commandline option `key`.
@@ -256,6 +302,7 @@ mod blob_cache {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use nydus_rafs::MetaType;
 
     #[test]
     fn test_backend_fs_type() {
@@ -281,6 +328,73 @@ mod tests {
         assert_eq!(format!("{}", FsBackendType::PassthroughFs), "PassthroughFs");
     }
 
+    #[test]
+    fn test_error_to_io_error_kind() {
+        let kind = |e: Error| io::Error::from(e).kind();
+
+        assert_eq!(kind(Error::NotReady), io::ErrorKind::WouldBlock);
+        assert_eq!(kind(Error::Unsupported), io::ErrorKind::Unsupported);
+        assert_eq!(kind(Error::AlreadyExists), io::ErrorKind::AlreadyExists);
+        assert_eq!(kind(Error::NotFound), io::ErrorKind::NotFound);
+        assert_eq!(
+            kind(Error::InvalidArguments("test".to_string())),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            kind(Error::InvalidConfig("test".to_string())),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            kind(Error::InvalidPrefetchList),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            kind(Error::FsTypeMismatch("RAFS".to_string())),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            kind(Error::ThreadSpawn(io::Error::from_raw_os_error(
+                libc::EAGAIN
+            ))),
+            io::ErrorKind::WouldBlock
+        );
+        assert_eq!(
+            kind(Error::WaitDaemon(io::Error::from_raw_os_error(
+                libc::ECHILD
+            ))),
+            io::Error::from_raw_os_error(libc::ECHILD).kind()
+        );
+    }
+
+    #[test]
+    fn test_rafs_error_to_daemon_error_kind() {
+        let kind = |e: RafsError| DaemonErrorKind::from(Error::Rafs(e));
+
+        assert!(matches!(
+            kind(RafsError::Unsupported),
+            DaemonErrorKind::Unsupported
+        ));
+        assert!(matches!(
+            kind(RafsError::InvalidImageData),
+            DaemonErrorKind::CorruptMetadata(_)
+        ));
+        assert!(matches!(
+            kind(RafsError::Incompatible(1)),
+            DaemonErrorKind::UnsupportedVersion(_)
+        ));
+        assert!(matches!(
+            kind(RafsError::IllegalMetaStruct(
+                MetaType::Regular,
+                "test".to_string()
+            )),
+            DaemonErrorKind::CorruptMetadata(_)
+        ));
+        match kind(RafsError::Uninitialized) {
+            DaemonErrorKind::Other(msg) => assert!(msg.starts_with("RAFS failed"), "{}", msg),
+            e => panic!("unexpected error kind {:?}", e),
+        }
+    }
+
     #[test]
     fn test_validate_thread_configuration() {
         assert_eq!(validate_threads_configuration("1").unwrap(), 1);
@@ -291,4 +405,16 @@ mod tests {
         assert!(validate_threads_configuration("1025").is_err());
         assert!(validate_threads_configuration("test").is_err());
     }
+
+    #[test]
+    fn test_calculate_threads_count() {
+        assert_eq!(calculate_threads_count(0, || 8), 8);
+        assert_eq!(calculate_threads_count(0, || 0), 1);
+        assert_eq!(calculate_threads_count(0, || 4096), 1024);
+        assert_eq!(calculate_threads_count(4, || 8), 4);
+        assert_eq!(calculate_threads_count(2048, || 8), 1024);
+
+        let cpus = std::thread::available_parallelism().unwrap().get() as u32;
+        assert_eq!(get_threads_count(0), cpus.min(1024));
+    }
 }
@@ -38,6 +38,8 @@ pub enum UpgradeMgrError {
     CloneFile(io::Error),
     #[error("failed to initialize fscache driver, {0}")]
     InitializeFscache(io::Error),
+    #[error("the upgrade storage backend can't carry file descriptors")]
+    FdPassingUnsupported,
 }
 
 impl From<UpgradeMgrError> for Error {
@@ -85,6 +87,15 @@ struct FscacheState {
 struct MountStateWrapper {
     cmd: FsBackendMountCmd,
     vfs_index: u8,
+    // Pinned filesystem instances are recreated from scratch instead of being migrated.
+    #[version(start = 2, default_fn = "default_pinned")]
+    pinned: bool,
+}
+
+impl MountStateWrapper {
+    fn default_pinned(_source_version: u16) -> bool {
+        false
+    }
 }
 
 struct FusedevState {
@@ -93,6 +104,23 @@ struct FusedevState {
     fuse_conn_id: u64,
 }
 
+impl FusedevState {
+    // Remove pinned filesystem instances and return their mount commands.
+    fn take_pinned_mounts(&mut self) -> Vec<FsBackendMountCmd> {
+        let pinned = self
+            .fs_mount_cmd_map
+            .iter()
+            .filter(|(_, mw)| mw.pinned)
+            .map(|(mp, _)| mp.to_string())
+            .collect::<Vec<_>>();
+        pinned
+            .iter()
+            .filter_map(|mp| self.fs_mount_cmd_map.remove(mp))
+            .map(|mw| mw.cmd)
+            .collect()
+    }
+}
+
 /// Online upgrade manager.
 pub struct UpgradeManager {
     fscache_deamon_stat: FscacheState,
@@ -104,6 +132,11 @@ pub struct UpgradeManager {
 impl UpgradeManager {
     /// Create a new instance of [UpgradeManager].
     pub fn new(socket_path: PathBuf) -> Self {
+        Self::with_backend(Box::new(UdsStorageBackend::new(socket_path)))
+    }
+
+    /// Create a new instance of [UpgradeManager] with the specified storage backend.
+    pub fn with_backend(backend: Box<dyn StorageBackend>) -> Self {
         UpgradeManager {
             fscache_deamon_stat: FscacheState {
                 blob_entry_map: HashMap::new(),
@@ -116,7 +149,7 @@ impl UpgradeManager {
                 fuse_conn_id: 0,
             },
             file: None,
-            backend: Box::new(UdsStorageBackend::new(socket_path)),
+            backend,
         }
     }
     pub fn add_blob_entry_state(&mut self, entry: BlobCacheEntry) {
@@ -175,6 +208,7 @@ impl UpgradeManager {
         let cmd_wrapper = MountStateWrapper {
             cmd: cmd.clone(),
             vfs_index,
+            pinned: false,
         };
         self.fuse_deamon_stat
             .fs_mount_cmd_map
@@ -196,6 +230,17 @@ impl UpgradeManager {
         }
     }
 
+    /// Pin a filesystem instance, so it will be recreated instead of migrated on online upgrade.
+    pub fn pin_mounts_state(&mut self, mountpoint: &str) -> Result<()> {
+        match self.fuse_deamon_stat.fs_mount_cmd_map.get_mut(mountpoint) {
+            Some(cmd_wrapper) => {
+                cmd_wrapper.pinned = true;
+                Ok(())
+            }
+            None => Err(Error::NotFound),
+        }
+    }
+
     /// Remove a filesystem instance from the upgrade manager.
     pub fn remove_mounts_state(&mut self, cmd: FsBackendUmountCmd) {
         if self
@@ -238,6 +283,10 @@ impl UpgradeManager {
     }
 
     pub fn hold_file(&mut self, fd: &File) -> Result<()> {
+        // Fail fast instead of silently dropping the fd on live upgrade.
+        if !self.backend.supports_fd_passing() {
+            return Err(UpgradeMgrError::FdPassingUnsupported.into());
+        }
         let f = fd.try_clone().map_err(UpgradeMgrError::CloneFile)?;
         self.file = Some(f);
 
@@ -384,6 +433,7 @@ pub mod fusedev_upgrade {
 
     use super::*;
     use crate::daemon::NydusDaemon;
+    use crate::fs_service::FsService;
     use crate::fusedev::{FusedevDaemon, FusedevFsService};
     use nydus_upgrade::persist::Snapshotter;
     use versionize::{VersionMap, Versionize, VersionizeResult};
@@ -401,6 +451,11 @@ pub mod fusedev_upgrade {
             vec![
                 // version 1
                 HashMap::from([(FusedevBackendState::type_id(), 1)]),
+                // version 2, add `MountStateWrapper::pinned`
+                HashMap::from([
+                    (FusedevBackendState::type_id(), 1),
+                    (MountStateWrapper::type_id(), 2),
+                ]),
                 // more versions for the future
             ]
         }
@@ -489,6 +544,17 @@ pub mod fusedev_upgrade {
         // restore vfs
         svc.get_vfs()
             .restore_from_bytes(&mut state.vfs_state_data)?;
+        // `FsService::mount()` needs to acquire the upgrade manager.
+        drop(mgr);
+
+        restore_mounts(svc.as_ref(), state)
+    }
+
+    // Restore filesystem instances of `state` after restoring the VFS of `svc`.
+    //
+    // Normal filesystem instances are migrated from the old daemon, pinned ones are recreated.
+    pub(super) fn restore_mounts(svc: &dyn FsService, mut state: FusedevState) -> Result<()> {
+        let pinned_mounts = state.take_pinned_mounts();
         state
             .fs_mount_cmd_map
             .iter()
@@ -498,9 +564,21 @@ pub mod fusedev_upgrade {
                 //mgr.add_mounts_state(cmd.clone(), *vfs_idx);
                 Ok(())
             })?;
+        // Pinned filesystem instances are not migrated, drop the mounts inherited from the old
+        // daemon and recreate them from scratch below.
+        for cmd in pinned_mounts.iter() {
+            svc.get_vfs().umount(&cmd.mountpoint)?;
+        }
 
         //restore upgrade manager fuse stat
-        mgr.fuse_deamon_stat = state;
+        svc.upgrade_mgr().unwrap().fuse_deamon_stat = state;
+
+        for cmd in pinned_mounts {
+            let mountpoint = cmd.mountpoint.clone();
+            svc.mount(cmd)?;
+            svc.upgrade_mgr().unwrap().pin_mounts_state(&mountpoint)?;
+            info!("pinned filesystem recreated at {}", mountpoint);
+        }
 
         Ok(())
     }
@@ -508,15 +586,113 @@ pub mod fusedev_upgrade {
 
 #[cfg(test)]
 mod tests {
+    use std::any::Any;
+    use std::sync::{Mutex, MutexGuard};
+
     use super::*;
-    use crate::fs_service::{FsBackendMountCmd, FsBackendUmountCmd};
+    use crate::fs_service::{
+        FsBackendCollection, FsBackendMountCmd, FsBackendUmountCmd, FsService,
+    };
     #[cfg(target_os = "linux")]
     use crate::upgrade::fscache_upgrade::FscacheBackendState;
     use crate::upgrade::fusedev_upgrade::FusedevBackendState;
     use crate::FsBackendType;
     use nydus_upgrade::persist::Snapshotter;
+    #[cfg(target_os = "linux")]
+    use vmm_sys_util::tempdir::TempDir;
     use vmm_sys_util::tempfile::TempFile;
 
+    struct TestFsService {
+        vfs: Vfs,
+        backend_collection: Mutex<FsBackendCollection>,
+        upgrade_mgr: Mutex<UpgradeManager>,
+    }
+
+    impl TestFsService {
+        fn new() -> Self {
+            TestFsService {
+                vfs: Vfs::new(Default::default()),
+                backend_collection: Default::default(),
+                upgrade_mgr: Mutex::new(UpgradeManager::new("dummy_socket".into())),
+            }
+        }
+    }
+
+    impl FsService for TestFsService {
+        fn get_vfs(&self) -> &Vfs {
+            &self.vfs
+        }
+
+        fn upgrade_mgr(&self) -> Option<MutexGuard<UpgradeManager>> {
+            Some(self.upgrade_mgr.lock().unwrap())
+        }
+
+        fn backend_collection(&self) -> MutexGuard<FsBackendCollection> {
+            self.backend_collection.lock().unwrap()
+        }
+
+        fn export_inflight_ops(&self) -> Result<Option<String>> {
+            Ok(None)
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_restore_pinned_mounts() {
+        let normal_dir = TempDir::new().unwrap();
+        let pinned_dir = TempDir::new().unwrap();
+        let cmd = |mountpoint: &str, dir: &TempDir| FsBackendMountCmd {
+            fs_type: FsBackendType::PassthroughFs,
+            config: "".to_string(),
+            mountpoint: mountpoint.to_string(),
+            source: dir.as_path().display().to_string(),
+            prefetch_files: None,
+        };
+
+        let old_svc = TestFsService::new();
+        old_svc.mount(cmd("/normal", &normal_dir)).unwrap();
+        old_svc.mount(cmd("/pinned", &pinned_dir)).unwrap();
+        old_svc
+            .upgrade_mgr()
+            .unwrap()
+            .pin_mounts_state("/pinned")
+            .unwrap();
+        let old_mgr = old_svc.upgrade_mgr().unwrap();
+        let normal_index = old_mgr.fuse_deamon_stat.fs_mount_cmd_map["/normal"].vfs_index;
+        let mut data = FusedevBackendState::from(&old_mgr.fuse_deamon_stat)
+            .save()
+            .unwrap();
+        drop(old_mgr);
+
+        // Restore the state saved by the old daemon in a new one.
+        let svc = TestFsService::new();
+        let backend_stat = FusedevBackendState::restore(&mut data).unwrap();
+        let mut state = FusedevState::from(&backend_stat);
+        svc.get_vfs()
+            .restore_from_bytes(&mut state.vfs_state_data)
+            .unwrap();
+        fusedev_upgrade::restore_mounts(&svc, state).unwrap();
+
+        // The normal filesystem is migrated to its old slot, the pinned one is recreated and
+        // stays pinned for the next upgrade.
+        assert!(svc.backend_from_mountpoint("/normal").unwrap().is_some());
+        assert!(svc.backend_from_mountpoint("/pinned").unwrap().is_some());
+        let mgr = svc.upgrade_mgr().unwrap();
+        let mounts = &mgr.fuse_deamon_stat.fs_mount_cmd_map;
+        assert_eq!(mounts.len(), 2);
+        assert_eq!(mounts["/normal"].vfs_index, normal_index);
+        assert!(!mounts["/normal"].pinned);
+        assert!(mounts["/pinned"].pinned);
+        assert_eq!(
+            mounts["/pinned"].cmd.source,
+            pinned_dir.as_path().display().to_string()
+        );
+    }
+
     #[test]
     fn test_failover_policy() {
         assert_eq!(
@@ -655,6 +831,39 @@ mod tests {
             .is_none());
     }
 
+    #[test]
+    fn test_upgrade_manager_pinned_mounts() {
+        let mut upgrade_mgr = UpgradeManager::new("dummy_socket".into());
+        let cmd = |mountpoint: &str| FsBackendMountCmd {
+            fs_type: FsBackendType::PassthroughFs,
+            config: "".to_string(),
+            mountpoint: mountpoint.to_string(),
+            source: "/tmp".to_string(),
+            prefetch_files: None,
+        };
+        upgrade_mgr.add_mounts_state(cmd("/normal"), 1);
+        upgrade_mgr.add_mounts_state(cmd("/pinned"), 2);
+        assert!(upgrade_mgr.pin_mounts_state("/pinned").is_ok());
+        assert!(matches!(
+            upgrade_mgr.pin_mounts_state("/missing"),
+            Err(Error::NotFound)
+        ));
+
+        let backend_stat = FusedevBackendState::from(&upgrade_mgr.fuse_deamon_stat);
+        let mut data = backend_stat.save().unwrap();
+        let backend_stat = FusedevBackendState::restore(&mut data).unwrap();
+        let mut stat = FusedevState::from(&backend_stat);
+        assert!(stat.fs_mount_cmd_map["/pinned"].pinned);
+        assert!(!stat.fs_mount_cmd_map["/normal"].pinned);
+
+        // Only normal filesystem instances are left to be migrated.
+        let pinned = stat.take_pinned_mounts();
+        assert_eq!(pinned.len(), 1);
+        assert_eq!(pinned[0].mountpoint, "/pinned");
+        assert_eq!(stat.fs_mount_cmd_map.len(), 1);
+        assert_eq!(stat.fs_mount_cmd_map["/normal"].vfs_index, 1);
+    }
+
     #[test]
     fn test_upgrade_manager_hold_fd() {
         let mut upgrade_mgr = UpgradeManager::new("dummy_socket".into());
@@ -663,4 +872,38 @@ mod tests {
         assert!(upgrade_mgr.hold_file(&temp).is_ok());
         assert!(upgrade_mgr.return_file().is_some());
     }
+
+    #[test]
+    fn test_upgrade_manager_fd_passing_unsupported() {
+        // A backend which only persists state data, like a shared memory region.
+        #[derive(Default)]
+        struct DataOnlyBackend {
+            data: Vec<u8>,
+        }
+
+        impl StorageBackend for DataOnlyBackend {
+            fn save(
+                &mut self,
+                _fds: &[std::os::fd::RawFd],
+                data: &[u8],
+            ) -> nydus_upgrade::backend::Result<usize> {
+                self.data = data.to_vec();
+                Ok(data.len())
+            }
+
+            fn restore(
+                &mut self,
+            ) -> nydus_upgrade::backend::Result<(Vec<std::os::fd::RawFd>, Vec<u8>)> {
+                Ok((Vec::new(), self.data.clone()))
+            }
+        }
+
+        let mut upgrade_mgr = UpgradeManager::with_backend(Box::<DataOnlyBackend>::default());
+        let temp = TempFile::new().unwrap().into_file();
+        assert!(matches!(
+            upgrade_mgr.hold_file(&temp),
+            Err(Error::UpgradeManager(UpgradeMgrError::FdPassingUnsupported))
+        ));
+        assert!(upgrade_mgr.return_file().is_none());
+    }
 }
@@ -0,0 +1,239 @@
+// Copyright (C) 2023 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Compare two RAFS filesystems to find out changed files and data chunks.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::OpenOptions;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use nydus_api::ConfigV2;
+use nydus_builder::Tree;
+use nydus_rafs::metadata::RafsSuper;
+use nydus_utils::digest::{DigestHasher, RafsDigest};
+use serde::Serialize;
+
+// Information about a file used to detect modifications.
+#[derive(PartialEq, Eq)]
+struct FileInfo {
+    mode: u32,
+    size: u64,
+    // Digest of data chunk ids and inline data for regular files, and of target path for symlinks.
+    digest: RafsDigest,
+}
+
+struct ImageInfo {
+    files: BTreeMap<PathBuf, FileInfo>,
+    // Compressed size of all data chunks, indexed by chunk digest.
+    chunks: HashMap<RafsDigest, u64>,
+}
+
+impl ImageInfo {
+    fn load(path: &Path, config: Arc<ConfigV2>) -> Result<Self> {
+        let (rs, _) = RafsSuper::load_from_file(path, config, false)
+            .with_context(|| format!("failed to load bootstrap {}", path.display()))?;
+        let digester = rs.meta.get_digester();
+        let tree = Tree::from_bootstrap(&rs, &mut ())
+            .with_context(|| format!("failed to load bootstrap {}", path.display()))?;
+        let mut files = BTreeMap::new();
+        let mut chunks = HashMap::new();
+
+        tree.walk_dfs_pre(&mut |t: &Tree| -> Result<()> {
+            let node = t.lock_node();
+            let mut hasher = RafsDigest::hasher(digester);
+            if node.is_reg() {
+                for chunk in node.chunks.iter() {
+                    hasher.digest_update(chunk.inner.id().as_ref());
+                    chunks.insert(*chunk.inner.id(), chunk.inner.compressed_size() as u64);
+                }
+            }
+            // Holds the symlink target, or data of tiny regular files inlined into the inode.
+            if let Some(symlink) = node.info.symlink.as_ref() {
+                hasher.digest_update(symlink.as_bytes());
+            }
+            files.insert(
+                node.target().to_path_buf(),
+                FileInfo {
+                    mode: node.inode.mode(),
+                    size: node.inode.size(),
+                    digest: hasher.digest_finalize(),
+                },
+            );
+            Ok(())
+        })?;
+
+        Ok(ImageInfo { files, chunks })
+    }
+}
+
+/// Differences between two RAFS filesystems.
+#[derive(Default, Serialize)]
+pub struct ImageDiff {
+    /// Paths only existing in the new filesystem.
+    pub added: Vec<PathBuf>,
+    /// Paths only existing in the old filesystem.
+    pub removed: Vec<PathBuf>,
+    /// Paths existing in both filesystems, but with different type, size or content.
+    pub modified: Vec<PathBuf>,
+    /// Compressed size of data chunks only referenced by the new filesystem.
+    pub added_blob_bytes: u64,
+    /// Compressed size of data chunks only referenced by the old filesystem.
+    pub removed_blob_bytes: u64,
+    /// Net change of compressed data size.
+    pub net_blob_bytes: i64,
+}
+
+impl ImageDiff {
+    /// Compare the `old` and `new` RAFS filesystems.
+    pub fn new(old: &Path, new: &Path, config: Arc<ConfigV2>) -> Result<Self> {
+        let old = ImageInfo::load(old, config.clone())?;
+        let new = ImageInfo::load(new, config)?;
+        let mut diff = ImageDiff::default();
+
+        for (path, info) in new.files.iter() {
+            match old.files.get(path) {
+                None => diff.added.push(path.clone()),
+                Some(v) if v != info => diff.modified.push(path.clone()),
+                Some(_) => {}
+            }
+        }
+        for path in old.files.keys() {
+            if !new.files.contains_key(path) {
+                diff.removed.push(path.clone());
+            }
+        }
+
+        for (id, size) in new.chunks.iter() {
+            if !old.chunks.contains_key(id) {
+                diff.added_blob_bytes += size;
+            }
+        }
+        for (id, size) in old.chunks.iter() {
+            if !new.chunks.contains_key(id) {
+                diff.removed_blob_bytes += size;
+            }
+        }
+        diff.net_blob_bytes = diff.added_blob_bytes as i64 - diff.removed_blob_bytes as i64;
+
+        Ok(diff)
+    }
+
+    pub fn dump(&self) {
+        for path in self.added.iter() {
+            println!("A\t{}", path.display());
+        }
+        for path in self.removed.iter() {
+            println!("D\t{}", path.display());
+        }
+        for path in self.modified.iter() {
+            println!("M\t{}", path.display());
+        }
+        println!(
+            "\nAdded Blob Bytes:\t{}\nRemoved Blob Bytes:\t{}\nNet Blob Bytes:\t\t{}",
+            self.added_blob_bytes, self.removed_blob_bytes, self.net_blob_bytes
+        );
+    }
+
+    pub fn dump_json(&self, path: &Path) -> Result<()> {
+        let w = OpenOptions::new()
+            .truncate(true)
+            .create(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Output file {:?} can't be opened", path))?;
+
+        serde_json::to_writer(w, self).context("Write output file failed")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use nydus_builder::test_utils::{build_with, new_build_context};
+    use nydus_builder::{ConversionType, DirectoryBuilder, Features};
+    use nydus_rafs::metadata::RafsVersion;
+    use nydus_utils::compress;
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    // Both images share `blob_dir`, with uncompressed chunks to make blob sizes predictable.
+    fn build_image(src_dir: &Path, blob_dir: &Path, name: &str, features: Features) -> PathBuf {
+        let mut ctx =
+            new_build_context(ConversionType::DirectoryToRafs, src_dir, blob_dir, features);
+        ctx.set_fs_version(RafsVersion::V5);
+        ctx.compressor = compress::Algorithm::None;
+        let bootstrap_path = blob_dir.join(name);
+        build_with(
+            &mut DirectoryBuilder::new(),
+            &mut ctx,
+            Some(&bootstrap_path),
+            None,
+        );
+
+        bootstrap_path
+    }
+
+    #[test]
+    fn test_diff_images() {
+        let src_dir = TempDir::new().unwrap();
+        let blob_dir = TempDir::new().unwrap();
+        let root = src_dir.as_path();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/b/file1"), vec![0x1u8; 0x1000]).unwrap();
+        fs::write(root.join("a/file2"), vec![0x2u8; 0x2000]).unwrap();
+        fs::write(root.join("file3"), vec![0x3u8; 0x3000]).unwrap();
+        std::os::unix::fs::symlink("a/file2", root.join("link")).unwrap();
+        let old = build_image(root, blob_dir.as_path(), "old", Features::new());
+
+        // Same size, different content.
+        fs::write(root.join("a/file2"), vec![0x4u8; 0x2000]).unwrap();
+        let new = build_image(root, blob_dir.as_path(), "new", Features::new());
+
+        let config = Arc::new(ConfigV2::default());
+        config.internal.set_blob_accessible(false);
+        let diff = ImageDiff::new(&old, &new, config.clone()).unwrap();
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.modified, vec![PathBuf::from("/a/file2")]);
+        assert_eq!(diff.added_blob_bytes, 0x2000);
+        assert_eq!(diff.removed_blob_bytes, 0x2000);
+        assert_eq!(diff.net_blob_bytes, 0);
+
+        let diff = ImageDiff::new(&old, &old, config).unwrap();
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert!(diff.modified.is_empty());
+        assert_eq!(diff.net_blob_bytes, 0);
+    }
+
+    #[test]
+    fn test_diff_images_with_inline_data() {
+        let src_dir = TempDir::new().unwrap();
+        let blob_dir = TempDir::new().unwrap();
+        let root = src_dir.as_path();
+        fs::write(root.join("tiny1"), b"tiny file 1").unwrap();
+        fs::write(root.join("tiny2"), b"tiny file 2").unwrap();
+        let features = Features::try_from("inline-data").unwrap();
+        let old = build_image(root, blob_dir.as_path(), "old", features.clone());
+
+        // Same size, different inline content.
+        fs::write(root.join("tiny2"), b"tiny file 3").unwrap();
+        let new = build_image(root, blob_dir.as_path(), "new", features);
+
+        let config = Arc::new(ConfigV2::default());
+        config.internal.set_blob_accessible(false);
+        let diff = ImageDiff::new(&old, &new, config).unwrap();
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.modified, vec![PathBuf::from("/tiny2")]);
+        assert_eq!(diff.net_blob_bytes, 0);
+    }
+}
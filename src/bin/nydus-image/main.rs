@@ -14,6 +14,7 @@ extern crate serde_json;
 #[macro_use]
 extern crate lazy_static;
 use crate::deduplicate::SqliteDatabase;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::fs::{self, metadata, DirEntry, File, OpenOptions};
 use std::os::unix::fs::FileTypeExt;
@@ -21,20 +22,23 @@ use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 use anyhow::{bail, Context, Result};
+use clap::builder::PossibleValuesParser;
 use clap::parser::ValueSource;
 use clap::{Arg, ArgAction, ArgMatches, Command as App};
 use nix::unistd::{getegid, geteuid};
-use nydus::{get_build_time_info, setup_logging};
+use nydus::{get_build_time_info, setup_logging, LogFormat};
 use nydus_api::{BuildTimeInfo, ConfigV2, LocalFsConfig};
 use nydus_builder::{
-    parse_chunk_dict_arg, ArtifactStorage, BlobCacheGenerator, BlobCompactor, BlobManager,
-    BootstrapManager, BuildContext, BuildOutput, Builder, ConversionType, DirectoryBuilder,
-    Feature, Features, HashChunkDict, Merger, Prefetch, PrefetchPolicy, StargzBuilder,
-    TarballBuilder, WhiteoutSpec,
+    load_private_key, parse_chunk_dict_arg, sign_file, ArtifactStorage, BlobCacheGenerator,
+    BlobCompactor, BlobManager, BootstrapManager, BuildContext, BuildOutput, Builder,
+    ConversionType, DedupIndex, DirectoryBuilder, Feature, Features, HashChunkDict, IdMapping,
+    Merger, Packer, Prefetch, PrefetchPolicy, StargzBuilder, TarballBuilder, WhiteoutSpec,
+    RAFS_MIN_CHUNK_SIZE,
 };
 use nydus_rafs::metadata::{MergeError, RafsSuper, RafsSuperConfig, RafsVersion};
 use nydus_storage::backend::localfs::LocalFs;
 use nydus_storage::backend::BlobBackend;
+use nydus_storage::cache::FileCacheMgr;
 use nydus_storage::device::BlobFeatures;
 use nydus_storage::factory::BlobFactory;
 use nydus_storage::meta::{format_blob_features, BatchContextGenerator};
@@ -46,7 +50,7 @@ use nydus_utils::{
 use serde::{Deserialize, Serialize};
 
 use crate::deduplicate::Deduplicate;
-use crate::unpack::{OCIUnpacker, Unpacker};
+use crate::unpack::{DirUnpacker, OCIUnpacker, Unpacker};
 use crate::validator::Validator;
 
 #[cfg(target_os = "linux")]
@@ -55,12 +59,15 @@ use nydus_service::ServiceArgs;
 use std::str::FromStr;
 
 mod deduplicate;
+mod diff;
 mod inspect;
 mod stat;
 mod unpack;
 mod validator;
 
 const BLOB_ID_MAXIMUM_LENGTH: usize = 255;
+/// Default algorithm to compress data chunks if `--compressor` is not specified.
+const DEFAULT_COMPRESSOR: compress::Algorithm = compress::Algorithm::Zstd;
 
 #[derive(Serialize, Deserialize, Default)]
 pub struct OutputSerializer {
@@ -154,6 +161,20 @@ impl OutputSerializer {
     }
 }
 
+// Accept names of compression algorithms to build images with, optionally excluding none.
+//
+// Gzip is only supported to access stargz images, data blobs compressed by gzip without
+// `CHUNK_INFO_V2` would be treated as legacy stargz blobs.
+fn compressor_parser(allow_none: bool) -> PossibleValuesParser {
+    PossibleValuesParser::new(
+        compress::Algorithm::ALL
+            .into_iter()
+            .filter(|algo| allow_none || !algo.is_none())
+            .filter(|algo| *algo != compress::Algorithm::GZip)
+            .map(|algo| algo.as_str()),
+    )
+}
+
 fn prepare_cmd_args(bti_string: &'static str) -> App {
     let arg_chunk_dict = Arg::new("chunk-dict")
         .long("chunk-dict")
@@ -195,6 +216,15 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                 .value_parser(["trace", "debug", "info", "warn", "error"])
                 .required(false)
                 .global(true),
+        )
+        .arg(
+            Arg::new("log-format")
+                .long("log-format")
+                .help("Format of log messages")
+                .default_value("text")
+                .value_parser(["text", "json"])
+                .required(false)
+                .global(true),
         );
 
     let app = app.subcommand(
@@ -262,6 +292,37 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                         .required_if_eq_any([("type", "estargztoc-ref"), ("type", "stargz_index")])
                         .help("OSS object id for the generated RAFS data blob")
                 )
+                .arg(
+                    Arg::new("blob-id-algo")
+                        .long("blob-id-algo")
+                        .help("Use digest of the generated RAFS data blob as blob id:")
+                        .value_parser(["sha256"])
+                        .conflicts_with("blob-id")
+                        .conflicts_with("blob-inline-meta")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("sign-key")
+                        .long("sign-key")
+                        .help("Path to PEM encoded Ed25519 private key to sign the generated bootstrap")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("bootstrap-compressor")
+                        .long("bootstrap-compressor")
+                        .help("Algorithm to compress the generated bootstrap, not supported by old nydusd:")
+                        .required(false)
+                        .conflicts_with("blob-inline-meta")
+                        .value_parser(compressor_parser(false)),
+                )
+                .arg(
+                    Arg::new("dedup-index")
+                        .long("dedup-index")
+                        .help("File path of chunk index shared by images for data deduplication across images, updated after build")
+                        .conflicts_with("chunk-dict")
+                        .conflicts_with("blob-inline-meta")
+                        .required(false),
+                )
                 .arg(
                     Arg::new("blob-data-size")
                         .long("blob-data-size")
@@ -280,6 +341,18 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                         .help("Set the size of data chunks, must be power of two and between 0x1000-0x1000000:")
                         .required(false),
                 )
+                .arg(
+                    Arg::new("min-chunk-size")
+                        .long("min-chunk-size")
+                        .help("Set the minimum size of data chunks for content-defined chunking, must be between 0x1000-0x1000000:")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("max-chunk-size")
+                        .long("max-chunk-size")
+                        .help("Set the maximum size of data chunks for content-defined chunking, must be between 0x1000-0x1000000:")
+                        .required(false),
+                )
                 .arg(
                     Arg::new("batch-size")
                         .long("batch-size")
@@ -287,13 +360,55 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                         .required(false)
                         .default_value("0"),
                 )
+                .arg(
+                    Arg::new("mode-mask")
+                        .long("mode-mask")
+                        .help("Clear permission bits of files and directories in octal, like umask:")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("force-dir-mode")
+                        .long("force-dir-mode")
+                        .help("Replace permission bits of directories with the octal mode:")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("force-file-mode")
+                        .long("force-file-mode")
+                        .help("Replace permission bits of regular files with the octal mode:")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("uid-map")
+                        .long("uid-map")
+                        .help("Map host user ids to user ids in the image, in format <image_id>:<host_id>:<count>, may be specified multiple times")
+                        .action(ArgAction::Append)
+                        .conflicts_with("repeatable")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("gid-map")
+                        .long("gid-map")
+                        .help("Map host group ids to group ids in the image, in format <image_id>:<host_id>:<count>, may be specified multiple times")
+                        .action(ArgAction::Append)
+                        .conflicts_with("repeatable")
+                        .required(false),
+                )
                 .arg(
                     Arg::new("compressor")
                         .long("compressor")
                         .help("Algorithm to compress data chunks:")
                         .required(false)
-                        .default_value("zstd")
-                        .value_parser(["none", "lz4_block", "zstd"]),
+                        .default_value(DEFAULT_COMPRESSOR.as_str())
+                        .value_parser(compressor_parser(true)),
+                )
+                .arg(
+                    Arg::new("chunk-compressor")
+                        .long("chunk-compressor")
+                        .help("Alternative algorithm to compress data chunks, the smaller output of it and `--compressor` is kept for each chunk, not supported by old nydusd:")
+                        .required(false)
+                        .conflicts_with("blob-cache-dir")
+                        .value_parser(compressor_parser(false)),
                 )
                 .arg(
                     Arg::new("digester")
@@ -315,7 +430,7 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                 .arg(
                     Arg::new("features")
                         .long("features")
-                        .value_parser(["blob-toc"])
+                        .value_parser(["blob-toc", "extended-inode", "inline-data"])
                         .help("Enable/disable features")
                 )
                 .arg(
@@ -348,6 +463,16 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                         .action(ArgAction::SetTrue)
                         .required(false)
                 )
+                .arg(
+                    Arg::new("verify-after-build")
+                        .long("verify-after-build")
+                        .help("Verify RAFS metadata and data chunks against the generated blob after build")
+                        .action(ArgAction::SetTrue)
+                        .required(false)
+                        .conflicts_with("blob-inline-meta")
+                        .conflicts_with("blob-cache-dir")
+                        .conflicts_with("encrypt")
+                )
                 .arg(
                     Arg::new("whiteout-spec")
                         .long("whiteout-spec")
@@ -582,6 +707,34 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                 )
         );
 
+    let app = app.subcommand(
+        App::new("gc-cache")
+            .about("Remove blob cache files not referenced by any live RAFS filesystem")
+            .arg(
+                Arg::new("cache-dir")
+                    .long("cache-dir")
+                    .short('D')
+                    .help("Working directory of the blob cache, hosting cache files")
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .required(true),
+            )
+            .arg(
+                Arg::new("bootstrap")
+                    .long("bootstrap")
+                    .short('B')
+                    .help("File path of RAFS meta blob/bootstrap of live filesystems, may be specified multiple times")
+                    .action(ArgAction::Append)
+                    .required_unless_present("all"),
+            )
+            .arg(
+                Arg::new("all")
+                    .long("all")
+                    .help("Remove cache files of all blobs not in use by running processes")
+                    .action(ArgAction::SetTrue)
+                    .conflicts_with("bootstrap"),
+            ),
+    );
+
     let app = app.subcommand(
         App::new("inspect")
             .about("Inspect RAFS filesystem metadata in interactive or request mode")
@@ -655,6 +808,24 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                 )
         );
 
+    let app = app.subcommand(
+        App::new("diff")
+            .about("Compare two RAFS filesystems and report changed files and data blob size")
+            .arg(
+                Arg::new("old")
+                    .long("old")
+                    .help("File path of RAFS meta blob/bootstrap of the old filesystem")
+                    .required(true),
+            )
+            .arg(
+                Arg::new("new")
+                    .long("new")
+                    .help("File path of RAFS meta blob/bootstrap of the new filesystem")
+                    .required(true),
+            )
+            .arg(arg_output_json.clone()),
+    );
+
     let app = app.subcommand(
             App::new("compact")
                 .about("(experimental)Compact specific nydus image, remove unused chunks in blobs, merge small blobs")
@@ -690,9 +861,59 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                 )
         );
 
+    let app = app.subcommand(
+        App::new("pack")
+            .about("Pack RAFS metadata and its data blob into a single-file image")
+            .arg(
+                Arg::new("bootstrap")
+                    .long("bootstrap")
+                    .short('B')
+                    .help("File path of RAFS metadata")
+                    .required(true),
+            )
+            .arg(
+                Arg::new("blob")
+                    .long("blob")
+                    .short('b')
+                    .help("File path of RAFS data blob")
+                    .required(true),
+            )
+            .arg(
+                Arg::new("output")
+                    .long("output")
+                    .short('O')
+                    .help("File path of the packed image, should be named by the blob id for mounting")
+                    .required(true),
+            ),
+    );
+
+    let app = app.subcommand(
+        App::new("split")
+            .about("Split a single-file image into RAFS metadata and data blob")
+            .arg(
+                Arg::new("IMAGE")
+                    .help("File path of the packed image")
+                    .required(true),
+            )
+            .arg(
+                Arg::new("bootstrap")
+                    .long("bootstrap")
+                    .short('B')
+                    .help("File path to save RAFS metadata")
+                    .required(true),
+            )
+            .arg(
+                Arg::new("blob")
+                    .long("blob")
+                    .short('b')
+                    .help("File path to save RAFS data blob")
+                    .required(true),
+            ),
+    );
+
     app.subcommand(
         App::new("unpack")
-            .about("Unpack a RAFS filesystem to a tar file")
+            .about("Unpack a RAFS filesystem to a tar file or a directory")
             .arg(
                 Arg::new("BOOTSTRAP")
                     .help("File path of RAFS metadata")
@@ -733,7 +954,13 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                 Arg::new("output")
                     .long("output")
                     .help("path for output tar file")
-                    .required(true),
+                    .required_unless_present("out"),
+            )
+            .arg(
+                Arg::new("out")
+                    .long("out")
+                    .help("directory to materialize the RAFS filesystem into")
+                    .conflicts_with("output"),
             ),
     )
 }
@@ -752,7 +979,13 @@ fn init_log(matches: &ArgMatches) -> Result<()> {
         .parse()
         .unwrap();
 
-    setup_logging(log_file, level, 0).context("failed to setup logging")
+    // Safe to unwrap because it has a default value and possible values are defined.
+    let log_format = matches
+        .get_one::<String>("log-format")
+        .unwrap()
+        .parse::<LogFormat>()?;
+
+    setup_logging(log_file, level, 0, log_format).context("failed to setup logging")
 }
 
 lazy_static! {
@@ -792,14 +1025,22 @@ fn main() -> Result<()> {
         result
     } else if let Some(matches) = cmd.subcommand_matches("check") {
         Command::check(matches, &build_info)
+    } else if let Some(matches) = cmd.subcommand_matches("gc-cache") {
+        Command::gc_cache(matches)
     } else if let Some(matches) = cmd.subcommand_matches("inspect") {
         Command::inspect(matches)
     } else if let Some(matches) = cmd.subcommand_matches("stat") {
         Command::stat(matches)
+    } else if let Some(matches) = cmd.subcommand_matches("diff") {
+        Command::diff(matches)
     } else if let Some(matches) = cmd.subcommand_matches("compact") {
         Command::compact(matches, &build_info)
     } else if let Some(matches) = cmd.subcommand_matches("unpack") {
         Command::unpack(matches)
+    } else if let Some(matches) = cmd.subcommand_matches("pack") {
+        Command::pack(matches)
+    } else if let Some(matches) = cmd.subcommand_matches("split") {
+        Command::split(matches)
     } else {
         #[cfg(target_os = "linux")]
         if let Some(matches) = cmd.subcommand_matches("export") {
@@ -831,6 +1072,7 @@ impl Command {
         let version = Self::get_fs_version(matches)?;
         let chunk_size = Self::get_chunk_size(matches, conversion_type)?;
         let batch_size = Self::get_batch_size(matches, version, conversion_type, chunk_size)?;
+        let (min_chunk_size, max_chunk_size) = Self::get_chunk_size_bounds(matches)?;
         let blob_cache_storage = Self::get_blob_cache_storage(matches, conversion_type)?;
         // blob-cacher-dir and blob-dir/blob are a set of mutually exclusive functions,
         // the former is used to generate blob cache, nydusd is directly started through blob cache,
@@ -840,6 +1082,21 @@ impl Command {
         } else {
             Self::get_blob_storage(matches, conversion_type)?
         };
+        let verify_storage = if matches.get_flag("verify-after-build") {
+            match conversion_type {
+                ConversionType::DirectoryToRafs
+                | ConversionType::EStargzToRafs
+                | ConversionType::TargzToRafs
+                | ConversionType::TarToRafs => {}
+                _ => bail!(
+                    "conversion type `{}` conflicts with `--verify-after-build`",
+                    conversion_type
+                ),
+            }
+            Some(blob_storage.clone())
+        } else {
+            None
+        };
 
         let aligned_chunk = if version.is_v6() && conversion_type != ConversionType::TarToTarfs {
             true
@@ -855,7 +1112,7 @@ impl Command {
         let mut compressor = matches
             .get_one::<String>("compressor")
             .map(|s| s.as_str())
-            .unwrap_or_default()
+            .unwrap_or(DEFAULT_COMPRESSOR.as_str())
             .parse()?;
         let mut digester = matches
             .get_one::<String>("digester")
@@ -870,6 +1127,21 @@ impl Command {
                 .unwrap_or_default(),
         )?;
         let encrypt = matches.get_flag("encrypt");
+        let sign_key = match matches.get_one::<String>("sign-key") {
+            Some(p) => Some(
+                load_private_key(Path::new(p))
+                    .with_context(|| format!("failed to load signing key {}", p))?,
+            ),
+            None => None,
+        };
+        if matches.get_one::<String>("blob-id-algo").is_some()
+            && (conversion_type.is_to_ref() || conversion_type == ConversionType::TarToTarfs)
+        {
+            bail!(
+                "conversion type '{}' conflicts with '--blob-id-algo', blob id is the digest of source tarball",
+                conversion_type
+            );
+        }
         match conversion_type {
             ConversionType::DirectoryToRafs => {
                 Self::ensure_directory(&source_path)?;
@@ -1060,6 +1332,12 @@ impl Command {
         if features.is_enabled(Feature::BlobToc) && version == RafsVersion::V5 {
             bail!("`--features blob-toc` can't be used with `--version 5` ");
         }
+        if features.is_enabled(Feature::ExtendedInode) && version == RafsVersion::V6 {
+            bail!("`--features extended-inode` can't be used with `--version 6` ");
+        }
+        if features.is_enabled(Feature::InlineData) && version == RafsVersion::V6 {
+            bail!("`--features inline-data` can't be used with `--version 6` ");
+        }
 
         if blob_cache_storage.is_some() {
             // In blob cache mode, we don't need to do any compression for the original data
@@ -1085,6 +1363,28 @@ impl Command {
         build_ctx.set_fs_version(version);
         build_ctx.set_chunk_size(chunk_size);
         build_ctx.set_batch_size(batch_size);
+        build_ctx.set_chunk_size_bounds(min_chunk_size, max_chunk_size)?;
+        if let Some(algo) = matches.get_one::<String>("chunk-compressor") {
+            if !matches!(
+                conversion_type,
+                ConversionType::DirectoryToRafs
+                    | ConversionType::TarToRafs
+                    | ConversionType::TargzToRafs
+                    | ConversionType::EStargzToRafs
+            ) {
+                bail!(
+                    "conversion type '{}' conflicts with '--chunk-compressor'",
+                    conversion_type
+                );
+            }
+            build_ctx.set_chunk_compressor(algo.parse()?)?;
+        }
+        let (mode_mask, force_dir_mode, force_file_mode) = Self::get_mode_policy(matches)?;
+        build_ctx.set_mode_policy(mode_mask, force_dir_mode, force_file_mode)?;
+        build_ctx.set_id_maps(
+            Self::get_id_maps(matches, "uid-map")?,
+            Self::get_id_maps(matches, "gid-map")?,
+        )?;
 
         let blob_cache_generator = match blob_cache_storage {
             Some(storage) => Some(BlobCacheGenerator::new(storage)?),
@@ -1100,23 +1400,39 @@ impl Command {
         build_ctx.set_configuration(config.clone());
 
         let mut blob_mgr = BlobManager::new(digester);
+        let chunk_dict_config = RafsSuperConfig {
+            version,
+            compressor,
+            digester,
+            chunk_size,
+            batch_size,
+            explicit_uidgid: !repeatable,
+            is_tarfs_mode: false,
+        };
+        let dedup_index = match matches.get_one::<String>("dedup-index") {
+            Some(p) => Some(DedupIndex::load(Path::new(p))?),
+            None => None,
+        };
         if let Some(chunk_dict_arg) = matches.get_one::<String>("chunk-dict") {
-            let config = RafsSuperConfig {
-                version,
-                compressor,
-                digester,
-                chunk_size,
-                batch_size,
-                explicit_uidgid: !repeatable,
-                is_tarfs_mode: false,
-            };
             let rafs_config = Arc::new(build_ctx.configuration.as_ref().clone());
             // The separate chunk dict bootstrap doesn't support blob accessible.
             rafs_config.internal.set_blob_accessible(false);
             blob_mgr.set_chunk_dict(timing_tracer!(
-                { HashChunkDict::from_commandline_arg(chunk_dict_arg, rafs_config, &config,) },
+                {
+                    HashChunkDict::from_commandline_arg(
+                        chunk_dict_arg,
+                        rafs_config,
+                        &chunk_dict_config,
+                    )
+                },
                 "import_chunk_dict"
             )?);
+        } else if let Some(index) = dedup_index.as_ref() {
+            let dict = timing_tracer!(
+                { index.to_chunk_dict(&chunk_dict_config) },
+                "import_dedup_index"
+            )?;
+            blob_mgr.set_chunk_dict(Arc::new(dict));
         }
 
         let mut bootstrap_mgr = if blob_inline_meta {
@@ -1125,6 +1441,9 @@ impl Command {
             let bootstrap_path = Self::get_bootstrap_storage(matches)?;
             BootstrapManager::new(Some(bootstrap_path), parent_path)
         };
+        if let Some(algo) = matches.get_one::<String>("bootstrap-compressor") {
+            bootstrap_mgr.set_compressor(algo.parse()?);
+        }
 
         // Legality has been checked and filtered by `get_batch_size()`.
         if build_ctx.batch_size > 0 {
@@ -1184,8 +1503,44 @@ impl Command {
             "total_build"
         )?;
 
+        let dedup_config = matches.get_one::<String>("dedup-index").map(|_| {
+            let config = Arc::new(build_ctx.configuration.as_ref().clone());
+            config.internal.set_blob_accessible(false);
+            config
+        });
         lazy_drop(build_ctx);
 
+        if let Some(storage) = verify_storage {
+            timing_tracer!(
+                { Self::verify_build_output(&build_output, storage, config) },
+                "verify_after_build"
+            )?;
+        }
+
+        if let Some(key) = sign_key {
+            let bootstrap_path = build_output
+                .bootstrap_path
+                .as_ref()
+                .ok_or_else(|| anyhow!("'--sign-key' requires a bootstrap file to sign"))?;
+            sign_file(Path::new(bootstrap_path), &key)
+                .with_context(|| format!("failed to sign bootstrap {}", bootstrap_path))?;
+        }
+
+        if let (Some(index_path), Some(rafs_config)) =
+            (matches.get_one::<String>("dedup-index"), dedup_config)
+        {
+            let bootstrap_path = build_output
+                .bootstrap_path
+                .as_ref()
+                .ok_or_else(|| anyhow!("'--dedup-index' requires a bootstrap file"))?;
+            DedupIndex::update(
+                Path::new(index_path),
+                Path::new(bootstrap_path),
+                rafs_config,
+                &chunk_dict_config,
+            )?;
+        }
+
         // Some operations like listing xattr pairs of certain namespace need the process
         // to be privileged. Therefore, trace what euid and egid are.
         event_tracer!("euid", "{}", geteuid());
@@ -1194,6 +1549,54 @@ impl Command {
         OutputSerializer::dump(matches, build_output, build_info, compressor, version)
     }
 
+    fn verify_build_output(
+        build_output: &BuildOutput,
+        blob_storage: Option<ArtifactStorage>,
+        config: Arc<ConfigV2>,
+    ) -> Result<()> {
+        let bootstrap_path = build_output
+            .bootstrap_path
+            .as_ref()
+            .context("missing bootstrap to verify")?;
+        let mut blob_files = HashMap::new();
+        match blob_storage {
+            Some(ArtifactStorage::FileDir(dir)) => {
+                for blob_id in build_output.blobs.iter() {
+                    let path = dir.join(blob_id);
+                    if path.is_file() {
+                        blob_files.insert(blob_id.to_string(), path);
+                    }
+                }
+            }
+            // The newly generated data blob is the last one in the blob table, and it can't be
+            // read back if dumped into a fifo.
+            Some(ArtifactStorage::SingleFile(path)) => {
+                if let (Some(blob_id), Some(_)) =
+                    (build_output.blobs.last(), build_output.blob_size)
+                {
+                    if path.is_file() {
+                        blob_files.insert(blob_id.to_string(), path);
+                    }
+                }
+            }
+            None => {}
+        }
+
+        // Data chunks are verified against local blob files directly, not through storage backend.
+        let config = Arc::new(config.as_ref().clone());
+        config.internal.set_blob_accessible(false);
+        let mut validator = Validator::new(Path::new(bootstrap_path), config)?;
+        validator
+            .verify(&blob_files)
+            .with_context(|| format!("failed to verify built image {}", bootstrap_path))?;
+        info!(
+            "verified RAFS filesystem with {} local data blobs",
+            blob_files.len()
+        );
+
+        Ok(())
+    }
+
     fn chunkdict_save(matches: &ArgMatches) -> Result<()> {
         let bootstrap_path = Self::get_bootstrap(matches)?;
         let config = Self::get_configuration(matches)?;
@@ -1356,8 +1759,15 @@ impl Command {
         config
             .internal
             .set_blob_accessible(matches.get_one::<String>("config").is_some());
-        let output = matches.get_one::<String>("output").expect("pass in output");
+        let out_dir = matches.get_one::<String>("out");
+        let output = match out_dir {
+            Some(dir) => dir,
+            None => matches.get_one::<String>("output").expect("pass in output"),
+        };
         if output.is_empty() {
+            if out_dir.is_some() {
+                return Err(anyhow!("invalid empty --out option"));
+            }
             return Err(anyhow!("invalid empty --output option"));
         }
 
@@ -1369,6 +1779,7 @@ impl Command {
                     blob_file: blob_path.to_str().unwrap().to_owned(),
                     dir: Default::default(),
                     alt_dirs: Default::default(),
+                    mmap: false,
                 };
                 let local_fs = LocalFs::new(&local_fs_conf, Some("unpacker"))
                     .with_context(|| format!("fail to create local backend for {:?}", blob_path))?;
@@ -1387,10 +1798,17 @@ impl Command {
             }
         };
 
-        OCIUnpacker::new(bootstrap, backend, output)
-            .with_context(|| "fail to create unpacker")?
-            .unpack(config)
-            .with_context(|| "fail to unpack")
+        if out_dir.is_some() {
+            DirUnpacker::new(bootstrap, backend, output)
+                .with_context(|| "fail to create unpacker")?
+                .unpack(config)
+                .with_context(|| "fail to unpack")
+        } else {
+            OCIUnpacker::new(bootstrap, backend, output)
+                .with_context(|| "fail to create unpacker")?
+                .unpack(config)
+                .with_context(|| "fail to unpack")
+        }
     }
 
     fn check(matches: &ArgMatches, build_info: &BuildTimeInfo) -> Result<()> {
@@ -1435,6 +1853,42 @@ impl Command {
         Ok(())
     }
 
+    fn gc_cache(matches: &ArgMatches) -> Result<()> {
+        let cache_dir = matches.get_one::<PathBuf>("cache-dir").unwrap();
+        if !cache_dir.is_dir() {
+            bail!(
+                "blob cache directory {} does not exist",
+                cache_dir.display()
+            );
+        }
+
+        let mut blob_ids = HashSet::new();
+        if let Some(bootstraps) = matches.get_many::<String>("bootstrap") {
+            for bootstrap in bootstraps {
+                let config = Arc::new(ConfigV2::default());
+                config.internal.set_blob_accessible(false);
+                let (sb, _) = RafsSuper::load_from_file(bootstrap, config, false)
+                    .with_context(|| format!("failed to load bootstrap {}", bootstrap))?;
+                for blob in sb.superblock.get_blob_infos() {
+                    blob_ids.insert(blob.blob_id());
+                }
+            }
+        }
+
+        let removed = FileCacheMgr::remove_unreferenced_files(cache_dir, &blob_ids)
+            .with_context(|| format!("failed to remove cache files in {}", cache_dir.display()))?;
+        for path in removed.iter() {
+            info!("removed unreferenced cache file {}", path.display());
+        }
+        println!(
+            "Removed {} unreferenced cache files, {} blobs are referenced",
+            removed.len(),
+            blob_ids.len()
+        );
+
+        Ok(())
+    }
+
     fn inspect(matches: &ArgMatches) -> Result<()> {
         let bootstrap_path = Self::get_bootstrap(matches)?;
         let mut config = Self::get_configuration(matches)?;
@@ -1525,6 +1979,42 @@ impl Command {
         Ok(())
     }
 
+    fn pack(matches: &ArgMatches) -> Result<()> {
+        let bootstrap = Path::new(matches.get_one::<String>("bootstrap").unwrap());
+        let blob = Path::new(matches.get_one::<String>("blob").unwrap());
+        let output = Path::new(matches.get_one::<String>("output").unwrap());
+        Packer::pack(bootstrap, blob, output)?;
+        info!("successfully packed image into {}", output.display());
+
+        Ok(())
+    }
+
+    fn split(matches: &ArgMatches) -> Result<()> {
+        let image = Path::new(matches.get_one::<String>("IMAGE").unwrap());
+        let bootstrap = Path::new(matches.get_one::<String>("bootstrap").unwrap());
+        let blob = Path::new(matches.get_one::<String>("blob").unwrap());
+        Packer::split(image, bootstrap, blob)?;
+        info!("successfully split image {}", image.display());
+
+        Ok(())
+    }
+
+    fn diff(matches: &ArgMatches) -> Result<()> {
+        let old = Path::new(matches.get_one::<String>("old").unwrap());
+        let new = Path::new(matches.get_one::<String>("new").unwrap());
+        let config = Arc::new(ConfigV2::default());
+        config.internal.set_blob_accessible(false);
+        let diff = diff::ImageDiff::new(old, new, config)?;
+
+        if let Some(path) = matches.get_one::<String>("output-json").map(PathBuf::from) {
+            diff.dump_json(&path)?;
+        } else {
+            diff.dump();
+        }
+
+        Ok(())
+    }
+
     fn get_bootstrap(matches: &ArgMatches) -> Result<&Path> {
         match matches.get_one::<String>("bootstrap") {
             Some(s) => Ok(Path::new(s)),
@@ -1719,6 +2209,49 @@ impl Command {
         }
     }
 
+    fn get_mode_policy(matches: &ArgMatches) -> Result<(u32, Option<u32>, Option<u32>)> {
+        let parse = |name: &str| -> Result<Option<u32>> {
+            match matches.get_one::<String>(name) {
+                None => Ok(None),
+                Some(v) => {
+                    let mode = u32::from_str_radix(v.trim_start_matches("0o"), 8)
+                        .context(format!("invalid {} {}", name, v))?;
+                    Ok(Some(mode))
+                }
+            }
+        };
+        let mode_mask = parse("mode-mask")?.unwrap_or(0);
+        let force_dir_mode = parse("force-dir-mode")?;
+        let force_file_mode = parse("force-file-mode")?;
+        Ok((mode_mask, force_dir_mode, force_file_mode))
+    }
+
+    fn get_id_maps(matches: &ArgMatches, name: &str) -> Result<Vec<IdMapping>> {
+        match matches.get_many::<String>(name) {
+            None => Ok(Vec::new()),
+            Some(maps) => maps.map(|v| IdMapping::from_str(v)).collect(),
+        }
+    }
+
+    fn get_chunk_size_bounds(matches: &ArgMatches) -> Result<(u32, u32)> {
+        let parse = |name: &str, default: u32| -> Result<u32> {
+            match matches.get_one::<String>(name) {
+                None => Ok(default),
+                Some(v) => {
+                    if v.starts_with("0x") || v.starts_with("0X") {
+                        u32::from_str_radix(&v[2..], 16).context(format!("invalid {} {}", name, v))
+                    } else {
+                        v.parse::<u32>().context(format!("invalid {} {}", name, v))
+                    }
+                }
+            }
+        };
+        // Bounds are validated by `BuildContext::set_chunk_size_bounds()`.
+        let min_chunk_size = parse("min-chunk-size", RAFS_MIN_CHUNK_SIZE)?;
+        let max_chunk_size = parse("max-chunk-size", RAFS_MAX_CHUNK_SIZE as u32)?;
+        Ok((min_chunk_size, max_chunk_size))
+    }
+
     fn get_batch_size(
         matches: &ArgMatches,
         version: RafsVersion,
@@ -1905,9 +2438,24 @@ impl Command {
 
 #[cfg(test)]
 mod tests {
-    use super::Command;
+    use super::{compressor_parser, Command};
+    use clap::builder::TypedValueParser;
+
     #[test]
     fn test_ensure_file() {
         Command::ensure_file("/dev/stdin").unwrap();
     }
+
+    #[test]
+    fn test_compressor_parser() {
+        let names = |allow_none| {
+            compressor_parser(allow_none)
+                .possible_values()
+                .unwrap()
+                .map(|v| v.get_name().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(true), ["none", "lz4_block", "zstd"]);
+        assert_eq!(names(false), ["lz4_block", "zstd"]);
+    }
 }
@@ -0,0 +1,346 @@
+// Copyright (C) 2023 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Unpacker to materialize a RAFS filesystem into a local directory.
+
+use std::collections::HashMap;
+use std::ffi::{CString, OsStr};
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use nix::sys::stat::{mknod, Mode, SFlag};
+use nix::unistd::{fchownat, geteuid, FchownatFlags, Gid, Uid};
+use nydus_api::ConfigV2;
+use nydus_rafs::metadata::{RafsInodeExt, RafsSuper};
+use nydus_rafs::RafsIterator;
+use nydus_storage::backend::BlobBackend;
+
+use super::pax::ChunkReader;
+use super::{create_blob_readers, Unpacker};
+
+/// An unpacker to write files, directories, symlinks and special files of a RAFS filesystem,
+/// together with their modes, ownership, timestamps and xattrs, into a local directory.
+pub struct DirUnpacker {
+    bootstrap: PathBuf,
+    blob_backend: Option<Arc<dyn BlobBackend + Send + Sync>>,
+    output: PathBuf,
+}
+
+impl DirUnpacker {
+    pub fn new(
+        bootstrap: &Path,
+        blob_backend: Option<Arc<dyn BlobBackend + Send + Sync>>,
+        output: &str,
+    ) -> Result<Self> {
+        Ok(DirUnpacker {
+            bootstrap: bootstrap.to_path_buf(),
+            blob_backend,
+            output: PathBuf::from(output),
+        })
+    }
+
+    fn unpack_file(
+        &self,
+        inode: &dyn RafsInodeExt,
+        target: &Path,
+        reader: &mut dyn Read,
+    ) -> Result<()> {
+        let mut file =
+            File::create(target).with_context(|| format!("fail to create file {:?}", target))?;
+        let size = io::copy(reader, &mut file)
+            .with_context(|| format!("fail to write file {:?}", target))?;
+        if size != inode.size() {
+            bail!(
+                "fail to unpack file {:?}, got {} bytes but expect {}",
+                target,
+                size,
+                inode.size()
+            );
+        }
+
+        Ok(())
+    }
+
+    fn unpack_special(&self, inode: &dyn RafsInodeExt, target: &Path) -> Result<()> {
+        let attr = inode.get_attr();
+        let kind = SFlag::from_bits_truncate(attr.mode & libc::S_IFMT);
+        let perm = Mode::from_bits_truncate(attr.mode & 0o7777);
+        mknod(target, kind, perm, attr.rdev as libc::dev_t)
+            .with_context(|| format!("fail to create special file {:?}", target))
+    }
+
+    fn set_attributes(&self, inode: &dyn RafsInodeExt, target: &Path) -> Result<()> {
+        let attr = inode.get_attr();
+        let path = CString::new(target.as_os_str().as_bytes())?;
+
+        if inode.has_xattr() {
+            for name in inode.get_xattrs()? {
+                let value = inode
+                    .get_xattr(OsStr::from_bytes(&name))?
+                    .unwrap_or_default();
+                let name = CString::new(name)?;
+                let ret = unsafe {
+                    libc::lsetxattr(
+                        path.as_ptr(),
+                        name.as_ptr(),
+                        value.as_ptr() as *const libc::c_void,
+                        value.len(),
+                        0,
+                    )
+                };
+                if ret != 0 {
+                    return Err(io::Error::last_os_error())
+                        .with_context(|| format!("fail to set xattr {:?} of {:?}", name, target));
+                }
+            }
+        }
+
+        // Only root is able to change ownership, and it must be done before setting the mode
+        // because chown clears the setuid/setgid bits.
+        if geteuid().is_root() {
+            fchownat(
+                None,
+                target,
+                Some(Uid::from_raw(attr.uid)),
+                Some(Gid::from_raw(attr.gid)),
+                FchownatFlags::NoFollowSymlink,
+            )
+            .with_context(|| format!("fail to set owner of {:?}", target))?;
+        }
+
+        if !inode.is_symlink() {
+            fs::set_permissions(target, fs::Permissions::from_mode(attr.mode & 0o7777))
+                .with_context(|| format!("fail to set mode of {:?}", target))?;
+        }
+
+        let times = [
+            libc::timespec {
+                tv_sec: attr.atime as libc::time_t,
+                tv_nsec: attr.atimensec as libc::c_long,
+            },
+            libc::timespec {
+                tv_sec: attr.mtime as libc::time_t,
+                tv_nsec: attr.mtimensec as libc::c_long,
+            },
+        ];
+        let ret = unsafe {
+            libc::utimensat(
+                libc::AT_FDCWD,
+                path.as_ptr(),
+                times.as_ptr(),
+                libc::AT_SYMLINK_NOFOLLOW,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error())
+                .with_context(|| format!("fail to set timestamps of {:?}", target));
+        }
+
+        Ok(())
+    }
+}
+
+impl Unpacker for DirUnpacker {
+    fn unpack(&self, config: Arc<ConfigV2>) -> Result<()> {
+        debug!(
+            "directory unpacker, bootstrap file: {:?}, output directory: {:?}",
+            self.bootstrap, self.output
+        );
+
+        let (rs, _) = RafsSuper::load_from_file(self.bootstrap.as_path(), config, false)?;
+        let (readers, compressors) =
+            create_blob_readers(rs.superblock.get_blob_infos(), &self.blob_backend)?;
+        fs::create_dir_all(&self.output)
+            .with_context(|| format!("fail to create output directory {:?}", self.output))?;
+
+        let mut links: HashMap<u64, PathBuf> = HashMap::new();
+        let mut dirs = Vec::new();
+        for (inode, path) in RafsIterator::new(&rs) {
+            let target = self.output.join(path.strip_prefix("/")?);
+            let mode = inode.get_attr().mode & libc::S_IFMT;
+
+            if inode.is_dir() {
+                if target != self.output {
+                    fs::create_dir(&target)
+                        .with_context(|| format!("fail to create directory {:?}", target))?;
+                }
+                // Set attributes of directories after all children have been created, otherwise
+                // the mtime gets updated and a read-only directory can't be populated.
+                dirs.push((inode, target));
+                continue;
+            }
+
+            if inode.is_hardlink() {
+                if let Some(link) = links.get(&inode.ino()) {
+                    fs::hard_link(link, &target)
+                        .with_context(|| format!("fail to create hardlink {:?}", target))?;
+                    continue;
+                }
+                links.insert(inode.ino(), target.clone());
+            }
+
+            if inode.is_reg() {
+                if let Some(data) = inode.get_inline_data() {
+                    self.unpack_file(inode.as_ref(), &target, &mut Cursor::new(data))?;
+                } else {
+                    let chunks = (0..inode.get_chunk_count())
+                        .map(|idx| inode.get_chunk_info(idx))
+                        .collect::<io::Result<Vec<_>>>()?;
+                    let mut reader = ChunkReader::new(compressors.clone(), readers.clone(), chunks);
+                    self.unpack_file(inode.as_ref(), &target, &mut reader)?;
+                }
+            } else if inode.is_symlink() {
+                std::os::unix::fs::symlink(inode.get_symlink()?, &target)
+                    .with_context(|| format!("fail to create symlink {:?}", target))?;
+            } else if mode == libc::S_IFSOCK {
+                // Sockets are created by applications at runtime, there's no content to restore.
+                warn!("skip unpacking socket {:?}", path);
+                continue;
+            } else {
+                self.unpack_special(inode.as_ref(), &target)?;
+            }
+            self.set_attributes(inode.as_ref(), &target)?;
+        }
+
+        for (inode, target) in dirs.iter().rev() {
+            self.set_attributes(inode.as_ref(), target)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::MetadataExt;
+
+    use nydus_builder::test_utils::build_image;
+    use nydus_builder::Features;
+    use nydus_rafs::metadata::RafsVersion;
+    use nydus_storage::backend::localfs::LocalFs;
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    fn assert_same_tree(src: &Path, dst: &Path) {
+        let src_md = fs::symlink_metadata(src).unwrap();
+        let dst_md = fs::symlink_metadata(dst).unwrap();
+        assert_eq!(src_md.mode(), dst_md.mode(), "mode of {:?}", dst);
+        assert_eq!(src_md.mtime(), dst_md.mtime(), "mtime of {:?}", dst);
+
+        let mut src_xattrs = xattr::list(src).unwrap().collect::<Vec<_>>();
+        let mut dst_xattrs = xattr::list(dst).unwrap().collect::<Vec<_>>();
+        src_xattrs.sort();
+        dst_xattrs.sort();
+        assert_eq!(src_xattrs, dst_xattrs, "xattrs of {:?}", dst);
+        for name in src_xattrs {
+            assert_eq!(
+                xattr::get(src, &name).unwrap(),
+                xattr::get(dst, &name).unwrap()
+            );
+        }
+
+        if src_md.is_symlink() {
+            assert_eq!(fs::read_link(src).unwrap(), fs::read_link(dst).unwrap());
+        } else if src_md.is_file() {
+            assert_eq!(fs::read(src).unwrap(), fs::read(dst).unwrap());
+        } else if src_md.is_dir() {
+            let mut names = fs::read_dir(src)
+                .unwrap()
+                .map(|e| e.unwrap().file_name())
+                .collect::<Vec<_>>();
+            let mut dst_names = fs::read_dir(dst)
+                .unwrap()
+                .map(|e| e.unwrap().file_name())
+                .collect::<Vec<_>>();
+            names.sort();
+            dst_names.sort();
+            assert_eq!(names, dst_names, "entries of {:?}", dst);
+            for name in names {
+                assert_same_tree(&src.join(&name), &dst.join(&name));
+            }
+        }
+    }
+
+    #[test]
+    fn test_unpack_to_directory() {
+        let src_dir = TempDir::new().unwrap();
+        let root = src_dir.as_path();
+        let content = (0..0x100000 + 0x123)
+            .map(|v| (v % 251) as u8)
+            .collect::<Vec<u8>>();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/b/file1"), &content).unwrap();
+        fs::write(root.join("a/file2"), b"file2").unwrap();
+        fs::write(root.join("empty"), b"").unwrap();
+        fs::hard_link(root.join("a/file2"), root.join("a/b/link")).unwrap();
+        std::os::unix::fs::symlink("a/file2", root.join("symlink")).unwrap();
+        xattr::set(root.join("a/b/file1"), "user.nydus", b"value").unwrap();
+        xattr::set(root.join("a"), "user.dir", b"").unwrap();
+        fs::set_permissions(root.join("a/file2"), fs::Permissions::from_mode(0o600)).unwrap();
+        fs::set_permissions(root.join("empty"), fs::Permissions::from_mode(0o4755)).unwrap();
+        fs::set_permissions(root.join("a/b"), fs::Permissions::from_mode(0o500)).unwrap();
+
+        for version in [RafsVersion::V5, RafsVersion::V6] {
+            let out_dir = TempDir::new().unwrap();
+            let (bootstrap_path, blob_dir) = build_image(root, version, Features::new());
+
+            let backend_config = nydus_api::LocalFsConfig {
+                blob_file: String::new(),
+                dir: blob_dir.as_path().display().to_string(),
+                alt_dirs: Vec::new(),
+                mmap: false,
+            };
+            let backend = Arc::new(LocalFs::new(&backend_config, Some("unpacker")).unwrap());
+            let config = Arc::new(ConfigV2::default());
+            config.internal.set_blob_accessible(false);
+            let out = out_dir.as_path().join("rootfs");
+            DirUnpacker::new(&bootstrap_path, Some(backend), out.to_str().unwrap())
+                .unwrap()
+                .unpack(config)
+                .unwrap();
+
+            assert_same_tree(&root.join("a"), &out.join("a"));
+            assert_same_tree(&root.join("empty"), &out.join("empty"));
+            assert_same_tree(&root.join("symlink"), &out.join("symlink"));
+            assert_eq!(
+                fs::metadata(out.join("a/file2")).unwrap().ino(),
+                fs::metadata(out.join("a/b/link")).unwrap().ino()
+            );
+
+            // Allow the temporary directory to be removed.
+            fs::set_permissions(out.join("a/b"), fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        fs::set_permissions(root.join("a/b"), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn test_unpack_inline_data_without_backend() {
+        let src_dir = TempDir::new().unwrap();
+        let root = src_dir.as_path();
+        fs::create_dir(root.join("dir")).unwrap();
+        fs::write(root.join("dir/tiny1"), b"tiny file 1").unwrap();
+        fs::write(root.join("tiny2"), vec![0x2u8; 64]).unwrap();
+        fs::write(root.join("empty"), b"").unwrap();
+
+        // All files are inlined into the bootstrap, so there's no data blob to access.
+        let features = Features::try_from("inline-data").unwrap();
+        let (bootstrap_path, _blob_dir) = build_image(root, RafsVersion::V5, features);
+        let out_dir = TempDir::new().unwrap();
+        let config = Arc::new(ConfigV2::default());
+        config.internal.set_blob_accessible(false);
+        let out = out_dir.as_path().join("rootfs");
+        DirUnpacker::new(&bootstrap_path, None, out.to_str().unwrap())
+            .unwrap()
+            .unpack(config)
+            .unwrap();
+
+        assert_same_tree(root, &out);
+    }
+}
@@ -16,8 +16,9 @@ use nydus_rafs::{
     metadata::{RafsInodeExt, RafsSuper},
     RafsIterator,
 };
-use nydus_storage::backend::BlobBackend;
+use nydus_storage::backend::{BlobBackend, BlobReader};
 use nydus_storage::device::BlobInfo;
+use nydus_utils::compress::Algorithm;
 use tar::{Builder, Header};
 
 use self::pax::{
@@ -26,6 +27,9 @@ use self::pax::{
     PAXSpecialSectionBuilder,
 };
 
+pub use self::dir::DirUnpacker;
+
+mod dir;
 mod pax;
 
 pub trait Unpacker {
@@ -178,20 +182,7 @@ impl OCITarBuilderFactory {
         blobs: Vec<Arc<BlobInfo>>,
         blob_backend: &Option<Arc<dyn BlobBackend + Send + Sync>>,
     ) -> Result<OCIRegBuilder> {
-        let mut readers = HashMap::new();
-        let mut compressors = HashMap::new();
-        for blob in blobs {
-            let blob_backend = blob_backend
-                .as_deref()
-                .with_context(|| "both blob path or blob backend config are not specified")?;
-            let reader = blob_backend
-                .get_reader(blob.blob_id().as_str())
-                .map_err(|err| anyhow!("fail to get reader, error {:?}", err))?;
-
-            let compressor = blob.compressor();
-            readers.insert(blob.blob_index(), reader);
-            compressors.insert(blob.blob_index(), compressor);
-        }
+        let (readers, compressors) = create_blob_readers(blobs, blob_backend)?;
 
         Ok(OCIRegBuilder::new(
             Rc::new(PAXExtensionSectionBuilder::new()),
@@ -201,6 +192,29 @@ impl OCITarBuilderFactory {
     }
 }
 
+/// Create readers and get compressors for data blobs, indexed by blob index.
+fn create_blob_readers(
+    blobs: Vec<Arc<BlobInfo>>,
+    blob_backend: &Option<Arc<dyn BlobBackend + Send + Sync>>,
+) -> Result<(HashMap<u32, Arc<dyn BlobReader>>, HashMap<u32, Algorithm>)> {
+    let mut readers = HashMap::new();
+    let mut compressors = HashMap::new();
+    for blob in blobs {
+        let blob_backend = blob_backend
+            .as_deref()
+            .with_context(|| "both blob path or blob backend config are not specified")?;
+        let reader = blob_backend
+            .get_reader(blob.blob_id().as_str())
+            .map_err(|err| anyhow!("fail to get reader, error {:?}", err))?;
+
+        let compressor = blob.compressor();
+        readers.insert(blob.blob_index(), reader);
+        compressors.insert(blob.blob_index(), compressor);
+    }
+
+    Ok((readers, compressors))
+}
+
 struct OCITarBuilder {
     writer: Builder<File>,
     builders: Vec<Box<dyn SectionBuilder>>,
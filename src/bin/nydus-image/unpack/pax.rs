@@ -185,6 +185,10 @@ impl OCIRegBuilder {
     }
 
     fn build_data(&self, inode: &dyn RafsInodeExt) -> Box<dyn Read> {
+        if let Some(data) = inode.get_inline_data() {
+            return Box::new(Cursor::new(data.to_vec()));
+        }
+
         let chunks = (0..inode.get_chunk_count())
             .map(|i| inode.get_chunk_info(i).unwrap())
             .collect();
@@ -698,7 +702,7 @@ impl Util {
     }
 }
 
-struct ChunkReader {
+pub struct ChunkReader {
     compressors: HashMap<u32, Algorithm>,
     readers: HashMap<u32, Arc<dyn BlobReader>>,
 
@@ -707,7 +711,7 @@ struct ChunkReader {
 }
 
 impl ChunkReader {
-    fn new(
+    pub fn new(
         compressors: HashMap<u32, Algorithm>,
         readers: HashMap<u32, Arc<dyn BlobReader>>,
         chunks: Vec<Arc<dyn BlobChunkInfo>>,
@@ -728,22 +732,32 @@ impl ChunkReader {
             .get(&chunk.blob_index())
             .expect("No valid reader")
             .clone();
-        reader
+        let size = reader
             .read(buf.as_mut_slice(), chunk.compressed_offset())
             .map_err(|err| {
                 error!("fail to read chunk, error: {:?}", err);
                 anyhow!("fail to read chunk, error: {:?}", err)
             })?;
+        if size != buf.len() {
+            bail!(
+                "fail to read chunk, got {} bytes but expect {}",
+                size,
+                buf.len()
+            );
+        }
 
         if !chunk.is_compressed() {
             self.chunk = Cursor::new(buf);
             return Ok(());
         }
 
-        let compressor = *self
-            .compressors
-            .get(&chunk.blob_index())
-            .expect("No valid compressor");
+        let compressor = match chunk.compressor() {
+            Some(compressor) => compressor,
+            None => *self
+                .compressors
+                .get(&chunk.blob_index())
+                .expect("No valid compressor"),
+        };
 
         let mut data = vec![0u8; chunk.uncompressed_size() as usize];
         compress::decompress(buf.as_mut_slice(), data.as_mut_slice(), compressor)
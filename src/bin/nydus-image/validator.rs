@@ -4,20 +4,38 @@
 
 //! Validator for RAFS format
 
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use anyhow::{Context, Result};
-use nydus_api::ConfigV2;
+use anyhow::{anyhow, bail, Context, Result};
+use nydus_api::{ConfigV2, LocalFsConfig};
 use nydus_builder::Tree;
+use nydus_rafs::metadata::chunk::ChunkWrapper;
 use nydus_rafs::metadata::{RafsSuper, RafsVersion};
-use nydus_storage::device::BlobInfo;
+use nydus_storage::backend::localfs::LocalFs;
+use nydus_storage::backend::BlobBackend;
+use nydus_storage::device::{BlobChunkInfo, BlobFeatures, BlobInfo};
+use nydus_storage::meta::BlobCompressionContextInfo;
 use nydus_utils::compress;
+use nydus_utils::digest::{self, DigestHasher, RafsDigest};
 
 pub struct Validator {
     sb: RafsSuper,
 }
 
+/// A local data blob file to verify chunks against.
+struct BlobFile {
+    file: File,
+    /// Blob meta to locate batch chunks, only loaded for blobs with batch chunks.
+    meta: Option<BlobCompressionContextInfo>,
+    /// The most recently decompressed batch and its index.
+    batch: Option<(u32, Vec<u8>)>,
+}
+
 impl Validator {
     pub fn new(bootstrap_path: &Path, config: Arc<ConfigV2>) -> Result<Self> {
         let (sb, _) = RafsSuper::load_from_file(bootstrap_path, config, false)?;
@@ -52,4 +70,383 @@ impl Validator {
             rafs_version,
         ))
     }
+
+    /// Verify the inode digest tree and chunk data of the filesystem.
+    ///
+    /// `blob_files` maps blob ids to local data blob files, chunks from other blobs are skipped.
+    pub fn verify(&mut self, blob_files: &HashMap<String, PathBuf>) -> Result<()> {
+        let err = "failed to load bootstrap for validator";
+        let tree = Tree::from_bootstrap(&self.sb, &mut ()).context(err)?;
+        if self.sb.meta.is_v5() {
+            Self::verify_digest_tree(&tree, self.sb.meta.get_digester())?;
+        }
+
+        // Blob meta extracted from data blobs is cached in a private work directory.
+        let work_dir =
+            std::env::temp_dir().join(format!("nydus-image-verify-{}", std::process::id()));
+        let result = Self::verify_chunks(&tree, &self.sb, blob_files, &work_dir);
+        if work_dir.exists() {
+            if let Err(e) = fs::remove_dir_all(&work_dir) {
+                warn!(
+                    "failed to remove work directory {}: {}",
+                    work_dir.display(),
+                    e
+                );
+            }
+        }
+        result
+    }
+
+    fn verify_chunks(
+        tree: &Tree,
+        sb: &RafsSuper,
+        blob_files: &HashMap<String, PathBuf>,
+        work_dir: &Path,
+    ) -> Result<()> {
+        let blob_infos = sb.superblock.get_blob_infos();
+        let mut files = HashMap::new();
+        for (idx, blob) in blob_infos.iter().enumerate() {
+            if let Some(path) = blob_files.get(&blob.blob_id()) {
+                let file = File::open(path)
+                    .with_context(|| format!("failed to open data blob {}", path.display()))?;
+                let meta = if blob.has_feature(BlobFeatures::BATCH) {
+                    Some(Self::load_blob_meta(path, blob, work_dir)?)
+                } else {
+                    None
+                };
+                let blob_file = BlobFile {
+                    file,
+                    meta,
+                    batch: None,
+                };
+                files.insert(idx as u32, blob_file);
+            }
+        }
+
+        let mut verified = HashSet::new();
+        let verify = &mut |t: &Tree| -> Result<()> {
+            let node = t.lock_node();
+            for chunk in node.chunks.iter() {
+                let chunk = &chunk.inner;
+                let blob_index = chunk.blob_index();
+                if let (Some(file), Some(blob)) = (
+                    files.get_mut(&blob_index),
+                    blob_infos.get(blob_index as usize),
+                ) {
+                    if verified.insert((blob_index, *chunk.id())) {
+                        Self::verify_chunk(file, blob, chunk).with_context(|| {
+                            format!("invalid data for file {:?}", node.target())
+                        })?;
+                    }
+                }
+            }
+            Ok(())
+        };
+        tree.walk_dfs_pre(verify)
+    }
+
+    fn load_blob_meta(
+        path: &Path,
+        blob: &BlobInfo,
+        work_dir: &Path,
+    ) -> Result<BlobCompressionContextInfo> {
+        fs::create_dir_all(work_dir)
+            .with_context(|| format!("failed to create work directory {}", work_dir.display()))?;
+        let config = LocalFsConfig {
+            blob_file: path.display().to_string(),
+            dir: Default::default(),
+            alt_dirs: Default::default(),
+            mmap: false,
+        };
+        let backend = LocalFs::new(&config, Some("validator"))
+            .with_context(|| format!("failed to create local backend for {:?}", path))?;
+        let reader = backend
+            .get_reader(&blob.blob_id())
+            .map_err(|err| anyhow!("failed to get reader, error {:?}", err))?;
+        let meta_path = work_dir.join(blob.blob_id());
+        BlobCompressionContextInfo::new(
+            &meta_path.display().to_string(),
+            blob,
+            Some(&reader),
+            false,
+        )
+        .with_context(|| format!("failed to load blob meta for blob {}", blob.blob_id()))
+    }
+
+    fn verify_digest_tree(tree: &Tree, digester: digest::Algorithm) -> Result<()> {
+        let verify = &mut |t: &Tree| -> Result<()> {
+            let node = t.lock_node();
+            let mut hasher = RafsDigest::hasher(digester);
+            if node.is_dir() {
+                for child in t.children.iter() {
+                    hasher.digest_update(child.lock_node().inode.digest().as_ref());
+                }
+            } else if node.is_reg() && node.inode.has_inline_data() {
+                // Data of tiny files is inlined into the inode and digested as a whole.
+                let data = node.info.symlink.as_ref().map(|v| v.as_bytes());
+                hasher.digest_update(data.unwrap_or_default());
+            } else if node.is_reg() && !node.chunks.is_empty() {
+                for chunk in node.chunks.iter() {
+                    hasher.digest_update(chunk.inner.id().as_ref());
+                }
+            } else {
+                return Ok(());
+            }
+
+            let digest = hasher.digest_finalize();
+            if &digest != node.inode.digest() {
+                bail!(
+                    "inode digest mismatch for {:?}, expect {}, got {}",
+                    node.target(),
+                    node.inode.digest(),
+                    digest
+                );
+            }
+            Ok(())
+        };
+        tree.walk_dfs_post(verify)
+    }
+
+    fn verify_chunk(file: &mut BlobFile, blob: &BlobInfo, chunk: &ChunkWrapper) -> Result<()> {
+        if chunk.is_encrypted() {
+            bail!("verifying encrypted chunks is not supported");
+        }
+
+        let is_batch = file
+            .meta
+            .as_ref()
+            .map(|meta| meta.is_batch_chunk(chunk.index()))
+            .unwrap_or_default();
+        let (offset, data) = if is_batch {
+            Self::read_batch_chunk(file, blob, chunk)?
+        } else {
+            (
+                chunk.compressed_offset(),
+                Self::read_chunk(&file.file, blob, chunk)?,
+            )
+        };
+
+        let digest = RafsDigest::from_buf(&data, blob.digester());
+        if &digest != chunk.id() {
+            bail!(
+                "chunk digest mismatch at offset 0x{:x} of blob {}, expect {}, got {}",
+                offset,
+                blob.blob_id(),
+                chunk.id(),
+                digest
+            );
+        }
+        Ok(())
+    }
+
+    /// Read a batch chunk by decompressing the whole batch, return the batch offset and data.
+    fn read_batch_chunk(
+        file: &mut BlobFile,
+        blob: &BlobInfo,
+        chunk: &ChunkWrapper,
+    ) -> Result<(u64, Vec<u8>)> {
+        // Safe to unwrap because batch chunks are only detected with blob meta.
+        let meta = file.meta.as_ref().unwrap();
+        let index = chunk.index();
+        let batch_index = meta.get_batch_index(index)?;
+        let c_offset = meta.get_chunk_info(index as usize).compressed_offset();
+        if !matches!(file.batch, Some((idx, _)) if idx == batch_index) {
+            let ctx = meta.get_batch_context(batch_index)?;
+            let c_size = ctx.compressed_size();
+            let d_size = ctx.uncompressed_batch_size();
+            let mut c_buf = vec![0u8; c_size as usize];
+            file.file
+                .read_exact_at(&mut c_buf, c_offset)
+                .with_context(|| {
+                    format!(
+                        "failed to read batch {} at offset 0x{:x}",
+                        batch_index, c_offset
+                    )
+                })?;
+            // Batches are stored as is if compression doesn't shrink them.
+            let data = if c_size != d_size {
+                let mut d_buf = vec![0u8; d_size as usize];
+                compress::decompress(&c_buf, &mut d_buf, blob.compressor()).with_context(|| {
+                    format!(
+                        "failed to decompress batch {} at offset 0x{:x}",
+                        batch_index, c_offset
+                    )
+                })?;
+                d_buf
+            } else {
+                c_buf
+            };
+            file.batch = Some((batch_index, data));
+        }
+
+        let data = &file.batch.as_ref().unwrap().1;
+        let start = meta.get_uncompressed_offset_in_batch_buf(index)? as usize;
+        let end = start + chunk.uncompressed_size() as usize;
+        if end > data.len() {
+            bail!(
+                "chunk {} exceeds batch {} of size 0x{:x}",
+                chunk.id(),
+                batch_index,
+                data.len()
+            );
+        }
+        Ok((c_offset, data[start..end].to_vec()))
+    }
+
+    fn read_chunk(file: &File, blob: &BlobInfo, chunk: &ChunkWrapper) -> Result<Vec<u8>> {
+        let mut c_buf = vec![0u8; chunk.compressed_size() as usize];
+        file.read_exact_at(&mut c_buf, chunk.compressed_offset())
+            .with_context(|| {
+                format!(
+                    "failed to read chunk {} at offset 0x{:x}",
+                    chunk.id(),
+                    chunk.compressed_offset()
+                )
+            })?;
+        let data = if chunk.is_compressed() {
+            let mut d_buf = vec![0u8; chunk.uncompressed_size() as usize];
+            let compressor = chunk.compressor().unwrap_or_else(|| blob.compressor());
+            compress::decompress(&c_buf, &mut d_buf, compressor).with_context(|| {
+                format!(
+                    "failed to decompress chunk {} at offset 0x{:x}",
+                    chunk.id(),
+                    chunk.compressed_offset()
+                )
+            })?;
+            d_buf
+        } else {
+            c_buf
+        };
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+
+    use nydus_builder::test_utils::{build_with, new_build_context, set_batch_size};
+    use nydus_builder::{ConversionType, DirectoryBuilder, Features};
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_verify_after_build() {
+        let src_dir = TempDir::new().unwrap();
+        let blob_dir = TempDir::new().unwrap();
+        let content = (0..0x100000 + 0x123)
+            .map(|v| (v % 251) as u8)
+            .collect::<Vec<u8>>();
+        fs::write(src_dir.as_path().join("file"), &content).unwrap();
+
+        // Store chunks uncompressed, so the test can corrupt a known chunk.
+        let mut ctx = new_build_context(
+            ConversionType::DirectoryToRafs,
+            src_dir.as_path(),
+            blob_dir.as_path(),
+            Features::new(),
+        );
+        ctx.compressor = compress::Algorithm::None;
+        let bootstrap_path = blob_dir.as_path().join("bootstrap");
+        let output = build_with(
+            &mut DirectoryBuilder::new(),
+            &mut ctx,
+            Some(&bootstrap_path),
+            None,
+        );
+        assert_eq!(output.blobs.len(), 1);
+        let blob_path = blob_dir.as_path().join(&output.blobs[0]);
+        let mut blob_files = HashMap::new();
+        blob_files.insert(output.blobs[0].clone(), blob_path.clone());
+
+        let config = Arc::new(ConfigV2::default());
+        let mut validator = Validator::new(&bootstrap_path, config.clone()).unwrap();
+        validator.verify(&blob_files).unwrap();
+
+        // Corrupt the first data chunk in the blob.
+        let file = OpenOptions::new().write(true).open(&blob_path).unwrap();
+        file.write_all_at(&[!content[0]], 0).unwrap();
+        let mut validator = Validator::new(&bootstrap_path, config).unwrap();
+        let err = validator.verify(&blob_files).unwrap_err();
+        assert!(format!("{:#}", err).contains("chunk digest mismatch at offset 0x0"));
+    }
+
+    #[test]
+    fn test_verify_batch_chunks() {
+        let src_dir = TempDir::new().unwrap();
+        let blob_dir = TempDir::new().unwrap();
+        for idx in 0..4u8 {
+            let path = src_dir.as_path().join(format!("small{}", idx));
+            fs::write(path, vec![idx + 1; 0x800]).unwrap();
+        }
+
+        // Store batches uncompressed, so the test can corrupt a known chunk.
+        let mut ctx = new_build_context(
+            ConversionType::DirectoryToRafs,
+            src_dir.as_path(),
+            blob_dir.as_path(),
+            Features::new(),
+        );
+        ctx.set_fs_version(RafsVersion::V6);
+        ctx.compressor = compress::Algorithm::None;
+        set_batch_size(&mut ctx, 0x10000);
+        let bootstrap_path = blob_dir.as_path().join("bootstrap");
+        let output = build_with(
+            &mut DirectoryBuilder::new(),
+            &mut ctx,
+            Some(&bootstrap_path),
+            None,
+        );
+        assert_eq!(output.blobs.len(), 1);
+        let blob_path = blob_dir.as_path().join(&output.blobs[0]);
+        let mut blob_files = HashMap::new();
+        blob_files.insert(output.blobs[0].clone(), blob_path.clone());
+
+        let config = Arc::new(ConfigV2::default());
+        let mut validator = Validator::new(&bootstrap_path, config.clone()).unwrap();
+        validator.verify(&blob_files).unwrap();
+
+        // Corrupt the last chunk packed into the batch at the start of the blob.
+        let file = OpenOptions::new().write(true).open(&blob_path).unwrap();
+        file.write_all_at(&[0], 0x1800).unwrap();
+        let mut validator = Validator::new(&bootstrap_path, config).unwrap();
+        let err = validator.verify(&blob_files).unwrap_err();
+        assert!(format!("{:#}", err).contains("chunk digest mismatch at offset 0x0"));
+    }
+
+    #[test]
+    fn test_verify_inline_data() {
+        let src_dir = TempDir::new().unwrap();
+        let blob_dir = TempDir::new().unwrap();
+        fs::write(src_dir.as_path().join("tiny"), b"tiny inline content").unwrap();
+        let mut ctx = new_build_context(
+            ConversionType::DirectoryToRafs,
+            src_dir.as_path(),
+            blob_dir.as_path(),
+            Features::try_from("inline-data").unwrap(),
+        );
+        ctx.set_fs_version(RafsVersion::V5);
+        let bootstrap_path = blob_dir.as_path().join("bootstrap");
+        let output = build_with(
+            &mut DirectoryBuilder::new(),
+            &mut ctx,
+            Some(&bootstrap_path),
+            None,
+        );
+        assert!(output.blobs.is_empty());
+
+        let config = Arc::new(ConfigV2::default());
+        let mut validator = Validator::new(&bootstrap_path, config.clone()).unwrap();
+        validator.verify(&HashMap::new()).unwrap();
+
+        // Corrupt the data inlined into the inode.
+        let mut bootstrap = fs::read(&bootstrap_path).unwrap();
+        let pos = bootstrap.windows(6).position(|v| v == b"inline").unwrap();
+        bootstrap[pos] = b'I';
+        fs::write(&bootstrap_path, bootstrap).unwrap();
+        let mut validator = Validator::new(&bootstrap_path, config).unwrap();
+        let err = validator.verify(&HashMap::new()).unwrap_err();
+        assert!(format!("{:#}", err).contains("inode digest mismatch for \"/tiny\""));
+    }
 }
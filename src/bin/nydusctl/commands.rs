@@ -216,12 +216,14 @@ Backend Type:       {backend_type}
 Read Amount:        {read_amount} Bytes ({read_count_mb} MB)
 Read Count:         {read_count}
 Read Errors:        {read_errors}
+Decompress Errors:  {decompress_errors}
 "#,
                 backend_type = m["backend_type"],
                 read_amount = m["read_amount_total"],
                 read_count = m["read_count"],
                 read_count_mb = m["read_amount_total"].as_f64().unwrap() / 1024.0 / 1024.0,
                 read_errors = m["read_errors"],
+                decompress_errors = m["decompress_errors"],
             );
 
             println!(
@@ -16,8 +16,9 @@ use nix::unistd::Pid;
 use nydus::daemon::NydusDaemon;
 use nydus::{FsBackendMountCmd, FsBackendType, FsBackendUmountCmd, FsService};
 use nydus_api::{
-    start_http_thread, ApiError, ApiMountCmd, ApiRequest, ApiResponse, ApiResponsePayload,
-    ApiResult, BlobCacheEntry, BlobCacheObjectId, DaemonConf, DaemonErrorKind, MetricsErrorKind,
+    start_http_thread, ApiError, ApiFadviseCmd, ApiMountCmd, ApiRequest, ApiResponse,
+    ApiResponsePayload, ApiResult, ApiWarmCmd, BlobCacheEntry, BlobCacheObjectId, DaemonConf,
+    DaemonErrorKind, DaemonThreadsConf, MetricsErrorKind,
 };
 use nydus_utils::metrics;
 
@@ -36,7 +37,9 @@ impl ApiServer {
         let resp = match request {
             // Common (v1/v2)
             ApiRequest::ConfigureDaemon(conf) => self.configure_daemon(conf),
+            ApiRequest::SetDaemonThreads(conf) => self.set_daemon_threads(conf),
             ApiRequest::GetDaemonInfo => self.daemon_info(true),
+            ApiRequest::GetDaemonVersion => Self::daemon_version(),
             ApiRequest::GetEvents => Self::events(),
             ApiRequest::Exit => self.do_exit(),
             ApiRequest::Start => self.do_start(),
@@ -45,6 +48,7 @@ impl ApiServer {
             ApiRequest::Mount(mountpoint, info) => self.do_mount(mountpoint, info),
             ApiRequest::Remount(mountpoint, info) => self.do_remount(mountpoint, info),
             ApiRequest::Umount(mountpoint) => self.do_umount(mountpoint),
+            ApiRequest::ValidateMount(info) => self.do_validate_mount(info),
             ApiRequest::ExportBackendMetrics(id) => Self::export_backend_metrics(id),
             ApiRequest::ExportBlobcacheMetrics(id) => Self::export_blobcache_metrics(id),
 
@@ -55,7 +59,12 @@ impl ApiServer {
             }
             ApiRequest::ExportFsAccessPatterns(id) => Self::export_access_patterns(id),
             ApiRequest::ExportFsBackendInfo(mountpoint) => self.backend_info(&mountpoint),
+            ApiRequest::ExportFsConfig(mountpoint) => self.fs_config(mountpoint.as_deref()),
+            ApiRequest::WarmFs(mountpoint, cmd) => self.warm_fs(&mountpoint, &cmd),
+            ApiRequest::GetWarmFsStatus(mountpoint) => self.warm_fs_status(&mountpoint),
+            ApiRequest::FadviseFs(mountpoint, cmd) => self.fadvise_fs(&mountpoint, &cmd),
             ApiRequest::ExportFsInflightMetrics => self.export_inflight_metrics(),
+            ApiRequest::ExportFsSlowOpsMetrics => self.export_slow_ops_metrics(),
 
             // Nydus API v2
             ApiRequest::GetDaemonInfoV2 => self.daemon_info(false),
@@ -89,6 +98,13 @@ impl ApiServer {
             })
     }
 
+    fn set_daemon_threads(&self, conf: DaemonThreadsConf) -> ApiResponse {
+        self.get_daemon_object()?
+            .set_threads(conf.threads)
+            .map(|_| ApiResponsePayload::Empty)
+            .map_err(|e| ApiError::DaemonAbnormal(e.into()))
+    }
+
     fn daemon_info(&self, include_fs_info: bool) -> ApiResponse {
         self.get_daemon_object()?
             .export_info(include_fs_info)
@@ -96,6 +112,11 @@ impl ApiServer {
             .map(ApiResponsePayload::DaemonInfo)
     }
 
+    fn daemon_version() -> ApiResponse {
+        let (_, bti) = nydus::get_build_time_info();
+        Ok(ApiResponsePayload::DaemonInfo(bti.dump_json()))
+    }
+
     /// External supervisor wants this instance to exit. But it can't just die leave
     /// some pending or in-flight fuse messages un-handled. So this method guarantees
     /// all fuse messages read from kernel are handled and replies are sent back.
@@ -179,6 +200,37 @@ impl ApiServer {
         Ok(ApiResponsePayload::FsBackendInfo(info))
     }
 
+    fn fs_config(&self, mountpoint: Option<&str>) -> ApiResponse {
+        let config = self
+            .get_default_fs_service()?
+            .export_fs_config(mountpoint)
+            .map_err(|e| ApiError::Metrics(MetricsErrorKind::Daemon(e.into())))?;
+        Ok(ApiResponsePayload::FsConfig(config))
+    }
+
+    fn warm_fs(&self, mountpoint: &str, cmd: &ApiWarmCmd) -> ApiResponse {
+        let report = self
+            .get_default_fs_service()?
+            .warm_fs(mountpoint, cmd)
+            .map_err(|e| ApiError::DaemonAbnormal(e.into()))?;
+        Ok(ApiResponsePayload::FsWarm(report))
+    }
+
+    fn warm_fs_status(&self, mountpoint: &str) -> ApiResponse {
+        let report = self
+            .get_default_fs_service()?
+            .warm_fs_status(mountpoint)
+            .map_err(|e| ApiError::DaemonAbnormal(e.into()))?;
+        Ok(ApiResponsePayload::FsWarm(report))
+    }
+
+    fn fadvise_fs(&self, mountpoint: &str, cmd: &ApiFadviseCmd) -> ApiResponse {
+        self.get_default_fs_service()?
+            .fadvise_fs(mountpoint, cmd)
+            .map(|_| ApiResponsePayload::Empty)
+            .map_err(|e| ApiError::DaemonAbnormal(e.into()))
+    }
+
     /// Detect if there is fop being hang.
     /// `ApiResponsePayload::Empty` will be converted to http status code 204, which means
     /// there is no requests being processed right now.
@@ -218,19 +270,40 @@ impl ApiServer {
         }
     }
 
+    /// Get number of fuse requests taking longer than the slow request threshold so far,
+    /// including those still being processed, in form of `{"slow_ops": 1}`.
+    fn export_slow_ops_metrics(&self) -> ApiResponse {
+        let fs = self.get_default_fs_service()?;
+        let resp = serde_json::json!({ "slow_ops": fs.slow_ops() });
+        Ok(ApiResponsePayload::FsSlowOpsMetrics(resp.to_string()))
+    }
+
     fn do_mount(&self, mountpoint: String, cmd: ApiMountCmd) -> ApiResponse {
         let fs_type = FsBackendType::from_str(&cmd.fs_type)
             .map_err(|e| ApiError::MountFilesystem(e.into()))?;
         let fs = self.get_default_fs_service()?;
+        // Pinned state lives in the upgrade manager, so it can't be honored without one.
+        if cmd.pinned && fs.upgrade_mgr().is_none() {
+            return Err(ApiError::MountFilesystem(DaemonErrorKind::UpgradeManager(
+                "pinned filesystems require online upgrade support".to_string(),
+            )));
+        }
         fs.mount(FsBackendMountCmd {
             fs_type,
-            mountpoint,
+            mountpoint: mountpoint.clone(),
             config: cmd.config,
             source: cmd.source,
             prefetch_files: cmd.prefetch_files,
         })
-        .map(|_| ApiResponsePayload::Empty)
-        .map_err(|e| ApiError::MountFilesystem(e.into()))
+        .map_err(|e| ApiError::MountFilesystem(e.into()))?;
+        if cmd.pinned {
+            if let Some(mut mgr) = fs.upgrade_mgr() {
+                mgr.pin_mounts_state(&mountpoint)
+                    .map_err(|e| ApiError::MountFilesystem(e.into()))?;
+            }
+        }
+
+        Ok(ApiResponsePayload::Empty)
     }
 
     fn do_remount(&self, mountpoint: String, cmd: ApiMountCmd) -> ApiResponse {
@@ -255,6 +328,21 @@ impl ApiServer {
             .map_err(|e| ApiError::MountFilesystem(e.into()))
     }
 
+    fn do_validate_mount(&self, cmd: ApiMountCmd) -> ApiResponse {
+        let fs_type = FsBackendType::from_str(&cmd.fs_type)
+            .map_err(|e| ApiError::MountFilesystem(e.into()))?;
+        self.get_default_fs_service()?
+            .validate_mount(&FsBackendMountCmd {
+                fs_type,
+                mountpoint: String::new(),
+                config: cmd.config,
+                source: cmd.source,
+                prefetch_files: cmd.prefetch_files,
+            })
+            .map(|_| ApiResponsePayload::Empty)
+            .map_err(|e| ApiError::MountFilesystem(e.into()))
+    }
+
     fn send_fuse_fd(&self) -> ApiResponse {
         let d = self.get_daemon_object()?;
 
@@ -14,17 +14,19 @@ extern crate nydus_api;
 
 use std::convert::TryInto;
 use std::io::{Error, ErrorKind, Result};
+use std::time::Duration;
 
 use clap::{Arg, ArgAction, ArgMatches, Command};
 use nix::sys::signal;
 use rlimit::Resource;
 
-use nydus::{dump_program_info, get_build_time_info, setup_logging, SubCmdArgs};
+use nydus::{dump_program_info, get_build_time_info, setup_logging, LogFormat, SubCmdArgs};
 use nydus_api::{BuildTimeInfo, ConfigV2};
 use nydus_service::daemon::DaemonController;
 use nydus_service::{
-    create_daemon, create_fuse_daemon, create_vfs_backend, validate_threads_configuration,
-    Error as NydusError, FsBackendMountCmd, FsBackendType, ServiceArgs,
+    create_daemon, create_fuse_daemon, create_vfs_backend, fs_backend_selftest,
+    validate_threads_configuration, Error as NydusError, FsBackendMountCmd, FsBackendType,
+    ServiceArgs,
 };
 
 use crate::api_server_glue::ApiServerController;
@@ -49,6 +51,27 @@ fn thread_validator(v: &str) -> std::result::Result<String, String> {
     validate_threads_configuration(v).map(|s| s.to_string())
 }
 
+fn fuse_thread_validator(v: &str) -> std::result::Result<String, String> {
+    // Zero means to scale FUSE worker threads with the number of online CPUs.
+    if v == "0" {
+        Ok(v.to_string())
+    } else {
+        thread_validator(v)
+    }
+}
+
+fn mount_retry_validator(v: &str) -> std::result::Result<String, String> {
+    v.parse::<u32>()
+        .map(|_| v.to_string())
+        .map_err(|_| format!("invalid mount retry count {}", v))
+}
+
+fn slow_request_threshold_validator(v: &str) -> std::result::Result<String, String> {
+    v.parse::<u64>()
+        .map(|_| v.to_string())
+        .map_err(|_| format!("invalid slow request threshold {}", v))
+}
+
 fn append_fs_options(app: Command) -> Command {
     app.arg(
         Arg::new("bootstrap")
@@ -111,8 +134,24 @@ fn append_fuse_options(app: Command) -> Command {
             .long("fuse-threads")
             .alias("thread-num")
             .default_value("4")
-            .help("Number of worker threads to serve FUSE I/O requests")
-            .value_parser(thread_validator)
+            .help("Number of worker threads to serve FUSE I/O requests, 0 to use the number of online CPUs")
+            .value_parser(fuse_thread_validator)
+            .required(false),
+    )
+    .arg(
+        Arg::new("mount-retry")
+            .long("mount-retry")
+            .default_value("0")
+            .help("Number of times to retry mounting the FUSE session with backoff on failure")
+            .value_parser(mount_retry_validator)
+            .required(false),
+    )
+    .arg(
+        Arg::new("slow-request-threshold")
+            .long("slow-request-threshold")
+            .default_value("0")
+            .help("Log FUSE requests taking longer than the threshold in milliseconds, 0 to disable")
+            .value_parser(slow_request_threshold_validator)
             .required(false),
     )
     .arg(
@@ -199,6 +238,20 @@ fn append_singleton_subcmd_options(cmd: Command) -> Command {
     cmd.subcommand(subcmd)
 }
 
+fn append_selftest_subcmd_options(cmd: Command) -> Command {
+    let subcmd = Command::new("selftest")
+        .about("Check whether a RAFS filesystem could be served, without mounting it")
+        .arg(
+            Arg::new("bootstrap")
+                .long("bootstrap")
+                .short('B')
+                .alias("metadata")
+                .help("Path to the RAFS filesystem metadata file")
+                .required(true),
+        );
+    cmd.subcommand(subcmd)
+}
+
 fn prepare_commandline_options() -> Command {
     let cmdline = Command::new("nydusd")
         .about("Nydus daemon to provide BlobCache, FsCache, FUSE, Virtio-fs and container image services")
@@ -244,6 +297,15 @@ fn prepare_commandline_options() -> Command {
                 .required(false)
                 .global(true),
         )
+        .arg(
+            Arg::new("log-format")
+                .long("log-format")
+                .help("Format of log messages")
+                .default_value("text")
+                .value_parser(["text", "json"])
+                .required(false)
+                .global(true),
+        )
         .arg(
             Arg::new("log-rotation-size")
                 .long("log-rotation-size")
@@ -285,6 +347,7 @@ fn prepare_commandline_options() -> Command {
     let cmdline = append_virtiofs_subcmd_options(cmdline);
     #[cfg(feature = "block-nbd")]
     let cmdline = self::nbd::append_nbd_subcmd_options(cmdline);
+    let cmdline = append_selftest_subcmd_options(cmdline);
     append_singleton_subcmd_options(cmdline)
 }
 
@@ -488,6 +551,16 @@ fn process_fs_service(
             .map(|n| n.parse().unwrap_or(1))
             .unwrap_or(1);
 
+        let mount_retry: u32 = args
+            .value_of("mount-retry")
+            .map(|n| n.parse().unwrap_or(0))
+            .unwrap_or(0);
+
+        let slow_threshold: u64 = args
+            .value_of("slow-request-threshold")
+            .map(|n| n.parse().unwrap_or(0))
+            .unwrap_or(0);
+
         let p = args
             .value_of("failover-policy")
             .unwrap_or(&"flush".to_string())
@@ -514,6 +587,8 @@ fn process_fs_service(
                 args.is_present("upgrade"),
                 !args.is_present("writable"),
                 p,
+                mount_retry,
+                Duration::from_millis(slow_threshold),
                 mount_cmd,
                 bti,
             )
@@ -719,6 +794,70 @@ mod nbd {
     }
 }
 
+// Check the FUSE device, configuration, storage backend and filesystem metadata one by one,
+// and print a report without establishing a mount.
+fn process_selftest(args: SubCmdArgs) -> Result<()> {
+    let report = |item: &str, result: Result<()>| -> bool {
+        match result {
+            Ok(()) => {
+                println!("{:<12}ok", item);
+                true
+            }
+            Err(e) => {
+                println!("{:<12}failed, {}", item, e);
+                false
+            }
+        }
+    };
+
+    #[cfg(target_os = "linux")]
+    let fuse_ok = report(
+        "fuse",
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/fuse")
+            .map(|_| ()),
+    );
+    #[cfg(not(target_os = "linux"))]
+    let fuse_ok = true;
+
+    let config = match args.value_of("config") {
+        Some(v) => std::fs::read_to_string(v)
+            .and_then(|content| content.parse::<ConfigV2>().map(|_| content)),
+        None => Err(einval!("option `-C/--config` is required")),
+    };
+    let (config, config_ok) = match config {
+        Ok(config) => (Some(config), report("config", Ok(()))),
+        Err(e) => (None, report("config", Err(e))),
+    };
+
+    let fs_ok = match config {
+        Some(config) => {
+            // Safe to unwrap because `bootstrap` is a mandatory option.
+            let bootstrap = args.value_of("bootstrap").unwrap();
+            let cmd = FsBackendMountCmd {
+                fs_type: FsBackendType::Rafs,
+                source: bootstrap.to_string(),
+                config,
+                mountpoint: "/".to_string(),
+                prefetch_files: None,
+            };
+            report(
+                "filesystem",
+                fs_backend_selftest(&cmd).map_err(|e| e.into()),
+            )
+        }
+        None => report("filesystem", Err(eother!("skipped due to invalid config"))),
+    };
+
+    if fuse_ok && config_ok && fs_ok {
+        Ok(())
+    } else {
+        Err(eother!("selftest failed"))
+    }
+}
+
 extern "C" fn sig_exit(_sig: std::os::raw::c_int) {
     DAEMON_CONTROLLER.shutdown();
 }
@@ -741,7 +880,13 @@ fn main() -> Result<()> {
         .parse::<u64>()
         .map_err(|e| einval!(format!("Invalid log rotation size: {}", e)))?;
 
-    setup_logging(logging_file, level, rotation_size)?;
+    // Safe to unwrap because it has default value and possible values are defined
+    let log_format = args
+        .get_one::<String>("log-format")
+        .unwrap()
+        .parse::<LogFormat>()?;
+
+    setup_logging(logging_file, level, rotation_size, log_format)?;
 
     // Initialize and run the daemon controller event loop.
     nydus::register_signal_handler(signal::SIGINT, sig_exit);
@@ -776,6 +921,12 @@ fn main() -> Result<()> {
             let subargs = SubCmdArgs::new(&args, subargs);
             self::nbd::process_nbd_service(subargs, bti, apisock)?;
         }
+        Some("selftest") => {
+            // Safe to unwrap because the subcommand is `selftest`.
+            let subargs = args.subcommand_matches("selftest").unwrap();
+            let subargs = SubCmdArgs::new(&args, subargs);
+            return process_selftest(subargs);
+        }
         _ => {
             let subargs = SubCmdArgs::new(&args, &args);
             process_fs_service(subargs, bti, apisock, true)?;
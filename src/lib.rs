@@ -11,7 +11,7 @@ use clap::parser::ValuesRef;
 use clap::ArgMatches;
 use nydus_api::BuildTimeInfo;
 
-pub use logger::{log_level_to_verbosity, setup_logging};
+pub use logger::{log_level_to_verbosity, setup_logging, LogFormat};
 pub use nydus_service::*;
 pub use signal::register_signal_handler;
 
@@ -95,3 +95,19 @@ pub fn get_build_time_info() -> (String, BuildTimeInfo) {
 
     (info_string, info)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dump_build_time_info_json() {
+        let (_, bti) = get_build_time_info();
+        let v: serde_json::Value = serde_json::from_str(&bti.dump_json()).unwrap();
+        assert_eq!(v["package_ver"], built_info::GIT_COMMIT_VERSION);
+        assert_eq!(v["git_commit"], built_info::GIT_COMMIT_HASH);
+        assert_eq!(v["build_time"], built_info::BUILT_TIME_UTC);
+        assert_eq!(v["profile"], built_info::PROFILE);
+        assert_eq!(v["rustc"], built_info::RUSTC_VERSION);
+    }
+}
@@ -6,12 +6,39 @@
 use std::env::current_dir;
 use std::io::Result;
 use std::path::PathBuf;
+use std::str::FromStr;
 
 use flexi_logger::{
     self, style, Cleanup, Criterion, DeferredNow, FileSpec, Logger, Naming,
     TS_DASHES_BLANK_COLONS_DOT_BLANK,
 };
 use log::{Level, LevelFilter, Record};
+use serde_json::json;
+
+/// Timestamp format for structured log records, which is compatible with RFC 3339.
+const TS_RFC3339: &str = "%Y-%m-%dT%H:%M:%S%.6f%:z";
+
+/// Format of log messages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable text lines.
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = std::io::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(einval!(format!("invalid log format {}", s))),
+        }
+    }
+}
 
 pub fn log_level_to_verbosity(level: log::LevelFilter) -> usize {
     if level == log::LevelFilter::Off {
@@ -85,6 +112,24 @@ fn colored_opt_format(
     }
 }
 
+fn json_format(
+    w: &mut dyn std::io::Write,
+    now: &mut DeferredNow,
+    record: &Record,
+) -> std::result::Result<(), std::io::Error> {
+    let msg = json!({
+        "ts": now.format(TS_RFC3339).to_string(),
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+        "fields": {
+            "file": get_file_name(record).unwrap_or("<unnamed>"),
+            "line": record.line().unwrap_or(0),
+        },
+    });
+    write!(w, "{}", msg)
+}
+
 /// Setup logging infrastructure for application.
 ///
 /// `log_file_path` is an absolute path to logging files or relative path from current working
@@ -92,10 +137,14 @@ fn colored_opt_format(
 /// Flexi logger always appends a suffix to file name whose default value is ".log"
 /// unless we set it intentionally. I don't like this passion. When the basename of `log_file_path`
 /// is "bar", the newly created log file will be "bar.log"
+///
+/// With [LogFormat::Json], each log record is emitted as a JSON object per line, with keys
+/// `ts`, `level`, `target`, `message` and `fields`.
 pub fn setup_logging(
     log_file_path: Option<PathBuf>,
     level: LevelFilter,
     rotation_size: u64,
+    format: LogFormat,
 ) -> Result<()> {
     if let Some(ref path) = log_file_path {
         // Do not try to canonicalize the path since the file may not exist yet.
@@ -145,7 +194,10 @@ pub fn setup_logging(
             .map_err(|_e| enosys!())?
             .log_to_file(spec)
             .append()
-            .format(opt_format);
+            .format(match format {
+                LogFormat::Text => opt_format,
+                LogFormat::Json => json_format,
+            });
 
         // Set log rotation
         if rotation_size > 0 {
@@ -167,7 +219,10 @@ pub fn setup_logging(
         // can't change log level to a higher level than what is passed to `flexi_logger`.
         Logger::try_with_env_or_str("trace")
             .map_err(|_e| enosys!())?
-            .format(colored_opt_format)
+            .format(match format {
+                LogFormat::Text => colored_opt_format,
+                LogFormat::Json => json_format,
+            })
             .start()
             .map_err(|e| eother!(e))?;
     }
@@ -199,6 +254,52 @@ mod tests {
         let level = LevelFilter::Info;
         let rotation_size = 1; // 1MB
 
-        assert!(setup_logging(log_file, level, rotation_size).is_ok());
+        assert!(setup_logging(log_file, level, rotation_size, LogFormat::Text).is_ok());
+    }
+
+    #[test]
+    fn test_log_format() {
+        assert_eq!(LogFormat::from_str("text").unwrap(), LogFormat::Text);
+        assert_eq!(LogFormat::from_str("json").unwrap(), LogFormat::Json);
+        assert!(LogFormat::from_str("xml").is_err());
+    }
+
+    #[test]
+    fn test_json_format() {
+        let mut buf = Vec::new();
+        let mut now = DeferredNow::new();
+        for (level, msg) in [(Level::Info, "mounted"), (Level::Error, "bad \"quote\"\n")] {
+            // Build the record in place, `format_args!()` can't outlive the statement.
+            json_format(
+                &mut buf,
+                &mut now,
+                &Record::builder()
+                    .args(format_args!("{}", msg))
+                    .level(level)
+                    .target("nydusd")
+                    .file(Some("service/src/fs_service.rs"))
+                    .line(Some(42))
+                    .build(),
+            )
+            .unwrap();
+            buf.push(b'\n');
+        }
+
+        let lines = std::str::from_utf8(&buf)
+            .unwrap()
+            .lines()
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        for line in lines.iter() {
+            let v: serde_json::Value = serde_json::from_str(line).unwrap();
+            for key in ["ts", "level", "target", "message", "fields"] {
+                assert!(v.get(key).is_some(), "missing key {} in {}", key, line);
+            }
+            assert_eq!(v["target"], "nydusd");
+            assert_eq!(v["fields"]["line"], 42);
+        }
+        let v: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(v["level"], "ERROR");
+        assert_eq!(v["message"], "bad \"quote\"\n");
     }
 }
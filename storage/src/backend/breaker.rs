@@ -0,0 +1,238 @@
+// Copyright (C) 2023 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Storage backend wrapper to fast-fail reads when the underlying backend keeps failing.
+//!
+//! When a remote backend is down, each read retries and times out, so requests pile up and
+//! fuse worker threads get stuck for a long time. The [BreakerBackend] counts consecutive read
+//! failures, and once the count reaches the threshold, it rejects reads immediately with
+//! [BackendError::Unavailable] for a cooldown window. After the cooldown, reads are sent to the
+//! underlying backend again to probe whether it has recovered.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use fuse_backend_rs::file_buf::FileVolatileSlice;
+use nydus_utils::metrics::BackendMetrics;
+
+use super::{BackendError, BackendResult, BlobBackend, BlobReader};
+
+#[derive(Default)]
+struct BreakerState {
+    // Number of consecutive failures.
+    failures: u32,
+    // Reads are rejected until this instant once the breaker is open.
+    open_until: Option<Instant>,
+}
+
+struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    fn new(threshold: u32, cooldown: Duration) -> Self {
+        CircuitBreaker {
+            threshold,
+            cooldown,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    fn check(&self, blob_id: &str) -> BackendResult<()> {
+        let state = self.state.lock().unwrap();
+        match state.open_until {
+            Some(deadline) if Instant::now() < deadline => Err(BackendError::Unavailable(format!(
+                "circuit breaker is open after {} consecutive failures, skip reading blob {}",
+                state.failures, blob_id
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    fn record<T>(&self, result: &BackendResult<T>) {
+        let mut state = self.state.lock().unwrap();
+        if result.is_ok() {
+            if state.open_until.is_some() {
+                info!("storage backend recovered, close circuit breaker");
+            }
+            state.failures = 0;
+            state.open_until = None;
+        } else {
+            state.failures = state.failures.saturating_add(1);
+            // A failed probe after the cooldown opens the breaker again immediately.
+            if state.failures >= self.threshold {
+                if state.open_until.is_none() {
+                    warn!(
+                        "storage backend failed {} times in a row, open circuit breaker",
+                        state.failures
+                    );
+                }
+                state.open_until = Some(Instant::now() + self.cooldown);
+            }
+        }
+    }
+}
+
+struct BreakerReader {
+    blob_id: String,
+    reader: Arc<dyn BlobReader>,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl BlobReader for BreakerReader {
+    fn blob_size(&self) -> BackendResult<u64> {
+        self.reader.blob_size()
+    }
+
+    fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        self.breaker.check(&self.blob_id)?;
+        let result = self.reader.try_read(buf, offset);
+        self.breaker.record(&result);
+        result
+    }
+
+    fn read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        self.breaker.check(&self.blob_id)?;
+        let result = self.reader.read(buf, offset);
+        self.breaker.record(&result);
+        result
+    }
+
+    fn readv(
+        &self,
+        bufs: &[FileVolatileSlice],
+        offset: u64,
+        max_size: usize,
+    ) -> BackendResult<usize> {
+        self.breaker.check(&self.blob_id)?;
+        let result = self.reader.readv(bufs, offset, max_size);
+        self.breaker.record(&result);
+        result
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        self.reader.metrics()
+    }
+
+    fn retry_limit(&self) -> u8 {
+        self.reader.retry_limit()
+    }
+
+    fn is_remote(&self) -> bool {
+        self.reader.is_remote()
+    }
+}
+
+/// A storage backend wrapper to fast-fail reads after repeated failures of another backend.
+///
+/// The state of the circuit breaker is shared by all blob readers created from the same
+/// [BreakerBackend] object.
+pub struct BreakerBackend {
+    backend: Arc<dyn BlobBackend + Send + Sync>,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl BreakerBackend {
+    /// Create a new instance of [BreakerBackend].
+    ///
+    /// Reads are rejected for `cooldown` after `threshold` consecutive failures.
+    pub fn new(
+        backend: Arc<dyn BlobBackend + Send + Sync>,
+        threshold: u32,
+        cooldown: Duration,
+    ) -> Self {
+        BreakerBackend {
+            backend,
+            breaker: Arc::new(CircuitBreaker::new(std::cmp::max(threshold, 1), cooldown)),
+        }
+    }
+}
+
+impl BlobBackend for BreakerBackend {
+    fn shutdown(&self) {
+        self.backend.shutdown()
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        self.backend.metrics()
+    }
+
+    fn get_reader(&self, blob_id: &str) -> BackendResult<Arc<dyn BlobReader>> {
+        let reader = self.backend.get_reader(blob_id)?;
+        Ok(Arc::new(BreakerReader {
+            blob_id: blob_id.to_string(),
+            reader,
+            breaker: self.breaker.clone(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+
+    use crate::test::{MockControl, MockControlledBackend};
+
+    #[test]
+    fn test_breaker_backend() {
+        let backend = Arc::new(MockControlledBackend::new(
+            "test_breaker_backend",
+            MockControl::default(),
+        ));
+        let control = backend.control.clone();
+        let metrics = backend.metrics.clone();
+        let backend = BreakerBackend::new(backend, 3, Duration::from_millis(200));
+        let reader = backend.get_reader("blob1").unwrap();
+        let mut buf = [0u8; 16];
+
+        assert_eq!(reader.read(&mut buf, 0).unwrap(), 16);
+        control.failing.store(true, Ordering::SeqCst);
+        for _ in 0..3 {
+            let err = reader.read(&mut buf, 0).unwrap_err();
+            assert!(matches!(err, BackendError::Unsupported(_)));
+        }
+        assert_eq!(control.reads.load(Ordering::SeqCst), 4);
+
+        // The breaker is open, reads from all readers fail fast without touching the backend.
+        let reader2 = backend.get_reader("blob2").unwrap();
+        for _ in 0..8 {
+            let err = reader.read(&mut buf, 0).unwrap_err();
+            assert!(matches!(err, BackendError::Unavailable(_)));
+            let err = reader2.try_read(&mut buf, 0).unwrap_err();
+            assert!(matches!(err, BackendError::Unavailable(_)));
+        }
+        assert_eq!(control.reads.load(Ordering::SeqCst), 4);
+
+        // A failed probe after the cooldown opens the breaker again.
+        std::thread::sleep(Duration::from_millis(250));
+        let err = reader.read(&mut buf, 0).unwrap_err();
+        assert!(matches!(err, BackendError::Unsupported(_)));
+        assert_eq!(control.reads.load(Ordering::SeqCst), 5);
+        let err = reader.read(&mut buf, 0).unwrap_err();
+        assert!(matches!(err, BackendError::Unavailable(_)));
+        assert_eq!(control.reads.load(Ordering::SeqCst), 5);
+
+        // The breaker closes once the backend recovers.
+        control.failing.store(false, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(250));
+        for _ in 0..4 {
+            assert_eq!(reader.read(&mut buf, 0).unwrap(), 16);
+            assert_eq!(reader2.read(&mut buf, 0).unwrap(), 16);
+        }
+        assert_eq!(control.reads.load(Ordering::SeqCst), 13);
+
+        // Failures are counted from scratch after the breaker is closed.
+        control.failing.store(true, Ordering::SeqCst);
+        for _ in 0..2 {
+            let err = reader.read(&mut buf, 0).unwrap_err();
+            assert!(matches!(err, BackendError::Unsupported(_)));
+        }
+        control.failing.store(false, Ordering::SeqCst);
+        assert_eq!(reader.read(&mut buf, 0).unwrap(), 16);
+        metrics.release().unwrap();
+    }
+}
@@ -74,6 +74,7 @@ pub(crate) struct ConnectionConfig {
     pub timeout: u32,
     pub connect_timeout: u32,
     pub retry_limit: u8,
+    pub idle_timeout: u32,
 }
 
 impl Default for ConnectionConfig {
@@ -85,6 +86,7 @@ impl Default for ConnectionConfig {
             timeout: 5,
             connect_timeout: 5,
             retry_limit: 0,
+            idle_timeout: 90,
         }
     }
 }
@@ -98,6 +100,7 @@ impl From<OssConfig> for ConnectionConfig {
             timeout: c.timeout,
             connect_timeout: c.connect_timeout,
             retry_limit: c.retry_limit,
+            idle_timeout: c.idle_timeout,
         }
     }
 }
@@ -111,6 +114,7 @@ impl From<S3Config> for ConnectionConfig {
             timeout: c.timeout,
             connect_timeout: c.connect_timeout,
             retry_limit: c.retry_limit,
+            idle_timeout: c.idle_timeout,
         }
     }
 }
@@ -124,6 +128,7 @@ impl From<RegistryConfig> for ConnectionConfig {
             timeout: c.timeout,
             connect_timeout: c.connect_timeout,
             retry_limit: c.retry_limit,
+            idle_timeout: c.idle_timeout,
         }
     }
 }
@@ -137,6 +142,7 @@ impl From<HttpProxyConfig> for ConnectionConfig {
             timeout: c.timeout,
             connect_timeout: c.connect_timeout,
             retry_limit: c.retry_limit,
+            idle_timeout: c.idle_timeout,
         }
     }
 }
@@ -608,9 +614,18 @@ impl Connection {
             None
         };
 
+        // Idle connections are reaped by the connection pool in background and re-established
+        // on demand.
+        let idle_timeout = if config.idle_timeout != 0 {
+            Some(Duration::from_secs(config.idle_timeout as u64))
+        } else {
+            None
+        };
+
         let mut cb = Client::builder()
             .timeout(timeout)
             .connect_timeout(connect_timeout)
+            .pool_idle_timeout(idle_timeout)
             .redirect(Policy::none());
 
         if config.skip_verify {
@@ -691,7 +706,9 @@ impl Connection {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::io::Cursor;
+    use std::io::{BufRead, BufReader, Cursor, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc;
 
     #[test]
     fn test_progress() {
@@ -740,10 +757,69 @@ mod tests {
         assert_eq!(config.timeout, 5);
         assert_eq!(config.connect_timeout, 5);
         assert_eq!(config.retry_limit, 0);
+        assert_eq!(config.idle_timeout, 90);
         assert_eq!(config.proxy.check_interval, 5);
         assert!(config.proxy.fallback);
         assert_eq!(config.proxy.ping_url, "");
         assert_eq!(config.proxy.url, "");
         assert!(config.mirrors.is_empty());
     }
+
+    // Serve one HTTP request with an empty response body over the keep-alive connection.
+    fn serve_request(stream: &TcpStream) {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" || line.is_empty() {
+                break;
+            }
+        }
+        let mut stream = stream;
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_connection_idle_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/blob", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            serve_request(&stream);
+            // Wait for the client to close the idle connection.
+            stream
+                .set_read_timeout(Some(Duration::from_secs(10)))
+                .unwrap();
+            let mut buf = [0u8; 1];
+            let closed = matches!((&stream).read(&mut buf), Ok(0));
+            tx.send(closed).unwrap();
+
+            // The connection should be re-established for the next request.
+            let (stream, _) = listener.accept().unwrap();
+            serve_request(&stream);
+        });
+
+        let config = ConnectionConfig {
+            idle_timeout: 1,
+            ..Default::default()
+        };
+        let connection = Connection::new(&config).unwrap();
+        let request = || {
+            let resp = connection
+                .call::<&[u8]>(Method::GET, &url, None, None, &mut HeaderMap::new(), true)
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert!(resp.bytes().unwrap().is_empty());
+        };
+
+        request();
+        assert!(rx.recv_timeout(Duration::from_secs(10)).unwrap());
+        request();
+        server.join().unwrap();
+    }
 }
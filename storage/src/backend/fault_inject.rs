@@ -0,0 +1,194 @@
+// Copyright (C) 2023 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Storage backend wrapper to inject faults into reads, for testing only.
+//!
+//! Resilience features such as retrying, circuit breaking and mirrors are hard to test against
+//! real storage backends, which fail at random. The [FaultInjectBackend] wraps another backend
+//! and may be programmed to fail, delay or corrupt a specific read, so those features can be
+//! tested deterministically.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use nydus_utils::metrics::BackendMetrics;
+
+use super::{BackendError, BackendResult, BlobBackend, BlobReader};
+
+/// Fault to inject into a read from the storage backend.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Fault {
+    /// Fail the read with [BackendError::Unavailable].
+    Error,
+    /// Delay the read by the duration before reading from the underlying backend.
+    Delay(Duration),
+    /// Flip all bits of the first byte of data read from the underlying backend.
+    Corrupt,
+}
+
+#[derive(Default)]
+struct FaultState {
+    // Number of reads issued so far, including retried ones.
+    reads: AtomicU64,
+    // Faults to inject, indexed by the sequence number of reads.
+    faults: Mutex<HashMap<u64, Fault>>,
+}
+
+struct FaultInjectReader {
+    reader: Arc<dyn BlobReader>,
+    state: Arc<FaultState>,
+}
+
+impl BlobReader for FaultInjectReader {
+    fn blob_size(&self) -> BackendResult<u64> {
+        self.reader.blob_size()
+    }
+
+    fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        let nth = self.state.reads.fetch_add(1, Ordering::AcqRel) + 1;
+        let fault = self.state.faults.lock().unwrap().remove(&nth);
+        match fault {
+            Some(Fault::Error) => Err(BackendError::Unavailable(format!(
+                "injected fault into read {}",
+                nth
+            ))),
+            Some(Fault::Delay(delay)) => {
+                std::thread::sleep(delay);
+                self.reader.try_read(buf, offset)
+            }
+            Some(Fault::Corrupt) => {
+                let size = self.reader.try_read(buf, offset)?;
+                if size > 0 {
+                    buf[0] = !buf[0];
+                }
+                Ok(size)
+            }
+            None => self.reader.try_read(buf, offset),
+        }
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        self.reader.metrics()
+    }
+
+    fn retry_limit(&self) -> u8 {
+        self.reader.retry_limit()
+    }
+
+    fn is_remote(&self) -> bool {
+        self.reader.is_remote()
+    }
+}
+
+/// A storage backend wrapper to inject faults into reads from another backend.
+///
+/// Reads are numbered from 1 in issuing order, shared by all blob readers created from the same
+/// [FaultInjectBackend] object. Each retry of a read counts as a new read.
+pub struct FaultInjectBackend {
+    backend: Arc<dyn BlobBackend + Send + Sync>,
+    state: Arc<FaultState>,
+}
+
+impl FaultInjectBackend {
+    /// Create a new instance of [FaultInjectBackend] without any fault programmed.
+    pub fn new(backend: Arc<dyn BlobBackend + Send + Sync>) -> Self {
+        FaultInjectBackend {
+            backend,
+            state: Arc::new(FaultState::default()),
+        }
+    }
+
+    /// Inject `fault` into the `nth` read, replacing the fault programmed for it.
+    pub fn inject(&self, nth: u64, fault: Fault) {
+        self.state.faults.lock().unwrap().insert(nth, fault);
+    }
+
+    /// Get number of reads issued so far.
+    pub fn reads(&self) -> u64 {
+        self.state.reads.load(Ordering::Acquire)
+    }
+}
+
+impl BlobBackend for FaultInjectBackend {
+    fn shutdown(&self) {
+        self.backend.shutdown()
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        self.backend.metrics()
+    }
+
+    fn get_reader(&self, blob_id: &str) -> BackendResult<Arc<dyn BlobReader>> {
+        let reader = self.backend.get_reader(blob_id)?;
+        Ok(Arc::new(FaultInjectReader {
+            reader,
+            state: self.state.clone(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    use crate::test::{MockControl, MockControlledBackend};
+
+    fn new_backend(id: &str, retry_limit: u8) -> FaultInjectBackend {
+        let control = MockControl {
+            retry_limit,
+            ..Default::default()
+        };
+        FaultInjectBackend::new(Arc::new(MockControlledBackend::new(id, control)))
+    }
+
+    #[test]
+    fn test_fault_inject_backend() {
+        let backend = new_backend("test_fault_inject_backend", 0);
+        backend.inject(2, Fault::Error);
+        backend.inject(3, Fault::Delay(Duration::from_millis(100)));
+        backend.inject(4, Fault::Corrupt);
+        let reader = backend.get_reader("blob").unwrap();
+        let mut buf = [0u8; 16];
+
+        assert_eq!(reader.read(&mut buf, 0x10).unwrap(), 16);
+        assert_eq!(buf[0], 0x10);
+
+        let err = reader.read(&mut buf, 0x10).unwrap_err();
+        assert!(matches!(err, BackendError::Unavailable(_)));
+
+        let start = Instant::now();
+        assert_eq!(reader.read(&mut buf, 0x10).unwrap(), 16);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(buf[0], 0x10);
+
+        assert_eq!(reader.read(&mut buf, 0x10).unwrap(), 16);
+        assert_eq!(buf[0], !0x10);
+        assert_eq!(buf[1], 0x11);
+
+        // Faults are consumed once injected.
+        assert_eq!(reader.read(&mut buf, 0x10).unwrap(), 16);
+        assert_eq!(buf[0], 0x10);
+        assert_eq!(backend.reads(), 5);
+    }
+
+    #[test]
+    fn test_fault_inject_backend_with_retry() {
+        let backend = new_backend("test_fault_inject_backend_with_retry", 1);
+        backend.inject(1, Fault::Error);
+        let reader = backend.get_reader("blob").unwrap();
+        let mut buf = [0u8; 16];
+
+        // The failure is absorbed by retrying.
+        assert_eq!(reader.read(&mut buf, 0).unwrap(), 16);
+        assert_eq!(backend.reads(), 2);
+
+        backend.inject(3, Fault::Error);
+        backend.inject(4, Fault::Error);
+        assert!(reader.read(&mut buf, 0).is_err());
+        assert_eq!(backend.reads(), 4);
+    }
+}
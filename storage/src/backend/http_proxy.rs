@@ -268,6 +268,11 @@ impl BlobReader for HttpProxyReader {
     fn metrics(&self) -> &nydus_utils::metrics::BackendMetrics {
         &self.metrics
     }
+
+    fn is_remote(&self) -> bool {
+        // A proxy over unix domain socket is local.
+        matches!(self.client, Client::Remote(_))
+    }
 }
 
 impl HttpProxy {
@@ -0,0 +1,172 @@
+// Copyright (C) 2023 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Storage backend wrapper to limit concurrent reads from the underlying backend.
+//!
+//! When many fuse worker threads read data at the same time, an unbounded number of requests may
+//! be sent to the storage backend, which may overwhelm remote services such as image registries.
+//! The [LimitedBackend] wraps another backend and queues reads exceeding the limit.
+
+use std::sync::{Arc, Condvar, Mutex};
+
+use fuse_backend_rs::file_buf::FileVolatileSlice;
+use nydus_utils::metrics::BackendMetrics;
+
+use super::{BackendResult, BlobBackend, BlobReader};
+
+struct ReadLimiter {
+    max_reads: usize,
+    inflight: Mutex<usize>,
+    condvar: Condvar,
+}
+
+impl ReadLimiter {
+    fn new(max_reads: usize) -> Self {
+        ReadLimiter {
+            max_reads,
+            inflight: Mutex::new(0),
+            condvar: Condvar::new(),
+        }
+    }
+
+    fn acquire(&self) -> ReadLimiterGuard<'_> {
+        let mut inflight = self.inflight.lock().unwrap();
+        while *inflight >= self.max_reads {
+            inflight = self.condvar.wait(inflight).unwrap();
+        }
+        *inflight += 1;
+        ReadLimiterGuard { limiter: self }
+    }
+}
+
+struct ReadLimiterGuard<'a> {
+    limiter: &'a ReadLimiter,
+}
+
+impl Drop for ReadLimiterGuard<'_> {
+    fn drop(&mut self) {
+        *self.limiter.inflight.lock().unwrap() -= 1;
+        self.limiter.condvar.notify_one();
+    }
+}
+
+struct LimitedReader {
+    reader: Arc<dyn BlobReader>,
+    limiter: Arc<ReadLimiter>,
+}
+
+impl BlobReader for LimitedReader {
+    fn blob_size(&self) -> BackendResult<u64> {
+        self.reader.blob_size()
+    }
+
+    fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        let _guard = self.limiter.acquire();
+        self.reader.try_read(buf, offset)
+    }
+
+    fn read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        let _guard = self.limiter.acquire();
+        self.reader.read(buf, offset)
+    }
+
+    fn readv(
+        &self,
+        bufs: &[FileVolatileSlice],
+        offset: u64,
+        max_size: usize,
+    ) -> BackendResult<usize> {
+        let _guard = self.limiter.acquire();
+        self.reader.readv(bufs, offset, max_size)
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        self.reader.metrics()
+    }
+
+    fn retry_limit(&self) -> u8 {
+        self.reader.retry_limit()
+    }
+
+    fn is_remote(&self) -> bool {
+        self.reader.is_remote()
+    }
+}
+
+/// A storage backend wrapper to limit the number of concurrent reads from another backend.
+///
+/// The limit is shared by all blob readers created from the same [LimitedBackend] object.
+pub struct LimitedBackend {
+    backend: Arc<dyn BlobBackend + Send + Sync>,
+    limiter: Arc<ReadLimiter>,
+}
+
+impl LimitedBackend {
+    /// Create a new instance of [LimitedBackend] allowing at most `max_reads` concurrent reads.
+    pub fn new(backend: Arc<dyn BlobBackend + Send + Sync>, max_reads: usize) -> Self {
+        LimitedBackend {
+            backend,
+            limiter: Arc::new(ReadLimiter::new(std::cmp::max(max_reads, 1))),
+        }
+    }
+}
+
+impl BlobBackend for LimitedBackend {
+    fn shutdown(&self) {
+        self.backend.shutdown()
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        self.backend.metrics()
+    }
+
+    fn get_reader(&self, blob_id: &str) -> BackendResult<Arc<dyn BlobReader>> {
+        let reader = self.backend.get_reader(blob_id)?;
+        Ok(Arc::new(LimitedReader {
+            reader,
+            limiter: self.limiter.clone(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    use crate::test::{MockControl, MockControlledBackend};
+
+    #[test]
+    fn test_limited_backend() {
+        let control = MockControl {
+            delay: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let backend = Arc::new(MockControlledBackend::new("test_limited_backend", control));
+        let counter = backend.control.clone();
+        let metrics = backend.metrics.clone();
+        let backend = Arc::new(LimitedBackend::new(backend, 3));
+        assert_eq!(backend.get_blob_size("blob").unwrap(), 0x1000);
+
+        let mut handles = Vec::new();
+        for idx in 0..16 {
+            let reader = backend.get_reader(&format!("blob{}", idx % 4)).unwrap();
+            handles.push(std::thread::spawn(move || {
+                let mut buf = [0u8; 16];
+                for _ in 0..4 {
+                    assert_eq!(reader.read(&mut buf, 0).unwrap(), 16);
+                }
+            }));
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert!(counter.max_inflight.load(Ordering::SeqCst) <= 3);
+        assert!(counter.max_inflight.load(Ordering::SeqCst) >= 1);
+        assert_eq!(counter.inflight.load(Ordering::SeqCst), 0);
+        metrics.release().unwrap();
+    }
+}
@@ -4,6 +4,7 @@
 
 //! Storage backend driver to access blobs on local filesystems.
 
+use std::cmp;
 use std::collections::HashMap;
 use std::fmt;
 use std::fs::{File, OpenOptions};
@@ -16,10 +17,11 @@ use fuse_backend_rs::file_buf::FileVolatileSlice;
 use nix::sys::uio;
 
 use nydus_api::LocalFsConfig;
+use nydus_utils::filemap::{clone_file, FileMapState};
 use nydus_utils::metrics::BackendMetrics;
 
 use crate::backend::{BackendError, BackendResult, BlobBackend, BlobReader};
-use crate::utils::{readv, MemSliceCursor};
+use crate::utils::{copyv, readv, MemSliceCursor};
 
 type LocalFsResult<T> = std::result::Result<T, LocalFsError>;
 
@@ -48,9 +50,29 @@ impl From<LocalFsError> for BackendError {
 struct LocalFsEntry {
     id: String,
     file: File,
+    // Memory mapped blob file, to serve reads without syscalls.
+    map: Option<FileMapState>,
     metrics: Arc<BackendMetrics>,
 }
 
+impl LocalFsEntry {
+    // Get data in range [offset, offset + size) from the mapped blob file, truncated at the end
+    // of the blob file.
+    fn get_mapped_data<'a>(
+        &self,
+        map: &'a FileMapState,
+        offset: u64,
+        size: usize,
+    ) -> BackendResult<&'a [u8]> {
+        let offset = cmp::min(offset, map.size() as u64) as usize;
+        let size = cmp::min(size, map.size() - offset);
+        map.get_slice::<u8>(offset, size).map_err(|e| {
+            let msg = format!("failed to read data from blob {}, {}", self.id, e);
+            LocalFsError::ReadBlob(msg).into()
+        })
+    }
+}
+
 impl BlobReader for LocalFsEntry {
     fn blob_size(&self) -> BackendResult<u64> {
         self.file.metadata().map(|v| v.len()).map_err(|e| {
@@ -60,6 +82,12 @@ impl BlobReader for LocalFsEntry {
     }
 
     fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        if let Some(map) = self.map.as_ref() {
+            let data = self.get_mapped_data(map, offset, buf.len())?;
+            buf[..data.len()].copy_from_slice(data);
+            return Ok(data.len());
+        }
+
         uio::pread(self.file.as_raw_fd(), buf, offset as i64).map_err(|e| {
             let msg = format!("failed to read data from blob {}, {}", self.id, e);
             LocalFsError::ReadBlob(msg).into()
@@ -72,6 +100,17 @@ impl BlobReader for LocalFsEntry {
         offset: u64,
         max_size: usize,
     ) -> BackendResult<usize> {
+        if let Some(map) = self.map.as_ref() {
+            let size = cmp::min(max_size, bufs.iter().map(|v| v.len()).sum());
+            let data = self.get_mapped_data(map, offset, size)?;
+            return copyv(&[data], bufs, 0, data.len(), 0, 0)
+                .map(|(n, _)| n)
+                .map_err(|e| {
+                    let msg = format!("failed to read data from blob {}, {:?}", self.id, e);
+                    LocalFsError::ReadBlob(msg).into()
+                });
+        }
+
         let mut c = MemSliceCursor::new(bufs);
         let mut iovec = c.consume(max_size);
 
@@ -96,6 +135,8 @@ pub struct LocalFs {
     dir: String,
     // Alternative directories to store blob files
     alt_dirs: Vec<String>,
+    // Whether to memory map blob files.
+    mmap: bool,
     // Metrics collector.
     metrics: Arc<BackendMetrics>,
     // Hashmap to map blob id to blob file.
@@ -104,6 +145,15 @@ pub struct LocalFs {
 
 impl LocalFs {
     pub fn new(config: &LocalFsConfig, id: Option<&str>) -> Result<LocalFs> {
+        Self::new_with_type(config, id, "localfs")
+    }
+
+    // Create a `LocalFs` object to be wrapped by other backends, with metrics of `backend_type`.
+    pub(crate) fn new_with_type(
+        config: &LocalFsConfig,
+        id: Option<&str>,
+        backend_type: &str,
+    ) -> Result<LocalFs> {
         let id = id.ok_or_else(|| einval!("LocalFs requires blob_id"))?;
 
         if config.blob_file.is_empty() && config.dir.is_empty() {
@@ -114,7 +164,8 @@ impl LocalFs {
             blob_file: config.blob_file.clone(),
             dir: config.dir.clone(),
             alt_dirs: config.alt_dirs.clone(),
-            metrics: BackendMetrics::new(id, "localfs"),
+            mmap: config.mmap,
+            metrics: BackendMetrics::new(id, backend_type),
             entries: RwLock::new(HashMap::new()),
         })
     }
@@ -126,8 +177,7 @@ impl LocalFs {
             Path::new(&self.blob_file).to_path_buf()
         } else {
             // Search blob file in dir and additionally in alt_dirs
-            let is_valid = |dir: &PathBuf| -> bool {
-                let blob = Path::new(&dir).join(blob_id);
+            let is_valid = |blob: &PathBuf| -> bool {
                 if let Ok(meta) = std::fs::metadata(blob) {
                     meta.len() != 0
                 } else {
@@ -155,6 +205,25 @@ impl LocalFs {
         })
     }
 
+    // Memory map the whole blob file, empty files are not mapped.
+    fn map_blob_file(file: &File, path: &Path) -> LocalFsResult<Option<FileMapState>> {
+        let size = file.metadata().map(|v| v.len()).map_err(|e| {
+            let msg = format!("failed to get size of blob file {}, {}", path.display(), e);
+            LocalFsError::BlobFile(msg)
+        })?;
+        if size == 0 {
+            return Ok(None);
+        }
+
+        clone_file(file.as_raw_fd())
+            .and_then(|file| FileMapState::new(file, 0, size as usize, false))
+            .map(Some)
+            .map_err(|e| {
+                let msg = format!("failed to mmap blob file {}, {}", path.display(), e);
+                LocalFsError::BlobFile(msg)
+            })
+    }
+
     #[allow(clippy::mutex_atomic)]
     fn get_blob(&self, blob_id: &str) -> LocalFsResult<Arc<dyn BlobReader>> {
         // Don't expect poisoned lock here.
@@ -174,6 +243,11 @@ impl LocalFs {
                 );
                 LocalFsError::BlobFile(msg)
             })?;
+        let map = if self.mmap {
+            Self::map_blob_file(&file, &blob_file_path)?
+        } else {
+            None
+        };
         // Don't expect poisoned lock here.
         let mut table_guard = self.entries.write().unwrap();
         if let Some(entry) = table_guard.get(blob_id) {
@@ -182,6 +256,7 @@ impl LocalFs {
             let entry = Arc::new(LocalFsEntry {
                 id: blob_id.to_owned(),
                 file,
+                map,
                 metrics: self.metrics.clone(),
             });
             table_guard.insert(blob_id.to_string(), entry.clone());
@@ -221,6 +296,7 @@ mod tests {
             blob_file: "".to_string(),
             dir: "".to_string(),
             alt_dirs: Vec::new(),
+            mmap: false,
         };
         assert!(LocalFs::new(&config, Some("test")).is_err());
 
@@ -228,6 +304,7 @@ mod tests {
             blob_file: "/a/b/c".to_string(),
             dir: "/a/b".to_string(),
             alt_dirs: Vec::new(),
+            mmap: false,
         };
         assert!(LocalFs::new(&config, None).is_err());
     }
@@ -238,6 +315,7 @@ mod tests {
             blob_file: "/a/b/cxxxxxxxxxxxxxxxxxxxxxxx".to_string(),
             dir: "/a/b".to_string(),
             alt_dirs: Vec::new(),
+            mmap: false,
         };
         let fs = LocalFs::new(&config, Some("test")).unwrap();
         assert!(fs.get_blob_path("test").is_err());
@@ -250,6 +328,7 @@ mod tests {
             blob_file: path.to_str().unwrap().to_owned(),
             dir: path.parent().unwrap().to_str().unwrap().to_owned(),
             alt_dirs: Vec::new(),
+            mmap: false,
         };
         let fs = LocalFs::new(&config, Some("test")).unwrap();
         assert_eq!(fs.get_blob_path("test").unwrap().to_str(), path.to_str());
@@ -258,6 +337,7 @@ mod tests {
             blob_file: "".to_string(),
             dir: path.parent().unwrap().to_str().unwrap().to_owned(),
             alt_dirs: Vec::new(),
+            mmap: false,
         };
         let fs = LocalFs::new(&config, Some(filename)).unwrap();
         assert_eq!(fs.get_blob_path(filename).unwrap().to_str(), path.to_str());
@@ -269,6 +349,7 @@ mod tests {
                 "/test".to_string(),
                 path.parent().unwrap().to_str().unwrap().to_owned(),
             ],
+            mmap: false,
         };
         let fs = LocalFs::new(&config, Some(filename)).unwrap();
         assert_eq!(fs.get_blob_path(filename).unwrap().to_str(), path.to_str());
@@ -283,6 +364,7 @@ mod tests {
             blob_file: "".to_string(),
             dir: path.parent().unwrap().to_str().unwrap().to_owned(),
             alt_dirs: Vec::new(),
+            mmap: false,
         };
         let fs = LocalFs::new(&config, Some(filename)).unwrap();
         let blob1 = fs.get_blob(filename).unwrap();
@@ -307,6 +389,7 @@ mod tests {
             blob_file: "".to_string(),
             dir: path.parent().unwrap().to_str().unwrap().to_owned(),
             alt_dirs: Vec::new(),
+            mmap: false,
         };
         let fs = LocalFs::new(&config, Some(filename)).unwrap();
         let blob1 = fs.get_reader(filename).unwrap();
@@ -333,7 +416,120 @@ mod tests {
         assert_eq!(buf3[0], 0x3);
 
         assert_eq!(blob2.blob_size().unwrap(), 4);
+        assert_eq!(fs.get_blob_size(filename).unwrap(), 4);
+        assert!(fs.get_blob_size("nonexist").is_err());
         let blob4 = fs.get_blob(filename).unwrap();
         assert_eq!(blob4.blob_size().unwrap(), 4);
     }
+
+    #[test]
+    fn test_localfs_blob_file_readonly() {
+        let tempfile = TempFile::new().unwrap();
+        let path = tempfile.as_path();
+        let filename = path.file_name().unwrap().to_str().unwrap();
+        let config = LocalFsConfig {
+            blob_file: "".to_string(),
+            dir: path.parent().unwrap().to_str().unwrap().to_owned(),
+            alt_dirs: Vec::new(),
+            mmap: false,
+        };
+        let fs = LocalFs::new(&config, Some(filename)).unwrap();
+        fs.get_blob(filename).unwrap();
+
+        let entry = fs.entries.read().unwrap().get(filename).unwrap().clone();
+        let flags = unsafe { libc::fcntl(entry.file.as_raw_fd(), libc::F_GETFL) };
+        assert!(flags >= 0);
+        assert_eq!(flags & libc::O_ACCMODE, libc::O_RDONLY);
+        let err = (&entry.file).write(&[0x1u8]).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EBADF));
+    }
+
+    // Get number of read syscalls issued by the current thread.
+    #[cfg(target_os = "linux")]
+    fn read_syscalls() -> u64 {
+        let io = std::fs::read_to_string("/proc/thread-self/io").unwrap();
+        io.lines()
+            .find_map(|l| l.strip_prefix("syscr:"))
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_localfs_mmap_read() {
+        let tempfile = TempFile::new().unwrap();
+        let path = tempfile.as_path();
+        let filename = path.file_name().unwrap().to_str().unwrap();
+        let content = (0..0x10000 + 0x123)
+            .map(|v| (v % 251) as u8)
+            .collect::<Vec<u8>>();
+        std::fs::write(path, &content).unwrap();
+
+        let mut backends = Vec::new();
+        let mut readers = Vec::new();
+        for (id, mmap) in [("test-localfs-pread", false), ("test-localfs-mmap", true)] {
+            let config = LocalFsConfig {
+                blob_file: "".to_string(),
+                dir: path.parent().unwrap().to_str().unwrap().to_owned(),
+                alt_dirs: Vec::new(),
+                mmap,
+            };
+            let fs = LocalFs::new(&config, Some(id)).unwrap();
+            readers.push(fs.get_reader(filename).unwrap());
+            backends.push(fs);
+        }
+        assert_eq!(readers[1].blob_size().unwrap(), content.len() as u64);
+
+        for (offset, size) in [
+            (0usize, 0x100usize),
+            (0x1000, 0x10000),
+            (0x10000, 0x1000),
+            (content.len(), 0x10),
+            (0x20000, 0x10),
+        ] {
+            let mut buf1 = vec![0u8; size];
+            let mut buf2 = vec![0u8; size];
+            let r1 = readers[0].read(&mut buf1, offset as u64).unwrap();
+            let r2 = readers[1].read(&mut buf2, offset as u64).unwrap();
+            assert_eq!(r1, r2);
+            assert_eq!(buf1, buf2);
+            if offset < content.len() {
+                assert_eq!(&buf2[..r2], &content[offset..offset + r2]);
+            } else {
+                assert_eq!(r2, 0);
+            }
+
+            let mut bufs1 = [vec![0u8; size / 2], vec![0u8; size - size / 2]];
+            let mut bufs2 = [vec![0u8; size / 2], vec![0u8; size - size / 2]];
+            let slices1 = bufs1
+                .iter_mut()
+                .map(|v| unsafe { FileVolatileSlice::from_raw_ptr(v.as_mut_ptr(), v.len()) })
+                .collect::<Vec<_>>();
+            let slices2 = bufs2
+                .iter_mut()
+                .map(|v| unsafe { FileVolatileSlice::from_raw_ptr(v.as_mut_ptr(), v.len()) })
+                .collect::<Vec<_>>();
+            let r1 = readers[0].readv(&slices1, offset as u64, size).unwrap();
+            let r2 = readers[1].readv(&slices2, offset as u64, size).unwrap();
+            assert_eq!(r1, r2);
+            assert_eq!(bufs1, bufs2);
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            let count_syscalls = |reader: &Arc<dyn BlobReader>| {
+                let mut buf = [0u8; 0x100];
+                let start = read_syscalls();
+                for idx in 0..64u64 {
+                    assert_eq!(reader.read(&mut buf, idx * 0x100).unwrap(), 0x100);
+                }
+                read_syscalls() - start
+            };
+            let pread_syscalls = count_syscalls(&readers[0]);
+            let mmap_syscalls = count_syscalls(&readers[1]);
+            assert!(pread_syscalls >= 64);
+            assert!(mmap_syscalls < pread_syscalls);
+        }
+    }
 }
@@ -13,6 +13,15 @@
 //!   The [LocalFs](localfs/struct.LocalFs.html) storage backend supports backend level data
 //!   prefetching, which is to load data into page cache.
 //! - [LocalDisk](localdisk/struct.LocalDisk.html): backend driver to access blobs on local disk.
+//! - [OciLayer](oci_layer/struct.OciLayer.html): experimental backend driver to access layers of
+//!   OCI images on local file system.
+//!
+//! The [LimitedBackend](limiter/struct.LimitedBackend.html) wrapper may be stacked on top of any
+//! storage backend to limit the number of concurrent reads, and the
+//! [BreakerBackend](breaker/struct.BreakerBackend.html) wrapper to fast-fail reads when the
+//! storage backend keeps failing. The test only
+//! [FaultInjectBackend](fault_inject/struct.FaultInjectBackend.html) wrapper injects faults into
+//! reads, enabled by the `backend-fault-inject` feature.
 
 use std::fmt;
 use std::io::Read;
@@ -27,6 +36,7 @@ use nydus_utils::{
 use crate::utils::{alloc_buf, copyv};
 use crate::StorageError;
 
+pub mod breaker;
 #[cfg(any(
     feature = "backend-oss",
     feature = "backend-registry",
@@ -34,14 +44,19 @@ use crate::StorageError;
     feature = "backend-http-proxy",
 ))]
 pub mod connection;
+#[cfg(any(test, feature = "backend-fault-inject"))]
+pub mod fault_inject;
 #[cfg(feature = "backend-http-proxy")]
 pub mod http_proxy;
+pub mod limiter;
 #[cfg(feature = "backend-localdisk")]
 pub mod localdisk;
 #[cfg(feature = "backend-localfs")]
 pub mod localfs;
 #[cfg(any(feature = "backend-oss", feature = "backend-s3"))]
 pub mod object_storage;
+#[cfg(feature = "backend-oci-layer")]
+pub mod oci_layer;
 #[cfg(feature = "backend-oss")]
 pub mod oss;
 #[cfg(feature = "backend-registry")]
@@ -56,6 +71,8 @@ pub enum BackendError {
     Unsupported(String),
     /// Failed to copy data from/into blob.
     CopyData(StorageError),
+    /// Storage backend is temporarily unavailable due to repeated failures.
+    Unavailable(String),
     #[cfg(feature = "backend-localdisk")]
     /// Error from LocalDisk storage backend.
     LocalDisk(self::localdisk::LocalDiskError),
@@ -78,6 +95,7 @@ impl fmt::Display for BackendError {
         match self {
             BackendError::Unsupported(s) => write!(f, "{}", s),
             BackendError::CopyData(e) => write!(f, "failed to copy data, {}", e),
+            BackendError::Unavailable(s) => write!(f, "backend unavailable, {}", s),
             #[cfg(feature = "backend-registry")]
             BackendError::Registry(e) => write!(f, "{:?}", e),
             #[cfg(feature = "backend-localfs")]
@@ -189,6 +207,7 @@ pub trait BlobReader: Send + Sync {
             let size = std::cmp::min(size, max_size);
             let mut data = alloc_buf(size);
 
+            // Only copy out data actually read, the tail of `data` is uninitialized on short read.
             let result = self.read(&mut data, offset)?;
             copyv(&[&data], bufs, 0, result, 0, 0)
                 .map(|r| r.0)
@@ -203,6 +222,14 @@ pub trait BlobReader: Send + Sync {
     fn retry_limit(&self) -> u8 {
         0
     }
+
+    /// Check whether the blob is accessed over network.
+    ///
+    /// Each request to a network backend costs a round trip, so callers should prefer a few big
+    /// requests over many small ones.
+    fn is_remote(&self) -> bool {
+        false
+    }
 }
 
 /// Trait to access blob files on backend storages, such as OSS, registry, local fs etc.
@@ -215,6 +242,18 @@ pub trait BlobBackend: Send + Sync {
 
     /// Get a blob reader object to access blod `blob_id`.
     fn get_reader(&self, blob_id: &str) -> BackendResult<Arc<dyn BlobReader>>;
+
+    /// Get size of blob `blob_id` from the storage backend.
+    fn get_blob_size(&self, blob_id: &str) -> BackendResult<u64> {
+        self.get_reader(blob_id)?.blob_size()
+    }
+
+    /// Check whether blob `blob_id` of `size` bytes is already present in the storage backend.
+    ///
+    /// Blob ids are content digests, so storing the blob again may be skipped if it's present.
+    fn has_blob(&self, blob_id: &str, size: u64) -> bool {
+        matches!(self.get_blob_size(blob_id), Ok(v) if v == size)
+    }
 }
 
 /// A buffered reader for `BlobReader` object.
@@ -274,3 +313,95 @@ impl Read for BlobBufReader {
         Ok(sz)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct ShortReader {
+        limit: usize,
+        metrics: Arc<BackendMetrics>,
+    }
+
+    impl BlobReader for ShortReader {
+        fn blob_size(&self) -> BackendResult<u64> {
+            Ok(self.limit as u64)
+        }
+
+        fn try_read(&self, buf: &mut [u8], _offset: u64) -> BackendResult<usize> {
+            let size = std::cmp::min(buf.len(), self.limit);
+            buf[..size].fill(0x5a);
+            Ok(size)
+        }
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+    }
+
+    struct SizeBackend {
+        metrics: Arc<BackendMetrics>,
+    }
+
+    impl BlobBackend for SizeBackend {
+        fn shutdown(&self) {}
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+
+        fn get_reader(&self, blob_id: &str) -> BackendResult<Arc<dyn BlobReader>> {
+            let limit = blob_id
+                .parse::<usize>()
+                .map_err(|_| BackendError::Unsupported(format!("unknown blob {}", blob_id)))?;
+            Ok(Arc::new(ShortReader {
+                limit,
+                metrics: self.metrics.clone(),
+            }))
+        }
+    }
+
+    #[test]
+    fn test_get_blob_size() {
+        let backend = SizeBackend {
+            metrics: BackendMetrics::new("test_get_blob_size", "mock"),
+        };
+        assert_eq!(backend.get_blob_size("0").unwrap(), 0);
+        assert_eq!(backend.get_blob_size("4096").unwrap(), 4096);
+        assert!(backend.get_blob_size("nonexist").is_err());
+        backend.metrics.release().unwrap();
+    }
+
+    #[test]
+    fn test_has_blob() {
+        let backend = SizeBackend {
+            metrics: BackendMetrics::new("test_has_blob", "mock"),
+        };
+        assert!(backend.has_blob("4096", 4096));
+        assert!(!backend.has_blob("4096", 4095));
+        assert!(!backend.has_blob("nonexist", 0));
+        backend.metrics.release().unwrap();
+    }
+
+    #[test]
+    fn test_readv_short_read() {
+        let reader = ShortReader {
+            limit: 6,
+            metrics: BackendMetrics::new("test_readv_short_read", "mock"),
+        };
+        let mut buf1 = [0u8; 4];
+        let mut buf2 = [0u8; 4];
+        let bufs = unsafe {
+            [
+                FileVolatileSlice::from_raw_ptr(buf1.as_mut_ptr(), buf1.len()),
+                FileVolatileSlice::from_raw_ptr(buf2.as_mut_ptr(), buf2.len()),
+            ]
+        };
+
+        // Only data actually read from the backend is copied to the destination buffers.
+        assert_eq!(reader.readv(&bufs, 0, 8).unwrap(), 6);
+        assert_eq!(buf1, [0x5a; 4]);
+        assert_eq!(buf2, [0x5a, 0x5a, 0, 0]);
+        reader.metrics.release().unwrap();
+    }
+}
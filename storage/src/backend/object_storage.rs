@@ -143,6 +143,10 @@ where
     fn retry_limit(&self) -> u8 {
         self.state.retry_limit()
     }
+
+    fn is_remote(&self) -> bool {
+        true
+    }
 }
 
 #[derive(Debug)]
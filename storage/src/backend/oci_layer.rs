@@ -0,0 +1,132 @@
+// Copyright (C) 2023 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Storage backend driver to access layers of OCI images stored on local filesystems.
+//!
+//! The backend serves RAFS images converted in reference mode, such as by
+//! `nydus-image create --type targz-ref`, directly from the original OCI image layers, so there's
+//! no need to re-upload the layers as nydus data blobs. Chunk to layer offset mapping is recorded
+//! in the `blob.meta` generated at conversion time.
+
+use std::io::Result;
+use std::path::Path;
+use std::sync::Arc;
+
+use nydus_api::{LocalFsConfig, OciLayerConfig};
+use nydus_utils::metrics::BackendMetrics;
+
+use crate::backend::localfs::LocalFs;
+use crate::backend::{BackendResult, BlobBackend, BlobReader};
+
+/// Name of the file to mark a directory as OCI image layout.
+const OCI_LAYOUT_FILE: &str = "oci-layout";
+/// Digest algorithm used to name layers in OCI image layout.
+const OCI_DIGEST_ALGORITHM: &str = "sha256";
+
+/// Storage backend to read blobs from an OCI image layout on local filesystem.
+pub struct OciLayer {
+    fs: LocalFs,
+}
+
+impl OciLayer {
+    pub fn new(config: &OciLayerConfig, id: Option<&str>) -> Result<OciLayer> {
+        if config.dir.is_empty() {
+            return Err(einval!("OCI image layout dir is required"));
+        }
+        let layout = Path::new(&config.dir);
+        if !layout.join(OCI_LAYOUT_FILE).is_file() {
+            return Err(einval!(format!(
+                "{} is not an OCI image layout",
+                layout.display()
+            )));
+        }
+
+        let dir = layout.join("blobs").join(OCI_DIGEST_ALGORITHM);
+        let config = LocalFsConfig {
+            blob_file: String::new(),
+            dir: dir.to_string_lossy().to_string(),
+            alt_dirs: config.alt_dirs.clone(),
+            mmap: false,
+        };
+        let fs = LocalFs::new_with_type(&config, id, "oci-layer")?;
+
+        Ok(OciLayer { fs })
+    }
+}
+
+impl BlobBackend for OciLayer {
+    fn shutdown(&self) {
+        self.fs.shutdown()
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        self.fs.metrics()
+    }
+
+    fn get_reader(&self, blob_id: &str) -> BackendResult<Arc<dyn BlobReader>> {
+        // Layers are referenced by digest, such as `sha256:<hex>`.
+        let blob_id = blob_id
+            .strip_prefix(OCI_DIGEST_ALGORITHM)
+            .and_then(|v| v.strip_prefix(':'))
+            .unwrap_or(blob_id);
+        self.fs.get_reader(blob_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vmm_sys_util::tempdir::TempDir;
+
+    #[test]
+    fn test_oci_layer_new() {
+        let config = OciLayerConfig {
+            dir: "".to_string(),
+            alt_dirs: Vec::new(),
+        };
+        assert!(OciLayer::new(&config, Some("test")).is_err());
+
+        let tmp_dir = TempDir::new().unwrap();
+        let config = OciLayerConfig {
+            dir: tmp_dir.as_path().to_str().unwrap().to_owned(),
+            alt_dirs: Vec::new(),
+        };
+        assert!(OciLayer::new(&config, Some("test")).is_err());
+        std::fs::write(tmp_dir.as_path().join(OCI_LAYOUT_FILE), "{}").unwrap();
+        assert!(OciLayer::new(&config, Some("test")).is_ok());
+    }
+
+    #[test]
+    fn test_oci_layer_get_reader() {
+        let layout_dir = TempDir::new().unwrap();
+        let meta_dir = TempDir::new().unwrap();
+        let layer_id = "a".repeat(64);
+        let meta_id = "b".repeat(64);
+        let blobs_dir = layout_dir.as_path().join("blobs").join("sha256");
+        std::fs::create_dir_all(&blobs_dir).unwrap();
+        std::fs::write(layout_dir.as_path().join(OCI_LAYOUT_FILE), "{}").unwrap();
+        std::fs::write(blobs_dir.join(&layer_id), [0x1u8, 0x2, 0x3, 0x4]).unwrap();
+        std::fs::write(meta_dir.as_path().join(&meta_id), [0x5u8, 0x6]).unwrap();
+
+        let config = OciLayerConfig {
+            dir: layout_dir.as_path().to_str().unwrap().to_owned(),
+            alt_dirs: vec![meta_dir.as_path().to_str().unwrap().to_owned()],
+        };
+        let backend = OciLayer::new(&config, Some("test")).unwrap();
+
+        let reader = backend.get_reader(&layer_id).unwrap();
+        assert_eq!(reader.blob_size().unwrap(), 4);
+        let mut buf = [0u8; 2];
+        assert_eq!(reader.read(&mut buf, 2).unwrap(), 2);
+        assert_eq!(buf, [0x3, 0x4]);
+
+        let reader = backend.get_reader(&format!("sha256:{}", layer_id)).unwrap();
+        assert_eq!(reader.blob_size().unwrap(), 4);
+
+        let reader = backend.get_reader(&meta_id).unwrap();
+        assert_eq!(reader.blob_size().unwrap(), 2);
+
+        assert!(backend.get_reader(&"c".repeat(64)).is_err());
+    }
+}
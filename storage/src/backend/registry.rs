@@ -174,7 +174,7 @@ struct RegistryState {
     // HTTP scheme like: https, http
     scheme: Scheme,
     host: String,
-    // Image repo name like: library/ubuntu
+    // Image repo name like: library/ubuntu, prefixed with namespace if configured
     repo: String,
     // Base64 encoded registry auth
     auth: Option<String>,
@@ -186,6 +186,8 @@ struct RegistryState {
     blob_url_scheme: String,
     // Replace registry redirected url host with the given host
     blob_redirected_host: String,
+    // Template of blob url path like: /v2/{repo}/blobs/sha256:{blob_id}
+    blob_url_template: String,
     // Cache bearer token (get from registry authentication server) or basic authentication auth string.
     // We need use it to reduce the pressure on token authentication server or reduce the base64 compute workload for every request.
     // Use RwLock here to avoid using mut backend trait object.
@@ -220,6 +222,22 @@ impl RegistryState {
         Ok(url.to_string())
     }
 
+    fn blob_url(&self, blob_id: &str) -> std::result::Result<String, ParseError> {
+        if self.blob_url_template.is_empty() {
+            return self.url(&format!("/blobs/sha256:{}", blob_id), &[]);
+        }
+
+        let path = self
+            .blob_url_template
+            .replace("{repo}", &self.repo)
+            .replace("{blob_id}", blob_id);
+        let url = format!("{}://{}", self.scheme, self.host.as_str());
+        let url = Url::parse(url.as_str())?;
+        let url = url.join(path.as_str())?;
+
+        Ok(url.to_string())
+    }
+
     fn needs_fallback_http(&self, e: &dyn Error) -> bool {
         match e.source() {
             Some(err) => match err.source() {
@@ -634,11 +652,10 @@ impl RegistryReader {
         offset: u64,
         allow_retry: bool,
     ) -> RegistryResult<usize> {
-        let url = format!("/blobs/sha256:{}", self.blob_id);
         let url = self
             .state
-            .url(url.as_str(), &[])
-            .map_err(|e| RegistryError::Url(url, e))?;
+            .blob_url(&self.blob_id)
+            .map_err(|e| RegistryError::Url(self.blob_id.clone(), e))?;
         let mut headers = HeaderMap::new();
         let end_at = offset + buf.len() as u64 - 1;
         let range = format!("bytes={}-{}", offset, end_at);
@@ -685,11 +702,10 @@ impl RegistryReader {
                     if self.state.needs_fallback_http(&e) =>
                 {
                     self.state.fallback_http();
-                    let url = format!("/blobs/sha256:{}", self.blob_id);
                     let url = self
                         .state
-                        .url(url.as_str(), &[])
-                        .map_err(|e| RegistryError::Url(url, e))?;
+                        .blob_url(&self.blob_id)
+                        .map_err(|e| RegistryError::Url(self.blob_id.clone(), e))?;
                     self.request::<&[u8]>(Method::GET, url.as_str(), None, headers.clone(), false)?
                 }
                 Err(RegistryError::Request(ConnectionError::Common(e))) => {
@@ -769,11 +785,10 @@ impl RegistryReader {
 impl BlobReader for RegistryReader {
     fn blob_size(&self) -> BackendResult<u64> {
         self.first.handle_force(&mut || -> BackendResult<u64> {
-            let url = format!("/blobs/sha256:{}", self.blob_id);
             let url = self
                 .state
-                .url(&url, &[])
-                .map_err(|e| RegistryError::Url(url, e))?;
+                .blob_url(&self.blob_id)
+                .map_err(|e| RegistryError::Url(self.blob_id.clone(), e))?;
 
             let resp = match self.request::<&[u8]>(
                 Method::HEAD,
@@ -787,11 +802,10 @@ impl BlobReader for RegistryReader {
                     if self.state.needs_fallback_http(&e) =>
                 {
                     self.state.fallback_http();
-                    let url = format!("/blobs/sha256:{}", self.blob_id);
                     let url = self
                         .state
-                        .url(&url, &[])
-                        .map_err(|e| RegistryError::Url(url, e))?;
+                        .blob_url(&self.blob_id)
+                        .map_err(|e| RegistryError::Url(self.blob_id.clone(), e))?;
                     self.request::<&[u8]>(Method::HEAD, url.as_str(), None, HeaderMap::new(), true)?
                 }
                 Err(e) => {
@@ -827,6 +841,10 @@ impl BlobReader for RegistryReader {
     fn retry_limit(&self) -> u8 {
         self.state.retry_limit
     }
+
+    fn is_remote(&self) -> bool {
+        true
+    }
 }
 
 /// Storage backend based on image registry.
@@ -867,11 +885,17 @@ impl Registry {
         } else {
             Scheme::new(true)
         };
+        let namespace = config.namespace.trim_matches('/');
+        let repo = if namespace.is_empty() {
+            config.repo.clone()
+        } else {
+            format!("{}/{}", namespace, config.repo)
+        };
 
         let state = Arc::new(RegistryState {
             scheme,
             host: config.host.clone(),
-            repo: config.repo.clone(),
+            repo,
             auth,
             cached_auth,
             username,
@@ -879,6 +903,7 @@ impl Registry {
             retry_limit,
             blob_url_scheme: config.blob_url_scheme.clone(),
             blob_redirected_host: config.blob_redirected_host.clone(),
+            blob_url_template: config.blob_url_template.clone(),
             cached_auth_using_http_get: HashCache::new(),
             cached_redirect: HashCache::new(),
             token_expired_at: ArcSwapOption::new(None),
@@ -1061,6 +1086,7 @@ mod tests {
             retry_limit: 5,
             blob_url_scheme: "https".to_string(),
             blob_redirected_host: "oss.alibaba-inc.com".to_string(),
+            blob_url_template: String::new(),
             cached_auth_using_http_get: Default::default(),
             cached_auth: Default::default(),
             cached_redirect: Default::default(),
@@ -1078,6 +1104,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_blob_url_template() {
+        let mut config = RegistryConfig {
+            scheme: "https".to_string(),
+            host: "my-registry.com".to_string(),
+            repo: "test/repo".to_string(),
+            ..Default::default()
+        };
+        let registry = Registry::new(&config, Some("test_blob_url_template")).unwrap();
+        assert_eq!(
+            registry.state.blob_url("abcd").unwrap(),
+            "https://my-registry.com/v2/test/repo/blobs/sha256:abcd"
+        );
+
+        config.namespace = "/tenant/".to_string();
+        config.blob_url_template = "/v2/{repo}/blobs/sha256:{blob_id}?ns=tenant".to_string();
+        let registry = Registry::new(&config, Some("test_blob_url_template_ns")).unwrap();
+        assert_eq!(
+            registry.state.blob_url("abcd").unwrap(),
+            "https://my-registry.com/v2/tenant/test/repo/blobs/sha256:abcd?ns=tenant"
+        );
+    }
+
     #[test]
     fn test_parse_auth() {
         let str = "Bearer realm=\"https://auth.my-registry.com/token\",service=\"my-registry.com\",scope=\"repository:test/repo:pull,push\"";
@@ -9,13 +9,14 @@
 //! performance. It may be used by both the userspace `FileCacheMgr` or the `FsCacheMgr` based
 //! on the in-kernel fscache system.
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::{ErrorKind, Read, Result};
 use std::mem::ManuallyDrop;
+use std::os::unix::fs::MetadataExt;
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Condvar, Mutex};
 use std::time::Duration;
 
 use fuse_backend_rs::file_buf::FileVolatileSlice;
@@ -27,9 +28,10 @@ use nydus_utils::{compress, digest, round_up_usize, DelayType, Delayer, FileRang
 use tokio::runtime::Runtime;
 
 use crate::backend::BlobReader;
+use crate::cache::chunklru::ChunkLruCache;
 use crate::cache::state::ChunkMap;
 use crate::cache::worker::{AsyncPrefetchConfig, AsyncPrefetchMessage, AsyncWorkerMgr};
-use crate::cache::{BlobCache, BlobIoMergeState};
+use crate::cache::{BlobCache, BlobIoMergeState, CacheStats, CacheUsage};
 use crate::device::{
     BlobChunkInfo, BlobInfo, BlobIoDesc, BlobIoRange, BlobIoSegment, BlobIoTag, BlobIoVec,
     BlobObject, BlobPrefetchRequest,
@@ -41,6 +43,8 @@ use crate::{StorageError, StorageResult, RAFS_BATCH_SIZE_TO_GAP_SHIFT, RAFS_DEFA
 const DOWNLOAD_META_RETRY_COUNT: u32 = 5;
 const DOWNLOAD_META_RETRY_DELAY: u64 = 400;
 const ENCRYPTION_PAGE_SIZE: usize = 4096;
+// Timeout in milliseconds to wait for chunk data being persisted in background.
+const DELAYED_PERSIST_WAIT_TIMEOUT: u64 = 10000;
 
 #[derive(Default, Clone)]
 pub(crate) struct FileCacheMeta {
@@ -179,15 +183,50 @@ impl BlobCCI {
     }
 }
 
+// Tracer of chunk data being persisted into the cache file in background.
+#[derive(Default)]
+pub(crate) struct PersistTracer {
+    inflight: Mutex<usize>,
+    cond: Condvar,
+}
+
+impl PersistTracer {
+    fn begin(&self) {
+        *self.inflight.lock().unwrap() += 1;
+    }
+
+    fn end(&self) {
+        let mut inflight = self.inflight.lock().unwrap();
+        *inflight -= 1;
+        if *inflight == 0 {
+            self.cond.notify_all();
+        }
+    }
+
+    // Wait for all chunk data to be persisted, return false on timeout.
+    fn wait(&self, timeout: Duration) -> bool {
+        let inflight = self.inflight.lock().unwrap();
+        let (inflight, _) = self
+            .cond
+            .wait_timeout_while(inflight, timeout, |v| *v > 0)
+            .unwrap();
+        *inflight == 0
+    }
+}
+
 pub(crate) struct FileCacheEntry {
     pub(crate) blob_id: String,
     pub(crate) blob_info: Arc<BlobInfo>,
     pub(crate) cache_cipher_object: Arc<Cipher>,
     pub(crate) cache_cipher_context: Arc<CipherContext>,
+    pub(crate) cache_usage: Arc<CacheUsage>,
     pub(crate) chunk_map: Arc<dyn ChunkMap>,
+    // In-memory cache of decompressed chunks shared with other blobs of the cache manager.
+    pub(crate) decompressed_lru: Option<Arc<ChunkLruCache>>,
     pub(crate) file: Arc<File>,
     pub(crate) meta: Option<FileCacheMeta>,
     pub(crate) metrics: Arc<BlobcacheMetrics>,
+    pub(crate) persist_tracer: Arc<PersistTracer>,
     pub(crate) prefetch_state: Arc<AtomicU32>,
     pub(crate) reader: Arc<dyn BlobReader>,
     pub(crate) runtime: Arc<Runtime>,
@@ -234,14 +273,17 @@ impl FileCacheEntry {
 
     fn delay_persist_chunk_data(&self, chunk: Arc<dyn BlobChunkInfo>, buffer: Arc<DataBuffer>) {
         let delayed_chunk_map = self.chunk_map.clone();
+        let cache_usage = self.cache_usage.clone();
         let file = self.file.clone();
         let metrics = self.metrics.clone();
+        let persist_tracer = self.persist_tracer.clone();
         let is_raw_data = self.is_raw_data;
         let is_cache_encrypted = self.is_cache_encrypted;
         let cipher_object = self.cache_cipher_object.clone();
         let cipher_context = self.cache_cipher_context.clone();
 
         metrics.buffered_backend_size.add(buffer.size() as u64);
+        persist_tracer.begin();
         self.runtime.spawn_blocking(move || {
             metrics.buffered_backend_size.sub(buffer.size() as u64);
             let mut t_buf;
@@ -272,9 +314,11 @@ impl FileCacheEntry {
                         Err(_) => {
                             Self::_update_chunk_pending_status(
                                 &delayed_chunk_map,
+                                &cache_usage,
                                 chunk.as_ref(),
                                 false,
                             );
+                            persist_tracer.end();
                             return;
                         }
                     }
@@ -290,7 +334,13 @@ impl FileCacheEntry {
                 chunk.uncompressed_offset()
             };
             let res = Self::persist_cached_data(&file, offset, buf);
-            Self::_update_chunk_pending_status(&delayed_chunk_map, chunk.as_ref(), res.is_ok());
+            Self::_update_chunk_pending_status(
+                &delayed_chunk_map,
+                &cache_usage,
+                chunk.as_ref(),
+                res.is_ok(),
+            );
+            persist_tracer.end();
         });
     }
 
@@ -327,16 +377,17 @@ impl FileCacheEntry {
     }
 
     fn update_chunk_pending_status(&self, chunk: &dyn BlobChunkInfo, success: bool) {
-        Self::_update_chunk_pending_status(&self.chunk_map, chunk, success)
+        Self::_update_chunk_pending_status(&self.chunk_map, &self.cache_usage, chunk, success)
     }
 
     fn _update_chunk_pending_status(
         chunk_map: &Arc<dyn ChunkMap>,
+        cache_usage: &CacheUsage,
         chunk: &dyn BlobChunkInfo,
         success: bool,
     ) {
         if success {
-            if let Err(e) = chunk_map.set_ready_and_clear_pending(chunk) {
+            if let Err(e) = Self::_set_chunk_ready(chunk_map, cache_usage, chunk) {
                 error!(
                     "Failed change caching state for chunk of offset {}, {:?}",
                     chunk.compressed_offset(),
@@ -352,6 +403,110 @@ impl FileCacheEntry {
         }
     }
 
+    // Mark the chunk as ready and account it as cached data if it wasn't ready yet.
+    fn _set_chunk_ready(
+        chunk_map: &Arc<dyn ChunkMap>,
+        cache_usage: &CacheUsage,
+        chunk: &dyn BlobChunkInfo,
+    ) -> Result<()> {
+        let was_ready = chunk_map.is_ready(chunk)?;
+        chunk_map.set_ready_and_clear_pending(chunk)?;
+        if !was_ready {
+            cache_usage.add_chunk(chunk);
+        }
+        Ok(())
+    }
+
+    // Reset the chunk to not ready and stop accounting it as cached data.
+    fn clear_chunk_ready(&self, chunk: &dyn BlobChunkInfo) -> Result<()> {
+        let was_ready = self.chunk_map.is_ready(chunk)?;
+        self.chunk_map.clear_ready(chunk)?;
+        if was_ready {
+            self.cache_usage.remove_chunk(chunk);
+        }
+        Ok(())
+    }
+
+    // Account chunks already cached when the entry is opened, following changes of chunk state
+    // are accounted by `_set_chunk_ready()` and `clear_chunk_ready()` on the fly.
+    pub(crate) fn load_cache_usage(&self) -> Result<()> {
+        // Only indexed chunk maps persist chunk state, nothing has been cached for other maps.
+        let map = match self.chunk_map.as_range_map() {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        let ready_count = map.get_ready_count()?;
+        if ready_count == 0 {
+            return Ok(());
+        }
+
+        if self.meta.is_some() {
+            let (mut chunks, mut bytes) = (0, 0);
+            for idx in 0..self.blob_info.chunk_count() {
+                if map.is_range_ready(idx, 1)? {
+                    let chunk = self.get_chunk_info(idx).ok_or_else(|| {
+                        einval!(format!(
+                            "failed to get chunk {} of blob {}",
+                            idx, self.blob_id
+                        ))
+                    })?;
+                    chunks += 1;
+                    bytes += self.cache_usage.chunk_size(chunk.as_ref());
+                }
+            }
+            self.cache_usage.load(chunks, bytes);
+        } else {
+            // There's no chunk information array for RAFS v5 blobs, so estimate size of cached
+            // data by blocks allocated to the cache file.
+            let bytes = self.file.metadata()?.blocks() * 512;
+            self.cache_usage.load(ready_count as u64, bytes);
+        }
+
+        Ok(())
+    }
+
+    /// Collect statistics of blob caches managed by a cache manager.
+    ///
+    /// Blob caches only referenced by the manager itself are not used by any filesystem, so they
+    /// are accounted as evictable because they may be reclaimed by `gc()`.
+    pub(crate) fn collect_stats(blobs: &HashMap<String, Arc<FileCacheEntry>>) -> CacheStats {
+        let mut stats = CacheStats::default();
+
+        for entry in blobs.values() {
+            let entry_stats = entry.stats();
+            stats.merge(&entry_stats);
+            if Arc::strong_count(entry) == 1 {
+                stats.evictable_bytes += entry_stats.total_bytes;
+            }
+        }
+
+        stats
+    }
+
+    // Figure out the last one of pending chunks to fetch together with `pending[start]` from a
+    // network backend. Chunks are fetched by one request to save round trips, even though data
+    // of ready chunks in between gets downloaded again, as long as gaps between them are small
+    // and the request doesn't exceed the prefetch batch size.
+    fn get_remote_range_end(
+        &self,
+        pending: &[Arc<dyn BlobChunkInfo>],
+        start: usize,
+    ) -> Result<usize> {
+        let max_size = self.prefetch_batch_size();
+        let max_gap = max_size >> RAFS_BATCH_SIZE_TO_GAP_SHIFT;
+        let mut end = start;
+        while end < pending.len() - 1 {
+            let (_, prev_end, _) = self.get_blob_range(&pending[end..=end])?;
+            let (next_start, _, _) = self.get_blob_range(&pending[end + 1..=end + 1])?;
+            let (_, _, size) = self.get_blob_range(&pending[start..=end + 1])?;
+            if next_start.saturating_sub(prev_end) > max_gap || size as u64 > max_size {
+                break;
+            }
+            end += 1;
+        }
+        Ok(end)
+    }
+
     fn prefetch_batch_size(&self) -> u64 {
         if self.prefetch_config.batch_size < 0x2_0000 {
             0x2_0000
@@ -574,6 +729,17 @@ impl BlobCache for FileCacheEntry {
         &self.chunk_map
     }
 
+    fn flush(&self) -> Result<()> {
+        // Chunk data being persisted in background would be lost if the process exits now.
+        let timeout = Duration::from_millis(DELAYED_PERSIST_WAIT_TIMEOUT);
+        if !self.persist_tracer.wait(timeout) {
+            return Err(eio!("timeout to wait for chunk data to be persisted"));
+        }
+        // Persist cached data before chunk state, so chunks are never marked as ready without data.
+        self.file.sync_data()?;
+        self.chunk_map.flush()
+    }
+
     fn get_chunk_info(&self, chunk_index: u32) -> Option<Arc<dyn BlobChunkInfo>> {
         self.meta
             .as_ref()
@@ -581,6 +747,10 @@ impl BlobCache for FileCacheEntry {
             .map(|v| BlobMetaChunk::new(chunk_index as usize, &v.state))
     }
 
+    fn stats(&self) -> CacheStats {
+        self.cache_usage.stats()
+    }
+
     fn get_blob_object(&self) -> Option<&dyn BlobObject> {
         if self.is_get_blob_object_supported {
             Some(self)
@@ -692,12 +862,19 @@ impl BlobCache for FileCacheEntry {
 
         let mut total_size = 0;
         let mut start = 0;
+        let is_remote = self.reader.is_remote();
         while start < pending.len() {
-            // Figure out the range with continuous chunk ids, be careful that `end` is inclusive.
-            let mut end = start;
-            while end < pending.len() - 1 && pending[end + 1].id() == pending[end].id() + 1 {
-                end += 1;
-            }
+            // Figure out the range to fetch, be careful that `end` is inclusive.
+            let end = if is_remote {
+                self.get_remote_range_end(&pending, start)?
+            } else {
+                // Figure out the range with continuous chunk ids.
+                let mut end = start;
+                while end < pending.len() - 1 && pending[end + 1].id() == pending[end].id() + 1 {
+                    end += 1;
+                }
+                end
+            };
 
             let (blob_offset, _blob_end, blob_size) = self.get_blob_range(&pending[start..=end])?;
             match self.read_chunks_from_backend(blob_offset, blob_size, &pending[start..=end], true)
@@ -749,6 +926,8 @@ impl BlobCache for FileCacheEntry {
 
         if iovec.is_empty() {
             Ok(0)
+        } else if iovec.is_uncached() && !self.is_zran() && !self.is_batch() {
+            self.read_uncached(iovec, buffers)
         } else if iovec.len() == 1 {
             let mut state = FileIoMergeState::new();
             let mut cursor = MemSliceCursor::new(buffers);
@@ -1040,6 +1219,39 @@ impl FileCacheEntry {
         Ok(total_read)
     }
 
+    // Read data for the user without saving data fetched from the backend into the cache file.
+    fn read_uncached(&self, iovec: &BlobIoVec, buffers: &[FileVolatileSlice]) -> Result<usize> {
+        let mut cursor = MemSliceCursor::new(buffers);
+        let mut total_read = 0;
+        for bio in iovec.bi_vec.iter() {
+            let chunk = &bio.chunkinfo;
+            let mut d = alloc_buf(chunk.uncompressed_size() as usize);
+            if self.chunk_map.is_ready(chunk)? && self.read_file_cache(chunk, &mut d).is_ok() {
+                self.metrics.whole_hits.inc();
+            } else {
+                self.read_chunk_from_backend(chunk, &mut d)?;
+            }
+
+            let read_size = copyv(
+                &[d.as_slice()],
+                cursor.inner_slice(),
+                bio.offset as usize,
+                bio.size as usize,
+                cursor.index,
+                cursor.offset,
+            )
+            .map(|r| r.0)
+            .map_err(|e| {
+                error!("failed to copy from chunk buf to buf: {:?}", e);
+                eother!(e)
+            })?;
+            cursor.move_cursor(read_size);
+            total_read += read_size;
+        }
+
+        Ok(total_read)
+    }
+
     fn dispatch_one_range(
         &self,
         req: &BlobIoRange,
@@ -1073,7 +1285,7 @@ impl FileCacheEntry {
                         chunk.uncompressed_offset(),
                         chunk.uncompressed_size(),
                         req.tags[i].clone(),
-                        None,
+                        Some(req.chunks[i].clone()),
                     )?;
                 } else {
                     state.commit()
@@ -1134,10 +1346,31 @@ impl FileCacheEntry {
     fn dispatch_cache_fast(&self, cursor: &mut MemSliceCursor, region: &Region) -> Result<usize> {
         let offset = region.blob_address + region.seg.offset as u64;
         let size = region.seg.len as usize;
-        let mut iovec = cursor.consume(size);
+        let (index, pos) = (cursor.index, cursor.offset);
+        let nr_read = {
+            let mut iovec = cursor.consume(size);
+            readv(self.file.as_raw_fd(), &mut iovec, offset)?
+        };
+        if nr_read == size {
+            self.metrics.partial_hits.inc();
+            return Ok(nr_read);
+        }
 
-        self.metrics.partial_hits.inc();
-        readv(self.file.as_raw_fd(), &mut iovec, offset)
+        // The chunk map claims chunks are ready, but data is missing from the cache file,
+        // so discard the stale state and fetch chunks from the backend again.
+        warn!(
+            "blob {}: only {} of {} bytes ready at offset 0x{:x} in cache file, refetch from backend",
+            self.blob_id, nr_read, size, offset
+        );
+        for c in region.chunks.iter() {
+            if self.clear_chunk_ready(c.as_ref()).is_err() {
+                // The chunk map doesn't support resetting chunk state, return what we've got.
+                return Ok(nr_read);
+            }
+        }
+        cursor.index = index;
+        cursor.offset = pos;
+        self.dispatch_cache_slow(cursor, region)
     }
 
     // Try to read data from blob cache and validate it, fallback to storage backend.
@@ -1317,9 +1550,26 @@ impl FileCacheEntry {
         // - digested or dummy chunk map is used.
         let is_ready = self.chunk_map.is_ready(chunk.as_ref())?;
         let try_cache = is_ready || !self.is_direct_chunkmap;
-        let buffer = if try_cache && self.read_file_cache(chunk.as_ref(), d.mut_slice()).is_ok() {
+        let cache_result = if try_cache {
+            self.read_file_cache(chunk.as_ref(), d.mut_slice())
+        } else {
+            Err(enoent!())
+        };
+        if is_ready {
+            if let Err(e) = cache_result.as_ref() {
+                // Don't let other readers trust the cached data until it has been refetched.
+                warn!(
+                    "blob {}: failed to read ready chunk {} from cache file, {}, refetch from backend",
+                    self.blob_id,
+                    chunk.id(),
+                    e
+                );
+                let _ = self.clear_chunk_ready(chunk.as_ref());
+            }
+        }
+        let buffer = if cache_result.is_ok() {
             self.metrics.whole_hits.inc();
-            self.chunk_map.set_ready_and_clear_pending(chunk.as_ref())?;
+            Self::_set_chunk_ready(&self.chunk_map, &self.cache_usage, chunk.as_ref())?;
             trace!(
                 "recover blob cache {} {} offset {} size {}",
                 chunk.id(),
@@ -1383,20 +1633,22 @@ impl FileCacheEntry {
                 chunk.compressed_size() as u64
             };
             let mut reader = FileRangeReader::new(&self.file, offset, size);
+            let compressor = self.chunk_compressor(chunk);
             if !chunk.is_compressed() {
                 reader.read_exact(buffer)?;
-            } else if self.blob_compressor() == compress::Algorithm::Lz4Block {
-                let mut buf = alloc_buf(size as usize);
-                reader.read_exact(&mut buf)?;
-                let size = compress::decompress(&buf, buffer, self.blob_compressor())?;
-                if size != buffer.len() {
-                    return Err(einval!(
-                        "data size decoded by lz4_block doesn't match expected"
-                    ));
+            } else if let Some(lru) = self.decompressed_lru.as_ref() {
+                let d_size = buffer.len();
+                let data = lru.get_or_insert_with(&self.blob_id, chunk.id(), || {
+                    let mut data = alloc_buf(d_size);
+                    self.decompress_file_cache(reader, size, &mut data, compressor)?;
+                    Ok(data)
+                })?;
+                if data.len() != d_size {
+                    return Err(einval!("size of cached decompressed data doesn't match"));
                 }
+                buffer.copy_from_slice(&data);
             } else {
-                let mut decoder = Decoder::new(reader, self.blob_compressor())?;
-                decoder.read_exact(buffer)?;
+                self.decompress_file_cache(reader, size, buffer, compressor)?;
             }
         } else if self.is_cache_encrypted {
             let offset = chunk.uncompressed_offset();
@@ -1430,6 +1682,37 @@ impl FileCacheEntry {
         Ok(())
     }
 
+    // Decompress chunk data of `size` bytes from the compressed cache file.
+    fn decompress_file_cache(
+        &self,
+        mut reader: FileRangeReader,
+        size: u64,
+        buffer: &mut [u8],
+        compressor: compress::Algorithm,
+    ) -> Result<()> {
+        if compressor == compress::Algorithm::Lz4Block {
+            let mut buf = alloc_buf(size as usize);
+            reader.read_exact(&mut buf)?;
+            let size = compress::decompress(&buf, buffer, compressor).map_err(|e| {
+                self.reader().metrics().decompress_error();
+                e
+            })?;
+            if size != buffer.len() {
+                self.reader().metrics().decompress_error();
+                return Err(einval!(
+                    "data size decoded by lz4_block doesn't match expected"
+                ));
+            }
+        } else {
+            let mut decoder = Decoder::new(reader, compressor)?;
+            decoder.read_exact(buffer).map_err(|e| {
+                self.reader().metrics().decompress_error();
+                e
+            })?;
+        }
+        Ok(())
+    }
+
     fn merge_requests_for_user(
         &self,
         bios: &[BlobIoDesc],
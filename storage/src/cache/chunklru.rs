@@ -0,0 +1,266 @@
+// Copyright (C) 2023 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! A small in-memory LRU cache for decompressed chunk data.
+//!
+//! When the cache file stores compressed data, each read of a chunk needs to decompress the
+//! chunk again. The [ChunkLruCache] keeps decompressed data of recently used chunks in memory,
+//! so repeated reads of hot files don't need to decompress the same data again and again.
+//!
+//! One [ChunkLruCache] is shared by all blobs managed by a blob cache manager, so memory used
+//! by decompressed data is bounded per cache manager instead of per blob.
+
+use std::collections::HashMap;
+use std::io::Result;
+use std::sync::{Arc, Mutex};
+
+// Slot index representing the end of the LRU list.
+const NIL: usize = usize::MAX;
+
+struct ChunkLruNode {
+    blob_id: Arc<str>,
+    index: u32,
+    data: Arc<Vec<u8>>,
+    prev: usize,
+    next: usize,
+}
+
+struct ChunkLruState {
+    // Total size of cached chunk data.
+    size: usize,
+    // Cached chunks, doubly linked from the least recently used to the most recently used one.
+    nodes: Vec<Option<ChunkLruNode>>,
+    // Unused slots in `nodes`.
+    free: Vec<usize>,
+    head: usize,
+    tail: usize,
+    // Slots of cached chunks, indexed by blob id and then by chunk index.
+    slots: HashMap<Arc<str>, HashMap<u32, usize>>,
+}
+
+impl ChunkLruState {
+    fn new() -> Self {
+        ChunkLruState {
+            size: 0,
+            nodes: Vec::new(),
+            free: Vec::new(),
+            head: NIL,
+            tail: NIL,
+            slots: HashMap::new(),
+        }
+    }
+
+    fn node(&self, slot: usize) -> &ChunkLruNode {
+        self.nodes[slot].as_ref().unwrap()
+    }
+
+    fn node_mut(&mut self, slot: usize) -> &mut ChunkLruNode {
+        self.nodes[slot].as_mut().unwrap()
+    }
+
+    fn lookup(&self, blob_id: &str, index: u32) -> Option<usize> {
+        self.slots.get(blob_id).and_then(|v| v.get(&index)).copied()
+    }
+
+    fn unlink(&mut self, slot: usize) {
+        let (prev, next) = {
+            let node = self.node(slot);
+            (node.prev, node.next)
+        };
+        if prev == NIL {
+            self.head = next;
+        } else {
+            self.node_mut(prev).next = next;
+        }
+        if next == NIL {
+            self.tail = prev;
+        } else {
+            self.node_mut(next).prev = prev;
+        }
+    }
+
+    fn push_back(&mut self, slot: usize) {
+        let tail = self.tail;
+        let node = self.node_mut(slot);
+        node.prev = tail;
+        node.next = NIL;
+        if tail == NIL {
+            self.head = slot;
+        } else {
+            self.node_mut(tail).next = slot;
+        }
+        self.tail = slot;
+    }
+
+    // Mark the chunk as the most recently used one.
+    fn touch(&mut self, slot: usize) {
+        if self.tail != slot {
+            self.unlink(slot);
+            self.push_back(slot);
+        }
+    }
+
+    fn insert(&mut self, blob_id: &str, index: u32, data: Arc<Vec<u8>>) {
+        if let Some(slot) = self.lookup(blob_id, index) {
+            let size = data.len();
+            let old = std::mem::replace(&mut self.node_mut(slot).data, data);
+            self.size = self.size - old.len() + size;
+            self.touch(slot);
+            return;
+        }
+
+        let blob_id = match self.slots.get_key_value(blob_id) {
+            Some((k, _)) => k.clone(),
+            None => Arc::from(blob_id),
+        };
+        self.size += data.len();
+        let node = ChunkLruNode {
+            blob_id: blob_id.clone(),
+            index,
+            data,
+            prev: NIL,
+            next: NIL,
+        };
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = Some(node);
+                slot
+            }
+            None => {
+                self.nodes.push(Some(node));
+                self.nodes.len() - 1
+            }
+        };
+        self.slots.entry(blob_id).or_default().insert(index, slot);
+        self.push_back(slot);
+    }
+
+    // Evict the least recently used chunk, return false if there's nothing to evict.
+    fn evict(&mut self) -> bool {
+        let slot = self.head;
+        if slot == NIL {
+            return false;
+        }
+
+        self.unlink(slot);
+        let node = self.nodes[slot].take().unwrap();
+        self.free.push(slot);
+        self.size -= node.data.len();
+        if let Some(chunks) = self.slots.get_mut(&node.blob_id) {
+            chunks.remove(&node.index);
+            if chunks.is_empty() {
+                self.slots.remove(&node.blob_id);
+            }
+        }
+
+        true
+    }
+}
+
+/// A size bounded LRU cache for decompressed chunk data, indexed by blob id and chunk index.
+pub(crate) struct ChunkLruCache {
+    capacity: usize,
+    state: Mutex<ChunkLruState>,
+}
+
+impl ChunkLruCache {
+    /// Create a new instance of [ChunkLruCache] caching at most `capacity` bytes of data.
+    pub fn new(capacity: usize) -> Self {
+        ChunkLruCache {
+            capacity,
+            state: Mutex::new(ChunkLruState::new()),
+        }
+    }
+
+    /// Get cached data for chunk `index` of blob `blob_id`, or generate it by `f` and cache it.
+    ///
+    /// Chunks bigger than the capacity of the cache are never cached.
+    pub fn get_or_insert_with<F>(&self, blob_id: &str, index: u32, f: F) -> Result<Arc<Vec<u8>>>
+    where
+        F: FnOnce() -> Result<Vec<u8>>,
+    {
+        {
+            let mut state = self.state.lock().unwrap();
+            if let Some(slot) = state.lookup(blob_id, index) {
+                state.touch(slot);
+                return Ok(state.node(slot).data.clone());
+            }
+        }
+
+        // Generate data without holding the lock, concurrent misses on the same chunk may both
+        // generate data, which is harmless.
+        let data = Arc::new(f()?);
+        if data.len() > self.capacity {
+            return Ok(data);
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.insert(blob_id, index, data.clone());
+        while state.size > self.capacity && state.evict() {}
+
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_chunk_lru_cache() {
+        let cache = ChunkLruCache::new(0x3000);
+        let count = Cell::new(0);
+        let decompress = |v: u8| {
+            count.set(count.get() + 1);
+            Ok(vec![v; 0x1000])
+        };
+
+        for _ in 0..8 {
+            let data = cache.get_or_insert_with("a", 1, || decompress(1)).unwrap();
+            assert_eq!(data[0], 1);
+        }
+        assert_eq!(count.get(), 1);
+
+        cache.get_or_insert_with("a", 2, || decompress(2)).unwrap();
+        cache.get_or_insert_with("a", 3, || decompress(3)).unwrap();
+        assert_eq!(count.get(), 3);
+        // Chunk 1 becomes the most recently used one, so chunk 2 is evicted by chunk 4.
+        cache.get_or_insert_with("a", 1, || decompress(1)).unwrap();
+        cache.get_or_insert_with("a", 4, || decompress(4)).unwrap();
+        assert_eq!(count.get(), 4);
+        cache.get_or_insert_with("a", 1, || decompress(1)).unwrap();
+        cache.get_or_insert_with("a", 3, || decompress(3)).unwrap();
+        assert_eq!(count.get(), 4);
+        cache.get_or_insert_with("a", 2, || decompress(2)).unwrap();
+        assert_eq!(count.get(), 5);
+        assert!(cache.state.lock().unwrap().size <= 0x3000);
+
+        // Chunks with the same index from different blobs don't collide, and share the capacity.
+        let data = cache.get_or_insert_with("b", 1, || decompress(11)).unwrap();
+        assert_eq!(data[0], 11);
+        assert_eq!(count.get(), 6);
+        let data = cache.get_or_insert_with("a", 2, || decompress(2)).unwrap();
+        assert_eq!(data[0], 2);
+        assert_eq!(count.get(), 6);
+        let state = cache.state.lock().unwrap();
+        assert_eq!(state.size, 0x3000);
+        assert!(state.lookup("a", 1).is_none());
+        assert!(state.lookup("b", 1).is_some());
+        assert_eq!(state.slots.values().map(|v| v.len()).sum::<usize>(), 3);
+        drop(state);
+
+        // Too big to be cached.
+        let data = cache
+            .get_or_insert_with("a", 5, || Ok(vec![5u8; 0x4000]))
+            .unwrap();
+        assert_eq!(data.len(), 0x4000);
+        assert!(cache.state.lock().unwrap().lookup("a", 5).is_none());
+
+        assert!(cache
+            .get_or_insert_with("a", 6, || Err(eio!("failed to decompress")))
+            .is_err());
+        assert!(cache.state.lock().unwrap().lookup("a", 6).is_none());
+    }
+}
@@ -256,7 +256,7 @@ mod tests {
 
     use crate::{
         cache::state::IndexedChunkMap,
-        device::{BlobIoChunk, BlobIoRange},
+        device::{BlobChunkFlags, BlobIoChunk, BlobIoRange},
         meta::tests::DummyBlobReader,
         test::{MockBackend, MockChunkInfo},
     };
@@ -469,4 +469,151 @@ mod tests {
         assert!(mgr.closed.load(Ordering::Acquire));
         drop(mgr);
     }
+
+    #[test]
+    fn test_dummy_cache_mixed_compressors() {
+        let dir = TempDir::new().unwrap();
+        let blob_path = dir.as_path().join("blob-0");
+        let chunk_data = [
+            vec![0x1u8; 0x1000],
+            vec![0x2u8; 0x1000],
+            vec![0x3u8; 0x1000],
+        ];
+        let (lz4_data, _) =
+            compress::compress(&chunk_data[0], compress::Algorithm::Lz4Block).unwrap();
+        let (zstd_data, _) = compress::compress(&chunk_data[2], compress::Algorithm::Zstd).unwrap();
+        let raw_chunks = [lz4_data.to_vec(), chunk_data[1].clone(), zstd_data.to_vec()];
+        // Chunks compressed by lz4, stored as is and compressed by the blob compressor.
+        let mut flags = [BlobChunkFlags::empty(); 3];
+        flags[0].set_compressor(compress::Algorithm::Lz4Block);
+        flags[1].set_compressor(compress::Algorithm::None);
+        flags[2] = BlobChunkFlags::COMPRESSED;
+
+        let mut chunks = Vec::new();
+        let mut blob = Vec::new();
+        for (idx, raw) in raw_chunks.iter().enumerate() {
+            chunks.push(MockChunkInfo {
+                flags: flags[idx],
+                compress_size: raw.len() as u32,
+                uncompress_size: 0x1000,
+                compress_offset: blob.len() as u64,
+                uncompress_offset: idx as u64 * 0x1000,
+                index: idx as u32,
+                ..Default::default()
+            });
+            blob.extend_from_slice(raw);
+        }
+        std::fs::write(&blob_path, &blob).unwrap();
+
+        let info = BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x3000,
+            blob.len() as u64,
+            0x1000,
+            3,
+            BlobFeatures::empty(),
+        );
+        let chunk_map = IndexedChunkMap::new(blob_path.to_str().unwrap(), 3, true).unwrap();
+        let reader: Arc<dyn BlobReader> = Arc::new(DummyBlobReader {
+            metrics: BackendMetrics::new("dummy_mixed", "localfs"),
+            file: OpenOptions::new().read(true).open(&blob_path).unwrap(),
+        });
+        let cache = DummyCache {
+            blob_id: "blob-0".to_string(),
+            blob_info: Arc::new(info),
+            chunk_map: Arc::new(chunk_map),
+            reader,
+            compressor: compress::Algorithm::Zstd,
+            digester: digest::Algorithm::Blake3,
+            is_legacy_stargz: false,
+            need_validation: false,
+        };
+
+        for (idx, chunk) in chunks.iter().enumerate() {
+            let mut buf = vec![0u8; 0x1000];
+            cache.read_chunk_from_backend(chunk, &mut buf).unwrap();
+            assert_eq!(buf, chunk_data[idx]);
+        }
+        assert_eq!(
+            cache.chunk_compressor(&chunks[0]),
+            compress::Algorithm::Lz4Block
+        );
+        assert_eq!(
+            cache.chunk_compressor(&chunks[1]),
+            compress::Algorithm::None
+        );
+        assert_eq!(
+            cache.chunk_compressor(&chunks[2]),
+            compress::Algorithm::Zstd
+        );
+    }
+
+    #[test]
+    fn test_dummy_cache_decompress_error() {
+        let dir = TempDir::new().unwrap();
+        let blob_path = dir.as_path().join("blob-0");
+        // Garbage which can't be decompressed by lz4.
+        std::fs::write(&blob_path, [0xffu8; 0x100]).unwrap();
+        let mut flags = BlobChunkFlags::empty();
+        flags.set_compressor(compress::Algorithm::Lz4Block);
+        let chunk: Arc<dyn BlobChunkInfo> = Arc::new(MockChunkInfo {
+            flags,
+            compress_size: 0x100,
+            uncompress_size: 0x1000,
+            ..Default::default()
+        });
+
+        let info = Arc::new(BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x1000,
+            0x100,
+            0x1000,
+            1,
+            BlobFeatures::empty(),
+        ));
+        let chunk_map = IndexedChunkMap::new(blob_path.to_str().unwrap(), 1, true).unwrap();
+        let metrics = BackendMetrics::new("dummy_decompress_error", "localfs");
+        let reader: Arc<dyn BlobReader> = Arc::new(DummyBlobReader {
+            metrics: metrics.clone(),
+            file: OpenOptions::new().read(true).open(&blob_path).unwrap(),
+        });
+        let cache = DummyCache {
+            blob_id: "blob-0".to_string(),
+            blob_info: info.clone(),
+            chunk_map: Arc::new(chunk_map),
+            reader,
+            compressor: compress::Algorithm::Lz4Block,
+            digester: digest::Algorithm::Blake3,
+            is_legacy_stargz: false,
+            need_validation: false,
+        };
+        let decompress_errors = || {
+            let m = nydus_utils::metrics::export_backend_metrics(&Some(
+                "dummy_decompress_error".to_string(),
+            ))
+            .unwrap();
+            let m: serde_json::Value = serde_json::from_str(&m).unwrap();
+            m["decompress_errors"].as_u64().unwrap()
+        };
+        assert_eq!(decompress_errors(), 0);
+
+        for round in 1..=2 {
+            let mut iovec = BlobIoVec::new(info.clone());
+            iovec.push(BlobIoDesc::new(
+                info.clone(),
+                BlobIoChunk::from(chunk.clone()),
+                0,
+                0x1000,
+                true,
+            ));
+            let mut buf = vec![0u8; 0x1000];
+            let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+            let err = cache.read(&mut iovec, &[slice]).unwrap_err();
+            assert_eq!(err.raw_os_error(), Some(libc::EIO));
+            assert_eq!(decompress_errors(), round);
+        }
+        metrics.release().unwrap();
+    }
 }
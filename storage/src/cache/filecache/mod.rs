@@ -3,9 +3,10 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use std::collections::HashMap;
-use std::fs::OpenOptions;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
 use std::io::Result;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::{Arc, RwLock};
 
@@ -17,15 +18,18 @@ use nydus_utils::metrics::BlobcacheMetrics;
 
 use crate::backend::BlobBackend;
 use crate::cache::cachedfile::{FileCacheEntry, FileCacheMeta};
+use crate::cache::chunklru::ChunkLruCache;
 use crate::cache::state::{
     BlobStateMap, ChunkMap, DigestedChunkMap, IndexedChunkMap, NoopChunkMap,
 };
 use crate::cache::worker::{AsyncPrefetchConfig, AsyncWorkerMgr};
-use crate::cache::{BlobCache, BlobCacheMgr};
+use crate::cache::{BlobCache, BlobCacheMgr, CacheStats, CacheUsage};
 use crate::device::{BlobFeatures, BlobInfo};
 
 pub const BLOB_RAW_FILE_SUFFIX: &str = ".blob.raw";
 pub const BLOB_DATA_FILE_SUFFIX: &str = ".blob.data";
+// Length of sha256 blob id in hex string.
+const BLOB_ID_LEN: usize = 64;
 
 /// An implementation of [BlobCacheMgr](../trait.BlobCacheMgr.html) to improve performance by
 /// caching uncompressed blob with local storage.
@@ -44,6 +48,7 @@ pub struct FileCacheMgr {
     cache_encrypted: bool,
     cache_convergent_encryption: bool,
     cache_encryption_key: String,
+    decompressed_lru: Option<Arc<ChunkLruCache>>,
     closed: Arc<AtomicBool>,
     user_io_batch_size: u32,
 }
@@ -62,6 +67,11 @@ impl FileCacheMgr {
         let metrics = BlobcacheMetrics::new(id, work_dir);
         let prefetch_config: Arc<AsyncPrefetchConfig> = Arc::new((&config.prefetch).into());
         let worker_mgr = AsyncWorkerMgr::new(metrics.clone(), prefetch_config.clone())?;
+        let decompressed_lru = if config.cache_compressed && blob_cfg.decompressed_lru_size > 0 {
+            Some(Arc::new(ChunkLruCache::new(blob_cfg.decompressed_lru_size)))
+        } else {
+            None
+        };
 
         Ok(FileCacheMgr {
             blobs: Arc::new(RwLock::new(HashMap::new())),
@@ -77,11 +87,108 @@ impl FileCacheMgr {
             cache_encrypted: blob_cfg.enable_encryption,
             cache_convergent_encryption: blob_cfg.enable_convergent_encryption,
             cache_encryption_key: blob_cfg.encryption_key.clone(),
+            decompressed_lru,
             closed: Arc::new(AtomicBool::new(false)),
             user_io_batch_size,
         })
     }
 
+    /// Remove cache files in `work_dir` for blobs not contained in `blob_ids`.
+    ///
+    /// Cache files are named after the blob id, optionally followed by a suffix, such as
+    /// `$id.blob.data`, `$id.blob.meta` and `$id.blob.data.chunk_map`. Files not named after a
+    /// sha256 blob id are left untouched. Cache files of a blob are also kept if any of them is
+    /// opened or mapped by a running process, such as a live nydusd, according to `/proc`.
+    /// Return paths of removed files.
+    pub fn remove_unreferenced_files(
+        work_dir: &Path,
+        blob_ids: &HashSet<String>,
+    ) -> Result<Vec<PathBuf>> {
+        let mut removed = Vec::new();
+        let in_use = Self::blob_ids_in_use(work_dir);
+
+        for entry in fs::read_dir(work_dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            let name = entry.file_name();
+            let blob_id = match name.to_str().and_then(|v| v.split('.').next()) {
+                Some(v) => v,
+                None => continue,
+            };
+            if blob_id.len() != BLOB_ID_LEN
+                || !blob_id.chars().all(|c| c.is_ascii_hexdigit())
+                || blob_ids.contains(blob_id)
+            {
+                continue;
+            }
+            if in_use.contains(blob_id) {
+                warn!(
+                    "cache file {:?} is in use by running processes, skip removing it",
+                    name
+                );
+                continue;
+            }
+            let path = entry.path();
+            fs::remove_file(&path)?;
+            removed.push(path);
+        }
+
+        Ok(removed)
+    }
+
+    // Collect ids of blobs whose cache files in `work_dir` are opened or mapped by any process.
+    //
+    // Processes owned by other users are invisible to unprivileged callers.
+    fn blob_ids_in_use(work_dir: &Path) -> HashSet<String> {
+        let mut in_use = HashSet::new();
+        let work_dir = match work_dir.canonicalize() {
+            Ok(v) => v,
+            Err(_) => return in_use,
+        };
+        let mut check = |path: &Path| {
+            if path.parent() == Some(work_dir.as_path()) {
+                if let Some(id) = path
+                    .file_name()
+                    .and_then(|v| v.to_str())
+                    .and_then(|v| v.split('.').next())
+                {
+                    in_use.insert(id.to_string());
+                }
+            }
+        };
+
+        let procs = match fs::read_dir("/proc") {
+            Ok(v) => v,
+            Err(_) => return HashSet::new(),
+        };
+        for entry in procs.flatten() {
+            let is_pid = entry.file_name().to_str().map(|v| v.parse::<u32>().is_ok());
+            if is_pid != Some(true) {
+                continue;
+            }
+            let proc_dir = entry.path();
+            if let Ok(fds) = fs::read_dir(proc_dir.join("fd")) {
+                for fd in fds.flatten() {
+                    if let Ok(target) = fs::read_link(fd.path()) {
+                        check(&target);
+                    }
+                }
+            }
+            // Chunk maps may stay mapped after the file descriptor has been closed.
+            if let Ok(maps) = fs::read_to_string(proc_dir.join("maps")) {
+                for line in maps.lines() {
+                    if let Some(path) = line.splitn(6, ' ').nth(5) {
+                        check(Path::new(path.trim_start()));
+                    }
+                }
+            }
+        }
+
+        in_use
+    }
+
     // Get the file cache entry for the specified blob object.
     fn get(&self, blob: &Arc<BlobInfo>) -> Option<Arc<FileCacheEntry>> {
         self.blobs.read().unwrap().get(&blob.blob_id()).cloned()
@@ -107,6 +214,7 @@ impl FileCacheMgr {
             Ok(entry.clone())
         } else {
             let blob_id = blob.blob_id();
+            entry.cache_usage.attach();
             guard.insert(blob_id.clone(), entry.clone());
             self.metrics
                 .underlying_files
@@ -150,11 +258,16 @@ impl BlobCacheMgr for FileCacheMgr {
             let mut guard = self.blobs.write().unwrap();
             if let Some(entry) = guard.get(key) {
                 if Arc::strong_count(entry) == 1 {
+                    entry.cache_usage.detach();
                     guard.remove(key);
                 }
             }
         }
 
+        self.metrics
+            .evictable_bytes
+            .set(self.stats().evictable_bytes);
+
         self.blobs.read().unwrap().len() == 0
     }
 
@@ -167,7 +280,15 @@ impl BlobCacheMgr for FileCacheMgr {
             .map(|v| v as Arc<dyn BlobCache>)
     }
 
-    fn check_stat(&self) {}
+    fn check_stat(&self) {
+        self.metrics
+            .evictable_bytes
+            .set(self.stats().evictable_bytes);
+    }
+
+    fn stats(&self) -> CacheStats {
+        FileCacheEntry::collect_stats(&self.blobs.read().unwrap())
+    }
 }
 
 impl Drop for FileCacheMgr {
@@ -315,15 +436,18 @@ impl FileCacheEntry {
             is_batch,
             is_zran,
         );
-        Ok(FileCacheEntry {
+        let entry = FileCacheEntry {
             blob_id,
             blob_info,
             cache_cipher_object,
             cache_cipher_context,
+            cache_usage: Arc::new(CacheUsage::new(mgr.metrics.clone(), mgr.cache_raw_data)),
             chunk_map,
+            decompressed_lru: mgr.decompressed_lru.clone(),
             file: Arc::new(file),
             meta,
             metrics: mgr.metrics.clone(),
+            persist_tracer: Default::default(),
             prefetch_state: Arc::new(AtomicU32::new(0)),
             reader,
             runtime,
@@ -343,7 +467,15 @@ impl FileCacheEntry {
             need_validation,
             user_io_batch_size: mgr.user_io_batch_size,
             prefetch_config,
-        })
+        };
+        if let Err(e) = entry.load_cache_usage() {
+            warn!(
+                "blob {}: failed to account cached data, {}",
+                entry.blob_id, e
+            );
+        }
+
+        Ok(entry)
     }
 
     fn create_chunk_map(
@@ -375,10 +507,566 @@ impl FileCacheEntry {
 
 #[cfg(test)]
 pub mod blob_cache_tests {
-    use nydus_api::FileCacheConfig;
+    use std::collections::HashSet;
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use nydus_api::{CacheConfigV2, FileCacheConfig};
     use vmm_sys_util::tempdir::TempDir;
     use vmm_sys_util::tempfile::TempFile;
 
+    use super::FileCacheMgr;
+    use crate::backend::BlobBackend;
+    use crate::cache::state::{ChunkMap, IndexedChunkMap};
+    use crate::cache::BlobCacheMgr;
+    use crate::factory::ASYNC_RUNTIME;
+    use crate::test::MockChunkInfo;
+
+    // Create a file cache manager caching data from `backend` in `work_dir`, with the default
+    // configuration adjusted by `update_config`.
+    fn new_filecache_mgr_with_backend(
+        id: &str,
+        work_dir: &Path,
+        backend: Arc<dyn BlobBackend>,
+        update_config: impl FnOnce(&mut CacheConfigV2),
+    ) -> FileCacheMgr {
+        let mut config = CacheConfigV2 {
+            cache_type: "filecache".to_string(),
+            file_cache: Some(FileCacheConfig {
+                work_dir: work_dir.display().to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        update_config(&mut config);
+        let mgr = FileCacheMgr::new(&config, backend, ASYNC_RUNTIME.clone(), id, 0).unwrap();
+        mgr.init().unwrap();
+        mgr
+    }
+
+    // Create a file cache manager caching data of blobs stored in `backend_dir` in `work_dir`.
+    #[cfg(feature = "backend-localfs")]
+    fn new_test_filecache_mgr(
+        id: &str,
+        work_dir: &Path,
+        backend_dir: &Path,
+        update_config: impl FnOnce(&mut CacheConfigV2),
+    ) -> FileCacheMgr {
+        use nydus_api::LocalFsConfig;
+
+        use crate::backend::localfs::LocalFs;
+
+        let config = LocalFsConfig {
+            blob_file: "".to_string(),
+            dir: backend_dir.display().to_string(),
+            alt_dirs: Vec::new(),
+            mmap: false,
+        };
+        let backend = Arc::new(LocalFs::new(&config, Some(id)).unwrap());
+        new_filecache_mgr_with_backend(id, work_dir, backend, update_config)
+    }
+
+    #[test]
+    fn test_remove_unreferenced_files() {
+        let tmp_dir = TempDir::new().unwrap();
+        let work_dir = tmp_dir.as_path();
+        let live_id = "a".repeat(64);
+        let dead_id = "b".repeat(64);
+        let busy_id = "c".repeat(64);
+
+        // Warm caches for three blobs.
+        for id in [&live_id, &dead_id, &busy_id] {
+            let blob_file = work_dir.join(id).to_str().unwrap().to_string();
+            fs::write(format!("{}.blob.data", blob_file), [0u8; 0x1000]).unwrap();
+            fs::write(format!("{}.blob.meta", blob_file), [0u8; 0x100]).unwrap();
+            let map = IndexedChunkMap::new(&format!("{}.blob.data", blob_file), 4, true).unwrap();
+            map.set_ready_and_clear_pending(&MockChunkInfo::new())
+                .unwrap();
+            map.flush().unwrap();
+        }
+        fs::write(work_dir.join("config.json"), "{}").unwrap();
+        fs::create_dir(work_dir.join(&dead_id)).unwrap();
+        // Cache files of an unreferenced blob opened by a running process are kept.
+        let _busy = fs::File::open(work_dir.join(format!("{}.blob.meta", busy_id))).unwrap();
+
+        let mut blob_ids = HashSet::new();
+        blob_ids.insert(live_id.clone());
+        let mut removed = FileCacheMgr::remove_unreferenced_files(work_dir, &blob_ids).unwrap();
+        removed.sort();
+        let expected: Vec<_> = ["blob.data", "blob.data.chunk_map", "blob.meta"]
+            .iter()
+            .map(|suffix| work_dir.join(format!("{}.{}", dead_id, suffix)))
+            .collect();
+        assert_eq!(removed, expected);
+
+        for suffix in ["blob.data", "blob.data.chunk_map", "blob.meta"] {
+            assert!(work_dir.join(format!("{}.{}", live_id, suffix)).exists());
+            assert!(!work_dir.join(format!("{}.{}", dead_id, suffix)).exists());
+            assert!(work_dir.join(format!("{}.{}", busy_id, suffix)).exists());
+        }
+        assert!(work_dir.join("config.json").exists());
+        assert!(work_dir.join(&dead_id).is_dir());
+    }
+
+    #[cfg(feature = "backend-localfs")]
+    #[test]
+    fn test_cache_files_in_work_dir() {
+        use crate::device::{BlobFeatures, BlobInfo};
+
+        let blob_dir = TempDir::new().unwrap();
+        let cache_dir = TempDir::new().unwrap();
+        let blob_id = "c".repeat(64);
+        fs::write(blob_dir.as_path().join(&blob_id), [0u8; 0x4000]).unwrap();
+
+        let mgr = new_test_filecache_mgr(
+            "test_cache_files_in_work_dir",
+            cache_dir.as_path(),
+            blob_dir.as_path(),
+            |_| {},
+        );
+
+        let blob_info = BlobInfo::new(
+            0,
+            blob_id.clone(),
+            0x4000,
+            0x4000,
+            0x1000,
+            4,
+            BlobFeatures::empty(),
+        );
+        mgr.get_blob_cache(&Arc::new(blob_info)).unwrap();
+
+        for suffix in ["blob.data", "blob.data.chunk_map"] {
+            let name = format!("{}.{}", blob_id, suffix);
+            assert!(cache_dir.as_path().join(&name).is_file());
+            assert!(!blob_dir.as_path().join(&name).exists());
+        }
+        mgr.destroy();
+    }
+
+    #[cfg(feature = "backend-localfs")]
+    #[test]
+    fn test_refetch_missing_ready_chunk() {
+        use std::time::{Duration, Instant};
+
+        use fuse_backend_rs::file_buf::FileVolatileSlice;
+
+        use crate::device::{BlobChunkInfo, BlobFeatures, BlobInfo, BlobIoDesc, BlobIoVec};
+
+        let blob_dir = TempDir::new().unwrap();
+        let cache_dir = TempDir::new().unwrap();
+        let blob_id = "d".repeat(64);
+        let data = (0..0x2000).map(|v| (v % 251) as u8).collect::<Vec<u8>>();
+        fs::write(blob_dir.as_path().join(&blob_id), &data).unwrap();
+
+        let mgr = new_test_filecache_mgr(
+            "test_refetch_missing_ready_chunk",
+            cache_dir.as_path(),
+            blob_dir.as_path(),
+            |_| {},
+        );
+
+        let blob_info = Arc::new(BlobInfo::new(
+            0,
+            blob_id.clone(),
+            0x2000,
+            0x2000,
+            0x1000,
+            2,
+            BlobFeatures::empty(),
+        ));
+        let cache = mgr.get_blob_cache(&blob_info).unwrap();
+        let chunk = Arc::new(MockChunkInfo {
+            compress_size: 0x1000,
+            uncompress_size: 0x1000,
+            compress_offset: 0x1000,
+            uncompress_offset: 0x1000,
+            index: 1,
+            ..Default::default()
+        }) as Arc<dyn BlobChunkInfo>;
+        let read_chunk = || {
+            let mut iovec = BlobIoVec::new(blob_info.clone());
+            iovec.push(BlobIoDesc::new(
+                blob_info.clone(),
+                chunk.clone().into(),
+                0,
+                0x1000,
+                true,
+            ));
+            let mut buf = vec![0u8; 0x1000];
+            let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+            assert_eq!(cache.read(&mut iovec, &[slice]).unwrap(), 0x1000);
+            buf
+        };
+        let wait_ready = || {
+            let start = Instant::now();
+            while !cache.get_chunk_map().is_ready(chunk.as_ref()).unwrap() {
+                assert!(start.elapsed() < Duration::from_secs(10));
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        };
+
+        assert_eq!(read_chunk(), &data[0x1000..]);
+        wait_ready();
+
+        // Drop cached data behind the chunk map, which still reports the chunk as ready.
+        let data_file = cache_dir.as_path().join(format!("{}.blob.data", blob_id));
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&data_file)
+            .unwrap()
+            .set_len(0)
+            .unwrap();
+        assert!(cache.get_chunk_map().is_ready(chunk.as_ref()).unwrap());
+
+        assert_eq!(read_chunk(), &data[0x1000..]);
+        wait_ready();
+        let cached = fs::read(&data_file).unwrap();
+        assert_eq!(cached.len(), 0x2000);
+        assert_eq!(&cached[0x1000..], &data[0x1000..]);
+        mgr.destroy();
+    }
+
+    #[cfg(feature = "backend-localfs")]
+    #[test]
+    fn test_flush_delayed_persisted_chunks() {
+        use fuse_backend_rs::file_buf::FileVolatileSlice;
+
+        use crate::device::{BlobChunkInfo, BlobFeatures, BlobInfo, BlobIoDesc, BlobIoVec};
+
+        let id = "test_flush_delayed_persisted_chunks";
+        let blob_dir = TempDir::new().unwrap();
+        let cache_dir = TempDir::new().unwrap();
+        let blob_id = "f".repeat(64);
+        let data = (0..0x4000).map(|v| (v % 241) as u8).collect::<Vec<u8>>();
+        fs::write(blob_dir.as_path().join(&blob_id), &data).unwrap();
+
+        let mgr = new_test_filecache_mgr(id, cache_dir.as_path(), blob_dir.as_path(), |_| {});
+
+        let blob_info = Arc::new(BlobInfo::new(
+            0,
+            blob_id.clone(),
+            0x4000,
+            0x4000,
+            0x1000,
+            4,
+            BlobFeatures::empty(),
+        ));
+        let cache = mgr.get_blob_cache(&blob_info).unwrap();
+        let chunks = (0..4u32)
+            .map(|idx| {
+                Arc::new(MockChunkInfo {
+                    compress_size: 0x1000,
+                    uncompress_size: 0x1000,
+                    compress_offset: idx as u64 * 0x1000,
+                    uncompress_offset: idx as u64 * 0x1000,
+                    index: idx,
+                    ..Default::default()
+                }) as Arc<dyn BlobChunkInfo>
+            })
+            .collect::<Vec<_>>();
+        for chunk in chunks.iter() {
+            let mut iovec = BlobIoVec::new(blob_info.clone());
+            iovec.push(BlobIoDesc::new(
+                blob_info.clone(),
+                chunk.clone().into(),
+                0,
+                0x1000,
+                true,
+            ));
+            let mut buf = vec![0u8; 0x1000];
+            let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+            assert_eq!(cache.read(&mut iovec, &[slice]).unwrap(), 0x1000);
+        }
+
+        // Chunk data fetched for user reads is written back in background, flush waits for it.
+        cache.flush().unwrap();
+        for chunk in chunks.iter() {
+            assert!(cache.get_chunk_map().is_ready(chunk.as_ref()).unwrap());
+        }
+        let cached = fs::read(cache_dir.as_path().join(format!("{}.blob.data", blob_id))).unwrap();
+        assert_eq!(cached, data);
+        mgr.destroy();
+    }
+
+    #[cfg(feature = "backend-localfs")]
+    #[test]
+    fn test_decompressed_lru_shared_by_blobs() {
+        use std::time::{Duration, Instant};
+
+        use fuse_backend_rs::file_buf::FileVolatileSlice;
+        use nydus_utils::compress;
+        use nydus_utils::metrics::export_backend_metrics;
+
+        use crate::device::{
+            BlobChunkFlags, BlobChunkInfo, BlobFeatures, BlobInfo, BlobIoDesc, BlobIoVec,
+        };
+
+        let id = "test_decompressed_lru_shared_by_blobs";
+        let blob_dir = TempDir::new().unwrap();
+        let cache_dir = TempDir::new().unwrap();
+        let blobs = ["a", "b"]
+            .iter()
+            .enumerate()
+            .map(|(idx, v)| {
+                let blob_id = v.repeat(64);
+                let data = (0..0x1000)
+                    .map(|v| ((v / 16 + idx) % 251) as u8)
+                    .collect::<Vec<u8>>();
+                let (compressed, is_compressed) =
+                    compress::compress(&data, compress::Algorithm::Lz4Block).unwrap();
+                assert!(is_compressed);
+                fs::write(blob_dir.as_path().join(&blob_id), &compressed).unwrap();
+                (blob_id, data, compressed.len() as u32)
+            })
+            .collect::<Vec<_>>();
+
+        // There's room for decompressed data of one chunk only.
+        let mgr = new_test_filecache_mgr(id, cache_dir.as_path(), blob_dir.as_path(), |config| {
+            config.cache_compressed = true;
+            config.file_cache.as_mut().unwrap().decompressed_lru_size = 0x1000;
+        });
+
+        let caches = blobs
+            .iter()
+            .map(|(blob_id, _, size)| {
+                let mut blob_info = BlobInfo::new(
+                    0,
+                    blob_id.clone(),
+                    0x1000,
+                    *size as u64,
+                    0x1000,
+                    1,
+                    BlobFeatures::empty(),
+                );
+                blob_info.set_compressor(compress::Algorithm::Lz4Block);
+                let blob_info = Arc::new(blob_info);
+                let chunk = Arc::new(MockChunkInfo {
+                    flags: BlobChunkFlags::COMPRESSED,
+                    compress_size: *size,
+                    uncompress_size: 0x1000,
+                    ..Default::default()
+                }) as Arc<dyn BlobChunkInfo>;
+                (mgr.get_blob_cache(&blob_info).unwrap(), blob_info, chunk)
+            })
+            .collect::<Vec<_>>();
+        let read_chunk = |idx: usize| {
+            let (cache, blob_info, chunk) = &caches[idx];
+            let mut iovec = BlobIoVec::new(blob_info.clone());
+            iovec.push(BlobIoDesc::new(
+                blob_info.clone(),
+                chunk.clone().into(),
+                0,
+                0x1000,
+                true,
+            ));
+            let mut buf = vec![0u8; 0x1000];
+            let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+            assert_eq!(cache.read(&mut iovec, &[slice]).unwrap(), 0x1000);
+            assert_eq!(buf, blobs[idx].1);
+        };
+        let fetch_chunk = |idx: usize| {
+            read_chunk(idx);
+            let (cache, _, chunk) = &caches[idx];
+            let start = Instant::now();
+            while !cache.get_chunk_map().is_ready(chunk.as_ref()).unwrap() {
+                assert!(start.elapsed() < Duration::from_secs(10));
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        };
+        let backend_reads = || {
+            let metrics = export_backend_metrics(&Some(id.to_string())).unwrap();
+            let metrics: serde_json::Value = serde_json::from_str(&metrics).unwrap();
+            metrics["read_count"].as_u64().unwrap()
+        };
+
+        // Decompressed data of the cached chunk is kept in memory once read from the cache file,
+        // so the chunk is still available after dropping the cache file.
+        fetch_chunk(0);
+        read_chunk(0);
+        assert_eq!(backend_reads(), 1);
+        let raw_file = cache_dir.as_path().join(format!("{}.blob.raw", blobs[0].0));
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&raw_file)
+            .unwrap()
+            .set_len(0)
+            .unwrap();
+        read_chunk(0);
+        assert_eq!(backend_reads(), 1);
+
+        // Reading the other blob evicts the chunk, which then has to be fetched again.
+        fetch_chunk(1);
+        read_chunk(1);
+        assert_eq!(backend_reads(), 2);
+        read_chunk(0);
+        assert_eq!(backend_reads(), 3);
+        mgr.destroy();
+    }
+
+    #[test]
+    fn test_prefetch_range_by_backend_type() {
+        use std::sync::Mutex;
+
+        use nydus_utils::metrics::BackendMetrics;
+
+        use crate::backend::{BackendResult, BlobReader};
+        use crate::device::{BlobChunkInfo, BlobFeatures, BlobInfo, BlobIoDesc, BlobIoRange};
+
+        struct RecordingReader {
+            remote: bool,
+            requests: Arc<Mutex<Vec<(u64, usize)>>>,
+            metrics: Arc<BackendMetrics>,
+        }
+
+        impl BlobReader for RecordingReader {
+            fn blob_size(&self) -> BackendResult<u64> {
+                Ok(0x40000)
+            }
+
+            fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+                self.requests.lock().unwrap().push((offset, buf.len()));
+                for (idx, v) in buf.iter_mut().enumerate() {
+                    *v = ((offset as usize + idx) % 251) as u8;
+                }
+                Ok(buf.len())
+            }
+
+            fn metrics(&self) -> &BackendMetrics {
+                &self.metrics
+            }
+
+            fn is_remote(&self) -> bool {
+                self.remote
+            }
+        }
+
+        struct RecordingBackend {
+            remote: bool,
+            requests: Arc<Mutex<Vec<(u64, usize)>>>,
+            metrics: Arc<BackendMetrics>,
+        }
+
+        impl BlobBackend for RecordingBackend {
+            fn shutdown(&self) {}
+
+            fn metrics(&self) -> &BackendMetrics {
+                &self.metrics
+            }
+
+            fn get_reader(&self, _blob_id: &str) -> BackendResult<Arc<dyn BlobReader>> {
+                Ok(Arc::new(RecordingReader {
+                    remote: self.remote,
+                    requests: self.requests.clone(),
+                    metrics: self.metrics.clone(),
+                }))
+            }
+        }
+
+        let data = (0..0x40000).map(|v| (v % 251) as u8).collect::<Vec<u8>>();
+        // Network backends fetch pending chunks by one request, including the ready chunk in
+        // between, unless the gap or the request size exceeds limits of the prefetch batch size.
+        for (idx, (remote, batch_size, ready, expected, total)) in [
+            (
+                true,
+                0x80_0000,
+                Some(1),
+                vec![(0u64, 0x40000usize)],
+                0x40000usize,
+            ),
+            (
+                true,
+                0x2_0000,
+                Some(1),
+                vec![(0, 0x10000), (0x20000, 0x20000)],
+                0x30000,
+            ),
+            (
+                true,
+                0x2_0000,
+                None,
+                vec![(0, 0x20000), (0x20000, 0x20000)],
+                0x40000,
+            ),
+            (
+                false,
+                0x80_0000,
+                Some(1),
+                vec![(0, 0x10000), (0x20000, 0x20000)],
+                0x30000,
+            ),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let id = format!("test_prefetch_range_by_backend_type_{}", idx);
+            let cache_dir = TempDir::new().unwrap();
+            let requests = Arc::new(Mutex::new(Vec::new()));
+            let metrics = BackendMetrics::new(&id, "mock");
+            let backend = Arc::new(RecordingBackend {
+                remote,
+                requests: requests.clone(),
+                metrics: metrics.clone(),
+            });
+            let mgr = new_filecache_mgr_with_backend(&id, cache_dir.as_path(), backend, |config| {
+                config.prefetch.batch_size = batch_size
+            });
+
+            let blob_id = "e".repeat(64);
+            let blob_info = Arc::new(BlobInfo::new(
+                0,
+                blob_id.clone(),
+                0x40000,
+                0x40000,
+                0x10000,
+                4,
+                BlobFeatures::empty(),
+            ));
+            let cache = mgr.get_blob_cache(&blob_info).unwrap();
+            let chunks = (0..4u32)
+                .map(|idx| {
+                    Arc::new(MockChunkInfo {
+                        compress_size: 0x10000,
+                        uncompress_size: 0x10000,
+                        compress_offset: idx as u64 * 0x10000,
+                        uncompress_offset: idx as u64 * 0x10000,
+                        index: idx,
+                        ..Default::default()
+                    }) as Arc<dyn BlobChunkInfo>
+                })
+                .collect::<Vec<_>>();
+            let bios = chunks
+                .iter()
+                .map(|c| BlobIoDesc::new(blob_info.clone(), c.clone().into(), 0, 0x10000, false))
+                .collect::<Vec<_>>();
+            let mut range = BlobIoRange::new(&bios[0], bios.len());
+            for bio in &bios[1..] {
+                range.merge(bio, 0);
+            }
+
+            // A ready chunk makes pending chunks not continuous.
+            if let Some(idx) = ready {
+                cache
+                    .get_chunk_map()
+                    .set_ready_and_clear_pending(chunks[idx].as_ref())
+                    .unwrap();
+            }
+            assert_eq!(cache.prefetch_range(&range).unwrap(), total);
+            assert_eq!(*requests.lock().unwrap(), expected);
+            for chunk in chunks.iter() {
+                assert!(cache.get_chunk_map().is_ready(chunk.as_ref()).unwrap());
+            }
+            let cached =
+                fs::read(cache_dir.as_path().join(format!("{}.blob.data", blob_id))).unwrap();
+            assert_eq!(&cached[0..0x10000], &data[0..0x10000]);
+            assert_eq!(&cached[0x20000..0x40000], &data[0x20000..0x40000]);
+
+            mgr.destroy();
+            metrics.release().unwrap();
+        }
+    }
+
     #[test]
     fn test_blob_cache_config() {
         // new blob cache
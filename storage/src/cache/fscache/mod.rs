@@ -17,7 +17,7 @@ use crate::backend::BlobBackend;
 use crate::cache::cachedfile::{FileCacheEntry, FileCacheMeta};
 use crate::cache::state::{BlobStateMap, IndexedChunkMap, RangeMap};
 use crate::cache::worker::{AsyncPrefetchConfig, AsyncWorkerMgr};
-use crate::cache::{BlobCache, BlobCacheMgr};
+use crate::cache::{BlobCache, BlobCacheMgr, CacheStats, CacheUsage};
 use crate::device::{BlobFeatures, BlobInfo, BlobObject};
 use crate::factory::BLOB_FACTORY;
 
@@ -103,6 +103,7 @@ impl FsCacheMgr {
             Ok(entry.clone())
         } else {
             let blob_id = blob.blob_id();
+            entry.cache_usage.attach();
             guard.insert(blob_id.clone(), entry.clone());
             self.metrics
                 .underlying_files
@@ -130,7 +131,9 @@ impl BlobCacheMgr for FsCacheMgr {
 
     fn gc(&self, id: Option<&str>) -> bool {
         if let Some(blob_id) = id {
-            self.blobs.write().unwrap().remove(blob_id);
+            if let Some(entry) = self.blobs.write().unwrap().remove(blob_id) {
+                entry.cache_usage.detach();
+            }
         } else {
             let mut reclaim = Vec::new();
             let guard = self.blobs.write().unwrap();
@@ -145,12 +148,17 @@ impl BlobCacheMgr for FsCacheMgr {
                 let mut guard = self.blobs.write().unwrap();
                 if let Some(entry) = guard.get(key) {
                     if Arc::strong_count(entry) == 1 {
+                        entry.cache_usage.detach();
                         guard.remove(key);
                     }
                 }
             }
         }
 
+        self.metrics
+            .evictable_bytes
+            .set(self.stats().evictable_bytes);
+
         self.blobs.read().unwrap().len() == 0
     }
 
@@ -185,6 +193,14 @@ impl BlobCacheMgr for FsCacheMgr {
         } else {
             self.blobs_check_count.store(0, Ordering::Release);
         }
+
+        self.metrics
+            .evictable_bytes
+            .set(self.stats().evictable_bytes);
+    }
+
+    fn stats(&self) -> CacheStats {
+        FileCacheEntry::collect_stats(&self.blobs.read().unwrap())
     }
 }
 
@@ -266,15 +282,18 @@ impl FileCacheEntry {
         )?));
         Self::restore_chunk_map(blob_info.clone(), file.clone(), &meta, &chunk_map);
 
-        Ok(FileCacheEntry {
+        let entry = FileCacheEntry {
             blob_id,
             blob_info: blob_info.clone(),
             cache_cipher_object: Default::default(),
             cache_cipher_context: Default::default(),
+            cache_usage: Arc::new(CacheUsage::new(mgr.metrics.clone(), false)),
             chunk_map,
+            decompressed_lru: None,
             file,
             meta: Some(meta),
             metrics: mgr.metrics.clone(),
+            persist_tracer: Default::default(),
             prefetch_state: Arc::new(AtomicU32::new(0)),
             reader,
             runtime,
@@ -294,7 +313,15 @@ impl FileCacheEntry {
             need_validation,
             user_io_batch_size: mgr.user_io_batch_size,
             prefetch_config,
-        })
+        };
+        if let Err(e) = entry.load_cache_usage() {
+            warn!(
+                "blob {}: failed to account cached data, {}",
+                entry.blob_id, e
+            );
+        }
+
+        Ok(entry)
     }
 
     fn restore_chunk_map(
@@ -18,12 +18,14 @@
 
 use std::cmp;
 use std::io::Result;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Instant;
 
 use fuse_backend_rs::file_buf::FileVolatileSlice;
 use nydus_utils::compress::zlib_random::ZranDecoder;
 use nydus_utils::crypt::{self, Cipher, CipherContext};
+use nydus_utils::metrics::{BlobcacheMetrics, Metric};
 use nydus_utils::{compress, digest};
 
 use crate::backend::{BlobBackend, BlobReader};
@@ -36,6 +38,7 @@ use crate::utils::{alloc_buf, check_digest};
 use crate::{StorageResult, RAFS_MAX_CHUNK_SIZE};
 
 mod cachedfile;
+mod chunklru;
 #[cfg(feature = "dedup")]
 mod dedup;
 mod dummycache;
@@ -135,6 +138,114 @@ impl<'a, F: FnMut(BlobIoRange)> BlobIoMergeState<'a, F> {
     }
 }
 
+/// Statistics about chunk data held by blob caches.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Bytes of chunk data cached in local storage.
+    pub total_bytes: u64,
+    /// Number of chunks in ready state.
+    pub chunk_count: u64,
+    /// Bytes of cached data which may be reclaimed because no filesystem is using the blob.
+    pub evictable_bytes: u64,
+}
+
+impl CacheStats {
+    /// Accumulate statistics from another `CacheStats` object.
+    pub fn merge(&mut self, other: &CacheStats) {
+        self.total_bytes += other.total_bytes;
+        self.chunk_count += other.chunk_count;
+        self.evictable_bytes += other.evictable_bytes;
+    }
+}
+
+/// Counters of chunk data held by a blob cache, updated when chunks change readiness state.
+///
+/// Changes are mirrored into the cache manager's `BlobcacheMetrics`, so cache occupancy is
+/// available without scanning chunk maps.
+pub(crate) struct CacheUsage {
+    bytes: AtomicU64,
+    chunks: AtomicU64,
+    metrics: Arc<BlobcacheMetrics>,
+    // Compressed chunk data is cached instead of uncompressed data.
+    raw_data: bool,
+}
+
+impl CacheUsage {
+    /// Create a new instance of `CacheUsage`.
+    pub fn new(metrics: Arc<BlobcacheMetrics>, raw_data: bool) -> Self {
+        CacheUsage {
+            bytes: AtomicU64::new(0),
+            chunks: AtomicU64::new(0),
+            metrics,
+            raw_data,
+        }
+    }
+
+    /// Initialize counters with chunks already cached when the blob cache is opened.
+    ///
+    /// The counters are not mirrored into metrics until `attach()` is called.
+    pub fn load(&self, chunks: u64, bytes: u64) {
+        self.chunks.store(chunks, Ordering::Release);
+        self.bytes.store(bytes, Ordering::Release);
+    }
+
+    /// Account all cached data into metrics when the blob cache is added to a cache manager.
+    pub fn attach(&self) {
+        self.metrics
+            .cached_chunks
+            .add(self.chunks.load(Ordering::Acquire));
+        self.metrics
+            .cached_bytes
+            .add(self.bytes.load(Ordering::Acquire));
+    }
+
+    /// Remove all cached data from metrics when the blob cache is removed from a cache manager.
+    pub fn detach(&self) {
+        self.metrics
+            .cached_chunks
+            .sub(self.chunks.load(Ordering::Acquire));
+        self.metrics
+            .cached_bytes
+            .sub(self.bytes.load(Ordering::Acquire));
+    }
+
+    /// Account a chunk which has just been changed into ready state.
+    pub fn add_chunk(&self, chunk: &dyn BlobChunkInfo) {
+        let size = self.chunk_size(chunk);
+        self.chunks.fetch_add(1, Ordering::AcqRel);
+        self.bytes.fetch_add(size, Ordering::AcqRel);
+        self.metrics.cached_chunks.inc();
+        self.metrics.cached_bytes.add(size);
+    }
+
+    /// Unaccount a chunk which has just been changed out of ready state.
+    pub fn remove_chunk(&self, chunk: &dyn BlobChunkInfo) {
+        let size = self.chunk_size(chunk);
+        self.chunks.fetch_sub(1, Ordering::AcqRel);
+        self.bytes.fetch_sub(size, Ordering::AcqRel);
+        self.metrics.cached_chunks.dec();
+        self.metrics.cached_bytes.sub(size);
+    }
+
+    /// Get size of cached data for a chunk.
+    pub fn chunk_size(&self, chunk: &dyn BlobChunkInfo) -> u64 {
+        if self.raw_data {
+            chunk.compressed_size() as u64
+        } else {
+            chunk.uncompressed_size() as u64
+        }
+    }
+
+    /// Get a snapshot of the counters.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            total_bytes: self.bytes.load(Ordering::Acquire),
+            chunk_count: self.chunks.load(Ordering::Acquire),
+            evictable_bytes: 0,
+        }
+    }
+}
+
 /// Trait representing a cache object for a blob on backend storage.
 ///
 /// The caller may use the `BlobCache` trait to access blob data on backend storage, with an
@@ -202,9 +313,22 @@ pub trait BlobCache: Send + Sync {
     /// Get the underlying `ChunkMap` object.
     fn get_chunk_map(&self) -> &Arc<dyn ChunkMap>;
 
+    /// Flush cached data and chunk readiness state to the underlying storage.
+    fn flush(&self) -> Result<()> {
+        self.get_chunk_map().flush()
+    }
+
     /// Get the `BlobChunkInfo` object corresponding to `chunk_index`.
     fn get_chunk_info(&self, chunk_index: u32) -> Option<Arc<dyn BlobChunkInfo>>;
 
+    /// Get statistics about chunk data cached for the blob.
+    ///
+    /// `evictable_bytes` is only reported by the blob cache manager, which knows whether the blob
+    /// is still in use.
+    fn stats(&self) -> CacheStats {
+        CacheStats::default()
+    }
+
     /// Get a `BlobObject` instance to directly access uncompressed blob file.
     fn get_blob_object(&self) -> Option<&dyn BlobObject> {
         None
@@ -325,7 +449,8 @@ pub trait BlobCache: Send + Sync {
                 &self.blob_cipher_context(),
                 chunk.is_encrypted(),
             )?;
-            self.decompress_chunk_data(&decrypted_buffer, buffer, chunk.is_compressed())?;
+            let compressor = self.chunk_compressor(chunk);
+            self.decompress_chunk_data(&decrypted_buffer, buffer, compressor)?;
             c_buf = Some(raw_buffer);
         }
 
@@ -346,21 +471,31 @@ pub trait BlobCache: Send + Sync {
         Ok(c_buf)
     }
 
-    /// Decompress chunk data.
+    /// Get the compression algorithm to decompress data of the chunk.
+    fn chunk_compressor(&self, chunk: &dyn BlobChunkInfo) -> compress::Algorithm {
+        if chunk.is_compressed() {
+            chunk.compressor().unwrap_or_else(|| self.blob_compressor())
+        } else {
+            compress::Algorithm::None
+        }
+    }
+
+    /// Decompress chunk data with `compressor`, `compress::Algorithm::None` means plain data.
     fn decompress_chunk_data(
         &self,
         raw_buffer: &[u8],
         buffer: &mut [u8],
-        is_compressed: bool,
+        compressor: compress::Algorithm,
     ) -> Result<()> {
-        if is_compressed {
-            let compressor = self.blob_compressor();
+        if !compressor.is_none() {
             let ret = compress::decompress(raw_buffer, buffer, compressor).map_err(|e| {
                 error!("failed to decompress chunk: {}", e);
-                e
+                self.reader().metrics().decompress_error();
+                eio!(format!("failed to decompress chunk, {}", e))
             })?;
             if ret != buffer.len() {
-                return Err(einval!(format!(
+                self.reader().metrics().decompress_error();
+                return Err(eio!(format!(
                     "size of decompressed data doesn't match expected, {} vs {}, raw_buffer: {}",
                     ret,
                     buffer.len(),
@@ -466,11 +601,17 @@ impl<'a, 'b> ChunkDecompressState<'a, 'b> {
             meta.state.is_encrypted(),
         )?;
         let mut output = alloc_buf(d_size as usize);
+        let compressor = if c_size != d_size {
+            self.cache.blob_compressor()
+        } else {
+            compress::Algorithm::None
+        };
 
         self.cache
-            .decompress_chunk_data(&decrypted_buffer, &mut output, c_size != d_size)?;
+            .decompress_chunk_data(&decrypted_buffer, &mut output, compressor)?;
 
         if output.len() != d_size as usize {
+            self.cache.reader().metrics().decompress_error();
             return Err(einval!(format!(
                 "decompressed data size doesn't match: {} vs {}",
                 output.len(),
@@ -507,7 +648,12 @@ impl<'a, 'b> ChunkDecompressState<'a, 'b> {
         let input = &self.c_buf[c_offset..c_offset + c_size as usize];
         let mut output = alloc_buf(ctx.out_len as usize);
         let mut decoder = ZranDecoder::new()?;
-        decoder.uncompress(&ctx, Some(dict), input, &mut output)?;
+        decoder
+            .uncompress(&ctx, Some(dict), input, &mut output)
+            .map_err(|e| {
+                self.cache.reader().metrics().decompress_error();
+                e
+            })?;
         self.d_buf = output;
 
         Ok(())
@@ -594,8 +740,9 @@ impl<'a, 'b> ChunkDecompressState<'a, 'b> {
             chunk.is_encrypted(),
         )?;
         let mut buffer = alloc_buf(d_size);
+        let compressor = self.cache.chunk_compressor(chunk);
         self.cache
-            .decompress_chunk_data(&decrypted_buffer, &mut buffer, chunk.is_compressed())?;
+            .decompress_chunk_data(&decrypted_buffer, &mut buffer, compressor)?;
         self.cache
             .validate_chunk_data(chunk, &buffer, false)
             .map_err(|e| {
@@ -657,6 +804,11 @@ pub(crate) trait BlobCacheMgr: Send + Sync {
 
     /// Check the blob cache data status, if data all ready stop prefetch workers.
     fn check_stat(&self);
+
+    /// Get statistics about chunk data cached by all managed blob caches.
+    fn stats(&self) -> CacheStats {
+        CacheStats::default()
+    }
 }
 
 #[cfg(test)]
@@ -783,4 +935,52 @@ mod tests {
         assert!(desc1.is_continuous(&desc2, 0));
         assert!(!desc1.is_continuous(&desc3, 0));
     }
+
+    #[test]
+    fn test_cache_usage() {
+        let chunks: Vec<MockChunkInfo> = (0..8)
+            .map(|index| MockChunkInfo {
+                index,
+                compress_size: 0x100 + index,
+                uncompress_size: 0x1000,
+                ..Default::default()
+            })
+            .collect();
+        let metrics = BlobcacheMetrics::new("test-cache-usage", "/tmp");
+
+        let usage = CacheUsage::new(metrics.clone(), false);
+        usage.load(2, 0x2000);
+        assert_eq!(metrics.cached_chunks.count(), 0);
+        usage.attach();
+        for chunk in chunks.iter().step_by(2) {
+            usage.add_chunk(chunk);
+        }
+        usage.remove_chunk(&chunks[0]);
+        let stats = usage.stats();
+        assert_eq!(stats.chunk_count, 5);
+        assert_eq!(stats.total_bytes, 0x5000);
+        assert_eq!(stats.evictable_bytes, 0);
+        assert_eq!(metrics.cached_chunks.count(), 5);
+        assert_eq!(metrics.cached_bytes.count(), 0x5000);
+
+        let raw_usage = CacheUsage::new(metrics.clone(), true);
+        raw_usage.attach();
+        for chunk in chunks.iter().skip(1).step_by(2) {
+            raw_usage.add_chunk(chunk);
+        }
+        let raw_stats = raw_usage.stats();
+        assert_eq!(raw_stats.chunk_count, 4);
+        assert_eq!(raw_stats.total_bytes, 0x100 * 4 + 1 + 3 + 5 + 7);
+        assert_eq!(metrics.cached_chunks.count(), 9);
+
+        let mut total = stats;
+        total.merge(&raw_stats);
+        assert_eq!(total.chunk_count, 9);
+        assert_eq!(total.total_bytes, 0x5000 + 0x410);
+
+        usage.detach();
+        assert_eq!(metrics.cached_chunks.count(), 4);
+        assert_eq!(metrics.cached_bytes.count(), 0x410);
+        metrics.release().unwrap();
+    }
 }
@@ -154,10 +154,18 @@ where
         }
     }
 
+    fn clear_ready(&self, chunk: &dyn BlobChunkInfo) -> Result<()> {
+        self.c.clear_ready(chunk)
+    }
+
     fn is_persist(&self) -> bool {
         self.c.is_persist()
     }
 
+    fn flush(&self) -> Result<()> {
+        self.c.flush()
+    }
+
     fn as_range_map(&self) -> Option<&dyn RangeMap<I = u32>> {
         let any = self as &dyn Any;
 
@@ -173,6 +181,10 @@ impl RangeMap for BlobStateMap<IndexedChunkMap, u32> {
         self.c.is_range_all_ready()
     }
 
+    fn get_ready_count(&self) -> Result<Self::I> {
+        self.c.get_ready_count()
+    }
+
     fn is_range_ready(&self, start: Self::I, count: Self::I) -> Result<bool> {
         self.c.is_range_ready(start, count)
     }
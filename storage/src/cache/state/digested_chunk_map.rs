@@ -50,6 +50,11 @@ impl ChunkMap for DigestedChunkMap {
         self.cache.write().unwrap().insert(*chunk.chunk_id());
         Ok(())
     }
+
+    fn clear_ready(&self, chunk: &dyn BlobChunkInfo) -> Result<()> {
+        self.cache.write().unwrap().remove(chunk.chunk_id());
+        Ok(())
+    }
 }
 
 impl ChunkIndexGetter for DigestedChunkMap {
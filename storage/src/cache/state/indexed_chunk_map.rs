@@ -55,10 +55,18 @@ impl ChunkMap for IndexedChunkMap {
         self.map.set_chunk_ready(chunk.id())
     }
 
+    fn clear_ready(&self, chunk: &dyn BlobChunkInfo) -> Result<()> {
+        self.map.clear_chunk_ready(chunk.id())
+    }
+
     fn is_persist(&self) -> bool {
         true
     }
 
+    fn flush(&self) -> Result<()> {
+        self.map.flush()
+    }
+
     fn as_range_map(&self) -> Option<&dyn RangeMap<I = u32>> {
         Some(self)
     }
@@ -72,6 +80,10 @@ impl RangeMap for IndexedChunkMap {
         self.map.is_range_all_ready()
     }
 
+    fn get_ready_count(&self) -> Result<u32> {
+        Ok(self.map.ready_count())
+    }
+
     fn is_range_ready(&self, start_index: u32, count: u32) -> Result<bool> {
         if !self.is_range_all_ready() {
             for idx in 0..count {
@@ -145,6 +157,65 @@ mod tests {
     use crate::device::v5::BlobV5ChunkInfo;
     use crate::test::MockChunkInfo;
 
+    #[test]
+    fn test_indexed_flush_and_reopen() {
+        let dir = TempDir::new().unwrap();
+        let blob_path = dir.as_path().join("blob-1");
+        let blob_path = blob_path.as_os_str().to_str().unwrap().to_string();
+        let chunks: Vec<MockChunkInfo> = (0..4)
+            .map(|index| MockChunkInfo {
+                index,
+                ..Default::default()
+            })
+            .collect();
+
+        let map = IndexedChunkMap::new(&blob_path, 4, true).unwrap();
+        map.set_ready_and_clear_pending(chunks[1].as_base())
+            .unwrap();
+        map.set_ready_and_clear_pending(chunks[3].as_base())
+            .unwrap();
+        map.flush().unwrap();
+        drop(map);
+
+        let map = IndexedChunkMap::new(&blob_path, 4, true).unwrap();
+        assert_eq!(map.map.not_ready_count.load(Ordering::Acquire), 2);
+        assert_eq!(map.get_ready_count().unwrap(), 2);
+        assert!(!map.is_ready(chunks[0].as_base()).unwrap());
+        assert!(map.is_ready(chunks[1].as_base()).unwrap());
+        assert!(!map.is_ready(chunks[2].as_base()).unwrap());
+        assert!(map.is_ready(chunks[3].as_base()).unwrap());
+    }
+
+    #[test]
+    fn test_indexed_clear_ready() {
+        let dir = TempDir::new().unwrap();
+        let blob_path = dir.as_path().join("blob-1");
+        let blob_path = blob_path.as_os_str().to_str().unwrap().to_string();
+        let chunks: Vec<MockChunkInfo> = (0..2)
+            .map(|index| MockChunkInfo {
+                index,
+                ..Default::default()
+            })
+            .collect();
+
+        let map = IndexedChunkMap::new(&blob_path, 2, true).unwrap();
+        for chunk in chunks.iter() {
+            map.set_ready_and_clear_pending(chunk.as_base()).unwrap();
+        }
+        assert!(map.is_range_all_ready());
+
+        map.clear_ready(chunks[1].as_base()).unwrap();
+        map.clear_ready(chunks[1].as_base()).unwrap();
+        assert!(!map.is_range_all_ready());
+        assert_eq!(map.map.not_ready_count.load(Ordering::Acquire), 1);
+        assert!(map.is_ready(chunks[0].as_base()).unwrap());
+        assert!(!map.is_ready(chunks[1].as_base()).unwrap());
+
+        map.set_ready_and_clear_pending(chunks[1].as_base())
+            .unwrap();
+        assert!(map.is_range_all_ready());
+    }
+
     #[test]
     fn test_indexed_new_invalid_file_size() {
         let dir = TempDir::new().unwrap();
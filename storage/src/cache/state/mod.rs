@@ -91,11 +91,23 @@ pub trait ChunkMap: Any + Send + Sync {
         panic!("no support of clear_pending()");
     }
 
+    /// Clear the ready state of the chunk, so it will be fetched from the backend again.
+    ///
+    /// It's used to recover from inconsistency between the chunk map and the cached data.
+    fn clear_ready(&self, _chunk: &dyn BlobChunkInfo) -> Result<()> {
+        Err(enosys!())
+    }
+
     /// Check whether the implementation supports state persistence.
     fn is_persist(&self) -> bool {
         false
     }
 
+    /// Flush chunk readiness state to the underlying storage.
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
     /// Convert the objet to an [RangeMap](trait.RangeMap.html) object.
     fn as_range_map(&self) -> Option<&dyn RangeMap<I = u32>> {
         None
@@ -115,6 +127,11 @@ pub trait RangeMap: Send + Sync {
         false
     }
 
+    /// Get number of chunks or data units in ready state.
+    fn get_ready_count(&self) -> Result<Self::I> {
+        Err(enosys!())
+    }
+
     /// Check whether all chunks or data in the range are ready for use.
     fn is_range_ready(&self, _start: Self::I, _count: Self::I) -> Result<bool> {
         Err(enosys!())
@@ -183,6 +200,7 @@ mod tests {
         assert!(m.is_pending(&chunk_info).is_ok());
         assert!(!m.is_pending(&chunk_info).unwrap());
         assert!(!m.is_range_all_ready());
+        assert!(m.get_ready_count().is_err());
         assert!(m.is_range_ready(0, 1).is_err());
         assert!(m.check_range_ready_and_mark_pending(0, 1).is_err());
         assert!(m.set_range_ready_and_clear_pending(0, 1).is_err());
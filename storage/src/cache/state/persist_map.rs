@@ -246,6 +246,20 @@ impl PersistMap {
         Ok(())
     }
 
+    /// Clear the ready bit of the chunk.
+    pub fn clear_chunk_ready(&self, index: u32) -> Result<()> {
+        let index = self.validate_index(index)?;
+        let mask = Self::index_to_mask(index);
+        let start = HEADER_SIZE + (index as usize >> 3);
+        let atomic_value = self.filemap.get_ref::<AtomicU8>(start).unwrap();
+
+        if atomic_value.fetch_and(!mask, Ordering::AcqRel) & mask == mask {
+            self.not_ready_count.fetch_add(1, Ordering::AcqRel);
+        }
+
+        Ok(())
+    }
+
     fn mark_all_ready(&self) {
         if self.filemap.sync_data().is_ok() {
             /*
@@ -257,8 +271,18 @@ impl PersistMap {
         }
     }
 
+    /// Flush the chunk state bitmap to disk.
+    pub fn flush(&self) -> Result<()> {
+        self.filemap.sync_data()
+    }
+
     #[inline]
     pub fn is_range_all_ready(&self) -> bool {
         self.not_ready_count.load(Ordering::Acquire) == 0
     }
+
+    #[inline]
+    pub fn ready_count(&self) -> u32 {
+        self.count - self.not_ready_count.load(Ordering::Acquire)
+    }
 }
@@ -68,6 +68,8 @@ bitflags! {
         const BATCH = 0x0000_0080;
         /// Whether the Blob is encrypted.
         const ENCRYPTED = 0x0000_0100;
+        /// Chunks may be compressed by algorithms other than the blob compressor.
+        const CHUNK_COMPRESSOR = 0x0000_0200;
         /// Blob has TAR headers to separate contents.
         const HAS_TAR_HEADER = 0x1000_0000;
         /// Blob has Table of Content (ToC) at the tail.
@@ -227,6 +229,11 @@ impl BlobInfo {
         self.blob_index
     }
 
+    /// Set the blob index in the blob array.
+    pub fn set_blob_index(&mut self, index: u32) {
+        self.blob_index = index;
+    }
+
     /// Get the id of the blob, with special handling of `inlined-meta` case.
     pub fn blob_id(&self) -> String {
         if (self.has_feature(BlobFeatures::INLINED_FS_META)
@@ -580,6 +587,8 @@ bitflags! {
         const ENCYPTED = 0x0000_0004;
         /// Chunk data is merged into a batch chunk.
         const BATCH = 0x0000_0008;
+        /// Compression algorithm of compressed chunk data, zero means the blob compressor.
+        const COMPRESSOR = 0x0000_00f0;
     }
 }
 
@@ -589,6 +598,35 @@ impl Default for BlobChunkFlags {
     }
 }
 
+impl BlobChunkFlags {
+    const COMPRESSOR_SHIFT: u32 = 4;
+
+    /// Get the compression algorithm recorded for the chunk, `None` for the blob compressor.
+    pub fn compressor(&self) -> Option<compress::Algorithm> {
+        let algo = (*self & BlobChunkFlags::COMPRESSOR).bits() >> Self::COMPRESSOR_SHIFT;
+        if algo == 0 {
+            None
+        } else {
+            compress::Algorithm::try_from(algo).ok()
+        }
+    }
+
+    /// Record the compression algorithm used to compress the chunk.
+    ///
+    /// Chunk compressed with `compress::Algorithm::None` is marked as uncompressed.
+    pub fn set_compressor(&mut self, algo: compress::Algorithm) {
+        self.remove(BlobChunkFlags::COMPRESSOR);
+        if algo.is_none() {
+            self.remove(BlobChunkFlags::COMPRESSED);
+        } else {
+            self.insert(BlobChunkFlags::COMPRESSED);
+            self.insert(BlobChunkFlags::from_bits_truncate(
+                (algo as u32) << Self::COMPRESSOR_SHIFT,
+            ));
+        }
+    }
+}
+
 /// Trait to provide basic information for a chunk.
 ///
 /// A `BlobChunkInfo` object describes how a chunk is located within the compressed and
@@ -647,6 +685,14 @@ pub trait BlobChunkInfo: Any + Sync + Send {
     /// Check whether the chunk is encrypted or not.
     fn is_encrypted(&self) -> bool;
 
+    /// Get the compression algorithm of the chunk if it differs from the blob compressor.
+    ///
+    /// Chunks in the same data blob may be compressed by different algorithms, `None` means the
+    /// chunk is compressed by the blob compressor.
+    fn compressor(&self) -> Option<compress::Algorithm> {
+        None
+    }
+
     fn as_any(&self) -> &dyn Any;
 }
 
@@ -704,6 +750,10 @@ impl BlobChunkInfo for BlobIoChunk {
         self.0.is_encrypted()
     }
 
+    fn compressor(&self) -> Option<compress::Algorithm> {
+        self.0.compressor()
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -789,6 +839,8 @@ pub struct BlobIoVec {
     bi_size: u64,
     /// Array of blob IOs, these IOs should executed sequentially.
     pub(crate) bi_vec: Vec<BlobIoDesc>,
+    /// Whether to serve the blob IOs without populating the blob cache.
+    bi_uncached: bool,
 }
 
 impl BlobIoVec {
@@ -798,6 +850,7 @@ impl BlobIoVec {
             bi_blob,
             bi_size: 0,
             bi_vec: Vec::with_capacity(128),
+            bi_uncached: false,
         }
     }
 
@@ -839,6 +892,14 @@ impl BlobIoVec {
         self.bi_size
     }
 
+    /// Get total uncompressed size of the data chunks touched by the blob io vector.
+    pub fn chunk_data_size(&self) -> u64 {
+        self.bi_vec
+            .iter()
+            .map(|d| d.chunkinfo.uncompressed_size() as u64)
+            .sum()
+    }
+
     /// Get an immutable reference to a `BlobIoDesc` entry.
     pub fn blob_io_desc(&self, index: usize) -> Option<&BlobIoDesc> {
         if index < self.bi_vec.len() {
@@ -862,6 +923,19 @@ impl BlobIoVec {
     pub fn has_same_blob(&self, desc: &BlobIoVec) -> bool {
         self.bi_blob.blob_index() == desc.bi_blob.blob_index()
     }
+
+    /// Set whether to read data without populating the blob cache.
+    ///
+    /// Data already in the cache may still be used, but data fetched from the storage backend
+    /// won't be saved into the cache.
+    pub fn set_uncached(&mut self, uncached: bool) {
+        self.bi_uncached = uncached;
+    }
+
+    /// Check whether to read data without populating the blob cache.
+    pub fn is_uncached(&self) -> bool {
+        self.bi_uncached
+    }
 }
 
 impl Debug for BlobIoVec {
@@ -1150,6 +1224,34 @@ impl BlobDevice {
         self.blob_count > 0
     }
 
+    /// Check whether all data blobs are reachable from the storage backend.
+    pub fn check_backend(&self) -> io::Result<()> {
+        for blob in self.blobs.load().iter() {
+            blob.reader().blob_size().map_err(|e| {
+                eio!(format!(
+                    "failed to access blob {} from storage backend, {}",
+                    blob.blob_id(),
+                    e
+                ))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Flush cached data and chunk state of all data blobs to the underlying storage.
+    pub fn flush(&self) -> io::Result<()> {
+        for blob in self.blobs.load().iter() {
+            blob.flush().map_err(|e| {
+                eio!(format!(
+                    "failed to flush cache for blob {}, {}",
+                    blob.blob_id(),
+                    e
+                ))
+            })?;
+        }
+        Ok(())
+    }
+
     /// Read a range of data from a data blob into the provided writer
     pub fn read_to(&self, w: &mut dyn ZeroCopyWriter, desc: &mut BlobIoVec) -> io::Result<usize> {
         // Validate that:
@@ -1173,6 +1275,55 @@ impl BlobDevice {
         }
     }
 
+    /// Read a range of data from a data blob into the provided buffer.
+    ///
+    /// The buffer must be big enough to hold all data described by `desc`.
+    pub fn read_to_buf(&self, desc: &mut BlobIoVec, buf: &mut [u8]) -> io::Result<usize> {
+        if desc.bi_vec.is_empty() {
+            if desc.bi_size == 0 {
+                Ok(0)
+            } else {
+                Err(einval!("BlobIoVec size doesn't match."))
+            }
+        } else if desc.blob_index() as usize >= self.blob_count {
+            Err(einval!("BlobIoVec has out of range blob_index."))
+        } else if (buf.len() as u64) < desc.size() {
+            Err(einval!("buffer is too small for BlobIoVec."))
+        } else {
+            let size = desc.size() as usize;
+            // Safe because the buffer is valid and big enough, and outlives the slice.
+            let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), size) };
+            let mut f = BlobDeviceIoVec::new(self, desc);
+            f.read_vectored_at_volatile(&[slice], 0)
+        }
+    }
+
+    /// Read a range of data from a data blob into a list of buffers, such as scattered guest memory.
+    ///
+    /// Data is scattered into the buffers in order by the cache layer, without copying through
+    /// an intermediate buffer. The buffers must be big enough to hold all data described by `desc`.
+    pub fn read_to_slices(
+        &self,
+        desc: &mut BlobIoVec,
+        bufs: &[FileVolatileSlice],
+    ) -> io::Result<usize> {
+        let size = bufs.iter().map(|v| v.len() as u64).sum::<u64>();
+        if desc.bi_vec.is_empty() {
+            if desc.bi_size == 0 {
+                Ok(0)
+            } else {
+                Err(einval!("BlobIoVec size doesn't match."))
+            }
+        } else if desc.blob_index() as usize >= self.blob_count {
+            Err(einval!("BlobIoVec has out of range blob_index."))
+        } else if size < desc.size() {
+            Err(einval!("buffers are too small for BlobIoVec."))
+        } else {
+            let mut f = BlobDeviceIoVec::new(self, desc);
+            f.read_vectored_at_volatile(bufs, 0)
+        }
+    }
+
     /// Try to prefetch specified blob data.
     pub fn prefetch(
         &self,
@@ -1292,6 +1443,32 @@ impl BlobDevice {
         }
     }
 
+    /// Get data chunks with compressed data starting at `compressed_offset`, by the blob meta.
+    ///
+    /// Chunks packed into the same batch share the compressed offset. Return `None` if the blob
+    /// meta isn't available, such as for RAFS v5 data blobs.
+    pub fn get_chunks_by_compressed_offset(
+        &self,
+        blob_index: u32,
+        compressed_offset: u64,
+    ) -> io::Result<Option<Vec<Arc<dyn BlobChunkInfo>>>> {
+        if blob_index as usize >= self.blob_count {
+            return Err(einval!(format!("invalid blob index {}", blob_index)));
+        }
+        let state = self.blobs.load();
+        let meta = match state[blob_index as usize].get_blob_meta_info()? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        let chunks = meta
+            .get_chunks_compressed(compressed_offset, 1, 0, false)?
+            .into_iter()
+            .filter(|c| c.compressed_offset() == compressed_offset)
+            .collect();
+
+        Ok(Some(chunks))
+    }
+
     fn get_blob_by_iovec(&self, iovec: &BlobIoVec) -> Option<Arc<dyn BlobCache>> {
         let blob_index = iovec.blob_index();
         if (blob_index as usize) < self.blob_count {
@@ -1334,8 +1511,8 @@ impl FileReadWriteVolatile for BlobDeviceIoVec<'_> {
     }
 
     fn write_volatile(&mut self, _slice: FileVolatileSlice) -> Result<usize, Error> {
-        // Skip because we don't really use it
-        unimplemented!();
+        // Blob devices are readonly, never write to the storage backend.
+        Err(Error::from_raw_os_error(libc::EROFS))
     }
 
     fn read_at_volatile(&mut self, slice: FileVolatileSlice, offset: u64) -> Result<usize, Error> {
@@ -1370,7 +1547,8 @@ impl FileReadWriteVolatile for BlobDeviceIoVec<'_> {
         _slice: FileVolatileSlice,
         _offset: u64,
     ) -> Result<usize, Error> {
-        unimplemented!()
+        // Blob devices are readonly, never write to the storage backend.
+        Err(Error::from_raw_os_error(libc::EROFS))
     }
 }
 
@@ -1437,6 +1615,29 @@ mod tests {
         assert!(!iochunk.is_compressed());
     }
 
+    #[test]
+    fn test_blob_device_io_vec_readonly() {
+        let device = BlobDevice::default();
+        let blob_info = Arc::new(BlobInfo::new(
+            0,
+            "test".to_owned(),
+            0x200000,
+            0x100000,
+            0x100000,
+            512,
+            BlobFeatures::_V5_NO_EXT_BLOB_TABLE,
+        ));
+        let mut iovec = BlobIoVec::new(blob_info);
+        let mut f = BlobDeviceIoVec::new(&device, &mut iovec);
+        let mut buf = [0u8; 16];
+        let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+
+        let err = f.write_volatile(slice).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EROFS));
+        let err = f.write_at_volatile(slice, 0).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EROFS));
+    }
+
     #[test]
     fn test_chunk_is_continuous() {
         let blob_info = Arc::new(BlobInfo::new(
@@ -1624,6 +1825,62 @@ mod tests {
         assert_eq!(chunk_count, iovec.len() as u32);
     }
 
+    #[test]
+    fn test_read_amplification() {
+        let chunk_size = 0x10_0000;
+        let chunk_count = 16;
+        let blob = Arc::new(BlobInfo::new(
+            0,
+            "blob_id".to_owned(),
+            chunk_size as u64 * chunk_count as u64,
+            chunk_size as u64 * chunk_count as u64,
+            chunk_size,
+            chunk_count,
+            BlobFeatures::default(),
+        ));
+        let ios = nydus_utils::metrics::FsIoStats::default();
+
+        // Issue many tiny reads at pseudo-random offsets, each one within a single chunk.
+        let mut seed = 0x2545_f491u32;
+        let mut read_bytes = 0u64;
+        for _ in 0..1000 {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let chunk_idx = seed % chunk_count;
+            let size = seed % 64 + 1;
+            let offset = (seed >> 8) % (chunk_size - size);
+            let chunk = Arc::new(MockChunkInfo {
+                block_id: Default::default(),
+                blob_index: 0,
+                flags: BlobChunkFlags::empty(),
+                compress_size: chunk_size,
+                compress_offset: chunk_idx as u64 * chunk_size as u64,
+                uncompress_size: chunk_size,
+                uncompress_offset: chunk_idx as u64 * chunk_size as u64,
+                file_offset: chunk_idx as u64 * chunk_size as u64,
+                index: chunk_idx,
+                reserved: 0,
+            }) as Arc<dyn BlobChunkInfo>;
+            let mut iovec = BlobIoVec::new(blob.clone());
+            iovec.push(BlobIoDesc::new(
+                blob.clone(),
+                BlobIoChunk(chunk),
+                offset,
+                size,
+                true,
+            ));
+            assert_eq!(iovec.chunk_data_size(), chunk_size as u64);
+            ios.read_amplification_update(iovec.size(), iovec.chunk_data_size());
+            read_bytes += size as u64;
+        }
+
+        // Every read fetches a whole chunk, so the ratio is chunk size over average read size.
+        let expected = 1000.0 * chunk_size as f64 / read_bytes as f64;
+        assert!((ios.read_amplification() - expected).abs() < 1e-6);
+        assert!(ios.read_amplification() >= chunk_size as f64 / 64.0);
+    }
+
     #[test]
     fn test_blob_info_blob_meta_id() {
         let blob_info = BlobInfo::new(
@@ -1651,4 +1908,27 @@ mod tests {
             "be7d77eeb719f70884758d1aa800ed0fb09d701aaec469964e9d54325f0d5fef".to_owned()
         );
     }
+
+    #[test]
+    fn test_chunk_flags_compressor() {
+        let mut flags = BlobChunkFlags::COMPRESSED | BlobChunkFlags::BATCH;
+        assert_eq!(flags.compressor(), None);
+
+        flags.set_compressor(compress::Algorithm::Lz4Block);
+        assert!(flags.contains(BlobChunkFlags::COMPRESSED | BlobChunkFlags::BATCH));
+        assert_eq!(flags.compressor(), Some(compress::Algorithm::Lz4Block));
+        flags.set_compressor(compress::Algorithm::Zstd);
+        assert_eq!(flags.compressor(), Some(compress::Algorithm::Zstd));
+        assert_eq!(
+            BlobChunkFlags::from_bits(flags.bits())
+                .unwrap()
+                .compressor(),
+            Some(compress::Algorithm::Zstd)
+        );
+
+        flags.set_compressor(compress::Algorithm::None);
+        assert!(!flags.contains(BlobChunkFlags::COMPRESSED));
+        assert!(flags.contains(BlobChunkFlags::BATCH));
+        assert_eq!(flags.compressor(), None);
+    }
 }
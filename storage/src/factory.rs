@@ -21,12 +21,16 @@ use nydus_api::{default_user_io_batch_size, BackendConfigV2, ConfigV2};
 use tokio::runtime::{Builder, Runtime};
 use tokio::time;
 
+use crate::backend::breaker::BreakerBackend;
 #[cfg(feature = "backend-http-proxy")]
 use crate::backend::http_proxy;
+use crate::backend::limiter::LimitedBackend;
 #[cfg(feature = "backend-localdisk")]
 use crate::backend::localdisk;
 #[cfg(feature = "backend-localfs")]
 use crate::backend::localfs;
+#[cfg(feature = "backend-oci-layer")]
+use crate::backend::oci_layer;
 #[cfg(feature = "backend-oss")]
 use crate::backend::oss;
 #[cfg(feature = "backend-registry")]
@@ -115,12 +119,6 @@ impl BlobFactory {
         config: &Arc<ConfigV2>,
         blob_info: &Arc<BlobInfo>,
     ) -> IOResult<Arc<dyn BlobCache>> {
-        let backend_cfg = config.get_backend_config()?;
-        let cache_cfg = config.get_cache_config()?;
-        let user_io_batch_size = config
-            .get_rafs_config()
-            .map_or_else(|_| default_user_io_batch_size(), |v| v.user_io_batch_size)
-            as u32;
         let key = BlobCacheMgrKey {
             config: config.clone(),
         };
@@ -129,39 +127,8 @@ impl BlobFactory {
         if let Some(mgr) = guard.get(&key) {
             return mgr.get_blob_cache(blob_info);
         }
-        let backend = Self::new_backend(backend_cfg, &blob_info.blob_id())?;
-        let mgr = match cache_cfg.cache_type.as_str() {
-            "blobcache" | "filecache" => {
-                let mgr = FileCacheMgr::new(
-                    cache_cfg,
-                    backend,
-                    ASYNC_RUNTIME.clone(),
-                    &config.id,
-                    user_io_batch_size,
-                )?;
-                mgr.init()?;
-                Arc::new(mgr) as Arc<dyn BlobCacheMgr>
-            }
-            #[cfg(target_os = "linux")]
-            "fscache" => {
-                let mgr = crate::cache::FsCacheMgr::new(
-                    cache_cfg,
-                    backend,
-                    ASYNC_RUNTIME.clone(),
-                    &config.id,
-                    user_io_batch_size,
-                )?;
-                mgr.init()?;
-                Arc::new(mgr) as Arc<dyn BlobCacheMgr>
-            }
-            _ => {
-                let mgr = DummyCacheMgr::new(cache_cfg, backend, false)?;
-                mgr.init()?;
-                Arc::new(mgr) as Arc<dyn BlobCacheMgr>
-            }
-        };
-
-        let mgr = guard.entry(key).or_insert_with(|| mgr);
+        let stack = build_storage(config, &blob_info.blob_id())?;
+        let mgr = guard.entry(key).or_insert_with(|| stack.cache_mgr);
 
         mgr.get_blob_cache(blob_info)
     }
@@ -238,6 +205,11 @@ impl BlobFactory {
                 config.get_http_proxy_config()?,
                 Some(blob_id),
             )?)),
+            #[cfg(feature = "backend-oci-layer")]
+            "oci-layer" => Ok(Arc::new(oci_layer::OciLayer::new(
+                config.get_oci_layer_config()?,
+                Some(blob_id),
+            )?)),
             _ => Err(einval!(format!(
                 "unsupported backend type '{}'",
                 config.backend_type
@@ -258,3 +230,183 @@ impl Default for BlobFactory {
         Self::new()
     }
 }
+
+/// Storage stack to access data blobs, with a blob cache manager wired to its storage backend.
+pub struct StorageStack {
+    /// Storage backend with all decorators applied.
+    pub backend: Arc<dyn BlobBackend + Send + Sync>,
+    /// Blob cache manager reading data from `backend`.
+    pub cache_mgr: Arc<dyn BlobCacheMgr>,
+}
+
+impl StorageStack {
+    /// Create a blob cache object for a blob.
+    pub fn get_blob_cache(&self, blob_info: &Arc<BlobInfo>) -> IOResult<Arc<dyn BlobCache>> {
+        self.cache_mgr.get_blob_cache(blob_info)
+    }
+}
+
+/// Build a storage stack for the blob with id `blob_id` from a single configuration.
+///
+/// The storage backend is created by [BlobFactory::new_backend()], wrapped by the concurrency
+/// limiter and circuit breaker according to the RAFS configuration, and then wired to a newly
+/// created blob cache manager. Retry and mirror policies are handled by the backend itself.
+/// Unlike [BlobFactory::new_blob_cache()], the returned stack is not shared with others.
+pub fn build_storage(config: &ConfigV2, blob_id: &str) -> IOResult<StorageStack> {
+    let backend = BlobFactory::new_backend(config.get_backend_config()?, blob_id)?;
+    let backend = decorate_backend(config, backend);
+    let cache_mgr = new_cache_mgr(config, backend.clone())?;
+
+    Ok(StorageStack { backend, cache_mgr })
+}
+
+fn decorate_backend(
+    config: &ConfigV2,
+    mut backend: Arc<dyn BlobBackend + Send + Sync>,
+) -> Arc<dyn BlobBackend + Send + Sync> {
+    let max_backend_reads = config
+        .get_rafs_config()
+        .map_or(0, |v| v.max_concurrent_backend_reads);
+    let (failure_threshold, failure_cooldown) = config.get_rafs_config().map_or((0, 0), |v| {
+        (v.backend_failure_threshold, v.backend_failure_cooldown)
+    });
+
+    if max_backend_reads > 0 {
+        backend = Arc::new(LimitedBackend::new(backend, max_backend_reads));
+    }
+    // Fast-fail before queueing up in the limiter.
+    if failure_threshold > 0 {
+        backend = Arc::new(BreakerBackend::new(
+            backend,
+            failure_threshold,
+            Duration::from_secs(failure_cooldown),
+        ));
+    }
+
+    backend
+}
+
+fn new_cache_mgr(
+    config: &ConfigV2,
+    backend: Arc<dyn BlobBackend + Send + Sync>,
+) -> IOResult<Arc<dyn BlobCacheMgr>> {
+    let cache_cfg = config.get_cache_config()?;
+    let user_io_batch_size = config
+        .get_rafs_config()
+        .map_or_else(|_| default_user_io_batch_size(), |v| v.user_io_batch_size)
+        as u32;
+
+    let mgr = match cache_cfg.cache_type.as_str() {
+        "blobcache" | "filecache" => {
+            let mgr = FileCacheMgr::new(
+                cache_cfg,
+                backend,
+                ASYNC_RUNTIME.clone(),
+                &config.id,
+                user_io_batch_size,
+            )?;
+            mgr.init()?;
+            Arc::new(mgr) as Arc<dyn BlobCacheMgr>
+        }
+        #[cfg(target_os = "linux")]
+        "fscache" => {
+            let mgr = crate::cache::FsCacheMgr::new(
+                cache_cfg,
+                backend,
+                ASYNC_RUNTIME.clone(),
+                &config.id,
+                user_io_batch_size,
+            )?;
+            mgr.init()?;
+            Arc::new(mgr) as Arc<dyn BlobCacheMgr>
+        }
+        _ => {
+            let mgr = DummyCacheMgr::new(cache_cfg, backend, false)?;
+            mgr.init()?;
+            Arc::new(mgr) as Arc<dyn BlobCacheMgr>
+        }
+    };
+
+    Ok(mgr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use nydus_api::{CacheConfigV2, RafsConfigV2};
+
+    use crate::backend::BackendError;
+    use crate::device::BlobFeatures;
+    use crate::test::{MockControl, MockControlledBackend};
+
+    #[test]
+    fn test_build_storage_stack() {
+        let mut config = ConfigV2::new("test_build_storage_stack");
+        config.cache = Some(CacheConfigV2 {
+            cache_type: "dummycache".to_string(),
+            ..Default::default()
+        });
+        config.rafs = Some(RafsConfigV2 {
+            max_concurrent_backend_reads: 1,
+            backend_failure_threshold: 2,
+            backend_failure_cooldown: 60,
+            ..Default::default()
+        });
+        // No backend configuration available.
+        assert!(build_storage(&config, "blob1").is_err());
+
+        let control = MockControl {
+            delay: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let backend = Arc::new(MockControlledBackend::new(
+            "test_build_storage_stack",
+            control,
+        ));
+        let control = backend.control.clone();
+        let metrics = backend.metrics.clone();
+        let backend = decorate_backend(&config, backend);
+        let stack = StorageStack {
+            backend: backend.clone(),
+            cache_mgr: new_cache_mgr(&config, backend).unwrap(),
+        };
+        let blob_info = Arc::new(BlobInfo::new(
+            0,
+            "blob1".to_string(),
+            0x1000,
+            0x1000,
+            0x1000,
+            1,
+            BlobFeatures::empty(),
+        ));
+        let cache = stack.get_blob_cache(&blob_info).unwrap();
+
+        // Reads through the blob cache are serialized by the concurrency limiter.
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut buf = [0u8; 16];
+                    assert_eq!(cache.reader().read(&mut buf, 0).unwrap(), 16);
+                });
+            }
+        });
+        assert_eq!(control.reads.load(Ordering::SeqCst), 4);
+        assert_eq!(control.max_inflight.load(Ordering::SeqCst), 1);
+
+        // The circuit breaker sits in front of the limiter and rejects reads once opened.
+        control.failing.store(true, Ordering::SeqCst);
+        let mut buf = [0u8; 16];
+        for _ in 0..2 {
+            let err = cache.reader().read(&mut buf, 0).unwrap_err();
+            assert!(matches!(err, BackendError::Unsupported(_)));
+        }
+        let err = cache.reader().read(&mut buf, 0).unwrap_err();
+        assert!(matches!(err, BackendError::Unavailable(_)));
+        assert_eq!(control.reads.load(Ordering::SeqCst), 6);
+
+        drop(cache);
+        drop(stack);
+        metrics.release().unwrap();
+    }
+}
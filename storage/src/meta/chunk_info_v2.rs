@@ -5,6 +5,8 @@
 use std::fmt::{Display, Formatter};
 use std::io::{Error, ErrorKind, Result};
 
+use nydus_utils::compress;
+
 use crate::device::BlobFeatures;
 use crate::meta::{BlobCompressionContext, BlobMetaChunkInfo, BLOB_CCT_CHUNK_SIZE_MASK};
 
@@ -19,12 +21,14 @@ const CHUNK_V2_FLAG_ZRAN: u64 = 0x2 << 56;
 const CHUNK_V2_FLAG_BATCH: u64 = 0x4 << 56;
 const CHUNK_V2_FLAG_ENCRYPTED: u64 = 0x8 << 56;
 const CHUNK_V2_FLAG_VALID: u64 = 0xf << 56;
+const CHUNK_V2_COMPRESSOR_SHIFT: u64 = 60;
+const CHUNK_V2_COMPRESSOR_MASK: u64 = 0xf << CHUNK_V2_COMPRESSOR_SHIFT;
 
 /// Chunk compression information on disk format V2.
 #[repr(C, packed)]
 #[derive(Clone, Copy, Default, Debug)]
 pub struct BlobChunkInfoV2Ondisk {
-    // 32bits: offset, 24bits: size, 8bits: flags
+    // 32bits: offset, 24bits: size, 4bits: flags, 4bits: compressor
     pub(crate) uncomp_info: u64,
     // offset: 40bits, 24bits: size
     pub(crate) comp_info: u64,
@@ -65,6 +69,25 @@ impl BlobChunkInfoV2Ondisk {
         }
     }
 
+    /// Record the algorithm to compress the chunk if it differs from the blob compressor.
+    pub(crate) fn set_compressor(&mut self, compressor: Option<compress::Algorithm>) {
+        self.uncomp_info &= u64::to_le(!CHUNK_V2_COMPRESSOR_MASK);
+        if let Some(algo) = compressor {
+            self.uncomp_info |= u64::to_le((algo as u64) << CHUNK_V2_COMPRESSOR_SHIFT);
+        }
+    }
+
+    /// Get the algorithm to compress the chunk, `None` means the blob compressor.
+    pub(crate) fn compressor(&self) -> Option<compress::Algorithm> {
+        let algo = (u64::from_le(self.uncomp_info) & CHUNK_V2_COMPRESSOR_MASK)
+            >> CHUNK_V2_COMPRESSOR_SHIFT;
+        if algo == 0 {
+            None
+        } else {
+            compress::Algorithm::try_from(algo as u32).ok()
+        }
+    }
+
     pub(crate) fn set_data(&mut self, data: u64) {
         self.data = u64::to_le(data);
     }
@@ -102,7 +125,8 @@ impl BlobChunkInfoV2Ondisk {
     }
 
     fn check_flags(&self) -> u8 {
-        ((u64::from_le(self.uncomp_info) & !CHUNK_V2_FLAG_VALID) >> 56) as u8
+        ((u64::from_le(self.uncomp_info) & !(CHUNK_V2_FLAG_VALID | CHUNK_V2_COMPRESSOR_MASK)) >> 56)
+            as u8
     }
 }
 
@@ -237,6 +261,22 @@ impl BlobMetaChunkInfo for BlobChunkInfoV2Ondisk {
             ));
         }
 
+        let algo = (u64::from_le(self.uncomp_info) & CHUNK_V2_COMPRESSOR_MASK)
+            >> CHUNK_V2_COMPRESSOR_SHIFT;
+        if algo != 0 {
+            if state.blob_features & BlobFeatures::CHUNK_COMPRESSOR.bits() == 0 {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    "invalid chunk compressor for blob without chunk compressor feature",
+                ));
+            } else if !self.is_compressed() || self.compressor().is_none() {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    format!("invalid chunk compressor {}", algo),
+                ));
+            }
+        }
+
         if state.blob_features & BlobFeatures::ZRAN.bits() == 0 && self.is_zran() {
             return Err(Error::new(
                 ErrorKind::Other,
@@ -503,4 +543,41 @@ mod tests {
         chunk.set_zran(false);
         assert!(chunk.validate(&ctx).is_ok());
     }
+
+    #[test]
+    fn test_chunk_on_disk_compressor() {
+        let mut ctx = BlobCompressionContext {
+            compressed_size: 0x100,
+            uncompressed_size: 0x1000,
+            ..Default::default()
+        };
+        let mut chunk = BlobChunkInfoV2Ondisk::default();
+        chunk.set_compressed_size(0x20);
+        chunk.set_uncompressed_size(0x1000);
+        chunk.set_compressed(true);
+        assert_eq!(chunk.compressor(), None);
+        assert!(chunk.validate(&ctx).is_ok());
+
+        chunk.set_compressor(Some(compress::Algorithm::Lz4Block));
+        assert_eq!(chunk.compressor(), Some(compress::Algorithm::Lz4Block));
+        assert!(chunk.is_compressed());
+        // The chunk compressor is only valid for blobs with the chunk compressor feature.
+        assert!(chunk.validate(&ctx).is_err());
+        ctx.blob_features = BlobFeatures::CHUNK_COMPRESSOR.bits();
+        assert!(chunk.validate(&ctx).is_ok());
+
+        chunk.set_compressor(Some(compress::Algorithm::Zstd));
+        assert_eq!(chunk.compressor(), Some(compress::Algorithm::Zstd));
+        assert_eq!(chunk.uncompressed_size(), 0x1000);
+        assert_eq!(chunk.compressed_size(), 0x20);
+
+        // Unknown algorithms are rejected.
+        chunk.uncomp_info |= u64::to_le(0xf << CHUNK_V2_COMPRESSOR_SHIFT);
+        assert_eq!(chunk.compressor(), None);
+        assert!(chunk.validate(&ctx).is_err());
+
+        chunk.set_compressor(None);
+        assert_eq!(chunk.compressor(), None);
+        assert!(chunk.validate(&ctx).is_ok());
+    }
 }
@@ -340,6 +340,15 @@ impl BlobCompressionContextHeader {
         }
     }
 
+    /// Set flag indicating chunks may be compressed by algorithms other than the blob compressor.
+    pub fn set_chunk_compressor(&mut self, enable: bool) {
+        if enable {
+            self.s_features |= BlobFeatures::CHUNK_COMPRESSOR.bits();
+        } else {
+            self.s_features &= !BlobFeatures::CHUNK_COMPRESSOR.bits();
+        }
+    }
+
     /// Get blob meta feature flags.
     pub fn features(&self) -> u32 {
         self.s_features
@@ -1162,6 +1171,7 @@ impl BlobMetaChunkArray {
         encrypted: bool,
         is_batch: bool,
         data: u64,
+        compressor: Option<compress::Algorithm>,
     ) {
         match self {
             BlobMetaChunkArray::V2(v) => {
@@ -1174,6 +1184,7 @@ impl BlobMetaChunkArray {
                 meta.set_encrypted(encrypted);
                 meta.set_batch(is_batch);
                 meta.set_data(data);
+                meta.set_compressor(compressor);
                 v.push(meta);
             }
             BlobMetaChunkArray::V1(_v) => unimplemented!(),
@@ -1359,6 +1370,13 @@ impl BlobMetaChunkArray {
         }
     }
 
+    fn compressor(&self, index: usize) -> Option<compress::Algorithm> {
+        match self {
+            BlobMetaChunkArray::V1(_v) => None,
+            BlobMetaChunkArray::V2(v) => v[index].compressor(),
+        }
+    }
+
     fn _get_chunk_index_nocheck<T: BlobMetaChunkInfo>(
         state: &BlobCompressionContext,
         chunks: &[T],
@@ -1854,6 +1872,10 @@ impl BlobChunkInfo for BlobMetaChunk {
         self.meta.chunk_info_array.is_encrypted(self.chunk_index)
     }
 
+    fn compressor(&self) -> Option<compress::Algorithm> {
+        self.meta.chunk_info_array.compressor(self.chunk_index)
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -1874,6 +1896,9 @@ impl BlobV5ChunkInfo for BlobMetaChunk {
         if self.is_compressed() {
             flags |= BlobChunkFlags::COMPRESSED;
         }
+        if let Some(algo) = self.compressor() {
+            flags.set_compressor(algo);
+        }
         flags
     }
 
@@ -1993,6 +2018,9 @@ pub fn format_blob_features(features: BlobFeatures) -> String {
     if features.contains(BlobFeatures::ENCRYPTED) {
         output += "encrypted ";
     }
+    if features.contains(BlobFeatures::CHUNK_COMPRESSOR) {
+        output += "chunk-compressor ";
+    }
     output.trim_end().to_string()
 }
 
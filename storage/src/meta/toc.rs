@@ -768,12 +768,14 @@ mod tests {
                 blob_file: "".to_string(),
                 dir: path.to_str().unwrap().to_string(),
                 alt_dirs: vec![],
+                mmap: false,
             }),
             localdisk: None,
             oss: None,
             registry: None,
             s3: None,
             http_proxy: None,
+            oci_layer: None,
         };
         let blob_mgr = BlobFactory::new_backend(&config, id).unwrap();
         let blob = blob_mgr.get_reader(id).unwrap();
@@ -829,11 +831,13 @@ mod tests {
                 blob_file: "".to_string(),
                 dir: path.to_str().unwrap().to_string(),
                 alt_dirs: vec![],
+                mmap: false,
             }),
             oss: None,
             registry: None,
             s3: None,
             http_proxy: None,
+            oci_layer: None,
             localdisk: None,
         };
         let blob_mgr = BlobFactory::new_backend(&config, id).unwrap();
@@ -864,12 +868,14 @@ mod tests {
                 blob_file: "".to_string(),
                 dir: path.to_str().unwrap().to_string(),
                 alt_dirs: vec![],
+                mmap: false,
             }),
             oss: None,
             registry: None,
             s3: None,
             localdisk: None,
             http_proxy: None,
+            oci_layer: None,
         };
         let blob_mgr = BlobFactory::new_backend(&config, id).unwrap();
         let blob = blob_mgr.get_reader(id).unwrap();
@@ -3,13 +3,16 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
+use nydus_utils::compress;
 use nydus_utils::digest::RafsDigest;
 use nydus_utils::metrics::BackendMetrics;
 
 use super::impl_getter;
-use crate::backend::{BackendResult, BlobBackend, BlobReader};
+use crate::backend::{BackendError, BackendResult, BlobBackend, BlobReader};
 use crate::device::v5::BlobV5ChunkInfo;
 use crate::device::{BlobChunkFlags, BlobChunkInfo};
 use std::any::Any;
@@ -55,6 +58,92 @@ impl BlobBackend for MockBackend {
     }
 }
 
+/// Behavior and statistics of readers created by [MockControlledBackend].
+#[derive(Default)]
+pub(crate) struct MockControl {
+    /// Fail all reads with [BackendError::Unsupported] when set.
+    pub failing: AtomicBool,
+    /// Latency of each read.
+    pub delay: Duration,
+    /// Value returned by [BlobReader::retry_limit].
+    pub retry_limit: u8,
+    /// Number of reads issued, including failed ones.
+    pub reads: AtomicUsize,
+    /// Number of reads in progress.
+    pub inflight: AtomicUsize,
+    /// Maximum number of concurrent reads observed.
+    pub max_inflight: AtomicUsize,
+}
+
+/// Mock reader of a blob of 0x1000 bytes, with data derived from offsets.
+pub(crate) struct MockControlledReader {
+    control: Arc<MockControl>,
+    metrics: Arc<BackendMetrics>,
+}
+
+impl BlobReader for MockControlledReader {
+    fn blob_size(&self) -> BackendResult<u64> {
+        Ok(0x1000)
+    }
+
+    fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        let control = &self.control;
+        let inflight = control.inflight.fetch_add(1, Ordering::SeqCst) + 1;
+        control.max_inflight.fetch_max(inflight, Ordering::SeqCst);
+        control.reads.fetch_add(1, Ordering::SeqCst);
+        if !control.delay.is_zero() {
+            std::thread::sleep(control.delay);
+        }
+        control.inflight.fetch_sub(1, Ordering::SeqCst);
+
+        if control.failing.load(Ordering::SeqCst) {
+            return Err(BackendError::Unsupported("mock failure".to_string()));
+        }
+        for (idx, v) in buf.iter_mut().enumerate() {
+            *v = (offset as usize + idx) as u8;
+        }
+        Ok(buf.len())
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        &self.metrics
+    }
+
+    fn retry_limit(&self) -> u8 {
+        self.control.retry_limit
+    }
+}
+
+/// Mock backend whose readers are driven by a shared [MockControl].
+pub(crate) struct MockControlledBackend {
+    pub control: Arc<MockControl>,
+    pub metrics: Arc<BackendMetrics>,
+}
+
+impl MockControlledBackend {
+    pub fn new(id: &str, control: MockControl) -> Self {
+        MockControlledBackend {
+            control: Arc::new(control),
+            metrics: BackendMetrics::new(id, "mock"),
+        }
+    }
+}
+
+impl BlobBackend for MockControlledBackend {
+    fn shutdown(&self) {}
+
+    fn metrics(&self) -> &BackendMetrics {
+        &self.metrics
+    }
+
+    fn get_reader(&self, _blob_id: &str) -> BackendResult<Arc<dyn BlobReader>> {
+        Ok(Arc::new(MockControlledReader {
+            control: self.control.clone(),
+            metrics: self.metrics.clone(),
+        }))
+    }
+}
+
 #[derive(Default, Clone)]
 pub(crate) struct MockChunkInfo {
     pub block_id: RafsDigest,
@@ -97,6 +186,10 @@ impl BlobChunkInfo for MockChunkInfo {
         false
     }
 
+    fn compressor(&self) -> Option<compress::Algorithm> {
+        self.flags.compressor()
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
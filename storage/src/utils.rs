@@ -22,16 +22,33 @@ use vm_memory::bytes::Bytes;
 
 use crate::{StorageError, StorageResult};
 
+// Maximum number of `IoVec` accepted by one `preadv` call, `IOV_MAX` on Linux and macOS.
+const IOV_MAX: usize = 1024;
+
 /// Just a simple wrapper for posix `preadv`. Provide a slice of `IoVec` as input.
+///
+/// The `iovec` slice is split into multiple `preadv` calls if it has more than `IOV_MAX` entries.
 pub fn readv(fd: RawFd, iovec: &mut [IoSliceMut], offset: u64) -> Result<usize> {
-    loop {
-        match preadv(fd, iovec, offset as off64_t).map_err(|_| last_error!()) {
-            Ok(ret) => return Ok(ret),
-            // Retry if the IO is interrupted by signal.
-            Err(err) if err.kind() != ErrorKind::Interrupted => return Err(err),
-            _ => continue,
+    let mut total = 0;
+
+    for iovec in iovec.chunks_mut(IOV_MAX) {
+        let size = iovec.iter().map(|v| v.len()).sum::<usize>();
+        let ret = loop {
+            match preadv(fd, iovec, (offset + total as u64) as off64_t).map_err(|_| last_error!()) {
+                Ok(ret) => break ret,
+                // Retry if the IO is interrupted by signal.
+                Err(err) if err.kind() != ErrorKind::Interrupted => return Err(err),
+                _ => continue,
+            }
+        };
+        total += ret;
+        // Stop on short read, following `IoVec` can't be filled anyway.
+        if ret < size {
+            break;
         }
     }
+
+    Ok(total)
 }
 
 /// Copy from buffer slice to another buffer slice.
@@ -58,6 +75,61 @@ pub fn copyv<S: AsRef<[u8]>>(
         return Err(StorageError::MemOverflow);
     }
 
+    match copyv_single(src, dst, offset, length, dst_index, dst_offset) {
+        Some(res) => res,
+        None => copyv_multi(src, dst, offset, length, dst_index, dst_offset),
+    }
+}
+
+// Fast path of `copyv()` for the common case of a single source buffer fitting in the current
+// destination slice, returns `None` if not applicable.
+//
+// Parameters must have been validated by `copyv()`.
+#[inline]
+fn copyv_single<S: AsRef<[u8]>>(
+    src: &[S],
+    dst: &[FileVolatileSlice],
+    offset: usize,
+    length: usize,
+    dst_index: usize,
+    dst_offset: usize,
+) -> Option<StorageResult<(usize, (usize, usize))>> {
+    if src.len() != 1 {
+        return None;
+    }
+    let s = src[0].as_ref();
+    let buffer_len = min(s.len() - offset, length);
+    let dst_slice = &dst[dst_index];
+    let dst_left = dst_slice.len() - dst_offset;
+    if buffer_len > dst_left {
+        return None;
+    }
+
+    let res = dst_slice
+        .write(&s[offset..offset + buffer_len], dst_offset)
+        .map_err(StorageError::VolatileSlice)
+        .map(|written| {
+            if written == dst_left {
+                (written, (dst_index + 1, 0))
+            } else {
+                (written, (dst_index, dst_offset + written))
+            }
+        });
+
+    Some(res)
+}
+
+// General path of `copyv()` to copy from multiple source buffers into multiple destination slices.
+//
+// Parameters must have been validated by `copyv()`.
+fn copyv_multi<S: AsRef<[u8]>>(
+    src: &[S],
+    dst: &[FileVolatileSlice],
+    offset: usize,
+    length: usize,
+    mut dst_index: usize,
+    mut dst_offset: usize,
+) -> StorageResult<(usize, (usize, usize))> {
     let mut copied = 0;
     let mut src_offset = offset;
     'next_source: for s in src {
@@ -222,6 +294,10 @@ pub fn readahead(fd: libc::c_int, mut offset: u64, end: u64) {
 }
 
 /// A customized buf allocator that avoids zeroing
+///
+/// Content of the returned buffer is uninitialized, so the caller must fully overwrite the buffer
+/// before using it. When filling it from a storage backend, the caller must check the number of
+/// bytes read and reject short reads, otherwise uninitialized memory may leak into served data.
 pub fn alloc_buf(size: usize) -> Vec<u8> {
     assert!(size < isize::MAX as usize);
     let layout = Layout::from_size_align(size, 0x1000)
@@ -239,6 +315,37 @@ pub fn check_digest(data: &[u8], digest: &RafsDigest, digester: digest::Algorith
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Write;
+    use std::os::unix::io::AsRawFd;
+    use vmm_sys_util::tempfile::TempFile;
+
+    #[test]
+    fn test_readv_more_than_iov_max() {
+        let file = TempFile::new().unwrap();
+        let data: Vec<u8> = (0..IOV_MAX * 2 + 16).map(|v| v as u8).collect();
+        file.as_file().write_all(&data).unwrap();
+
+        let count = IOV_MAX + 100;
+        let mut bufs = vec![[0u8; 2]; count];
+        let mut iovec: Vec<IoSliceMut> = bufs
+            .iter_mut()
+            .map(|b| IoSliceMut::new(&mut b[..]))
+            .collect();
+        let size = readv(file.as_file().as_raw_fd(), &mut iovec, 8).unwrap();
+        assert_eq!(size, count * 2);
+        drop(iovec);
+        assert_eq!(bufs.concat(), data[8..8 + count * 2]);
+
+        // Short read at end of file.
+        let mut bufs = vec![[0u8; 2]; count];
+        let mut iovec: Vec<IoSliceMut> = bufs
+            .iter_mut()
+            .map(|b| IoSliceMut::new(&mut b[..]))
+            .collect();
+        let offset = data.len() - IOV_MAX * 2 - 4;
+        let size = readv(file.as_file().as_raw_fd(), &mut iovec, offset as u64).unwrap();
+        assert_eq!(size, IOV_MAX * 2 + 4);
+    }
 
     #[test]
     fn test_copyv() {
@@ -303,6 +410,59 @@ mod tests {
         assert_eq!(dst_buf2[3], 6);
     }
 
+    #[test]
+    fn test_copyv_single_fast_path() {
+        let src = [vec![1u8, 2, 3, 4, 5, 6]];
+        // (offset, length, dst_index, dst_offset)
+        let cases = [
+            (0, 4, 0, 0),
+            (1, 3, 0, 1),
+            (2, 2, 1, 2),
+            (0, 6, 0, 0),
+            (6, 1, 1, 4),
+            (0, 5, 0, 3),
+        ];
+
+        for (offset, length, dst_index, dst_offset) in cases {
+            let mut fast_buf = [[0u8; 4], [0u8; 4]];
+            let mut general_buf = [[0u8; 4], [0u8; 4]];
+            let fast_dst = unsafe {
+                [
+                    FileVolatileSlice::from_raw_ptr(fast_buf[0].as_mut_ptr(), 4),
+                    FileVolatileSlice::from_raw_ptr(fast_buf[1].as_mut_ptr(), 4),
+                ]
+            };
+            let general_dst = unsafe {
+                [
+                    FileVolatileSlice::from_raw_ptr(general_buf[0].as_mut_ptr(), 4),
+                    FileVolatileSlice::from_raw_ptr(general_buf[1].as_mut_ptr(), 4),
+                ]
+            };
+
+            let general =
+                copyv_multi(&src, &general_dst, offset, length, dst_index, dst_offset).unwrap();
+            let fits = min(src[0].len() - offset, length) <= 4 - dst_offset;
+            match copyv_single(&src, &fast_dst, offset, length, dst_index, dst_offset) {
+                Some(fast) => {
+                    assert!(fits);
+                    assert_eq!(fast.unwrap(), general);
+                    assert_eq!(fast_buf, general_buf);
+                }
+                None => assert!(!fits),
+            }
+            assert_eq!(
+                copyv(&src, &fast_dst, offset, length, dst_index, dst_offset).unwrap(),
+                general
+            );
+        }
+
+        // Not applicable to multiple source buffers.
+        let src = [vec![1u8], vec![2u8]];
+        let mut buf = [0u8; 4];
+        let dst = unsafe { [FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), 4)] };
+        assert!(copyv_single(&src, &dst, 0, 2, 0, 0).is_none());
+    }
+
     #[test]
     fn test_mem_slice_cursor_move() {
         let mut buf1 = vec![0x0u8; 2];
@@ -10,10 +10,20 @@ pub mod unix_domain_socket;
 pub enum StorageBackendErr {
     #[error("failed to create UnixStream, {0}")]
     CreateUnixStream(io::Error),
+    #[error("failed to create UnixListener, {0}")]
+    CreateUnixListener(io::Error),
+    #[error("failed to accept UnixStream, {0}")]
+    AcceptUnixStream(io::Error),
     #[error("failed to send fd over UnixStream, {0}")]
     SendFd(io::Error),
     #[error("failed to receive fd over UnixStream, {0}")]
     RecvFd(io::Error),
+    #[error("failed to send state data over UnixStream, {0}")]
+    SendData(io::Error),
+    #[error("failed to receive state data over UnixStream, {0}")]
+    RecvData(io::Error),
+    #[error("state data is too large, {0} bytes")]
+    DataTooLarge(usize),
     #[error("no enough fds")]
     NoEnoughFds,
 }
@@ -29,6 +39,11 @@ pub trait StorageBackend: Send + Sync {
     /// Restore the dev fds and daemon state data for online upgrade.
     /// Returns the fds and state data
     fn restore(&mut self) -> Result<(Vec<RawFd>, Vec<u8>)>;
+
+    /// Check whether the backend is able to carry fds, such as the FUSE device fd.
+    fn supports_fd_passing(&self) -> bool {
+        false
+    }
 }
 
 #[cfg(test)]
@@ -60,6 +75,10 @@ mod test {
             fn restore(&mut self) -> Result<(Vec<RawFd>, Vec<u8>)> {
                 Ok((self.fds.clone(), self.data.clone()))
             }
+
+            fn supports_fd_passing(&self) -> bool {
+                true
+            }
         }
 
         const FDS_LEN: usize = 10;
@@ -68,6 +87,7 @@ mod test {
         let data: [u8; DATA_LEN] = [7, 8, 9, 10, 12];
 
         let mut backend: Box<dyn StorageBackend> = Box::<TestStorageBackend>::default();
+        assert!(backend.supports_fd_passing());
         let saved_data_len = backend.save(&fds, &data).unwrap();
         assert_eq!(saved_data_len, DATA_LEN);
 
@@ -3,14 +3,89 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::{
-    os::{fd::RawFd, unix::net::UnixStream},
-    path::PathBuf,
+    io::{ErrorKind, Read, Write},
+    os::{
+        fd::RawFd,
+        unix::net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
 };
 
 use sendfd::{RecvWithFd, SendWithFd};
 
 use super::{Result, StorageBackend, StorageBackendErr};
 
+const MAX_STATE_DATA_LENGTH: usize = 1024 * 32;
+const MAX_STATE_FDS: usize = 16;
+
+fn check_state(fds: &[RawFd], data: &[u8]) -> Result<()> {
+    if fds.is_empty() {
+        return Err(StorageBackendErr::NoEnoughFds);
+    }
+    // The receiver can't accept more data than `MAX_STATE_DATA_LENGTH`, so reject oversized
+    // state data instead of leaving a truncated copy behind.
+    if data.len() > MAX_STATE_DATA_LENGTH {
+        return Err(StorageBackendErr::DataTooLarge(data.len()));
+    }
+    Ok(())
+}
+
+fn send_state(socket: &mut UnixStream, fds: &[RawFd], data: &[u8]) -> Result<usize> {
+    let len = loop {
+        match socket.send_with_fd(data, fds) {
+            Ok(len) => break len,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(StorageBackendErr::SendFd(e)),
+        }
+    };
+    // The fds have been sent along with the first part of data, send the remaining data.
+    if len < data.len() {
+        socket
+            .write_all(&data[len..])
+            .map_err(StorageBackendErr::SendData)?;
+    }
+
+    Ok(data.len())
+}
+
+fn recv_state(socket: &UnixStream) -> Result<(Vec<RawFd>, Vec<u8>)> {
+    let mut data = vec![0u8; MAX_STATE_DATA_LENGTH];
+    let mut fds = vec![0i32; MAX_STATE_FDS];
+    let (len, fds_cnt) = loop {
+        match socket.recv_with_fd(data.as_mut_slice(), fds.as_mut_slice()) {
+            Ok(v) => break v,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(StorageBackendErr::RecvFd(e)),
+        }
+    };
+
+    if fds_cnt == 0 {
+        return Err(StorageBackendErr::NoEnoughFds);
+    }
+    fds.truncate(fds_cnt);
+    data.truncate(len);
+    Ok((fds, data))
+}
+
+/// Receive fds and all state data, until the sender closes the connection.
+///
+/// The fds come along with the first part of data, which may be followed by more parts.
+fn recv_all(mut socket: UnixStream) -> Result<(Vec<RawFd>, Vec<u8>)> {
+    let (fds, mut data) = recv_state(&socket)?;
+    let mut buf = Vec::new();
+    (&mut socket)
+        .take((MAX_STATE_DATA_LENGTH - data.len()) as u64 + 1)
+        .read_to_end(&mut buf)
+        .map_err(StorageBackendErr::RecvData)?;
+    data.append(&mut buf);
+    if data.len() > MAX_STATE_DATA_LENGTH {
+        return Err(StorageBackendErr::DataTooLarge(data.len()));
+    }
+    Ok((fds, data))
+}
+
+/// Storage backend for nydusd to save/restore fds and state data to/from the supervisor
+/// through a Unix domain socket.
 pub struct UdsStorageBackend {
     socket_path: PathBuf,
 }
@@ -21,36 +96,209 @@ impl UdsStorageBackend {
     }
 }
 
-const MAX_STATE_DATA_LENGTH: usize = 1024 * 32;
-
 impl StorageBackend for UdsStorageBackend {
     fn save(&mut self, fds: &[RawFd], data: &[u8]) -> Result<usize> {
-        if fds.is_empty() {
-            return Err(StorageBackendErr::NoEnoughFds);
-        }
-
-        let socket =
+        check_state(fds, data)?;
+        let mut socket =
             UnixStream::connect(&self.socket_path).map_err(StorageBackendErr::CreateUnixStream)?;
-        let len = socket
-            .send_with_fd(data, fds)
-            .map_err(StorageBackendErr::SendFd)?;
-
-        Ok(len)
+        send_state(&mut socket, fds, data)
     }
 
     fn restore(&mut self) -> Result<(Vec<RawFd>, Vec<u8>)> {
-        let mut data = vec![0u8; MAX_STATE_DATA_LENGTH];
-        let mut fds = vec![0i32; 16];
         let socket =
             UnixStream::connect(&self.socket_path).map_err(StorageBackendErr::CreateUnixStream)?;
-        let (_, fds_cnt) = socket
-            .recv_with_fd(data.as_mut_slice(), fds.as_mut_slice())
-            .map_err(StorageBackendErr::RecvFd)?;
+        recv_all(socket)
+    }
+
+    fn supports_fd_passing(&self) -> bool {
+        true
+    }
+}
+
+/// Supervisor side of [UdsStorageBackend], which listens on the Unix domain socket and keeps
+/// fds and state data for nydusd across online upgrade.
+///
+/// Fds received by [UdsStorageListener::accept_and_recv()] are owned by the caller.
+pub struct UdsStorageListener {
+    listener: UnixListener,
+}
+
+impl UdsStorageListener {
+    /// Bind to the Unix domain socket at `socket_path`, which must not exist yet.
+    pub fn bind<P: AsRef<Path>>(socket_path: P) -> Result<Self> {
+        let listener =
+            UnixListener::bind(socket_path).map_err(StorageBackendErr::CreateUnixListener)?;
+        Ok(UdsStorageListener { listener })
+    }
+
+    /// Accept a connection from [UdsStorageBackend::save()] and receive the fds and state data.
+    pub fn accept_and_recv(&self) -> Result<(Vec<RawFd>, Vec<u8>)> {
+        let socket = self.accept()?;
+        recv_all(socket)
+    }
+
+    /// Accept a connection from [UdsStorageBackend::restore()] and send the fds and state data.
+    pub fn accept_and_send(&self, fds: &[RawFd], data: &[u8]) -> Result<usize> {
+        check_state(fds, data)?;
+        let mut socket = self.accept()?;
+        send_state(&mut socket, fds, data)
+    }
+
+    fn accept(&self) -> Result<UnixStream> {
+        loop {
+            match self.listener.accept() {
+                Ok((socket, _)) => return Ok(socket),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(StorageBackendErr::AcceptUnixStream(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{Seek, SeekFrom};
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::thread;
+
+    use super::*;
+
+    fn prepare_socket_path(name: &str) -> (PathBuf, PathBuf) {
+        let tmp_dir =
+            std::env::temp_dir().join(format!("nydus-upgrade-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&tmp_dir).unwrap();
+        let socket_path = tmp_dir.join("upgrade.sock");
+        let _ = std::fs::remove_file(&socket_path);
+        (tmp_dir, socket_path)
+    }
 
-        if fds.is_empty() {
-            return Err(StorageBackendErr::NoEnoughFds);
+    #[test]
+    fn test_uds_storage_backend_save_restore() {
+        let (tmp_dir, socket_path) = prepare_socket_path("save-restore");
+        let listener = UdsStorageListener::bind(&socket_path).unwrap();
+        let sizes = [1usize, 0x100, 0x1000, 0x4000, MAX_STATE_DATA_LENGTH];
+
+        // A simple state server, which keeps the last saved fds and data, and returns them on
+        // the following connection.
+        let server = thread::spawn(move || {
+            for _ in sizes.iter() {
+                let (fds, data) = listener.accept_and_recv().unwrap();
+                listener.accept_and_send(&fds, &data).unwrap();
+                for fd in fds {
+                    drop(unsafe { File::from_raw_fd(fd) });
+                }
+            }
+        });
+
+        let file = File::open("/dev/null").unwrap();
+        let mut backend = UdsStorageBackend::new(socket_path.clone());
+        assert!(backend.supports_fd_passing());
+        for size in sizes.iter() {
+            let data = (0..*size).map(|v| (v % 251) as u8).collect::<Vec<u8>>();
+            assert_eq!(backend.save(&[file.as_raw_fd()], &data).unwrap(), *size);
+            let (fds, restored) = backend.restore().unwrap();
+            assert_eq!(fds.len(), 1);
+            assert_eq!(restored, data);
+            drop(unsafe { File::from_raw_fd(fds[0]) });
         }
-        fds.truncate(fds_cnt);
-        Ok((fds, data))
+        server.join().unwrap();
+
+        assert!(matches!(
+            backend.save(&[file.as_raw_fd()], &vec![0u8; MAX_STATE_DATA_LENGTH + 1]),
+            Err(StorageBackendErr::DataTooLarge(_))
+        ));
+        assert!(matches!(
+            backend.save(&[], &[0u8]),
+            Err(StorageBackendErr::NoEnoughFds)
+        ));
+        std::fs::remove_dir_all(&tmp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_uds_storage_backend_restore_multi_part_state() {
+        let (tmp_dir, socket_path) = prepare_socket_path("multi-part");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let data = (0..MAX_STATE_DATA_LENGTH)
+            .map(|v| (v % 247) as u8)
+            .collect::<Vec<u8>>();
+        let expected = data.clone();
+
+        // Send the state in several parts, the restorer must wait for all of them.
+        let server = thread::spawn(move || {
+            let file = File::open("/dev/null").unwrap();
+            let (mut socket, _) = listener.accept().unwrap();
+            let len = socket
+                .send_with_fd(&data[..0x100], &[file.as_raw_fd()])
+                .unwrap();
+            assert_eq!(len, 0x100);
+            for part in data[0x100..].chunks(0x1800) {
+                thread::sleep(std::time::Duration::from_millis(5));
+                socket.write_all(part).unwrap();
+            }
+        });
+
+        let mut backend = UdsStorageBackend::new(socket_path);
+        let (fds, restored) = backend.restore().unwrap();
+        server.join().unwrap();
+        assert_eq!(fds.len(), 1);
+        assert_eq!(restored, expected);
+        drop(unsafe { File::from_raw_fd(fds[0]) });
+        std::fs::remove_dir_all(&tmp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_uds_storage_listener_pass_fd_between_daemons() {
+        let (tmp_dir, socket_path) = prepare_socket_path("listener");
+        let listener = UdsStorageListener::bind(&socket_path).unwrap();
+        assert!(matches!(
+            UdsStorageListener::bind(&socket_path),
+            Err(StorageBackendErr::CreateUnixListener(_))
+        ));
+
+        // The supervisor holds the fd and opaque state data of the old daemon, then hands them
+        // over to the new daemon.
+        let supervisor = thread::spawn(move || {
+            let (fds, data) = listener.accept_and_recv().unwrap();
+            assert_eq!(fds.len(), 1);
+            assert_eq!(listener.accept_and_send(&fds, &data).unwrap(), data.len());
+            drop(unsafe { File::from_raw_fd(fds[0]) });
+            data
+        });
+
+        // Stand-in for the FUSE device fd, which must survive the hand-over.
+        let dev_path = tmp_dir.join("fuse-dev");
+        let mut dev = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&dev_path)
+            .unwrap();
+        dev.write_all(b"fuse session").unwrap();
+        let opaque = (0..MAX_STATE_DATA_LENGTH)
+            .map(|v| (v % 253) as u8)
+            .collect::<Vec<u8>>();
+
+        let mut old_daemon = UdsStorageBackend::new(socket_path.clone());
+        assert_eq!(
+            old_daemon.save(&[dev.as_raw_fd()], &opaque).unwrap(),
+            opaque.len()
+        );
+        drop(dev);
+
+        let mut new_daemon = UdsStorageBackend::new(socket_path);
+        let (fds, restored) = new_daemon.restore().unwrap();
+        assert_eq!(supervisor.join().unwrap(), opaque);
+        assert_eq!(restored, opaque);
+        assert_eq!(fds.len(), 1);
+
+        let mut dev = unsafe { File::from_raw_fd(fds[0]) };
+        dev.seek(SeekFrom::Start(0)).unwrap();
+        let mut buf = Vec::new();
+        dev.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"fuse session");
+
+        std::fs::remove_dir_all(&tmp_dir).unwrap();
     }
 }
@@ -6,7 +6,7 @@ use std::fmt::Debug;
 use std::io::{Error as IoError, ErrorKind, Result};
 use std::{any::TypeId, collections::HashMap};
 
-use dbs_snapshot::Snapshot;
+use dbs_snapshot::{Error as SnapshotError, Snapshot};
 use versionize::{VersionMap, Versionize};
 
 /// A list of versions.
@@ -55,9 +55,21 @@ pub trait Snapshotter: Versionize + Sized + Debug {
     }
 
     /// Restores the struct from a `Vec<u8>`.
+    ///
+    /// State data saved by older versions is migrated to the latest version, and state data
+    /// with an unknown version is rejected.
     fn restore(buf: &mut Vec<u8>) -> Result<Self> {
-        match Snapshot::load(&mut buf.as_slice(), buf.len(), Self::new_version_map()) {
+        let version_map = Self::new_version_map();
+        let latest_version = version_map.latest_version();
+        match Snapshot::load(&mut buf.as_slice(), buf.len(), version_map) {
             Ok((o, _)) => Ok(o),
+            Err(SnapshotError::InvalidDataVersion(v)) => Err(IoError::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "unsupported snapshot data version {}, supported versions are 1 to {}",
+                    v, latest_version
+                ),
+            )),
             Err(e) => Err(IoError::new(
                 ErrorKind::Other,
                 format!("Failed to load snapshot: {:?}", e),
@@ -65,3 +77,90 @@ pub trait Snapshotter: Versionize + Sized + Debug {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use versionize::VersionizeResult;
+    use versionize_derive::Versionize;
+
+    #[derive(Versionize, PartialEq, Debug)]
+    struct TestState {
+        fd_count: u32,
+        #[version(start = 2, default_fn = "default_mounts")]
+        mounts: Vec<String>,
+    }
+
+    impl TestState {
+        fn default_mounts(_source_version: u16) -> Vec<String> {
+            vec!["/".to_string()]
+        }
+    }
+
+    impl Snapshotter for TestState {
+        fn get_versions() -> Versions {
+            vec![
+                HashMap::from([(TestState::type_id(), 1)]),
+                HashMap::from([(TestState::type_id(), 2)]),
+            ]
+        }
+    }
+
+    // Save the state data as the specified data version.
+    fn save_as_version(state: &TestState, version: u16) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let mut snapshot = Snapshot::new(TestState::new_version_map(), version);
+        snapshot.save(&mut buf, state).unwrap();
+        buf
+    }
+
+    // The data version is stored right after the 64-bit magic id.
+    fn set_data_version(buf: &mut [u8], version: u16) {
+        buf[8..10].copy_from_slice(&version.to_le_bytes());
+    }
+
+    #[test]
+    fn test_snapshotter_save_restore() {
+        let state = TestState {
+            fd_count: 1,
+            mounts: vec!["/mnt".to_string()],
+        };
+        let mut buf = state.save().unwrap();
+        assert_eq!(TestState::restore(&mut buf).unwrap(), state);
+    }
+
+    #[test]
+    fn test_snapshotter_migrate_older_version() {
+        let state = TestState {
+            fd_count: 2,
+            mounts: vec!["/mnt".to_string()],
+        };
+        let mut buf = save_as_version(&state, 1);
+        let restored = TestState::restore(&mut buf).unwrap();
+        assert_eq!(restored.fd_count, 2);
+        assert_eq!(restored.mounts, vec!["/".to_string()]);
+    }
+
+    #[test]
+    fn test_snapshotter_reject_unknown_version() {
+        let state = TestState {
+            fd_count: 1,
+            mounts: Vec::new(),
+        };
+
+        let mut buf = state.save().unwrap();
+        set_data_version(&mut buf, 0);
+        let err = TestState::restore(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "unsupported snapshot data version 0, supported versions are 1 to 2"
+        );
+
+        let mut buf = state.save().unwrap();
+        set_data_version(&mut buf, 3);
+        let err = TestState::restore(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().contains("data version 3"));
+    }
+}
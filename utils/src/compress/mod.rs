@@ -5,7 +5,7 @@
 use std::borrow::Cow;
 use std::convert::TryFrom;
 use std::fmt;
-use std::io::{BufReader, Error, Read, Result, Write};
+use std::io::{BufReader, Error, ErrorKind, Read, Result, Write};
 use std::str::FromStr;
 
 mod lz4_standard;
@@ -37,13 +37,21 @@ impl FromStr for Algorithm {
     type Err = Error;
 
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        match s {
-            "none" => Ok(Self::None),
-            "lz4_block" => Ok(Self::Lz4Block),
-            "gzip" => Ok(Self::GZip),
-            "zstd" => Ok(Self::Zstd),
-            _ => Err(einval!("compression algorithm should be none or lz4_block")),
-        }
+        Self::ALL
+            .iter()
+            .find(|algo| algo.as_str() == s)
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<&str> = Self::ALL.iter().map(|algo| algo.as_str()).collect();
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "unknown compression algorithm `{}`, should be one of: {}",
+                        s,
+                        names.join(", ")
+                    ),
+                )
+            })
     }
 }
 
@@ -84,6 +92,24 @@ impl TryFrom<u64> for Algorithm {
 }
 
 impl Algorithm {
+    /// All supported compression algorithms.
+    pub const ALL: [Algorithm; 4] = [
+        Algorithm::None,
+        Algorithm::Lz4Block,
+        Algorithm::GZip,
+        Algorithm::Zstd,
+    ];
+
+    /// Get name of the compression algorithm, which is accepted by `Algorithm::from_str()`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Algorithm::None => "none",
+            Algorithm::Lz4Block => "lz4_block",
+            Algorithm::GZip => "gzip",
+            Algorithm::Zstd => "zstd",
+        }
+    }
+
     /// Check whether the compression algorithm is none.
     pub fn is_none(self) -> bool {
         self == Self::None
@@ -252,6 +278,21 @@ mod tests {
     use std::path::Path;
     use vmm_sys_util::tempfile::TempFile;
 
+    #[test]
+    fn test_compress_algorithm_from_str() {
+        for algo in Algorithm::ALL {
+            assert_eq!(Algorithm::from_str(algo.as_str()).unwrap(), algo);
+        }
+
+        // The returned error, not only the log, tells which algorithms are accepted.
+        let err = Algorithm::from_str("brotli").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(
+            err.to_string(),
+            "unknown compression algorithm `brotli`, should be one of: none, lz4_block, gzip, zstd"
+        );
+    }
+
     #[test]
     fn test_compress_algorithm_gzip() {
         let buf = vec![0x2u8; 4095];
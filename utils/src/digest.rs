@@ -27,9 +27,19 @@ pub enum Algorithm {
     Sha256 = 1,
 }
 
+impl Algorithm {
+    /// Get the name of the digest algorithm, which may be parsed back by `Algorithm::from_str()`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Algorithm::Blake3 => "blake3",
+            Algorithm::Sha256 => "sha256",
+        }
+    }
+}
+
 impl fmt::Display for Algorithm {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+        write!(f, "{}", self.as_str())
     }
 }
 
@@ -227,6 +237,10 @@ mod test {
         assert_eq!(Algorithm::from_str("sha256").unwrap(), Algorithm::Sha256);
         Algorithm::from_str("Blake3").unwrap_err();
         Algorithm::from_str("SHA256").unwrap_err();
+
+        for algo in [Algorithm::Blake3, Algorithm::Sha256] {
+            assert_eq!(Algorithm::from_str(&algo.to_string()).unwrap(), algo);
+        }
     }
 
     #[test]
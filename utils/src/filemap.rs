@@ -93,6 +93,55 @@ impl FileMapState {
         self.size
     }
 
+    /// Prefault the mapped region into memory and lock it by mlock().
+    ///
+    /// Return `Ok(false)` if the region is only prefaulted because it exceeds `RLIMIT_MEMLOCK`.
+    pub fn prefault_and_lock(&self) -> Result<bool> {
+        if self.base.is_null() || self.size == 0 {
+            return Ok(true);
+        }
+
+        let base = self.base as *mut libc::c_void;
+        if unsafe { libc::madvise(base, self.size, libc::MADV_WILLNEED) } < 0 {
+            return Err(last_error!("failed to prefault memory mapped file region"));
+        }
+
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } < 0 {
+            return Err(last_error!("failed to get RLIMIT_MEMLOCK"));
+        }
+        if limit.rlim_cur != libc::RLIM_INFINITY && self.size as libc::rlim_t > limit.rlim_cur {
+            return Ok(false);
+        }
+        if unsafe { libc::mlock(base, self.size) } < 0 {
+            return Err(last_error!("failed to lock memory mapped file region"));
+        }
+
+        Ok(true)
+    }
+
+    /// Check whether all pages of the mapped region are resident in memory.
+    #[cfg(target_os = "linux")]
+    pub fn is_resident(&self) -> Result<bool> {
+        if self.base.is_null() || self.size == 0 {
+            return Ok(true);
+        }
+
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64;
+        let mut pages = vec![0u8; crate::div_round_up(self.size as u64, page_size) as usize];
+        let base = self.base as *mut libc::c_void;
+        if unsafe { libc::mincore(base, self.size, pages.as_mut_ptr()) } < 0 {
+            return Err(last_error!(
+                "failed to get residency of memory mapped file region"
+            ));
+        }
+
+        Ok(pages.iter().all(|v| v & 0x1 != 0))
+    }
+
     /// Cast a subregion of the mapped area to an object reference.
     pub fn get_ref<T>(&self, offset: usize) -> Result<&T> {
         let start = self.base.wrapping_add(offset);
@@ -211,6 +260,41 @@ pub fn clone_file(fd: RawFd) -> Result<File> {
     }
 }
 
+/// Copy `data` into a sealed in-memory file, which can't be modified any more.
+#[cfg(target_os = "linux")]
+pub fn sealed_copy(data: &[u8]) -> Result<File> {
+    use nix::fcntl::{fcntl, FcntlArg, SealFlag};
+    use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+    use std::ffi::CString;
+    use std::io::{Seek, SeekFrom, Write};
+
+    let name = CString::new("nydus-sealed-file").unwrap();
+    let fd = memfd_create(
+        &name,
+        MemFdCreateFlag::MFD_CLOEXEC | MemFdCreateFlag::MFD_ALLOW_SEALING,
+    )
+    .map_err(|e| eother!(format!("failed to create memfd, {}", e)))?;
+    // Safe because we have just created the fd.
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    file.write_all(data)?;
+    let seals = SealFlag::F_SEAL_SEAL
+        | SealFlag::F_SEAL_SHRINK
+        | SealFlag::F_SEAL_GROW
+        | SealFlag::F_SEAL_WRITE;
+    fcntl(fd, FcntlArg::F_ADD_SEALS(seals))
+        .map_err(|e| eother!(format!("failed to seal memfd, {}", e)))?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
+
+/// Copy `data` into a sealed in-memory file, which can't be modified any more.
+#[cfg(not(target_os = "linux"))]
+pub fn sealed_copy(_data: &[u8]) -> Result<File> {
+    Err(enosys!(
+        "sealed in-memory files are only supported on Linux"
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use vmm_sys_util::tempfile::TempFile;
@@ -242,6 +326,24 @@ mod tests {
         drop(map);
     }
 
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_prefault_and_lock_file_map() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let path = PathBuf::from(root_dir).join("../tests/texture/bootstrap/rafs-v6-2.2.boot");
+        let file = OpenOptions::new().read(true).open(path).unwrap();
+        let size = file.metadata().unwrap().len() as usize;
+        let map = FileMapState::new(file, 0, size, false).unwrap();
+
+        // Locking may be denied by RLIMIT_MEMLOCK, but pages are prefaulted anyway.
+        let _ = map.prefault_and_lock();
+        assert!(map.is_resident().unwrap());
+
+        let map = FileMapState::default();
+        assert!(map.prefault_and_lock().unwrap());
+        assert!(map.is_resident().unwrap());
+    }
+
     #[test]
     fn create_default_file_map_object() {
         let map = FileMapState::default();
@@ -14,7 +14,7 @@ use std::collections::{HashMap, HashSet};
 use std::ops::{Deref, Drop};
 use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
 use nydus_api::http::MetricsError;
 
@@ -206,6 +206,19 @@ pub struct FsIoStats {
     data_read: BasicMetric,
     // Cumulative bytes for different block size.
     block_count_read: [BasicMetric; BLOCK_READ_SIZES_MAX],
+    // Bytes requested by reads served from data chunks, excluding inline data.
+    amplify_read_bytes: BasicMetric,
+    // Uncompressed size of the data chunks backing those reads.
+    amplify_chunk_bytes: BasicMetric,
+    // Number of reads reaching the read amplification warning threshold.
+    amplified_reads: BasicMetric,
+    // Number of warnings logged about those reads, which are rate limited.
+    amplified_read_warns: BasicMetric,
+    // Time spent to load filesystem metadata when mounting, including loading and validating
+    // inodes in cached mode. Unit as micro-seconds.
+    metadata_load_latency: BasicMetric,
+    // Time spent to mount the filesystem, including loading metadata. Unit as micro-seconds.
+    mount_latency: BasicMetric,
     // Counters for successful various file operations.
     fop_hits: [BasicMetric; StatsFop::Max as usize],
     // Counters for failed file operations.
@@ -228,6 +241,9 @@ pub struct FsIoStats {
     // record regular file read
     #[serde(skip_serializing, skip_deserializing)]
     recent_read_files: InodeBitmap,
+    // Time of the last warning about amplified reads, and value of `amplified_reads` then.
+    #[serde(skip_serializing, skip_deserializing)]
+    amplified_read_last_warn: Mutex<Option<(Instant, u64)>>,
 }
 
 macro_rules! impl_iostat_option {
@@ -300,6 +316,27 @@ impl FsIoStats {
         }
     }
 
+    /// Get inode numbers of files which have been read, in the order of their first access.
+    ///
+    /// It returns an empty list if access pattern recording is disabled.
+    pub fn accessed_files(&self) -> Vec<Inode> {
+        let records = self.access_patterns.read().unwrap();
+        let mut files = records
+            .values()
+            .filter(|r| r.nr_read.count() != 0)
+            .map(|r| {
+                (
+                    r.first_access_time_secs.load(Ordering::Relaxed),
+                    r.first_access_time_nanos.load(Ordering::Relaxed),
+                    r.ino,
+                )
+            })
+            .collect::<Vec<_>>();
+        files.sort_unstable();
+
+        files.into_iter().map(|(_, _, ino)| ino).collect()
+    }
+
     fn file_stats_update(&self, ino: Inode, fop: StatsFop, bsize: usize, success: bool) {
         self.fop_update(fop, bsize, success);
 
@@ -352,6 +389,59 @@ impl FsIoStats {
         }
     }
 
+    /// Record a read of `read_bytes` which is backed by `chunk_bytes` of data chunks.
+    pub fn read_amplification_update(&self, read_bytes: u64, chunk_bytes: u64) {
+        self.amplify_read_bytes.add(read_bytes);
+        self.amplify_chunk_bytes.add(chunk_bytes);
+    }
+
+    /// Record a read reaching the read amplification warning threshold.
+    ///
+    /// Warnings are logged at most once per `interval`, return the number of such reads since
+    /// the last warning if the caller should log a warning now.
+    pub fn amplified_read_update(&self, interval: Duration) -> Option<u64> {
+        self.amplified_reads.inc();
+        let mut last_warn = self.amplified_read_last_warn.lock().unwrap();
+        let now = Instant::now();
+        let warned_reads = match *last_warn {
+            Some((time, _)) if now.duration_since(time) < interval => return None,
+            Some((_, reads)) => reads,
+            None => 0,
+        };
+        let reads = self.amplified_reads.count();
+        *last_warn = Some((now, reads));
+        self.amplified_read_warns.inc();
+
+        Some(reads - warned_reads)
+    }
+
+    /// Get ratio of data chunk bytes to bytes requested by reads, zero if nothing has been read.
+    pub fn read_amplification(&self) -> f64 {
+        let read_bytes = self.amplify_read_bytes.count();
+        if read_bytes == 0 {
+            0.0
+        } else {
+            self.amplify_chunk_bytes.count() as f64 / read_bytes as f64
+        }
+    }
+
+    /// Record time spent to load filesystem metadata and to mount the filesystem.
+    pub fn mount_latency_update(&self, metadata_load: &Duration, mount: &Duration) {
+        self.metadata_load_latency
+            .set(saturating_duration_micros(metadata_load));
+        self.mount_latency.set(saturating_duration_micros(mount));
+    }
+
+    /// Get time spent to load filesystem metadata in micro-seconds.
+    pub fn metadata_load_latency(&self) -> u64 {
+        self.metadata_load_latency.count()
+    }
+
+    /// Get time spent to mount the filesystem in micro-seconds.
+    pub fn mount_latency(&self) -> u64 {
+        self.mount_latency.count()
+    }
+
     /// Mark starting of filesystem operation.
     pub fn latency_start(&self) -> Option<SystemTime> {
         if !self.measure_latency.load(Ordering::Relaxed) {
@@ -617,6 +707,8 @@ pub struct BackendMetrics {
     read_count: BasicMetric,
     // Cumulative count of read failure to backend
     read_errors: BasicMetric,
+    // Cumulative count of chunks failed to decompress, usually caused by corrupted blobs
+    decompress_errors: BasicMetric,
     // Cumulative amount of data from to backend in unit of Byte. External tools
     // are responsible for calculating BPS from this field.
     read_amount_total: BasicMetric,
@@ -680,6 +772,11 @@ impl BackendMetrics {
         }
     }
 
+    /// Mark a failure to decompress chunk data read from the backend.
+    pub fn decompress_error(&self) {
+        self.decompress_errors.inc();
+    }
+
     fn export_metrics(&self) -> IoStatsResult<String> {
         serde_json::to_string(self).map_err(MetricsError::Serialize)
     }
@@ -752,6 +849,12 @@ pub struct BlobcacheMetrics {
     pub prefetch_end_time_millis: BasicMetric,
     pub buffered_backend_size: BasicMetric,
     pub data_all_ready: AtomicBool,
+    // Bytes of chunk data held by the blobcache, updated as chunks get cached or invalidated.
+    pub cached_bytes: BasicMetric,
+    // Number of chunks held by the blobcache.
+    pub cached_chunks: BasicMetric,
+    // Bytes of cached data for blobs no longer used by any filesystem, which may be reclaimed.
+    pub evictable_bytes: BasicMetric,
 }
 
 impl BlobcacheMetrics {
@@ -946,6 +1049,21 @@ mod tests {
         assert!(f.recent_read_files.is_set(node1 as u64));
     }
 
+    #[test]
+    fn test_accessed_files() {
+        let f = FsIoStats::default();
+        f.access_pattern_enabled.store(true, Ordering::Relaxed);
+        for ino in 1..=3 {
+            f.new_file_counter(ino);
+        }
+        f.file_stats_update(3, StatsFop::Read, 4000, true);
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        f.file_stats_update(1, StatsFop::Read, 4000, true);
+        f.file_stats_update(3, StatsFop::Read, 4000, true);
+        f.file_stats_update(2, StatsFop::Open, 0, true);
+        assert_eq!(f.accessed_files(), vec![3, 1]);
+    }
+
     #[test]
     fn test_fop_update() {
         let f = FsIoStats::default();
@@ -960,6 +1078,25 @@ mod tests {
         assert_eq!(f.fop_errors[StatsFop::Opendir as usize].count(), 1);
     }
 
+    #[test]
+    fn test_read_amplification() {
+        let f = FsIoStats::default();
+        assert_eq!(f.read_amplification(), 0.0);
+        f.read_amplification_update(0x1000, 0x10_0000);
+        f.read_amplification_update(0x1000, 0x10_0000);
+        assert_eq!(f.read_amplification(), 256.0);
+        assert!(f.export_fs_stats().unwrap().contains("amplify_chunk_bytes"));
+
+        let interval = Duration::from_secs(3600);
+        assert_eq!(f.amplified_read_update(interval), Some(1));
+        assert_eq!(f.amplified_read_update(interval), None);
+        assert_eq!(f.amplified_read_update(interval), None);
+        assert_eq!(f.amplified_reads.count(), 3);
+        assert_eq!(f.amplified_read_warns.count(), 1);
+        assert_eq!(f.amplified_read_update(Duration::ZERO), Some(3));
+        assert_eq!(f.amplified_read_warns.count(), 2);
+    }
+
     #[test]
     fn test_latecny() {
         let f = FsIoStats::default();
@@ -1047,6 +1184,13 @@ mod tests {
             assert_eq!(metrics.len(), 1);
         }
         assert!(m1.export_metrics().is_ok());
+        m1.cached_bytes.set(0x3000);
+        m1.cached_chunks.set(3);
+        m1.evictable_bytes.set(0x1000);
+        let exported = m1.export_metrics().unwrap();
+        assert!(exported.contains("\"cached_bytes\":12288"));
+        assert!(exported.contains("\"cached_chunks\":3"));
+        assert!(exported.contains("\"evictable_bytes\":4096"));
         assert!(m1.release().is_ok());
         {
             let metrics = BLOBCACHE_METRICS.read().unwrap();
@@ -2,15 +2,19 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+//! Readers shared by the daemon and the builder.
+//!
+//! Readers calculating digests of source data, such as `BufReaderInfo`, are only needed to build
+//! images and live in the `nydus-builder` crate, so the daemon doesn't link the blob hashing code.
+//!
+//! ```compile_fail
+//! use nydus_utils::BufReaderInfo;
+//! ```
+
 use std::fs::File;
-use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::io::Read;
 use std::marker::PhantomData;
 use std::os::unix::io::{AsRawFd, RawFd};
-use std::sync::{Arc, Mutex};
-
-use sha2::Sha256;
-
-use crate::digest::DigestHasher;
 
 /// A wrapper reader to read a range of data from a file.
 pub struct FileRangeReader<'a> {
@@ -43,79 +47,6 @@ impl<'a> Read for FileRangeReader<'a> {
     }
 }
 
-struct BufReaderState<R: Read> {
-    reader: BufReader<R>,
-    pos: u64,
-    hash: Sha256,
-}
-
-/// A wrapper over `BufReader` to track current position.
-pub struct BufReaderInfo<R: Read> {
-    calc_digest: bool,
-    state: Arc<Mutex<BufReaderState<R>>>,
-}
-
-impl<R: Read> BufReaderInfo<R> {
-    /// Create a new instance of `BufReaderPos` from a `BufReader`.
-    pub fn from_buf_reader(buf_reader: BufReader<R>) -> Self {
-        let state = BufReaderState {
-            reader: buf_reader,
-            pos: 0,
-            hash: Sha256::default(),
-        };
-        Self {
-            calc_digest: true,
-            state: Arc::new(Mutex::new(state)),
-        }
-    }
-
-    /// Get current position of the reader.
-    pub fn position(&self) -> u64 {
-        self.state.lock().unwrap().pos
-    }
-
-    /// Get the hash object.
-    pub fn get_hash_object(&self) -> Sha256 {
-        self.state.lock().unwrap().hash.clone()
-    }
-
-    /// Enable or disable blob digest calculation.
-    pub fn enable_digest_calculation(&mut self, enable: bool) {
-        self.calc_digest = enable;
-    }
-}
-
-impl<R: Read> Read for BufReaderInfo<R> {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        let mut state = self.state.lock().unwrap();
-        state.reader.read(buf).map(|v| {
-            state.pos += v as u64;
-            if v > 0 && self.calc_digest {
-                state.hash.digest_update(&buf[..v]);
-            }
-            v
-        })
-    }
-}
-
-impl<R: Read + Seek> Seek for BufReaderInfo<R> {
-    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
-        let mut state = self.state.lock().unwrap();
-        let pos = state.reader.seek(pos)?;
-        state.pos = pos;
-        Ok(pos)
-    }
-}
-
-impl<R: Read> Clone for BufReaderInfo<R> {
-    fn clone(&self) -> Self {
-        Self {
-            calc_digest: self.calc_digest,
-            state: self.state.clone(),
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
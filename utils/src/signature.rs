@@ -0,0 +1,129 @@
+// Copyright (C) 2023 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Utilities to verify RAFS metadata files signed with Ed25519 keys.
+//!
+//! A signed file has a trailer appended to the original content:
+//! |signature: 64 bytes|magic: 8 bytes|
+//! where the signature covers all data before the trailer. Readers unaware of the signature just
+//! see some trailing data, so signed files stay compatible with them.
+//!
+//! Files are signed by the `nydus-builder` crate, the daemon only needs to verify them with
+//! public keys.
+
+use std::fs::{self, File};
+use std::io::{Read, Result, Seek, SeekFrom};
+use std::path::Path;
+
+use openssl::pkey::{Id, PKey, Public};
+use openssl::sign::Verifier;
+
+use crate::filemap::sealed_copy;
+
+/// Magic number at the end of signed files.
+pub const SIGNATURE_MAGIC: [u8; 8] = *b"RAFSSIG1";
+/// Size of Ed25519 signatures.
+pub const SIGNATURE_SIZE: usize = 64;
+/// Size of the signature trailer appended to signed files.
+pub const SIGNATURE_TRAILER_SIZE: usize = SIGNATURE_SIZE + SIGNATURE_MAGIC.len();
+
+/// Load a PEM encoded Ed25519 public key to verify signed files.
+pub fn load_public_key(path: &Path) -> Result<PKey<Public>> {
+    let pem = fs::read(path)?;
+    let key = PKey::public_key_from_pem(&pem).map_err(|e| {
+        einval!(format!(
+            "failed to load public key from {}, {}",
+            path.display(),
+            e
+        ))
+    })?;
+    if key.id() != Id::ED25519 {
+        return Err(einval!(format!(
+            "public key {} is not an Ed25519 key",
+            path.display()
+        )));
+    }
+    Ok(key)
+}
+
+/// Verify the signature trailer of `file`, no larger than `max_size`, with the public `key`.
+///
+/// On success, return a sealed in-memory copy of the verified content without the trailer, so
+/// later modifications to `file` can't bypass the verification.
+pub fn verify_file(file: &mut File, key: &PKey<Public>, max_size: u64) -> Result<File> {
+    let size = file.metadata()?.len();
+    if size > max_size {
+        return Err(einval!(format!(
+            "size of signed file {} exceeds the limit {}",
+            size, max_size
+        )));
+    }
+    file.seek(SeekFrom::Start(0))?;
+    let mut data = Vec::with_capacity(size as usize);
+    file.take(max_size + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > max_size {
+        return Err(einval!("signed file grows while being verified"));
+    }
+    if data.len() < SIGNATURE_TRAILER_SIZE
+        || data[data.len() - SIGNATURE_MAGIC.len()..] != SIGNATURE_MAGIC
+    {
+        return Err(eacces!("file is not signed"));
+    }
+
+    let content_size = data.len() - SIGNATURE_TRAILER_SIZE;
+    let (content, trailer) = data.split_at(content_size);
+    let mut verifier = Verifier::new_without_digest(key)
+        .map_err(|e| eother!(format!("failed to create verifier, {}", e)))?;
+    match verifier.verify_oneshot(&trailer[..SIGNATURE_SIZE], content) {
+        Ok(true) => sealed_copy(content),
+        Ok(false) => Err(eacces!("signature mismatch")),
+        Err(e) => Err(eacces!(format!("failed to verify signature, {}", e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use vmm_sys_util::tempfile::TempFile;
+
+    fn texture_path(name: &str) -> PathBuf {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        PathBuf::from(root_dir).join("../tests/texture").join(name)
+    }
+
+    #[test]
+    fn test_verify_file() {
+        let public_key = load_public_key(&texture_path("signing/ed25519-public.pem")).unwrap();
+        assert!(load_public_key(&texture_path("signing/ed25519-private.pem")).is_err());
+
+        let content = fs::read(texture_path("bootstrap/rafs-v5.boot")).unwrap();
+        let tmp_file = TempFile::new().unwrap();
+        fs::write(tmp_file.as_path(), &content).unwrap();
+        let mut file = File::open(tmp_file.as_path()).unwrap();
+        assert!(verify_file(&mut file, &public_key, u64::MAX).is_err());
+
+        // The trailer is generated by signing `rafs-v5.boot` with `ed25519-private.pem`.
+        let trailer = fs::read(texture_path("signing/rafs-v5.boot.sig")).unwrap();
+        assert_eq!(trailer.len(), SIGNATURE_TRAILER_SIZE);
+        let mut data = content.clone();
+        data.extend_from_slice(&trailer);
+        fs::write(tmp_file.as_path(), &data).unwrap();
+        let mut file = File::open(tmp_file.as_path()).unwrap();
+        assert!(verify_file(&mut file, &public_key, data.len() as u64 - 1).is_err());
+        let mut verified = verify_file(&mut file, &public_key, data.len() as u64).unwrap();
+
+        // The verified copy is not affected by later changes to the file.
+        data[0x100] ^= 0x1;
+        fs::write(tmp_file.as_path(), &data).unwrap();
+        let mut copy = Vec::new();
+        verified.read_to_end(&mut copy).unwrap();
+        assert_eq!(copy, content);
+        assert!(verified.write_all(b"data").is_err());
+
+        let mut file = File::open(tmp_file.as_path()).unwrap();
+        assert!(verify_file(&mut file, &public_key, u64::MAX).is_err());
+    }
+}